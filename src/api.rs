@@ -1,14 +1,37 @@
 use crate::db::*;
+use crate::jobs::Job;
 use crate::model::*;
+use crate::patch::Patch;
 use crate::resumable::PendingUpload;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Default, Deserialize)]
 pub struct QueryParams {
     pub q: Option<String>,
     pub page: Option<u32>,
     pub limit: Option<u32>,
     pub state: Option<String>,
+    pub favorite: Option<bool>,
+    pub status: Option<ReadingStatus>,
+    pub visibility: Option<Visibility>,
+}
+
+/// `GET /books/export`; the book filters are read from `QueryParams`
+#[derive(Debug, Deserialize)]
+pub struct ExportQuery {
+    #[serde(default = "default_export_format")]
+    pub format: String,
+    /// Download remote covers; when false only generated covers are used
+    #[serde(default = "default_true")]
+    pub covers: bool,
+}
+
+fn default_export_format() -> String {
+    "pdf".to_string()
+}
+
+fn default_true() -> bool {
+    true
 }
 
 #[derive(Debug, Deserialize)]
@@ -27,6 +50,15 @@ pub struct UploadInitResponse {
     pub completed_chunks: i64,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub key: Option<String>,
+    /// Presigned URLs to PUT each part to, for an upload initialized with `direct`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub part_urls: Vec<PartUrl>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PartUrl {
+    pub part_number: i32,
+    pub url: String,
 }
 
 #[derive(Debug, Serialize)]
@@ -40,6 +72,62 @@ pub struct UpdateBookRequest {
     pub author_ids: Vec<i32>,
     pub tag_ids: Vec<i32>,
     pub category_ids: Vec<i32>,
+    #[serde(default)]
+    pub reading_status: Option<ReadingStatus>,
+}
+
+/// Body of `PATCH /books/:id` (`application/merge-patch+json`). Relation lists are
+/// replaced wholesale; `null` empties them.
+#[derive(Debug, Deserialize, Default)]
+pub struct PatchBookRequest {
+    #[serde(default)]
+    pub title: Patch<String>,
+    #[serde(default)]
+    pub description: Patch<String>,
+    #[serde(default)]
+    pub cover_url: Patch<String>,
+    #[serde(default)]
+    pub pages: Patch<i32>,
+    #[serde(default)]
+    pub ratings: Patch<i32>,
+    #[serde(default)]
+    pub isbn: Patch<String>,
+    #[serde(default)]
+    pub publish_date: Patch<String>,
+    #[serde(default)]
+    pub reading_status: Patch<ReadingStatus>,
+    #[serde(default)]
+    pub visibility: Patch<Visibility>,
+    #[serde(default)]
+    pub author_ids: Patch<Vec<i32>>,
+    #[serde(default)]
+    pub tag_ids: Patch<Vec<i32>>,
+    #[serde(default)]
+    pub category_ids: Patch<Vec<i32>>,
+}
+
+/// Body of `PUT /books/:id/favorite`; with no body the flag is toggled
+#[derive(Debug, Deserialize, Default)]
+pub struct FavoriteRequest {
+    pub is_favorite: Option<bool>,
+}
+
+/// Body of `POST /books/:id/open`; omitted fields keep their stored values
+#[derive(Debug, Deserialize, Default)]
+pub struct OpenBookRequest {
+    /// Fraction of the book read, from 0 to 1
+    pub progress: Option<f64>,
+    pub position: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Default)]
+pub struct ContinueReadingQuery {
+    pub limit: Option<u32>,
+}
+
+#[derive(Debug, Deserialize, Default)]
+pub struct EnrichBookRequest {
+    pub isbn: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -47,6 +135,58 @@ pub struct CreateEntityRequest {
     pub name: String,
 }
 
+#[derive(Debug, Default, Deserialize)]
+pub struct AuthorQueryParams {
+    pub q: Option<String>,
+    pub page: Option<u32>,
+    pub limit: Option<u32>,
+}
+
+/// Body of `PUT /authors/:id`; members follow merge-patch semantics
+#[derive(Debug, Default, Deserialize)]
+pub struct UpdateAuthorRequest {
+    #[serde(default)]
+    pub name: Patch<String>,
+    #[serde(default)]
+    pub bio: Patch<String>,
+    #[serde(default)]
+    pub photo_url: Patch<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct DeleteAuthorQuery {
+    /// Unlink the author from their books instead of refusing the delete
+    #[serde(default)]
+    pub force: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AttachVersionRequest {
+    /// Object key of a completed upload
+    pub key: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateShelfRequest {
+    pub name: String,
+    pub description: Option<String>,
+}
+
+/// A JSON merge patch: `"description": null` clears the description. The name
+/// can be changed but not cleared.
+#[derive(Debug, Deserialize, Default)]
+pub struct UpdateShelfRequest {
+    #[serde(default)]
+    pub name: Patch<String>,
+    #[serde(default)]
+    pub description: Patch<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ShelfBooksRequest {
+    pub book_ids: Vec<i32>,
+}
+
 #[derive(Debug, Serialize, Default)]
 pub struct APIResponse {
     #[serde(skip_serializing_if = "String::is_empty")]
@@ -59,6 +199,22 @@ pub struct APIResponse {
     pub upload_id: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub metadata: Option<MetadataAggregate>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub shelves: Vec<Shelf>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub revisions: Vec<BookRevision>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub authors: Vec<AuthorAggregate>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total_authors: Option<u32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub jobs: Vec<Job>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub versions: Vec<BookVersion>,
+    /// Existing books an upload may be a new version of, with the same title or
+    /// ISBN but a different file
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub matches: Vec<Book>,
 }
 
 #[derive(Debug, Serialize)]
@@ -68,13 +224,13 @@ pub struct EntityResponse<T> {
 
 impl APIResponse {
     pub fn new_from_msg(msg: &str) -> Self {
-        return APIResponse {
+        APIResponse {
             status: msg.to_owned(),
             ..Default::default()
-        };
+        }
     }
 
     pub fn to_json(self) -> impl serde::Serialize {
-        return self;
+        self
     }
 }
@@ -0,0 +1,373 @@
+//! Printable catalog of the library (`GET /books/export?format=pdf`): one row per
+//! book with its cover, title, authors and the shelves it sits on, for keeping an
+//! inventory of the physical collection. Text uses the standard Helvetica fonts so
+//! nothing has to be embedded; characters outside WinAnsi are printed as `?`.
+
+use std::io::Write;
+
+use anyhow::Result;
+use flate2::{Compression, write::ZlibEncoder};
+use pdf_writer::{Content, Filter, Finish, Name, Pdf, Rect, Ref, Str, TextStr};
+
+use crate::cover;
+
+const PAGE_WIDTH: f32 = 595.0;
+const PAGE_HEIGHT: f32 = 842.0;
+const MARGIN: f32 = 42.0;
+const HEADER_HEIGHT: f32 = 40.0;
+const FOOTER_HEIGHT: f32 = 20.0;
+const ROW_HEIGHT: f32 = 78.0;
+const COVER_WIDTH: f32 = 44.0;
+const COVER_HEIGHT: f32 = 66.0;
+const TEXT_LEFT: f32 = MARGIN + COVER_WIDTH + 14.0;
+const TITLE_SIZE: f32 = 11.0;
+const DETAIL_SIZE: f32 = 9.0;
+/// Generated covers are downsampled by this factor before embedding
+const GENERATED_COVER_SCALE: u32 = 4;
+/// Remote covers larger than this are skipped
+pub const MAX_COVER_BYTES: usize = 2 * 1024 * 1024;
+
+const REGULAR: Name = Name(b"F1");
+const BOLD: Name = Name(b"F2");
+
+pub struct CatalogEntry {
+    pub title: String,
+    pub authors: Vec<String>,
+    pub shelves: Vec<String>,
+    pub isbn: String,
+    pub publish_date: String,
+    pub pages: i32,
+    pub cover: Option<CoverImage>,
+}
+
+pub enum CoverImage {
+    /// JPEG data, embedded as is
+    Jpeg {
+        data: Vec<u8>,
+        width: u32,
+        height: u32,
+        components: u8,
+    },
+    /// 8-bit RGB pixels
+    Rgb { pixels: Vec<u8>, width: u32, height: u32 },
+}
+
+impl CoverImage {
+    /// Reads the frame header of a baseline or progressive JPEG
+    pub fn from_jpeg(data: Vec<u8>) -> Option<Self> {
+        if !data.starts_with(&[0xFF, 0xD8]) {
+            return None;
+        }
+        let mut i = 2;
+        while i + 4 <= data.len() {
+            if data[i] != 0xFF {
+                return None;
+            }
+            let marker = data[i + 1];
+            let length = u16::from_be_bytes([data[i + 2], data[i + 3]]) as usize;
+            let is_frame = matches!(marker, 0xC0..=0xCF) && !matches!(marker, 0xC4 | 0xC8 | 0xCC);
+            if is_frame {
+                let frame = data.get(i + 4..i + 10)?;
+                let height = u16::from_be_bytes([frame[1], frame[2]]) as u32;
+                let width = u16::from_be_bytes([frame[3], frame[4]]) as u32;
+                let components = frame[5];
+                if width == 0 || height == 0 || !matches!(components, 1 | 3) {
+                    return None;
+                }
+                return Some(CoverImage::Jpeg {
+                    data,
+                    width,
+                    height,
+                    components,
+                });
+            }
+            i += 2 + length;
+        }
+        None
+    }
+
+    /// The typographic cover the library shows for books without one, at thumbnail size
+    pub fn generated(title: &str, author: Option<&str>) -> Option<Self> {
+        let (width, height, pixels) = cover::typographic_rgb(title, author).ok()?;
+        let (width, height, pixels) = downsample(width, height, &pixels, GENERATED_COVER_SCALE);
+        Some(CoverImage::Rgb { pixels, width, height })
+    }
+}
+
+/// Box-filter downsampling of RGB pixels
+fn downsample(width: u32, height: u32, pixels: &[u8], factor: u32) -> (u32, u32, Vec<u8>) {
+    let (out_width, out_height) = (width / factor, height / factor);
+    let mut out = Vec::with_capacity((out_width * out_height * 3) as usize);
+    for y in 0..out_height {
+        for x in 0..out_width {
+            let mut sum = [0u32; 3];
+            for dy in 0..factor {
+                for dx in 0..factor {
+                    let i = (((y * factor + dy) * width + x * factor + dx) * 3) as usize;
+                    for (c, total) in sum.iter_mut().enumerate() {
+                        *total += pixels[i + c] as u32;
+                    }
+                }
+            }
+            out.extend(sum.iter().map(|total| (total / (factor * factor)) as u8));
+        }
+    }
+    (out_width, out_height, out)
+}
+
+/// Downloads a cover for embedding; only JPEGs can be embedded without decoding
+pub async fn fetch_cover(client: &reqwest::Client, url: &str) -> Option<CoverImage> {
+    let resp = client.get(url).send().await.ok()?.error_for_status().ok()?;
+    if resp.content_length().is_some_and(|len| len as usize > MAX_COVER_BYTES) {
+        return None;
+    }
+    let data = resp.bytes().await.ok()?;
+    if data.len() > MAX_COVER_BYTES {
+        return None;
+    }
+    CoverImage::from_jpeg(data.to_vec())
+}
+
+/// Encodes text for the WinAnsi-encoded standard fonts
+fn win_ansi(text: &str) -> Vec<u8> {
+    text.chars()
+        .map(|ch| match ch {
+            ' '..='~' => ch as u8,
+            '\u{A0}'..='\u{FF}' => ch as u32 as u8,
+            '\u{2018}' => 0x91,
+            '\u{2019}' => 0x92,
+            '\u{201C}' => 0x93,
+            '\u{201D}' => 0x94,
+            '\u{2022}' => 0x95,
+            '\u{2013}' => 0x96,
+            '\u{2014}' => 0x97,
+            '\u{2026}' => 0x85,
+            _ => b'?',
+        })
+        .collect()
+}
+
+/// Shortens `text` to roughly fit `max_width` points. Helvetica averages a little
+/// over half an em per character, which is close enough for a catalog.
+fn fit(text: &str, size: f32, max_width: f32) -> String {
+    let max_chars = (max_width / (size * 0.55)) as usize;
+    if text.chars().count() <= max_chars {
+        return text.to_string();
+    }
+    let mut shortened: String = text.chars().take(max_chars.saturating_sub(1)).collect();
+    shortened = shortened.trim_end().to_string();
+    shortened.push('\u{2026}');
+    shortened
+}
+
+fn compress(data: &[u8]) -> Vec<u8> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    // Writing to a Vec cannot fail
+    let _ = encoder.write_all(data);
+    encoder.finish().unwrap_or_default()
+}
+
+fn rows_per_page() -> usize {
+    ((PAGE_HEIGHT - 2.0 * MARGIN - HEADER_HEIGHT - FOOTER_HEIGHT) / ROW_HEIGHT) as usize
+}
+
+fn text(content: &mut Content, font: Name, size: f32, x: f32, y: f32, value: &str) {
+    content
+        .begin_text()
+        .set_font(font, size)
+        .next_line(x, y)
+        .show(Str(&win_ansi(value)))
+        .end_text();
+}
+
+/// Lays the entries out on A4 pages, a heading on each page and page numbers at the bottom.
+pub fn render_pdf(heading: &str, generated_on: &str, entries: &[CatalogEntry]) -> Result<Vec<u8>> {
+    let mut next_ref = Ref::new(1);
+    let catalog_id = next_ref.bump();
+    let tree_id = next_ref.bump();
+    let regular_id = next_ref.bump();
+    let bold_id = next_ref.bump();
+    let info_id = next_ref.bump();
+
+    let mut pdf = Pdf::new();
+    pdf.type1_font(regular_id)
+        .base_font(Name(b"Helvetica"))
+        .encoding_predefined(Name(b"WinAnsiEncoding"));
+    pdf.type1_font(bold_id)
+        .base_font(Name(b"Helvetica-Bold"))
+        .encoding_predefined(Name(b"WinAnsiEncoding"));
+    pdf.document_info(info_id)
+        .title(TextStr(heading))
+        .producer(TextStr(concat!("bibliotek ", env!("CARGO_PKG_VERSION"))));
+
+    let chunks: Vec<&[CatalogEntry]> = if entries.is_empty() {
+        vec![&[]]
+    } else {
+        entries.chunks(rows_per_page()).collect()
+    };
+    let total_pages = chunks.len();
+    let mut page_ids = Vec::with_capacity(total_pages);
+    let text_width = PAGE_WIDTH - MARGIN - TEXT_LEFT;
+
+    for (page_index, rows) in chunks.iter().enumerate() {
+        let page_id = next_ref.bump();
+        let content_id = next_ref.bump();
+        page_ids.push(page_id);
+
+        let mut content = Content::new();
+        let top = PAGE_HEIGHT - MARGIN;
+        text(&mut content, BOLD, 16.0, MARGIN, top - 16.0, heading);
+        let summary = format!("{} books \u{2022} {}", entries.len(), generated_on);
+        text(&mut content, REGULAR, DETAIL_SIZE, MARGIN, top - 30.0, &summary);
+        content
+            .set_stroke_gray(0.6)
+            .set_line_width(0.5)
+            .move_to(MARGIN, top - HEADER_HEIGHT + 4.0)
+            .line_to(PAGE_WIDTH - MARGIN, top - HEADER_HEIGHT + 4.0)
+            .stroke();
+
+        let mut images = Vec::new();
+        for (row, entry) in rows.iter().enumerate() {
+            let row_top = top - HEADER_HEIGHT - row as f32 * ROW_HEIGHT - 6.0;
+
+            if let Some(image) = &entry.cover {
+                let image_id = next_ref.bump();
+                let name = format!("Im{}", row);
+                let (data, width, height, filter, gray) = match image {
+                    CoverImage::Jpeg {
+                        data,
+                        width,
+                        height,
+                        components,
+                    } => (data.clone(), *width, *height, Filter::DctDecode, *components == 1),
+                    CoverImage::Rgb { pixels, width, height } => {
+                        (compress(pixels), *width, *height, Filter::FlateDecode, false)
+                    }
+                };
+                let mut xobject = pdf.image_xobject(image_id, &data);
+                xobject.filter(filter);
+                xobject.width(width as i32);
+                xobject.height(height as i32);
+                if gray {
+                    xobject.color_space().device_gray();
+                } else {
+                    xobject.color_space().device_rgb();
+                }
+                xobject.bits_per_component(8);
+                xobject.finish();
+
+                content
+                    .save_state()
+                    .transform([COVER_WIDTH, 0.0, 0.0, COVER_HEIGHT, MARGIN, row_top - COVER_HEIGHT])
+                    .x_object(Name(name.as_bytes()))
+                    .restore_state();
+                images.push((name, image_id));
+            } else {
+                content
+                    .set_stroke_gray(0.8)
+                    .rect(MARGIN, row_top - COVER_HEIGHT, COVER_WIDTH, COVER_HEIGHT)
+                    .stroke();
+            }
+
+            let title = fit(&entry.title, TITLE_SIZE, text_width);
+            text(&mut content, BOLD, TITLE_SIZE, TEXT_LEFT, row_top - 12.0, &title);
+
+            let authors = if entry.authors.is_empty() {
+                "Unknown author".to_string()
+            } else {
+                entry.authors.join(", ")
+            };
+            text(
+                &mut content,
+                REGULAR,
+                DETAIL_SIZE + 1.0,
+                TEXT_LEFT,
+                row_top - 26.0,
+                &fit(&authors, DETAIL_SIZE + 1.0, text_width),
+            );
+
+            let mut details = Vec::new();
+            if !entry.publish_date.is_empty() {
+                details.push(entry.publish_date.clone());
+            }
+            if entry.pages > 0 {
+                details.push(format!("{} pages", entry.pages));
+            }
+            if !entry.isbn.is_empty() {
+                details.push(format!("ISBN {}", entry.isbn));
+            }
+            if !details.is_empty() {
+                let details = fit(&details.join(" \u{2022} "), DETAIL_SIZE, text_width);
+                text(&mut content, REGULAR, DETAIL_SIZE, TEXT_LEFT, row_top - 40.0, &details);
+            }
+
+            let shelves = if entry.shelves.is_empty() {
+                "Shelf: none".to_string()
+            } else {
+                format!("Shelf: {}", entry.shelves.join(", "))
+            };
+            text(
+                &mut content,
+                REGULAR,
+                DETAIL_SIZE,
+                TEXT_LEFT,
+                row_top - 54.0,
+                &fit(&shelves, DETAIL_SIZE, text_width),
+            );
+        }
+
+        let footer = format!("Page {} of {}", page_index + 1, total_pages);
+        text(&mut content, REGULAR, DETAIL_SIZE, PAGE_WIDTH - MARGIN - 60.0, MARGIN - 8.0, &footer);
+
+        let content = compress(&content.finish());
+        pdf.stream(content_id, &content).filter(Filter::FlateDecode);
+
+        let mut page = pdf.page(page_id);
+        page.parent(tree_id)
+            .media_box(Rect::new(0.0, 0.0, PAGE_WIDTH, PAGE_HEIGHT))
+            .contents(content_id);
+        let mut resources = page.resources();
+        resources.fonts().pair(REGULAR, regular_id).pair(BOLD, bold_id);
+        if !images.is_empty() {
+            let mut x_objects = resources.x_objects();
+            for (name, id) in &images {
+                x_objects.pair(Name(name.as_bytes()), *id);
+            }
+        }
+    }
+
+    pdf.pages(tree_id)
+        .kids(page_ids.iter().copied())
+        .count(total_pages as i32);
+    pdf.catalog(catalog_id).pages(tree_id);
+    Ok(pdf.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_paginates_entries() {
+        let entries: Vec<CatalogEntry> = (0..rows_per_page() + 1)
+            .map(|i| CatalogEntry {
+                title: format!("Gödel, Escher, Bach \u{2014} Volume {}", i),
+                authors: vec!["Douglas Hofstadter".to_string()],
+                shelves: vec!["Living room".to_string()],
+                isbn: "9780465026562".to_string(),
+                publish_date: "1979".to_string(),
+                pages: 777,
+                cover: (i == 0)
+                    .then(|| CoverImage::generated("Gödel, Escher, Bach", None))
+                    .flatten(),
+            })
+            .collect();
+
+        let pdf = render_pdf("Catalog", "2024-01-01", &entries).unwrap();
+        assert!(pdf.starts_with(b"%PDF-"));
+        let text = String::from_utf8_lossy(&pdf);
+        assert!(text.contains("/Count 2"));
+        assert_eq!(text.matches("/Subtype /Image").count(), 1);
+        assert_eq!(win_ansi("G\u{f6}del \u{2014} \u{4e2d}"), b"G\xf6del \x97 ?");
+    }
+}
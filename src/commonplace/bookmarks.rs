@@ -0,0 +1,235 @@
+//! Imports the bookmarks HTML file Chrome, Firefox and Safari export (the old
+//! Netscape format) as website resources (`POST /import/bookmarks`).
+//! Bookmarks are matched to website resources by URL, so importing the file again, or
+//! bookmarks of pages already highlighted, adds no duplicates. The folders a bookmark
+//! is in become tags on its resource, along with Firefox's own bookmark tags; the
+//! browser's root folders (the bookmarks bar, other bookmarks) are left out.
+
+use anyhow::Result;
+use serde::Serialize;
+
+use super::capture::{decode_entities, is_capturable, page_text};
+use super::compute_resource_hash;
+use crate::db::Database;
+
+/// Largest bookmarks file accepted by the import endpoint
+pub const MAX_BOOKMARKS_BYTES: usize = 32 * 1024 * 1024;
+
+/// Attributes browsers put on the folders they create themselves
+const ROOT_FOLDER_ATTRIBUTES: &[&str] = &["personal_toolbar_folder", "unfiled_bookmarks_folder"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bookmark {
+    pub url: String,
+    pub title: Option<String>,
+    pub tags: Vec<String>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct BookmarksImport {
+    /// Bookmarks that became new resources
+    pub created: usize,
+    /// Bookmarks of pages that already were resources
+    pub existing: usize,
+    /// Bookmarks that are not web pages (`javascript:`, `place:`, ...)
+    pub skipped: usize,
+    /// Tags added to resources
+    pub tagged: usize,
+}
+
+/// `name="value"` pairs of a start tag, names lowercased
+fn attributes(tag: &str) -> Vec<(String, String)> {
+    let mut attrs = Vec::new();
+    let mut rest = tag.split_once(char::is_whitespace).map_or("", |(_, rest)| rest);
+    while let Some(eq) = rest.find('=') {
+        let name = rest[..eq].trim().to_ascii_lowercase();
+        let value = rest[eq + 1..].trim_start();
+        let (value, after) = match value.strip_prefix('"') {
+            Some(quoted) => quoted.split_once('"').unwrap_or((quoted, "")),
+            None => value.split_once(char::is_whitespace).unwrap_or((value, "")),
+        };
+        // A valueless attribute before this one ends up at the start of the name
+        let name = name.rsplit(char::is_whitespace).next().unwrap_or_default().to_string();
+        attrs.push((name, decode_entities(value)));
+        rest = after;
+    }
+    attrs
+}
+
+fn attribute<'a>(attrs: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    attrs.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str())
+}
+
+/// Reads the bookmarks in a Netscape bookmarks file. Like [`page_text`] this is not a
+/// full HTML parser; the format is regular enough that it does not need one.
+pub fn parse(html: &str) -> Vec<Bookmark> {
+    let mut bookmarks = Vec::new();
+    // The folders around the current position; None for a root folder
+    let mut folders: Vec<Option<String>> = Vec::new();
+    // The folder whose heading was just read, opened by the next <DL>
+    let mut heading: Option<Option<String>> = None;
+    let mut rest = html;
+
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start..];
+        let Some(end) = rest.find('>') else {
+            break;
+        };
+        let tag = &rest[1..end];
+        rest = &rest[end + 1..];
+
+        let closing = tag.starts_with('/');
+        let name = tag
+            .trim_start_matches('/')
+            .split(|c: char| !c.is_ascii_alphanumeric())
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        match (name.as_str(), closing) {
+            ("dl", false) => folders.push(heading.take().flatten()),
+            ("dl", true) => {
+                folders.pop();
+            }
+            ("h3", false) => {
+                let attrs = attributes(tag);
+                let (text, after) = element_text(rest, "h3");
+                rest = after;
+                let root = ROOT_FOLDER_ATTRIBUTES
+                    .iter()
+                    .any(|name| attribute(&attrs, name).is_some());
+                heading = Some(Some(text).filter(|text| !root && !text.is_empty()));
+            }
+            ("a", false) => {
+                let attrs = attributes(tag);
+                let (text, after) = element_text(rest, "a");
+                rest = after;
+                let Some(url) = attribute(&attrs, "href").map(str::trim) else {
+                    continue;
+                };
+                let mut tags: Vec<String> = folders.iter().flatten().cloned().collect();
+                for tag in attribute(&attrs, "tags").unwrap_or_default().split(',') {
+                    let tag = tag.trim();
+                    if !tag.is_empty() && !tags.iter().any(|t| t == tag) {
+                        tags.push(tag.to_string());
+                    }
+                }
+                bookmarks.push(Bookmark {
+                    url: url.to_string(),
+                    title: Some(text).filter(|text| !text.is_empty()),
+                    tags,
+                });
+            }
+            _ => {}
+        }
+    }
+    bookmarks
+}
+
+/// The text up to `</name>`, and what follows it
+fn element_text<'a>(html: &'a str, name: &str) -> (String, &'a str) {
+    let close = format!("</{}", name);
+    let end = html.to_ascii_lowercase().find(&close).unwrap_or(html.len());
+    (page_text(&html[..end]).trim().to_string(), &html[end..])
+}
+
+/// Adds the bookmarks as website resources, in one transaction
+pub async fn import(db: &Database, bookmarks: &[Bookmark]) -> Result<BookmarksImport> {
+    let _guard = db.begin().await?;
+    match import_all(db, bookmarks).await {
+        Ok(summary) => {
+            db.commit().await?;
+            Ok(summary)
+        }
+        Err(e) => {
+            let _ = db.rollback().await;
+            Err(e)
+        }
+    }
+}
+
+async fn import_all(db: &Database, bookmarks: &[Bookmark]) -> Result<BookmarksImport> {
+    let lib = db.commonplace();
+    let mut summary = BookmarksImport::default();
+    for bookmark in bookmarks {
+        if !is_capturable(&bookmark.url) {
+            summary.skipped += 1;
+            continue;
+        }
+        let (resource, created) = lib
+            .find_or_create_website(&bookmark.url, Some(compute_resource_hash(&bookmark.url)))
+            .await?;
+        if created {
+            summary.created += 1;
+        } else {
+            summary.existing += 1;
+        }
+        for tag in &bookmark.tags {
+            if lib.tag_resource(resource.id, tag).await? {
+                summary.tagged += 1;
+            }
+        }
+    }
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::test_db;
+
+    const EXPORT: &str = r#"<!DOCTYPE NETSCAPE-Bookmark-file-1>
+<META HTTP-EQUIV="Content-Type" CONTENT="text/html; charset=UTF-8">
+<TITLE>Bookmarks</TITLE>
+<H1>Bookmarks</H1>
+<DL><p>
+    <DT><H3 ADD_DATE="1700000000" PERSONAL_TOOLBAR_FOLDER="true">Bookmarks bar</H3>
+    <DL><p>
+        <DT><A HREF="https://example.com/?a=1&amp;b=2" ADD_DATE="1700000001">Example &amp; Co</A>
+        <DT><H3 ADD_DATE="1700000002">Reading</H3>
+        <DL><p>
+            <DT><A HREF="https://example.com/essay" TAGS="long,essays">An Essay</A>
+            <DT><H3>Papers</H3>
+            <DL><p>
+                <DT><A HREF="https://example.com/paper.pdf">Paper</A>
+            </DL><p>
+        </DL><p>
+        <DT><A HREF="javascript:alert(1)">Bookmarklet</A>
+    </DL><p>
+    <DT><A HREF="https://example.com/essay">An Essay, again</A>
+</DL><p>
+"#;
+
+    #[tokio::test]
+    async fn test_folders_become_tags() {
+        let bookmarks = parse(EXPORT);
+        let tags: Vec<(&str, Vec<&str>)> = bookmarks
+            .iter()
+            .map(|b| (b.url.as_str(), b.tags.iter().map(String::as_str).collect()))
+            .collect();
+        assert_eq!(
+            tags,
+            [
+                ("https://example.com/?a=1&b=2", vec![]),
+                ("https://example.com/essay", vec!["Reading", "long", "essays"]),
+                ("https://example.com/paper.pdf", vec!["Reading", "Papers"]),
+                ("javascript:alert(1)", vec![]),
+                ("https://example.com/essay", vec![]),
+            ]
+        );
+        assert_eq!(bookmarks[0].title.as_deref(), Some("Example & Co"));
+
+        let db = test_db().await;
+        let summary = import(&db, &bookmarks).await.unwrap();
+        assert_eq!((summary.created, summary.existing, summary.skipped, summary.tagged), (3, 1, 1, 5));
+        let again = import(&db, &bookmarks).await.unwrap();
+        assert_eq!((again.created, again.existing, again.tagged), (0, 4, 0));
+
+        let lib = db.commonplace();
+        let essay = lib
+            .find_resource_by_title("https://example.com/essay")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(lib.list_resource_tags(essay.id).await.unwrap(), ["essays", "long", "Reading"]);
+    }
+}
@@ -0,0 +1,253 @@
+//! Canonical annotation boundary. Every source stores where a highlight sits in its
+//! own shape (Light chunks, Research/react-pdf-highlighter positions, Zotero and Kobo
+//! locations); they are normalized into [`Boundary`] on ingest so readers only have
+//! to understand one schema. Source-specific leftovers are kept under `extra`.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// Bumped when the canonical shape changes incompatibly
+pub const BOUNDARY_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Boundary {
+    pub version: u32,
+    /// Where the annotation came from: light, research, zotero, manual, ...
+    pub source: String,
+    /// 1-based page the annotation starts on
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub page: Option<i64>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rects: Vec<Rect>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quote: Option<TextQuote>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub offsets: Option<CharOffsets>,
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    pub extra: Map<String, Value>,
+}
+
+/// A highlighted region as fractions of the page size, origin at the top left
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Rect {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub page: Option<i64>,
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+/// W3C text quote selector: the highlighted text plus optional surrounding context
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TextQuote {
+    pub exact: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prefix: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub suffix: Option<String>,
+}
+
+/// Character range in the source's plain text, end exclusive
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CharOffsets {
+    pub start: i64,
+    pub end: i64,
+}
+
+impl Boundary {
+    pub fn new(source: &str) -> Self {
+        Self {
+            version: BOUNDARY_VERSION,
+            source: source.to_string(),
+            page: None,
+            rects: Vec::new(),
+            quote: None,
+            offsets: None,
+            extra: Map::new(),
+        }
+    }
+
+    pub fn with_quote(mut self, text: &str) -> Self {
+        if !text.trim().is_empty() {
+            self.quote = Some(TextQuote {
+                exact: text.to_string(),
+                prefix: None,
+                suffix: None,
+            });
+        }
+        self
+    }
+
+    pub fn into_value(self) -> Value {
+        serde_json::to_value(self).unwrap_or(Value::Null)
+    }
+
+    /// Light web highlights: text chunks and the page url, no geometry
+    pub fn from_light(text: &str, chunks: &[String], date: &str, group_id: i64, url: &str) -> Self {
+        let mut boundary = Self::new("light").with_quote(text);
+        boundary.extra.insert("groupID".to_string(), group_id.into());
+        boundary.extra.insert("date".to_string(), date.into());
+        boundary.extra.insert("chunks".to_string(), chunks.into());
+        boundary.extra.insert("url".to_string(), url.into());
+        boundary
+    }
+
+    /// Research (react-pdf-highlighter) positions: rects in viewport pixels, each
+    /// carrying the viewport size it was measured against
+    pub fn from_research(text: &str, page_number: Option<i64>, position: Option<&Value>) -> Self {
+        let mut boundary = Self::new("research").with_quote(text);
+        boundary.page = page_number.or_else(|| {
+            position
+                .and_then(|p| p.pointer("/boundingRect/pageNumber"))
+                .and_then(Value::as_i64)
+        });
+
+        let rects = position.and_then(|p| p.get("rects")).and_then(Value::as_array);
+        boundary.rects = rects
+            .map(|rects| rects.iter().filter_map(scaled_rect).collect())
+            .unwrap_or_default();
+        boundary
+    }
+
+    /// Readwise highlights: a location whose unit depends on the source (a page, an
+    /// ebook location, a position in an article), and no geometry
+    pub fn from_readwise(text: &str, location: Option<i64>, location_type: Option<&str>) -> Self {
+        let mut boundary = Self::new("readwise").with_quote(text);
+        match (location, location_type) {
+            (Some(page), Some("page")) => boundary.page = Some(page),
+            (Some(location), location_type) => {
+                boundary.extra.insert("location".to_string(), location.into());
+                if let Some(location_type) = location_type {
+                    boundary.extra.insert("location_type".to_string(), location_type.into());
+                }
+            }
+            (None, _) => {}
+        }
+        boundary
+    }
+
+    /// Zotero PDF annotations: a 0-based page index and rects in PDF points, without
+    /// the page size needed to make them relative, so they are kept under `extra`
+    pub fn from_zotero(text: &str, position: Option<&Value>, page_label: Option<&str>) -> Self {
+        let mut boundary = Self::new("zotero").with_quote(text);
+        if let Some(position) = position {
+            boundary.page = position.get("pageIndex").and_then(Value::as_i64).map(|index| index + 1);
+            if let Some(rects) = position.get("rects") {
+                boundary.extra.insert("rects".to_string(), rects.clone());
+            }
+        }
+        if let Some(label) = page_label.filter(|label| !label.is_empty()) {
+            boundary.extra.insert("pageLabel".to_string(), label.into());
+        }
+        boundary
+    }
+
+    /// Kobo bookmarks: the chapter file, how far into it the highlight starts (0 to 1)
+    /// and the EPUB container path of its start, no page or geometry
+    pub fn from_kobo(
+        text: &str,
+        chapter: Option<&str>,
+        chapter_progress: Option<f64>,
+        start_path: Option<&str>,
+    ) -> Self {
+        let mut boundary = Self::new("kobo").with_quote(text);
+        if let Some(chapter) = chapter {
+            boundary.extra.insert("chapter".to_string(), chapter.into());
+        }
+        if let Some(progress) = chapter_progress {
+            boundary.extra.insert("chapterProgress".to_string(), progress.into());
+        }
+        if let Some(path) = start_path.filter(|path| !path.is_empty()) {
+            boundary.extra.insert("startContainerPath".to_string(), path.into());
+        }
+        boundary
+    }
+}
+
+/// Converts a react-pdf-highlighter rect (`x1..y2` plus the viewport `width`/`height`)
+fn scaled_rect(rect: &Value) -> Option<Rect> {
+    let get = |key: &str| rect.get(key).and_then(Value::as_f64);
+    let (x1, y1, x2, y2) = (get("x1")?, get("y1")?, get("x2")?, get("y2")?);
+    let (width, height) = (get("width")?, get("height")?);
+    if width <= 0.0 || height <= 0.0 {
+        return None;
+    }
+    Some(Rect {
+        page: rect.get("pageNumber").and_then(Value::as_i64),
+        x: x1 / width,
+        y: y1 / height,
+        width: (x2 - x1) / width,
+        height: (y2 - y1) / height,
+    })
+}
+
+/// Brings a boundary from any known source shape into the canonical schema.
+/// Canonical boundaries pass through unchanged; unknown shapes are kept under `extra`.
+pub fn normalize(value: &Value, text: Option<&str>) -> Value {
+    let Some(obj) = value.as_object() else {
+        return value.clone();
+    };
+    if obj.contains_key("version") {
+        return value.clone();
+    }
+    let text = text.unwrap_or_default();
+    let str_field = |key: &str| obj.get(key).and_then(Value::as_str).unwrap_or_default();
+
+    let boundary = if let Some(chunks) = obj.get("chunks").and_then(Value::as_array) {
+        let chunks: Vec<String> = chunks.iter().filter_map(|c| c.as_str().map(str::to_string)).collect();
+        let group_id = obj.get("groupID").and_then(Value::as_i64).unwrap_or_default();
+        Boundary::from_light(text, &chunks, str_field("date"), group_id, str_field("url"))
+    } else if str_field("source") == "research" {
+        // Research stores its position as a JSON string
+        let position = match obj.get("position") {
+            Some(Value::String(s)) => serde_json::from_str(s).ok(),
+            other => other.cloned(),
+        };
+        let page = obj.get("pageNumber").and_then(Value::as_i64);
+        Boundary::from_research(text, page, position.as_ref())
+    } else {
+        let source = Some(str_field("source")).filter(|s| !s.is_empty()).unwrap_or("unknown");
+        let mut boundary = Boundary::new(source).with_quote(text);
+        boundary.page = obj
+            .get("pageNumber")
+            .or_else(|| obj.get("page"))
+            .and_then(Value::as_i64);
+        boundary.extra = obj.clone();
+        boundary.extra.remove("source");
+        boundary
+    };
+    boundary.into_value()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_normalize_known_sources() {
+        let light = normalize(
+            &json!({"groupID": 7, "date": "2024-01-01", "chunks": ["a", "b"], "url": "https://x.test"}),
+            Some("a b"),
+        );
+        assert_eq!(light["source"], "light");
+        assert_eq!(light["quote"]["exact"], "a b");
+        assert_eq!(light["extra"]["chunks"], json!(["a", "b"]));
+
+        let position = json!({
+            "boundingRect": {"x1": 10.0, "y1": 20.0, "x2": 60.0, "y2": 40.0, "width": 100.0, "height": 200.0, "pageNumber": 3},
+            "rects": [{"x1": 10.0, "y1": 20.0, "x2": 60.0, "y2": 40.0, "width": 100.0, "height": 200.0, "pageNumber": 3}],
+        });
+        let research =
+            normalize(&json!({"pageNumber": 3, "position": position.to_string(), "source": "research"}), Some("quote"));
+        assert_eq!(research["page"], 3);
+        assert_eq!(research["rects"][0], json!({"page": 3, "x": 0.1, "y": 0.1, "width": 0.5, "height": 0.1}));
+
+        let manual = normalize(&json!({"page": 2, "note": "margin"}), None);
+        assert_eq!((manual["source"].as_str(), manual["page"].as_i64()), (Some("unknown"), Some(2)));
+        assert_eq!(manual["extra"]["note"], "margin");
+
+        assert_eq!(normalize(&light, Some("ignored")), light);
+    }
+}
@@ -0,0 +1,315 @@
+//! Captures of website resources. Pages change after they are highlighted, and a
+//! highlight whose text is gone stops anchoring; keeping the readable text of each
+//! capture lets the versions endpoint show what changed in between.
+
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use similar::{ChangeTag, TextDiff};
+
+use crate::db::Database;
+use crate::jobs::{self, JobHandler};
+
+const REQUEST_TIMEOUT_SECS: u64 = 20;
+/// Pages larger than this are not worth keeping as text
+const MAX_PAGE_BYTES: usize = 5 * 1024 * 1024;
+const DIFF_CONTEXT_LINES: usize = 3;
+
+/// Elements whose content is never readable text
+const SKIPPED_ELEMENTS: &[&str] = &["script", "style", "noscript", "template", "svg", "head"];
+
+/// Elements that start a new line in the extracted text
+const BLOCK_ELEMENTS: &[&str] = &[
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "br",
+    "dd",
+    "div",
+    "dl",
+    "dt",
+    "figcaption",
+    "footer",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hr",
+    "li",
+    "main",
+    "nav",
+    "ol",
+    "p",
+    "pre",
+    "section",
+    "table",
+    "td",
+    "th",
+    "tr",
+    "ul",
+];
+
+pub struct PageFetcher {
+    client: reqwest::Client,
+}
+
+impl Default for PageFetcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PageFetcher {
+    pub fn new() -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(REQUEST_TIMEOUT_SECS))
+            .user_agent(concat!("bibliotek/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .unwrap_or_default();
+        Self { client }
+    }
+
+    /// Downloads `url` and returns its readable text
+    pub async fn fetch(&self, url: &str) -> Result<String> {
+        let (content_type, body) = self.get(url).await?;
+        if content_type.contains("html") {
+            Ok(page_text(&body))
+        } else {
+            Ok(body)
+        }
+    }
+
+    /// Downloads `url`, which has to be an HTML page, and returns the markup
+    pub async fn fetch_html(&self, url: &str) -> Result<String> {
+        let (content_type, body) = self.get(url).await?;
+        if !content_type.contains("html") {
+            anyhow::bail!("not an HTML page: {}", content_type);
+        }
+        Ok(body)
+    }
+
+    /// The content type and body of a text page
+    async fn get(&self, url: &str) -> Result<(String, String)> {
+        let resp = self.client.get(url).send().await?.error_for_status()?;
+        let content_type = resp
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or("text/html")
+            .to_ascii_lowercase();
+        if !content_type.starts_with("text/") && !content_type.contains("html") {
+            anyhow::bail!("unsupported content type {}", content_type);
+        }
+
+        let body = resp.bytes().await?;
+        if body.len() > MAX_PAGE_BYTES {
+            anyhow::bail!("page is larger than {} bytes", MAX_PAGE_BYTES);
+        }
+        Ok((content_type, String::from_utf8_lossy(&body).into_owned()))
+    }
+}
+
+pub const CAPTURE_JOB: &str = "resource.capture";
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CapturePayload {
+    resource_id: i32,
+    url: String,
+}
+
+/// Queues a capture of each `(resource_id, url)` so the caller does not wait on the
+/// network. Failed fetches are retried by the job queue.
+pub async fn enqueue_captures(db: &Database, pages: Vec<(i32, String)>) -> Result<()> {
+    for (resource_id, url) in pages.into_iter().filter(|(_, url)| is_capturable(url)) {
+        jobs::enqueue(&db.connection(), CAPTURE_JOB, &CapturePayload { resource_id, url }).await?;
+    }
+    Ok(())
+}
+
+/// Runs the jobs queued by [`enqueue_captures`]
+#[derive(Default)]
+pub struct CaptureJob {
+    fetcher: PageFetcher,
+}
+
+#[async_trait]
+impl JobHandler for CaptureJob {
+    fn kind(&self) -> &'static str {
+        CAPTURE_JOB
+    }
+
+    async fn run(&self, db: &Database, payload: &serde_json::Value) -> Result<()> {
+        let page: CapturePayload = serde_json::from_value(payload.clone())?;
+        let content = self.fetcher.fetch(&page.url).await?;
+        let lib = db.commonplace();
+        if let Some(version) = db
+            .write(|| lib.record_resource_version(page.resource_id, &page.url, &content))
+            .await?
+        {
+            tracing::debug!("Captured {} as version {}", page.url, version.id);
+        }
+        Ok(())
+    }
+}
+
+/// Whether `url` is something the fetcher can capture
+pub fn is_capturable(url: &str) -> bool {
+    url.starts_with("http://") || url.starts_with("https://")
+}
+
+/// Reduces an HTML document to its text, one block element per line. This is not a
+/// full HTML parser; it only has to be stable enough that diffs show real changes.
+pub fn page_text(html: &str) -> String {
+    let mut out = String::new();
+    let mut skipping: Option<String> = None;
+    let mut rest = html;
+
+    while let Some(start) = rest.find('<') {
+        if skipping.is_none() {
+            out.push_str(&decode_entities(&rest[..start]));
+        }
+        rest = &rest[start..];
+
+        if rest.starts_with("<!--") {
+            rest = rest.find("-->").map_or("", |end| &rest[end + 3..]);
+            continue;
+        }
+        let Some(end) = rest.find('>') else {
+            rest = "";
+            break;
+        };
+        let tag = &rest[1..end];
+        rest = &rest[end + 1..];
+
+        let closing = tag.starts_with('/');
+        let name: String = tag
+            .trim_start_matches('/')
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric())
+            .collect::<String>()
+            .to_ascii_lowercase();
+
+        match &skipping {
+            Some(skipped) if closing && *skipped == name => skipping = None,
+            Some(_) => {}
+            None if !closing && !tag.ends_with('/') && SKIPPED_ELEMENTS.contains(&name.as_str()) => {
+                skipping = Some(name)
+            }
+            None if BLOCK_ELEMENTS.contains(&name.as_str()) => out.push('\n'),
+            None => {}
+        }
+    }
+    if skipping.is_none() {
+        out.push_str(&decode_entities(rest));
+    }
+
+    out.lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+pub(crate) fn decode_entities(text: &str) -> String {
+    if !text.contains('&') {
+        return text.to_string();
+    }
+
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let decoded = rest.find(';').filter(|end| *end <= 10).and_then(|end| {
+            let entity = &rest[1..end];
+            let ch = match entity {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                "nbsp" => Some(' '),
+                _ => entity
+                    .strip_prefix("#x")
+                    .or_else(|| entity.strip_prefix("#X"))
+                    .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                    .or_else(|| entity.strip_prefix('#').and_then(|dec| dec.parse().ok()))
+                    .and_then(char::from_u32),
+            };
+            ch.map(|ch| (ch, end))
+        });
+        match decoded {
+            Some((ch, end)) => {
+                out.push(ch);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Line diff between two captures
+#[derive(Debug, Clone, Serialize)]
+pub struct ContentDiff {
+    pub additions: usize,
+    pub deletions: usize,
+    /// Unified diff of the text; empty when nothing changed
+    pub patch: String,
+}
+
+pub fn diff(old: &str, new: &str) -> ContentDiff {
+    let diff = TextDiff::from_lines(old, new);
+    let (mut additions, mut deletions) = (0, 0);
+    for change in diff.iter_all_changes() {
+        match change.tag() {
+            ChangeTag::Insert => additions += 1,
+            ChangeTag::Delete => deletions += 1,
+            ChangeTag::Equal => {}
+        }
+    }
+
+    let patch = if additions + deletions == 0 {
+        String::new()
+    } else {
+        diff.unified_diff()
+            .context_radius(DIFF_CONTEXT_LINES)
+            .missing_newline_hint(false)
+            .header("previous", "current")
+            .to_string()
+    };
+    ContentDiff {
+        additions,
+        deletions,
+        patch,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_page_text_and_diff() {
+        let html = r#"<html><head><title>T</title><style>p { color: red }</style></head>
+            <body><h1>Notes &amp; Queries</h1><!-- nav --><p>First   paragraph<br/>wraps</p>
+            <script>var x = "<p>";</script><p>It&#39;s &lt;done&gt;</p></body></html>"#;
+        let text = page_text(html);
+        assert_eq!(text, "Notes & Queries\nFirst paragraph\nwraps\nIt's <done>");
+
+        let changed = diff(&text, &text.replace("wraps", "wraps again"));
+        assert_eq!((changed.additions, changed.deletions), (1, 1));
+        assert!(changed.patch.contains("+wraps again"));
+        assert!(diff(&text, &text).patch.is_empty());
+    }
+}
@@ -0,0 +1,113 @@
+//! Highlights of two resources compared (`GET /commonplace/resources/:id/compare`),
+//! for a document that came in from two sources, e.g. a Research item and the same
+//! page synced from Light, to check nothing was lost when switching reading apps.
+//!
+//! Apps disagree on whitespace, quotes and where a highlight starts and ends, so two
+//! highlights match when their normalized text is the same or one contains the other.
+
+use serde::Serialize;
+
+use super::{Annotation, Resource};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Comparison {
+    pub resource: Resource,
+    pub other: Resource,
+    /// Highlights of `resource` that `other` has too
+    pub matched: usize,
+    pub only_in_resource: Vec<Annotation>,
+    pub only_in_other: Vec<Annotation>,
+}
+
+/// Lowercased words only, so formatting differences between apps don't count
+fn normalize(text: &str) -> String {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn overlaps(a: &str, b: &str) -> bool {
+    !a.is_empty() && !b.is_empty() && (a.contains(b) || b.contains(a))
+}
+
+pub fn compare(
+    resource: Resource,
+    annotations: Vec<Annotation>,
+    other: Resource,
+    others: Vec<Annotation>,
+) -> Comparison {
+    let texts: Vec<String> = annotations.iter().map(|a| normalize(&a.text)).collect();
+    let other_texts: Vec<String> = others.iter().map(|a| normalize(&a.text)).collect();
+    let found_in = |text: &String, among: &[String]| among.iter().any(|t| overlaps(text, t));
+
+    let (matched, only_in_resource): (Vec<_>, Vec<_>) = annotations
+        .into_iter()
+        .zip(&texts)
+        .partition(|(_, text)| found_in(text, &other_texts));
+    let only_in_other = others
+        .into_iter()
+        .zip(&other_texts)
+        .filter(|(_, text)| !found_in(text, &texts))
+        .map(|(annotation, _)| annotation)
+        .collect();
+
+    Comparison {
+        resource,
+        other,
+        matched: matched.len(),
+        only_in_resource: only_in_resource.into_iter().map(|(annotation, _)| annotation).collect(),
+        only_in_other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commonplace::{CreateAnnotation, CreateResource, ResourceType};
+    use crate::test_support::test_db;
+
+    #[tokio::test]
+    async fn test_compare_matches_loosely() {
+        let db = test_db().await;
+        let lib = db.commonplace();
+        let mut sides = Vec::new();
+        for (title, texts) in [
+            ("https://example.com/essay", &["The map is not the territory.", "Read slowly", "Only here"][..]),
+            ("Essay", &["the MAP is not  the territory", "read slowly, and twice", "Only there"][..]),
+        ] {
+            let resource = lib
+                .create_resource(CreateResource {
+                    title: title.to_string(),
+                    resource_type: ResourceType::Website,
+                    external_id: None,
+                    content_hash: None,
+                })
+                .await
+                .unwrap();
+            for text in texts {
+                lib.create_annotation(CreateAnnotation {
+                    resource_id: resource.id,
+                    text: text.to_string(),
+                    color: None,
+                    boundary: None,
+                    external_id: None,
+                    content_hash: None,
+                })
+                .await
+                .unwrap();
+            }
+            let annotations = lib.list_annotations_by_resource(resource.id).await.unwrap();
+            sides.push((resource, annotations));
+        }
+
+        let (other, others) = sides.pop().unwrap();
+        let (resource, annotations) = sides.pop().unwrap();
+        let comparison = compare(resource, annotations, other, others);
+        assert_eq!(comparison.matched, 2);
+        let texts = |annotations: &[Annotation]| annotations.iter().map(|a| a.text.clone()).collect::<Vec<_>>();
+        assert_eq!(texts(&comparison.only_in_resource), vec!["Only here"]);
+        assert_eq!(texts(&comparison.only_in_other), vec!["Only there"]);
+    }
+}
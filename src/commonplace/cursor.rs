@@ -0,0 +1,165 @@
+//! Cursor pagination for the commonplace list endpoints. An offset shifts whenever a
+//! sync inserts or deletes rows between two page requests, so pages skip or repeat
+//! items; a cursor instead names the last row seen by its `(created_at, id)` and the
+//! next page starts strictly after it. Lists are ordered newest first, so "after"
+//! means older.
+//!
+//! Cursors are opaque to clients: take `next_cursor` from one response and pass it
+//! back as `?cursor=`.
+
+use super::{Annotation, Note, Resource};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cursor {
+    pub created_at: String,
+    pub id: i32,
+}
+
+impl Cursor {
+    pub fn encode(&self) -> String {
+        hex::encode(format!("{}|{}", self.created_at, self.id))
+    }
+
+    pub fn decode(value: &str) -> Option<Self> {
+        let decoded = String::from_utf8(hex::decode(value).ok()?).ok()?;
+        let (created_at, id) = decoded.rsplit_once('|')?;
+        if created_at.is_empty() {
+            return None;
+        }
+        Some(Self {
+            created_at: created_at.to_string(),
+            id: id.parse().ok()?,
+        })
+    }
+}
+
+/// Where a page starts
+#[derive(Debug, Clone)]
+pub enum Page {
+    Offset(i32),
+    After(Cursor),
+}
+
+impl Default for Page {
+    fn default() -> Self {
+        Page::Offset(0)
+    }
+}
+
+impl Page {
+    /// Condition for rows after the cursor, if any, and its params. Rows have to be
+    /// ordered by `created_at DESC, id DESC` for it to hold.
+    pub(crate) fn condition(&self) -> Option<(&'static str, Vec<libsql::Value>)> {
+        match self {
+            Page::Offset(_) => None,
+            Page::After(cursor) => Some((
+                "(created_at < ? OR (created_at = ? AND id < ?))",
+                vec![
+                    cursor.created_at.clone().into(),
+                    cursor.created_at.clone().into(),
+                    cursor.id.into(),
+                ],
+            )),
+        }
+    }
+
+    pub(crate) fn offset(&self) -> i32 {
+        match self {
+            Page::Offset(offset) => *offset,
+            Page::After(_) => 0,
+        }
+    }
+}
+
+/// Rows that can be paged through with a [`Cursor`]
+pub trait Paginated {
+    fn cursor(&self) -> Cursor;
+}
+
+/// Cursor for the page after `items`, or `None` when this page wasn't full and so
+/// was the last one
+pub fn next_cursor<T: Paginated>(items: &[T], limit: i32) -> Option<String> {
+    if items.len() < limit.max(1) as usize {
+        return None;
+    }
+    items.last().map(|item| item.cursor().encode())
+}
+
+impl Paginated for Resource {
+    fn cursor(&self) -> Cursor {
+        Cursor {
+            created_at: self.created_at.clone(),
+            id: self.id,
+        }
+    }
+}
+
+impl Paginated for Annotation {
+    fn cursor(&self) -> Cursor {
+        Cursor {
+            created_at: self.created_at.clone(),
+            id: self.id,
+        }
+    }
+}
+
+impl Paginated for Note {
+    fn cursor(&self) -> Cursor {
+        Cursor {
+            created_at: self.created_at.clone(),
+            id: self.id,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commonplace::{CreateResource, ResourceType};
+    use crate::test_support::test_db;
+
+    #[tokio::test]
+    async fn test_cursor_pages_survive_inserts() {
+        let db = test_db().await;
+        let lib = db.commonplace();
+        for i in 0..5 {
+            lib.create_resource(CreateResource {
+                title: format!("https://example.com/{}", i),
+                resource_type: ResourceType::Website,
+                external_id: None,
+                content_hash: None,
+            })
+            .await
+            .unwrap();
+        }
+
+        let first = lib
+            .list_resources(2, &Page::default(), None, None, None, None, None)
+            .await
+            .unwrap();
+        let cursor = next_cursor(&first, 2).unwrap();
+        // A sync adding a resource between pages would shift an offset by one
+        lib.create_resource(CreateResource {
+            title: "https://example.com/new".to_string(),
+            resource_type: ResourceType::Website,
+            external_id: None,
+            content_hash: None,
+        })
+        .await
+        .unwrap();
+
+        let page = Page::After(Cursor::decode(&cursor).unwrap());
+        let second = lib
+            .list_resources(2, &page, None, None, None, None, None)
+            .await
+            .unwrap();
+        let third = lib
+            .list_resources(2, &Page::After(second[1].cursor()), None, None, None, None, None)
+            .await
+            .unwrap();
+        let seen: Vec<i32> = first.iter().chain(&second).chain(&third).map(|r| r.id).collect();
+        assert_eq!(seen, vec![5, 4, 3, 2, 1]);
+        assert_eq!(next_cursor(&third, 2), None);
+        assert_eq!(Cursor::decode("not a cursor"), None);
+    }
+}
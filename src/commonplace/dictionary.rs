@@ -0,0 +1,132 @@
+//! Definitions for vocabulary saved without a meaning. Only the Free Dictionary API
+//! (dictionaryapi.dev) is supported; the provider can be turned off in the config.
+
+use std::time::Duration;
+
+use anyhow::Result;
+use serde::Deserialize;
+
+use crate::config::{self, DictionaryProvider};
+
+const REQUEST_TIMEOUT_SECS: u64 = 10;
+/// Parts of speech kept in a definition
+const MAX_MEANINGS: usize = 3;
+
+pub struct Dictionary {
+    client: reqwest::Client,
+    config: config::Dictionary,
+}
+
+impl Default for Dictionary {
+    fn default() -> Self {
+        Self::new(config::Dictionary::default())
+    }
+}
+
+impl Dictionary {
+    pub fn new(config: config::Dictionary) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(REQUEST_TIMEOUT_SECS))
+            .user_agent(concat!("bibliotek/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .unwrap_or_default();
+        Self { client, config }
+    }
+
+    /// A dictionary that never looks anything up
+    pub fn disabled() -> Self {
+        Self::new(config::Dictionary {
+            provider: DictionaryProvider::None,
+            ..Default::default()
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.provider != DictionaryProvider::None
+    }
+
+    /// Definition of `word`, or None if the provider does not know it or is disabled
+    pub async fn define(&self, word: &str) -> Result<Option<String>> {
+        let word = word.trim();
+        if !self.is_enabled() || word.is_empty() {
+            return Ok(None);
+        }
+
+        let url = format!(
+            "{}/{}/{}",
+            self.config.url.trim_end_matches('/'),
+            urlencoding::encode(&self.config.language),
+            urlencoding::encode(word)
+        );
+        let resp = self.client.get(&url).send().await?;
+        if resp.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let entries: Vec<Entry> = resp.error_for_status()?.json().await?;
+        Ok(definition(&entries))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Entry {
+    #[serde(default)]
+    meanings: Vec<Meaning>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Meaning {
+    #[serde(default)]
+    part_of_speech: Option<String>,
+    #[serde(default)]
+    definitions: Vec<Definition>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Definition {
+    definition: String,
+}
+
+/// First definition of each part of speech, e.g. `noun: ...; verb: ...`
+fn definition(entries: &[Entry]) -> Option<String> {
+    let mut seen = Vec::new();
+    let mut parts = Vec::new();
+    for meaning in entries.iter().flat_map(|e| &e.meanings) {
+        let Some(first) = meaning.definitions.first() else {
+            continue;
+        };
+        let pos = meaning.part_of_speech.as_deref().unwrap_or_default();
+        if seen.contains(&pos) {
+            continue;
+        }
+        seen.push(pos);
+        parts.push(match pos {
+            "" => first.definition.clone(),
+            pos => format!("{}: {}", pos, first.definition),
+        });
+        if parts.len() == MAX_MEANINGS {
+            break;
+        }
+    }
+    (!parts.is_empty()).then(|| parts.join("; "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_definition_keeps_one_per_part_of_speech() {
+        let entries: Vec<Entry> = serde_json::from_str(
+            r#"[{"word": "gloss", "meanings": [
+                {"partOfSpeech": "noun", "definitions": [{"definition": "A surface shine."}, {"definition": "A note."}]},
+                {"partOfSpeech": "verb", "definitions": [{"definition": "To add a gloss to."}]}
+            ]}, {"word": "gloss", "meanings": [
+                {"partOfSpeech": "noun", "definitions": [{"definition": "A marginal annotation."}]}
+            ]}]"#,
+        )
+        .unwrap();
+        assert_eq!(definition(&entries).as_deref(), Some("noun: A surface shine.; verb: To add a gloss to."));
+        assert_eq!(definition(&[]), None);
+    }
+}
@@ -0,0 +1,168 @@
+//! Cold highlights: annotations that have never come up in the daily review, an
+//! export or a digest, so without help they would never be seen again. A digest
+//! (`GET /commonplace/digest`) hands out the oldest of them, and being in a digest
+//! warms them, so each digest works further back through the library. One is built
+//! each day in the background when `cold_digest_size` is set.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use serde::Serialize;
+use tokio_util::sync::CancellationToken;
+
+use super::review::ReviewItem;
+use crate::db::Database;
+
+pub const DEFAULT_LIMIT: i32 = 20;
+pub const MAX_LIMIT: i32 = 200;
+const TICK: Duration = Duration::from_secs(3600);
+
+/// Where an annotation was shown outside its resource
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Surfacing {
+    Review,
+    Export,
+    Digest,
+}
+
+impl Surfacing {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Surfacing::Review => "review",
+            Surfacing::Export => "export",
+            Surfacing::Digest => "digest",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Digest {
+    pub created_at: String,
+    pub items: Vec<ReviewItem>,
+}
+
+/// Cold highlights, oldest first
+pub async fn cold(db: &Database, limit: i32) -> Result<Vec<ReviewItem>> {
+    let lib = db.commonplace();
+    let ids = lib.cold_annotation_ids(limit).await?;
+    lib.review_items(&ids).await
+}
+
+/// Builds a digest of the `size` oldest cold highlights. `None` when nothing is cold.
+pub async fn build(db: &Database, size: i32) -> Result<Option<Digest>> {
+    let lib = db.commonplace();
+    let ids = lib.cold_annotation_ids(size).await?;
+    if ids.is_empty() {
+        return Ok(None);
+    }
+    let created_at = db.write(|| lib.record_surfacings(&ids, Surfacing::Digest)).await?;
+    let items = lib.review_items(&ids).await?;
+    Ok(Some(Digest { created_at, items }))
+}
+
+pub async fn latest(db: &Database) -> Result<Option<Digest>> {
+    let lib = db.commonplace();
+    let Some((created_at, ids)) = lib.latest_digest().await? else {
+        return Ok(None);
+    };
+    let items = lib.review_items(&ids).await?;
+    Ok(Some(Digest { created_at, items }))
+}
+
+/// Builds a digest once a day
+pub struct DigestScheduler {
+    db: Arc<Database>,
+    size: i32,
+}
+
+impl DigestScheduler {
+    pub fn new(db: Arc<Database>, size: i32) -> Self {
+        Self { db, size }
+    }
+
+    async fn build_if_due(&self) -> Result<Option<Digest>> {
+        let today = chrono::Utc::now().format("%Y-%m-%d").to_string();
+        if let Some((created_at, _)) = self.db.commonplace().latest_digest().await?
+            && created_at.starts_with(&today)
+        {
+            return Ok(None);
+        }
+        build(&self.db, self.size).await
+    }
+
+    pub fn start(self, cancel: CancellationToken) {
+        if self.size <= 0 {
+            tracing::info!("Cold highlight digest disabled");
+            return;
+        }
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(TICK);
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => {
+                        match self.build_if_due().await {
+                            Ok(Some(digest)) => tracing::info!("Built digest of {} cold highlights", digest.items.len()),
+                            Ok(None) => {}
+                            Err(e) => tracing::warn!("Failed to build cold highlight digest: {}", e),
+                        }
+                    }
+                    _ = cancel.cancelled() => {
+                        tracing::info!("Digest scheduler shutting down");
+                        break;
+                    }
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commonplace::{CreateAnnotation, CreateResource, ResourceType};
+    use crate::test_support::test_db;
+
+    #[tokio::test]
+    async fn test_digest_takes_oldest_cold_highlights() {
+        let db = test_db().await;
+        let lib = db.commonplace();
+        let resource = lib
+            .create_resource(CreateResource {
+                title: "https://example.com/essay".to_string(),
+                resource_type: ResourceType::Website,
+                external_id: None,
+                content_hash: None,
+            })
+            .await
+            .unwrap();
+        let mut ids = Vec::new();
+        for text in ["first", "second", "third"] {
+            let annotation = lib
+                .create_annotation(CreateAnnotation {
+                    resource_id: resource.id,
+                    text: text.to_string(),
+                    color: None,
+                    boundary: None,
+                    external_id: None,
+                    content_hash: None,
+                })
+                .await
+                .unwrap();
+            ids.push(annotation.id);
+        }
+        lib.record_review(ids[0]).await.unwrap();
+
+        let digest = build(&db, 1).await.unwrap().unwrap();
+        assert_eq!(digest.items[0].annotation.id, ids[1]);
+        let latest = latest(&db).await.unwrap().unwrap();
+        assert_eq!(latest.items.len(), 1);
+        let cold: Vec<i32> = cold(&db, DEFAULT_LIMIT)
+            .await
+            .unwrap()
+            .iter()
+            .map(|i| i.annotation.id)
+            .collect();
+        assert_eq!(cold, vec![ids[2]]);
+    }
+}
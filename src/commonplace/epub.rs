@@ -0,0 +1,303 @@
+//! A resource's highlights, comments and notes as a small EPUB
+//! (`GET /commonplace/resources/:id/export?format=epub`), for reading them back on
+//! an e-reader. One document per section, with a table of contents that lists every
+//! highlight so a reader can jump straight to one. Both an EPUB 3 nav document and
+//! an EPUB 2 NCX are written, since older readers only understand the latter.
+
+use std::io::{Cursor, Write};
+
+use anyhow::Result;
+use quick_xml::escape::escape;
+use zip::{CompressionMethod, ZipWriter, write::SimpleFileOptions};
+
+use super::{Comment, ResourceFull};
+
+/// Characters of a highlight shown as its table of contents entry
+const TOC_LABEL_CHARS: usize = 60;
+
+const CONTAINER: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles><rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/></rootfiles>
+</container>"#;
+
+const STYLE: &str = "body { font-family: serif; line-height: 1.5; }
+blockquote { margin: 1em 0; padding-left: .8em; border-left: 3px solid #999; }
+.comment { margin-left: 1.5em; font-size: .9em; }
+.permalink { font-size: .8em; }";
+
+struct Section {
+    file: &'static str,
+    title: &'static str,
+    body: String,
+    /// Anchors within the section listed under it in the table of contents
+    entries: Vec<(String, String)>,
+}
+
+/// Builds the EPUB. Highlight permalinks are prefixed with `origin` so they open
+/// the app from the reader, when it can follow links at all.
+pub fn resource_epub(resource: &ResourceFull, exported_on: &str, origin: &str) -> Result<Vec<u8>> {
+    let title = &resource.resource.title;
+    let mut sections = Vec::new();
+
+    if !resource.annotations.is_empty() {
+        let mut body = String::new();
+        let mut entries = Vec::new();
+        for item in &resource.annotations {
+            let annotation = &item.annotation;
+            let anchor = format!("annotation-{}", annotation.id);
+            body.push_str(&format!(
+                "<blockquote id=\"{}\"><p>{} <a class=\"permalink\" href=\"{}{}\">¶</a></p></blockquote>\n",
+                anchor,
+                escape(annotation.text.as_str()),
+                escape(origin),
+                escape(annotation.permalink().as_str()),
+            ));
+            for (depth, comment) in item.comments.iter().flat_map(Comment::walk) {
+                body.push_str(&format!(
+                    "<p class=\"comment\" style=\"padding-left: {}em\">{}</p>\n",
+                    depth,
+                    escape(comment.content.as_str())
+                ));
+            }
+            entries.push((anchor, toc_label(&annotation.text)));
+        }
+        sections.push(Section {
+            file: "highlights.xhtml",
+            title: "Highlights",
+            body,
+            entries,
+        });
+    }
+
+    if !resource.notes.is_empty() {
+        let body = resource
+            .notes
+            .iter()
+            .map(|note| paragraphs(&note.content))
+            .collect::<Vec<_>>()
+            .join("<hr/>\n");
+        sections.push(Section {
+            file: "notes.xhtml",
+            title: "Notes",
+            body,
+            entries: Vec::new(),
+        });
+    }
+
+    if sections.is_empty() {
+        sections.push(Section {
+            file: "highlights.xhtml",
+            title: "Highlights",
+            body: "<p>Nothing highlighted yet.</p>".to_string(),
+            entries: Vec::new(),
+        });
+    }
+
+    let identifier = format!("urn:bibliotek:commonplace:resource:{}:{}", resource.resource.id, exported_on);
+    let mut buf = Cursor::new(Vec::new());
+    let mut zip = ZipWriter::new(&mut buf);
+    // The mimetype entry has to come first and be stored uncompressed
+    zip.start_file("mimetype", SimpleFileOptions::default().compression_method(CompressionMethod::Stored))?;
+    zip.write_all(b"application/epub+zip")?;
+
+    let options = SimpleFileOptions::default();
+    let mut write = |name: &str, content: &str| -> Result<()> {
+        zip.start_file(name, options)?;
+        zip.write_all(content.as_bytes())?;
+        Ok(())
+    };
+    write("META-INF/container.xml", CONTAINER)?;
+    write("OEBPS/style.css", STYLE)?;
+    write("OEBPS/content.opf", &package(title, &identifier, exported_on, &sections))?;
+    write("OEBPS/nav.xhtml", &nav(title, &sections))?;
+    write("OEBPS/toc.ncx", &ncx(title, &identifier, &sections))?;
+    for section in &sections {
+        write(&format!("OEBPS/{}", section.file), &document(section.title, &section.body))?;
+    }
+    zip.finish()?;
+
+    Ok(buf.into_inner())
+}
+
+fn toc_label(text: &str) -> String {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if text.chars().count() <= TOC_LABEL_CHARS {
+        return text;
+    }
+    let cut: String = text.chars().take(TOC_LABEL_CHARS).collect();
+    format!("{}…", cut.trim_end())
+}
+
+/// Blank-line separated paragraphs, with single newlines kept as line breaks
+fn paragraphs(text: &str) -> String {
+    text.split("\n\n")
+        .map(str::trim)
+        .filter(|p| !p.is_empty())
+        .map(|p| format!("<p>{}</p>\n", escape(p).replace('\n', "<br/>")))
+        .collect()
+}
+
+fn document(title: &str, body: &str) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops">
+<head><title>{0}</title><link rel="stylesheet" type="text/css" href="style.css"/></head>
+<body>
+<h1>{0}</h1>
+{1}</body>
+</html>"#,
+        escape(title),
+        body
+    )
+}
+
+fn package(title: &str, identifier: &str, exported_on: &str, sections: &[Section]) -> String {
+    let manifest: String = sections
+        .iter()
+        .enumerate()
+        .map(|(i, s)| format!("    <item id=\"s{}\" href=\"{}\" media-type=\"application/xhtml+xml\"/>\n", i, s.file))
+        .collect();
+    let spine: String = (0..sections.len())
+        .map(|i| format!("    <itemref idref=\"s{}\"/>\n", i))
+        .collect();
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="3.0" unique-identifier="id">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <dc:identifier id="id">{identifier}</dc:identifier>
+    <dc:title>{title}</dc:title>
+    <dc:language>en</dc:language>
+    <dc:date>{exported_on}</dc:date>
+    <meta property="dcterms:modified">{exported_on}T00:00:00Z</meta>
+  </metadata>
+  <manifest>
+    <item id="nav" href="nav.xhtml" media-type="application/xhtml+xml" properties="nav"/>
+    <item id="ncx" href="toc.ncx" media-type="application/x-dtbncx+xml"/>
+    <item id="css" href="style.css" media-type="text/css"/>
+{manifest}  </manifest>
+  <spine toc="ncx">
+{spine}  </spine>
+</package>"#,
+        identifier = escape(identifier),
+        title = escape(title),
+    )
+}
+
+fn nav(title: &str, sections: &[Section]) -> String {
+    let mut items = String::new();
+    for section in sections {
+        items.push_str(&format!("    <li><a href=\"{}\">{}</a>", section.file, section.title));
+        if !section.entries.is_empty() {
+            items.push_str("\n      <ol>\n");
+            for (anchor, label) in &section.entries {
+                items.push_str(&format!(
+                    "        <li><a href=\"{}#{}\">{}</a></li>\n",
+                    section.file,
+                    anchor,
+                    escape(label.as_str())
+                ));
+            }
+            items.push_str("      </ol>\n    ");
+        }
+        items.push_str("</li>\n");
+    }
+    document(title, &format!("<nav epub:type=\"toc\">\n  <ol>\n{}  </ol>\n</nav>\n", items))
+}
+
+fn ncx(title: &str, identifier: &str, sections: &[Section]) -> String {
+    let mut order = 0;
+    let mut points = String::new();
+    for section in sections {
+        order += 1;
+        points.push_str(&format!(
+            "  <navPoint id=\"p{0}\" playOrder=\"{0}\"><navLabel><text>{1}</text></navLabel><content src=\"{2}\"/>\n",
+            order, section.title, section.file
+        ));
+        for (anchor, label) in &section.entries {
+            order += 1;
+            points.push_str(&format!(
+                "    <navPoint id=\"p{0}\" playOrder=\"{0}\"><navLabel><text>{1}</text></navLabel><content src=\"{2}#{3}\"/></navPoint>\n",
+                order,
+                escape(label.as_str()),
+                section.file,
+                anchor
+            ));
+        }
+        points.push_str("  </navPoint>\n");
+    }
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<ncx xmlns="http://www.daisy.org/z3986/2005/ncx/" version="2005-1">
+  <head><meta name="dtb:uid" content="{}"/></head>
+  <docTitle><text>{}</text></docTitle>
+  <navMap>
+{}  </navMap>
+</ncx>"#,
+        escape(identifier),
+        escape(title),
+        points
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commonplace::{CreateAnnotation, CreateComment, CreateResource, ResourceType};
+    use crate::test_support::test_db;
+    use std::io::Read;
+    use zip::ZipArchive;
+
+    #[tokio::test]
+    async fn test_resource_epub() {
+        let db = test_db().await;
+        let lib = db.commonplace();
+        let resource = lib
+            .create_resource(CreateResource {
+                title: "Notes & Queries".to_string(),
+                resource_type: ResourceType::Website,
+                external_id: None,
+                content_hash: None,
+            })
+            .await
+            .unwrap();
+        let annotation = lib
+            .create_annotation(CreateAnnotation {
+                resource_id: resource.id,
+                text: "<less> is more".to_string(),
+                color: None,
+                boundary: None,
+                external_id: None,
+                content_hash: None,
+            })
+            .await
+            .unwrap();
+        lib.create_comment(CreateComment {
+            annotation_id: annotation.id,
+            content: "agreed".to_string(),
+            external_id: None,
+            content_hash: None,
+            parent_comment_id: None,
+        })
+        .await
+        .unwrap();
+        let full = lib.get_resource_full(resource.id).await.unwrap().unwrap();
+
+        let epub = resource_epub(&full, "2024-05-01", "http://books.local").unwrap();
+        let mut archive = ZipArchive::new(Cursor::new(epub)).unwrap();
+        assert_eq!(archive.by_index(0).unwrap().name(), "mimetype");
+        let mut read = |name: &str| {
+            let mut content = String::new();
+            archive.by_name(name).unwrap().read_to_string(&mut content).unwrap();
+            content
+        };
+        assert!(read("OEBPS/content.opf").contains("<dc:title>Notes &amp; Queries</dc:title>"));
+        let anchor = format!("highlights.xhtml#annotation-{}", annotation.id);
+        assert!(read("OEBPS/nav.xhtml").contains(&format!("<a href=\"{}\">&lt;less&gt; is more</a>", anchor)));
+        assert!(read("OEBPS/toc.ncx").contains(&anchor));
+        let highlights = read("OEBPS/highlights.xhtml");
+        assert!(highlights.contains("&lt;less&gt; is more"));
+        assert!(highlights.contains(&format!("http://books.local/a/{}", annotation.public_id)));
+        assert!(highlights.contains("agreed"));
+    }
+}
@@ -0,0 +1,280 @@
+//! Versioned JSON archive of the whole commonplace dataset (`GET /commonplace/export`).
+//! Tables are read a page at a time and streamed as they are serialized, so exporting
+//! a large library never holds it all in memory. Soft-deleted rows are included with
+//! their `deleted_at`, so an archive restores to exactly what was there.
+//!
+//! ```text
+//! {"format": "bibliotek.commonplace", "version": 1, "exported_at": "...",
+//!  "resources": [...], "annotations": [...], "comments": [...], "notes": [...], "words": [...]}
+//! ```
+//!
+//! Words can also be exported on their own as CSV (`GET /commonplace/words/export`),
+//! for vocabulary spreadsheets kept outside the app.
+//!
+//! A single resource exports as a self-contained HTML page
+//! (`GET /commonplace/resources/:id/export?format=html`) that can be emailed or
+//! printed, with each highlight linking back to its permalink, or as an EPUB
+//! (`format=epub`, see [`super::epub`]).
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use askama::Template;
+use axum::body::Bytes;
+use futures_util::{Stream, stream};
+
+use super::{Comment, ResourceFull, WordCitation};
+use crate::db::Database;
+
+pub const ARCHIVE_FORMAT: &str = "bibliotek.commonplace";
+/// Bumped when the archive layout changes incompatibly
+pub const ARCHIVE_VERSION: u32 = 1;
+const PAGE_SIZE: i32 = 500;
+
+/// Tables in the order they appear in the archive; parents come before children
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveTable {
+    Resources,
+    Annotations,
+    Comments,
+    Notes,
+    Words,
+}
+
+impl ArchiveTable {
+    pub const ALL: [ArchiveTable; 5] = [
+        ArchiveTable::Resources,
+        ArchiveTable::Annotations,
+        ArchiveTable::Comments,
+        ArchiveTable::Notes,
+        ArchiveTable::Words,
+    ];
+
+    /// Key of the table's array in the archive, which is also the table name
+    pub fn key(&self) -> &'static str {
+        match self {
+            ArchiveTable::Resources => "resources",
+            ArchiveTable::Annotations => "annotations",
+            ArchiveTable::Comments => "comments",
+            ArchiveTable::Notes => "notes",
+            ArchiveTable::Words => "words",
+        }
+    }
+}
+
+enum State {
+    Start,
+    Table { index: usize, after_id: i64, first: bool },
+    Done,
+}
+
+/// Streams the archive as JSON text. A database error ends the stream early, which
+/// the client sees as a truncated (invalid) document rather than a partial backup.
+pub fn archive_stream(db: Arc<Database>, exported_at: String) -> impl Stream<Item = Result<Bytes, std::io::Error>> {
+    stream::unfold(State::Start, move |state| {
+        let db = db.clone();
+        let exported_at = exported_at.clone();
+        async move {
+            match state {
+                State::Start => {
+                    let header = format!(
+                        r#"{{"format":{},"version":{},"exported_at":{},"{}":["#,
+                        serde_json::Value::from(ARCHIVE_FORMAT),
+                        ARCHIVE_VERSION,
+                        serde_json::Value::from(exported_at),
+                        ArchiveTable::ALL[0].key()
+                    );
+                    let next = State::Table {
+                        index: 0,
+                        after_id: 0,
+                        first: true,
+                    };
+                    Some((Ok(Bytes::from(header)), next))
+                }
+                State::Table { index, after_id, first } => {
+                    let table = ArchiveTable::ALL[index];
+                    let rows = match db.commonplace().archive_page(table, after_id, PAGE_SIZE).await {
+                        Ok(rows) => rows,
+                        Err(e) => {
+                            tracing::error!("Failed to export {}: {}", table.key(), e);
+                            return Some((Err(std::io::Error::other(e.to_string())), State::Done));
+                        }
+                    };
+
+                    let Some(last_id) = rows.last().and_then(|row| row["id"].as_i64()) else {
+                        let (chunk, next) = match ArchiveTable::ALL.get(index + 1) {
+                            Some(next) => (
+                                format!(r#"],"{}":["#, next.key()),
+                                State::Table {
+                                    index: index + 1,
+                                    after_id: 0,
+                                    first: true,
+                                },
+                            ),
+                            None => ("]}".to_string(), State::Done),
+                        };
+                        return Some((Ok(Bytes::from(chunk)), next));
+                    };
+
+                    let mut chunk = Vec::new();
+                    for (i, row) in rows.iter().enumerate() {
+                        if !first || i > 0 {
+                            chunk.push(b',');
+                        }
+                        if let Err(e) = serde_json::to_writer(&mut chunk, row) {
+                            return Some((Err(std::io::Error::other(e)), State::Done));
+                        }
+                    }
+                    let next = State::Table {
+                        index,
+                        after_id: last_id,
+                        first: false,
+                    };
+                    Some((Ok(Bytes::from(chunk)), next))
+                }
+                State::Done => None,
+            }
+        }
+    })
+}
+
+const WORDS_CSV_HEADER: [&str; 7] = [
+    "word",
+    "meaning",
+    "part_of_speech",
+    "source",
+    "annotation",
+    "example",
+    "added_at",
+];
+
+/// Words as CSV, one row per word with a header row
+pub fn words_csv(words: &[WordCitation]) -> Result<Vec<u8>> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record(WORDS_CSV_HEADER)?;
+    for word in words {
+        writer.write_record([
+            word.word.as_str(),
+            word.meaning.as_str(),
+            word.part_of_speech.as_deref().unwrap_or_default(),
+            word.source.as_str(),
+            word.annotation.as_deref().unwrap_or_default(),
+            word.example.as_deref().unwrap_or_default(),
+            word.created_at.as_str(),
+        ])?;
+    }
+    Ok(writer.into_inner()?)
+}
+
+struct ExportHighlight {
+    text: String,
+    permalink: String,
+    favorite: bool,
+    color: Option<String>,
+    /// Each comment with its depth in the thread
+    comments: Vec<(usize, String)>,
+}
+
+#[derive(Template)]
+#[template(path = "resource_export.html")]
+struct ResourceExport {
+    title: String,
+    exported_on: String,
+    highlights: Vec<ExportHighlight>,
+    notes: Vec<String>,
+}
+
+/// A resource's highlights, their comments and its notes as a standalone HTML page.
+/// Permalinks are prefixed with `origin` so they still work from a saved copy.
+pub fn resource_html(resource: &ResourceFull, exported_on: &str, origin: &str) -> Result<String> {
+    let page = ResourceExport {
+        title: resource.resource.title.clone(),
+        exported_on: exported_on.to_string(),
+        highlights: resource
+            .annotations
+            .iter()
+            .map(|item| ExportHighlight {
+                text: item.annotation.text.clone(),
+                permalink: format!("{}{}", origin, item.annotation.permalink()),
+                favorite: item.annotation.is_favorite,
+                color: item.annotation.color.as_deref().and_then(css_color),
+                comments: item
+                    .comments
+                    .iter()
+                    .flat_map(Comment::walk)
+                    .map(|(depth, c)| (depth, c.content.clone()))
+                    .collect(),
+            })
+            .collect(),
+        notes: resource.notes.iter().map(|n| n.content.clone()).collect(),
+    };
+    Ok(page.render()?)
+}
+
+/// Annotation colors come from sync sources as names ("yellow") or hex codes;
+/// anything else is dropped rather than written into a style attribute
+fn css_color(color: &str) -> Option<String> {
+    let valid = match color.strip_prefix('#') {
+        Some(hex) => matches!(hex.len(), 3 | 6 | 8) && hex.chars().all(|c| c.is_ascii_hexdigit()),
+        None => !color.is_empty() && color.chars().all(|c| c.is_ascii_alphabetic()),
+    };
+    valid.then(|| color.to_ascii_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commonplace::{CreateAnnotation, CreateResource, ResourceType};
+    use crate::test_support::test_db;
+    use futures_util::TryStreamExt;
+
+    #[test]
+    fn test_css_color() {
+        assert_eq!(css_color("Yellow").as_deref(), Some("yellow"));
+        assert_eq!(css_color("#FFD400").as_deref(), Some("#ffd400"));
+        assert_eq!(css_color("red;background:url(x)"), None);
+        assert_eq!(css_color("#12345"), None);
+    }
+
+    #[tokio::test]
+    async fn test_archive_includes_deleted_rows() {
+        let db = test_db().await;
+        let lib = db.commonplace();
+        let resource = lib
+            .create_resource(CreateResource {
+                title: "https://example.com/essay".to_string(),
+                resource_type: ResourceType::Website,
+                external_id: None,
+                content_hash: None,
+            })
+            .await
+            .unwrap();
+        for text in ["kept", "removed"] {
+            let annotation = lib
+                .create_annotation(CreateAnnotation {
+                    resource_id: resource.id,
+                    text: text.to_string(),
+                    color: None,
+                    boundary: None,
+                    external_id: None,
+                    content_hash: None,
+                })
+                .await
+                .unwrap();
+            if text == "removed" {
+                lib.soft_delete_annotation(annotation.id).await.unwrap();
+            }
+        }
+
+        let chunks: Vec<Bytes> = archive_stream(db.clone(), "2024-01-01T00:00:00Z".to_string())
+            .try_collect()
+            .await
+            .unwrap();
+        let archive: serde_json::Value = serde_json::from_slice(&chunks.concat()).unwrap();
+        assert_eq!(archive["version"], ARCHIVE_VERSION);
+        assert_eq!(archive["resources"][0]["type"], "website");
+        assert_eq!(archive["annotations"].as_array().unwrap().len(), 2);
+        assert!(archive["annotations"][1]["deleted_at"].is_string());
+        assert_eq!(archive["words"], serde_json::json!([]));
+    }
+}
@@ -1,23 +1,56 @@
 use axum::{
     Json,
+    body::{Body, Bytes},
     extract::{Path, Query, State},
-    http::StatusCode,
+    http::{HeaderMap, StatusCode, header},
     response::{IntoResponse, Response},
 };
 use serde::{Deserialize, Serialize};
-
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+
+use super::bookmarks;
+use super::capture::{self, ContentDiff, PageFetcher};
+use super::compare;
+use super::cursor::{self, Cursor, Page};
+use super::digest::{self, Surfacing};
+use super::epub;
+use super::export;
+use super::import::{self, Archive};
+use super::links::LinkStatus;
+use super::markdown;
+use super::permalink;
+use super::review;
+use super::snapshot;
+use super::srs;
+use super::terms::{self, Scoring};
+use super::trash;
 use super::{
-    Commonplace, CreateAnnotation, CreateComment, CreateNote, CreateResource, CreateWord, UpdateAnnotation,
-    UpdateComment, UpdateNote, UpdateResource, UpdateWord,
+    Annotation, AnnotationFilter, Commonplace, CreateAnnotation, CreateAnnotationLink, CreateComment, CreateNote,
+    CreateResource, CreateWord, Resource, ResourceFull, ResourceStatus, ResourceType, ResourceVersion, Restore,
+    UpdateAnnotation, UpdateComment, UpdateNote, UpdateResource, UpdateWord, is_unique_violation,
 };
+use crate::fieldset::{Fieldset, FieldsetParams};
 use crate::handler::AppState;
+use crate::model::Visibility;
+use crate::sync::runs::Entity;
 
 #[derive(Debug, Deserialize)]
 pub struct ResourceListParams {
     pub limit: Option<i32>,
     pub offset: Option<i32>,
+    /// `next_cursor` from the previous page, instead of an offset
+    pub cursor: Option<String>,
     #[serde(rename = "type")]
     pub resource_type: Option<String>,
+    /// ok, redirected, gone (or dead) or error, from the last link check
+    pub link_status: Option<String>,
+    /// unread, reading or done
+    pub status: Option<String>,
+    /// private, shared or public
+    pub visibility: Option<Visibility>,
+    /// Only resources with this tag
+    pub tag: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -35,10 +68,30 @@ pub struct ErrorResponse {
     pub error: String,
 }
 
+/// A list page, with the cursor to pass as `?cursor=` for the next one (null on the
+/// last page)
+#[derive(Debug, Serialize)]
+pub struct CommonplacePage<T> {
+    pub data: T,
+    pub next_cursor: Option<String>,
+}
+
 fn success<T: Serialize>(data: T) -> Response {
     (StatusCode::OK, Json(CommonplaceApiResponse { data })).into_response()
 }
 
+fn paginated<T: Serialize>(data: T, next_cursor: Option<String>) -> Response {
+    (StatusCode::OK, Json(CommonplacePage { data, next_cursor })).into_response()
+}
+
+fn parse_page(offset: Option<i32>, cursor: Option<&str>) -> Result<Page, &'static str> {
+    match (offset, cursor) {
+        (Some(_), Some(_)) => Err("Use either offset or cursor, not both"),
+        (_, Some(cursor)) => Cursor::decode(cursor).map(Page::After).ok_or("Invalid cursor"),
+        (offset, None) => Ok(Page::Offset(offset.unwrap_or(0))),
+    }
+}
+
 fn created<T: Serialize>(data: T) -> Response {
     (StatusCode::CREATED, Json(CommonplaceApiResponse { data })).into_response()
 }
@@ -51,15 +104,99 @@ fn bad_request(msg: &str) -> Response {
     (StatusCode::BAD_REQUEST, Json(ErrorResponse { error: msg.to_string() })).into_response()
 }
 
+fn conflict(msg: &str) -> Response {
+    (StatusCode::CONFLICT, Json(ErrorResponse { error: msg.to_string() })).into_response()
+}
+
 fn internal_error(msg: &str) -> Response {
     (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse { error: msg.to_string() })).into_response()
 }
 
+fn group_by_id<T>(items: Vec<T>, key: impl Fn(&T) -> i32) -> HashMap<i32, Vec<T>> {
+    let mut grouped: HashMap<i32, Vec<T>> = HashMap::new();
+    for item in items {
+        grouped.entry(key(&item)).or_default().push(item);
+    }
+    grouped
+}
+
+fn take_json<T: Serialize>(grouped: &mut HashMap<i32, Vec<T>>, id: i32) -> Value {
+    serde_json::to_value(grouped.remove(&id).unwrap_or_default()).unwrap_or(Value::Null)
+}
+
+/// Lists with only `?fields=` need no extra queries; relations are fetched in one
+/// batched query per included relation.
+async fn expand_resources(
+    lib: &Commonplace<'_>,
+    fieldset: &Fieldset,
+    resources: Vec<super::Resource>,
+) -> anyhow::Result<Vec<Value>> {
+    let ids: Vec<i32> = resources.iter().map(|r| r.id).collect();
+
+    let mut annotations = if fieldset.includes("annotations") {
+        group_by_id(lib.list_annotations_by_resources(&ids).await?, |a| a.resource_id)
+    } else {
+        HashMap::new()
+    };
+    let mut notes = if fieldset.includes("notes") {
+        group_by_id(lib.list_notes_by_resources(&ids).await?, |n| n.resource_id)
+    } else {
+        HashMap::new()
+    };
+    let mut words = if fieldset.includes("words") {
+        group_by_id(lib.list_words_by_resources(&ids).await?, |w| w.resource_id)
+    } else {
+        HashMap::new()
+    };
+
+    Ok(resources
+        .iter()
+        .map(|resource| {
+            let mut item = Fieldset::to_value(resource);
+            if fieldset.includes("annotations") {
+                item.insert("annotations".to_string(), take_json(&mut annotations, resource.id));
+            }
+            if fieldset.includes("notes") {
+                item.insert("notes".to_string(), take_json(&mut notes, resource.id));
+            }
+            if fieldset.includes("words") {
+                item.insert("words".to_string(), take_json(&mut words, resource.id));
+            }
+            fieldset.project(item)
+        })
+        .collect())
+}
+
+async fn expand_annotations(
+    lib: &Commonplace<'_>,
+    fieldset: &Fieldset,
+    annotations: Vec<super::Annotation>,
+) -> anyhow::Result<Vec<Value>> {
+    let mut comments = if fieldset.includes("comments") {
+        let ids: Vec<i32> = annotations.iter().map(|a| a.id).collect();
+        group_by_id(lib.list_comments_by_annotations(&ids).await?, |c| c.annotation_id)
+    } else {
+        HashMap::new()
+    };
+
+    Ok(annotations
+        .iter()
+        .map(|annotation| {
+            let mut item = Fieldset::to_value(annotation);
+            if fieldset.includes("comments") {
+                item.insert("comments".to_string(), take_json(&mut comments, annotation.id));
+            }
+            fieldset.project(item)
+        })
+        .collect())
+}
+
 pub async fn create_resource(State(state): State<AppState>, Json(payload): Json<CreateResource>) -> Response {
-    let lib = Commonplace::new(state.db.connection());
+    let lib = state.db.commonplace();
 
     match lib.create_resource(payload).await {
         Ok(resource) => created(resource),
+        Err(e) if is_unique_violation(&e) => conflict("A resource with this URL or external id already exists"),
         Err(e) => {
             tracing::error!("Failed to create resource: {}", e);
             internal_error("Failed to create resource")
@@ -68,7 +205,7 @@ pub async fn create_resource(State(state): State<AppState>, Json(payload): Json<
 }
 
 pub async fn get_resource(State(state): State<AppState>, Path(id): Path<i32>) -> Response {
-    let lib = Commonplace::new(state.db.connection());
+    let lib = state.db.commonplace();
 
     match lib.get_resource(id).await {
         Ok(Some(resource)) => success(resource),
@@ -81,7 +218,7 @@ pub async fn get_resource(State(state): State<AppState>, Path(id): Path<i32>) ->
 }
 
 pub async fn get_resource_full(State(state): State<AppState>, Path(id): Path<i32>) -> Response {
-    let lib = Commonplace::new(state.db.connection());
+    let lib = state.db.commonplace();
 
     match lib.get_resource_full(id).await {
         Ok(Some(resource)) => success(resource),
@@ -93,13 +230,91 @@ pub async fn get_resource_full(State(state): State<AppState>, Path(id): Path<i32
     }
 }
 
-pub async fn list_resources(State(state): State<AppState>, Query(params): Query<ResourceListParams>) -> Response {
-    let lib = Commonplace::new(state.db.connection());
+/// Most resources one batch request can ask for
+const MAX_BATCH_RESOURCES: usize = 100;
+
+#[derive(Debug, Deserialize)]
+pub struct ResourceBatchRequest {
+    pub ids: Vec<i32>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ResourceBatch {
+    pub resources: Vec<ResourceFull>,
+    /// Requested ids with no live resource, e.g. deleted since the client synced
+    pub missing: Vec<i32>,
+}
+
+/// Full resources for a list of ids in one request, for clients that keep a
+/// local copy and would otherwise fetch `/resources/:id/full` one at a time
+pub async fn get_resources_full(State(state): State<AppState>, Json(payload): Json<ResourceBatchRequest>) -> Response {
+    if payload.ids.len() > MAX_BATCH_RESOURCES {
+        return bad_request(&format!("At most {} ids can be fetched at once", MAX_BATCH_RESOURCES));
+    }
+
+    match state.db.commonplace().get_resources_full(&payload.ids).await {
+        Ok(resources) => {
+            let mut seen: HashSet<i32> = resources.iter().map(|r| r.resource.id).collect();
+            let missing = payload.ids.into_iter().filter(|id| seen.insert(*id)).collect();
+            success(ResourceBatch { resources, missing })
+        }
+        Err(e) => {
+            tracing::error!("Failed to get resources: {}", e);
+            internal_error("Failed to get resources")
+        }
+    }
+}
+
+pub async fn list_resources(
+    State(state): State<AppState>,
+    Query(params): Query<ResourceListParams>,
+    Query(fields): Query<FieldsetParams>,
+) -> Response {
+    let lib = state.db.commonplace();
     let limit = params.limit.unwrap_or(50).min(100);
-    let offset = params.offset.unwrap_or(0);
+    let page = match parse_page(params.offset, params.cursor.as_deref()) {
+        Ok(page) => page,
+        Err(e) => return bad_request(e),
+    };
+    let fieldset = match Fieldset::parse(&fields, &["annotations", "notes", "words"]) {
+        Ok(f) => f,
+        Err(e) => return bad_request(&e),
+    };
+
+    let link_status = match params.link_status.as_deref().map(LinkStatus::from_str) {
+        Some(None) => return bad_request("link_status must be one of ok, redirected, gone, dead, error"),
+        Some(status) => status,
+        None => None,
+    };
+    let status = match params.status.as_deref().map(ResourceStatus::from_str) {
+        Some(None) => return bad_request("status must be one of unread, reading, done"),
+        Some(status) => status,
+        None => None,
+    };
 
-    match lib.list_resources(limit, offset, params.resource_type.as_deref()).await {
-        Ok(resources) => success(resources),
+    match lib
+        .list_resources(
+            limit,
+            &page,
+            params.resource_type.as_deref(),
+            link_status,
+            status,
+            params.visibility,
+            params.tag.as_deref(),
+        )
+        .await
+    {
+        Ok(resources) if fieldset.is_empty() => {
+            let next = cursor::next_cursor(&resources, limit);
+            paginated(resources, next)
+        }
+        Ok(resources) => match expand_resources(&lib, &fieldset, resources.clone()).await {
+            Ok(items) => paginated(items, cursor::next_cursor(&resources, limit)),
+            Err(e) => {
+                tracing::error!("Failed to expand resources: {}", e);
+                internal_error("Failed to list resources")
+            }
+        },
         Err(e) => {
             tracing::error!("Failed to list resources: {}", e);
             internal_error("Failed to list resources")
@@ -112,11 +327,15 @@ pub async fn update_resource(
     Path(id): Path<i32>,
     Json(payload): Json<UpdateResource>,
 ) -> Response {
-    let lib = Commonplace::new(state.db.connection());
+    if payload.progress.is_some_and(|p| !(0.0..=1.0).contains(&p)) {
+        return bad_request("progress must be between 0 and 1");
+    }
+    let lib = state.db.commonplace();
 
     match lib.update_resource(id, payload).await {
         Ok(Some(resource)) => success(resource),
         Ok(None) => not_found("Resource not found"),
+        Err(e) if is_unique_violation(&e) => conflict("A resource with this URL already exists"),
         Err(e) => {
             tracing::error!("Failed to update resource: {}", e);
             internal_error("Failed to update resource")
@@ -124,8 +343,42 @@ pub async fn update_resource(
     }
 }
 
+#[derive(Debug, Deserialize)]
+pub struct ResourceBookRequest {
+    /// null unlinks the resource
+    pub book_id: Option<i32>,
+}
+
+/// Links a resource to the library book it was read from, so its annotations count
+/// towards that book's activity.
+pub async fn set_resource_book(
+    State(state): State<AppState>,
+    Path(id): Path<i32>,
+    Json(payload): Json<ResourceBookRequest>,
+) -> Response {
+    if let Some(book_id) = payload.book_id {
+        match state.db.get_book_by_id(book_id).await {
+            Ok(Some(book)) if book.deleted_at.is_none() => {}
+            Ok(_) => return not_found("Book not found"),
+            Err(e) => {
+                tracing::error!("Failed to get book: {}", e);
+                return internal_error("Failed to link resource");
+            }
+        }
+    }
+
+    match state.db.commonplace().set_resource_book(id, payload.book_id).await {
+        Ok(Some(resource)) => success(resource),
+        Ok(None) => not_found("Resource not found"),
+        Err(e) => {
+            tracing::error!("Failed to link resource: {}", e);
+            internal_error("Failed to link resource")
+        }
+    }
+}
+
 pub async fn delete_resource(State(state): State<AppState>, Path(id): Path<i32>) -> Response {
-    let lib = Commonplace::new(state.db.connection());
+    let lib = state.db.commonplace();
 
     match lib.delete_resource(id).await {
         Ok(true) => (StatusCode::NO_CONTENT, ()).into_response(),
@@ -138,7 +391,7 @@ pub async fn delete_resource(State(state): State<AppState>, Path(id): Path<i32>)
 }
 
 pub async fn create_annotation(State(state): State<AppState>, Json(payload): Json<CreateAnnotation>) -> Response {
-    let lib = Commonplace::new(state.db.connection());
+    let lib = state.db.commonplace();
 
     match lib.create_annotation(payload).await {
         Ok(annotation) => created(annotation),
@@ -150,7 +403,7 @@ pub async fn create_annotation(State(state): State<AppState>, Json(payload): Jso
 }
 
 pub async fn get_annotation(State(state): State<AppState>, Path(id): Path<i32>) -> Response {
-    let lib = Commonplace::new(state.db.connection());
+    let lib = state.db.commonplace();
 
     match lib.get_annotation(id).await {
         Ok(Some(annotation)) => success(annotation),
@@ -162,11 +415,42 @@ pub async fn get_annotation(State(state): State<AppState>, Path(id): Path<i32>)
     }
 }
 
-pub async fn list_annotations_by_resource(State(state): State<AppState>, Path(resource_id): Path<i32>) -> Response {
-    let lib = Commonplace::new(state.db.connection());
+#[derive(Debug, Deserialize)]
+pub struct FavoriteParams {
+    /// Only favorites (`true`) or only the rest (`false`)
+    pub favorite: Option<bool>,
+}
+
+pub async fn list_annotations_by_resource(
+    State(state): State<AppState>,
+    Path(resource_id): Path<i32>,
+    Query(params): Query<FavoriteParams>,
+    Query(fields): Query<FieldsetParams>,
+) -> Response {
+    let lib = state.db.commonplace();
+    let fieldset = match Fieldset::parse(&fields, &["comments"]) {
+        Ok(f) => f,
+        Err(e) => return bad_request(&e),
+    };
 
-    match lib.list_annotations_by_resource(resource_id).await {
-        Ok(annotations) => success(annotations),
+    let listed = lib
+        .list_annotations_by_resource(resource_id)
+        .await
+        .map(|mut annotations| {
+            if let Some(favorite) = params.favorite {
+                annotations.retain(|a| a.is_favorite == favorite);
+            }
+            annotations
+        });
+    match listed {
+        Ok(annotations) if fieldset.is_empty() => success(annotations),
+        Ok(annotations) => match expand_annotations(&lib, &fieldset, annotations).await {
+            Ok(items) => success(items),
+            Err(e) => {
+                tracing::error!("Failed to expand annotations: {}", e);
+                internal_error("Failed to list annotations")
+            }
+        },
         Err(e) => {
             tracing::error!("Failed to list annotations: {}", e);
             internal_error("Failed to list annotations")
@@ -174,12 +458,34 @@ pub async fn list_annotations_by_resource(State(state): State<AppState>, Path(re
     }
 }
 
+#[derive(Debug, Deserialize)]
+pub struct FavoriteRequest {
+    pub is_favorite: Option<bool>,
+}
+
+/// Stars or unstars an annotation; with no body the flag is toggled
+pub async fn set_annotation_favorite(
+    State(state): State<AppState>,
+    Path(id): Path<i32>,
+    payload: Option<Json<FavoriteRequest>>,
+) -> Response {
+    let favorite = payload.and_then(|Json(p)| p.is_favorite);
+    match state.db.commonplace().set_annotation_favorite(id, favorite).await {
+        Ok(Some(annotation)) => success(annotation),
+        Ok(None) => not_found("Annotation not found"),
+        Err(e) => {
+            tracing::error!("Failed to update favorite: {}", e);
+            internal_error("Failed to update favorite")
+        }
+    }
+}
+
 pub async fn update_annotation(
     State(state): State<AppState>,
     Path(id): Path<i32>,
     Json(payload): Json<UpdateAnnotation>,
 ) -> Response {
-    let lib = Commonplace::new(state.db.connection());
+    let lib = state.db.commonplace();
 
     match lib.update_annotation(id, payload).await {
         Ok(Some(annotation)) => success(annotation),
@@ -192,7 +498,7 @@ pub async fn update_annotation(
 }
 
 pub async fn delete_annotation(State(state): State<AppState>, Path(id): Path<i32>) -> Response {
-    let lib = Commonplace::new(state.db.connection());
+    let lib = state.db.commonplace();
 
     match lib.delete_annotation(id).await {
         Ok(true) => (StatusCode::NO_CONTENT, ()).into_response(),
@@ -204,8 +510,338 @@ pub async fn delete_annotation(State(state): State<AppState>, Path(id): Path<i32
     }
 }
 
+/// Restores a soft-deleted row and returns it. 409 when its parent is still deleted.
+async fn restore(state: &AppState, entity: Entity, id: i32, name: &str) -> Response {
+    fn to_json<T: Serialize>(row: Option<T>) -> Option<Value> {
+        row.and_then(|row| serde_json::to_value(row).ok())
+    }
+
+    let lib = state.db.commonplace();
+    let restored = match lib.restore(entity, id).await {
+        Ok(Restore::Restored) => match entity {
+            Entity::Resource => lib.get_resource(id).await.map(to_json),
+            Entity::Annotation => lib.get_annotation(id).await.map(to_json),
+            Entity::Comment => lib.get_comment(id).await.map(to_json),
+            Entity::Note => lib.get_note(id).await.map(to_json),
+        },
+        Ok(Restore::NotFound) => return not_found(&format!("{} not found", name)),
+        Ok(Restore::ParentDeleted { entity, id }) => {
+            let error = format!("Parent in {} ({}) is deleted; restore it first", entity, id);
+            return conflict(&error);
+        }
+        Err(e) if is_unique_violation(&e) => {
+            return conflict(&format!("{} conflicts with a live duplicate; delete that one first", name));
+        }
+        Err(e) => Err(e),
+    };
+
+    match restored {
+        Ok(Some(row)) => success(row),
+        Ok(None) => not_found(&format!("{} not found", name)),
+        Err(e) => {
+            tracing::error!("Failed to restore {}: {}", name.to_lowercase(), e);
+            internal_error(&format!("Failed to restore {}", name.to_lowercase()))
+        }
+    }
+}
+
+pub async fn restore_resource(State(state): State<AppState>, Path(id): Path<i32>) -> Response {
+    restore(&state, Entity::Resource, id, "Resource").await
+}
+
+pub async fn restore_annotation(State(state): State<AppState>, Path(id): Path<i32>) -> Response {
+    restore(&state, Entity::Annotation, id, "Annotation").await
+}
+
+pub async fn restore_comment(State(state): State<AppState>, Path(id): Path<i32>) -> Response {
+    restore(&state, Entity::Comment, id, "Comment").await
+}
+
+pub async fn restore_note(State(state): State<AppState>, Path(id): Path<i32>) -> Response {
+    restore(&state, Entity::Note, id, "Note").await
+}
+
+/// Notes and words captured without a resource (see `POST /quick`), waiting to be
+/// moved to where they belong
+pub async fn list_inbox(State(state): State<AppState>) -> Response {
+    match state.db.commonplace().list_inbox().await {
+        Ok(inbox) => success(inbox),
+        Err(e) => {
+            tracing::error!("Failed to list inbox: {}", e);
+            internal_error("Failed to list inbox")
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TrashParams {
+    pub limit: Option<i32>,
+}
+
+pub async fn list_trash(State(state): State<AppState>, Query(params): Query<TrashParams>) -> Response {
+    let limit = params.limit.unwrap_or(trash::DEFAULT_LIMIT).clamp(1, 1000);
+    match state.db.commonplace().list_trash(limit).await {
+        Ok(trash) => success(trash),
+        Err(e) => {
+            tracing::error!("Failed to list trash: {}", e);
+            internal_error("Failed to list trash")
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PurgeParams {
+    pub older_than_days: Option<u32>,
+}
+
+/// Permanently deletes rows that have been in the trash longer than `older_than_days`
+pub async fn purge_trash(State(state): State<AppState>, Query(params): Query<PurgeParams>) -> Response {
+    let days = params.older_than_days.unwrap_or(trash::DEFAULT_PURGE_AGE_DAYS);
+    match trash::purge(&state.db, days).await {
+        Ok(summary) => {
+            tracing::info!(
+                "Purged commonplace trash older than {} days: {} resources, {} annotations, {} comments, {} notes",
+                days,
+                summary.resources,
+                summary.annotations,
+                summary.comments,
+                summary.notes
+            );
+            success(summary)
+        }
+        Err(e) => {
+            tracing::error!("Failed to purge trash: {}", e);
+            internal_error("Failed to purge trash")
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BulkDeleteParams {
+    pub resource_id: Option<i32>,
+    pub color: Option<String>,
+    /// Date (`2024-01-31`) or RFC 3339 timestamp; matches annotations created before it
+    pub before: Option<String>,
+    #[serde(default)]
+    pub confirm: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BulkDeleteResult {
+    pub deleted: u64,
+}
+
+/// Timestamp in the format `created_at` is stored in, so the two compare as strings
+fn parse_before(value: &str) -> Option<String> {
+    if let Ok(date) = chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        return Some(format!("{}T00:00:00.000Z", date.format("%Y-%m-%d")));
+    }
+    chrono::DateTime::parse_from_rfc3339(value).ok().map(|ts| {
+        ts.with_timezone(&chrono::Utc)
+            .to_rfc3339_opts(chrono::SecondsFormat::Millis, true)
+    })
+}
+
+/// Like [`parse_before`], but a bare date covers the whole day, so `to=2024-01-31`
+/// includes annotations made on the 31st
+fn parse_until(value: &str) -> Option<String> {
+    match chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        Ok(date) => date
+            .succ_opt()
+            .map(|next| format!("{}T00:00:00.000Z", next.format("%Y-%m-%d"))),
+        Err(_) => parse_before(value),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AnnotationListParams {
+    pub color: Option<String>,
+    /// Date or RFC 3339 timestamp; annotations created on or after it
+    pub from: Option<String>,
+    /// Date or RFC 3339 timestamp; annotations created up to it (a date is inclusive)
+    pub to: Option<String>,
+    pub resource_type: Option<String>,
+    /// Sync source (`light`, `research`, ...) or `manual` for annotations made here
+    pub source: Option<String>,
+    pub favorite: Option<bool>,
+    pub limit: Option<i32>,
+    pub offset: Option<i32>,
+    /// `next_cursor` from the previous page, instead of an offset
+    pub cursor: Option<String>,
+}
+
+/// Annotations across every resource, newest first, e.g. all yellow highlights
+/// from websites in the last month
+pub async fn list_annotations(State(state): State<AppState>, Query(params): Query<AnnotationListParams>) -> Response {
+    let after = match params.from.as_deref().map(parse_before) {
+        Some(None) => return bad_request("from must be a date (YYYY-MM-DD) or an RFC 3339 timestamp"),
+        Some(after) => after,
+        None => None,
+    };
+    let before = match params.to.as_deref().map(parse_until) {
+        Some(None) => return bad_request("to must be a date (YYYY-MM-DD) or an RFC 3339 timestamp"),
+        Some(before) => before,
+        None => None,
+    };
+    if let Some(resource_type) = params.resource_type.as_deref()
+        && ResourceType::from_str(resource_type).is_none()
+    {
+        return bad_request(&format!("Unknown resource_type: {}", resource_type));
+    }
+
+    let filter = AnnotationFilter {
+        color: params.color,
+        after,
+        before,
+        resource_type: params.resource_type,
+        source: params.source,
+        favorite: params.favorite,
+        ..Default::default()
+    };
+    let limit = params.limit.unwrap_or(50).min(100);
+    let page = match parse_page(params.offset, params.cursor.as_deref()) {
+        Ok(page) => page,
+        Err(e) => return bad_request(e),
+    };
+
+    match state.db.commonplace().list_annotations(&filter, limit, &page).await {
+        Ok(annotations) => {
+            let next = cursor::next_cursor(&annotations, limit);
+            paginated(annotations, next)
+        }
+        Err(e) => {
+            tracing::error!("Failed to list annotations: {}", e);
+            internal_error("Failed to list annotations")
+        }
+    }
+}
+
+/// Soft-deletes every annotation matching the filters, e.g. to undo a botched import.
+/// At least one filter is required, and nothing is deleted without `confirm=true`;
+/// the refusal says how many annotations would have been removed.
+pub async fn bulk_delete_annotations(
+    State(state): State<AppState>,
+    Query(params): Query<BulkDeleteParams>,
+) -> Response {
+    let before = match params.before.as_deref().map(parse_before) {
+        Some(None) => return bad_request("before must be a date (YYYY-MM-DD) or an RFC 3339 timestamp"),
+        Some(before) => before,
+        None => None,
+    };
+    let filter = AnnotationFilter {
+        resource_id: params.resource_id,
+        color: params.color,
+        before,
+        ..Default::default()
+    };
+    if filter.is_empty() {
+        return bad_request("At least one of resource_id, color or before is required");
+    }
+
+    let lib = state.db.commonplace();
+    if !params.confirm {
+        return match lib.count_annotations_matching(&filter).await {
+            Ok(matched) => {
+                bad_request(&format!("{} annotations match; repeat with confirm=true to delete them", matched))
+            }
+            Err(e) => {
+                tracing::error!("Failed to count annotations: {}", e);
+                internal_error("Failed to delete annotations")
+            }
+        };
+    }
+
+    match lib.soft_delete_annotations_matching(&filter).await {
+        Ok(deleted) => {
+            tracing::info!(deleted, "bulk deleted annotations");
+            success(BulkDeleteResult { deleted })
+        }
+        Err(e) => {
+            tracing::error!("Failed to delete annotations: {}", e);
+            internal_error("Failed to delete annotations")
+        }
+    }
+}
+
+pub async fn create_annotation_link(
+    State(state): State<AppState>,
+    Path(id): Path<i32>,
+    Json(payload): Json<CreateAnnotationLink>,
+) -> Response {
+    let lib = state.db.commonplace();
+    if payload.target_id == id {
+        return bad_request("An annotation cannot link to itself");
+    }
+    for annotation_id in [id, payload.target_id] {
+        match lib.get_annotation(annotation_id).await {
+            Ok(Some(_)) => {}
+            Ok(None) => return not_found(&format!("Annotation {} not found", annotation_id)),
+            Err(e) => {
+                tracing::error!("Failed to get annotation: {}", e);
+                return internal_error("Failed to create annotation link");
+            }
+        }
+    }
+
+    match lib.create_annotation_link(id, payload).await {
+        Ok(Some(link)) => created(link),
+        Ok(None) => conflict("Annotations are already linked this way"),
+        Err(e) => {
+            tracing::error!("Failed to create annotation link: {}", e);
+            internal_error("Failed to create annotation link")
+        }
+    }
+}
+
+/// Outgoing links and backlinks of an annotation
+pub async fn list_annotation_links(State(state): State<AppState>, Path(id): Path<i32>) -> Response {
+    let lib = state.db.commonplace();
+
+    match lib.get_annotation(id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return not_found("Annotation not found"),
+        Err(e) => {
+            tracing::error!("Failed to get annotation: {}", e);
+            return internal_error("Failed to list annotation links");
+        }
+    }
+
+    match lib.list_annotation_links(id).await {
+        Ok(links) => success(links),
+        Err(e) => {
+            tracing::error!("Failed to list annotation links: {}", e);
+            internal_error("Failed to list annotation links")
+        }
+    }
+}
+
+pub async fn delete_annotation_link(State(state): State<AppState>, Path((id, link_id)): Path<(i32, i32)>) -> Response {
+    let lib = state.db.commonplace();
+
+    match lib.delete_annotation_link(id, link_id).await {
+        Ok(true) => (StatusCode::NO_CONTENT, ()).into_response(),
+        Ok(false) => not_found("Annotation link not found"),
+        Err(e) => {
+            tracing::error!("Failed to delete annotation link: {}", e);
+            internal_error("Failed to delete annotation link")
+        }
+    }
+}
+
 pub async fn create_comment(State(state): State<AppState>, Json(payload): Json<CreateComment>) -> Response {
-    let lib = Commonplace::new(state.db.connection());
+    let lib = state.db.commonplace();
+
+    if let Some(parent_id) = payload.parent_comment_id {
+        match lib.get_comment(parent_id).await {
+            Ok(Some(parent)) if parent.annotation_id == payload.annotation_id => {}
+            Ok(Some(_)) => return bad_request("Replies must be on the same annotation as their parent"),
+            Ok(None) => return bad_request("Parent comment not found"),
+            Err(e) => {
+                tracing::error!("Failed to get parent comment: {}", e);
+                return internal_error("Failed to create comment");
+            }
+        }
+    }
 
     match lib.create_comment(payload).await {
         Ok(comment) => created(comment),
@@ -217,7 +853,7 @@ pub async fn create_comment(State(state): State<AppState>, Json(payload): Json<C
 }
 
 pub async fn get_comment(State(state): State<AppState>, Path(id): Path<i32>) -> Response {
-    let lib = Commonplace::new(state.db.connection());
+    let lib = state.db.commonplace();
 
     match lib.get_comment(id).await {
         Ok(Some(comment)) => success(comment),
@@ -229,11 +865,19 @@ pub async fn get_comment(State(state): State<AppState>, Path(id): Path<i32>) ->
     }
 }
 
-pub async fn list_comments_by_annotation(State(state): State<AppState>, Path(annotation_id): Path<i32>) -> Response {
-    let lib = Commonplace::new(state.db.connection());
+pub async fn list_comments_by_annotation(
+    State(state): State<AppState>,
+    Path(annotation_id): Path<i32>,
+    Query(fields): Query<FieldsetParams>,
+) -> Response {
+    let lib = state.db.commonplace();
+    let fieldset = match Fieldset::parse(&fields, &[]) {
+        Ok(f) => f,
+        Err(e) => return bad_request(&e),
+    };
 
     match lib.list_comments_by_annotation(annotation_id).await {
-        Ok(comments) => success(comments),
+        Ok(comments) => success(fieldset.project_all(&comments)),
         Err(e) => {
             tracing::error!("Failed to list comments: {}", e);
             internal_error("Failed to list comments")
@@ -246,7 +890,7 @@ pub async fn update_comment(
     Path(id): Path<i32>,
     Json(payload): Json<UpdateComment>,
 ) -> Response {
-    let lib = Commonplace::new(state.db.connection());
+    let lib = state.db.commonplace();
 
     match lib.update_comment(id, payload).await {
         Ok(Some(comment)) => success(comment),
@@ -259,7 +903,7 @@ pub async fn update_comment(
 }
 
 pub async fn delete_comment(State(state): State<AppState>, Path(id): Path<i32>) -> Response {
-    let lib = Commonplace::new(state.db.connection());
+    let lib = state.db.commonplace();
 
     match lib.delete_comment(id).await {
         Ok(true) => (StatusCode::NO_CONTENT, ()).into_response(),
@@ -272,7 +916,7 @@ pub async fn delete_comment(State(state): State<AppState>, Path(id): Path<i32>)
 }
 
 pub async fn create_note(State(state): State<AppState>, Json(payload): Json<CreateNote>) -> Response {
-    let lib = Commonplace::new(state.db.connection());
+    let lib = state.db.commonplace();
 
     match lib.create_note(payload).await {
         Ok(note) => created(note),
@@ -284,7 +928,7 @@ pub async fn create_note(State(state): State<AppState>, Json(payload): Json<Crea
 }
 
 pub async fn get_note(State(state): State<AppState>, Path(id): Path<i32>) -> Response {
-    let lib = Commonplace::new(state.db.connection());
+    let lib = state.db.commonplace();
 
     match lib.get_note(id).await {
         Ok(Some(note)) => success(note),
@@ -296,11 +940,58 @@ pub async fn get_note(State(state): State<AppState>, Path(id): Path<i32>) -> Res
     }
 }
 
-pub async fn list_notes_by_resource(State(state): State<AppState>, Path(resource_id): Path<i32>) -> Response {
-    let lib = Commonplace::new(state.db.connection());
+/// The note's Markdown as a sanitized HTML fragment
+pub async fn render_note(State(state): State<AppState>, Path(id): Path<i32>) -> Response {
+    let lib = state.db.commonplace();
+
+    match lib.get_note(id).await {
+        Ok(Some(note)) => {
+            ([(header::CONTENT_TYPE, "text/html; charset=utf-8")], markdown::render(&note.content)).into_response()
+        }
+        Ok(None) => not_found("Note not found"),
+        Err(e) => {
+            tracing::error!("Failed to render note: {}", e);
+            internal_error("Failed to render note")
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NoteListParams {
+    /// Without a limit or cursor every note is returned
+    pub limit: Option<i32>,
+    pub cursor: Option<String>,
+}
+
+pub async fn list_notes_by_resource(
+    State(state): State<AppState>,
+    Path(resource_id): Path<i32>,
+    Query(params): Query<NoteListParams>,
+    Query(fields): Query<FieldsetParams>,
+) -> Response {
+    let lib = state.db.commonplace();
+    let fieldset = match Fieldset::parse(&fields, &[]) {
+        Ok(f) => f,
+        Err(e) => return bad_request(&e),
+    };
+
+    let listed = match (params.limit, params.cursor.as_deref()) {
+        (None, None) => lib.list_notes_by_resource(resource_id).await.map(|notes| (notes, None)),
+        (limit, cursor) => {
+            let limit = limit.unwrap_or(50).min(100);
+            let page = match parse_page(None, cursor) {
+                Ok(page) => page,
+                Err(e) => return bad_request(e),
+            };
+            lib.list_notes_page(resource_id, limit, &page).await.map(|notes| {
+                let next = cursor::next_cursor(&notes, limit);
+                (notes, next)
+            })
+        }
+    };
 
-    match lib.list_notes_by_resource(resource_id).await {
-        Ok(notes) => success(notes),
+    match listed {
+        Ok((notes, next)) => paginated(fieldset.project_all(&notes), next),
         Err(e) => {
             tracing::error!("Failed to list notes: {}", e);
             internal_error("Failed to list notes")
@@ -313,7 +1004,7 @@ pub async fn update_note(
     Path(id): Path<i32>,
     Json(payload): Json<UpdateNote>,
 ) -> Response {
-    let lib = Commonplace::new(state.db.connection());
+    let lib = state.db.commonplace();
 
     match lib.update_note(id, payload).await {
         Ok(Some(note)) => success(note),
@@ -325,8 +1016,46 @@ pub async fn update_note(
     }
 }
 
+#[derive(Debug, Deserialize)]
+pub struct MoveRequest {
+    pub resource_id: i32,
+}
+
+/// Checks that a note or word can be moved to `resource_id`
+async fn move_target(lib: &Commonplace<'_>, resource_id: i32) -> Result<(), Response> {
+    match lib.get_resource(resource_id).await {
+        Ok(Some(resource)) if resource.deleted_at.is_none() => Ok(()),
+        Ok(_) => Err(not_found("Resource not found")),
+        Err(e) => {
+            tracing::error!("Failed to get resource: {}", e);
+            Err(internal_error("Failed to move"))
+        }
+    }
+}
+
+/// Files a note under another resource, typically out of the inbox
+pub async fn move_note(
+    State(state): State<AppState>,
+    Path(id): Path<i32>,
+    Json(payload): Json<MoveRequest>,
+) -> Response {
+    let lib = state.db.commonplace();
+    if let Err(response) = move_target(&lib, payload.resource_id).await {
+        return response;
+    }
+
+    match lib.move_note(id, payload.resource_id).await {
+        Ok(Some(note)) => success(note),
+        Ok(None) => not_found("Note not found"),
+        Err(e) => {
+            tracing::error!("Failed to move note: {}", e);
+            internal_error("Failed to move note")
+        }
+    }
+}
+
 pub async fn delete_note(State(state): State<AppState>, Path(id): Path<i32>) -> Response {
-    let lib = Commonplace::new(state.db.connection());
+    let lib = state.db.commonplace();
 
     match lib.delete_note(id).await {
         Ok(true) => (StatusCode::NO_CONTENT, ()).into_response(),
@@ -338,8 +1067,17 @@ pub async fn delete_note(State(state): State<AppState>, Path(id): Path<i32>) ->
     }
 }
 
-pub async fn create_word(State(state): State<AppState>, Json(payload): Json<CreateWord>) -> Response {
-    let lib = Commonplace::new(state.db.connection());
+pub async fn create_word(State(state): State<AppState>, Json(mut payload): Json<CreateWord>) -> Response {
+    let lib = state.db.commonplace();
+
+    // A failed lookup still saves the word; the definition can be refreshed later
+    if payload.meaning.trim().is_empty() {
+        match state.dictionary.define(&payload.name).await {
+            Ok(Some(meaning)) => payload.meaning = meaning,
+            Ok(None) => {}
+            Err(e) => tracing::warn!("Failed to look up {}: {}", payload.name, e),
+        }
+    }
 
     match lib.create_word(payload).await {
         Ok(word) => created(word),
@@ -350,10 +1088,48 @@ pub async fn create_word(State(state): State<AppState>, Json(payload): Json<Crea
     }
 }
 
-pub async fn get_word(State(state): State<AppState>, Path(id): Path<i32>) -> Response {
-    let lib = Commonplace::new(state.db.connection());
+/// Replaces the word's meaning with the definition from the configured dictionary
+pub async fn refresh_word_definition(State(state): State<AppState>, Path(id): Path<i32>) -> Response {
+    if !state.dictionary.is_enabled() {
+        return bad_request("No dictionary provider is configured");
+    }
+    let lib = state.db.commonplace();
+    let word = match lib.get_word(id).await {
+        Ok(Some(word)) => word,
+        Ok(None) => return not_found("Word not found"),
+        Err(e) => {
+            tracing::error!("Failed to get word: {}", e);
+            return internal_error("Failed to refresh definition");
+        }
+    };
 
-    match lib.get_word(id).await {
+    let meaning = match state.dictionary.define(&word.name).await {
+        Ok(Some(meaning)) => meaning,
+        Ok(None) => return not_found("No definition found"),
+        Err(e) => {
+            tracing::warn!("Failed to look up {}: {}", word.name, e);
+            let error = format!("Dictionary lookup failed: {}", e);
+            return (StatusCode::BAD_GATEWAY, Json(ErrorResponse { error })).into_response();
+        }
+    };
+    let update = UpdateWord {
+        meaning: Some(meaning),
+        ..Default::default()
+    };
+    match lib.update_word(id, update).await {
+        Ok(Some(word)) => success(word),
+        Ok(None) => not_found("Word not found"),
+        Err(e) => {
+            tracing::error!("Failed to update word: {}", e);
+            internal_error("Failed to refresh definition")
+        }
+    }
+}
+
+pub async fn get_word(State(state): State<AppState>, Path(id): Path<i32>) -> Response {
+    let lib = state.db.commonplace();
+
+    match lib.get_word_with_occurrences(id).await {
         Ok(Some(word)) => success(word),
         Ok(None) => not_found("Word not found"),
         Err(e) => {
@@ -363,11 +1139,19 @@ pub async fn get_word(State(state): State<AppState>, Path(id): Path<i32>) -> Res
     }
 }
 
-pub async fn list_words_by_resource(State(state): State<AppState>, Path(resource_id): Path<i32>) -> Response {
-    let lib = Commonplace::new(state.db.connection());
+pub async fn list_words_by_resource(
+    State(state): State<AppState>,
+    Path(resource_id): Path<i32>,
+    Query(fields): Query<FieldsetParams>,
+) -> Response {
+    let lib = state.db.commonplace();
+    let fieldset = match Fieldset::parse(&fields, &[]) {
+        Ok(f) => f,
+        Err(e) => return bad_request(&e),
+    };
 
     match lib.list_words_by_resource(resource_id).await {
-        Ok(words) => success(words),
+        Ok(words) => success(fieldset.project_all(&words)),
         Err(e) => {
             tracing::error!("Failed to list words: {}", e);
             internal_error("Failed to list words")
@@ -375,8 +1159,272 @@ pub async fn list_words_by_resource(State(state): State<AppState>, Path(resource
     }
 }
 
-pub async fn search_words(State(state): State<AppState>, Query(params): Query<SearchParams>) -> Response {
-    let lib = Commonplace::new(state.db.connection());
+#[derive(Debug, Deserialize)]
+pub struct CompareParams {
+    pub with: i32,
+}
+
+/// A resource with its highlights, or `None` when there is no such resource
+async fn resource_with_annotations(
+    lib: &Commonplace<'_>,
+    id: i32,
+) -> anyhow::Result<Option<(Resource, Vec<Annotation>)>> {
+    let Some(resource) = lib.get_resource(id).await? else {
+        return Ok(None);
+    };
+    let annotations = lib.list_annotations_by_resource(id).await?;
+    Ok(Some((resource, annotations)))
+}
+
+pub async fn compare_resources(
+    State(state): State<AppState>,
+    Path(id): Path<i32>,
+    Query(params): Query<CompareParams>,
+) -> Response {
+    if params.with == id {
+        return bad_request("Cannot compare a resource with itself");
+    }
+    let lib = state.db.commonplace();
+    let (this, other) =
+        match tokio::try_join!(resource_with_annotations(&lib, id), resource_with_annotations(&lib, params.with)) {
+            Ok(sides) => sides,
+            Err(e) => {
+                tracing::error!("Failed to load resources {} and {} for comparison: {}", id, params.with, e);
+                return internal_error("Failed to compare resources");
+            }
+        };
+    let Some((resource, annotations)) = this else {
+        return not_found(&format!("Resource {} not found", id));
+    };
+    let Some((other, others)) = other else {
+        return not_found(&format!("Resource {} not found", params.with));
+    };
+    success(compare::compare(resource, annotations, other, others))
+}
+
+/// The newest snapshot of a website resource, as the HTML document it was stored as
+pub async fn get_resource_snapshot(State(state): State<AppState>, Path(id): Path<i32>) -> Response {
+    let snapshot = match state.db.commonplace().latest_resource_snapshot(id).await {
+        Ok(Some(snapshot)) => snapshot,
+        Ok(None) => return not_found("No snapshot of this resource"),
+        Err(e) => {
+            tracing::error!("Failed to get snapshot of resource {}: {}", id, e);
+            return internal_error("Failed to get snapshot");
+        }
+    };
+    match state.resumable.download_file(&snapshot.key).await {
+        Ok(body) => (
+            [
+                (header::CONTENT_TYPE, "text/html; charset=utf-8"),
+                (header::CONTENT_SECURITY_POLICY, snapshot::CONTENT_SECURITY_POLICY),
+            ],
+            body,
+        )
+            .into_response(),
+        Err(e) => {
+            tracing::error!("Failed to read snapshot {}: {}", snapshot.key, e);
+            internal_error("Failed to read snapshot")
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TermParams {
+    pub limit: Option<usize>,
+    #[serde(default)]
+    pub scoring: Scoring,
+}
+
+pub async fn list_resource_terms(
+    State(state): State<AppState>,
+    Path(id): Path<i32>,
+    Query(params): Query<TermParams>,
+) -> Response {
+    let lib = state.db.commonplace();
+    match lib.get_resource(id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return not_found("Resource not found"),
+        Err(e) => {
+            tracing::error!("Failed to get resource: {}", e);
+            return internal_error("Failed to get resource");
+        }
+    }
+
+    let texts = match lib.list_resource_texts().await {
+        Ok(texts) => texts,
+        Err(e) => {
+            tracing::error!("Failed to list resource texts: {}", e);
+            return internal_error("Failed to compute terms");
+        }
+    };
+    let limit = params.limit.unwrap_or(terms::DEFAULT_LIMIT).clamp(1, terms::MAX_LIMIT);
+    success(terms::top_terms(id, &texts, params.scoring, limit))
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct CaptureRequest {
+    /// Defaults to the resource title, which is the page url for synced websites
+    pub url: Option<String>,
+    /// Page text captured by the client, e.g. for pages behind a login; skips the fetch
+    pub content: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VersionParams {
+    /// Diff every version against this one instead of its predecessor
+    pub base: Option<i32>,
+    #[serde(default)]
+    pub content: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ResourceVersionView {
+    pub id: i32,
+    pub url: String,
+    pub content_hash: String,
+    pub captured_at: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+    /// Changes from the previous (or `base`) version; absent for the first capture
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub diff: Option<ContentDiff>,
+}
+
+impl ResourceVersionView {
+    fn new(version: ResourceVersion, against: Option<&ResourceVersion>, with_content: bool) -> Self {
+        let diff = against
+            .filter(|other| other.id != version.id)
+            .map(|other| capture::diff(&other.content, &version.content));
+        Self {
+            id: version.id,
+            url: version.url,
+            content_hash: version.content_hash,
+            captured_at: version.captured_at,
+            content: with_content.then_some(version.content),
+            diff,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct CaptureResult {
+    /// False when the page matched the latest version and nothing was stored
+    pub changed: bool,
+    pub version: Option<ResourceVersionView>,
+}
+
+/// Fetches the page (unless the client sent its text) and stores it as a new version
+/// when it differs from the last capture.
+pub async fn capture_resource(
+    State(state): State<AppState>,
+    Path(id): Path<i32>,
+    payload: Option<Json<CaptureRequest>>,
+) -> Response {
+    let lib = state.db.commonplace();
+    let payload = payload.map(|Json(p)| p).unwrap_or_default();
+
+    let resource = match lib.get_resource(id).await {
+        Ok(Some(resource)) => resource,
+        Ok(None) => return not_found("Resource not found"),
+        Err(e) => {
+            tracing::error!("Failed to get resource: {}", e);
+            return internal_error("Failed to get resource");
+        }
+    };
+    if resource.resource_type != ResourceType::Website {
+        return bad_request("Only website resources can be captured");
+    }
+
+    let url = payload.url.unwrap_or(resource.title);
+    let content = match payload.content {
+        Some(content) => content,
+        None if !capture::is_capturable(&url) => return bad_request("url must be an http(s) address"),
+        None => match PageFetcher::new().fetch(&url).await {
+            Ok(content) => content,
+            Err(e) => {
+                tracing::warn!("Failed to fetch {}: {}", url, e);
+                let error = format!("Failed to fetch page: {}", e);
+                return (StatusCode::BAD_GATEWAY, Json(ErrorResponse { error })).into_response();
+            }
+        },
+    };
+
+    let previous = match lib.list_resource_versions(id).await {
+        Ok(mut versions) => versions.pop(),
+        Err(e) => {
+            tracing::error!("Failed to list resource versions: {}", e);
+            return internal_error("Failed to capture resource");
+        }
+    };
+    match lib.record_resource_version(id, &url, &content).await {
+        Ok(Some(version)) => created(CaptureResult {
+            changed: true,
+            version: Some(ResourceVersionView::new(version, previous.as_ref(), false)),
+        }),
+        Ok(None) => success(CaptureResult {
+            changed: false,
+            version: previous.map(|v| ResourceVersionView::new(v, None, false)),
+        }),
+        Err(e) => {
+            tracing::error!("Failed to record resource version: {}", e);
+            internal_error("Failed to capture resource")
+        }
+    }
+}
+
+pub async fn list_resource_versions(
+    State(state): State<AppState>,
+    Path(id): Path<i32>,
+    Query(params): Query<VersionParams>,
+) -> Response {
+    let lib = state.db.commonplace();
+
+    match lib.get_resource(id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return not_found("Resource not found"),
+        Err(e) => {
+            tracing::error!("Failed to get resource: {}", e);
+            return internal_error("Failed to get resource");
+        }
+    }
+
+    let versions = match lib.list_resource_versions(id).await {
+        Ok(versions) => versions,
+        Err(e) => {
+            tracing::error!("Failed to list resource versions: {}", e);
+            return internal_error("Failed to list resource versions");
+        }
+    };
+    let base = match params.base {
+        Some(base_id) => match versions.iter().find(|v| v.id == base_id) {
+            Some(base) => Some(base.clone()),
+            None => return bad_request("base is not a version of this resource"),
+        },
+        None => None,
+    };
+
+    // Newest first, each diffed against the capture before it (or the base)
+    let mut views = Vec::with_capacity(versions.len());
+    let mut previous: Option<&ResourceVersion> = None;
+    for version in &versions {
+        let against = base.as_ref().or(previous);
+        views.push(ResourceVersionView::new(version.clone(), against, params.content));
+        previous = Some(version);
+    }
+    views.reverse();
+    success(views)
+}
+
+pub async fn search_words(
+    State(state): State<AppState>,
+    Query(params): Query<SearchParams>,
+    Query(fields): Query<FieldsetParams>,
+) -> Response {
+    let lib = state.db.commonplace();
+    let fieldset = match Fieldset::parse(&fields, &[]) {
+        Ok(f) => f,
+        Err(e) => return bad_request(&e),
+    };
 
     let query = match params.q {
         Some(q) if !q.is_empty() => q,
@@ -384,7 +1432,7 @@ pub async fn search_words(State(state): State<AppState>, Query(params): Query<Se
     };
 
     match lib.search_words(&query).await {
-        Ok(words) => success(words),
+        Ok(words) => success(fieldset.project_all(&words)),
         Err(e) => {
             tracing::error!("Failed to search words: {}", e);
             internal_error("Failed to search words")
@@ -392,12 +1440,139 @@ pub async fn search_words(State(state): State<AppState>, Query(params): Query<Se
     }
 }
 
+#[derive(Debug, Deserialize)]
+pub struct DueWordsParams {
+    pub limit: Option<i32>,
+}
+
+pub async fn list_due_words(State(state): State<AppState>, Query(params): Query<DueWordsParams>) -> Response {
+    let limit = params.limit.unwrap_or(20).clamp(1, 100);
+    let now = chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true);
+
+    match state.db.commonplace().list_due_words(&now, limit).await {
+        Ok(words) => success(words),
+        Err(e) => {
+            tracing::error!("Failed to list due words: {}", e);
+            internal_error("Failed to list due words")
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WordReviewRequest {
+    /// 0 (forgotten) to 5 (perfect recall)
+    pub grade: u8,
+}
+
+pub async fn review_word(
+    State(state): State<AppState>,
+    Path(id): Path<i32>,
+    Json(payload): Json<WordReviewRequest>,
+) -> Response {
+    if payload.grade > srs::MAX_GRADE {
+        return bad_request("grade must be between 0 and 5");
+    }
+
+    match state.db.commonplace().review_word(id, payload.grade).await {
+        Ok(Some(word)) => success(word),
+        Ok(None) => not_found("Word not found"),
+        Err(e) => {
+            tracing::error!("Failed to review word: {}", e);
+            internal_error("Failed to review word")
+        }
+    }
+}
+
+pub async fn move_word(
+    State(state): State<AppState>,
+    Path(id): Path<i32>,
+    Json(payload): Json<MoveRequest>,
+) -> Response {
+    let lib = state.db.commonplace();
+    if let Err(response) = move_target(&lib, payload.resource_id).await {
+        return response;
+    }
+
+    match lib.move_word(id, payload.resource_id).await {
+        Ok(Some(word)) => success(word),
+        Ok(None) => not_found("Word not found"),
+        Err(e) => {
+            tracing::error!("Failed to move word: {}", e);
+            internal_error("Failed to move word")
+        }
+    }
+}
+
+/// Records that the word also turned up in another resource
+pub async fn add_word_occurrence(
+    State(state): State<AppState>,
+    Path(id): Path<i32>,
+    Json(payload): Json<MoveRequest>,
+) -> Response {
+    let lib = state.db.commonplace();
+    let found = async {
+        let word = lib.get_word(id).await?;
+        let resource = lib.get_resource(payload.resource_id).await?;
+        Ok::<_, anyhow::Error>((word.is_some(), resource.is_some()))
+    }
+    .await;
+    match found {
+        Ok((false, _)) => return not_found("Word not found"),
+        Ok((_, false)) => return not_found("Resource not found"),
+        Ok(_) => {}
+        Err(e) => {
+            tracing::error!("Failed to look up word occurrence: {}", e);
+            return internal_error("Failed to add word occurrence");
+        }
+    }
+
+    if let Err(e) = lib.add_word_occurrence(id, payload.resource_id).await {
+        tracing::error!("Failed to add word occurrence: {}", e);
+        return internal_error("Failed to add word occurrence");
+    }
+    match lib.get_word_with_occurrences(id).await {
+        Ok(Some(word)) => created(word),
+        Ok(None) => not_found("Word not found"),
+        Err(e) => {
+            tracing::error!("Failed to get word: {}", e);
+            internal_error("Failed to get word")
+        }
+    }
+}
+
+pub async fn remove_word_occurrence(
+    State(state): State<AppState>,
+    Path((id, resource_id)): Path<(i32, i32)>,
+) -> Response {
+    let lib = state.db.commonplace();
+    match lib.get_word(id).await {
+        Ok(Some(word)) if word.resource_id == resource_id => {
+            return bad_request("The word was saved from this resource; move it instead");
+        }
+        Ok(Some(_)) => {}
+        Ok(None) => return not_found("Word not found"),
+        Err(e) => {
+            tracing::error!("Failed to get word: {}", e);
+            return internal_error("Failed to remove word occurrence");
+        }
+    }
+
+    match lib.remove_word_occurrence(id, resource_id).await {
+        Ok(true) => (StatusCode::NO_CONTENT, ()).into_response(),
+        Ok(false) => not_found("Word was not encountered in this resource"),
+        Err(e) => {
+            tracing::error!("Failed to remove word occurrence: {}", e);
+            internal_error("Failed to remove word occurrence")
+        }
+    }
+}
+
 pub async fn update_word(
     State(state): State<AppState>,
     Path(id): Path<i32>,
     Json(payload): Json<UpdateWord>,
 ) -> Response {
-    let lib = Commonplace::new(state.db.connection());
+    let lib = state.db.commonplace();
 
     match lib.update_word(id, payload).await {
         Ok(Some(word)) => success(word),
@@ -410,7 +1585,7 @@ pub async fn update_word(
 }
 
 pub async fn delete_word(State(state): State<AppState>, Path(id): Path<i32>) -> Response {
-    let lib = Commonplace::new(state.db.connection());
+    let lib = state.db.commonplace();
 
     match lib.delete_word(id).await {
         Ok(true) => (StatusCode::NO_CONTENT, ()).into_response(),
@@ -421,3 +1596,272 @@ pub async fn delete_word(State(state): State<AppState>, Path(id): Path<i32>) ->
         }
     }
 }
+
+/// Streams every resource, annotation, comment, note and word as one JSON archive
+pub async fn export_archive(State(state): State<AppState>) -> Response {
+    let now = chrono::Utc::now();
+    let stream = export::archive_stream(state.db.clone(), now.to_rfc3339_opts(chrono::SecondsFormat::Millis, true));
+    let disposition = format!("attachment; filename=\"commonplace-{}.json\"", now.format("%Y-%m-%d"));
+    (
+        StatusCode::OK,
+        [
+            (header::CONTENT_TYPE, "application/json".to_string()),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        Body::from_stream(stream),
+    )
+        .into_response()
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WordExportParams {
+    pub format: Option<String>,
+}
+
+pub async fn export_words(State(state): State<AppState>, Query(params): Query<WordExportParams>) -> Response {
+    let format = params.format.as_deref().unwrap_or("csv");
+    if format != "csv" {
+        return bad_request("Unsupported export format, expected csv");
+    }
+
+    let words = match state.db.commonplace().list_word_citations().await {
+        Ok(words) => words,
+        Err(e) => {
+            tracing::error!("Failed to list words for export: {}", e);
+            return internal_error("Failed to export words");
+        }
+    };
+    let body = match export::words_csv(&words) {
+        Ok(body) => body,
+        Err(e) => {
+            tracing::error!("Failed to write words CSV: {}", e);
+            return internal_error("Failed to export words");
+        }
+    };
+
+    let disposition = format!("attachment; filename=\"words-{}.csv\"", chrono::Utc::now().format("%Y-%m-%d"));
+    (
+        StatusCode::OK,
+        [
+            (header::CONTENT_TYPE, "text/csv; charset=utf-8".to_string()),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        body,
+    )
+        .into_response()
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ResourceExportParams {
+    pub format: Option<String>,
+    /// Export only favorite highlights
+    #[serde(default)]
+    pub favorite: bool,
+}
+
+pub async fn export_resource(
+    State(state): State<AppState>,
+    Path(id): Path<i32>,
+    Query(params): Query<ResourceExportParams>,
+    headers: HeaderMap,
+) -> Response {
+    let format = params.format.as_deref().unwrap_or("html");
+    if format != "html" && format != "epub" {
+        return bad_request("Unsupported export format, expected html or epub");
+    }
+
+    let mut resource = match state.db.commonplace().get_resource_full(id).await {
+        Ok(Some(resource)) => resource,
+        Ok(None) => return not_found("Resource not found"),
+        Err(e) => {
+            tracing::error!("Failed to get resource for export: {}", e);
+            return internal_error("Failed to export resource");
+        }
+    };
+    if params.favorite {
+        resource.annotations.retain(|item| item.annotation.is_favorite);
+    }
+    let ids: Vec<i32> = resource.annotations.iter().map(|item| item.annotation.id).collect();
+    if let Err(e) = state.db.commonplace().record_surfacings(&ids, Surfacing::Export).await {
+        tracing::warn!("Failed to record export surfacings: {}", e);
+    }
+    let today = chrono::Utc::now().format("%Y-%m-%d").to_string();
+    let origin = permalink::origin(&headers).unwrap_or_default();
+    let (body, content_type) = match format {
+        "epub" => (epub::resource_epub(&resource, &today, &origin), "application/epub+zip"),
+        _ => (
+            export::resource_html(&resource, &today, &origin).map(String::into_bytes),
+            "text/html; charset=utf-8",
+        ),
+    };
+    let body = match body {
+        Ok(body) => body,
+        Err(e) => {
+            tracing::error!("Failed to render resource export: {}", e);
+            return internal_error("Failed to export resource");
+        }
+    };
+
+    let disposition = format!("attachment; filename=\"resource-{}-{}.{}\"", id, today, format);
+    (
+        StatusCode::OK,
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        body,
+    )
+        .into_response()
+}
+
+/// Restores an archive from `GET /commonplace/export`. Rows already present are
+/// matched rather than duplicated, so the same archive can be imported repeatedly.
+pub async fn import_archive(State(state): State<AppState>, body: Bytes) -> Response {
+    let archive: Archive = match serde_json::from_slice(&body) {
+        Ok(archive) => archive,
+        Err(e) => return bad_request(&format!("Invalid archive: {}", e)),
+    };
+    if let Err(msg) = archive.validate() {
+        return bad_request(&msg);
+    }
+
+    match import::import_archive(&state.db, &archive).await {
+        Ok(summary) => success(summary),
+        Err(e) => {
+            tracing::error!("Failed to import archive: {}", e);
+            internal_error("Failed to import archive")
+        }
+    }
+}
+
+/// Imports a bookmarks HTML file exported by a browser. Its folders become tags.
+pub async fn import_bookmarks(State(state): State<AppState>, body: Bytes) -> Response {
+    let html = String::from_utf8_lossy(&body);
+    let bookmarks = bookmarks::parse(&html);
+    if bookmarks.is_empty() {
+        return bad_request("No bookmarks found; expected a browser's bookmarks HTML export");
+    }
+
+    match bookmarks::import(&state.db, &bookmarks).await {
+        Ok(summary) => success(summary),
+        Err(e) => {
+            tracing::error!("Failed to import bookmarks: {}", e);
+            internal_error("Failed to import bookmarks")
+        }
+    }
+}
+
+pub async fn list_resource_tags(State(state): State<AppState>, Path(id): Path<i32>) -> Response {
+    let lib = state.db.commonplace();
+    match lib.get_resource(id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return not_found("Resource not found"),
+        Err(e) => {
+            tracing::error!("Failed to get resource: {}", e);
+            return internal_error("Failed to get resource");
+        }
+    }
+
+    match lib.list_resource_tags(id).await {
+        Ok(tags) => success(tags),
+        Err(e) => {
+            tracing::error!("Failed to list tags of resource {}: {}", id, e);
+            internal_error("Failed to list resource tags")
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReviewParams {
+    pub count: Option<usize>,
+    /// Review only favorite highlights
+    #[serde(default)]
+    pub favorite: bool,
+}
+
+/// Today's highlights to review. The same selection is returned all day.
+pub async fn daily_review(State(state): State<AppState>, Query(params): Query<ReviewParams>) -> Response {
+    let count = params
+        .count
+        .unwrap_or(review::DEFAULT_COUNT)
+        .clamp(1, review::MAX_COUNT);
+    let today = chrono::Utc::now().date_naive();
+    let lib = state.db.commonplace();
+
+    let candidates = match lib.review_candidates(&review::day_start(today), params.favorite).await {
+        Ok(candidates) => candidates,
+        Err(e) => {
+            tracing::error!("Failed to list review candidates: {}", e);
+            return internal_error("Failed to build review");
+        }
+    };
+    let ids = review::select(&candidates, today, count);
+    if let Err(e) = lib.record_surfacings(&ids, Surfacing::Review).await {
+        tracing::warn!("Failed to record review surfacings: {}", e);
+    }
+    match lib.review_items(&ids).await {
+        Ok(items) => success(items),
+        Err(e) => {
+            tracing::error!("Failed to load review items: {}", e);
+            internal_error("Failed to build review")
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ColdParams {
+    pub limit: Option<i32>,
+}
+
+/// Highlights never reviewed, exported or digested, oldest first
+pub async fn list_cold(State(state): State<AppState>, Query(params): Query<ColdParams>) -> Response {
+    let limit = params
+        .limit
+        .unwrap_or(digest::DEFAULT_LIMIT)
+        .clamp(1, digest::MAX_LIMIT);
+    match digest::cold(&state.db, limit).await {
+        Ok(items) => success(items),
+        Err(e) => {
+            tracing::error!("Failed to list cold highlights: {}", e);
+            internal_error("Failed to list cold highlights")
+        }
+    }
+}
+
+pub async fn latest_digest(State(state): State<AppState>) -> Response {
+    match digest::latest(&state.db).await {
+        Ok(Some(digest)) => success(digest),
+        Ok(None) => not_found("No digest has been built yet"),
+        Err(e) => {
+            tracing::error!("Failed to get digest: {}", e);
+            internal_error("Failed to get digest")
+        }
+    }
+}
+
+/// Builds a digest now instead of waiting for the daily one; 204 when nothing is cold
+pub async fn create_digest(State(state): State<AppState>, Query(params): Query<ColdParams>) -> Response {
+    let size = params
+        .limit
+        .unwrap_or(digest::DEFAULT_LIMIT)
+        .clamp(1, digest::MAX_LIMIT);
+    match digest::build(&state.db, size).await {
+        Ok(Some(digest)) => created(digest),
+        Ok(None) => (StatusCode::NO_CONTENT, ()).into_response(),
+        Err(e) => {
+            tracing::error!("Failed to build digest: {}", e);
+            internal_error("Failed to build digest")
+        }
+    }
+}
+
+pub async fn mark_reviewed(State(state): State<AppState>, Path(id): Path<i32>) -> Response {
+    match state.db.commonplace().record_review(id).await {
+        Ok(Some(stats)) => created(stats),
+        Ok(None) => not_found("Annotation not found"),
+        Err(e) => {
+            tracing::error!("Failed to record review: {}", e);
+            internal_error("Failed to record review")
+        }
+    }
+}
@@ -0,0 +1,638 @@
+//! Restores an archive written by [`super::export`] (`POST /commonplace/import`).
+//! Every row is matched against what is already stored — by `external_id`, then
+//! `content_hash`, then its text within the same parent — so importing the same
+//! archive twice, or into a library it was taken from, does not duplicate anything.
+//! A matched row is only overwritten when the archived copy was updated more recently.
+
+use std::collections::HashMap;
+
+use anyhow::Result;
+use libsql::Connection;
+use serde::{Deserialize, Serialize};
+
+use super::export::{ARCHIVE_FORMAT, ARCHIVE_VERSION};
+use super::{Annotation, Comment, Note, Resource, Word};
+use crate::db::Database;
+
+/// Largest archive accepted by the import endpoint
+pub const MAX_ARCHIVE_BYTES: usize = 256 * 1024 * 1024;
+
+#[derive(Debug, Deserialize)]
+pub struct Archive {
+    pub format: String,
+    pub version: u32,
+    #[serde(default)]
+    pub exported_at: Option<String>,
+    #[serde(default)]
+    pub resources: Vec<Resource>,
+    #[serde(default)]
+    pub annotations: Vec<Annotation>,
+    #[serde(default)]
+    pub comments: Vec<Comment>,
+    #[serde(default)]
+    pub notes: Vec<Note>,
+    #[serde(default)]
+    pub words: Vec<Word>,
+}
+
+impl Archive {
+    /// Rejects documents that are not commonplace archives or come from a newer version
+    pub fn validate(&self) -> Result<(), String> {
+        if self.format != ARCHIVE_FORMAT {
+            return Err(format!("Unsupported archive format: {}", self.format));
+        }
+        if self.version == 0 || self.version > ARCHIVE_VERSION {
+            return Err(format!(
+                "Unsupported archive version {}; this server reads up to version {}",
+                self.version, ARCHIVE_VERSION
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct TableSummary {
+    pub created: usize,
+    pub updated: usize,
+    pub unchanged: usize,
+    /// Rows whose parent is neither in the archive nor in the database
+    pub skipped: usize,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct ImportSummary {
+    pub resources: TableSummary,
+    pub annotations: TableSummary,
+    pub comments: TableSummary,
+    pub notes: TableSummary,
+    pub words: TableSummary,
+}
+
+enum Upsert {
+    Created(i32),
+    Updated(i32),
+    Unchanged(i32),
+}
+
+impl TableSummary {
+    fn record(&mut self, upsert: Upsert) -> i32 {
+        match upsert {
+            Upsert::Created(id) => {
+                self.created += 1;
+                id
+            }
+            Upsert::Updated(id) => {
+                self.updated += 1;
+                id
+            }
+            Upsert::Unchanged(id) => {
+                self.unchanged += 1;
+                id
+            }
+        }
+    }
+}
+
+/// How an archived row is matched to a stored one
+struct Match<'a> {
+    table: &'static str,
+    /// Parent column and local parent id the match is restricted to
+    parent: Option<(&'static str, i32)>,
+    external_id: Option<&'a str>,
+    content_hash: Option<&'a str>,
+    /// Columns compared when the row has neither an external id nor a hash
+    fallback: Vec<(&'static str, libsql::Value)>,
+}
+
+/// Imports the archive in one transaction; either all of it is applied or none.
+pub async fn import_archive(db: &Database, archive: &Archive) -> Result<ImportSummary> {
+    let conn = &db.connection();
+    let importer = Importer { conn };
+    db.write(|| importer.run(archive)).await
+}
+
+struct Importer<'a> {
+    conn: &'a Connection,
+}
+
+impl Importer<'_> {
+    async fn run(&self, archive: &Archive) -> Result<ImportSummary> {
+        let mut summary = ImportSummary::default();
+
+        let mut resource_ids = HashMap::new();
+        for resource in &archive.resources {
+            let upsert = self.upsert_resource(resource).await?;
+            resource_ids.insert(resource.id, summary.resources.record(upsert));
+        }
+
+        let mut annotation_ids = HashMap::new();
+        for annotation in &archive.annotations {
+            let Some(&resource_id) = resource_ids.get(&annotation.resource_id) else {
+                summary.annotations.skipped += 1;
+                continue;
+            };
+            let upsert = self.upsert_annotation(annotation, resource_id).await?;
+            annotation_ids.insert(annotation.id, summary.annotations.record(upsert));
+        }
+
+        // Parents have lower ids than their replies, so they are mapped first
+        let mut comment_ids = HashMap::new();
+        for comment in &archive.comments {
+            match annotation_ids.get(&comment.annotation_id) {
+                Some(&annotation_id) => {
+                    let parent_id = comment.parent_comment_id.and_then(|id| comment_ids.get(&id).copied());
+                    let upsert = self.upsert_comment(comment, annotation_id, parent_id).await?;
+                    comment_ids.insert(comment.id, summary.comments.record(upsert));
+                }
+                None => summary.comments.skipped += 1,
+            }
+        }
+
+        for note in &archive.notes {
+            match resource_ids.get(&note.resource_id) {
+                Some(&resource_id) => {
+                    let upsert = self.upsert_note(note, resource_id).await?;
+                    summary.notes.record(upsert);
+                }
+                None => summary.notes.skipped += 1,
+            }
+        }
+
+        for word in &archive.words {
+            match resource_ids.get(&word.resource_id) {
+                Some(&resource_id) => {
+                    let upsert = self.upsert_word(word, resource_id).await?;
+                    summary.words.record(upsert);
+                }
+                None => summary.words.skipped += 1,
+            }
+        }
+
+        Ok(summary)
+    }
+
+    /// Id and `updated_at` of the stored row matching `m`, including soft-deleted rows
+    async fn find(&self, m: Match<'_>) -> Result<Option<(i32, String)>> {
+        let mut conditions = Vec::new();
+        let mut params: Vec<libsql::Value> = Vec::new();
+        if let Some((column, id)) = m.parent {
+            conditions.push(format!("{} = ?", column));
+            params.push(id.into());
+        }
+        if let Some(external_id) = m.external_id {
+            conditions.push("external_id = ?".to_string());
+            params.push(external_id.into());
+        } else if let Some(content_hash) = m.content_hash {
+            conditions.push("content_hash = ?".to_string());
+            params.push(content_hash.into());
+        } else {
+            for (column, value) in m.fallback {
+                conditions.push(format!("{} = ?", column));
+                params.push(value);
+            }
+        }
+
+        let query =
+            format!("SELECT id, updated_at FROM {} WHERE {} ORDER BY id LIMIT 1", m.table, conditions.join(" AND "));
+        let mut rows = self.conn.query(&query, params).await?;
+        match rows.next().await? {
+            Some(row) => Ok(Some((row.get(0)?, row.get(1)?))),
+            None => Ok(None),
+        }
+    }
+
+    async fn insert(&self, query: &str, params: Vec<libsql::Value>) -> Result<i32> {
+        let mut rows = self.conn.query(query, params).await?;
+        let row = rows
+            .next()
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("insert returned no row"))?;
+        Ok(row.get(0)?)
+    }
+
+    async fn upsert_resource(&self, resource: &Resource) -> Result<Upsert> {
+        let existing = self
+            .find(Match {
+                table: "resources",
+                parent: None,
+                external_id: resource.external_id.as_deref(),
+                content_hash: resource.content_hash.as_deref(),
+                fallback: vec![
+                    ("title", resource.title.clone().into()),
+                    ("type", resource.resource_type.as_str().into()),
+                ],
+            })
+            .await?;
+        let config = resource.config.as_ref().map(serde_json::to_string).transpose()?;
+
+        match existing {
+            Some((id, updated_at)) if resource.updated_at > updated_at => {
+                let query = r#"
+                    UPDATE resources
+                    SET title = ?, type = ?, content_hash = ?, config = ?, deleted_at = ?, updated_at = ?,
+                        status = ?, progress = ?, visibility = ?
+                    WHERE id = ?
+                "#;
+                let params = libsql::params![
+                    resource.title.clone(),
+                    resource.resource_type.as_str(),
+                    resource.content_hash.clone(),
+                    config,
+                    resource.deleted_at.clone(),
+                    resource.updated_at.clone(),
+                    resource.status.as_str(),
+                    resource.progress,
+                    resource.visibility.as_str(),
+                    id
+                ];
+                self.conn.execute(query, params).await?;
+                Ok(Upsert::Updated(id))
+            }
+            Some((id, _)) => Ok(Upsert::Unchanged(id)),
+            None => {
+                // Archived book ids belong to the library the archive was taken from;
+                // PDFs are linked by title the same way new resources are
+                let query = r#"
+                    INSERT INTO resources
+                        (title, type, external_id, content_hash, config, deleted_at, created_at, updated_at, status,
+                         progress, visibility, book_id)
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, CASE WHEN ?2 = 'pdf' THEN (
+                        SELECT id FROM books WHERE title = ?1 COLLATE NOCASE AND deleted_at IS NULL ORDER BY id LIMIT 1
+                    ) END)
+                    RETURNING id
+                "#;
+                let params = vec![
+                    resource.title.clone().into(),
+                    resource.resource_type.as_str().into(),
+                    resource.external_id.clone().into(),
+                    resource.content_hash.clone().into(),
+                    config.into(),
+                    resource.deleted_at.clone().into(),
+                    resource.created_at.clone().into(),
+                    resource.updated_at.clone().into(),
+                    resource.status.as_str().into(),
+                    resource.progress.into(),
+                    resource.visibility.into(),
+                ];
+                Ok(Upsert::Created(self.insert(query, params).await?))
+            }
+        }
+    }
+
+    async fn upsert_annotation(&self, annotation: &Annotation, resource_id: i32) -> Result<Upsert> {
+        let existing = self
+            .find(Match {
+                table: "annotations",
+                parent: Some(("resource_id", resource_id)),
+                external_id: annotation.external_id.as_deref(),
+                content_hash: annotation.content_hash.as_deref(),
+                fallback: vec![("text", annotation.text.clone().into())],
+            })
+            .await?;
+        let boundary = annotation.boundary.as_ref().map(serde_json::to_string).transpose()?;
+
+        match existing {
+            Some((id, updated_at)) if annotation.updated_at > updated_at => {
+                let query = r#"
+                    UPDATE annotations
+                    SET text = ?, color = ?, boundary = ?, content_hash = ?, deleted_at = ?, updated_at = ?, is_favorite = ?
+                    WHERE id = ?
+                "#;
+                let params = libsql::params![
+                    annotation.text.clone(),
+                    annotation.color.clone(),
+                    boundary,
+                    annotation.content_hash.clone(),
+                    annotation.deleted_at.clone(),
+                    annotation.updated_at.clone(),
+                    annotation.is_favorite as i32,
+                    id
+                ];
+                self.conn.execute(query, params).await?;
+                Ok(Upsert::Updated(id))
+            }
+            Some((id, _)) => Ok(Upsert::Unchanged(id)),
+            None => {
+                let query = r#"
+                    INSERT INTO annotations
+                        (resource_id, text, color, boundary, external_id, content_hash, deleted_at, created_at, updated_at,
+                         is_favorite, public_id)
+                    VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?,
+                            -- Keep the archived permalink unless another annotation here already uses it
+                            COALESCE(
+                                (SELECT ?11 WHERE ?11 != '' AND NOT EXISTS (SELECT 1 FROM annotations WHERE public_id = ?11)),
+                                lower(hex(randomblob(8)))
+                            ))
+                    RETURNING id
+                "#;
+                let params = vec![
+                    resource_id.into(),
+                    annotation.text.clone().into(),
+                    annotation.color.clone().into(),
+                    boundary.into(),
+                    annotation.external_id.clone().into(),
+                    annotation.content_hash.clone().into(),
+                    annotation.deleted_at.clone().into(),
+                    annotation.created_at.clone().into(),
+                    annotation.updated_at.clone().into(),
+                    (annotation.is_favorite as i32).into(),
+                    annotation.public_id.clone().into(),
+                ];
+                Ok(Upsert::Created(self.insert(query, params).await?))
+            }
+        }
+    }
+
+    async fn upsert_comment(&self, comment: &Comment, annotation_id: i32, parent_id: Option<i32>) -> Result<Upsert> {
+        let existing = self
+            .find(Match {
+                table: "comments",
+                parent: Some(("annotation_id", annotation_id)),
+                external_id: comment.external_id.as_deref(),
+                content_hash: comment.content_hash.as_deref(),
+                fallback: vec![("content", comment.content.clone().into())],
+            })
+            .await?;
+
+        match existing {
+            Some((id, updated_at)) if comment.updated_at > updated_at => {
+                let query = r#"
+                    UPDATE comments
+                    SET content = ?, content_hash = ?, deleted_at = ?, updated_at = ?, parent_comment_id = ?
+                    WHERE id = ?
+                "#;
+                let params = libsql::params![
+                    comment.content.clone(),
+                    comment.content_hash.clone(),
+                    comment.deleted_at.clone(),
+                    comment.updated_at.clone(),
+                    parent_id,
+                    id
+                ];
+                self.conn.execute(query, params).await?;
+                Ok(Upsert::Updated(id))
+            }
+            Some((id, _)) => Ok(Upsert::Unchanged(id)),
+            None => {
+                let query = r#"
+                    INSERT INTO comments
+                        (annotation_id, content, external_id, content_hash, deleted_at, created_at, updated_at,
+                         parent_comment_id)
+                    VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+                    RETURNING id
+                "#;
+                let params = vec![
+                    annotation_id.into(),
+                    comment.content.clone().into(),
+                    comment.external_id.clone().into(),
+                    comment.content_hash.clone().into(),
+                    comment.deleted_at.clone().into(),
+                    comment.created_at.clone().into(),
+                    comment.updated_at.clone().into(),
+                    parent_id.into(),
+                ];
+                Ok(Upsert::Created(self.insert(query, params).await?))
+            }
+        }
+    }
+
+    async fn upsert_note(&self, note: &Note, resource_id: i32) -> Result<Upsert> {
+        let existing = self
+            .find(Match {
+                table: "notes",
+                parent: Some(("resource_id", resource_id)),
+                external_id: note.external_id.as_deref(),
+                content_hash: note.content_hash.as_deref(),
+                fallback: vec![("content", note.content.clone().into())],
+            })
+            .await?;
+
+        match existing {
+            Some((id, updated_at)) if note.updated_at > updated_at => {
+                let query = r#"
+                    UPDATE notes SET content = ?, content_hash = ?, deleted_at = ?, updated_at = ?
+                    WHERE id = ?
+                "#;
+                let params = libsql::params![
+                    note.content.clone(),
+                    note.content_hash.clone(),
+                    note.deleted_at.clone(),
+                    note.updated_at.clone(),
+                    id
+                ];
+                self.conn.execute(query, params).await?;
+                Ok(Upsert::Updated(id))
+            }
+            Some((id, _)) => Ok(Upsert::Unchanged(id)),
+            None => {
+                let query = r#"
+                    INSERT INTO notes (resource_id, content, external_id, content_hash, deleted_at, created_at, updated_at)
+                    VALUES (?, ?, ?, ?, ?, ?, ?)
+                    RETURNING id
+                "#;
+                let params = vec![
+                    resource_id.into(),
+                    note.content.clone().into(),
+                    note.external_id.clone().into(),
+                    note.content_hash.clone().into(),
+                    note.deleted_at.clone().into(),
+                    note.created_at.clone().into(),
+                    note.updated_at.clone().into(),
+                ];
+                Ok(Upsert::Created(self.insert(query, params).await?))
+            }
+        }
+    }
+
+    /// Words have no external id or hash. The same word can be saved more than once with
+    /// different meanings, so both are compared.
+    async fn upsert_word(&self, word: &Word, resource_id: i32) -> Result<Upsert> {
+        let existing = self
+            .find(Match {
+                table: "words",
+                parent: Some(("resource_id", resource_id)),
+                external_id: None,
+                content_hash: None,
+                fallback: vec![
+                    ("name", word.name.clone().into()),
+                    ("meaning", word.meaning.clone().into()),
+                ],
+            })
+            .await?;
+
+        match existing {
+            Some((id, updated_at)) if word.updated_at > updated_at => {
+                let query = r#"
+                    UPDATE words SET meaning = ?, part_of_speech = ?, phonetic = ?, example = ?, updated_at = ?
+                    WHERE id = ?
+                "#;
+                let params = libsql::params![
+                    word.meaning.clone(),
+                    word.part_of_speech.clone(),
+                    word.phonetic.clone(),
+                    word.example.clone(),
+                    word.updated_at.clone(),
+                    id
+                ];
+                self.conn.execute(query, params).await?;
+                Ok(Upsert::Updated(id))
+            }
+            Some((id, _)) => Ok(Upsert::Unchanged(id)),
+            None => {
+                let query = r#"
+                    INSERT INTO words
+                        (resource_id, name, meaning, created_at, updated_at,
+                         ease, interval_days, repetitions, due_at, last_reviewed_at, part_of_speech, phonetic, example)
+                    VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+                    RETURNING id
+                "#;
+                let params = vec![
+                    resource_id.into(),
+                    word.name.clone().into(),
+                    word.meaning.clone().into(),
+                    word.created_at.clone().into(),
+                    word.updated_at.clone().into(),
+                    word.ease.into(),
+                    word.interval_days.into(),
+                    word.repetitions.into(),
+                    word.due_at.clone().into(),
+                    word.last_reviewed_at.clone().into(),
+                    word.part_of_speech.clone().into(),
+                    word.phonetic.clone().into(),
+                    word.example.clone().into(),
+                ];
+                Ok(Upsert::Created(self.insert(query, params).await?))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commonplace::export::archive_stream;
+    use crate::commonplace::{CreateAnnotation, CreateComment, CreateResource, CreateWord, ResourceType};
+    use crate::test_support::test_db;
+    use axum::body::Bytes;
+    use futures_util::TryStreamExt;
+
+    #[tokio::test]
+    async fn test_reimporting_an_export_is_a_no_op() {
+        let db = test_db().await;
+        let lib = db.commonplace();
+        let resource = lib
+            .create_resource(CreateResource {
+                title: "https://example.com/essay".to_string(),
+                resource_type: ResourceType::Website,
+                external_id: None,
+                content_hash: None,
+            })
+            .await
+            .unwrap();
+        lib.create_annotation(CreateAnnotation {
+            resource_id: resource.id,
+            text: "a sentence worth keeping".to_string(),
+            color: None,
+            boundary: None,
+            external_id: Some("light:1".to_string()),
+            content_hash: None,
+        })
+        .await
+        .unwrap();
+        lib.create_word(CreateWord {
+            resource_id: resource.id,
+            name: "apophenia".to_string(),
+            meaning: "seeing patterns in noise".to_string(),
+            part_of_speech: Some("noun".to_string()),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let chunks: Vec<Bytes> = archive_stream(db.clone(), "2024-01-01T00:00:00Z".to_string())
+            .try_collect()
+            .await
+            .unwrap();
+        let mut archive: Archive = serde_json::from_slice(&chunks.concat()).unwrap();
+        archive.validate().unwrap();
+        assert_eq!(archive.words[0].part_of_speech.as_deref(), Some("noun"));
+
+        let summary = import_archive(&db, &archive).await.unwrap();
+        assert_eq!(summary.resources.unchanged, 1);
+        assert_eq!(summary.annotations.unchanged, 1);
+        assert_eq!(summary.words.unchanged, 1);
+
+        // A newer archived copy wins, and rows whose parent is missing are skipped
+        archive.annotations[0].text = "a sentence worth keeping, edited".to_string();
+        archive.annotations[0].updated_at = "2999-01-01T00:00:00.000Z".to_string();
+        archive.words[0].resource_id = 999;
+        let summary = import_archive(&db, &archive).await.unwrap();
+        assert_eq!(summary.annotations.updated, 1);
+        assert_eq!(summary.words.skipped, 1);
+
+        let annotations = lib.list_annotations_by_resource(resource.id).await.unwrap();
+        assert_eq!(annotations.len(), 1);
+        assert_eq!(annotations[0].text, "a sentence worth keeping, edited");
+    }
+
+    #[tokio::test]
+    async fn test_comment_threads_survive_an_import() {
+        let db = test_db().await;
+        let lib = db.commonplace();
+        let resource = lib
+            .create_resource(CreateResource {
+                title: "https://example.com/thread".to_string(),
+                resource_type: ResourceType::Website,
+                external_id: None,
+                content_hash: None,
+            })
+            .await
+            .unwrap();
+        let annotation = lib
+            .create_annotation(CreateAnnotation {
+                resource_id: resource.id,
+                text: "a claim".to_string(),
+                color: None,
+                boundary: None,
+                external_id: None,
+                content_hash: None,
+            })
+            .await
+            .unwrap();
+        let mut parent_id = None;
+        for content in ["is this right?", "it is not", "it is, see chapter 2"] {
+            let comment = lib
+                .create_comment(CreateComment {
+                    annotation_id: annotation.id,
+                    content: content.to_string(),
+                    external_id: None,
+                    content_hash: None,
+                    parent_comment_id: parent_id,
+                })
+                .await
+                .unwrap();
+            parent_id = Some(comment.id);
+        }
+
+        let chunks: Vec<Bytes> = archive_stream(db.clone(), "2024-01-01T00:00:00Z".to_string())
+            .try_collect()
+            .await
+            .unwrap();
+        let archive: Archive = serde_json::from_slice(&chunks.concat()).unwrap();
+
+        let fresh = test_db().await;
+        let summary = import_archive(&fresh, &archive).await.unwrap();
+        assert_eq!(summary.comments.created, 3);
+        let lib = fresh.commonplace();
+        let annotation = &lib.get_resource_full(1).await.unwrap().unwrap().annotations[0];
+        let comments = lib.list_comments_by_annotation(annotation.annotation.id).await.unwrap();
+        assert_eq!(comments.len(), 1);
+        let thread: Vec<(usize, &str)> = comments[0]
+            .walk()
+            .into_iter()
+            .map(|(depth, c)| (depth, c.content.as_str()))
+            .collect();
+        assert_eq!(thread, vec![(0, "is this right?"), (1, "it is not"), (2, "it is, see chapter 2")]);
+    }
+}
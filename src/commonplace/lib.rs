@@ -4,8 +4,23 @@ use libsql::params::IntoParams;
 use serde::{Deserialize, Serialize};
 use serde_json::Value as JsonValue;
 use sha2::{Digest, Sha256};
-
+use std::collections::{HashMap, HashSet};
+
+use super::boundary::normalize as normalize_boundary;
+use super::cursor::Page;
+use super::digest::Surfacing;
+use super::export::ArchiveTable;
+use super::links::LinkStatus;
+use super::review::{ReviewCandidate, ReviewItem, ReviewStats};
+use super::srs::Schedule;
+use super::trash::Trash;
+use crate::events::{Event, EventBus};
+use crate::model::Visibility;
 use crate::sync::Syncable;
+use crate::sync::runs::{self, Change, Entity};
+
+/// External id of the inbox resource (see [`Commonplace::inbox`])
+pub const INBOX_EXTERNAL_ID: &str = "bibliotek:inbox";
 
 /// Compute SHA256 hash from multiple string parts
 fn compute_hash(parts: &[&str]) -> String {
@@ -50,6 +65,7 @@ impl ResourceType {
         }
     }
 
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(s: &str) -> Option<Self> {
         match s.to_lowercase().as_str() {
             "website" => Some(ResourceType::Website),
@@ -59,6 +75,36 @@ impl ResourceType {
     }
 }
 
+/// Where a resource stands in the read-later queue
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ResourceStatus {
+    #[default]
+    Unread,
+    Reading,
+    Done,
+}
+
+impl ResourceStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ResourceStatus::Unread => "unread",
+            ResourceStatus::Reading => "reading",
+            ResourceStatus::Done => "done",
+        }
+    }
+
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "unread" => Some(ResourceStatus::Unread),
+            "reading" => Some(ResourceStatus::Reading),
+            "done" => Some(ResourceStatus::Done),
+            _ => None,
+        }
+    }
+}
+
 /// Resource configuration stored as JSON
 /// For PDFs, this can contain chapter boundaries
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -81,6 +127,15 @@ pub struct Resource {
     pub deleted_at: Option<String>,
     pub created_at: String,
     pub updated_at: String,
+    /// Library book this resource was read from
+    pub book_id: Option<i32>,
+    #[serde(default)]
+    pub status: ResourceStatus,
+    /// How far through the resource the reader is, from 0 to 1
+    #[serde(default)]
+    pub progress: f64,
+    #[serde(default)]
+    pub visibility: Visibility,
 }
 
 impl Syncable for Resource {
@@ -107,6 +162,24 @@ pub struct Annotation {
     pub deleted_at: Option<String>,
     pub created_at: String,
     pub updated_at: String,
+    /// Archives from before favorites have no flag
+    #[serde(default)]
+    pub is_favorite: bool,
+    /// Id used in the annotation's permalink; archives from before permalinks have
+    /// none and get a new one on import
+    #[serde(default)]
+    pub public_id: String,
+}
+
+impl Annotation {
+    /// Server-relative link that opens the annotation in the reader
+    pub fn permalink(&self) -> String {
+        permalink(&self.public_id)
+    }
+}
+
+pub fn permalink(public_id: &str) -> String {
+    format!("/a/{}", public_id)
 }
 
 impl Syncable for Annotation {
@@ -131,6 +204,52 @@ pub struct Comment {
     pub deleted_at: Option<String>,
     pub created_at: String,
     pub updated_at: String,
+    /// Comment this one replies to
+    #[serde(default)]
+    pub parent_comment_id: Option<i32>,
+    /// Filled in by the listings that return threads
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub replies: Vec<Comment>,
+}
+
+impl Comment {
+    /// This comment and its replies, depth first, each with its depth in the thread
+    pub fn walk(&self) -> Vec<(usize, &Comment)> {
+        let mut out = Vec::new();
+        let mut stack = vec![(0, self)];
+        while let Some((depth, comment)) = stack.pop() {
+            out.push((depth, comment));
+            stack.extend(comment.replies.iter().rev().map(|reply| (depth + 1, reply)));
+        }
+        out
+    }
+}
+
+/// Nests `comments` under their parents, keeping their order. A reply whose parent is
+/// not in the list (deleted, or on another page) is kept at the top level.
+pub fn thread_comments(comments: Vec<Comment>) -> Vec<Comment> {
+    let ids: HashSet<i32> = comments.iter().map(|c| c.id).collect();
+    let mut replies: HashMap<i32, Vec<Comment>> = HashMap::new();
+    let mut roots = Vec::new();
+    for comment in comments {
+        match comment.parent_comment_id.filter(|parent| ids.contains(parent)) {
+            Some(parent) => replies.entry(parent).or_default().push(comment),
+            None => roots.push(comment),
+        }
+    }
+
+    fn attach(comment: &mut Comment, replies: &mut HashMap<i32, Vec<Comment>>) {
+        if let Some(mut children) = replies.remove(&comment.id) {
+            for child in &mut children {
+                attach(child, replies);
+            }
+            comment.replies = children;
+        }
+    }
+    for root in &mut roots {
+        attach(root, &mut replies);
+    }
+    roots
 }
 
 impl Syncable for Comment {
@@ -177,6 +296,145 @@ pub struct Word {
     pub meaning: String,
     pub created_at: String,
     pub updated_at: String,
+    /// Spaced repetition state, see [`super::srs`]
+    #[serde(default = "super::srs::default_ease")]
+    pub ease: f64,
+    #[serde(default)]
+    pub interval_days: i64,
+    #[serde(default)]
+    pub repetitions: i64,
+    /// None until the first review; the word is due immediately
+    #[serde(default)]
+    pub due_at: Option<String>,
+    #[serde(default)]
+    pub last_reviewed_at: Option<String>,
+    /// e.g. "noun"
+    #[serde(default)]
+    pub part_of_speech: Option<String>,
+    /// Pronunciation, e.g. "/ɡlɒs/"
+    #[serde(default)]
+    pub phonetic: Option<String>,
+    /// A sentence using the word
+    #[serde(default)]
+    pub example: Option<String>,
+}
+
+/// A resource a word was encountered in
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WordOccurrence {
+    pub resource_id: i32,
+    pub title: String,
+    #[serde(rename = "type")]
+    pub resource_type: ResourceType,
+    pub created_at: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WordWithOccurrences {
+    #[serde(flatten)]
+    pub word: Word,
+    /// Oldest first, starting with the resource the word was saved from
+    pub occurrences: Vec<WordOccurrence>,
+}
+
+/// A word with the resource it was found in and the highlight that contains it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WordCitation {
+    pub word: String,
+    pub meaning: String,
+    pub part_of_speech: Option<String>,
+    /// Title of the resource, which is the URL for websites
+    pub source: String,
+    /// Earliest highlight in the resource that contains the word
+    pub annotation: Option<String>,
+    pub example: Option<String>,
+    pub created_at: String,
+}
+
+/// Text of a website resource as captured at one point in time
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResourceVersion {
+    pub id: i32,
+    pub resource_id: i32,
+    pub url: String,
+    pub content: String,
+    pub content_hash: String,
+    pub captured_at: String,
+}
+
+/// Readable HTML of a website resource kept in the bucket, see [`super::snapshot`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResourceSnapshot {
+    pub id: i32,
+    pub resource_id: i32,
+    pub url: String,
+    pub key: String,
+    pub title: Option<String>,
+    pub size: i64,
+    pub created_at: String,
+}
+
+/// How an annotation relates to the one it links to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LinkRelation {
+    RelatesTo,
+    Contradicts,
+}
+
+impl LinkRelation {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LinkRelation::RelatesTo => "relates_to",
+            LinkRelation::Contradicts => "contradicts",
+        }
+    }
+
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "relates_to" => Some(LinkRelation::RelatesTo),
+            "contradicts" => Some(LinkRelation::Contradicts),
+            _ => None,
+        }
+    }
+}
+
+/// A directed link from `source_id` to `target_id`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnnotationLink {
+    pub id: i32,
+    pub source_id: i32,
+    pub target_id: i32,
+    pub relation: LinkRelation,
+    pub created_at: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkDirection {
+    /// The annotation links to the other one
+    Outgoing,
+    /// The other annotation links to this one (a backlink)
+    Incoming,
+}
+
+/// The annotation at the other end of a link, seen from one annotation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LinkedAnnotation {
+    pub link_id: i32,
+    pub relation: LinkRelation,
+    pub direction: LinkDirection,
+    pub created_at: String,
+    pub annotation: Annotation,
+}
+
+/// Outcome of an insert-or-update
+#[derive(Debug, Clone, PartialEq)]
+pub enum Upsert<T> {
+    Created(T),
+    Updated(T),
+    Unchanged(T),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -188,13 +446,18 @@ pub struct CreateResource {
     pub content_hash: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct UpdateResource {
     pub title: Option<String>,
     #[serde(rename = "type")]
     pub resource_type: Option<ResourceType>,
     pub content_hash: Option<String>,
     pub config: Option<ResourceConfig>,
+    pub status: Option<ResourceStatus>,
+    /// Setting progress without a status moves an unread resource to reading, and
+    /// any resource to done at 1
+    pub progress: Option<f64>,
+    pub visibility: Option<Visibility>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -215,12 +478,21 @@ pub struct UpdateAnnotation {
     pub content_hash: Option<String>,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateAnnotationLink {
+    pub target_id: i32,
+    pub relation: LinkRelation,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CreateComment {
     pub annotation_id: i32,
     pub content: String,
     pub external_id: Option<String>,
     pub content_hash: Option<String>,
+    /// Comment on the same annotation this one replies to
+    #[serde(default)]
+    pub parent_comment_id: Option<i32>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -243,39 +515,138 @@ pub struct UpdateNote {
     pub content_hash: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct CreateWord {
     pub resource_id: i32,
     pub name: String,
+    /// Looked up in the configured dictionary when empty
+    #[serde(default)]
     pub meaning: String,
+    #[serde(default)]
+    pub part_of_speech: Option<String>,
+    #[serde(default)]
+    pub phonetic: Option<String>,
+    #[serde(default)]
+    pub example: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Fields left out are kept; an empty part_of_speech, phonetic or example clears it
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct UpdateWord {
     pub name: Option<String>,
     pub meaning: Option<String>,
+    pub part_of_speech: Option<String>,
+    pub phonetic: Option<String>,
+    pub example: Option<String>,
+}
+
+/// Outcome of [`Commonplace::restore`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Restore {
+    NotFound,
+    /// The parent row (named by its table) has to be restored first
+    ParentDeleted {
+        entity: &'static str,
+        id: i32,
+    },
+    Restored,
 }
 
 pub struct Commonplace<'a> {
-    conn: &'a Connection,
+    conn: Connection,
+    events: Option<&'a EventBus>,
+    sync_run: Option<i64>,
 }
 
 impl<'a> Commonplace<'a> {
-    pub fn new(conn: &'a Connection) -> Self {
-        Self { conn }
+    pub fn new(conn: &Connection) -> Self {
+        Self {
+            conn: conn.clone(),
+            events: None,
+            sync_run: None,
+        }
+    }
+
+    /// Publishes annotation changes to `events`
+    pub fn with_events(mut self, events: &'a EventBus) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Journals the resources, annotations, comments and notes written through this
+    /// handle against sync run `run_id`, so the run can be rolled back
+    pub fn with_sync_run(mut self, run_id: i64) -> Self {
+        self.sync_run = Some(run_id);
+        self
+    }
+
+    pub fn connection(&self) -> &Connection {
+        &self.conn
+    }
+
+    pub fn sync_run(&self) -> Option<i64> {
+        self.sync_run
+    }
+
+    /// Records that a synced row matches its source's version hashing to `content_hash`,
+    /// without changing its content
+    pub async fn set_content_hash(&self, entity: Entity, id: i32, content_hash: &str) -> Result<()> {
+        self.journal(entity, id, Change::Updated).await?;
+        let query = format!("UPDATE {} SET content_hash = ? WHERE id = ?", entity.table());
+        self.conn.execute(&query, libsql::params![content_hash, id]).await?;
+        Ok(())
+    }
+
+    /// Updates and deletions must be journaled before they are made
+    async fn journal(&self, entity: Entity, id: i32, change: Change) -> Result<()> {
+        match self.sync_run {
+            Some(run_id) => runs::record_change(&self.conn, run_id, entity, id, change).await,
+            None => Ok(()),
+        }
+    }
+
+    fn publish(&self, event: Event) {
+        if let Some(events) = self.events {
+            events.publish(event);
+        }
+    }
+
+    fn publish_annotation(&self, annotation: &Annotation, created: bool) {
+        self.publish(Event::AnnotationUpserted {
+            annotation_id: annotation.id,
+            resource_id: annotation.resource_id,
+            created,
+            permalink: annotation.permalink(),
+        });
     }
 
+    /// PDF resources are linked to the library book with the same title, if there is one
     pub async fn create_resource(&self, input: CreateResource) -> Result<Resource> {
-        let query = r#"
-            INSERT INTO resources (title, type, external_id, content_hash)
-            VALUES (?, ?, ?, ?)
-            RETURNING id, title, type, external_id, content_hash, config, deleted_at, created_at, updated_at
-        "#;
+        match self.insert_resource(input, "").await? {
+            Some(resource) => Ok(resource),
+            None => anyhow::bail!("Failed to create resource"),
+        }
+    }
+
+    /// Inserts a resource unless `on_conflict` (an `ON CONFLICT ... DO NOTHING`
+    /// clause) skips it, in which case `None` is returned
+    async fn insert_resource(&self, input: CreateResource, on_conflict: &str) -> Result<Option<Resource>> {
+        let query = format!(
+            r#"
+            INSERT INTO resources (title, type, external_id, content_hash, book_id)
+            VALUES (?1, ?2, ?3, ?4, CASE WHEN ?2 = 'pdf' THEN (
+                SELECT id FROM books WHERE title = ?1 COLLATE NOCASE AND deleted_at IS NULL ORDER BY id LIMIT 1
+            ) END)
+            {}
+            RETURNING id, title, type, external_id, content_hash, config, deleted_at, created_at, updated_at, book_id, status, progress, visibility
+        "#,
+            on_conflict
+        );
 
         let mut rows = self
             .conn
             .query(
-                query,
+                &query,
                 libsql::params![
                     input.title,
                     input.resource_type.as_str(),
@@ -285,16 +656,86 @@ impl<'a> Commonplace<'a> {
             )
             .await?;
 
-        if let Some(row) = rows.next().await? {
-            Ok(self.row_to_resource(&row)?)
-        } else {
-            anyhow::bail!("Failed to create resource")
+        match rows.next().await? {
+            Some(row) => {
+                let resource = self.row_to_resource(&row)?;
+                self.journal(Entity::Resource, resource.id, Change::Created).await?;
+                self.publish(Event::ResourceCreated {
+                    resource_id: resource.id,
+                    resource_type: resource.resource_type.as_str().to_string(),
+                    title: resource.title.clone(),
+                });
+                Ok(Some(resource))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Creates the resource with `input.external_id`, or brings the live one up to
+    /// date when its content hash differs. The insert and the uniqueness check are
+    /// one statement, so concurrent syncs of the same item cannot both create it.
+    pub async fn upsert_resource(&self, input: CreateResource) -> Result<Upsert<Resource>> {
+        let Some(external_id) = input.external_id.clone() else {
+            anyhow::bail!("Upserting a resource needs an external id")
+        };
+        let (title, content_hash) = (input.title.clone(), input.content_hash.clone());
+        let on_conflict = "ON CONFLICT (external_id) WHERE external_id IS NOT NULL AND deleted_at IS NULL DO NOTHING";
+        if let Some(resource) = self.insert_resource(input, on_conflict).await? {
+            return Ok(Upsert::Created(resource));
+        }
+
+        let Some(existing) = self.find_resource_by_external_id(&external_id).await? else {
+            anyhow::bail!("Resource {} was deleted while upserting", external_id)
+        };
+        if content_hash.is_some() && existing.content_hash == content_hash {
+            return Ok(Upsert::Unchanged(existing));
+        }
+
+        let query = r#"
+            UPDATE resources
+            SET title = ?, content_hash = ?, updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
+            WHERE id = ?
+        "#;
+        self.journal(Entity::Resource, existing.id, Change::Updated).await?;
+        self.conn
+            .execute(query, libsql::params![title, content_hash, existing.id])
+            .await?;
+        match self.get_resource(existing.id).await? {
+            Some(resource) => Ok(Upsert::Updated(resource)),
+            None => anyhow::bail!("Resource {} was deleted while upserting", external_id),
+        }
+    }
+
+    /// The live website resource for `url`, created if there is none. Returns
+    /// whether it was created.
+    pub async fn find_or_create_website(&self, url: &str, content_hash: Option<String>) -> Result<(Resource, bool)> {
+        let input = CreateResource {
+            title: url.to_string(),
+            resource_type: ResourceType::Website,
+            external_id: None,
+            content_hash,
+        };
+        let on_conflict = "ON CONFLICT (title) WHERE type = 'website' AND deleted_at IS NULL DO NOTHING";
+        if let Some(resource) = self.insert_resource(input, on_conflict).await? {
+            return Ok((resource, true));
+        }
+
+        let query = r#"
+            SELECT id, title, type, external_id, content_hash, config, deleted_at, created_at, updated_at, book_id, status, progress, visibility
+            FROM resources WHERE title = ? AND type = 'website' AND deleted_at IS NULL
+        "#;
+        match self
+            .query_one(query, libsql::params![url], |row| self.row_to_resource(row))
+            .await?
+        {
+            Some(resource) => Ok((resource, false)),
+            None => anyhow::bail!("Website {} was deleted while creating it", url),
         }
     }
 
     pub async fn get_resource(&self, id: i32) -> Result<Option<Resource>> {
         let query = r#"
-            SELECT id, title, type, external_id, content_hash, config, deleted_at, created_at, updated_at
+            SELECT id, title, type, external_id, content_hash, config, deleted_at, created_at, updated_at, book_id, status, progress, visibility
             FROM resources WHERE id = ? AND deleted_at IS NULL
         "#;
         self.query_one(query, libsql::params![id], |row| self.row_to_resource(row))
@@ -303,16 +744,52 @@ impl<'a> Commonplace<'a> {
 
     pub async fn find_resource_by_title(&self, title: &str) -> Result<Option<Resource>> {
         let query = r#"
-            SELECT id, title, type, external_id, content_hash, config, deleted_at, created_at, updated_at
+            SELECT id, title, type, external_id, content_hash, config, deleted_at, created_at, updated_at, book_id, status, progress, visibility
             FROM resources WHERE title = ? AND deleted_at IS NULL
         "#;
         self.query_one(query, libsql::params![title], |row| self.row_to_resource(row))
             .await
     }
 
+    /// A resource read from the library book with this ISBN, hyphens ignored
+    pub async fn find_resource_by_isbn(&self, isbn: &str) -> Result<Option<Resource>> {
+        let query = r#"
+            SELECT r.id, r.title, r.type, r.external_id, r.content_hash, r.config, r.deleted_at, r.created_at, r.updated_at, r.book_id, r.status, r.progress, r.visibility
+            FROM resources r
+            JOIN books b ON b.id = r.book_id
+            WHERE REPLACE(b.isbn, '-', '') = REPLACE(?, '-', '') AND b.isbn != '' AND r.deleted_at IS NULL
+            ORDER BY r.created_at ASC
+        "#;
+        self.query_one(query, libsql::params![isbn], |row| self.row_to_resource(row))
+            .await
+    }
+
+    /// The resource notes and words without a source are filed under, created on
+    /// first use
+    pub async fn inbox(&self) -> Result<Resource> {
+        if let Some(inbox) = self.find_resource_by_external_id(INBOX_EXTERNAL_ID).await? {
+            return Ok(inbox);
+        }
+        self.create_resource(CreateResource {
+            title: "Inbox".to_string(),
+            resource_type: ResourceType::Website,
+            external_id: Some(INBOX_EXTERNAL_ID.to_string()),
+            content_hash: None,
+        })
+        .await
+    }
+
+    /// The inbox with everything still filed in it
+    pub async fn list_inbox(&self) -> Result<Inbox> {
+        let resource = self.inbox().await?;
+        let notes = self.list_notes_by_resource(resource.id).await?;
+        let words = self.list_words_by_resource(resource.id).await?;
+        Ok(Inbox { resource, notes, words })
+    }
+
     pub async fn find_resource_by_external_id(&self, external_id: &str) -> Result<Option<Resource>> {
         let query = r#"
-            SELECT id, title, type, external_id, content_hash, config, deleted_at, created_at, updated_at
+            SELECT id, title, type, external_id, content_hash, config, deleted_at, created_at, updated_at, book_id, status, progress, visibility
             FROM resources WHERE external_id = ? AND deleted_at IS NULL
         "#;
         self.query_one(query, libsql::params![external_id], |row| self.row_to_resource(row))
@@ -321,7 +798,7 @@ impl<'a> Commonplace<'a> {
 
     pub async fn find_resources_by_source_prefix(&self, prefix: &str) -> Result<Vec<Resource>> {
         let query = r#"
-            SELECT id, title, type, external_id, content_hash, config, deleted_at, created_at, updated_at
+            SELECT id, title, type, external_id, content_hash, config, deleted_at, created_at, updated_at, book_id, status, progress, visibility
             FROM resources WHERE external_id LIKE ? AND deleted_at IS NULL
         "#;
 
@@ -336,37 +813,134 @@ impl<'a> Commonplace<'a> {
         Ok(resources)
     }
 
-    pub async fn list_resources(&self, limit: i32, offset: i32, resource_type: Option<&str>) -> Result<Vec<Resource>> {
+    /// The `limit` most recently deleted rows of each type
+    pub async fn list_trash(&self, limit: i32) -> Result<Trash> {
+        let query = r#"
+            SELECT id, title, type, external_id, content_hash, config, deleted_at, created_at, updated_at, book_id, status, progress, visibility
+            FROM resources WHERE deleted_at IS NOT NULL
+            ORDER BY deleted_at DESC LIMIT ?
+        "#;
+        let mut rows = self.conn.query(query, libsql::params![limit]).await?;
         let mut resources = Vec::new();
+        while let Some(row) = rows.next().await? {
+            resources.push(self.row_to_resource(&row)?);
+        }
 
-        if let Some(rtype) = resource_type {
-            let query = r#"
-                SELECT id, title, type, external_id, content_hash, config, deleted_at, created_at, updated_at
-                FROM resources
-                WHERE type = ? AND deleted_at IS NULL
-                ORDER BY created_at DESC
-                LIMIT ? OFFSET ?
-            "#;
+        let query = r#"
+            SELECT id, resource_id, text, color, boundary, external_id, content_hash, deleted_at, created_at, updated_at, is_favorite, public_id
+            FROM annotations WHERE deleted_at IS NOT NULL
+            ORDER BY deleted_at DESC LIMIT ?
+        "#;
+        let mut rows = self.conn.query(query, libsql::params![limit]).await?;
+        let mut annotations = Vec::new();
+        while let Some(row) = rows.next().await? {
+            annotations.push(self.row_to_annotation(&row)?);
+        }
 
-            let mut rows = self.conn.query(query, libsql::params![rtype, limit, offset]).await?;
+        let query = r#"
+            SELECT id, annotation_id, content, external_id, content_hash, deleted_at, created_at, updated_at, parent_comment_id
+            FROM comments WHERE deleted_at IS NOT NULL
+            ORDER BY deleted_at DESC LIMIT ?
+        "#;
+        let mut rows = self.conn.query(query, libsql::params![limit]).await?;
+        let mut comments = Vec::new();
+        while let Some(row) = rows.next().await? {
+            comments.push(self.row_to_comment(&row)?);
+        }
 
-            while let Some(row) = rows.next().await? {
-                resources.push(self.row_to_resource(&row)?);
-            }
-        } else {
-            let query = r#"
-                SELECT id, title, type, external_id, content_hash, config, deleted_at, created_at, updated_at
-                FROM resources
-                WHERE deleted_at IS NULL
-                ORDER BY created_at DESC
-                LIMIT ? OFFSET ?
-            "#;
+        let query = r#"
+            SELECT id, resource_id, content, external_id, content_hash, deleted_at, created_at, updated_at
+            FROM notes WHERE deleted_at IS NOT NULL
+            ORDER BY deleted_at DESC LIMIT ?
+        "#;
+        let mut rows = self.conn.query(query, libsql::params![limit]).await?;
+        let mut notes = Vec::new();
+        while let Some(row) = rows.next().await? {
+            notes.push(self.row_to_note(&row)?);
+        }
 
-            let mut rows = self.conn.query(query, libsql::params![limit, offset]).await?;
+        Ok(Trash {
+            resources,
+            annotations,
+            comments,
+            notes,
+        })
+    }
 
-            while let Some(row) = rows.next().await? {
-                resources.push(self.row_to_resource(&row)?);
-            }
+    /// Live resources linked to a library book
+    pub async fn list_resources_by_book(&self, book_id: i32) -> Result<Vec<Resource>> {
+        let query = r#"
+            SELECT id, title, type, external_id, content_hash, config, deleted_at, created_at, updated_at, book_id, status, progress, visibility
+            FROM resources
+            WHERE book_id = ? AND deleted_at IS NULL
+            ORDER BY created_at ASC
+        "#;
+
+        let mut rows = self.conn.query(query, libsql::params![book_id]).await?;
+        let mut resources = Vec::new();
+        while let Some(row) = rows.next().await? {
+            resources.push(self.row_to_resource(&row)?);
+        }
+        Ok(resources)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub async fn list_resources(
+        &self,
+        limit: i32,
+        page: &Page,
+        resource_type: Option<&str>,
+        link_status: Option<LinkStatus>,
+        status: Option<ResourceStatus>,
+        visibility: Option<Visibility>,
+        tag: Option<&str>,
+    ) -> Result<Vec<Resource>> {
+        let mut conditions = vec!["deleted_at IS NULL"];
+        let mut params: Vec<libsql::Value> = Vec::new();
+        if let Some(rtype) = resource_type {
+            conditions.push("type = ?");
+            params.push(rtype.into());
+        }
+        if let Some(status) = status {
+            conditions.push("status = ?");
+            params.push(status.as_str().into());
+        }
+        if let Some(visibility) = visibility {
+            conditions.push("visibility = ?");
+            params.push(visibility.into());
+        }
+        if let Some(status) = link_status {
+            conditions.push("id IN (SELECT resource_id FROM resource_links WHERE status = ?)");
+            params.push(status.as_str().into());
+        }
+        if let Some(tag) = tag {
+            conditions.push(
+                "id IN (SELECT rt.resource_id FROM resource_tags rt JOIN tags t ON t.id = rt.tag_id WHERE t.name = ?)",
+            );
+            params.push(tag.into());
+        }
+        if let Some((condition, values)) = page.condition() {
+            conditions.push(condition);
+            params.extend(values);
+        }
+        params.push(limit.into());
+        params.push(page.offset().into());
+
+        let query = format!(
+            r#"
+            SELECT id, title, type, external_id, content_hash, config, deleted_at, created_at, updated_at, book_id, status, progress, visibility
+            FROM resources
+            WHERE {}
+            ORDER BY created_at DESC, id DESC
+            LIMIT ? OFFSET ?
+        "#,
+            conditions.join(" AND ")
+        );
+
+        let mut rows = self.conn.query(&query, params).await?;
+        let mut resources = Vec::new();
+        while let Some(row) = rows.next().await? {
+            resources.push(self.row_to_resource(&row)?);
         }
 
         Ok(resources)
@@ -397,6 +971,25 @@ impl<'a> Commonplace<'a> {
             let json_str = serde_json::to_string(config)?;
             params.push(json_str.into());
         }
+        if let Some(status) = input.status {
+            updates.push("status = ?");
+            params.push(status.as_str().into());
+        }
+        if let Some(visibility) = input.visibility {
+            updates.push("visibility = ?");
+            params.push(visibility.into());
+        }
+        if let Some(progress) = input.progress {
+            if input.status.is_none() {
+                updates.push(
+                    "status = CASE WHEN ? >= 1 THEN 'done' WHEN status = 'unread' AND ? > 0 THEN 'reading' ELSE status END",
+                );
+                params.push(progress.into());
+                params.push(progress.into());
+            }
+            updates.push("progress = ?");
+            params.push(progress.into());
+        }
 
         if updates.is_empty() {
             return self.get_resource(id).await;
@@ -407,10 +1000,21 @@ impl<'a> Commonplace<'a> {
 
         let query = format!("UPDATE resources SET {} WHERE id = ? AND deleted_at IS NULL", updates.join(", "));
 
+        self.journal(Entity::Resource, id, Change::Updated).await?;
         self.conn.execute(&query, params).await?;
         self.get_resource(id).await
     }
 
+    /// Links the resource to a library book, or unlinks it when `book_id` is None
+    pub async fn set_resource_book(&self, id: i32, book_id: Option<i32>) -> Result<Option<Resource>> {
+        let query = r#"
+            UPDATE resources SET book_id = ?, updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
+            WHERE id = ? AND deleted_at IS NULL
+        "#;
+        self.conn.execute(query, libsql::params![book_id, id]).await?;
+        self.get_resource(id).await
+    }
+
     pub async fn delete_resource(&self, id: i32) -> Result<bool> {
         let result = self
             .conn
@@ -438,6 +1042,10 @@ impl<'a> Commonplace<'a> {
             deleted_at: row.get(6)?,
             created_at: row.get(7)?,
             updated_at: row.get(8)?,
+            book_id: row.get(9)?,
+            status: ResourceStatus::from_str(&row.get::<String>(10)?).unwrap_or_default(),
+            progress: row.get(11)?,
+            visibility: Visibility::from_str(&row.get::<String>(12)?).unwrap_or_default(),
         })
     }
 
@@ -458,17 +1066,22 @@ impl<'a> Commonplace<'a> {
             SET deleted_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
             WHERE id = ?
         "#;
+        self.journal(Entity::Resource, id, Change::Deleted).await?;
         let result = self.conn.execute(query, libsql::params![id]).await?;
         Ok(result > 0)
     }
 
     pub async fn create_annotation(&self, input: CreateAnnotation) -> Result<Annotation> {
-        let boundary_json = input.boundary.as_ref().map(|b| serde_json::to_string(b)).transpose()?;
+        let boundary_json = input
+            .boundary
+            .as_ref()
+            .map(|b| serde_json::to_string(&normalize_boundary(b, Some(&input.text))))
+            .transpose()?;
 
         let query = r#"
-            INSERT INTO annotations (resource_id, text, color, boundary, external_id, content_hash)
-            VALUES (?, ?, ?, ?, ?, ?)
-            RETURNING id, resource_id, text, color, boundary, external_id, content_hash, deleted_at, created_at, updated_at
+            INSERT INTO annotations (resource_id, text, color, boundary, external_id, content_hash, public_id)
+            VALUES (?, ?, ?, ?, ?, ?, lower(hex(randomblob(8))))
+            RETURNING id, resource_id, text, color, boundary, external_id, content_hash, deleted_at, created_at, updated_at, is_favorite, public_id
         "#;
 
         let mut rows = self
@@ -487,7 +1100,10 @@ impl<'a> Commonplace<'a> {
             .await?;
 
         if let Some(row) = rows.next().await? {
-            Ok(self.row_to_annotation(&row)?)
+            let annotation = self.row_to_annotation(&row)?;
+            self.journal(Entity::Annotation, annotation.id, Change::Created).await?;
+            self.publish_annotation(&annotation, true);
+            Ok(annotation)
         } else {
             anyhow::bail!("Failed to create annotation")
         }
@@ -495,7 +1111,7 @@ impl<'a> Commonplace<'a> {
 
     pub async fn get_annotation(&self, id: i32) -> Result<Option<Annotation>> {
         let query = r#"
-            SELECT id, resource_id, text, color, boundary, external_id, content_hash, deleted_at, created_at, updated_at
+            SELECT id, resource_id, text, color, boundary, external_id, content_hash, deleted_at, created_at, updated_at, is_favorite, public_id
             FROM annotations WHERE id = ? AND deleted_at IS NULL
         "#;
 
@@ -508,13 +1124,28 @@ impl<'a> Commonplace<'a> {
         }
     }
 
-    pub async fn find_annotation_by_external_id(&self, external_id: &str) -> Result<Option<Annotation>> {
+    pub async fn find_annotation_by_public_id(&self, public_id: &str) -> Result<Option<Annotation>> {
         let query = r#"
-            SELECT id, resource_id, text, color, boundary, external_id, content_hash, deleted_at, created_at, updated_at
-            FROM annotations WHERE external_id = ? AND deleted_at IS NULL
+            SELECT id, resource_id, text, color, boundary, external_id, content_hash, deleted_at, created_at, updated_at, is_favorite, public_id
+            FROM annotations WHERE public_id = ? AND deleted_at IS NULL
         "#;
 
-        let mut rows = self.conn.query(query, libsql::params![external_id]).await?;
+        let mut rows = self.conn.query(query, libsql::params![public_id]).await?;
+
+        if let Some(row) = rows.next().await? {
+            Ok(Some(self.row_to_annotation(&row)?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub async fn find_annotation_by_external_id(&self, external_id: &str) -> Result<Option<Annotation>> {
+        let query = r#"
+            SELECT id, resource_id, text, color, boundary, external_id, content_hash, deleted_at, created_at, updated_at, is_favorite, public_id
+            FROM annotations WHERE external_id = ? AND deleted_at IS NULL
+        "#;
+
+        let mut rows = self.conn.query(query, libsql::params![external_id]).await?;
 
         if let Some(row) = rows.next().await? {
             Ok(Some(self.row_to_annotation(&row)?))
@@ -531,14 +1162,14 @@ impl<'a> Commonplace<'a> {
         let pattern = format!("{}:%", prefix);
         let mut rows = if let Some(rid) = resource_id {
             let query = r#"
-                SELECT id, resource_id, text, color, boundary, external_id, content_hash, deleted_at, created_at, updated_at
+                SELECT id, resource_id, text, color, boundary, external_id, content_hash, deleted_at, created_at, updated_at, is_favorite, public_id
                 FROM annotations
                 WHERE external_id LIKE ? AND deleted_at IS NULL AND resource_id = ?
             "#;
             self.conn.query(query, libsql::params![pattern, rid]).await?
         } else {
             let query = r#"
-                SELECT id, resource_id, text, color, boundary, external_id, content_hash, deleted_at, created_at, updated_at
+                SELECT id, resource_id, text, color, boundary, external_id, content_hash, deleted_at, created_at, updated_at, is_favorite, public_id
                 FROM annotations
                 WHERE external_id LIKE ? AND deleted_at IS NULL
             "#;
@@ -555,9 +1186,10 @@ impl<'a> Commonplace<'a> {
 
     pub async fn list_annotations_by_resource(&self, resource_id: i32) -> Result<Vec<Annotation>> {
         let query = r#"
-            SELECT id, resource_id, text, color, boundary, external_id, content_hash, deleted_at, created_at, updated_at
+            SELECT id, resource_id, text, color, boundary, external_id, content_hash, deleted_at, created_at, updated_at, is_favorite, public_id
             FROM annotations
             WHERE resource_id = ? AND deleted_at IS NULL
+                AND resource_id IN (SELECT id FROM resources WHERE deleted_at IS NULL)
             ORDER BY created_at ASC
         "#;
 
@@ -571,6 +1203,27 @@ impl<'a> Commonplace<'a> {
         Ok(annotations)
     }
 
+    /// Sets the favorite flag, or flips it when `favorite` is `None`
+    pub async fn set_annotation_favorite(&self, id: i32, favorite: Option<bool>) -> Result<Option<Annotation>> {
+        if self.get_annotation(id).await?.is_none() {
+            return Ok(None);
+        }
+        let query = r#"
+            UPDATE annotations
+            SET is_favorite = COALESCE(?, 1 - is_favorite), updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
+            WHERE id = ? AND deleted_at IS NULL
+        "#;
+        self.journal(Entity::Annotation, id, Change::Updated).await?;
+        self.conn
+            .execute(query, libsql::params![favorite.map(|f| f as i32), id])
+            .await?;
+        let annotation = self.get_annotation(id).await?;
+        if let Some(annotation) = &annotation {
+            self.publish_annotation(annotation, false);
+        }
+        Ok(annotation)
+    }
+
     pub async fn update_annotation(&self, id: i32, input: UpdateAnnotation) -> Result<Option<Annotation>> {
         if self.get_annotation(id).await?.is_none() {
             return Ok(None);
@@ -589,7 +1242,7 @@ impl<'a> Commonplace<'a> {
         }
         if let Some(boundary) = &input.boundary {
             updates.push("boundary = ?");
-            let json_str = serde_json::to_string(boundary)?;
+            let json_str = serde_json::to_string(&normalize_boundary(boundary, input.text.as_deref()))?;
             params.push(json_str.into());
         }
         if let Some(content_hash) = &input.content_hash {
@@ -606,8 +1259,13 @@ impl<'a> Commonplace<'a> {
 
         let query = format!("UPDATE annotations SET {} WHERE id = ? AND deleted_at IS NULL", updates.join(", "));
 
+        self.journal(Entity::Annotation, id, Change::Updated).await?;
         self.conn.execute(&query, params).await?;
-        self.get_annotation(id).await
+        let annotation = self.get_annotation(id).await?;
+        if let Some(annotation) = &annotation {
+            self.publish_annotation(annotation, false);
+        }
+        Ok(annotation)
     }
 
     pub async fn delete_annotation(&self, id: i32) -> Result<bool> {
@@ -633,6 +1291,8 @@ impl<'a> Commonplace<'a> {
             deleted_at: row.get(7)?,
             created_at: row.get(8)?,
             updated_at: row.get(9)?,
+            is_favorite: row.get::<i32>(10)? != 0,
+            public_id: row.get(11)?,
         })
     }
 
@@ -642,15 +1302,143 @@ impl<'a> Commonplace<'a> {
             SET deleted_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
             WHERE id = ?
         "#;
+        self.journal(Entity::Annotation, id, Change::Deleted).await?;
         let result = self.conn.execute(query, libsql::params![id]).await?;
         Ok(result > 0)
     }
 
+    /// Live annotations matching `filter` across all live resources, newest first
+    pub async fn list_annotations(
+        &self,
+        filter: &AnnotationFilter,
+        limit: i32,
+        page: &Page,
+    ) -> Result<Vec<Annotation>> {
+        let (mut conditions, mut params) = filter.to_sql();
+        if let Some((condition, values)) = page.condition() {
+            conditions = format!("{} AND {}", conditions, condition);
+            params.extend(values);
+        }
+        params.push(limit.into());
+        params.push(page.offset().into());
+        let query = format!(
+            r#"
+            SELECT id, resource_id, text, color, boundary, external_id, content_hash, deleted_at, created_at, updated_at, is_favorite, public_id
+            FROM annotations
+            WHERE {} AND resource_id IN (SELECT id FROM resources WHERE deleted_at IS NULL)
+            ORDER BY created_at DESC, id DESC
+            LIMIT ? OFFSET ?
+        "#,
+            conditions
+        );
+
+        let mut rows = self.conn.query(&query, params).await?;
+        let mut annotations = Vec::new();
+        while let Some(row) = rows.next().await? {
+            annotations.push(self.row_to_annotation(&row)?);
+        }
+
+        Ok(annotations)
+    }
+
+    /// Soft-deletes every live annotation matching `filter` and returns how many
+    pub async fn soft_delete_annotations_matching(&self, filter: &AnnotationFilter) -> Result<u64> {
+        let (conditions, params) = filter.to_sql();
+        let query =
+            format!("UPDATE annotations SET deleted_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now') WHERE {}", conditions);
+        Ok(self.conn.execute(&query, params).await?)
+    }
+
+    pub async fn count_annotations_matching(&self, filter: &AnnotationFilter) -> Result<u64> {
+        let (conditions, params) = filter.to_sql();
+        let query = format!("SELECT COUNT(*) FROM annotations WHERE {}", conditions);
+        let count: Option<i64> = self.query_one(&query, params, |row| Ok(row.get(0)?)).await?;
+        Ok(count.unwrap_or(0) as u64)
+    }
+
+    /// Links annotation `source_id` to `input.target_id`. Returns `None` when the
+    /// same link already exists.
+    pub async fn create_annotation_link(
+        &self,
+        source_id: i32,
+        input: CreateAnnotationLink,
+    ) -> Result<Option<AnnotationLink>> {
+        let query = r#"
+            INSERT INTO annotation_links (source_id, target_id, relation)
+            VALUES (?, ?, ?)
+            ON CONFLICT (source_id, target_id, relation) DO NOTHING
+            RETURNING id, source_id, target_id, relation, created_at
+        "#;
+        self.query_one(query, libsql::params![source_id, input.target_id, input.relation.as_str()], |row| {
+            let relation: String = row.get(3)?;
+            Ok(AnnotationLink {
+                id: row.get(0)?,
+                source_id: row.get(1)?,
+                target_id: row.get(2)?,
+                relation: LinkRelation::from_str(&relation)
+                    .ok_or_else(|| anyhow::anyhow!("Invalid link relation: {}", relation))?,
+                created_at: row.get(4)?,
+            })
+        })
+        .await
+    }
+
+    /// Links from and backlinks to an annotation, oldest first. Links to deleted
+    /// annotations are left out.
+    pub async fn list_annotation_links(&self, annotation_id: i32) -> Result<Vec<LinkedAnnotation>> {
+        let query = r#"
+            SELECT a.id, a.resource_id, a.text, a.color, a.boundary, a.external_id, a.content_hash,
+                   a.deleted_at, a.created_at, a.updated_at, a.is_favorite, a.public_id, l.id, l.relation, 'outgoing', l.created_at
+            FROM annotation_links l
+            JOIN annotations a ON a.id = l.target_id AND a.deleted_at IS NULL
+            WHERE l.source_id = ?1
+            UNION ALL
+            SELECT a.id, a.resource_id, a.text, a.color, a.boundary, a.external_id, a.content_hash,
+                   a.deleted_at, a.created_at, a.updated_at, a.is_favorite, a.public_id, l.id, l.relation, 'incoming', l.created_at
+            FROM annotation_links l
+            JOIN annotations a ON a.id = l.source_id AND a.deleted_at IS NULL
+            WHERE l.target_id = ?1
+            ORDER BY 13
+        "#;
+
+        let mut rows = self.conn.query(query, libsql::params![annotation_id]).await?;
+        let mut links = Vec::new();
+        while let Some(row) = rows.next().await? {
+            let relation: String = row.get(13)?;
+            let direction: String = row.get(14)?;
+            links.push(LinkedAnnotation {
+                link_id: row.get(12)?,
+                relation: LinkRelation::from_str(&relation)
+                    .ok_or_else(|| anyhow::anyhow!("Invalid link relation: {}", relation))?,
+                direction: if direction == "outgoing" {
+                    LinkDirection::Outgoing
+                } else {
+                    LinkDirection::Incoming
+                },
+                created_at: row.get(15)?,
+                annotation: self.row_to_annotation(&row)?,
+            });
+        }
+        Ok(links)
+    }
+
+    /// Removes link `link_id` if `annotation_id` is at either end of it
+    pub async fn delete_annotation_link(&self, annotation_id: i32, link_id: i32) -> Result<bool> {
+        let result = self
+            .conn
+            .execute(
+                "DELETE FROM annotation_links WHERE id = ?1 AND (source_id = ?2 OR target_id = ?2)",
+                libsql::params![link_id, annotation_id],
+            )
+            .await?;
+        Ok(result > 0)
+    }
+
     pub async fn create_comment(&self, input: CreateComment) -> Result<Comment> {
         let query = r#"
-            INSERT INTO comments (annotation_id, content, external_id, content_hash)
-            VALUES (?, ?, ?, ?)
-            RETURNING id, annotation_id, content, external_id, content_hash, deleted_at, created_at, updated_at
+            INSERT INTO comments (annotation_id, content, external_id, content_hash, parent_comment_id)
+            VALUES (?, ?, ?, ?, ?)
+            RETURNING id, annotation_id, content, external_id, content_hash, deleted_at, created_at, updated_at, parent_comment_id
         "#;
 
         let mut rows = self
@@ -661,13 +1449,16 @@ impl<'a> Commonplace<'a> {
                     input.annotation_id,
                     input.content,
                     input.external_id,
-                    input.content_hash
+                    input.content_hash,
+                    input.parent_comment_id
                 ],
             )
             .await?;
 
         if let Some(row) = rows.next().await? {
-            Ok(self.row_to_comment(&row)?)
+            let comment = self.row_to_comment(&row)?;
+            self.journal(Entity::Comment, comment.id, Change::Created).await?;
+            Ok(comment)
         } else {
             anyhow::bail!("Failed to create comment")
         }
@@ -675,7 +1466,7 @@ impl<'a> Commonplace<'a> {
 
     pub async fn get_comment(&self, id: i32) -> Result<Option<Comment>> {
         let query = r#"
-            SELECT id, annotation_id, content, external_id, content_hash, deleted_at, created_at, updated_at
+            SELECT id, annotation_id, content, external_id, content_hash, deleted_at, created_at, updated_at, parent_comment_id
             FROM comments WHERE id = ? AND deleted_at IS NULL
         "#;
 
@@ -690,7 +1481,7 @@ impl<'a> Commonplace<'a> {
 
     pub async fn find_comment_by_external_id(&self, external_id: &str) -> Result<Option<Comment>> {
         let query = r#"
-            SELECT id, annotation_id, content, external_id, content_hash, deleted_at, created_at, updated_at
+            SELECT id, annotation_id, content, external_id, content_hash, deleted_at, created_at, updated_at, parent_comment_id
             FROM comments WHERE external_id = ? AND deleted_at IS NULL
         "#;
 
@@ -706,7 +1497,7 @@ impl<'a> Commonplace<'a> {
     pub async fn find_comments_by_source_prefix(&self, prefix: &str) -> Result<Vec<Comment>> {
         let pattern = format!("{}:%", prefix);
         let query = r#"
-            SELECT id, annotation_id, content, external_id, content_hash, deleted_at, created_at, updated_at
+            SELECT id, annotation_id, content, external_id, content_hash, deleted_at, created_at, updated_at, parent_comment_id
             FROM comments
             WHERE external_id LIKE ? AND deleted_at IS NULL
         "#;
@@ -723,9 +1514,10 @@ impl<'a> Commonplace<'a> {
 
     pub async fn list_comments_by_annotation(&self, annotation_id: i32) -> Result<Vec<Comment>> {
         let query = r#"
-            SELECT id, annotation_id, content, external_id, content_hash, deleted_at, created_at, updated_at
+            SELECT id, annotation_id, content, external_id, content_hash, deleted_at, created_at, updated_at, parent_comment_id
             FROM comments
             WHERE annotation_id = ? AND deleted_at IS NULL
+                AND annotation_id IN (SELECT id FROM annotations WHERE deleted_at IS NULL)
             ORDER BY created_at ASC
         "#;
 
@@ -736,7 +1528,7 @@ impl<'a> Commonplace<'a> {
             comments.push(self.row_to_comment(&row)?);
         }
 
-        Ok(comments)
+        Ok(thread_comments(comments))
     }
 
     pub async fn update_comment(&self, id: i32, input: UpdateComment) -> Result<Option<Comment>> {
@@ -757,6 +1549,7 @@ impl<'a> Commonplace<'a> {
 
         let query = format!("UPDATE comments SET {} WHERE id = ? AND deleted_at IS NULL", updates.join(", "));
 
+        self.journal(Entity::Comment, id, Change::Updated).await?;
         self.conn.execute(&query, params).await?;
         self.get_comment(id).await
     }
@@ -779,6 +1572,8 @@ impl<'a> Commonplace<'a> {
             deleted_at: row.get(5)?,
             created_at: row.get(6)?,
             updated_at: row.get(7)?,
+            parent_comment_id: row.get(8)?,
+            replies: Vec::new(),
         })
     }
 
@@ -788,6 +1583,7 @@ impl<'a> Commonplace<'a> {
             SET deleted_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
             WHERE id = ?
         "#;
+        self.journal(Entity::Comment, id, Change::Deleted).await?;
         let result = self.conn.execute(query, libsql::params![id]).await?;
         Ok(result > 0)
     }
@@ -805,7 +1601,9 @@ impl<'a> Commonplace<'a> {
             .await?;
 
         if let Some(row) = rows.next().await? {
-            Ok(self.row_to_note(&row)?)
+            let note = self.row_to_note(&row)?;
+            self.journal(Entity::Note, note.id, Change::Created).await?;
+            Ok(note)
         } else {
             anyhow::bail!("Failed to create note")
         }
@@ -859,11 +1657,42 @@ impl<'a> Commonplace<'a> {
         Ok(notes)
     }
 
+    /// A page of a resource's live notes, newest first
+    pub async fn list_notes_page(&self, resource_id: i32, limit: i32, page: &Page) -> Result<Vec<Note>> {
+        let mut conditions = vec!["resource_id = ?", "deleted_at IS NULL"];
+        let mut params: Vec<libsql::Value> = vec![resource_id.into()];
+        if let Some((condition, values)) = page.condition() {
+            conditions.push(condition);
+            params.extend(values);
+        }
+        params.push(limit.into());
+        params.push(page.offset().into());
+        let query = format!(
+            r#"
+            SELECT id, resource_id, content, external_id, content_hash, deleted_at, created_at, updated_at
+            FROM notes
+            WHERE {} AND resource_id IN (SELECT id FROM resources WHERE deleted_at IS NULL)
+            ORDER BY created_at DESC, id DESC
+            LIMIT ? OFFSET ?
+        "#,
+            conditions.join(" AND ")
+        );
+
+        let mut rows = self.conn.query(&query, params).await?;
+        let mut notes = Vec::new();
+        while let Some(row) = rows.next().await? {
+            notes.push(self.row_to_note(&row)?);
+        }
+
+        Ok(notes)
+    }
+
     pub async fn list_notes_by_resource(&self, resource_id: i32) -> Result<Vec<Note>> {
         let query = r#"
             SELECT id, resource_id, content, external_id, content_hash, deleted_at, created_at, updated_at
             FROM notes
             WHERE resource_id = ? AND deleted_at IS NULL
+                AND resource_id IN (SELECT id FROM resources WHERE deleted_at IS NULL)
             ORDER BY created_at DESC
         "#;
 
@@ -877,6 +1706,27 @@ impl<'a> Commonplace<'a> {
         Ok(notes)
     }
 
+    /// Annotation text and note content of every live resource, as `(resource_id, text)`
+    pub async fn list_resource_texts(&self) -> Result<Vec<(i32, String)>> {
+        let query = r#"
+            SELECT a.resource_id, a.text FROM annotations a
+            JOIN resources r ON r.id = a.resource_id
+            WHERE a.deleted_at IS NULL AND r.deleted_at IS NULL
+            UNION ALL
+            SELECT n.resource_id, n.content FROM notes n
+            JOIN resources r ON r.id = n.resource_id
+            WHERE n.deleted_at IS NULL AND r.deleted_at IS NULL
+        "#;
+
+        let mut rows = self.conn.query(query, ()).await?;
+        let mut texts = Vec::new();
+        while let Some(row) = rows.next().await? {
+            texts.push((row.get(0)?, row.get(1)?));
+        }
+
+        Ok(texts)
+    }
+
     pub async fn update_note(&self, id: i32, input: UpdateNote) -> Result<Option<Note>> {
         if self.get_note(id).await?.is_none() {
             return Ok(None);
@@ -895,10 +1745,26 @@ impl<'a> Commonplace<'a> {
 
         let query = format!("UPDATE notes SET {} WHERE id = ? AND deleted_at IS NULL", updates.join(", "));
 
+        self.journal(Entity::Note, id, Change::Updated).await?;
         self.conn.execute(&query, params).await?;
         self.get_note(id).await
     }
 
+    /// Files a note under another resource, e.g. out of the inbox once it is clear
+    /// where it belongs
+    pub async fn move_note(&self, id: i32, resource_id: i32) -> Result<Option<Note>> {
+        if self.get_note(id).await?.is_none() {
+            return Ok(None);
+        }
+        let query = r#"
+            UPDATE notes SET resource_id = ?, updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
+            WHERE id = ? AND deleted_at IS NULL
+        "#;
+        self.journal(Entity::Note, id, Change::Updated).await?;
+        self.conn.execute(query, libsql::params![resource_id, id]).await?;
+        self.get_note(id).await
+    }
+
     pub async fn delete_note(&self, id: i32) -> Result<bool> {
         let result = self
             .conn
@@ -926,20 +1792,80 @@ impl<'a> Commonplace<'a> {
             SET deleted_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
             WHERE id = ?
         "#;
+        self.journal(Entity::Note, id, Change::Deleted).await?;
         let result = self.conn.execute(query, libsql::params![id]).await?;
         Ok(result > 0)
     }
 
+    /// Clears `deleted_at` on a soft-deleted row. A row whose parent (the resource of
+    /// an annotation or note, the annotation of a comment) is still deleted is left
+    /// alone, since it would stay hidden. Restoring a live row is a no-op.
+    pub async fn restore(&self, entity: Entity, id: i32) -> Result<Restore> {
+        let parent = match entity {
+            Entity::Resource => None,
+            Entity::Annotation | Entity::Note => Some((Entity::Resource, "resource_id")),
+            Entity::Comment => Some((Entity::Annotation, "annotation_id")),
+        };
+        let parent_column = parent.map(|(_, column)| column).unwrap_or("NULL");
+        let query = format!("SELECT deleted_at, {} FROM {} WHERE id = ?", parent_column, entity.table());
+        let row: Option<(Option<String>, Option<i32>)> = self
+            .query_one(&query, libsql::params![id], |row| Ok((row.get(0)?, row.get(1)?)))
+            .await?;
+        let Some((deleted_at, parent_id)) = row else {
+            return Ok(Restore::NotFound);
+        };
+        if deleted_at.is_none() {
+            return Ok(Restore::Restored);
+        }
+
+        if let (Some((parent, _)), Some(parent_id)) = (parent, parent_id) {
+            let query = format!("SELECT deleted_at IS NOT NULL FROM {} WHERE id = ?", parent.table());
+            let parent_deleted: Option<bool> = self
+                .query_one(&query, libsql::params![parent_id], |row| Ok(row.get(0)?))
+                .await?;
+            if parent_deleted == Some(true) {
+                return Ok(Restore::ParentDeleted {
+                    entity: parent.table(),
+                    id: parent_id,
+                });
+            }
+        }
+
+        self.journal(entity, id, Change::Updated).await?;
+        let query = format!(
+            "UPDATE {} SET deleted_at = NULL, updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now') WHERE id = ?",
+            entity.table()
+        );
+        self.conn.execute(&query, libsql::params![id]).await?;
+        if entity == Entity::Annotation
+            && let Some(annotation) = self.get_annotation(id).await?
+        {
+            self.publish_annotation(&annotation, false);
+        }
+        Ok(Restore::Restored)
+    }
+
     pub async fn create_word(&self, input: CreateWord) -> Result<Word> {
         let query = r#"
-            INSERT INTO words (resource_id, name, meaning)
-            VALUES (?, ?, ?)
-            RETURNING id, resource_id, name, meaning, created_at, updated_at
+            INSERT INTO words (resource_id, name, meaning, part_of_speech, phonetic, example)
+            VALUES (?, ?, ?, NULLIF(?, ''), NULLIF(?, ''), NULLIF(?, ''))
+            RETURNING id, resource_id, name, meaning, created_at, updated_at,
+                ease, interval_days, repetitions, due_at, last_reviewed_at, part_of_speech, phonetic, example
         "#;
 
         let mut rows = self
             .conn
-            .query(query, libsql::params![input.resource_id, input.name, input.meaning])
+            .query(
+                query,
+                libsql::params![
+                    input.resource_id,
+                    input.name,
+                    input.meaning,
+                    input.part_of_speech,
+                    input.phonetic,
+                    input.example
+                ],
+            )
             .await?;
 
         if let Some(row) = rows.next().await? {
@@ -951,7 +1877,8 @@ impl<'a> Commonplace<'a> {
 
     pub async fn get_word(&self, id: i32) -> Result<Option<Word>> {
         let query = r#"
-            SELECT id, resource_id, name, meaning, created_at, updated_at
+            SELECT id, resource_id, name, meaning, created_at, updated_at,
+                ease, interval_days, repetitions, due_at, last_reviewed_at, part_of_speech, phonetic, example
             FROM words WHERE id = ?
         "#;
 
@@ -964,11 +1891,14 @@ impl<'a> Commonplace<'a> {
         }
     }
 
+    /// Words encountered in the resource, including ones saved from another
     pub async fn list_words_by_resource(&self, resource_id: i32) -> Result<Vec<Word>> {
         let query = r#"
-            SELECT id, resource_id, name, meaning, created_at, updated_at
+            SELECT id, resource_id, name, meaning, created_at, updated_at,
+                ease, interval_days, repetitions, due_at, last_reviewed_at, part_of_speech, phonetic, example
             FROM words
-            WHERE resource_id = ?
+            WHERE id IN (SELECT word_id FROM word_occurrences WHERE resource_id = ?1)
+              AND EXISTS (SELECT 1 FROM resources WHERE id = ?1 AND deleted_at IS NULL)
             ORDER BY name ASC
         "#;
 
@@ -984,9 +1914,11 @@ impl<'a> Commonplace<'a> {
 
     pub async fn search_words(&self, query_str: &str) -> Result<Vec<Word>> {
         let query = r#"
-            SELECT id, resource_id, name, meaning, created_at, updated_at
+            SELECT id, resource_id, name, meaning, created_at, updated_at,
+                ease, interval_days, repetitions, due_at, last_reviewed_at, part_of_speech, phonetic, example
             FROM words
-            WHERE name LIKE ? OR meaning LIKE ?
+            WHERE (name LIKE ? OR meaning LIKE ?)
+                AND resource_id IN (SELECT id FROM resources WHERE deleted_at IS NULL)
             ORDER BY name ASC
         "#;
 
@@ -1020,6 +1952,18 @@ impl<'a> Commonplace<'a> {
             updates.push("meaning = ?");
             params.push(meaning.clone().into());
         }
+        if let Some(part_of_speech) = &input.part_of_speech {
+            updates.push("part_of_speech = NULLIF(?, '')");
+            params.push(part_of_speech.clone().into());
+        }
+        if let Some(phonetic) = &input.phonetic {
+            updates.push("phonetic = NULLIF(?, '')");
+            params.push(phonetic.clone().into());
+        }
+        if let Some(example) = &input.example {
+            updates.push("example = NULLIF(?, '')");
+            params.push(example.clone().into());
+        }
 
         if updates.is_empty() {
             return self.get_word(id).await;
@@ -1034,6 +1978,158 @@ impl<'a> Commonplace<'a> {
         self.get_word(id).await
     }
 
+    /// Live resources the word was encountered in, oldest first
+    pub async fn list_word_occurrences(&self, word_id: i32) -> Result<Vec<WordOccurrence>> {
+        let query = r#"
+            SELECT r.id, r.title, r.type, o.created_at
+            FROM word_occurrences o
+            JOIN resources r ON r.id = o.resource_id AND r.deleted_at IS NULL
+            WHERE o.word_id = ?
+            ORDER BY o.created_at ASC, o.id ASC
+        "#;
+
+        let mut rows = self.conn.query(query, libsql::params![word_id]).await?;
+        let mut occurrences = Vec::new();
+        while let Some(row) = rows.next().await? {
+            let resource_type: String = row.get(2)?;
+            occurrences.push(WordOccurrence {
+                resource_id: row.get(0)?,
+                title: row.get(1)?,
+                resource_type: ResourceType::from_str(&resource_type)
+                    .ok_or_else(|| anyhow::anyhow!("Invalid resource type: {}", resource_type))?,
+                created_at: row.get(3)?,
+            });
+        }
+        Ok(occurrences)
+    }
+
+    pub async fn get_word_with_occurrences(&self, id: i32) -> Result<Option<WordWithOccurrences>> {
+        let Some(word) = self.get_word(id).await? else {
+            return Ok(None);
+        };
+        let occurrences = self.list_word_occurrences(id).await?;
+        Ok(Some(WordWithOccurrences { word, occurrences }))
+    }
+
+    /// Records that the word was encountered in another resource; a no-op if it
+    /// already was
+    pub async fn add_word_occurrence(&self, word_id: i32, resource_id: i32) -> Result<()> {
+        self.conn
+            .execute(
+                "INSERT OR IGNORE INTO word_occurrences (word_id, resource_id) VALUES (?, ?)",
+                libsql::params![word_id, resource_id],
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Unlinks a resource from the word. The resource the word was saved from
+    /// can't be removed this way; move the word instead.
+    pub async fn remove_word_occurrence(&self, word_id: i32, resource_id: i32) -> Result<bool> {
+        let query = r#"
+            DELETE FROM word_occurrences
+            WHERE word_id = ?1 AND resource_id = ?2
+              AND NOT EXISTS (SELECT 1 FROM words WHERE id = ?1 AND resource_id = ?2)
+        "#;
+        let result = self.conn.execute(query, libsql::params![word_id, resource_id]).await?;
+        Ok(result > 0)
+    }
+
+    pub async fn move_word(&self, id: i32, resource_id: i32) -> Result<Option<Word>> {
+        let query = r#"
+            UPDATE words SET resource_id = ?, updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
+            WHERE id = ?
+        "#;
+        self.conn.execute(query, libsql::params![resource_id, id]).await?;
+        self.get_word(id).await
+    }
+
+    /// Every word with its source citation, alphabetically
+    pub async fn list_word_citations(&self) -> Result<Vec<WordCitation>> {
+        let query = r#"
+            SELECT w.name, w.meaning, w.part_of_speech, r.title,
+                (SELECT a.text FROM annotations a
+                 WHERE a.resource_id = w.resource_id AND a.deleted_at IS NULL
+                   AND instr(lower(a.text), lower(w.name)) > 0
+                 ORDER BY a.id LIMIT 1),
+                w.example, w.created_at
+            FROM words w
+            JOIN resources r ON r.id = w.resource_id AND r.deleted_at IS NULL
+            ORDER BY w.name COLLATE NOCASE ASC, w.id ASC
+        "#;
+
+        let mut rows = self.conn.query(query, ()).await?;
+        let mut words = Vec::new();
+        while let Some(row) = rows.next().await? {
+            words.push(WordCitation {
+                word: row.get(0)?,
+                meaning: row.get(1)?,
+                part_of_speech: row.get(2)?,
+                source: row.get(3)?,
+                annotation: row.get(4)?,
+                example: row.get(5)?,
+                created_at: row.get(6)?,
+            });
+        }
+        Ok(words)
+    }
+
+    /// Words whose next review is due by `now`: overdue words first, then words that
+    /// have never been reviewed
+    pub async fn list_due_words(&self, now: &str, limit: i32) -> Result<Vec<Word>> {
+        let query = r#"
+            SELECT id, resource_id, name, meaning, created_at, updated_at,
+                ease, interval_days, repetitions, due_at, last_reviewed_at, part_of_speech, phonetic, example
+            FROM words
+            WHERE (due_at IS NULL OR due_at <= ?)
+                AND resource_id IN (SELECT id FROM resources WHERE deleted_at IS NULL)
+            ORDER BY due_at IS NULL, due_at, id
+            LIMIT ?
+        "#;
+
+        let mut rows = self.conn.query(query, libsql::params![now, limit]).await?;
+        let mut words = Vec::new();
+        while let Some(row) = rows.next().await? {
+            words.push(self.row_to_word(&row)?);
+        }
+        Ok(words)
+    }
+
+    /// Grades a review of the word and schedules the next one
+    pub async fn review_word(&self, id: i32, grade: u8) -> Result<Option<Word>> {
+        let Some(word) = self.get_word(id).await? else {
+            return Ok(None);
+        };
+        let schedule = Schedule {
+            ease: word.ease,
+            interval_days: word.interval_days,
+            repetitions: word.repetitions,
+        }
+        .review(grade);
+
+        let now = chrono::Utc::now();
+        let timestamp = |at: chrono::DateTime<chrono::Utc>| at.to_rfc3339_opts(chrono::SecondsFormat::Millis, true);
+        let query = r#"
+            UPDATE words
+            SET ease = ?, interval_days = ?, repetitions = ?, due_at = ?, last_reviewed_at = ?
+            WHERE id = ?
+        "#;
+        self.conn
+            .execute(
+                query,
+                libsql::params![
+                    schedule.ease,
+                    schedule.interval_days,
+                    schedule.repetitions,
+                    timestamp(schedule.due_at(now)),
+                    timestamp(now),
+                    id
+                ],
+            )
+            .await?;
+        self.get_word(id).await
+    }
+
     pub async fn delete_word(&self, id: i32) -> Result<bool> {
         let result = self
             .conn
@@ -1050,7 +2146,530 @@ impl<'a> Commonplace<'a> {
             meaning: row.get(3)?,
             created_at: row.get(4)?,
             updated_at: row.get(5)?,
+            ease: row.get(6)?,
+            interval_days: row.get(7)?,
+            repetitions: row.get(8)?,
+            due_at: row.get(9)?,
+            last_reviewed_at: row.get(10)?,
+            part_of_speech: row.get(11)?,
+            phonetic: row.get(12)?,
+            example: row.get(13)?,
+        })
+    }
+
+    /// Stores a capture of the resource's content. Returns `None` when it matches the
+    /// latest stored version, so repeated captures of an unchanged page add nothing.
+    pub async fn record_resource_version(
+        &self,
+        resource_id: i32,
+        url: &str,
+        content: &str,
+    ) -> Result<Option<ResourceVersion>> {
+        let content_hash = compute_hash(&[content]);
+        let latest = self
+            .query_one(
+                "SELECT content_hash FROM resource_versions WHERE resource_id = ? ORDER BY id DESC LIMIT 1",
+                libsql::params![resource_id],
+                |row| Ok(row.get::<String>(0)?),
+            )
+            .await?;
+        if latest.as_deref() == Some(content_hash.as_str()) {
+            return Ok(None);
+        }
+
+        let query = r#"
+            INSERT INTO resource_versions (resource_id, url, content, content_hash)
+            VALUES (?, ?, ?, ?)
+            RETURNING id, resource_id, url, content, content_hash, captured_at
+        "#;
+        self.query_one(query, libsql::params![resource_id, url, content, content_hash], |row| {
+            self.row_to_resource_version(row)
         })
+        .await
+    }
+
+    /// Versions of a resource, oldest first
+    pub async fn list_resource_versions(&self, resource_id: i32) -> Result<Vec<ResourceVersion>> {
+        let query = r#"
+            SELECT id, resource_id, url, content, content_hash, captured_at
+            FROM resource_versions
+            WHERE resource_id = ?
+            ORDER BY id ASC
+        "#;
+
+        let mut rows = self.conn.query(query, libsql::params![resource_id]).await?;
+        let mut versions = Vec::new();
+        while let Some(row) = rows.next().await? {
+            versions.push(self.row_to_resource_version(&row)?);
+        }
+        Ok(versions)
+    }
+
+    /// Drops every captured version of a resource but the latest, which capture
+    /// diffs still compare against
+    pub async fn prune_resource_versions(&self, resource_id: i32) -> Result<u64> {
+        let query = r#"
+            DELETE FROM resource_versions
+            WHERE resource_id = ?1
+              AND id < (SELECT MAX(id) FROM resource_versions WHERE resource_id = ?1)
+        "#;
+        Ok(self.conn.execute(query, libsql::params![resource_id]).await?)
+    }
+
+    /// Live resources with no change to them, their annotations, notes or words
+    /// since `cutoff`, skipping the inbox and any already archived since their last
+    /// change. Oldest first.
+    pub async fn inactive_resource_ids(&self, cutoff: &str, limit: i32) -> Result<Vec<i32>> {
+        let query = r#"
+            WITH activity AS (
+                SELECT resources.id,
+                       MAX(
+                           resources.updated_at,
+                           COALESCE((SELECT MAX(updated_at) FROM annotations WHERE resource_id = resources.id), ''),
+                           COALESCE((SELECT MAX(updated_at) FROM notes WHERE resource_id = resources.id), ''),
+                           COALESCE((SELECT MAX(updated_at) FROM words WHERE resource_id = resources.id), '')
+                       ) AS last_active
+                FROM resources
+                WHERE resources.deleted_at IS NULL
+                  AND (resources.external_id IS NULL OR resources.external_id != ?3)
+            )
+            SELECT id FROM activity
+            WHERE last_active < ?1
+              AND NOT EXISTS (
+                  SELECT 1 FROM resource_archives
+                  WHERE resource_id = activity.id AND archived_at > activity.last_active
+              )
+            ORDER BY last_active ASC, id ASC
+            LIMIT ?2
+        "#;
+
+        let mut rows = self
+            .conn
+            .query(query, libsql::params![cutoff, limit, INBOX_EXTERNAL_ID])
+            .await?;
+        let mut ids = Vec::new();
+        while let Some(row) = rows.next().await? {
+            ids.push(row.get(0)?);
+        }
+        Ok(ids)
+    }
+
+    pub async fn record_resource_archive(&self, resource_id: i32, key: &str) -> Result<()> {
+        self.conn
+            .execute(
+                "INSERT INTO resource_archives (resource_id, key) VALUES (?, ?)",
+                libsql::params![resource_id, key],
+            )
+            .await?;
+        Ok(())
+    }
+
+    pub async fn record_resource_snapshot(
+        &self,
+        resource_id: i32,
+        url: &str,
+        key: &str,
+        title: Option<&str>,
+        size: i64,
+    ) -> Result<ResourceSnapshot> {
+        let query = r#"
+            INSERT INTO resource_snapshots (resource_id, url, key, title, size)
+            VALUES (?, ?, ?, ?, ?)
+            RETURNING id, resource_id, url, key, title, size, created_at
+        "#;
+        match self
+            .query_one(query, libsql::params![resource_id, url, key, title, size], |row| {
+                self.row_to_resource_snapshot(row)
+            })
+            .await?
+        {
+            Some(snapshot) => Ok(snapshot),
+            None => anyhow::bail!("Failed to record snapshot of resource {}", resource_id),
+        }
+    }
+
+    /// The newest snapshot of a live resource
+    pub async fn latest_resource_snapshot(&self, resource_id: i32) -> Result<Option<ResourceSnapshot>> {
+        let query = r#"
+            SELECT s.id, s.resource_id, s.url, s.key, s.title, s.size, s.created_at
+            FROM resource_snapshots s
+            JOIN resources r ON r.id = s.resource_id
+            WHERE s.resource_id = ? AND r.deleted_at IS NULL
+            ORDER BY s.id DESC
+            LIMIT 1
+        "#;
+        self.query_one(query, libsql::params![resource_id], |row| self.row_to_resource_snapshot(row))
+            .await
+    }
+
+    /// Tags a resource, creating the tag if no book or resource has it yet. False if
+    /// the resource already had it.
+    pub async fn tag_resource(&self, resource_id: i32, name: &str) -> Result<bool> {
+        self.conn
+            .execute("INSERT OR IGNORE INTO tags (name) VALUES (?)", libsql::params![name])
+            .await?;
+        let tagged = self
+            .conn
+            .execute(
+                r#"
+                INSERT OR IGNORE INTO resource_tags (resource_id, tag_id)
+                SELECT ?, id FROM tags WHERE name = ?
+                "#,
+                libsql::params![resource_id, name],
+            )
+            .await?;
+        Ok(tagged > 0)
+    }
+
+    pub async fn list_resource_tags(&self, resource_id: i32) -> Result<Vec<String>> {
+        let query = r#"
+            SELECT t.name FROM resource_tags rt
+            JOIN tags t ON t.id = rt.tag_id
+            WHERE rt.resource_id = ?
+            ORDER BY t.name COLLATE NOCASE
+        "#;
+        let mut rows = self.conn.query(query, libsql::params![resource_id]).await?;
+        let mut tags = Vec::new();
+        while let Some(row) = rows.next().await? {
+            tags.push(row.get(0)?);
+        }
+        Ok(tags)
+    }
+
+    fn row_to_resource_snapshot(&self, row: &libsql::Row) -> Result<ResourceSnapshot> {
+        Ok(ResourceSnapshot {
+            id: row.get(0)?,
+            resource_id: row.get(1)?,
+            url: row.get(2)?,
+            key: row.get(3)?,
+            title: row.get(4)?,
+            size: row.get(5)?,
+            created_at: row.get(6)?,
+        })
+    }
+
+    fn row_to_resource_version(&self, row: &libsql::Row) -> Result<ResourceVersion> {
+        Ok(ResourceVersion {
+            id: row.get(0)?,
+            resource_id: row.get(1)?,
+            url: row.get(2)?,
+            content: row.get(3)?,
+            content_hash: row.get(4)?,
+            captured_at: row.get(5)?,
+        })
+    }
+
+    /// Rows of `table` with an id above `after_id`, in id order and including
+    /// soft-deleted rows, serialized the same way the API returns them
+    pub async fn archive_page(&self, table: ArchiveTable, after_id: i64, limit: i32) -> Result<Vec<JsonValue>> {
+        let columns = match table {
+            ArchiveTable::Resources => {
+                "id, title, type, external_id, content_hash, config, deleted_at, created_at, updated_at, book_id, status, progress, visibility"
+            }
+            ArchiveTable::Annotations => {
+                "id, resource_id, text, color, boundary, external_id, content_hash, deleted_at, created_at, updated_at, is_favorite, public_id"
+            }
+            ArchiveTable::Comments => {
+                "id, annotation_id, content, external_id, content_hash, deleted_at, created_at, updated_at, parent_comment_id"
+            }
+            ArchiveTable::Notes => {
+                "id, resource_id, content, external_id, content_hash, deleted_at, created_at, updated_at"
+            }
+            ArchiveTable::Words => {
+                concat!(
+                    "id, resource_id, name, meaning, created_at, updated_at, ease, interval_days, repetitions, ",
+                    "due_at, last_reviewed_at, part_of_speech, phonetic, example"
+                )
+            }
+        };
+        let query = format!("SELECT {} FROM {} WHERE id > ? ORDER BY id LIMIT ?", columns, table.key());
+
+        let mut rows = self.conn.query(&query, libsql::params![after_id, limit]).await?;
+        let mut page = Vec::new();
+        while let Some(row) = rows.next().await? {
+            let value = match table {
+                ArchiveTable::Resources => serde_json::to_value(self.row_to_resource(&row)?)?,
+                ArchiveTable::Annotations => serde_json::to_value(self.row_to_annotation(&row)?)?,
+                ArchiveTable::Comments => serde_json::to_value(self.row_to_comment(&row)?)?,
+                ArchiveTable::Notes => serde_json::to_value(self.row_to_note(&row)?)?,
+                ArchiveTable::Words => serde_json::to_value(self.row_to_word(&row)?)?,
+            };
+            page.push(value);
+        }
+        Ok(page)
+    }
+
+    pub async fn list_annotations_by_resources(&self, resource_ids: &[i32]) -> Result<Vec<Annotation>> {
+        if resource_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let (placeholders, params) = id_params(resource_ids);
+        let query = format!(
+            r#"
+            SELECT id, resource_id, text, color, boundary, external_id, content_hash, deleted_at, created_at, updated_at, is_favorite, public_id
+            FROM annotations
+            WHERE resource_id IN ({}) AND deleted_at IS NULL
+            ORDER BY created_at ASC
+        "#,
+            placeholders
+        );
+
+        let mut rows = self.conn.query(&query, params).await?;
+        let mut annotations = Vec::new();
+
+        while let Some(row) = rows.next().await? {
+            annotations.push(self.row_to_annotation(&row)?);
+        }
+
+        Ok(annotations)
+    }
+
+    pub async fn list_comments_by_annotations(&self, annotation_ids: &[i32]) -> Result<Vec<Comment>> {
+        if annotation_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let (placeholders, params) = id_params(annotation_ids);
+        let query = format!(
+            r#"
+            SELECT id, annotation_id, content, external_id, content_hash, deleted_at, created_at, updated_at, parent_comment_id
+            FROM comments
+            WHERE annotation_id IN ({}) AND deleted_at IS NULL
+            ORDER BY created_at ASC
+        "#,
+            placeholders
+        );
+
+        let mut rows = self.conn.query(&query, params).await?;
+        let mut comments = Vec::new();
+
+        while let Some(row) = rows.next().await? {
+            comments.push(self.row_to_comment(&row)?);
+        }
+
+        Ok(thread_comments(comments))
+    }
+
+    pub async fn list_notes_by_resources(&self, resource_ids: &[i32]) -> Result<Vec<Note>> {
+        if resource_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let (placeholders, params) = id_params(resource_ids);
+        let query = format!(
+            r#"
+            SELECT id, resource_id, content, external_id, content_hash, deleted_at, created_at, updated_at
+            FROM notes
+            WHERE resource_id IN ({}) AND deleted_at IS NULL
+            ORDER BY created_at DESC
+        "#,
+            placeholders
+        );
+
+        let mut rows = self.conn.query(&query, params).await?;
+        let mut notes = Vec::new();
+
+        while let Some(row) = rows.next().await? {
+            notes.push(self.row_to_note(&row)?);
+        }
+
+        Ok(notes)
+    }
+
+    pub async fn list_words_by_resources(&self, resource_ids: &[i32]) -> Result<Vec<Word>> {
+        if resource_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let (placeholders, params) = id_params(resource_ids);
+        let query = format!(
+            r#"
+            SELECT id, resource_id, name, meaning, created_at, updated_at,
+                ease, interval_days, repetitions, due_at, last_reviewed_at, part_of_speech, phonetic, example
+            FROM words
+            WHERE resource_id IN ({})
+            ORDER BY name ASC
+        "#,
+            placeholders
+        );
+
+        let mut rows = self.conn.query(&query, params).await?;
+        let mut words = Vec::new();
+
+        while let Some(row) = rows.next().await? {
+            words.push(self.row_to_word(&row)?);
+        }
+
+        Ok(words)
+    }
+
+    /// Live annotations on live resources with their reviews from before `before`,
+    /// optionally only the favorites
+    pub async fn review_candidates(&self, before: &str, favorites_only: bool) -> Result<Vec<ReviewCandidate>> {
+        let query = r#"
+            SELECT annotations.id, annotations.created_at,
+                   COUNT(annotation_reviews.id), MAX(annotation_reviews.reviewed_at)
+            FROM annotations
+            JOIN resources ON resources.id = annotations.resource_id AND resources.deleted_at IS NULL
+            LEFT JOIN annotation_reviews
+                ON annotation_reviews.annotation_id = annotations.id AND annotation_reviews.reviewed_at < ?1
+            WHERE annotations.deleted_at IS NULL AND (?2 = 0 OR annotations.is_favorite = 1)
+            GROUP BY annotations.id
+        "#;
+
+        let mut rows = self
+            .conn
+            .query(query, libsql::params![before, favorites_only as i32])
+            .await?;
+        let mut candidates = Vec::new();
+        while let Some(row) = rows.next().await? {
+            candidates.push(ReviewCandidate {
+                annotation_id: row.get(0)?,
+                created_at: row.get(1)?,
+                review_count: row.get(2)?,
+                last_reviewed_at: row.get(3)?,
+            });
+        }
+        Ok(candidates)
+    }
+
+    /// The annotations in `ids` with their resource title and full review history,
+    /// in the order of `ids`
+    pub async fn review_items(&self, ids: &[i32]) -> Result<Vec<ReviewItem>> {
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let (placeholders, params) = id_params(ids);
+        let query = format!(
+            r#"
+            SELECT annotations.id, annotations.resource_id, annotations.text, annotations.color,
+                   annotations.boundary, annotations.external_id, annotations.content_hash,
+                   annotations.deleted_at, annotations.created_at, annotations.updated_at,
+                   annotations.is_favorite, annotations.public_id, resources.title,
+                   (SELECT COUNT(*) FROM annotation_reviews WHERE annotation_id = annotations.id),
+                   (SELECT MAX(reviewed_at) FROM annotation_reviews WHERE annotation_id = annotations.id)
+            FROM annotations
+            JOIN resources ON resources.id = annotations.resource_id
+            WHERE annotations.id IN ({})
+        "#,
+            placeholders
+        );
+
+        let mut rows = self.conn.query(&query, params).await?;
+        let mut items = Vec::new();
+        while let Some(row) = rows.next().await? {
+            items.push(ReviewItem {
+                annotation: self.row_to_annotation(&row)?,
+                resource_title: row.get(12)?,
+                review_count: row.get(13)?,
+                last_reviewed_at: row.get(14)?,
+            });
+        }
+        items.sort_by_key(|item| ids.iter().position(|id| *id == item.annotation.id));
+        Ok(items)
+    }
+
+    /// Records that the annotation was reviewed now. Returns None if it does not exist.
+    pub async fn record_review(&self, annotation_id: i32) -> Result<Option<ReviewStats>> {
+        if self.get_annotation(annotation_id).await?.is_none() {
+            return Ok(None);
+        }
+        self.conn
+            .execute("INSERT INTO annotation_reviews (annotation_id) VALUES (?)", libsql::params![annotation_id])
+            .await?;
+
+        let query = r#"
+            SELECT COUNT(*), MAX(reviewed_at) FROM annotation_reviews WHERE annotation_id = ?
+        "#;
+        self.query_one(query, libsql::params![annotation_id], |row| {
+            Ok(ReviewStats {
+                annotation_id,
+                review_count: row.get(0)?,
+                last_reviewed_at: row.get(1)?,
+            })
+        })
+        .await
+    }
+
+    /// Live annotations that were never reviewed or surfaced anywhere, oldest first
+    pub async fn cold_annotation_ids(&self, limit: i32) -> Result<Vec<i32>> {
+        let query = r#"
+            SELECT annotations.id
+            FROM annotations
+            JOIN resources ON resources.id = annotations.resource_id AND resources.deleted_at IS NULL
+            WHERE annotations.deleted_at IS NULL
+              AND NOT EXISTS (SELECT 1 FROM annotation_reviews WHERE annotation_id = annotations.id)
+              AND NOT EXISTS (SELECT 1 FROM annotation_surfacings WHERE annotation_id = annotations.id)
+            ORDER BY annotations.created_at ASC, annotations.id ASC
+            LIMIT ?
+        "#;
+
+        let mut rows = self.conn.query(query, libsql::params![limit]).await?;
+        let mut ids = Vec::new();
+        while let Some(row) = rows.next().await? {
+            ids.push(row.get(0)?);
+        }
+        Ok(ids)
+    }
+
+    /// Records that the annotations in `ids` were shown via `via`, skipping any
+    /// already recorded for the same `via` today, so reloading the daily review
+    /// does not pile up rows. Returns the timestamp the rows share.
+    pub async fn record_surfacings(&self, ids: &[i32], via: Surfacing) -> Result<String> {
+        let now: Option<String> = self
+            .query_one("SELECT strftime('%Y-%m-%dT%H:%M:%fZ', 'now')", (), |row| Ok(row.get(0)?))
+            .await?;
+        let now = now.ok_or_else(|| anyhow::anyhow!("Failed to read current time"))?;
+        if ids.is_empty() {
+            return Ok(now);
+        }
+
+        let (placeholders, mut params) = id_params(ids);
+        params.insert(0, now.clone().into());
+        params.insert(0, via.as_str().into());
+        let query = format!(
+            r#"
+            INSERT INTO annotation_surfacings (annotation_id, via, surfaced_at)
+            SELECT id, ?1, ?2 FROM annotations
+            WHERE id IN ({})
+              AND NOT EXISTS (
+                  SELECT 1 FROM annotation_surfacings
+                  WHERE annotation_id = annotations.id AND via = ?1 AND surfaced_at >= substr(?2, 1, 10)
+              )
+        "#,
+            placeholders
+        );
+        self.conn.execute(&query, params).await?;
+        Ok(now)
+    }
+
+    /// When the most recent digest was built and its annotations, oldest first
+    pub async fn latest_digest(&self) -> Result<Option<(String, Vec<i32>)>> {
+        let created_at: Option<String> = self
+            .query_one(
+                "SELECT MAX(surfaced_at) FROM annotation_surfacings WHERE via = ?",
+                libsql::params![Surfacing::Digest.as_str()],
+                |row| Ok(row.get::<Option<String>>(0)?),
+            )
+            .await?
+            .flatten();
+        let Some(created_at) = created_at else {
+            return Ok(None);
+        };
+
+        let query = r#"
+            SELECT annotations.id
+            FROM annotation_surfacings
+            JOIN annotations ON annotations.id = annotation_surfacings.annotation_id
+            WHERE annotation_surfacings.via = ? AND annotation_surfacings.surfaced_at = ?
+            ORDER BY annotations.created_at ASC, annotations.id ASC
+        "#;
+        let mut rows = self
+            .conn
+            .query(query, libsql::params![Surfacing::Digest.as_str(), created_at.clone()])
+            .await?;
+        let mut ids = Vec::new();
+        while let Some(row) = rows.next().await? {
+            ids.push(row.get(0)?);
+        }
+        Ok(Some((created_at, ids)))
     }
 
     pub async fn get_resource_full(&self, id: i32) -> Result<Option<ResourceFull>> {
@@ -1076,6 +2695,177 @@ impl<'a> Commonplace<'a> {
             words,
         }))
     }
+
+    /// [`Commonplace::get_resource_full`] for several resources, in a fixed number
+    /// of queries. Resources come back in the order asked for; missing or deleted
+    /// ones are left out.
+    pub async fn get_resources_full(&self, ids: &[i32]) -> Result<Vec<ResourceFull>> {
+        let mut seen = HashSet::new();
+        let ids: Vec<i32> = ids.iter().copied().filter(|id| seen.insert(*id)).collect();
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let (placeholders, params) = id_params(&ids);
+        let query = format!(
+            r#"
+            SELECT id, title, type, external_id, content_hash, config, deleted_at, created_at, updated_at, book_id, status, progress, visibility
+            FROM resources WHERE id IN ({}) AND deleted_at IS NULL
+        "#,
+            placeholders
+        );
+        let mut rows = self.conn.query(&query, params).await?;
+        let mut resources = HashMap::new();
+        while let Some(row) = rows.next().await? {
+            let resource = self.row_to_resource(&row)?;
+            resources.insert(resource.id, resource);
+        }
+        let ids: Vec<i32> = ids.into_iter().filter(|id| resources.contains_key(id)).collect();
+
+        let annotations = self.list_annotations_by_resources(&ids).await?;
+        let annotation_ids: Vec<i32> = annotations.iter().map(|a| a.id).collect();
+        let mut comments: HashMap<i32, Vec<Comment>> = HashMap::new();
+        for comment in self.list_comments_by_annotations(&annotation_ids).await? {
+            comments.entry(comment.annotation_id).or_default().push(comment);
+        }
+        let mut annotations_by_resource: HashMap<i32, Vec<AnnotationWithComments>> = HashMap::new();
+        for annotation in annotations {
+            let comments = comments.remove(&annotation.id).unwrap_or_default();
+            annotations_by_resource
+                .entry(annotation.resource_id)
+                .or_default()
+                .push(AnnotationWithComments { annotation, comments });
+        }
+        let mut notes: HashMap<i32, Vec<Note>> = HashMap::new();
+        for note in self.list_notes_by_resources(&ids).await? {
+            notes.entry(note.resource_id).or_default().push(note);
+        }
+        let mut words = self.list_word_occurrences_by_resources(&ids).await?;
+
+        Ok(ids
+            .iter()
+            .filter_map(|id| {
+                Some(ResourceFull {
+                    resource: resources.remove(id)?,
+                    annotations: annotations_by_resource.remove(id).unwrap_or_default(),
+                    notes: notes.remove(id).unwrap_or_default(),
+                    words: words.remove(id).unwrap_or_default(),
+                })
+            })
+            .collect())
+    }
+
+    /// Words encountered in each of the resources, keyed by resource id. A word
+    /// seen in several of them is listed under each.
+    async fn list_word_occurrences_by_resources(&self, resource_ids: &[i32]) -> Result<HashMap<i32, Vec<Word>>> {
+        let mut words: HashMap<i32, Vec<Word>> = HashMap::new();
+        if resource_ids.is_empty() {
+            return Ok(words);
+        }
+        let (placeholders, params) = id_params(resource_ids);
+        let query = format!(
+            r#"
+            SELECT words.id, words.resource_id, name, meaning, words.created_at, updated_at,
+                ease, interval_days, repetitions, due_at, last_reviewed_at, part_of_speech, phonetic, example,
+                word_occurrences.resource_id
+            FROM words
+            JOIN word_occurrences ON word_occurrences.word_id = words.id
+            WHERE word_occurrences.resource_id IN ({})
+            ORDER BY name ASC
+        "#,
+            placeholders
+        );
+
+        let mut rows = self.conn.query(&query, params).await?;
+        while let Some(row) = rows.next().await? {
+            words.entry(row.get(14)?).or_default().push(self.row_to_word(&row)?);
+        }
+        Ok(words)
+    }
+}
+
+/// External id prefix shared by rows a source synced, e.g. `light` for `light:42`.
+/// Rows created through the API have no external id and count as `manual`.
+pub const MANUAL_SOURCE: &str = "manual";
+
+/// Selects live annotations for listing and bulk operations; unset fields match
+/// everything
+#[derive(Debug, Clone, Default)]
+pub struct AnnotationFilter {
+    pub resource_id: Option<i32>,
+    pub color: Option<String>,
+    /// Only annotations created at or after this timestamp
+    pub after: Option<String>,
+    /// Only annotations created before this timestamp
+    pub before: Option<String>,
+    /// Only annotations on resources of this type
+    pub resource_type: Option<String>,
+    /// Only annotations synced from this source (see [`MANUAL_SOURCE`])
+    pub source: Option<String>,
+    pub favorite: Option<bool>,
+}
+
+impl AnnotationFilter {
+    pub fn is_empty(&self) -> bool {
+        self.resource_id.is_none()
+            && self.color.is_none()
+            && self.after.is_none()
+            && self.before.is_none()
+            && self.resource_type.is_none()
+            && self.source.is_none()
+            && self.favorite.is_none()
+    }
+
+    fn to_sql(&self) -> (String, Vec<libsql::Value>) {
+        let mut conditions = vec!["deleted_at IS NULL"];
+        let mut params: Vec<libsql::Value> = Vec::new();
+        if let Some(resource_id) = self.resource_id {
+            conditions.push("resource_id = ?");
+            params.push(resource_id.into());
+        }
+        if let Some(color) = &self.color {
+            conditions.push("color = ?");
+            params.push(color.clone().into());
+        }
+        if let Some(after) = &self.after {
+            conditions.push("created_at >= ?");
+            params.push(after.clone().into());
+        }
+        if let Some(before) = &self.before {
+            conditions.push("created_at < ?");
+            params.push(before.clone().into());
+        }
+        if let Some(resource_type) = &self.resource_type {
+            conditions.push("resource_id IN (SELECT id FROM resources WHERE type = ?)");
+            params.push(resource_type.clone().into());
+        }
+        match self.source.as_deref() {
+            Some(MANUAL_SOURCE) => conditions.push("external_id IS NULL"),
+            Some(source) => {
+                conditions.push("external_id LIKE ?");
+                params.push(format!("{}:%", source).into());
+            }
+            None => {}
+        }
+        if let Some(favorite) = self.favorite {
+            conditions.push("is_favorite = ?");
+            params.push((favorite as i32).into());
+        }
+        (conditions.join(" AND "), params)
+    }
+}
+
+/// Whether `e` is SQLite refusing a row that breaks a unique index, such as a
+/// second live resource with the same external id or website URL
+pub fn is_unique_violation(e: &anyhow::Error) -> bool {
+    e.to_string().contains("UNIQUE constraint failed")
+}
+
+/// `?, ?, ?` placeholders and matching params for an `IN (...)` clause
+fn id_params(ids: &[i32]) -> (String, Vec<libsql::Value>) {
+    let placeholders = vec!["?"; ids.len()].join(", ");
+    let params = ids.iter().map(|id| libsql::Value::from(*id)).collect();
+    (placeholders, params)
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -1085,6 +2875,14 @@ pub struct AnnotationWithComments {
     pub comments: Vec<Comment>,
 }
 
+/// Notes and words waiting in the inbox to be filed under a resource
+#[derive(Debug, Clone, Serialize)]
+pub struct Inbox {
+    pub resource: Resource,
+    pub notes: Vec<Note>,
+    pub words: Vec<Word>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ResourceFull {
     #[serde(flatten)]
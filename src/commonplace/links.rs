@@ -0,0 +1,266 @@
+//! Dead-link detection for website resources. A background checker sends a HEAD
+//! request to each page on a fixed interval and records the outcome in
+//! `resource_links`, so pages that are about to vanish can be archived while they
+//! still exist (`GET /commonplace/resources?link_status=dead`).
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use reqwest::{StatusCode, redirect};
+use serde::{Deserialize, Serialize};
+use tokio_util::sync::CancellationToken;
+
+use crate::db::Database;
+
+const REQUEST_TIMEOUT_SECS: u64 = 15;
+/// Resources checked per run; the rest are picked up on the next tick
+const BATCH_SIZE: i64 = 50;
+/// Consecutive errors after which a link is treated as gone
+const MAX_FAILURES: i64 = 3;
+const TICK: Duration = Duration::from_secs(15 * 60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkStatus {
+    Ok,
+    Redirected,
+    Gone,
+    /// The check failed in a way that may be temporary (timeouts, 5xx, blocked)
+    Error,
+}
+
+impl LinkStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LinkStatus::Ok => "ok",
+            LinkStatus::Redirected => "redirected",
+            LinkStatus::Gone => "gone",
+            LinkStatus::Error => "error",
+        }
+    }
+
+    /// Accepts `dead` as an alias for `gone`
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "ok" => Some(LinkStatus::Ok),
+            "redirected" => Some(LinkStatus::Redirected),
+            "gone" | "dead" => Some(LinkStatus::Gone),
+            "error" => Some(LinkStatus::Error),
+            _ => None,
+        }
+    }
+}
+
+/// Outcome of a single check, before consecutive failures are taken into account
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinkCheck {
+    pub status: LinkStatus,
+    pub http_status: Option<u16>,
+    pub location: Option<String>,
+    pub error: Option<String>,
+}
+
+impl LinkCheck {
+    fn from_response(code: StatusCode, location: Option<String>) -> Self {
+        let status = if code.is_success() {
+            LinkStatus::Ok
+        } else if code.is_redirection() {
+            LinkStatus::Redirected
+        } else if code == StatusCode::NOT_FOUND || code == StatusCode::GONE {
+            LinkStatus::Gone
+        } else {
+            LinkStatus::Error
+        };
+        Self {
+            status,
+            http_status: Some(code.as_u16()),
+            location: location.filter(|_| status == LinkStatus::Redirected),
+            error: (status == LinkStatus::Error).then(|| code.to_string()),
+        }
+    }
+
+    fn from_error(error: &reqwest::Error) -> Self {
+        Self {
+            status: LinkStatus::Error,
+            http_status: None,
+            location: None,
+            error: Some(error.to_string()),
+        }
+    }
+}
+
+/// Status to record given this check and the failures that preceded it, plus the new
+/// failure count
+fn settle(check: &LinkCheck, previous_failures: i64) -> (LinkStatus, i64) {
+    if check.status != LinkStatus::Error {
+        return (check.status, 0);
+    }
+    let failures = previous_failures + 1;
+    if failures >= MAX_FAILURES {
+        (LinkStatus::Gone, failures)
+    } else {
+        (LinkStatus::Error, failures)
+    }
+}
+
+pub struct LinkChecker {
+    db: Arc<Database>,
+    client: reqwest::Client,
+    interval_hours: u64,
+}
+
+impl LinkChecker {
+    pub fn new(db: Arc<Database>, interval_hours: u64) -> Self {
+        // Redirects are reported, not followed
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(REQUEST_TIMEOUT_SECS))
+            .user_agent(concat!("bibliotek/", env!("CARGO_PKG_VERSION")))
+            .redirect(redirect::Policy::none())
+            .build()
+            .unwrap_or_default();
+        Self {
+            db,
+            client,
+            interval_hours,
+        }
+    }
+
+    pub async fn check(&self, url: &str) -> LinkCheck {
+        let check = match self.client.head(url).send().await {
+            Ok(resp) => LinkCheck::from_response(resp.status(), location(&resp)),
+            Err(e) => return LinkCheck::from_error(&e),
+        };
+        // Some servers do not implement HEAD; ask again with GET
+        match check.http_status.and_then(|s| StatusCode::from_u16(s).ok()) {
+            Some(StatusCode::METHOD_NOT_ALLOWED | StatusCode::NOT_IMPLEMENTED) => {
+                match self.client.get(url).send().await {
+                    Ok(resp) => LinkCheck::from_response(resp.status(), location(&resp)),
+                    Err(e) => LinkCheck::from_error(&e),
+                }
+            }
+            _ => check,
+        }
+    }
+
+    /// Website resources with an http(s) title that were never checked or whose last
+    /// check is older than the interval
+    async fn due(&self) -> Result<Vec<(i32, String, i64)>> {
+        let query = r#"
+            SELECT resources.id, resources.title, COALESCE(resource_links.failures, 0)
+            FROM resources
+            LEFT JOIN resource_links ON resource_links.resource_id = resources.id
+            WHERE resources.type = 'website'
+              AND resources.deleted_at IS NULL
+              AND (resources.title LIKE 'http://%' OR resources.title LIKE 'https://%')
+              AND (resource_links.checked_at IS NULL
+                   OR resource_links.checked_at < strftime('%Y-%m-%dT%H:%M:%fZ', 'now', ?))
+            ORDER BY resource_links.checked_at IS NOT NULL, resource_links.checked_at
+            LIMIT ?
+        "#;
+        let cutoff = format!("-{} hours", self.interval_hours);
+        let mut rows = self
+            .db
+            .connection()
+            .query(query, libsql::params![cutoff, BATCH_SIZE])
+            .await?;
+        let mut due = Vec::new();
+        while let Some(row) = rows.next().await? {
+            due.push((row.get(0)?, row.get(1)?, row.get(2)?));
+        }
+        Ok(due)
+    }
+
+    async fn record(&self, resource_id: i32, url: &str, check: &LinkCheck, failures: i64) -> Result<()> {
+        let (status, failures) = settle(check, failures);
+        let query = r#"
+            INSERT INTO resource_links (resource_id, url, status, http_status, location, error, failures, checked_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?, strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))
+            ON CONFLICT (resource_id) DO UPDATE SET
+                url = excluded.url,
+                status = excluded.status,
+                http_status = excluded.http_status,
+                location = excluded.location,
+                error = excluded.error,
+                failures = excluded.failures,
+                checked_at = excluded.checked_at
+        "#;
+        let params = libsql::params![
+            resource_id,
+            url,
+            status.as_str(),
+            check.http_status.map(i64::from),
+            check.location.clone(),
+            check.error.clone(),
+            failures
+        ];
+        self.db
+            .write(|| async { Ok(self.db.connection().execute(query, params).await?) })
+            .await?;
+        if status == LinkStatus::Gone {
+            tracing::info!(resource_id, url, "link is gone");
+        }
+        Ok(())
+    }
+
+    /// Checks every resource that is due and returns how many were checked.
+    pub async fn check_due(&self) -> Result<usize> {
+        let due = self.due().await?;
+        for (resource_id, url, failures) in &due {
+            let check = self.check(url).await;
+            self.record(*resource_id, url, &check, *failures).await?;
+        }
+        Ok(due.len())
+    }
+
+    pub fn start(self, cancel: CancellationToken) {
+        if self.interval_hours == 0 {
+            tracing::info!("Link checker disabled");
+            return;
+        }
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(TICK);
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => {
+                        match self.check_due().await {
+                            Ok(0) => {}
+                            Ok(checked) => tracing::info!("Checked {} resource links", checked),
+                            Err(e) => tracing::warn!("Failed to check resource links: {}", e),
+                        }
+                    }
+                    _ = cancel.cancelled() => {
+                        tracing::info!("Link checker shutting down");
+                        break;
+                    }
+                }
+            }
+        });
+    }
+}
+
+fn location(resp: &reqwest::Response) -> Option<String> {
+    resp.headers()
+        .get(reqwest::header::LOCATION)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_repeated_errors_mark_link_gone() {
+        let moved = LinkCheck::from_response(StatusCode::MOVED_PERMANENTLY, Some("https://new.test".to_string()));
+        assert_eq!(moved.status, LinkStatus::Redirected);
+        assert_eq!(moved.location.as_deref(), Some("https://new.test"));
+        assert_eq!(LinkCheck::from_response(StatusCode::GONE, None).status, LinkStatus::Gone);
+
+        let unavailable = LinkCheck::from_response(StatusCode::SERVICE_UNAVAILABLE, None);
+        assert_eq!(settle(&unavailable, 0), (LinkStatus::Error, 1));
+        assert_eq!(settle(&unavailable, MAX_FAILURES - 1), (LinkStatus::Gone, MAX_FAILURES));
+        assert_eq!(settle(&LinkCheck::from_response(StatusCode::OK, None), 2), (LinkStatus::Ok, 0));
+    }
+}
@@ -0,0 +1,35 @@
+//! Notes rendered from Markdown to HTML on the server
+//! (`GET /commonplace/notes/:id/rendered`), so clients without a renderer of their
+//! own (extension popups, e-readers) can show them as-is. Note content is whatever
+//! was typed or synced in, so the output is sanitized before it leaves: scripts,
+//! event handlers and other unsafe markup are dropped, and links get
+//! `rel="noopener noreferrer"`.
+
+use ammonia::clean;
+use pulldown_cmark::{Options, Parser, html};
+
+/// Renders Markdown to an HTML fragment that is safe to insert into a page
+pub fn render(markdown: &str) -> String {
+    let options = Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH | Options::ENABLE_FOOTNOTES;
+    let mut out = String::new();
+    html::push_html(&mut out, Parser::new_ext(markdown, options));
+    clean(&out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_sanitizes_markdown() {
+        let rendered = render(
+            "# Title\n\nSome *emphasis* and a [link](https://example.com).\n\n<script>alert(1)</script>\n\n<img src=x onerror=alert(1)>",
+        );
+        assert!(rendered.contains("<h1>Title</h1>"));
+        assert!(rendered.contains("<em>emphasis</em>"));
+        assert!(rendered.contains("<a href=\"https://example.com\" rel=\"noopener noreferrer\">link</a>"));
+        assert!(!rendered.contains("script"));
+        assert!(!rendered.contains("onerror"));
+        assert!(!render("[x](javascript:alert(1))").contains("javascript"));
+    }
+}
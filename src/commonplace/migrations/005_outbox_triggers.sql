@@ -0,0 +1,26 @@
+-- Record annotation events in the outbox atomically with the write itself.
+-- Soft deletes are not upserts, so updates that leave the row deleted are skipped.
+CREATE TRIGGER IF NOT EXISTS annotations_outbox_insert
+AFTER INSERT ON annotations
+BEGIN
+    INSERT INTO outbox (event_type, payload)
+    VALUES ('annotation_upserted', json_object(
+        'type', 'annotation_upserted',
+        'annotation_id', NEW.id,
+        'resource_id', NEW.resource_id,
+        'created', json('true')
+    ));
+END;
+
+CREATE TRIGGER IF NOT EXISTS annotations_outbox_update
+AFTER UPDATE ON annotations
+WHEN NEW.deleted_at IS NULL
+BEGIN
+    INSERT INTO outbox (event_type, payload)
+    VALUES ('annotation_upserted', json_object(
+        'type', 'annotation_upserted',
+        'annotation_id', NEW.id,
+        'resource_id', NEW.resource_id,
+        'created', json('false')
+    ));
+END;
@@ -0,0 +1,65 @@
+-- Backfill annotation boundaries into the canonical schema (see commonplace/boundary.rs).
+-- The rewrite isn't a content change, so the outbox events the update trigger queues
+-- for it are dropped again at the end.
+CREATE TEMP TABLE boundary_backfill_outbox AS SELECT COALESCE(MAX(id), 0) AS last_id FROM outbox;
+
+-- Light: chunk data without geometry
+UPDATE annotations
+SET boundary = json_object(
+    'version', 1,
+    'source', 'light',
+    'quote', json(CASE WHEN trim(text) != '' THEN json_object('exact', text) END),
+    'extra', json_object(
+        'groupID', json_extract(boundary, '$.groupID'),
+        'date', json_extract(boundary, '$.date'),
+        'chunks', json(json_extract(boundary, '$.chunks')),
+        'url', json_extract(boundary, '$.url')
+    )
+)
+WHERE json_type(boundary) = 'object'
+  AND json_extract(boundary, '$.version') IS NULL
+  AND json_extract(boundary, '$.chunks') IS NOT NULL;
+
+-- Research: react-pdf-highlighter position stored as a JSON string, rects in viewport pixels
+UPDATE annotations
+SET boundary = json_object(
+    'version', 1,
+    'source', 'research',
+    'page', COALESCE(
+        json_extract(boundary, '$.pageNumber'),
+        json_extract(json_extract(boundary, '$.position'), '$.boundingRect.pageNumber')
+    ),
+    'rects', json((
+        SELECT json_group_array(json_object(
+            'page', json_extract(r.value, '$.pageNumber'),
+            'x', 1.0 * json_extract(r.value, '$.x1') / json_extract(r.value, '$.width'),
+            'y', 1.0 * json_extract(r.value, '$.y1') / json_extract(r.value, '$.height'),
+            'width', 1.0 * (json_extract(r.value, '$.x2') - json_extract(r.value, '$.x1')) / json_extract(r.value, '$.width'),
+            'height', 1.0 * (json_extract(r.value, '$.y2') - json_extract(r.value, '$.y1')) / json_extract(r.value, '$.height')
+        ))
+        FROM json_each(json_extract(annotations.boundary, '$.position'), '$.rects') AS r
+        WHERE json_extract(r.value, '$.width') > 0 AND json_extract(r.value, '$.height') > 0
+    )),
+    'quote', json(CASE WHEN trim(text) != '' THEN json_object('exact', text) END)
+)
+WHERE json_type(boundary) = 'object'
+  AND json_extract(boundary, '$.version') IS NULL
+  AND json_extract(boundary, '$.source') = 'research';
+
+-- Anything else (seed data, manual entries): keep the original under extra
+UPDATE annotations
+SET boundary = json_object(
+    'version', 1,
+    'source', COALESCE(json_extract(boundary, '$.source'), 'unknown'),
+    'page', COALESCE(json_extract(boundary, '$.pageNumber'), json_extract(boundary, '$.page')),
+    'quote', json(CASE WHEN trim(text) != '' THEN json_object('exact', text) END),
+    'extra', json(json_remove(boundary, '$.source'))
+)
+WHERE json_type(boundary) = 'object'
+  AND json_extract(boundary, '$.version') IS NULL;
+
+DELETE FROM outbox
+WHERE event_type = 'annotation_upserted'
+  AND id > (SELECT last_id FROM boundary_backfill_outbox);
+
+DROP TABLE boundary_backfill_outbox;
@@ -0,0 +1,12 @@
+-- Captured text of website resources, one row per capture whose content changed
+CREATE TABLE IF NOT EXISTS resource_versions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    resource_id INTEGER NOT NULL,
+    url TEXT NOT NULL,
+    content TEXT NOT NULL,
+    content_hash TEXT NOT NULL,
+    captured_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    FOREIGN KEY (resource_id) REFERENCES resources (id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_resource_versions_resource_id ON resource_versions (resource_id, id);
@@ -0,0 +1,15 @@
+-- Latest link check of each website resource
+CREATE TABLE IF NOT EXISTS resource_links (
+    resource_id INTEGER PRIMARY KEY,
+    url TEXT NOT NULL,
+    status TEXT NOT NULL CHECK (status IN ('ok', 'redirected', 'gone', 'error')),
+    http_status INTEGER,
+    location TEXT, -- redirect target
+    error TEXT,
+    failures INTEGER NOT NULL DEFAULT 0, -- consecutive checks that ended in 'error'
+    checked_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    FOREIGN KEY (resource_id) REFERENCES resources (id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_resource_links_status ON resource_links (status);
+CREATE INDEX IF NOT EXISTS idx_resource_links_checked_at ON resource_links (checked_at);
@@ -0,0 +1,15 @@
+-- Book a resource was read from, so annotation activity can be shown on the library.
+-- Not a foreign key: books live in another module's schema and are purged independently.
+ALTER TABLE resources ADD COLUMN book_id INTEGER;
+
+CREATE INDEX IF NOT EXISTS idx_resources_book_id ON resources (book_id);
+
+-- Link existing PDF resources to the book with the same title
+UPDATE resources
+SET book_id = (
+    SELECT books.id FROM books
+    WHERE books.title = resources.title COLLATE NOCASE AND books.deleted_at IS NULL
+    ORDER BY books.id
+    LIMIT 1
+)
+WHERE type = 'pdf' AND book_id IS NULL;
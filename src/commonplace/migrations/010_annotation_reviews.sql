@@ -0,0 +1,9 @@
+-- One row each time an annotation is marked as seen in the daily review
+CREATE TABLE IF NOT EXISTS annotation_reviews (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    annotation_id INTEGER NOT NULL,
+    reviewed_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    FOREIGN KEY (annotation_id) REFERENCES annotations (id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_annotation_reviews_annotation_id ON annotation_reviews (annotation_id);
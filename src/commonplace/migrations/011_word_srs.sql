@@ -0,0 +1,9 @@
+-- SM-2 spaced repetition state for vocabulary. A word without due_at has never been
+-- reviewed and is due straight away.
+ALTER TABLE words ADD COLUMN ease REAL NOT NULL DEFAULT 2.5;
+ALTER TABLE words ADD COLUMN interval_days INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE words ADD COLUMN repetitions INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE words ADD COLUMN due_at TEXT;
+ALTER TABLE words ADD COLUMN last_reviewed_at TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_words_due_at ON words (due_at);
@@ -0,0 +1,5 @@
+-- Dictionary details for vocabulary: part of speech, pronunciation and an example
+-- sentence, all optional.
+ALTER TABLE words ADD COLUMN part_of_speech TEXT;
+ALTER TABLE words ADD COLUMN phonetic TEXT;
+ALTER TABLE words ADD COLUMN example TEXT;
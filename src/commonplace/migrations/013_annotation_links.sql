@@ -0,0 +1,14 @@
+-- Typed links between two annotations; each link is a backlink of its target
+CREATE TABLE IF NOT EXISTS annotation_links (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    source_id INTEGER NOT NULL,
+    target_id INTEGER NOT NULL,
+    relation TEXT NOT NULL CHECK (relation IN ('relates_to', 'contradicts')),
+    created_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    CHECK (source_id != target_id),
+    UNIQUE (source_id, target_id, relation),
+    FOREIGN KEY (source_id) REFERENCES annotations (id) ON DELETE CASCADE,
+    FOREIGN KEY (target_id) REFERENCES annotations (id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_annotation_links_target_id ON annotation_links (target_id);
@@ -0,0 +1,13 @@
+-- One row each time an annotation is shown outside its resource: in the daily
+-- review, in an export or in a cold-highlights digest. Annotations without any
+-- row here (or in annotation_reviews) are "cold".
+CREATE TABLE IF NOT EXISTS annotation_surfacings (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    annotation_id INTEGER NOT NULL,
+    via TEXT NOT NULL CHECK (via IN ('review', 'export', 'digest')),
+    surfaced_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    FOREIGN KEY (annotation_id) REFERENCES annotations (id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_annotation_surfacings_annotation_id ON annotation_surfacings (annotation_id);
+CREATE INDEX IF NOT EXISTS idx_annotation_surfacings_via ON annotation_surfacings (via, surfaced_at);
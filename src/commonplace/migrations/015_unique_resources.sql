@@ -0,0 +1,52 @@
+-- At most one live resource per external id, and one live website per URL, so
+-- concurrent syncs can upsert instead of racing a lookup against an insert.
+-- Existing duplicates are merged into the oldest row first: their annotations,
+-- notes, words and captured versions move over and the duplicate is soft-deleted.
+CREATE TEMP TABLE duplicate_resources (id INTEGER PRIMARY KEY, keep_id INTEGER NOT NULL);
+
+INSERT INTO duplicate_resources (id, keep_id)
+SELECT dup.id, MIN(keep.id)
+FROM resources dup
+JOIN resources keep ON keep.external_id = dup.external_id AND keep.id < dup.id AND keep.deleted_at IS NULL
+WHERE dup.deleted_at IS NULL AND dup.external_id IS NOT NULL
+GROUP BY dup.id;
+
+UPDATE annotations SET resource_id = (SELECT keep_id FROM duplicate_resources WHERE id = annotations.resource_id)
+WHERE resource_id IN (SELECT id FROM duplicate_resources);
+UPDATE notes SET resource_id = (SELECT keep_id FROM duplicate_resources WHERE id = notes.resource_id)
+WHERE resource_id IN (SELECT id FROM duplicate_resources);
+UPDATE words SET resource_id = (SELECT keep_id FROM duplicate_resources WHERE id = words.resource_id)
+WHERE resource_id IN (SELECT id FROM duplicate_resources);
+UPDATE resource_versions SET resource_id = (SELECT keep_id FROM duplicate_resources WHERE id = resource_versions.resource_id)
+WHERE resource_id IN (SELECT id FROM duplicate_resources);
+UPDATE resources SET deleted_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
+WHERE id IN (SELECT id FROM duplicate_resources);
+
+DELETE FROM duplicate_resources;
+
+INSERT INTO duplicate_resources (id, keep_id)
+SELECT dup.id, MIN(keep.id)
+FROM resources dup
+JOIN resources keep
+    ON keep.title = dup.title AND keep.type = 'website' AND keep.id < dup.id AND keep.deleted_at IS NULL
+WHERE dup.deleted_at IS NULL AND dup.type = 'website'
+GROUP BY dup.id;
+
+UPDATE annotations SET resource_id = (SELECT keep_id FROM duplicate_resources WHERE id = annotations.resource_id)
+WHERE resource_id IN (SELECT id FROM duplicate_resources);
+UPDATE notes SET resource_id = (SELECT keep_id FROM duplicate_resources WHERE id = notes.resource_id)
+WHERE resource_id IN (SELECT id FROM duplicate_resources);
+UPDATE words SET resource_id = (SELECT keep_id FROM duplicate_resources WHERE id = words.resource_id)
+WHERE resource_id IN (SELECT id FROM duplicate_resources);
+UPDATE resource_versions SET resource_id = (SELECT keep_id FROM duplicate_resources WHERE id = resource_versions.resource_id)
+WHERE resource_id IN (SELECT id FROM duplicate_resources);
+UPDATE resources SET deleted_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
+WHERE id IN (SELECT id FROM duplicate_resources);
+
+DROP TABLE duplicate_resources;
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_resources_live_external_id
+ON resources (external_id) WHERE external_id IS NOT NULL AND deleted_at IS NULL;
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_resources_live_website_title
+ON resources (title) WHERE type = 'website' AND deleted_at IS NULL;
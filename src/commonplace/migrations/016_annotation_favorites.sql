@@ -0,0 +1,3 @@
+-- Starred highlights, to surface the best ones in review and exports
+ALTER TABLE annotations ADD COLUMN is_favorite INTEGER NOT NULL DEFAULT 0;
+CREATE INDEX IF NOT EXISTS idx_annotations_is_favorite ON annotations (is_favorite);
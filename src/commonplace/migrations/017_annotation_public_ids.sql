@@ -0,0 +1,38 @@
+-- Stable ids for annotation permalinks (/a/:public_id). Row ids are not exposed
+-- in links, since an archive restored elsewhere renumbers them.
+ALTER TABLE annotations ADD COLUMN public_id TEXT;
+
+-- The triggers are recreated below with the permalink; dropping them first also
+-- keeps the backfill from queueing an event for every annotation
+DROP TRIGGER IF EXISTS annotations_outbox_insert;
+DROP TRIGGER IF EXISTS annotations_outbox_update;
+
+UPDATE annotations SET public_id = lower(hex(randomblob(8))) WHERE public_id IS NULL;
+CREATE UNIQUE INDEX IF NOT EXISTS idx_annotations_public_id ON annotations (public_id);
+
+CREATE TRIGGER IF NOT EXISTS annotations_outbox_insert
+AFTER INSERT ON annotations
+BEGIN
+    INSERT INTO outbox (event_type, payload)
+    VALUES ('annotation_upserted', json_object(
+        'type', 'annotation_upserted',
+        'annotation_id', NEW.id,
+        'resource_id', NEW.resource_id,
+        'created', json('true'),
+        'permalink', '/a/' || NEW.public_id
+    ));
+END;
+
+CREATE TRIGGER IF NOT EXISTS annotations_outbox_update
+AFTER UPDATE ON annotations
+WHEN NEW.deleted_at IS NULL
+BEGIN
+    INSERT INTO outbox (event_type, payload)
+    VALUES ('annotation_upserted', json_object(
+        'type', 'annotation_upserted',
+        'annotation_id', NEW.id,
+        'resource_id', NEW.resource_id,
+        'created', json('false'),
+        'permalink', '/a/' || NEW.public_id
+    ));
+END;
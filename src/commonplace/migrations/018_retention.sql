@@ -0,0 +1,24 @@
+-- One row per execution of a retention policy, with what it did
+CREATE TABLE IF NOT EXISTS retention_runs (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    policy TEXT NOT NULL CHECK (policy IN ('purge_deleted', 'archive_inactive')),
+    status TEXT NOT NULL DEFAULT 'running' CHECK (status IN ('running', 'ok', 'failed')),
+    summary TEXT,
+    error TEXT,
+    started_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    finished_at TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_retention_runs_policy ON retention_runs (policy, id);
+
+-- Compressed exports of inactive resources in the bucket. A resource is archived
+-- again only if it sees activity after its last archive.
+CREATE TABLE IF NOT EXISTS resource_archives (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    resource_id INTEGER NOT NULL,
+    key TEXT NOT NULL,
+    archived_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    FOREIGN KEY (resource_id) REFERENCES resources (id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_resource_archives_resource_id ON resource_archives (resource_id, archived_at);
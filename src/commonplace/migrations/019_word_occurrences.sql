@@ -0,0 +1,37 @@
+-- Every resource a word was encountered in. The resource a word was saved from is
+-- always among them; the triggers keep that row in step with words.resource_id.
+CREATE TABLE IF NOT EXISTS word_occurrences (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    word_id INTEGER NOT NULL,
+    resource_id INTEGER NOT NULL,
+    created_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    UNIQUE (word_id, resource_id),
+    FOREIGN KEY (word_id) REFERENCES words (id) ON DELETE CASCADE,
+    FOREIGN KEY (resource_id) REFERENCES resources (id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_word_occurrences_resource_id ON word_occurrences (resource_id);
+
+INSERT OR IGNORE INTO word_occurrences (word_id, resource_id, created_at)
+SELECT id, resource_id, created_at FROM words;
+
+CREATE TRIGGER IF NOT EXISTS words_occurrence_insert
+AFTER INSERT ON words
+BEGIN
+    INSERT OR IGNORE INTO word_occurrences (word_id, resource_id) VALUES (NEW.id, NEW.resource_id);
+END;
+
+-- Moving a word (e.g. out of the inbox) moves the occurrence it was saved with
+CREATE TRIGGER IF NOT EXISTS words_occurrence_move
+AFTER UPDATE OF resource_id ON words
+WHEN NEW.resource_id != OLD.resource_id
+BEGIN
+    DELETE FROM word_occurrences WHERE word_id = NEW.id AND resource_id = OLD.resource_id;
+    INSERT OR IGNORE INTO word_occurrences (word_id, resource_id) VALUES (NEW.id, NEW.resource_id);
+END;
+
+CREATE TRIGGER IF NOT EXISTS words_occurrence_delete
+AFTER DELETE ON words
+BEGIN
+    DELETE FROM word_occurrences WHERE word_id = OLD.id;
+END;
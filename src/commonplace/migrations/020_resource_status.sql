@@ -0,0 +1,7 @@
+-- Read-later state of a resource: whether it has been read, and how far through it
+-- the reader got as a fraction
+ALTER TABLE resources ADD COLUMN status TEXT NOT NULL DEFAULT 'unread'
+    CHECK (status IN ('unread', 'reading', 'done'));
+ALTER TABLE resources ADD COLUMN progress REAL NOT NULL DEFAULT 0
+    CHECK (progress BETWEEN 0 AND 1);
+CREATE INDEX IF NOT EXISTS idx_resources_status ON resources (status);
@@ -0,0 +1,4 @@
+-- Comment a comment replies to. Not a foreign key: a purged parent leaves its
+-- replies behind, and those are shown at the top level of the thread.
+ALTER TABLE comments ADD COLUMN parent_comment_id INTEGER;
+CREATE INDEX IF NOT EXISTS idx_comments_parent ON comments (parent_comment_id) WHERE parent_comment_id IS NOT NULL;
@@ -0,0 +1,4 @@
+-- Same as books.visibility: whether the resource's page is listed, reachable by
+-- link only, or never shown on a public surface
+ALTER TABLE resources ADD COLUMN visibility TEXT NOT NULL DEFAULT 'public'
+    CHECK (visibility IN ('private', 'shared', 'public'));
@@ -0,0 +1,19 @@
+-- Record resource changes in the command palette's journal (see palette_changes)
+CREATE TRIGGER IF NOT EXISTS palette_resources_insert
+AFTER INSERT ON resources
+BEGIN
+    INSERT INTO palette_changes (kind, entity_id) VALUES ('resource', NEW.id);
+END;
+
+CREATE TRIGGER IF NOT EXISTS palette_resources_update
+AFTER UPDATE OF title, type, deleted_at ON resources
+WHEN OLD.title IS NOT NEW.title OR OLD.type IS NOT NEW.type OR OLD.deleted_at IS NOT NEW.deleted_at
+BEGIN
+    INSERT INTO palette_changes (kind, entity_id) VALUES ('resource', NEW.id);
+END;
+
+CREATE TRIGGER IF NOT EXISTS palette_resources_delete
+AFTER DELETE ON resources
+BEGIN
+    INSERT INTO palette_changes (kind, entity_id) VALUES ('resource', OLD.id);
+END;
@@ -0,0 +1,14 @@
+-- Readable HTML snapshots of website resources kept in the bucket, so highlights
+-- keep their context after the page moves or disappears
+CREATE TABLE IF NOT EXISTS resource_snapshots (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    resource_id INTEGER NOT NULL,
+    url TEXT NOT NULL,
+    key TEXT NOT NULL,
+    title TEXT,
+    size INTEGER NOT NULL,
+    created_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    FOREIGN KEY (resource_id) REFERENCES resources (id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_resource_snapshots_resource_id ON resource_snapshots (resource_id, id);
@@ -0,0 +1,12 @@
+-- Tags on resources, from the same vocabulary as the library's book tags
+CREATE TABLE IF NOT EXISTS resource_tags (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    resource_id INTEGER NOT NULL,
+    tag_id INTEGER NOT NULL,
+    created_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    FOREIGN KEY (resource_id) REFERENCES resources (id) ON DELETE CASCADE,
+    FOREIGN KEY (tag_id) REFERENCES tags (id)
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_resource_tags_unique ON resource_tags (resource_id, tag_id);
+CREATE INDEX IF NOT EXISTS idx_resource_tags_tag_id ON resource_tags (tag_id);
@@ -0,0 +1,5 @@
+DROP TABLE IF EXISTS words;
+DROP TABLE IF EXISTS notes;
+DROP TABLE IF EXISTS comments;
+DROP TABLE IF EXISTS annotations;
+DROP TABLE IF EXISTS resources;
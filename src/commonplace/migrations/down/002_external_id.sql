@@ -0,0 +1,9 @@
+DROP INDEX IF EXISTS idx_resources_external_id;
+DROP INDEX IF EXISTS idx_annotations_external_id;
+DROP INDEX IF EXISTS idx_notes_external_id;
+DROP INDEX IF EXISTS idx_comments_external_id;
+
+ALTER TABLE resources DROP COLUMN external_id;
+ALTER TABLE annotations DROP COLUMN external_id;
+ALTER TABLE notes DROP COLUMN external_id;
+ALTER TABLE comments DROP COLUMN external_id;
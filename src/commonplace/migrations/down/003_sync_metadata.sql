@@ -0,0 +1,19 @@
+DROP INDEX IF EXISTS idx_resources_content_hash;
+DROP INDEX IF EXISTS idx_annotations_content_hash;
+DROP INDEX IF EXISTS idx_notes_content_hash;
+DROP INDEX IF EXISTS idx_comments_content_hash;
+
+DROP INDEX IF EXISTS idx_resources_deleted_at;
+DROP INDEX IF EXISTS idx_annotations_deleted_at;
+DROP INDEX IF EXISTS idx_notes_deleted_at;
+DROP INDEX IF EXISTS idx_comments_deleted_at;
+
+ALTER TABLE resources DROP COLUMN content_hash;
+ALTER TABLE annotations DROP COLUMN content_hash;
+ALTER TABLE notes DROP COLUMN content_hash;
+ALTER TABLE comments DROP COLUMN content_hash;
+
+ALTER TABLE resources DROP COLUMN deleted_at;
+ALTER TABLE annotations DROP COLUMN deleted_at;
+ALTER TABLE notes DROP COLUMN deleted_at;
+ALTER TABLE comments DROP COLUMN deleted_at;
@@ -0,0 +1,2 @@
+DROP TRIGGER IF EXISTS annotations_outbox_update;
+DROP TRIGGER IF EXISTS annotations_outbox_insert;
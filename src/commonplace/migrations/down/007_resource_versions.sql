@@ -0,0 +1,2 @@
+DROP INDEX IF EXISTS idx_resource_versions_resource_id;
+DROP TABLE IF EXISTS resource_versions;
@@ -0,0 +1,3 @@
+DROP INDEX IF EXISTS idx_resource_links_checked_at;
+DROP INDEX IF EXISTS idx_resource_links_status;
+DROP TABLE IF EXISTS resource_links;
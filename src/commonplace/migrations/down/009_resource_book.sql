@@ -0,0 +1,2 @@
+DROP INDEX IF EXISTS idx_resources_book_id;
+ALTER TABLE resources DROP COLUMN book_id;
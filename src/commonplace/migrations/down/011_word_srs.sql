@@ -0,0 +1,6 @@
+DROP INDEX IF EXISTS idx_words_due_at;
+ALTER TABLE words DROP COLUMN last_reviewed_at;
+ALTER TABLE words DROP COLUMN due_at;
+ALTER TABLE words DROP COLUMN repetitions;
+ALTER TABLE words DROP COLUMN interval_days;
+ALTER TABLE words DROP COLUMN ease;
@@ -0,0 +1,3 @@
+ALTER TABLE words DROP COLUMN example;
+ALTER TABLE words DROP COLUMN phonetic;
+ALTER TABLE words DROP COLUMN part_of_speech;
@@ -0,0 +1,2 @@
+DROP INDEX IF EXISTS idx_annotation_links_target_id;
+DROP TABLE IF EXISTS annotation_links;
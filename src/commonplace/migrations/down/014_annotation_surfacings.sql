@@ -0,0 +1,3 @@
+DROP INDEX IF EXISTS idx_annotation_surfacings_via;
+DROP INDEX IF EXISTS idx_annotation_surfacings_annotation_id;
+DROP TABLE IF EXISTS annotation_surfacings;
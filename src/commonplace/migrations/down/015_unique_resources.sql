@@ -0,0 +1,2 @@
+DROP INDEX IF EXISTS idx_resources_live_website_title;
+DROP INDEX IF EXISTS idx_resources_live_external_id;
@@ -0,0 +1,2 @@
+DROP INDEX IF EXISTS idx_annotations_is_favorite;
+ALTER TABLE annotations DROP COLUMN is_favorite;
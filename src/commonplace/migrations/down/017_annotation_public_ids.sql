@@ -0,0 +1,30 @@
+DROP TRIGGER IF EXISTS annotations_outbox_insert;
+DROP TRIGGER IF EXISTS annotations_outbox_update;
+
+CREATE TRIGGER IF NOT EXISTS annotations_outbox_insert
+AFTER INSERT ON annotations
+BEGIN
+    INSERT INTO outbox (event_type, payload)
+    VALUES ('annotation_upserted', json_object(
+        'type', 'annotation_upserted',
+        'annotation_id', NEW.id,
+        'resource_id', NEW.resource_id,
+        'created', json('true')
+    ));
+END;
+
+CREATE TRIGGER IF NOT EXISTS annotations_outbox_update
+AFTER UPDATE ON annotations
+WHEN NEW.deleted_at IS NULL
+BEGIN
+    INSERT INTO outbox (event_type, payload)
+    VALUES ('annotation_upserted', json_object(
+        'type', 'annotation_upserted',
+        'annotation_id', NEW.id,
+        'resource_id', NEW.resource_id,
+        'created', json('false')
+    ));
+END;
+
+DROP INDEX IF EXISTS idx_annotations_public_id;
+ALTER TABLE annotations DROP COLUMN public_id;
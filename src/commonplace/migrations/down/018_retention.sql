@@ -0,0 +1,4 @@
+DROP INDEX IF EXISTS idx_resource_archives_resource_id;
+DROP TABLE IF EXISTS resource_archives;
+DROP INDEX IF EXISTS idx_retention_runs_policy;
+DROP TABLE IF EXISTS retention_runs;
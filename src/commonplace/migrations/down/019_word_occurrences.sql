@@ -0,0 +1,5 @@
+DROP TRIGGER IF EXISTS words_occurrence_delete;
+DROP TRIGGER IF EXISTS words_occurrence_move;
+DROP TRIGGER IF EXISTS words_occurrence_insert;
+DROP INDEX IF EXISTS idx_word_occurrences_resource_id;
+DROP TABLE IF EXISTS word_occurrences;
@@ -0,0 +1,3 @@
+DROP INDEX IF EXISTS idx_resources_status;
+ALTER TABLE resources DROP COLUMN progress;
+ALTER TABLE resources DROP COLUMN status;
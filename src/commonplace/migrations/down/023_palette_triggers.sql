@@ -0,0 +1,3 @@
+DROP TRIGGER IF EXISTS palette_resources_delete;
+DROP TRIGGER IF EXISTS palette_resources_update;
+DROP TRIGGER IF EXISTS palette_resources_insert;
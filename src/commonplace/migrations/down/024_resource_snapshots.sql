@@ -0,0 +1,2 @@
+DROP INDEX IF EXISTS idx_resource_snapshots_resource_id;
+DROP TABLE IF EXISTS resource_snapshots;
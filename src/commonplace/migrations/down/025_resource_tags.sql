@@ -0,0 +1,3 @@
+DROP INDEX IF EXISTS idx_resource_tags_tag_id;
+DROP INDEX IF EXISTS idx_resource_tags_unique;
+DROP TABLE IF EXISTS resource_tags;
@@ -1,7 +1,28 @@
+pub mod bookmarks;
+pub mod boundary;
+pub mod capture;
+pub mod compare;
+pub mod cursor;
+pub mod dictionary;
+pub mod digest;
+pub mod epub;
+pub mod export;
 mod handler;
+pub mod import;
 mod lib;
+pub mod links;
+pub mod markdown;
+pub mod permalink;
+pub mod quick;
+pub mod retention;
+pub mod review;
 mod routes;
+pub mod snapshot;
+pub mod srs;
+pub mod terms;
+pub mod trash;
 
+pub use handler::import_bookmarks;
 pub use lib::*;
 pub use routes::routes;
 
@@ -11,5 +32,77 @@ pub fn migrations() -> &'static [(&'static str, &'static str)] {
         ("commonplace_002_external_id.sql", include_str!("migrations/002_external_id.sql")),
         ("commonplace_003_sync_metadata.sql", include_str!("migrations/003_sync_metadata.sql")),
         ("commonplace_004_resource_config.sql", include_str!("migrations/004_resource_config.sql")),
+        ("commonplace_005_outbox_triggers.sql", include_str!("migrations/005_outbox_triggers.sql")),
+        ("commonplace_006_normalize_boundaries.sql", include_str!("migrations/006_normalize_boundaries.sql")),
+        ("commonplace_007_resource_versions.sql", include_str!("migrations/007_resource_versions.sql")),
+        ("commonplace_008_resource_links.sql", include_str!("migrations/008_resource_links.sql")),
+        ("commonplace_009_resource_book.sql", include_str!("migrations/009_resource_book.sql")),
+        ("commonplace_010_annotation_reviews.sql", include_str!("migrations/010_annotation_reviews.sql")),
+        ("commonplace_011_word_srs.sql", include_str!("migrations/011_word_srs.sql")),
+        ("commonplace_012_word_details.sql", include_str!("migrations/012_word_details.sql")),
+        ("commonplace_013_annotation_links.sql", include_str!("migrations/013_annotation_links.sql")),
+        (
+            "commonplace_014_annotation_surfacings.sql",
+            include_str!("migrations/014_annotation_surfacings.sql"),
+        ),
+        ("commonplace_015_unique_resources.sql", include_str!("migrations/015_unique_resources.sql")),
+        ("commonplace_016_annotation_favorites.sql", include_str!("migrations/016_annotation_favorites.sql")),
+        (
+            "commonplace_017_annotation_public_ids.sql",
+            include_str!("migrations/017_annotation_public_ids.sql"),
+        ),
+        ("commonplace_018_retention.sql", include_str!("migrations/018_retention.sql")),
+        ("commonplace_019_word_occurrences.sql", include_str!("migrations/019_word_occurrences.sql")),
+        ("commonplace_020_resource_status.sql", include_str!("migrations/020_resource_status.sql")),
+        ("commonplace_021_comment_threads.sql", include_str!("migrations/021_comment_threads.sql")),
+        ("commonplace_022_resource_visibility.sql", include_str!("migrations/022_resource_visibility.sql")),
+        ("commonplace_023_palette_triggers.sql", include_str!("migrations/023_palette_triggers.sql")),
+        ("commonplace_024_resource_snapshots.sql", include_str!("migrations/024_resource_snapshots.sql")),
+        ("commonplace_025_resource_tags.sql", include_str!("migrations/025_resource_tags.sql")),
+    ]
+}
+
+pub fn down_migrations() -> &'static [(&'static str, &'static str)] {
+    &[
+        ("commonplace_001_schema.sql", include_str!("migrations/down/001_schema.sql")),
+        ("commonplace_002_external_id.sql", include_str!("migrations/down/002_external_id.sql")),
+        ("commonplace_003_sync_metadata.sql", include_str!("migrations/down/003_sync_metadata.sql")),
+        ("commonplace_004_resource_config.sql", include_str!("migrations/down/004_resource_config.sql")),
+        ("commonplace_005_outbox_triggers.sql", include_str!("migrations/down/005_outbox_triggers.sql")),
+        (
+            "commonplace_006_normalize_boundaries.sql",
+            include_str!("migrations/down/006_normalize_boundaries.sql"),
+        ),
+        ("commonplace_007_resource_versions.sql", include_str!("migrations/down/007_resource_versions.sql")),
+        ("commonplace_008_resource_links.sql", include_str!("migrations/down/008_resource_links.sql")),
+        ("commonplace_009_resource_book.sql", include_str!("migrations/down/009_resource_book.sql")),
+        ("commonplace_010_annotation_reviews.sql", include_str!("migrations/down/010_annotation_reviews.sql")),
+        ("commonplace_011_word_srs.sql", include_str!("migrations/down/011_word_srs.sql")),
+        ("commonplace_012_word_details.sql", include_str!("migrations/down/012_word_details.sql")),
+        ("commonplace_013_annotation_links.sql", include_str!("migrations/down/013_annotation_links.sql")),
+        (
+            "commonplace_014_annotation_surfacings.sql",
+            include_str!("migrations/down/014_annotation_surfacings.sql"),
+        ),
+        ("commonplace_015_unique_resources.sql", include_str!("migrations/down/015_unique_resources.sql")),
+        (
+            "commonplace_016_annotation_favorites.sql",
+            include_str!("migrations/down/016_annotation_favorites.sql"),
+        ),
+        (
+            "commonplace_017_annotation_public_ids.sql",
+            include_str!("migrations/down/017_annotation_public_ids.sql"),
+        ),
+        ("commonplace_018_retention.sql", include_str!("migrations/down/018_retention.sql")),
+        ("commonplace_019_word_occurrences.sql", include_str!("migrations/down/019_word_occurrences.sql")),
+        ("commonplace_020_resource_status.sql", include_str!("migrations/down/020_resource_status.sql")),
+        ("commonplace_021_comment_threads.sql", include_str!("migrations/down/021_comment_threads.sql")),
+        (
+            "commonplace_022_resource_visibility.sql",
+            include_str!("migrations/down/022_resource_visibility.sql"),
+        ),
+        ("commonplace_023_palette_triggers.sql", include_str!("migrations/down/023_palette_triggers.sql")),
+        ("commonplace_024_resource_snapshots.sql", include_str!("migrations/down/024_resource_snapshots.sql")),
+        ("commonplace_025_resource_tags.sql", include_str!("migrations/down/025_resource_tags.sql")),
     ]
 }
@@ -0,0 +1,166 @@
+//! Annotation permalinks (`GET /a/:public_id`). Exports and outbox events carry
+//! these rather than row ids, so a link kept in another tool survives an archive
+//! being restored elsewhere. Following one opens the owning resource in a reader
+//! with the highlight in view: PDFs in the web app on the page from the
+//! annotation's boundary, websites in the plain HTML view. With `public.enabled`, a
+//! link to a highlight on a private resource is not found.
+
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use serde_json::Value;
+
+use super::{Annotation, Resource, ResourceType};
+use crate::handler::AppState;
+use crate::response::{internal_error, not_found};
+
+/// Where a permalink lands: the resource's reader view, anchored at `#annotation-<id>`
+pub fn reader_url(resource: &Resource, annotation: &Annotation) -> String {
+    let anchor = format!("#annotation-{}", annotation.id);
+    match resource.resource_type {
+        ResourceType::Pdf => {
+            let page = annotation
+                .boundary
+                .as_ref()
+                .and_then(|boundary| boundary.get("page"))
+                .and_then(Value::as_i64);
+            match page {
+                Some(page) => format!("/reading/{}?page={}{}", resource.id, page, anchor),
+                None => format!("/reading/{}{}", resource.id, anchor),
+            }
+        }
+        ResourceType::Website => format!("/html/commonplace/resources/{}{}", resource.id, anchor),
+    }
+}
+
+/// Origin the request was made to, for turning permalinks into absolute URLs in
+/// documents that leave the app
+pub fn origin(headers: &HeaderMap) -> Option<String> {
+    let host = headers.get(header::HOST)?.to_str().ok()?;
+    let scheme = headers
+        .get("x-forwarded-proto")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("http");
+    Some(format!("{}://{}", scheme, host))
+}
+
+pub async fn open_permalink(State(state): State<AppState>, Path(public_id): Path<String>) -> Response {
+    let lib = state.db.commonplace();
+    let found = async {
+        let Some(annotation) = lib.find_annotation_by_public_id(&public_id).await? else {
+            return Ok(None);
+        };
+        // A share link must not lead to a private resource when the library is public
+        let resource = lib
+            .get_resource(annotation.resource_id)
+            .await?
+            .filter(|resource| !state.public.enabled || resource.visibility.is_linkable());
+        Ok::<_, anyhow::Error>(resource.map(|resource| reader_url(&resource, &annotation)))
+    }
+    .await;
+
+    match found {
+        Ok(Some(location)) => (StatusCode::FOUND, [(header::LOCATION, location)]).into_response(),
+        Ok(None) => not_found("Annotation not found"),
+        Err(e) => {
+            tracing::error!("Failed to resolve permalink {}: {}", public_id, e);
+            internal_error("Failed to resolve permalink")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::commonplace::{CreateAnnotation, CreateResource, UpdateResource};
+    use crate::model::Visibility;
+    use crate::test_support::{test_db, test_state};
+
+    #[tokio::test]
+    async fn test_permalink_resolves_to_reader_page() {
+        let db = test_db().await;
+        let lib = db.commonplace();
+        let resource = lib
+            .create_resource(CreateResource {
+                title: "Paper".to_string(),
+                resource_type: ResourceType::Pdf,
+                external_id: None,
+                content_hash: None,
+            })
+            .await
+            .unwrap();
+        let annotation = lib
+            .create_annotation(CreateAnnotation {
+                resource_id: resource.id,
+                text: "highlight".to_string(),
+                color: None,
+                boundary: Some(serde_json::json!({"version": 1, "source": "research", "page": 7})),
+                external_id: None,
+                content_hash: None,
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(annotation.permalink(), format!("/a/{}", annotation.public_id));
+        let found = lib
+            .find_annotation_by_public_id(&annotation.public_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(found.id, annotation.id);
+        assert_eq!(
+            reader_url(&resource, &found),
+            format!("/reading/{}?page=7#annotation-{}", resource.id, annotation.id)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_private_resources_are_not_shared_in_public_mode() {
+        let mut state = test_state().await;
+        let lib = state.db.commonplace();
+        let resource = lib
+            .create_resource(CreateResource {
+                title: "https://example.com/essay".to_string(),
+                resource_type: ResourceType::Website,
+                external_id: None,
+                content_hash: None,
+            })
+            .await
+            .unwrap();
+        let annotation = lib
+            .create_annotation(CreateAnnotation {
+                resource_id: resource.id,
+                text: "highlight".to_string(),
+                color: None,
+                boundary: None,
+                external_id: None,
+                content_hash: None,
+            })
+            .await
+            .unwrap();
+        let private = UpdateResource {
+            visibility: Some(Visibility::Private),
+            ..Default::default()
+        };
+        lib.update_resource(resource.id, private).await.unwrap();
+
+        let open = |state: &AppState| open_permalink(State(state.clone()), Path(annotation.public_id.clone()));
+        assert_eq!(open(&state).await.status(), StatusCode::FOUND);
+        state.public = Arc::new(crate::config::Public {
+            enabled: true,
+            base_url: None,
+        });
+        assert_eq!(open(&state).await.status(), StatusCode::NOT_FOUND);
+
+        let shared = UpdateResource {
+            visibility: Some(Visibility::Shared),
+            ..Default::default()
+        };
+        lib.update_resource(resource.id, shared).await.unwrap();
+        assert_eq!(open(&state).await.status(), StatusCode::FOUND);
+    }
+}
@@ -0,0 +1,206 @@
+//! Quick capture (`POST /quick`): a single piece of free text, filed wherever it
+//! seems to belong, for capturing from a shell or a keyboard shortcut without
+//! choosing an endpoint. The body is either plain text or `{"text": "..."}`.
+//!
+//! - a lone http(s) URL becomes a website resource, with the page captured
+//! - `word: meaning` on one line becomes a word in the inbox (an empty meaning is
+//!   looked up in the dictionary)
+//! - anything else becomes a note in the inbox
+
+use axum::{
+    Json,
+    body::Bytes,
+    extract::State,
+    http::{HeaderMap, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+
+use super::capture::{self, PageFetcher};
+use super::{Commonplace, CreateNote, CreateWord, Note, Resource, Word};
+use crate::handler::AppState;
+use crate::response::{ApiResponse, bad_request, internal_error};
+
+/// Longest text accepted as the word part of `word: meaning`
+const MAX_WORD_CHARS: usize = 40;
+const MAX_WORD_PARTS: usize = 3;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Quick {
+    Url(String),
+    Word { name: String, meaning: String },
+    Note(String),
+}
+
+/// Decides what `text` is. `None` when there is nothing to capture.
+pub fn classify(text: &str) -> Option<Quick> {
+    let text = text.trim();
+    if text.is_empty() {
+        return None;
+    }
+    if capture::is_capturable(text) && !text.contains(char::is_whitespace) {
+        return Some(Quick::Url(text.to_string()));
+    }
+    if !text.contains('\n')
+        && !text.contains("://")
+        && let Some((name, meaning)) = text.split_once(':')
+        && is_word(name.trim())
+    {
+        return Some(Quick::Word {
+            name: name.trim().to_string(),
+            meaning: meaning.trim().to_string(),
+        });
+    }
+    Some(Quick::Note(text.to_string()))
+}
+
+/// A word or short phrase: up to a few space-separated runs of letters
+fn is_word(name: &str) -> bool {
+    let parts: Vec<&str> = name.split(' ').collect();
+    !name.is_empty()
+        && name.chars().count() <= MAX_WORD_CHARS
+        && parts.len() <= MAX_WORD_PARTS
+        && parts
+            .iter()
+            .all(|part| !part.is_empty() && part.chars().all(|c| c.is_alphabetic() || c == '\'' || c == '-'))
+}
+
+#[derive(Debug, Deserialize)]
+struct QuickRequest {
+    text: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+enum Captured {
+    Resource {
+        resource: Resource,
+        /// False when the page could not be fetched; the resource is kept anyway
+        captured: bool,
+    },
+    Word {
+        word: Word,
+    },
+    Note {
+        note: Note,
+    },
+}
+
+pub async fn quick_capture(State(state): State<AppState>, headers: HeaderMap, body: Bytes) -> Response {
+    let is_json = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("application/json"));
+    let text = if is_json {
+        match serde_json::from_slice::<QuickRequest>(&body) {
+            Ok(request) => request.text,
+            Err(e) => return bad_request(&format!("Invalid request: {}", e)),
+        }
+    } else {
+        match String::from_utf8(body.to_vec()) {
+            Ok(text) => text,
+            Err(_) => return bad_request("Body must be UTF-8 text"),
+        }
+    };
+    let Some(quick) = classify(&text) else {
+        return bad_request("Nothing to capture");
+    };
+
+    let lib = state.db.commonplace();
+    let result = match quick {
+        Quick::Url(url) => capture_url(&lib, url).await,
+        Quick::Word { name, mut meaning } => {
+            if meaning.is_empty() {
+                match state.dictionary.define(&name).await {
+                    Ok(Some(definition)) => meaning = definition,
+                    Ok(None) => {}
+                    Err(e) => tracing::warn!("Failed to look up {}: {}", name, e),
+                }
+            }
+            file_word(&lib, name, meaning).await
+        }
+        Quick::Note(content) => file_note(&lib, content).await,
+    };
+
+    match result {
+        Ok(captured) => (StatusCode::CREATED, Json(ApiResponse { data: captured })).into_response(),
+        Err(e) => {
+            tracing::error!("Failed to quick-capture: {}", e);
+            internal_error("Failed to capture")
+        }
+    }
+}
+
+async fn capture_url(lib: &Commonplace<'_>, url: String) -> anyhow::Result<Captured> {
+    let (resource, _) = lib.find_or_create_website(&url, None).await?;
+
+    let captured = match PageFetcher::new().fetch(&url).await {
+        Ok(content) => {
+            lib.record_resource_version(resource.id, &url, &content).await?;
+            true
+        }
+        Err(e) => {
+            tracing::warn!("Failed to fetch {}: {}", url, e);
+            false
+        }
+    };
+    Ok(Captured::Resource { resource, captured })
+}
+
+async fn file_word(lib: &Commonplace<'_>, name: String, meaning: String) -> anyhow::Result<Captured> {
+    let inbox = lib.inbox().await?;
+    let word = lib
+        .create_word(CreateWord {
+            resource_id: inbox.id,
+            name,
+            meaning,
+            ..Default::default()
+        })
+        .await?;
+    Ok(Captured::Word { word })
+}
+
+async fn file_note(lib: &Commonplace<'_>, content: String) -> anyhow::Result<Captured> {
+    let inbox = lib.inbox().await?;
+    let note = lib
+        .create_note(CreateNote {
+            resource_id: inbox.id,
+            content,
+            external_id: None,
+            content_hash: None,
+        })
+        .await?;
+    Ok(Captured::Note { note })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify() {
+        assert_eq!(classify("  "), None);
+        assert_eq!(classify("https://example.com/essay\n"), Some(Quick::Url("https://example.com/essay".to_string())));
+        assert_eq!(
+            classify("petrichor: the smell of rain on dry earth"),
+            Some(Quick::Word {
+                name: "petrichor".to_string(),
+                meaning: "the smell of rain on dry earth".to_string()
+            })
+        );
+        assert_eq!(
+            classify("ad hoc:"),
+            Some(Quick::Word {
+                name: "ad hoc".to_string(),
+                meaning: String::new()
+            })
+        );
+        for note in [
+            "read https://example.com later",
+            "remember to email Ana about chapter 3: she asked twice",
+            "todo: one\ntwo",
+        ] {
+            assert_eq!(classify(note), Some(Quick::Note(note.to_string())));
+        }
+    }
+}
@@ -0,0 +1,451 @@
+//! Retention policies for commonplace data, run once a day in the background and
+//! on demand from the admin API (`/admin/retention`). Each is off until its age is
+//! set in the `retention` config section.
+//!
+//! - `purge_deleted` permanently removes rows soft-deleted more than
+//!   `purge_deleted_after_days` ago, like a trash purge
+//! - `archive_inactive` writes each resource untouched for
+//!   `archive_inactive_after_days` to the bucket as a gzipped archive (the format
+//!   `POST /commonplace/import` restores, plus its captured page versions), then
+//!   drops all but the latest captured version, which is where the space goes
+//!
+//! Every run is recorded in `retention_runs` with a summary of what it did.
+
+use std::io::Write;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use axum::{
+    extract::{Path, Query, State},
+    response::Response,
+};
+use flate2::{Compression, write::GzEncoder};
+use libsql::Connection;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value as JsonValue, json};
+use tokio_util::sync::CancellationToken;
+
+use super::export::{ARCHIVE_FORMAT, ARCHIVE_VERSION};
+use super::trash;
+use crate::config;
+use crate::db::Database;
+use crate::handler::AppState;
+use crate::object_store::ObjectStore;
+use crate::response::{bad_request, internal_error, success};
+
+/// Resources archived by a single run; the rest wait for the next day
+pub const MAX_ARCHIVES_PER_RUN: i32 = 100;
+pub const DEFAULT_REPORT_LIMIT: i32 = 20;
+const TICK: Duration = Duration::from_secs(3600);
+const UNKNOWN_POLICY: &str = "Unknown policy, expected purge_deleted or archive_inactive";
+const REPORT_COLUMNS: &str = "id, policy, status, summary, error, started_at, finished_at";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Policy {
+    PurgeDeleted,
+    ArchiveInactive,
+}
+
+impl Policy {
+    pub const ALL: [Policy; 2] = [Policy::PurgeDeleted, Policy::ArchiveInactive];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Policy::PurgeDeleted => "purge_deleted",
+            Policy::ArchiveInactive => "archive_inactive",
+        }
+    }
+
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|policy| policy.as_str() == s)
+    }
+
+    /// Configured age in days; 0 when the policy is off
+    pub fn after_days(&self, cfg: &config::Retention) -> u32 {
+        match self {
+            Policy::PurgeDeleted => cfg.purge_deleted_after_days,
+            Policy::ArchiveInactive => cfg.archive_inactive_after_days,
+        }
+    }
+}
+
+/// One run of a policy. `summary` is the policy's own report: a trash purge
+/// summary, or an [`ArchiveSummary`].
+#[derive(Debug, Clone, Serialize)]
+pub struct Report {
+    pub id: i64,
+    pub policy: String,
+    /// running, ok or failed
+    pub status: String,
+    pub summary: Option<JsonValue>,
+    pub error: Option<String>,
+    pub started_at: String,
+    pub finished_at: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ArchivedResource {
+    pub resource_id: i32,
+    pub title: String,
+    pub key: String,
+    pub pruned_versions: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ArchiveSummary {
+    pub cutoff: String,
+    pub archived: Vec<ArchivedResource>,
+    /// Resources that could not be archived; they are retried on the next run
+    pub failed: Vec<i32>,
+}
+
+/// Runs `policy` now and records the outcome. Errors only if the run could not be
+/// recorded; a failing policy is reported as a failed run.
+pub async fn run(db: &Database, store: &dyn ObjectStore, cfg: &config::Retention, policy: Policy) -> Result<Report> {
+    let conn = &db.connection();
+    let id = db.write(|| start_run(conn, policy)).await?;
+    let days = policy.after_days(cfg);
+    let outcome = match policy {
+        Policy::PurgeDeleted => trash::purge(db, days)
+            .await
+            .and_then(|summary| Ok(serde_json::to_value(summary)?)),
+        Policy::ArchiveInactive => archive_inactive(db, store, cfg, days)
+            .await
+            .and_then(|summary| Ok(serde_json::to_value(summary)?)),
+    };
+    if let Err(e) = &outcome {
+        tracing::warn!("Retention policy {} failed: {}", policy.as_str(), e);
+    }
+    db.write(|| finish_run(conn, id, outcome)).await?;
+    get_report(conn, id)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Retention run {} disappeared", id))
+}
+
+async fn archive_inactive(
+    db: &Database,
+    store: &dyn ObjectStore,
+    cfg: &config::Retention,
+    days: u32,
+) -> Result<ArchiveSummary> {
+    let lib = db.commonplace();
+    let cutoff = (chrono::Utc::now() - chrono::Duration::days(days as i64))
+        .format("%Y-%m-%dT%H:%M:%S%.3fZ")
+        .to_string();
+    let ids = lib.inactive_resource_ids(&cutoff, MAX_ARCHIVES_PER_RUN).await?;
+
+    let mut summary = ArchiveSummary {
+        cutoff,
+        archived: Vec::new(),
+        failed: Vec::new(),
+    };
+    for id in ids {
+        match archive_resource(db, store, &cfg.archive_prefix, id).await {
+            Ok(Some(archived)) => summary.archived.push(archived),
+            Ok(None) => {}
+            Err(e) => {
+                tracing::warn!("Failed to archive resource {}: {}", id, e);
+                summary.failed.push(id);
+            }
+        }
+    }
+    Ok(summary)
+}
+
+async fn archive_resource(
+    db: &Database,
+    store: &dyn ObjectStore,
+    prefix: &str,
+    resource_id: i32,
+) -> Result<Option<ArchivedResource>> {
+    let lib = db.commonplace();
+    let Some(full) = lib.get_resource_full(resource_id).await? else {
+        return Ok(None);
+    };
+    let versions = lib.list_resource_versions(resource_id).await?;
+    let now = chrono::Utc::now();
+    let comments: Vec<_> = full.annotations.iter().flat_map(|item| &item.comments).collect();
+    let document = json!({
+        "format": ARCHIVE_FORMAT,
+        "version": ARCHIVE_VERSION,
+        "exported_at": now.to_rfc3339(),
+        "resources": [&full.resource],
+        "annotations": full.annotations.iter().map(|item| &item.annotation).collect::<Vec<_>>(),
+        "comments": comments,
+        "notes": &full.notes,
+        "words": &full.words,
+        "resource_versions": versions,
+    });
+
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&serde_json::to_vec(&document)?)?;
+    let data = encoder.finish()?;
+
+    let key = format!("{}/resource-{}-{}.json.gz", prefix.trim_end_matches('/'), resource_id, now.format("%Y%m%d"));
+    store.put_object(&key, data, "application/gzip").await?;
+    let pruned_versions = db
+        .write(|| async {
+            lib.record_resource_archive(resource_id, &key).await?;
+            lib.prune_resource_versions(resource_id).await
+        })
+        .await?;
+
+    Ok(Some(ArchivedResource {
+        resource_id,
+        title: full.resource.title,
+        key,
+        pruned_versions,
+    }))
+}
+
+async fn start_run(conn: &Connection, policy: Policy) -> Result<i64> {
+    let mut rows = conn
+        .query("INSERT INTO retention_runs (policy) VALUES (?) RETURNING id", libsql::params![policy.as_str()])
+        .await?;
+    match rows.next().await? {
+        Some(row) => Ok(row.get(0)?),
+        None => anyhow::bail!("Failed to record retention run"),
+    }
+}
+
+async fn finish_run(conn: &Connection, id: i64, outcome: Result<JsonValue>) -> Result<()> {
+    let (status, summary, error) = match outcome {
+        Ok(summary) => ("ok", Some(summary.to_string()), None),
+        Err(e) => ("failed", None, Some(e.to_string())),
+    };
+    conn.execute(
+        r#"
+        UPDATE retention_runs
+        SET status = ?, summary = ?, error = ?, finished_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
+        WHERE id = ?
+        "#,
+        libsql::params![status, summary, error, id],
+    )
+    .await?;
+    Ok(())
+}
+
+fn row_to_report(row: &libsql::Row) -> Result<Report> {
+    let summary: Option<String> = row.get(3)?;
+    Ok(Report {
+        id: row.get(0)?,
+        policy: row.get(1)?,
+        status: row.get(2)?,
+        summary: summary.map(|s| serde_json::from_str(&s)).transpose()?,
+        error: row.get(4)?,
+        started_at: row.get(5)?,
+        finished_at: row.get(6)?,
+    })
+}
+
+async fn get_report(conn: &Connection, id: i64) -> Result<Option<Report>> {
+    let query = format!("SELECT {} FROM retention_runs WHERE id = ?", REPORT_COLUMNS);
+    let mut rows = conn.query(&query, libsql::params![id]).await?;
+    match rows.next().await? {
+        Some(row) => Ok(Some(row_to_report(&row)?)),
+        None => Ok(None),
+    }
+}
+
+/// Runs of `policy`, most recent first
+pub async fn list_reports(conn: &Connection, policy: Policy, limit: i32) -> Result<Vec<Report>> {
+    let query = format!("SELECT {} FROM retention_runs WHERE policy = ? ORDER BY id DESC LIMIT ?", REPORT_COLUMNS);
+    let mut rows = conn.query(&query, libsql::params![policy.as_str(), limit]).await?;
+    let mut reports = Vec::new();
+    while let Some(row) = rows.next().await? {
+        reports.push(row_to_report(&row)?);
+    }
+    Ok(reports)
+}
+
+/// Runs each enabled policy once a day
+pub struct RetentionScheduler {
+    db: Arc<Database>,
+    store: Arc<dyn ObjectStore>,
+    cfg: config::Retention,
+}
+
+impl RetentionScheduler {
+    pub fn new(db: Arc<Database>, store: Arc<dyn ObjectStore>, cfg: config::Retention) -> Self {
+        Self { db, store, cfg }
+    }
+
+    async fn run_due(&self) -> Result<()> {
+        let today = chrono::Utc::now().format("%Y-%m-%d").to_string();
+        for policy in Policy::ALL {
+            if policy.after_days(&self.cfg) == 0 {
+                continue;
+            }
+            let last = list_reports(&self.db.connection(), policy, 1).await?;
+            if last.first().is_some_and(|report| report.started_at.starts_with(&today)) {
+                continue;
+            }
+            let report = run(&self.db, self.store.as_ref(), &self.cfg, policy).await?;
+            tracing::info!("Retention policy {} finished: {}", policy.as_str(), report.status);
+        }
+        Ok(())
+    }
+
+    pub fn start(self, cancel: CancellationToken) {
+        if Policy::ALL.iter().all(|policy| policy.after_days(&self.cfg) == 0) {
+            tracing::info!("Retention policies disabled");
+            return;
+        }
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(TICK);
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => {
+                        if let Err(e) = self.run_due().await {
+                            tracing::warn!("Failed to run retention policies: {}", e);
+                        }
+                    }
+                    _ = cancel.cancelled() => {
+                        tracing::info!("Retention scheduler shutting down");
+                        break;
+                    }
+                }
+            }
+        });
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct PolicyStatus {
+    pub policy: Policy,
+    pub enabled: bool,
+    pub after_days: u32,
+    pub last_run: Option<Report>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReportParams {
+    pub limit: Option<i32>,
+}
+
+/// `GET /admin/retention`: each policy's settings and its most recent run
+pub async fn list_policies(State(state): State<AppState>) -> Response {
+    let mut policies = Vec::new();
+    for policy in Policy::ALL {
+        let last_run = match list_reports(&state.db.connection(), policy, 1).await {
+            Ok(mut reports) => reports.pop(),
+            Err(e) => {
+                tracing::error!("Failed to list retention runs: {}", e);
+                return internal_error("Failed to list retention policies");
+            }
+        };
+        let after_days = policy.after_days(&state.retention);
+        policies.push(PolicyStatus {
+            policy,
+            enabled: after_days > 0,
+            after_days,
+            last_run,
+        });
+    }
+    success(policies)
+}
+
+/// `GET /admin/retention/:policy/reports`
+pub async fn list_policy_reports(
+    State(state): State<AppState>,
+    Path(policy): Path<String>,
+    Query(params): Query<ReportParams>,
+) -> Response {
+    let Some(policy) = Policy::from_str(&policy) else {
+        return bad_request(UNKNOWN_POLICY);
+    };
+    let limit = params.limit.unwrap_or(DEFAULT_REPORT_LIMIT).clamp(1, 200);
+    match list_reports(&state.db.connection(), policy, limit).await {
+        Ok(reports) => success(reports),
+        Err(e) => {
+            tracing::error!("Failed to list retention runs: {}", e);
+            internal_error("Failed to list retention reports")
+        }
+    }
+}
+
+/// `POST /admin/retention/:policy/run`: runs an enabled policy now
+pub async fn run_policy(State(state): State<AppState>, Path(policy): Path<String>) -> Response {
+    let Some(policy) = Policy::from_str(&policy) else {
+        return bad_request(UNKNOWN_POLICY);
+    };
+    if policy.after_days(&state.retention) == 0 {
+        return bad_request("Policy is disabled in the retention config");
+    }
+    match run(&state.db, state.resumable.as_ref(), &state.retention, policy).await {
+        Ok(report) => success(report),
+        Err(e) => {
+            tracing::error!("Failed to run retention policy {}: {}", policy.as_str(), e);
+            internal_error("Failed to run retention policy")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commonplace::{CreateResource, ResourceType};
+    use crate::object_store::MemoryObjectStore;
+    use crate::test_support::test_db;
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+
+    #[tokio::test]
+    async fn test_archive_inactive_resources() {
+        let db = test_db().await;
+        let lib = db.commonplace();
+        let mut ids = Vec::new();
+        for title in ["https://example.com/old", "https://example.com/new"] {
+            let resource = lib
+                .create_resource(CreateResource {
+                    title: title.to_string(),
+                    resource_type: ResourceType::Website,
+                    external_id: None,
+                    content_hash: None,
+                })
+                .await
+                .unwrap();
+            lib.record_resource_version(resource.id, title, "<p>v1</p>")
+                .await
+                .unwrap();
+            lib.record_resource_version(resource.id, title, "<p>v2</p>")
+                .await
+                .unwrap();
+            ids.push(resource.id);
+        }
+        db.connection()
+            .execute(
+                "UPDATE resources SET updated_at = '2020-01-01T00:00:00.000Z' WHERE id = ?",
+                libsql::params![ids[0]],
+            )
+            .await
+            .unwrap();
+
+        let store = MemoryObjectStore::new();
+        let cfg = config::Retention {
+            archive_inactive_after_days: 365,
+            ..Default::default()
+        };
+        let report = run(&db, &store, &cfg, Policy::ArchiveInactive).await.unwrap();
+        assert_eq!(report.status, "ok");
+        let summary = report.summary.unwrap();
+        let archived = summary["archived"].as_array().unwrap();
+        assert_eq!(archived.len(), 1);
+        assert_eq!(archived[0]["resource_id"], ids[0]);
+        assert_eq!(archived[0]["pruned_versions"], 1);
+
+        let data = store.get(archived[0]["key"].as_str().unwrap()).await.unwrap();
+        let mut document = String::new();
+        GzDecoder::new(data.as_slice()).read_to_string(&mut document).unwrap();
+        let document: JsonValue = serde_json::from_str(&document).unwrap();
+        assert_eq!(document["resources"][0]["id"], ids[0]);
+        assert_eq!(document["resource_versions"].as_array().unwrap().len(), 2);
+
+        // Archived since its last change, so the next run leaves it alone
+        let report = run(&db, &store, &cfg, Policy::ArchiveInactive).await.unwrap();
+        assert!(report.summary.unwrap()["archived"].as_array().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,120 @@
+//! Daily review: a handful of past highlights resurfaced each day
+//! (`GET /commonplace/review`). The selection is a weighted sample seeded by the
+//! date, so it stays the same all day, and favours highlights that are old or have
+//! rarely been reviewed. Only reviews from before today count towards the weights,
+//! so marking today's highlights as seen does not reshuffle them.
+
+use chrono::{Datelike, NaiveDate};
+use serde::Serialize;
+
+use super::Annotation;
+
+pub const DEFAULT_COUNT: usize = 5;
+pub const MAX_COUNT: usize = 50;
+/// Highlights reviewed within this many days are much less likely to come back
+const RECENT_DAYS: i64 = 7;
+
+/// An annotation that can be resurfaced, with its review history
+#[derive(Debug, Clone)]
+pub struct ReviewCandidate {
+    pub annotation_id: i32,
+    pub created_at: String,
+    pub review_count: i64,
+    pub last_reviewed_at: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ReviewStats {
+    pub annotation_id: i32,
+    pub review_count: i64,
+    pub last_reviewed_at: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ReviewItem {
+    #[serde(flatten)]
+    pub annotation: Annotation,
+    pub resource_title: String,
+    pub review_count: i64,
+    pub last_reviewed_at: Option<String>,
+}
+
+/// Start of `day` in the timestamp format the tables use
+pub fn day_start(day: NaiveDate) -> String {
+    format!("{}T00:00:00.000Z", day.format("%Y-%m-%d"))
+}
+
+fn date_of(timestamp: &str) -> Option<NaiveDate> {
+    timestamp
+        .get(..10)
+        .and_then(|d| NaiveDate::parse_from_str(d, "%Y-%m-%d").ok())
+}
+
+fn weight(candidate: &ReviewCandidate, day: NaiveDate) -> f64 {
+    let age_days = date_of(&candidate.created_at).map_or(0, |created| (day - created).num_days().max(0));
+    let mut weight = (1 + age_days) as f64 / ((1 + candidate.review_count) as f64).powi(2);
+    let recently_reviewed = candidate
+        .last_reviewed_at
+        .as_deref()
+        .and_then(date_of)
+        .is_some_and(|reviewed| (day - reviewed).num_days() < RECENT_DAYS);
+    if recently_reviewed {
+        weight *= 0.1;
+    }
+    weight
+}
+
+/// splitmix64, used as a stable hash of (day, annotation)
+fn mix(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+/// Picks up to `count` annotation ids for `day`, without repeats. Weighted sampling
+/// by Efraimidis-Spirakis: each candidate gets the key `ln(u) / weight` for a
+/// pseudo-random `u` in (0, 1], and the largest keys win.
+pub fn select(candidates: &[ReviewCandidate], day: NaiveDate, count: usize) -> Vec<i32> {
+    let seed = mix(day.num_days_from_ce() as u64);
+    let mut keyed: Vec<(f64, i32)> = candidates
+        .iter()
+        .map(|candidate| {
+            let bits = mix(seed ^ candidate.annotation_id as u64) >> 11;
+            let u = (bits + 1) as f64 / (1u64 << 53) as f64;
+            (u.ln() / weight(candidate, day), candidate.annotation_id)
+        })
+        .collect();
+    keyed.sort_by(|a, b| b.0.total_cmp(&a.0).then(a.1.cmp(&b.1)));
+    keyed.into_iter().take(count).map(|(_, id)| id).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(id: i32, created_at: &str, review_count: i64) -> ReviewCandidate {
+        ReviewCandidate {
+            annotation_id: id,
+            created_at: created_at.to_string(),
+            review_count,
+            last_reviewed_at: None,
+        }
+    }
+
+    #[test]
+    fn test_selection_is_stable_per_day_and_prefers_old_unreviewed() {
+        let day = NaiveDate::from_ymd_opt(2024, 6, 1).unwrap();
+        let mut candidates: Vec<ReviewCandidate> = (1..=50)
+            .map(|id| candidate(id, "2024-05-31T12:00:00.000Z", 3))
+            .collect();
+        candidates.push(candidate(100, "2019-01-01T00:00:00.000Z", 0));
+
+        let picks = select(&candidates, day, 5);
+        assert_eq!(picks.len(), 5);
+        assert_eq!(picks, select(&candidates, day, 5));
+        assert!(picks.contains(&100));
+        assert_ne!(picks, select(&candidates, day.succ_opt().unwrap(), 5));
+        assert_eq!(select(&candidates[..2], day, 5).len(), 2);
+    }
+}
@@ -1,38 +1,123 @@
 use axum::{
     Router,
+    extract::DefaultBodyLimit,
     routing::{delete, get, post, put},
 };
 
-use super::handler;
+use super::{handler, import};
 use crate::handler::AppState;
 
 pub fn routes() -> Router<AppState> {
     Router::new()
         .route("/resources", get(handler::list_resources))
         .route("/resources", post(handler::create_resource))
+        .route("/resources/full/batch", post(handler::get_resources_full))
         .route("/resources/:id", get(handler::get_resource))
         .route("/resources/:id", put(handler::update_resource))
         .route("/resources/:id", delete(handler::delete_resource))
+        .route("/resources/:id/book", put(handler::set_resource_book))
+        .route("/resources/:id/restore", post(handler::restore_resource))
         .route("/resources/:id/full", get(handler::get_resource_full))
+        .route("/resources/:id/export", get(handler::export_resource))
         .route("/resources/:id/annotations", get(handler::list_annotations_by_resource))
         .route("/resources/:id/notes", get(handler::list_notes_by_resource))
         .route("/resources/:id/words", get(handler::list_words_by_resource))
+        .route("/resources/:id/tags", get(handler::list_resource_tags))
+        .route("/resources/:id/terms", get(handler::list_resource_terms))
+        .route("/resources/:id/versions", get(handler::list_resource_versions))
+        .route("/resources/:id/versions", post(handler::capture_resource))
+        .route("/resources/:id/snapshot", get(handler::get_resource_snapshot))
+        .route("/resources/:id/compare", get(handler::compare_resources))
+        .route("/annotations", get(handler::list_annotations))
         .route("/annotations", post(handler::create_annotation))
+        .route("/annotations", delete(handler::bulk_delete_annotations))
         .route("/annotations/:id", get(handler::get_annotation))
         .route("/annotations/:id", put(handler::update_annotation))
         .route("/annotations/:id", delete(handler::delete_annotation))
         .route("/annotations/:id/comments", get(handler::list_comments_by_annotation))
+        .route("/annotations/:id/restore", post(handler::restore_annotation))
+        .route("/annotations/:id/favorite", put(handler::set_annotation_favorite))
+        .route("/annotations/:id/links", get(handler::list_annotation_links))
+        .route("/annotations/:id/links", post(handler::create_annotation_link))
+        .route("/annotations/:id/links/:link_id", delete(handler::delete_annotation_link))
         .route("/comments", post(handler::create_comment))
         .route("/comments/:id", get(handler::get_comment))
         .route("/comments/:id", put(handler::update_comment))
         .route("/comments/:id", delete(handler::delete_comment))
+        .route("/comments/:id/restore", post(handler::restore_comment))
         .route("/notes", post(handler::create_note))
         .route("/notes/:id", get(handler::get_note))
         .route("/notes/:id", put(handler::update_note))
         .route("/notes/:id", delete(handler::delete_note))
+        .route("/notes/:id/rendered", get(handler::render_note))
+        .route("/notes/:id/restore", post(handler::restore_note))
+        .route("/notes/:id/resource", put(handler::move_note))
         .route("/words", post(handler::create_word))
         .route("/words", get(handler::search_words))
+        .route("/words/due", get(handler::list_due_words))
+        .route("/words/export", get(handler::export_words))
         .route("/words/:id", get(handler::get_word))
         .route("/words/:id", put(handler::update_word))
         .route("/words/:id", delete(handler::delete_word))
+        .route("/words/:id/review", post(handler::review_word))
+        .route("/words/:id/resource", put(handler::move_word))
+        .route("/words/:id/occurrences", post(handler::add_word_occurrence))
+        .route("/words/:id/occurrences/:resource_id", delete(handler::remove_word_occurrence))
+        .route("/words/:id/refresh-definition", post(handler::refresh_word_definition))
+        .route("/review", get(handler::daily_review))
+        .route("/review/:id/seen", post(handler::mark_reviewed))
+        .route("/cold", get(handler::list_cold))
+        .route("/digest", get(handler::latest_digest))
+        .route("/digest", post(handler::create_digest))
+        .route("/inbox", get(handler::list_inbox))
+        .route("/trash", get(handler::list_trash))
+        .route("/trash/purge", post(handler::purge_trash))
+        .route("/export", get(handler::export_archive))
+        .route("/import", post(handler::import_archive).layer(DefaultBodyLimit::max(import::MAX_ARCHIVE_BYTES)))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Body;
+    use axum::http::{Method, Request, StatusCode, header::CONTENT_TYPE};
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::commonplace::{CreateResource, ResourceType};
+    use crate::test_support::{read_json, test_state};
+
+    #[tokio::test]
+    async fn test_batch_route_is_literal() {
+        let state = test_state().await;
+        let resource = state
+            .db
+            .commonplace()
+            .create_resource(CreateResource {
+                title: "On Reading".to_string(),
+                resource_type: ResourceType::Website,
+                external_id: None,
+                content_hash: None,
+            })
+            .await
+            .unwrap();
+        let app = routes().with_state(state);
+        let send = |method: Method, uri: &str| {
+            let request = Request::builder()
+                .method(method)
+                .uri(uri)
+                .header(CONTENT_TYPE, "application/json")
+                .body(Body::from(format!(r#"{{"ids": [{}, 999]}}"#, resource.id)))
+                .unwrap();
+            app.clone().oneshot(request)
+        };
+
+        let (status, body) = read_json(send(Method::POST, "/resources/full/batch").await.unwrap()).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["data"]["resources"][0]["id"], resource.id);
+        assert_eq!(body["data"]["missing"], serde_json::json!([999]));
+
+        // Only the literal path reaches the batch handler
+        let resp = send(Method::POST, "/resources/fullzzz").await.unwrap();
+        assert_eq!(resp.status(), StatusCode::METHOD_NOT_ALLOWED);
+    }
 }
@@ -0,0 +1,265 @@
+//! Snapshots of website resources, so highlights keep their context after a page
+//! moves or disappears. With `app.snapshot_websites` on, every new website resource,
+//! whichever way it was created, is fetched in the background and the readable part
+//! of the page (the article, without navigation, scripts or forms) is kept in the
+//! bucket as a standalone HTML document. `GET /commonplace/resources/:id/snapshot`
+//! serves the newest one. Images still load from the original site.
+
+use std::sync::Arc;
+
+use ammonia::{Builder, Url, UrlRelative};
+use anyhow::Result;
+use async_trait::async_trait;
+use libsql::Connection;
+use quick_xml::escape::escape;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::error::RecvError;
+use tokio_util::sync::CancellationToken;
+
+use super::capture::{PageFetcher, is_capturable, page_text};
+use super::{ResourceSnapshot, ResourceType};
+use crate::db::Database;
+use crate::events::Event;
+use crate::jobs::{self, JobHandler};
+use crate::object_store::ObjectStore;
+
+pub const SNAPSHOT_JOB: &str = "resource.snapshot";
+const KEY_PREFIX: &str = "snapshots/commonplace";
+
+/// Snapshots are served from the app's origin, so they may not run anything or
+/// load more than images and their own styles
+pub const CONTENT_SECURITY_POLICY: &str = "default-src 'none'; img-src * data:; style-src 'unsafe-inline'";
+
+/// Elements dropped together with everything inside them
+const CLUTTER: &[&str] = &[
+    "nav", "header", "footer", "aside", "form", "button", "iframe", "noscript", "template", "svg",
+];
+
+/// Where the readable part of a page is looked for, best guess first
+const CONTENT_ELEMENTS: &[&str] = &["article", "main", "body"];
+
+const STYLE: &str = "body { max-width: 42em; margin: 2em auto; padding: 0 1em; font-family: serif; line-height: 1.5; }
+img { max-width: 100%; height: auto; }
+.snapshot { font-family: sans-serif; font-size: .85em; color: #666; border-bottom: 1px solid #ddd; padding-bottom: .5em; }";
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SnapshotPayload {
+    resource_id: i32,
+    url: String,
+}
+
+/// The readable part of a page
+#[derive(Debug)]
+pub struct Readable {
+    pub title: Option<String>,
+    /// Sanitized HTML, with links and images made absolute
+    pub content: String,
+}
+
+/// Slice of `html` from the first `<tag>` to the last `</tag>`. `lower` is `html`
+/// ASCII-lowercased, which keeps byte offsets the same.
+fn element<'a>(html: &'a str, lower: &str, tag: &str) -> Option<&'a str> {
+    let open = format!("<{}", tag);
+    let start = lower
+        .match_indices(&open)
+        .map(|(i, _)| i)
+        .find(|&i| lower[i + open.len()..].starts_with(|c: char| c == '>' || c.is_ascii_whitespace()))?;
+    let close = format!("</{}>", tag);
+    let end = lower.rfind(&close)? + close.len();
+    (end > start).then(|| &html[start..end])
+}
+
+/// Picks the readable part of `html` and sanitizes it. `url` is the page's address,
+/// which relative links are resolved against.
+pub fn readable(html: &str, url: &str) -> Readable {
+    let lower = html.to_ascii_lowercase();
+    let title = element(html, &lower, "title")
+        .map(|title| page_text(title).trim().to_string())
+        .filter(|title| !title.is_empty());
+    let content = CONTENT_ELEMENTS
+        .iter()
+        .find_map(|tag| element(html, &lower, tag))
+        .unwrap_or(html);
+
+    let mut builder = Builder::default();
+    builder.rm_tags(CLUTTER).add_clean_content_tags(CLUTTER);
+    if let Ok(base) = Url::parse(url) {
+        builder.url_relative(UrlRelative::RewriteWithBase(base));
+    }
+    Readable {
+        title,
+        content: builder.clean(content).to_string(),
+    }
+}
+
+fn document(readable: &Readable, url: &str, taken_at: &str) -> String {
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>{title}</title>
+<style>{style}</style>
+</head>
+<body>
+<p class="snapshot">Snapshot of <a href="{url}">{url}</a> taken {taken_at}</p>
+{content}
+</body>
+</html>
+"#,
+        title = escape(readable.title.as_deref().unwrap_or(url)),
+        style = STYLE,
+        url = escape(url),
+        taken_at = taken_at,
+        content = readable.content,
+    )
+}
+
+/// Stores the readable part of `html`, fetched from `url`, as the newest snapshot of
+/// the resource
+pub async fn store(
+    db: &Database,
+    store: &dyn ObjectStore,
+    resource_id: i32,
+    url: &str,
+    html: &str,
+) -> Result<ResourceSnapshot> {
+    let now = chrono::Utc::now();
+    let readable = readable(html, url);
+    let data = document(&readable, url, &now.format("%Y-%m-%d %H:%M UTC").to_string()).into_bytes();
+    let size = data.len() as i64;
+    let key = format!("{}/resource-{}-{}.html", KEY_PREFIX, resource_id, now.format("%Y%m%dT%H%M%S"));
+    store.put_object(&key, data, "text/html; charset=utf-8").await?;
+    let lib = db.commonplace();
+    db.write(|| lib.record_resource_snapshot(resource_id, url, &key, readable.title.as_deref(), size))
+        .await
+}
+
+/// Queues a snapshot of a website resource on `conn`, for callers that already hold
+/// the transaction lock
+pub async fn queue(conn: &Connection, resource_id: i32, url: &str) -> Result<()> {
+    let payload = SnapshotPayload {
+        resource_id,
+        url: url.to_string(),
+    };
+    jobs::enqueue(conn, SNAPSHOT_JOB, &payload).await?;
+    Ok(())
+}
+
+/// Queues a snapshot job under the transaction lock, since it runs outside of any
+/// request
+async fn enqueue(db: &Database, payload: &SnapshotPayload) -> Result<()> {
+    let _guard = db.begin().await?;
+    match queue(&db.connection(), payload.resource_id, &payload.url).await {
+        Ok(_) => db.commit().await,
+        Err(e) => {
+            let _ = db.rollback().await;
+            Err(e)
+        }
+    }
+}
+
+/// Queues a snapshot of each website resource created from now on, until `cancel`
+/// fires
+pub fn start(db: Arc<Database>, cancel: CancellationToken) {
+    let mut events = db.events().subscribe();
+    tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                received = events.recv() => match received {
+                    Ok(Event::ResourceCreated { resource_id, resource_type, title })
+                        if resource_type == ResourceType::Website.as_str() && is_capturable(&title) =>
+                    {
+                        let payload = SnapshotPayload { resource_id, url: title };
+                        if let Err(e) = enqueue(&db, &payload).await {
+                            tracing::warn!("Failed to queue snapshot of {}: {}", payload.url, e);
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(RecvError::Lagged(missed)) => tracing::warn!("snapshots missed {} events", missed),
+                    Err(RecvError::Closed) => break,
+                },
+                _ = cancel.cancelled() => break,
+            }
+        }
+    });
+}
+
+/// Runs the jobs queued by [`start`]
+pub struct SnapshotJob {
+    fetcher: PageFetcher,
+    store: Arc<dyn ObjectStore>,
+}
+
+impl SnapshotJob {
+    pub fn new(store: Arc<dyn ObjectStore>) -> Self {
+        Self {
+            fetcher: PageFetcher::new(),
+            store,
+        }
+    }
+}
+
+#[async_trait]
+impl JobHandler for SnapshotJob {
+    fn kind(&self) -> &'static str {
+        SNAPSHOT_JOB
+    }
+
+    async fn run(&self, db: &Database, payload: &serde_json::Value) -> Result<()> {
+        let page: SnapshotPayload = serde_json::from_value(payload.clone())?;
+        let lib = db.commonplace();
+        // Deleted before its turn came
+        if lib.get_resource(page.resource_id).await?.is_none() {
+            return Ok(());
+        }
+        let html = self.fetcher.fetch_html(&page.url).await?;
+        let snapshot = store(db, self.store.as_ref(), page.resource_id, &page.url, &html).await?;
+        tracing::debug!("Stored snapshot of {} as {}", page.url, snapshot.key);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commonplace::CreateResource;
+    use crate::object_store::MemoryObjectStore;
+    use crate::test_support::test_db;
+
+    #[tokio::test]
+    async fn test_snapshot_keeps_readable_content() {
+        let html = r#"<html><head><title>Notes &amp; Queries</title><script>track()</script></head>
+            <body><nav><a href="/">Home</a></nav>
+            <article><h1>On Reading</h1><p onclick="x()">Read <a href="/more">slowly</a>.</p>
+            <img src="img/fig.png"><form><input name="q"></form></article>
+            <footer>Copyright</footer></body></html>"#;
+        let page = readable(html, "https://example.com/essays/reading");
+        assert_eq!(page.title.as_deref(), Some("Notes & Queries"));
+        assert!(page.content.contains("<h1>On Reading</h1>"));
+        assert!(page.content.contains("href=\"https://example.com/more\""));
+        assert!(page.content.contains("src=\"https://example.com/essays/img/fig.png\""));
+        for gone in ["Home", "Copyright", "track()", "onclick", "<input"] {
+            assert!(!page.content.contains(gone), "{} was kept", gone);
+        }
+
+        let db = test_db().await;
+        let lib = db.commonplace();
+        let resource = lib
+            .create_resource(CreateResource {
+                title: "https://example.com/essays/reading".to_string(),
+                resource_type: ResourceType::Website,
+                external_id: None,
+                content_hash: None,
+            })
+            .await
+            .unwrap();
+        let objects = MemoryObjectStore::new();
+        let snapshot = store(&db, &objects, resource.id, &resource.title, html).await.unwrap();
+        let latest = lib.latest_resource_snapshot(resource.id).await.unwrap().unwrap();
+        assert_eq!((latest.id, latest.title.as_deref()), (snapshot.id, Some("Notes & Queries")));
+        let stored = String::from_utf8(objects.download_file(&latest.key).await.unwrap()).unwrap();
+        assert!(stored.contains("<title>Notes &amp; Queries</title>"));
+        assert!(stored.contains("<h1>On Reading</h1>"));
+    }
+}
@@ -0,0 +1,90 @@
+//! SM-2 spaced repetition for vocabulary (`GET /commonplace/words/due`,
+//! `POST /commonplace/words/:id/review`). Each review is graded 0-5; a good grade
+//! pushes the next review further out by the word's ease factor, a failed one starts
+//! the word over the next day.
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+pub const DEFAULT_EASE: f64 = 2.5;
+const MIN_EASE: f64 = 1.3;
+pub const MAX_GRADE: u8 = 5;
+/// Grades below this count as a failed recall
+const PASSING_GRADE: u8 = 3;
+
+pub fn default_ease() -> f64 {
+    DEFAULT_EASE
+}
+
+/// Scheduling state stored on each word
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Schedule {
+    pub ease: f64,
+    pub interval_days: i64,
+    pub repetitions: i64,
+}
+
+impl Default for Schedule {
+    fn default() -> Self {
+        Self {
+            ease: DEFAULT_EASE,
+            interval_days: 0,
+            repetitions: 0,
+        }
+    }
+}
+
+impl Schedule {
+    /// Schedule after a review graded `grade` (0 = forgotten, 5 = perfect recall)
+    pub fn review(self, grade: u8) -> Self {
+        let grade = grade.min(MAX_GRADE);
+        let miss = f64::from(MAX_GRADE - grade);
+        let ease = (self.ease + 0.1 - miss * (0.08 + miss * 0.02)).max(MIN_EASE);
+
+        if grade < PASSING_GRADE {
+            return Self {
+                ease,
+                interval_days: 1,
+                repetitions: 0,
+            };
+        }
+        let interval_days = match self.repetitions {
+            0 => 1,
+            1 => 6,
+            _ => (self.interval_days as f64 * self.ease).round() as i64,
+        };
+        Self {
+            ease,
+            interval_days,
+            repetitions: self.repetitions + 1,
+        }
+    }
+
+    pub fn due_at(&self, reviewed_at: DateTime<Utc>) -> DateTime<Utc> {
+        reviewed_at + Duration::days(self.interval_days)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sm2_intervals() {
+        let first = Schedule::default().review(5);
+        assert_eq!((first.interval_days, first.repetitions), (1, 1));
+        let second = first.review(4);
+        assert_eq!(second.interval_days, 6);
+        let third = second.review(4);
+        assert_eq!(third.interval_days, (6.0 * second.ease).round() as i64);
+
+        let lapsed = third.review(1);
+        assert_eq!((lapsed.interval_days, lapsed.repetitions), (1, 0));
+        assert!(lapsed.ease < third.ease);
+        let hardest = Schedule {
+            ease: MIN_EASE,
+            ..lapsed
+        };
+        assert_eq!(hardest.review(0).ease, MIN_EASE);
+    }
+}
@@ -0,0 +1,127 @@
+//! Top terms of a resource (`GET /commonplace/resources/:id/terms`), a quick topical
+//! summary of a long read drawn from its highlights and notes. Terms are scored by
+//! TF-IDF across all resources by default, so words that show up in everything
+//! someone highlights sink below the ones particular to this read; `scoring=tf`
+//! ranks by plain frequency instead.
+
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+
+pub const DEFAULT_LIMIT: usize = 25;
+pub const MAX_LIMIT: usize = 100;
+/// Shorter words are almost never topical
+const MIN_TERM_CHARS: usize = 3;
+
+const STOPWORDS: &str = "\
+    about above after again against all also although among and another any are around because been \
+    before being below between both but can cannot could did does doing done down during each either \
+    else even ever every few for from further get gets got had has have having her here hers herself him \
+    himself his how however into its itself just least less let like made make makes many may might more \
+    most much must neither nor not now off often once one only other others our ours ourselves out over \
+    own per rather really same say says said see she should since some something still such than that \
+    the their theirs them themselves then there these they thing things this those though through thus \
+    too under until upon use used very was way were what when where whether which while who whom whose \
+    why will with within without would yet you your yours yourself yourselves";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Scoring {
+    Tf,
+    #[default]
+    Tfidf,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Term {
+    pub term: String,
+    pub count: usize,
+    pub score: f64,
+}
+
+/// Lowercased words of `text` worth counting: no stopwords, numbers or short words
+fn tokenize(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric() && c != '\'' && c != '’')
+        .map(|word| word.trim_matches(['\'', '’']).to_lowercase())
+        .map(|word| match word.strip_suffix("'s").or_else(|| word.strip_suffix("’s")) {
+            Some(stem) => stem.to_string(),
+            None => word,
+        })
+        .filter(|word| word.chars().count() >= MIN_TERM_CHARS)
+        .filter(|word| !word.chars().all(|c| c.is_numeric()))
+        .filter(|word| !STOPWORDS.split_whitespace().any(|stopword| stopword == word))
+}
+
+/// The `limit` best scoring terms of `resource_id`, given the `(resource_id, text)`
+/// pairs of every resource. Ties go to the more frequent term, then alphabetically.
+pub fn top_terms(resource_id: i32, texts: &[(i32, String)], scoring: Scoring, limit: usize) -> Vec<Term> {
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    let mut documents: HashMap<i32, HashSet<String>> = HashMap::new();
+    for (id, text) in texts {
+        let terms = documents.entry(*id).or_default();
+        for term in tokenize(text) {
+            if *id == resource_id {
+                *counts.entry(term.clone()).or_default() += 1;
+            }
+            terms.insert(term);
+        }
+    }
+
+    let total: usize = counts.values().sum();
+    let resources = documents.len() as f64;
+    let mut terms: Vec<Term> = counts
+        .into_iter()
+        .map(|(term, count)| {
+            let tf = count as f64 / total as f64;
+            let score = match scoring {
+                Scoring::Tf => tf,
+                Scoring::Tfidf => {
+                    let df = documents.values().filter(|terms| terms.contains(&term)).count() as f64;
+                    // Smoothed, so a term every resource has still counts for something
+                    tf * (((1.0 + resources) / (1.0 + df)).ln() + 1.0)
+                }
+            };
+            Term { term, count, score }
+        })
+        .collect();
+    terms.sort_by(|a, b| {
+        b.score
+            .total_cmp(&a.score)
+            .then(b.count.cmp(&a.count))
+            .then_with(|| a.term.cmp(&b.term))
+    });
+    terms.truncate(limit);
+    terms
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_top_terms() {
+        let texts = vec![
+            (1, "The spice must flow. Spice is the water of Arrakis.".to_string()),
+            (1, "Arrakis teaches the attitude of the knife; the spice's price is water.".to_string()),
+            (2, "Water and more water: the Fremen's stillsuits save water.".to_string()),
+        ];
+
+        let tf = top_terms(1, &texts, Scoring::Tf, 3);
+        let names: Vec<&str> = tf.iter().map(|t| t.term.as_str()).collect();
+        assert_eq!(names, vec!["spice", "arrakis", "water"]);
+        assert_eq!(tf[0].count, 3);
+
+        // Water is in every resource, so it falls behind Arrakis, which is as frequent
+        let tfidf = top_terms(1, &texts, Scoring::Tfidf, 10);
+        let rank = |term: &str| tfidf.iter().position(|t| t.term == term).unwrap();
+        assert_eq!(rank("spice"), 0);
+        assert_eq!(rank("arrakis"), 1);
+        assert!(tfidf[1].score > tfidf[rank("water")].score);
+        assert!(
+            tfidf
+                .iter()
+                .all(|t| !["the", "is", "of", "must"].contains(&t.term.as_str()))
+        );
+        assert!(top_terms(3, &texts, Scoring::Tfidf, 10).is_empty());
+    }
+}
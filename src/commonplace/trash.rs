@@ -0,0 +1,167 @@
+//! Soft-deleted commonplace rows (`GET /commonplace/trash`) and purging them for
+//! good (`POST /commonplace/trash/purge`). Syncs and deletes only ever set
+//! `deleted_at`, so without a purge the tables grow forever.
+//!
+//! Purging a resource also removes everything hanging off it (annotations and their
+//! comments, notes, words, captured versions, link checks, archive records), whether
+//! or not those were deleted themselves, since nothing can reach them once the
+//! resource is gone.
+
+use anyhow::Result;
+use libsql::Connection;
+use serde::Serialize;
+
+use super::{Annotation, Comment, Note, Resource};
+use crate::db::Database;
+
+pub const DEFAULT_LIMIT: i32 = 100;
+pub const DEFAULT_PURGE_AGE_DAYS: u32 = 30;
+
+/// Most recently deleted rows of each type
+#[derive(Debug, Clone, Serialize)]
+pub struct Trash {
+    pub resources: Vec<Resource>,
+    pub annotations: Vec<Annotation>,
+    pub comments: Vec<Comment>,
+    pub notes: Vec<Note>,
+}
+
+/// Rows removed by a purge, per table
+#[derive(Debug, Clone, Serialize)]
+pub struct PurgeSummary {
+    pub cutoff: String,
+    pub resources: u64,
+    pub annotations: u64,
+    pub comments: u64,
+    pub notes: u64,
+    pub words: u64,
+}
+
+/// Permanently deletes rows soft-deleted more than `older_than_days` ago, in one
+/// transaction
+pub async fn purge(db: &Database, older_than_days: u32) -> Result<PurgeSummary> {
+    let _guard = db.begin().await?;
+    match purge_before(&db.connection(), older_than_days).await {
+        Ok(summary) => {
+            db.commit().await?;
+            Ok(summary)
+        }
+        Err(e) => {
+            let _ = db.rollback().await;
+            Err(e)
+        }
+    }
+}
+
+async fn purge_before(conn: &Connection, older_than_days: u32) -> Result<PurgeSummary> {
+    let modifier = format!("-{} days", older_than_days);
+    let mut rows = conn
+        .query("SELECT strftime('%Y-%m-%dT%H:%M:%fZ', 'now', ?)", libsql::params![modifier])
+        .await?;
+    let cutoff: String = match rows.next().await? {
+        Some(row) => row.get(0)?,
+        None => anyhow::bail!("Failed to compute purge cutoff"),
+    };
+
+    // Children go first, while the parent rows they are matched by still exist
+    let purged_resources = "SELECT id FROM resources WHERE deleted_at < ?1";
+    let purged_annotations =
+        format!("SELECT id FROM annotations WHERE deleted_at < ?1 OR resource_id IN ({})", purged_resources);
+    let before = cutoff.as_str();
+    let delete = |query: String| async move { conn.execute(&query, libsql::params![before]).await };
+
+    let purged_comments =
+        format!("SELECT id FROM comments WHERE deleted_at < ?1 OR annotation_id IN ({})", purged_annotations);
+    delete(format!(
+        "UPDATE comments SET parent_comment_id = NULL WHERE parent_comment_id IN ({})",
+        purged_comments
+    ))
+    .await?;
+    let comments = delete(format!("DELETE FROM comments WHERE id IN ({})", purged_comments)).await?;
+    delete(format!(
+        "DELETE FROM annotation_links WHERE source_id IN ({0}) OR target_id IN ({0})",
+        purged_annotations
+    ))
+    .await?;
+    delete(format!("DELETE FROM annotation_reviews WHERE annotation_id IN ({})", purged_annotations)).await?;
+    delete(format!("DELETE FROM annotation_surfacings WHERE annotation_id IN ({})", purged_annotations)).await?;
+    let annotations = delete(format!("DELETE FROM annotations WHERE id IN ({})", purged_annotations)).await?;
+    let notes =
+        delete(format!("DELETE FROM notes WHERE deleted_at < ?1 OR resource_id IN ({})", purged_resources)).await?;
+    delete(format!("DELETE FROM word_occurrences WHERE resource_id IN ({})", purged_resources)).await?;
+    let words = delete(format!("DELETE FROM words WHERE resource_id IN ({})", purged_resources)).await?;
+    delete(format!("DELETE FROM resource_versions WHERE resource_id IN ({})", purged_resources)).await?;
+    delete(format!("DELETE FROM resource_links WHERE resource_id IN ({})", purged_resources)).await?;
+    delete(format!("DELETE FROM resource_archives WHERE resource_id IN ({})", purged_resources)).await?;
+    let resources = delete(format!("DELETE FROM resources WHERE id IN ({})", purged_resources)).await?;
+
+    Ok(PurgeSummary {
+        cutoff,
+        resources,
+        annotations,
+        comments,
+        notes,
+        words,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commonplace::{CreateAnnotation, CreateComment, CreateResource, ResourceType};
+    use crate::test_support::test_db;
+
+    #[tokio::test]
+    async fn test_purge_removes_old_trash_and_its_children() {
+        let db = test_db().await;
+        let lib = db.commonplace();
+        let mut resource_ids = Vec::new();
+        for title in ["https://example.com/old", "https://example.com/recent"] {
+            let resource = lib
+                .create_resource(CreateResource {
+                    title: title.to_string(),
+                    resource_type: ResourceType::Website,
+                    external_id: None,
+                    content_hash: None,
+                })
+                .await
+                .unwrap();
+            let annotation = lib
+                .create_annotation(CreateAnnotation {
+                    resource_id: resource.id,
+                    text: "highlight".to_string(),
+                    color: None,
+                    boundary: None,
+                    external_id: None,
+                    content_hash: None,
+                })
+                .await
+                .unwrap();
+            lib.create_comment(CreateComment {
+                annotation_id: annotation.id,
+                content: "comment".to_string(),
+                external_id: None,
+                content_hash: None,
+                parent_comment_id: None,
+            })
+            .await
+            .unwrap();
+            lib.soft_delete_resource(resource.id).await.unwrap();
+            resource_ids.push(resource.id);
+        }
+        db.connection()
+            .execute(
+                "UPDATE resources SET deleted_at = '2020-01-01T00:00:00.000Z' WHERE id = ?",
+                libsql::params![resource_ids[0]],
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(lib.list_trash(DEFAULT_LIMIT).await.unwrap().resources.len(), 2);
+        let summary = purge(&db, 30).await.unwrap();
+        assert_eq!((summary.resources, summary.annotations, summary.comments), (1, 1, 1));
+        let trash = lib.list_trash(DEFAULT_LIMIT).await.unwrap();
+        assert_eq!(trash.resources.len(), 1);
+        assert_eq!(trash.resources[0].id, resource_ids[1]);
+    }
+}
@@ -1,7 +1,8 @@
 use anyhow::Result;
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use serde::Deserialize;
 use serde_yaml;
+use std::collections::HashMap;
 use std::env;
 use std::fs;
 use std::path::PathBuf;
@@ -10,8 +11,38 @@ use std::path::PathBuf;
 #[command(name = "bibliotek")]
 #[command(about = "Runs the bibliotek service", long_about = None)]
 pub struct Cli {
-    #[arg(short = 'c', long = "config")]
+    #[arg(short = 'c', long = "config", global = true)]
     pub config_path: Option<String>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Apply pending schema migrations, or move one migration set to a given version
+    Migrate {
+        /// Print the SQL that would run without touching the database
+        #[arg(long)]
+        dry_run: bool,
+        /// Target migration name (e.g. commonplace_003_sync_metadata); later migrations
+        /// in the same set are reverted using their down migrations
+        #[arg(long)]
+        to: Option<String>,
+    },
+    /// Fill the database with generated books, highlights and words for development
+    Seed {
+        #[arg(long, default_value_t = 50)]
+        books: usize,
+        #[arg(long, default_value_t = 500)]
+        annotations: usize,
+        /// Vocabulary entries to create (defaults to a tenth of --annotations)
+        #[arg(long)]
+        words: Option<usize>,
+        /// Random seed; the same seed produces the same data
+        #[arg(long, default_value_t = 42)]
+        seed: u64,
+    },
 }
 
 pub fn default_config_dir() -> PathBuf {
@@ -35,12 +66,63 @@ pub struct App {
     pub turso_auth_token: Option<String>,
     #[serde(default = "default_sync_interval")]
     pub sync_interval_seconds: u64,
+    /// Look up missing book metadata on OpenLibrary/Google Books after each upload
+    #[serde(default)]
+    pub enrich_on_upload: bool,
+    /// How often website resources are checked for dead links; 0 disables the checker
+    #[serde(default = "default_link_check_interval")]
+    pub link_check_interval_hours: u64,
+    /// How often registered feeds are polled for new entries; 0 disables the poller
+    #[serde(default = "default_feed_poll_interval")]
+    pub feed_poll_interval_minutes: u64,
+    /// How many cold highlights go into the daily digest; 0 disables it
+    #[serde(default = "default_cold_digest_size")]
+    pub cold_digest_size: i32,
+    /// How many background jobs (checksums, enrichment, page captures) run at once
+    #[serde(default = "default_job_workers")]
+    pub job_workers: usize,
+    /// Keep a diff log of what each sync run changed (`GET /sync/runs/:id/diff`)
+    #[serde(default)]
+    pub sync_diff_log: bool,
+    /// Keep a readable snapshot of each new website resource in the bucket
+    #[serde(default)]
+    pub snapshot_websites: bool,
+    /// How often unfinished uploads are looked for and aborted; 0 disables the cleanup
+    #[serde(default = "default_upload_cleanup_interval")]
+    pub upload_cleanup_interval_minutes: u64,
+    /// Age at which an unfinished upload is aborted
+    #[serde(default = "default_upload_max_age")]
+    pub upload_max_age_hours: u64,
 }
 
 fn default_sync_interval() -> u64 {
     60
 }
 
+fn default_link_check_interval() -> u64 {
+    24
+}
+
+fn default_feed_poll_interval() -> u64 {
+    60
+}
+
+fn default_cold_digest_size() -> i32 {
+    10
+}
+
+fn default_job_workers() -> usize {
+    2
+}
+
+fn default_upload_cleanup_interval() -> u64 {
+    60
+}
+
+fn default_upload_max_age() -> u64 {
+    24
+}
+
 #[derive(Debug, Deserialize, Default)]
 pub struct Storage {
     pub aws_access_key_id: String,
@@ -53,22 +135,383 @@ pub struct Storage {
 
 impl App {
     pub fn get_db(&self) -> &str {
-        return &self.database;
+        &self.database
     }
 
     pub fn get_port(&self) -> i32 {
-        return self.port;
+        self.port
     }
 
     pub fn get_bucket(&self) -> &str {
-        return &self.bucket;
+        &self.bucket
+    }
+}
+
+/// Where definitions come from when a word is saved without a meaning
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum DictionaryProvider {
+    /// Free Dictionary API (dictionaryapi.dev)
+    #[default]
+    Dictionaryapi,
+    /// Never look words up
+    None,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct Dictionary {
+    #[serde(default)]
+    pub provider: DictionaryProvider,
+    #[serde(default = "default_dictionary_url")]
+    pub url: String,
+    #[serde(default = "default_dictionary_language")]
+    pub language: String,
+}
+
+impl Default for Dictionary {
+    fn default() -> Self {
+        Self {
+            provider: DictionaryProvider::default(),
+            url: default_dictionary_url(),
+            language: default_dictionary_language(),
+        }
+    }
+}
+
+fn default_dictionary_url() -> String {
+    "https://api.dictionaryapi.dev/api/v2/entries".to_string()
+}
+
+fn default_dictionary_language() -> String {
+    "en".to_string()
+}
+
+/// Cleanup applied to a book title when it has to be made from the file name, see
+/// [`crate::titles::TitleCleaner`]
+#[derive(Debug, Deserialize, Clone)]
+pub struct Titles {
+    /// Characters that stand in for spaces in file names
+    #[serde(default = "default_title_separators")]
+    pub separators: String,
+    /// ISBN-10/13 numbers, with or without an "ISBN" prefix
+    #[serde(default = "default_true")]
+    pub strip_isbn: bool,
+    /// Download-site suffixes such as "(z-lib.org)" or "(libgen)"
+    #[serde(default = "default_true")]
+    pub strip_source_tags: bool,
+    /// "2nd Edition", "Revised ed." and the like
+    #[serde(default = "default_true")]
+    pub strip_edition: bool,
+    /// Anything in square or curly brackets, e.g. "[EPUB]" or "{retail}"
+    #[serde(default = "default_true")]
+    pub strip_bracketed: bool,
+    /// Extra regular expressions removed from the title
+    #[serde(default)]
+    pub strip_patterns: Vec<String>,
+    #[serde(default = "default_true")]
+    pub title_case: bool,
+}
+
+impl Default for Titles {
+    fn default() -> Self {
+        Self {
+            separators: default_title_separators(),
+            strip_isbn: true,
+            strip_source_tags: true,
+            strip_edition: true,
+            strip_bracketed: true,
+            strip_patterns: Vec::new(),
+            title_case: true,
+        }
+    }
+}
+
+fn default_title_separators() -> String {
+    "_-".to_string()
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum JournalMode {
+    /// Readers don't block the writer, and the writer doesn't block readers
+    #[default]
+    Wal,
+    /// SQLite's rollback journal, for filesystems without shared memory
+    Delete,
+}
+
+impl JournalMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JournalMode::Wal => "WAL",
+            JournalMode::Delete => "DELETE",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Synchronous {
+    Off,
+    /// Durable except for the last commits on power loss; safe with WAL
+    #[default]
+    Normal,
+    Full,
+}
+
+impl Synchronous {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Synchronous::Off => "OFF",
+            Synchronous::Normal => "NORMAL",
+            Synchronous::Full => "FULL",
+        }
+    }
+}
+
+/// Connection settings applied when the database is opened. Syncs write while the
+/// web app reads, so the defaults favour concurrency: WAL, and waiting a while for
+/// a lock instead of failing with "database is locked".
+#[derive(Debug, Deserialize, Clone)]
+pub struct Sqlite {
+    #[serde(default)]
+    pub journal_mode: JournalMode,
+    #[serde(default)]
+    pub synchronous: Synchronous,
+    /// How long a statement waits for a lock held elsewhere before failing
+    #[serde(default = "default_busy_timeout_ms")]
+    pub busy_timeout_ms: u64,
+}
+
+impl Default for Sqlite {
+    fn default() -> Self {
+        Self {
+            journal_mode: JournalMode::default(),
+            synchronous: Synchronous::default(),
+            busy_timeout_ms: default_busy_timeout_ms(),
+        }
+    }
+}
+
+fn default_busy_timeout_ms() -> u64 {
+    5000
+}
+
+/// Commonplace retention policies, run daily by
+/// [`crate::commonplace::retention::RetentionScheduler`]. Each is off when its age is 0.
+#[derive(Debug, Deserialize, Clone)]
+pub struct Retention {
+    /// Purge soft-deleted rows this many days after they were deleted
+    #[serde(default)]
+    pub purge_deleted_after_days: u32,
+    /// Export resources untouched for this many days to the bucket, then delete them
+    #[serde(default)]
+    pub archive_inactive_after_days: u32,
+    /// Key prefix of archived resources in the bucket
+    #[serde(default = "default_archive_prefix")]
+    pub archive_prefix: String,
+}
+
+impl Default for Retention {
+    fn default() -> Self {
+        Self {
+            purge_deleted_after_days: 0,
+            archive_inactive_after_days: 0,
+            archive_prefix: default_archive_prefix(),
+        }
+    }
+}
+
+fn default_archive_prefix() -> String {
+    "archives/commonplace".to_string()
+}
+
+/// Publishing the `/html` pages to search engines and link previews, see
+/// [`crate::public`]. It does not restrict who can reach the server, but private books
+/// and resources are then not found: their pages, files, covers and share links.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct Public {
+    /// Serve `/sitemap.xml` and embed schema.org metadata in the book pages
+    #[serde(default)]
+    pub enabled: bool,
+    /// Absolute URL the library is published under, e.g. `https://books.example.com`.
+    /// Falls back to the host each request was made to.
+    #[serde(default)]
+    pub base_url: Option<String>,
+}
+
+/// A way of getting the text out of a PDF, see [`crate::text_extract`]
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ExtractorBackend {
+    /// Built in: reads the text operators of the page content streams
+    TextOps,
+    /// Poppler's `pdftotext` command
+    Poppler,
+}
+
+/// Text extraction from PDFs. Backends are tried in order until one gives text that
+/// scores at least `min_quality`; if none does, the best scoring text is used.
+#[derive(Debug, Deserialize, Clone)]
+pub struct Extraction {
+    #[serde(default = "default_extraction_backends")]
+    pub backends: Vec<ExtractorBackend>,
+    /// Share of characters that must not look garbled, from 0 to 1
+    #[serde(default = "default_min_quality")]
+    pub min_quality: f64,
+    /// Path to poppler's `pdftotext`
+    #[serde(default = "default_pdftotext")]
+    pub pdftotext: String,
+}
+
+impl Default for Extraction {
+    fn default() -> Self {
+        Self {
+            backends: default_extraction_backends(),
+            min_quality: default_min_quality(),
+            pdftotext: default_pdftotext(),
+        }
     }
 }
 
+fn default_extraction_backends() -> Vec<ExtractorBackend> {
+    vec![ExtractorBackend::TextOps, ExtractorBackend::Poppler]
+}
+
+fn default_min_quality() -> f64 {
+    0.9
+}
+
+fn default_pdftotext() -> String {
+    "pdftotext".to_string()
+}
+
+/// Running as a metadata-only mirror of another instance, see [`crate::mirror`]
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct Mirror {
+    /// Address of the primary instance, e.g. `https://nas.example.com:5999`. Unset
+    /// for the primary itself.
+    #[serde(default)]
+    pub primary_url: Option<String>,
+}
+
+/// What a sync does with an annotation, comment or note that was edited here and
+/// changed in its source since the last sync
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum ConflictPolicy {
+    /// Overwrite the edit made here
+    #[default]
+    SourceWins,
+    /// Keep the edit made here, and ignore the source's version
+    LocalWins,
+    /// Leave both alone and list the conflict under `GET /sync/conflicts`
+    Manual,
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct SyncSettings {
+    #[serde(default)]
+    pub conflict_policy: ConflictPolicy,
+    /// Overrides by source name; "light" covers every browser, "light:chrome" one of them
+    #[serde(default)]
+    pub conflict_policies: HashMap<String, ConflictPolicy>,
+}
+
+/// Sources the sync engine runs, which `conflict_policies` may name
+const SYNC_SOURCES: &[&str] = &[
+    "kobo", "light", "pocket", "reader", "readwise", "research", "wallabag", "zotero",
+];
+
+impl SyncSettings {
+    /// Rejects a policy for a source that doesn't exist, which would otherwise be
+    /// silently ignored
+    fn validate(&self) -> Result<()> {
+        for source in self.conflict_policies.keys() {
+            let parent = source.split_once(':').map_or(source.as_str(), |(parent, _)| parent);
+            if !SYNC_SOURCES.contains(&parent) {
+                anyhow::bail!(
+                    "sync.conflict_policies: unknown source '{}', expected one of {}",
+                    source,
+                    SYNC_SOURCES.join(", ")
+                );
+            }
+        }
+        Ok(())
+    }
+
+    pub fn conflict_policy(&self, source: &str) -> ConflictPolicy {
+        let parent = source.split_once(':').map(|(parent, _)| parent);
+        self.conflict_policies
+            .get(source)
+            .or_else(|| parent.and_then(|parent| self.conflict_policies.get(parent)))
+            .copied()
+            .unwrap_or(self.conflict_policy)
+    }
+}
+
+/// A URL told about finished syncs, see [`crate::webhook`]
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct Webhook {
+    /// Unset disables the webhook
+    #[serde(default)]
+    pub url: Option<String>,
+    /// Key the `X-Bibliotek-Signature` HMAC is made with; unsigned without one
+    #[serde(default)]
+    pub secret: Option<String>,
+    /// Sources to report, e.g. `[light, research]`; every source when empty
+    #[serde(default)]
+    pub sources: Vec<String>,
+}
+
+/// A self-hosted wallabag instance to sync entries from, see [`crate::wallabag`]. The
+/// client id and secret are those of an API client created under "API clients
+/// management" in wallabag.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct Wallabag {
+    /// Base URL of the instance; unset disables the sync
+    #[serde(default)]
+    pub url: Option<String>,
+    #[serde(default)]
+    pub client_id: String,
+    #[serde(default)]
+    pub client_secret: String,
+    #[serde(default)]
+    pub username: String,
+    #[serde(default)]
+    pub password: String,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct Config {
     pub app: App,
+    /// Not needed on a mirror
+    #[serde(default)]
     pub storage: Storage,
+    #[serde(default)]
+    pub dictionary: Dictionary,
+    #[serde(default)]
+    pub titles: Titles,
+    #[serde(default)]
+    pub sqlite: Sqlite,
+    #[serde(default)]
+    pub retention: Retention,
+    #[serde(default)]
+    pub public: Public,
+    #[serde(default)]
+    pub extraction: Extraction,
+    #[serde(default)]
+    pub mirror: Mirror,
+    #[serde(default)]
+    pub sync: SyncSettings,
+    #[serde(default)]
+    pub webhook: Webhook,
+    #[serde(default)]
+    pub wallabag: Wallabag,
 }
 
 impl Config {
@@ -81,6 +524,7 @@ impl Config {
         let yaml_str = fs::read_to_string(path)?;
         let yaml_with_env = Config::substitute_env_vars(&yaml_str)?;
         let config: Config = serde_yaml::from_str(&yaml_with_env)?;
+        config.sync.validate()?;
         Ok(config)
     }
 
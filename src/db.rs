@@ -1,23 +1,26 @@
-use crate::config::Config;
+use crate::api::{PatchBookRequest, UpdateAuthorRequest};
+use crate::authors;
+use crate::commonplace::Commonplace;
+use crate::config::{Config, Sqlite};
+use crate::enrich::{AuthorAuthority, EnrichedMetadata};
+use crate::events::{Event, EventBus};
 use crate::handler::HandlerParams;
 use crate::model::*;
+use crate::patch::{Patch, SetClause};
+use crate::tx;
 use anyhow::Result;
 use libsql::{Builder, Connection, Database as LibsqlDatabase};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::env;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use tokio::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use tokio::sync::{Mutex, OwnedMutexGuard};
 use tokio_util::sync::CancellationToken;
 
-const SYSTEM_MIGRATIONS: &[(&str, &str)] =
-    &[("system/000_migrations_table.sql", include_str!("migrations/system/000_migrations_table.sql"))];
-
-const MIGRATIONS: &[(&str, &str)] = &[
-    ("001_schema.sql", include_str!("migrations/001_schema.sql")),
-    ("002_seed_categories.sql", include_str!("migrations/002_seed_categories.sql")),
-    ("003_add_book_status.sql", include_str!("migrations/003_add_book_status.sql")),
-];
+const AUTHOR_COLUMNS: &str = "id, name, bio, photo_url, canonical_name, birth_year, authority_id";
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct MetadataAggregate {
@@ -25,19 +28,254 @@ pub struct MetadataAggregate {
     pub tags: Vec<TagAggregate>,
     pub categories: Vec<CategoryAggregate>,
     pub ratings: Vec<RatingAggregate>,
+    pub reading_statuses: Vec<ReadingStatusAggregate>,
+}
+
+/// Lock contention seen since startup
+#[derive(Debug, Default)]
+struct Contention {
+    transactions: AtomicU64,
+    waits: AtomicU64,
+    wait_ms_total: AtomicU64,
+    wait_ms_max: AtomicU64,
+    busy_errors: AtomicU64,
+}
+
+impl Contention {
+    fn waited(&self, wait: Duration) {
+        let ms = wait.as_millis() as u64;
+        self.waits.fetch_add(1, Ordering::Relaxed);
+        self.wait_ms_total.fetch_add(ms, Ordering::Relaxed);
+        self.wait_ms_max.fetch_max(ms, Ordering::Relaxed);
+    }
+}
+
+/// Connection settings in effect and lock contention since startup, for
+/// `GET /admin/db/stats`
+#[derive(Debug, Serialize)]
+pub struct DbStats {
+    pub journal_mode: String,
+    pub synchronous: String,
+    pub busy_timeout_ms: i64,
+    pub transactions: u64,
+    /// Transactions that had to wait for another one on the shared connection
+    pub contended_transactions: u64,
+    pub wait_ms_total: u64,
+    pub wait_ms_max: u64,
+    /// Failures with "database is locked" / "database is busy"
+    pub busy_errors: u64,
+}
+
+/// Whether `e` is SQLite giving up on a lock held by another connection
+pub fn is_busy(e: &anyhow::Error) -> bool {
+    let msg = e.to_string();
+    msg.contains("database is locked") || msg.contains("database table is locked") || msg.contains("database is busy")
+}
+
+/// Applies the `sqlite` config section to a new connection. The pragmas return a
+/// row, so they go through `query`.
+async fn configure(conn: &Connection, cfg: &Sqlite) -> Result<()> {
+    conn.query(&format!("PRAGMA busy_timeout = {}", cfg.busy_timeout_ms), ())
+        .await?;
+    // Embedded replicas manage their own journal, so a refusal there isn't fatal
+    let journal_mode = format!("PRAGMA journal_mode = {}", cfg.journal_mode.as_str());
+    if let Err(e) = conn.query(&journal_mode, ()).await {
+        tracing::warn!("[db] failed to set journal mode to {}: {}", cfg.journal_mode.as_str(), e);
+    }
+    conn.query(&format!("PRAGMA synchronous = {}", cfg.synchronous.as_str()), ())
+        .await?;
+    Ok(())
+}
+
+/// Holds the transaction lock while a transaction from [`Database::begin`] is open
+#[must_use]
+pub struct TxGuard {
+    _lock: Option<OwnedMutexGuard<()>>,
 }
 
 pub struct Database {
     db: LibsqlDatabase,
     conn: Connection,
-    tx_lock: Mutex<()>,
+    sqlite: Sqlite,
+    /// Backing file of a [`Database::new_in_memory`] database, removed on drop
+    scratch: Option<PathBuf>,
+    tx_lock: Arc<Mutex<()>>,
+    contention: Contention,
     turso_url: Option<String>,
     turso_auth_token: Option<String>,
+    events: EventBus,
+}
+
+impl Drop for Database {
+    fn drop(&mut self) {
+        if let Some(path) = &self.scratch {
+            for suffix in ["", "-wal", "-shm"] {
+                let _ = std::fs::remove_file(format!("{}{}", path.display(), suffix));
+            }
+        }
+    }
 }
 
 impl Database {
-    pub fn connection(&self) -> &Connection {
-        &self.conn
+    /// The connection to run statements on: the request's own connection within a
+    /// request transaction (see [`crate::tx`]), the shared one everywhere else
+    pub fn connection(&self) -> Connection {
+        tx::request_connection().unwrap_or_else(|| self.conn.clone())
+    }
+
+    /// Opens another connection to the database, configured like the shared one
+    pub async fn connect(&self) -> Result<Connection> {
+        let conn = self.db.connect()?;
+        configure(&conn, &self.sqlite).await?;
+        Ok(conn)
+    }
+
+    pub fn events(&self) -> &EventBus {
+        &self.events
+    }
+
+    /// Records `event` in the outbox and publishes it on the bus. For changes made
+    /// outside a transaction; transactional writes record their events themselves.
+    pub async fn emit(&self, event: Event) {
+        if let Err(e) = crate::outbox::record(&self.connection(), &event).await {
+            tracing::warn!("failed to record {} event in outbox: {}", event.kind(), e);
+        }
+        self.events.publish(event);
+    }
+
+    /// Commonplace repository over this database, publishing to its event bus
+    pub fn commonplace(&self) -> Commonplace<'_> {
+        Commonplace::new(&self.connection()).with_events(&self.events)
+    }
+
+    /// Takes the transaction lock and starts a transaction on the shared
+    /// connection, keeping track of how long the lock took to get. Within a request
+    /// transaction (see [`crate::tx`]) this opens a savepoint on the request's
+    /// connection instead, and the lock is held by the request until it ends.
+    pub async fn begin(&self) -> Result<TxGuard> {
+        match tx::request_transaction() {
+            Some(open) => {
+                if !open {
+                    let guard = self.lock_tx().await;
+                    self.execute_tx("BEGIN TRANSACTION").await?;
+                    tx::opened(guard);
+                }
+                self.execute_tx("SAVEPOINT request_step").await?;
+                Ok(TxGuard { _lock: None })
+            }
+            None => {
+                let guard = self.lock_tx().await;
+                self.execute_tx("BEGIN TRANSACTION").await?;
+                Ok(TxGuard { _lock: Some(guard) })
+            }
+        }
+    }
+
+    pub async fn commit(&self) -> Result<()> {
+        if tx::request_transaction() == Some(true) {
+            return self.execute_tx("RELEASE request_step").await;
+        }
+        self.execute_tx("COMMIT").await
+    }
+
+    /// Undoes the writes since [`Database::begin`]. Within a request transaction
+    /// only that step is undone; the request goes on.
+    pub async fn rollback(&self) -> Result<()> {
+        if tx::request_transaction() == Some(true) {
+            self.execute_tx("ROLLBACK TO request_step").await?;
+            return self.execute_tx("RELEASE request_step").await;
+        }
+        self.execute_tx("ROLLBACK").await
+    }
+
+    /// Runs `f` as one transaction under the transaction lock, rolling it back if it
+    /// fails. Writes made outside a request (background tasks, job handlers) must go
+    /// through here or [`Database::begin`]: a bare write on the shared connection
+    /// while another task has a transaction open becomes part of that transaction.
+    /// Within a request it is a step of the request's transaction. `f` mustn't call
+    /// `begin` itself, and shouldn't wait on the network while holding the lock.
+    pub async fn write<T, F, Fut>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce() -> Fut,
+        Fut: std::future::Future<Output = Result<T>>,
+    {
+        let _guard = self.begin().await?;
+        match f().await {
+            Ok(value) => {
+                self.commit().await?;
+                Ok(value)
+            }
+            Err(e) => {
+                let _ = self.rollback().await;
+                Err(e)
+            }
+        }
+    }
+
+    async fn lock_tx(&self) -> OwnedMutexGuard<()> {
+        let guard = match self.tx_lock.clone().try_lock_owned() {
+            Ok(guard) => guard,
+            Err(_) => {
+                let started = Instant::now();
+                let guard = self.tx_lock.clone().lock_owned().await;
+                self.contention.waited(started.elapsed());
+                guard
+            }
+        };
+        self.contention.transactions.fetch_add(1, Ordering::Relaxed);
+        guard
+    }
+
+    async fn execute_tx(&self, query: &str) -> Result<()> {
+        if let Err(e) = self.connection().execute(query, ()).await {
+            let e = e.into();
+            self.note_error(&e);
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Counts `e` towards [`DbStats::busy_errors`] if it is a lock timeout
+    pub fn note_error(&self, e: &anyhow::Error) {
+        if is_busy(e) {
+            self.contention.busy_errors.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub async fn stats(&self) -> Result<DbStats> {
+        let mut rows = self.connection().query("PRAGMA journal_mode", ()).await?;
+        let journal_mode: String = match rows.next().await? {
+            Some(row) => row.get(0)?,
+            None => String::new(),
+        };
+        let mut rows = self.connection().query("PRAGMA synchronous", ()).await?;
+        let synchronous = match rows.next().await? {
+            Some(row) => match row.get::<i64>(0)? {
+                0 => "off",
+                1 => "normal",
+                2 => "full",
+                3 => "extra",
+                _ => "unknown",
+            },
+            None => "unknown",
+        };
+        let mut rows = self.connection().query("PRAGMA busy_timeout", ()).await?;
+        let busy_timeout_ms = match rows.next().await? {
+            Some(row) => row.get(0)?,
+            None => 0,
+        };
+
+        let c = &self.contention;
+        Ok(DbStats {
+            journal_mode: journal_mode.to_lowercase(),
+            synchronous: synchronous.to_string(),
+            busy_timeout_ms,
+            transactions: c.transactions.load(Ordering::Relaxed),
+            contended_transactions: c.waits.load(Ordering::Relaxed),
+            wait_ms_total: c.wait_ms_total.load(Ordering::Relaxed),
+            wait_ms_max: c.wait_ms_max.load(Ordering::Relaxed),
+            busy_errors: c.busy_errors.load(Ordering::Relaxed),
+        })
     }
 
     pub fn is_replica(&self) -> bool {
@@ -65,6 +303,7 @@ impl Database {
                 tokio::select! {
                     _ = interval.tick() => {
                         if let Err(e) = db.sync().await {
+                            db.note_error(&e);
                             tracing::warn!("Failed to sync database: {}", e);
                         }
                     }
@@ -77,53 +316,14 @@ impl Database {
         });
     }
 
-    async fn is_migration_applied(conn: &Connection, name: &str) -> Result<bool> {
-        let query = "SELECT 1 FROM _migrations WHERE name = ?";
-        match conn.query(query, libsql::params![name]).await {
-            Ok(mut rows) => Ok(rows.next().await?.is_some()),
-            Err(e) => {
-                if e.to_string().contains("no such table") {
-                    Ok(false)
-                } else {
-                    Err(e.into())
-                }
-            }
-        }
-    }
-
-    async fn record_migration(conn: &Connection, name: &str) -> Result<()> {
-        let query = r#"
-            INSERT INTO _migrations (name, applied_at)
-            VALUES (?, strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))
-        "#;
-        match conn.execute(query, libsql::params![name]).await {
-            Ok(_) => Ok(()),
-            Err(e) => {
-                if e.to_string().contains("no such table") {
-                    Ok(())
-                } else {
-                    Err(e.into())
-                }
-            }
-        }
-    }
-
-    async fn run_migration(conn: &Connection, name: &str, sql: &str) -> Result<()> {
-        if Self::is_migration_applied(conn, name).await? {
-            tracing::debug!("migration {} already applied, skipping", name);
-            return Ok(());
-        }
-
-        tracing::info!("applying migration: {}", name);
-        conn.execute_batch(sql)
-            .await
-            .map_err(|e| anyhow::anyhow!("failed to execute migration {name}: {e}"))?;
-
-        Self::record_migration(conn, name).await?;
-        Ok(())
+    pub async fn new(cfg: &Config) -> Result<Self> {
+        let db = Self::open(cfg).await?;
+        crate::migrate::run_pending(&db.conn).await?;
+        Ok(db)
     }
 
-    pub async fn new(cfg: &Config) -> Result<Self> {
+    /// Opens the database without applying migrations, for the `migrate` subcommand.
+    pub async fn open(cfg: &Config) -> Result<Self> {
         let base_dir = env::var("MONO_DATA_DIR")
             .ok()
             .unwrap_or_else(|| crate::config::default_config_dir().to_string_lossy().to_string());
@@ -143,32 +343,52 @@ impl Database {
 
         let conn = db.connect()?;
         conn.query("SELECT 1", ()).await?;
-
-        for (filename, sql) in SYSTEM_MIGRATIONS {
-            Self::run_migration(&conn, filename, sql).await?;
-        }
-
-        for (filename, sql) in MIGRATIONS {
-            Self::run_migration(&conn, filename, sql).await?;
-        }
-
-        for (filename, sql) in crate::commonplace::migrations() {
-            Self::run_migration(&conn, filename, sql).await?;
-        }
-
-        for (filename, sql) in crate::research::migrations() {
-            Self::run_migration(&conn, filename, sql).await?;
-        }
+        configure(&conn, &cfg.sqlite).await?;
 
         Ok(Database {
             db,
             conn,
-            tx_lock: Mutex::new(()),
+            sqlite: cfg.sqlite.clone(),
+            scratch: None,
+            tx_lock: Arc::new(Mutex::new(())),
+            contention: Contention::default(),
             turso_url,
             turso_auth_token,
+            events: EventBus::default(),
         })
     }
 
+    /// Opens a fresh scratch database with every migration applied. Nothing outlives
+    /// it, so each call gets an isolated, empty library. It lives in a temporary
+    /// file rather than in memory so that request transactions can open their own
+    /// connections to it.
+    pub async fn new_in_memory() -> Result<Self> {
+        static SCRATCH: AtomicU64 = AtomicU64::new(0);
+        let path = env::temp_dir().join(format!(
+            "bibliotek-{}-{}.db",
+            std::process::id(),
+            SCRATCH.fetch_add(1, Ordering::Relaxed)
+        ));
+        let db = Builder::new_local(&path).build().await?;
+        let conn = db.connect()?;
+        let sqlite = Sqlite::default();
+        configure(&conn, &sqlite).await?;
+
+        let db = Database {
+            db,
+            conn,
+            sqlite,
+            scratch: Some(path),
+            tx_lock: Arc::new(Mutex::new(())),
+            contention: Contention::default(),
+            turso_url: None,
+            turso_auth_token: None,
+            events: EventBus::default(),
+        };
+        crate::migrate::run_pending(&db.conn).await?;
+        Ok(db)
+    }
+
     fn split_comma_separated_string(s: String) -> Vec<String> {
         s.split(',')
             .map(|s| s.trim().to_string())
@@ -176,9 +396,88 @@ impl Database {
             .collect()
     }
 
-    pub async fn count_books(&self, query: Option<&str>) -> Result<u32> {
-        let mut rows = if let Some(search) = query {
-            let sql = r#"
+    /// Maps a row selected with the column order used by the book listing queries:
+    /// id, title, url, cover_url, ratings, description, pages, author_ids, tag_ids, category_ids,
+    /// isbn, publish_date, cover_attribution, is_favorite, reading_status, deleted_at, annotation_count,
+    /// note_count, last_annotated_at, last_opened_at, progress, position, jobs
+    fn row_to_book(row: &libsql::Row) -> Result<Book> {
+        let book_authors_ids: String = row.get::<Option<String>>(7)?.unwrap_or_default();
+        let book_tags_ids: String = row.get::<Option<String>>(8)?.unwrap_or_default();
+        let book_categories_ids: String = row.get::<Option<String>>(9)?.unwrap_or_default();
+
+        Ok(Book {
+            id: row.get(0)?,
+            title: row.get(1)?,
+            download_url: row.get(2)?,
+            cover_url: row.get::<Option<String>>(3)?.unwrap_or_default(),
+            ratings: row.get::<Option<i32>>(4)?.unwrap_or(0),
+            description: row.get::<Option<String>>(5)?.unwrap_or_default(),
+            pages: row.get::<Option<i32>>(6)?.unwrap_or(0),
+            author_ids: Self::split_comma_separated_string(book_authors_ids),
+            tag_ids: Self::split_comma_separated_string(book_tags_ids),
+            category_ids: Self::split_comma_separated_string(book_categories_ids),
+            isbn: row.get::<Option<String>>(10)?.unwrap_or_default(),
+            publish_date: row.get::<Option<String>>(11)?.unwrap_or_default(),
+            cover_attribution: row.get::<Option<String>>(12)?.unwrap_or_default(),
+            is_favorite: row.get::<Option<i32>>(13)?.unwrap_or(0) != 0,
+            reading_status: row
+                .get::<Option<String>>(14)?
+                .and_then(|s| ReadingStatus::from_str(&s))
+                .unwrap_or_default(),
+            deleted_at: row.get(15)?,
+            annotation_count: row.get::<Option<i32>>(16)?.unwrap_or(0),
+            note_count: row.get::<Option<i32>>(17)?.unwrap_or(0),
+            last_annotated_at: row.get(18)?,
+            last_opened_at: row.get(19)?,
+            progress: row.get(20)?,
+            position: row.get(21)?,
+            jobs: row
+                .get::<Option<String>>(22)?
+                .and_then(|jobs| serde_json::from_str(&jobs).ok())
+                .unwrap_or_default(),
+            visibility: row
+                .get::<Option<String>>(23)?
+                .and_then(|s| Visibility::from_str(&s))
+                .unwrap_or_default(),
+        })
+    }
+
+    /// WHERE clause and params for the book listing filters
+    fn book_filters(params: &HandlerParams) -> (String, Vec<libsql::Value>) {
+        let mut conditions = vec![if params.trashed {
+            "books.deleted_at IS NOT NULL".to_string()
+        } else {
+            "books.deleted_at IS NULL".to_string()
+        }];
+        let mut values: Vec<libsql::Value> = Vec::new();
+
+        if let Some(search) = &params.query {
+            conditions.push(
+                "(books.title LIKE ? OR authors.name LIKE ? OR tags.name LIKE ? OR categories.name LIKE ?)".to_string(),
+            );
+            let pattern = format!("%{}%", search);
+            values.extend(std::iter::repeat_n(libsql::Value::from(pattern), 4));
+        }
+        if let Some(favorite) = params.favorite {
+            conditions.push("books.is_favorite = ?".to_string());
+            values.push(libsql::Value::from(favorite as i32));
+        }
+        if let Some(status) = params.reading_status {
+            conditions.push("books.reading_status = ?".to_string());
+            values.push(status.into());
+        }
+        if let Some(visibility) = params.visibility {
+            conditions.push("books.visibility = ?".to_string());
+            values.push(visibility.into());
+        }
+
+        (format!("WHERE {}", conditions.join(" AND ")), values)
+    }
+
+    pub async fn count_books(&self, params: &HandlerParams) -> Result<u32> {
+        let (filter, values) = Self::book_filters(params);
+        let sql = format!(
+            r#"
 SELECT COUNT(DISTINCT books.id) FROM books
 LEFT JOIN book_authors ON book_authors.book_id = books.id
 LEFT JOIN authors ON authors.id = book_authors.author_id
@@ -186,13 +485,11 @@ LEFT JOIN book_tags ON book_tags.book_id = books.id
 LEFT JOIN tags ON tags.id = book_tags.tag_id
 LEFT JOIN book_categories ON book_categories.book_id = books.id
 LEFT JOIN categories ON categories.id = book_categories.category_id
-WHERE books.title LIKE ? OR authors.name LIKE ? OR tags.name LIKE ? OR categories.name LIKE ?
-"#;
-            let pattern = format!("%{}%", search);
-            self.conn.query(sql, (pattern.clone(), pattern.clone(), pattern.clone(), pattern)).await?
-        } else {
-            self.conn.query("SELECT COUNT(*) FROM books", ()).await?
-        };
+{}
+"#,
+            filter
+        );
+        let mut rows = self.connection().query(&sql, values).await?;
         if let Some(row) = rows.next().await? {
             let count: i32 = row.get(0)?;
             return Ok(count as u32);
@@ -201,31 +498,9 @@ WHERE books.title LIKE ? OR authors.name LIKE ? OR tags.name LIKE ? OR categorie
     }
 
     pub async fn get_books(&self, params: HandlerParams) -> Result<Vec<Book>> {
-        let last_n_books = r#"
-SELECT
-    books.id as book_id,
-    books.title,
-    books.url,
-    books.cover_url,
-    books.ratings,
-    books.description,
-    books.pages,
-    GROUP_CONCAT(DISTINCT CAST(authors.id AS TEXT)) as author_ids,
-    GROUP_CONCAT(DISTINCT CAST(tags.id AS TEXT)) as tag_ids,
-    GROUP_CONCAT(DISTINCT CAST(categories.id AS TEXT)) as category_ids
-FROM books
-LEFT JOIN book_authors ON book_authors.book_id = books.id
-LEFT JOIN authors ON authors.id = book_authors.author_id
-LEFT JOIN book_tags ON book_tags.book_id = books.id
-LEFT JOIN tags ON tags.id = book_tags.tag_id
-LEFT JOIN book_categories ON book_categories.book_id = books.id
-LEFT JOIN categories ON categories.id = book_categories.category_id
-GROUP BY books.id, books.title, books.url, books.cover_url, books.ratings
-ORDER BY book_id DESC
-LIMIT ? OFFSET ?
-"#;
-
-        let search_books = r#"
+        let (filter, mut values) = Self::book_filters(&params);
+        let query = format!(
+            r#"
 SELECT
     books.id as book_id,
     books.title,
@@ -236,7 +511,25 @@ SELECT
     books.pages,
     GROUP_CONCAT(DISTINCT CAST(authors.id AS TEXT)) as author_ids,
     GROUP_CONCAT(DISTINCT CAST(tags.id AS TEXT)) as tag_ids,
-    GROUP_CONCAT(DISTINCT CAST(categories.id AS TEXT)) as category_ids
+    GROUP_CONCAT(DISTINCT CAST(categories.id AS TEXT)) as category_ids,
+    books.isbn,
+    books.publish_date,
+    books.cover_attribution,
+    books.is_favorite,
+    books.reading_status,
+    books.deleted_at,
+    (SELECT COUNT(*) FROM annotations JOIN resources ON resources.id = annotations.resource_id
+     WHERE resources.book_id = books.id AND resources.deleted_at IS NULL AND annotations.deleted_at IS NULL) as annotation_count,
+    (SELECT COUNT(*) FROM notes JOIN resources ON resources.id = notes.resource_id
+     WHERE resources.book_id = books.id AND resources.deleted_at IS NULL AND notes.deleted_at IS NULL) as note_count,
+    (SELECT MAX(annotations.created_at) FROM annotations JOIN resources ON resources.id = annotations.resource_id
+     WHERE resources.book_id = books.id AND resources.deleted_at IS NULL AND annotations.deleted_at IS NULL) as last_annotated_at,
+    book_opens.last_opened_at,
+    book_opens.progress,
+    book_opens.position,
+    (SELECT json_group_object(kind, status)
+     FROM (SELECT kind, status FROM jobs WHERE jobs.book_id = books.id ORDER BY id)) as jobs,
+    books.visibility
 FROM books
 LEFT JOIN book_authors ON book_authors.book_id = books.id
 LEFT JOIN authors ON authors.id = book_authors.author_id
@@ -244,61 +537,22 @@ LEFT JOIN book_tags ON book_tags.book_id = books.id
 LEFT JOIN tags ON tags.id = book_tags.tag_id
 LEFT JOIN book_categories ON book_categories.book_id = books.id
 LEFT JOIN categories ON categories.id = book_categories.category_id
-WHERE books.title LIKE ? OR authors.name LIKE ? OR tags.name LIKE ? OR categories.name LIKE ?
+LEFT JOIN book_opens ON book_opens.book_id = books.id
+{}
 GROUP BY books.id, books.title, books.url, books.cover_url, books.ratings
 ORDER BY book_id DESC
 LIMIT ? OFFSET ?
-"#;
+"#,
+            filter
+        );
+        values.push(libsql::Value::from(params.limit as i32));
+        values.push(libsql::Value::from(params.offset as i32));
 
-        let mut rows = if let Some(search) = &params.query {
-            self.conn
-                .query(
-                    search_books,
-                    (
-                        format!("%{}%", search),
-                        format!("%{}%", search),
-                        format!("%{}%", search),
-                        format!("%{}%", search),
-                        params.limit as i32,
-                        params.offset as i32,
-                    ),
-                )
-                .await?
-        } else {
-            self.conn
-                .query(last_n_books, (params.limit as i32, params.offset as i32))
-                .await?
-        };
+        let mut rows = self.connection().query(&query, values).await?;
         let mut books: Vec<Book> = vec![];
 
         while let Some(row) = rows.next().await? {
-            let book_id: i32 = row.get(0)?;
-            let book_title: String = row.get(1)?;
-            let book_url: String = row.get(2)?;
-            let book_cover_url: String = row.get::<Option<String>>(3)?.unwrap_or_default();
-            let book_ratings: i32 = row.get::<Option<i32>>(4)?.unwrap_or(0);
-            let book_description: String = row.get::<Option<String>>(5)?.unwrap_or_default();
-            let book_pages: i32 = row.get::<Option<i32>>(6)?.unwrap_or(0);
-            let book_authors_ids: String = row.get::<Option<String>>(7)?.unwrap_or_default();
-            let book_tags_ids: String = row.get::<Option<String>>(8)?.unwrap_or_default();
-            let book_categories_ids: String = row.get::<Option<String>>(9)?.unwrap_or_default();
-
-            let book_authors = Self::split_comma_separated_string(book_authors_ids);
-            let book_tags = Self::split_comma_separated_string(book_tags_ids);
-            let book_categories = Self::split_comma_separated_string(book_categories_ids);
-
-            books.push(Book {
-                id: book_id,
-                title: book_title,
-                download_url: book_url,
-                cover_url: book_cover_url,
-                ratings: book_ratings,
-                description: book_description,
-                pages: book_pages,
-                author_ids: book_authors,
-                tag_ids: book_tags,
-                category_ids: book_categories,
-            });
+            books.push(Self::row_to_book(&row)?);
         }
 
         Ok(books)
@@ -308,21 +562,24 @@ LIMIT ? OFFSET ?
         let query = r#"
 WITH
 author_count AS (
-    SELECT authors.id, authors.name, COUNT(book_authors.id) as count
+    SELECT authors.id, authors.name, COUNT(books.id) as count
     FROM authors
     LEFT JOIN book_authors ON authors.id = book_authors.author_id
+    LEFT JOIN books ON books.id = book_authors.book_id AND books.deleted_at IS NULL
     GROUP BY authors.id, authors.name
 ),
 category_count AS (
-    SELECT categories.id, categories.name, COUNT(book_categories.id) as count
+    SELECT categories.id, categories.name, COUNT(books.id) as count
     FROM categories
     LEFT JOIN book_categories ON categories.id = book_categories.category_id
+    LEFT JOIN books ON books.id = book_categories.book_id AND books.deleted_at IS NULL
     GROUP BY categories.id, categories.name
 ),
 tag_count AS (
-    SELECT tags.id, tags.name, COUNT(book_tags.id) as count
+    SELECT tags.id, tags.name, COUNT(books.id) as count
     FROM tags
     LEFT JOIN book_tags ON tags.id = book_tags.tag_id
+    LEFT JOIN books ON books.id = book_tags.book_id AND books.deleted_at IS NULL
     GROUP BY tags.id, tags.name
 ),
 ratings_count AS (
@@ -331,9 +588,18 @@ SELECT
     cast(ratings as TEXT) as name,
     COUNT(*) as count
 FROM books
-WHERE ratings IS NOT NULL
+WHERE ratings IS NOT NULL AND deleted_at IS NULL
 GROUP BY ratings
 ORDER BY ratings DESC
+),
+reading_status_count AS (
+SELECT
+    ROW_NUMBER() OVER (ORDER BY reading_status) as id,
+    reading_status as name,
+    COUNT(*) as count
+FROM books
+WHERE deleted_at IS NULL
+GROUP BY reading_status
 )
 SELECT 'author' as type, id, name, count FROM author_count
 UNION ALL
@@ -342,6 +608,8 @@ UNION ALL
 SELECT 'tag' as type, id, name, count FROM tag_count
 UNION ALL
 SELECT 'ratings' as type, id, name, count FROM ratings_count
+UNION ALL
+SELECT 'reading_status' as type, id, name, count FROM reading_status_count
 ORDER BY type, count DESC;
         "#;
 
@@ -349,8 +617,13 @@ ORDER BY type, count DESC;
         let mut author_aggregates: Vec<AuthorAggregate> = vec![];
         let mut tag_aggregates: Vec<TagAggregate> = vec![];
         let mut ratings_aggregates: Vec<RatingAggregate> = vec![];
+        // Every status is listed, even with no books in it
+        let mut reading_status_aggregates: Vec<ReadingStatusAggregate> = ReadingStatus::ALL
+            .into_iter()
+            .map(|status| ReadingStatusAggregate { status, count: 0 })
+            .collect();
 
-        let mut rows = self.conn.query(query, ()).await?;
+        let mut rows = self.connection().query(query, ()).await?;
 
         while let Some(row) = rows.next().await? {
             let aggregate_type = row
@@ -364,7 +637,16 @@ ORDER BY type, count DESC;
 
             match aggregate_type.as_str() {
                 "author" => author_aggregates.push(AuthorAggregate {
-                    author: Author { id, name },
+                    author: Author {
+                        id,
+                        name,
+                        bio: None,
+                        photo_url: None,
+                        canonical_name: None,
+                        birth_year: None,
+                        authority_id: None,
+                        aliases: vec![],
+                    },
                     count,
                 }),
                 "category" => category_aggregates.push(CategoryAggregate {
@@ -376,9 +658,14 @@ ORDER BY type, count DESC;
                     count,
                 }),
                 "ratings" => ratings_aggregates.push(RatingAggregate {
-                    rating: Rating { id, name: name },
+                    rating: Rating { id, name },
                     count,
                 }),
+                "reading_status" => {
+                    if let Some(aggregate) = reading_status_aggregates.iter_mut().find(|a| a.status.as_str() == name) {
+                        aggregate.count = count;
+                    }
+                }
                 _ => {
                     tracing::error!("invalid type: ->{}", aggregate_type);
                     continue;
@@ -391,6 +678,7 @@ ORDER BY type, count DESC;
             categories: category_aggregates,
             tags: tag_aggregates,
             ratings: ratings_aggregates,
+            reading_statuses: reading_status_aggregates,
         })
     }
 
@@ -406,7 +694,25 @@ SELECT
     books.pages,
     GROUP_CONCAT(DISTINCT CAST(authors.id AS TEXT)) as author_ids,
     GROUP_CONCAT(DISTINCT CAST(tags.id AS TEXT)) as tag_ids,
-    GROUP_CONCAT(DISTINCT CAST(categories.id AS TEXT)) as category_ids
+    GROUP_CONCAT(DISTINCT CAST(categories.id AS TEXT)) as category_ids,
+    books.isbn,
+    books.publish_date,
+    books.cover_attribution,
+    books.is_favorite,
+    books.reading_status,
+    books.deleted_at,
+    (SELECT COUNT(*) FROM annotations JOIN resources ON resources.id = annotations.resource_id
+     WHERE resources.book_id = books.id AND resources.deleted_at IS NULL AND annotations.deleted_at IS NULL) as annotation_count,
+    (SELECT COUNT(*) FROM notes JOIN resources ON resources.id = notes.resource_id
+     WHERE resources.book_id = books.id AND resources.deleted_at IS NULL AND notes.deleted_at IS NULL) as note_count,
+    (SELECT MAX(annotations.created_at) FROM annotations JOIN resources ON resources.id = annotations.resource_id
+     WHERE resources.book_id = books.id AND resources.deleted_at IS NULL AND annotations.deleted_at IS NULL) as last_annotated_at,
+    book_opens.last_opened_at,
+    book_opens.progress,
+    book_opens.position,
+    (SELECT json_group_object(kind, status)
+     FROM (SELECT kind, status FROM jobs WHERE jobs.book_id = books.id ORDER BY id)) as jobs,
+    books.visibility
 FROM books
 LEFT JOIN book_authors ON book_authors.book_id = books.id
 LEFT JOIN authors ON authors.id = book_authors.author_id
@@ -414,65 +720,120 @@ LEFT JOIN book_tags ON book_tags.book_id = books.id
 LEFT JOIN tags ON tags.id = book_tags.tag_id
 LEFT JOIN book_categories ON book_categories.book_id = books.id
 LEFT JOIN categories ON categories.id = book_categories.category_id
+LEFT JOIN book_opens ON book_opens.book_id = books.id
 WHERE books.id = ?
 GROUP BY books.id, books.title, books.url, books.cover_url, books.ratings
 "#;
 
-        let mut rows = self.conn.query(query, libsql::params![book_id]).await?;
+        let mut rows = self.connection().query(query, libsql::params![book_id]).await?;
 
         if let Some(row) = rows.next().await? {
-            let book_id: i32 = row.get(0)?;
-            let book_title: String = row.get(1)?;
-            let book_url: String = row.get(2)?;
-            let book_cover_url: String = row.get::<Option<String>>(3)?.unwrap_or_default();
-            let book_ratings: i32 = row.get::<Option<i32>>(4)?.unwrap_or(0);
-            let book_description: String = row.get::<Option<String>>(5)?.unwrap_or_default();
-            let book_pages: i32 = row.get::<Option<i32>>(6)?.unwrap_or(0);
-            let book_authors_ids: String = row.get::<Option<String>>(7)?.unwrap_or_default();
-            let book_tags_ids: String = row.get::<Option<String>>(8)?.unwrap_or_default();
-            let book_categories_ids: String = row.get::<Option<String>>(9)?.unwrap_or_default();
-
-            let book_authors = Self::split_comma_separated_string(book_authors_ids);
-            let book_tags = Self::split_comma_separated_string(book_tags_ids);
-            let book_categories = Self::split_comma_separated_string(book_categories_ids);
-
-            Ok(Some(Book {
-                id: book_id,
-                title: book_title,
-                download_url: book_url,
-                cover_url: book_cover_url,
-                ratings: book_ratings,
-                description: book_description,
-                pages: book_pages,
-                author_ids: book_authors,
-                tag_ids: book_tags,
-                category_ids: book_categories,
-            }))
+            Ok(Some(Self::row_to_book(&row)?))
         } else {
             Ok(None)
         }
     }
 
+    /// Finds the author by exact name, then by alias (see [`authors::name_key`]), and
+    /// only creates a new one when neither matches
     pub async fn get_or_create_author(&self, name: &str) -> Result<i32> {
+        let mut rows = self
+            .connection()
+            .query("SELECT id FROM authors WHERE name = ? LIMIT 1", libsql::params![name])
+            .await?;
+        if let Some(row) = rows.next().await? {
+            return Ok(row.get(0)?);
+        }
+
+        let key = authors::name_key(name);
+        if let Some(author_id) = self.find_author_by_key(&key).await? {
+            self.add_author_alias(author_id, name).await?;
+            return Ok(author_id);
+        }
+
         let insert_query = "INSERT OR IGNORE INTO authors (name) VALUES (?)";
-        self.conn.execute(insert_query, libsql::params![name]).await?;
+        self.connection().execute(insert_query, libsql::params![name]).await?;
 
         let select_query = "SELECT id FROM authors WHERE name = ? LIMIT 1";
-        let mut rows = self.conn.query(select_query, libsql::params![name]).await?;
+        let mut rows = self.connection().query(select_query, libsql::params![name]).await?;
 
         if let Some(row) = rows.next().await? {
-            Ok(row.get(0)?)
+            let author_id = row.get(0)?;
+            self.add_author_alias(author_id, name).await?;
+            Ok(author_id)
         } else {
             anyhow::bail!("Failed to get or create author: {}", name)
         }
     }
 
+    async fn find_author_by_key(&self, key: &str) -> Result<Option<i32>> {
+        if key.is_empty() {
+            return Ok(None);
+        }
+        let mut rows = self
+            .connection()
+            .query("SELECT author_id FROM author_aliases WHERE name_key = ?", libsql::params![key])
+            .await?;
+        if let Some(row) = rows.next().await? {
+            return Ok(Some(row.get(0)?));
+        }
+
+        // Authors created before aliases existed have no keys yet
+        let mut rows = self
+            .connection()
+            .query(
+                "SELECT id, name FROM authors WHERE id NOT IN (SELECT author_id FROM author_aliases) ORDER BY id",
+                (),
+            )
+            .await?;
+        while let Some(row) = rows.next().await? {
+            let name: String = row.get(1)?;
+            if authors::name_key(&name) == key {
+                let author_id = row.get(0)?;
+                self.add_author_alias(author_id, &name).await?;
+                return Ok(Some(author_id));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Records `alias` as a spelling of the author. A key already claimed by another
+    /// author is left with them.
+    async fn add_author_alias(&self, author_id: i32, alias: &str) -> Result<()> {
+        let key = authors::name_key(alias);
+        if key.is_empty() {
+            return Ok(());
+        }
+        self.connection()
+            .execute(
+                "INSERT OR IGNORE INTO author_aliases (author_id, alias, name_key) VALUES (?, ?, ?)",
+                libsql::params![author_id, alias, key],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn get_author_aliases(&self, author_id: i32) -> Result<Vec<String>> {
+        let mut rows = self
+            .connection()
+            .query(
+                "SELECT alias FROM author_aliases WHERE author_id = ? ORDER BY alias COLLATE NOCASE",
+                libsql::params![author_id],
+            )
+            .await?;
+        let mut aliases = vec![];
+        while let Some(row) = rows.next().await? {
+            aliases.push(row.get(0)?);
+        }
+        Ok(aliases)
+    }
+
     pub async fn get_or_create_tag(&self, name: &str) -> Result<i32> {
         let insert_query = "INSERT OR IGNORE INTO tags (name) VALUES (?)";
-        self.conn.execute(insert_query, libsql::params![name]).await?;
+        self.connection().execute(insert_query, libsql::params![name]).await?;
 
         let select_query = "SELECT id FROM tags WHERE name = ? LIMIT 1";
-        let mut rows = self.conn.query(select_query, libsql::params![name]).await?;
+        let mut rows = self.connection().query(select_query, libsql::params![name]).await?;
 
         if let Some(row) = rows.next().await? {
             Ok(row.get(0)?)
@@ -483,10 +844,10 @@ GROUP BY books.id, books.title, books.url, books.cover_url, books.ratings
 
     pub async fn get_or_create_category(&self, name: &str) -> Result<i32> {
         let insert_query = "INSERT OR IGNORE INTO categories (name) VALUES (?)";
-        self.conn.execute(insert_query, libsql::params![name]).await?;
+        self.connection().execute(insert_query, libsql::params![name]).await?;
 
         let select_query = "SELECT id FROM categories WHERE name = ? LIMIT 1";
-        let mut rows = self.conn.query(select_query, libsql::params![name]).await?;
+        let mut rows = self.connection().query(select_query, libsql::params![name]).await?;
 
         if let Some(row) = rows.next().await? {
             Ok(row.get(0)?)
@@ -495,6 +856,22 @@ GROUP BY books.id, books.title, books.url, books.cover_url, books.ratings
         }
     }
 
+    /// Names of the categories a book is in, alphabetically
+    pub async fn get_book_category_names(&self, book_id: i32) -> Result<Vec<String>> {
+        let query = "SELECT categories.name FROM categories
+            JOIN book_categories ON categories.id = book_categories.category_id
+            WHERE book_categories.book_id = ?
+            ORDER BY categories.name";
+        let mut rows = self.connection().query(query, libsql::params![book_id]).await?;
+
+        let mut names = Vec::new();
+        while let Some(row) = rows.next().await? {
+            names.push(row.get(0)?);
+        }
+        Ok(names)
+    }
+
+    #[allow(clippy::too_many_arguments)]
     pub async fn create_book(
         &self,
         title: &str,
@@ -508,37 +885,46 @@ GROUP BY books.id, books.title, books.url, books.cover_url, books.ratings
         category_names: &[String],
         status: &str,
     ) -> Result<i32> {
-        let _guard = self.tx_lock.lock().await;
-
-        self.conn.execute("BEGIN TRANSACTION", ()).await?;
-
-        let result = self
-            .create_book_internal(
-                title,
-                url,
-                cover_url,
-                description,
-                pages,
-                ratings,
-                author_names,
-                tag_names,
-                category_names,
-                status,
-            )
-            .await;
+        let _guard = self.begin().await?;
+
+        let result = async {
+            let book_id = self
+                .create_book_internal(
+                    title,
+                    url,
+                    cover_url,
+                    description,
+                    pages,
+                    ratings,
+                    author_names,
+                    tag_names,
+                    category_names,
+                    status,
+                )
+                .await?;
+            let event = Event::BookCreated {
+                book_id,
+                title: title.to_string(),
+            };
+            crate::outbox::record(&self.connection(), &event).await?;
+            Ok::<_, anyhow::Error>((book_id, event))
+        }
+        .await;
 
         match result {
-            Ok(book_id) => {
-                self.conn.execute("COMMIT", ()).await?;
+            Ok((book_id, event)) => {
+                self.commit().await?;
+                self.events.publish(event);
                 Ok(book_id)
             }
             Err(e) => {
-                let _ = self.conn.execute("ROLLBACK", ()).await;
+                let _ = self.rollback().await;
                 Err(e)
             }
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     async fn create_book_internal(
         &self,
         title: &str,
@@ -559,7 +945,7 @@ GROUP BY books.id, books.title, books.url, books.cover_url, books.ratings
         "#;
 
         let mut rows = self
-            .conn
+            .connection()
             .query(insert_book, libsql::params![title, url, cover_url, description, pages, ratings, status])
             .await?;
 
@@ -572,7 +958,7 @@ GROUP BY books.id, books.title, books.url, books.cover_url, books.ratings
         for author_name in author_names {
             let author_id = self.get_or_create_author(author_name).await?;
             let link_query = "INSERT OR IGNORE INTO book_authors (book_id, author_id) VALUES (?, ?)";
-            self.conn
+            self.connection()
                 .execute(link_query, libsql::params![book_id, author_id])
                 .await?;
         }
@@ -580,13 +966,15 @@ GROUP BY books.id, books.title, books.url, books.cover_url, books.ratings
         for tag_name in tag_names {
             let tag_id = self.get_or_create_tag(tag_name).await?;
             let link_query = "INSERT OR IGNORE INTO book_tags (book_id, tag_id) VALUES (?, ?)";
-            self.conn.execute(link_query, libsql::params![book_id, tag_id]).await?;
+            self.connection()
+                .execute(link_query, libsql::params![book_id, tag_id])
+                .await?;
         }
 
         for category_name in category_names {
             let category_id = self.get_or_create_category(category_name).await?;
             let link_query = "INSERT OR IGNORE INTO book_categories (book_id, category_id) VALUES (?, ?)";
-            self.conn
+            self.connection()
                 .execute(link_query, libsql::params![book_id, category_id])
                 .await?;
         }
@@ -601,22 +989,28 @@ GROUP BY books.id, books.title, books.url, books.cover_url, books.ratings
         author_ids: &[i32],
         tag_ids: &[i32],
         category_ids: &[i32],
+        reading_status: Option<ReadingStatus>,
     ) -> Result<()> {
-        let _guard = self.tx_lock.lock().await;
+        let _guard = self.begin().await?;
 
-        self.conn.execute("BEGIN TRANSACTION", ()).await?;
-
-        let result = self
-            .update_book_internal(book_id, title, author_ids, tag_ids, category_ids)
-            .await;
+        let result = async {
+            let before = self.get_book_by_id(book_id).await?;
+            self.update_book_internal(book_id, title, author_ids, tag_ids, category_ids, reading_status)
+                .await?;
+            if let Some(before) = before {
+                self.record_book_revisions(&before).await?;
+            }
+            Ok::<(), anyhow::Error>(())
+        }
+        .await;
 
         match result {
             Ok(_) => {
-                self.conn.execute("COMMIT", ()).await?;
+                self.commit().await?;
                 Ok(())
             }
             Err(e) => {
-                let _ = self.conn.execute("ROLLBACK", ()).await;
+                let _ = self.rollback().await;
                 Err(e)
             }
         }
@@ -629,77 +1023,370 @@ GROUP BY books.id, books.title, books.url, books.cover_url, books.ratings
         author_ids: &[i32],
         tag_ids: &[i32],
         category_ids: &[i32],
+        reading_status: Option<ReadingStatus>,
     ) -> Result<()> {
-        self.conn
-            .execute(
-                "UPDATE books SET title = ?, updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now') WHERE id = ?",
-                libsql::params![title, book_id],
-            )
+        let query = r#"
+UPDATE books SET
+    title = ?,
+    reading_status = COALESCE(?, reading_status),
+    updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
+WHERE id = ?
+"#;
+        self.connection()
+            .execute(query, libsql::params![title, reading_status.map(|s| s.as_str()), book_id])
             .await?;
 
-        self.conn
-            .execute("DELETE FROM book_authors WHERE book_id = ?", libsql::params![book_id])
+        self.replace_book_links("book_authors", "author_id", book_id, author_ids)
+            .await?;
+        self.replace_book_links("book_tags", "tag_id", book_id, tag_ids).await?;
+        self.replace_book_links("book_categories", "category_id", book_id, category_ids)
             .await?;
-        for author_id in author_ids {
-            self.conn
+
+        Ok(())
+    }
+
+    /// Field values compared between revisions, with empty fields as None
+    fn revision_fields(book: &Book) -> [(&'static str, Option<String>); 12] {
+        let text = |s: &str| Some(s.to_string()).filter(|s| !s.is_empty());
+        let number = |n: i32| Some(n.to_string()).filter(|_| n != 0);
+        let ids = |ids: &[String]| {
+            let mut ids: Vec<i32> = ids.iter().filter_map(|id| id.parse().ok()).collect();
+            ids.sort_unstable();
+            let joined = ids.iter().map(|id| id.to_string()).collect::<Vec<_>>().join(",");
+            text(&joined)
+        };
+        [
+            ("title", text(&book.title)),
+            ("description", text(&book.description)),
+            ("cover_url", text(&book.cover_url)),
+            ("pages", number(book.pages)),
+            ("ratings", number(book.ratings)),
+            ("isbn", text(&book.isbn)),
+            ("publish_date", text(&book.publish_date)),
+            ("reading_status", Some(book.reading_status.as_str().to_string())),
+            ("visibility", Some(book.visibility.as_str().to_string())),
+            ("author_ids", ids(&book.author_ids)),
+            ("tag_ids", ids(&book.tag_ids)),
+            ("category_ids", ids(&book.category_ids)),
+        ]
+    }
+
+    /// Diffs `before` against the book's current state and records every changed field.
+    /// Runs inside the edit's transaction.
+    async fn record_book_revisions(&self, before: &Book) -> Result<()> {
+        let Some(after) = self.get_book_by_id(before.id).await? else {
+            return Ok(());
+        };
+        let old = Self::revision_fields(before);
+        let new = Self::revision_fields(&after);
+        for ((field, old_value), (_, new_value)) in old.into_iter().zip(new) {
+            if old_value == new_value {
+                continue;
+            }
+            self.connection()
                 .execute(
-                    "INSERT OR IGNORE INTO book_authors (book_id, author_id) VALUES (?, ?)",
-                    libsql::params![book_id, *author_id],
+                    "INSERT INTO book_revisions (book_id, field, old_value, new_value) VALUES (?, ?, ?, ?)",
+                    libsql::params![before.id, field, old_value, new_value],
                 )
                 .await?;
         }
+        Ok(())
+    }
 
-        self.conn
-            .execute("DELETE FROM book_tags WHERE book_id = ?", libsql::params![book_id])
-            .await?;
-        for tag_id in tag_ids {
-            self.conn
-                .execute(
-                    "INSERT OR IGNORE INTO book_tags (book_id, tag_id) VALUES (?, ?)",
-                    libsql::params![book_id, *tag_id],
-                )
-                .await?;
+    /// Newest first
+    pub async fn get_book_revisions(&self, book_id: i32) -> Result<Vec<BookRevision>> {
+        let query = r#"
+            SELECT id, book_id, field, old_value, new_value, created_at
+            FROM book_revisions
+            WHERE book_id = ?
+            ORDER BY id DESC
+        "#;
+        let mut rows = self.connection().query(query, libsql::params![book_id]).await?;
+        let mut revisions = Vec::new();
+        while let Some(row) = rows.next().await? {
+            revisions.push(BookRevision {
+                id: row.get(0)?,
+                book_id: row.get(1)?,
+                field: row.get(2)?,
+                old_value: row.get(3)?,
+                new_value: row.get(4)?,
+                created_at: row.get(5)?,
+            });
         }
+        Ok(revisions)
+    }
 
-        self.conn
-            .execute("DELETE FROM book_categories WHERE book_id = ?", libsql::params![book_id])
+    async fn replace_book_links(&self, table: &str, column: &str, book_id: i32, ids: &[i32]) -> Result<()> {
+        self.connection()
+            .execute(&format!("DELETE FROM {} WHERE book_id = ?", table), libsql::params![book_id])
             .await?;
-        for category_id in category_ids {
-            self.conn
-                .execute(
-                    "INSERT OR IGNORE INTO book_categories (book_id, category_id) VALUES (?, ?)",
-                    libsql::params![book_id, *category_id],
-                )
+        let insert = format!("INSERT OR IGNORE INTO {} (book_id, {}) VALUES (?, ?)", table, column);
+        for id in ids {
+            self.connection()
+                .execute(&insert, libsql::params![book_id, *id])
                 .await?;
         }
-
         Ok(())
     }
 
-    pub async fn create_author(&self, name: &str) -> Result<Author> {
-        self.conn
-            .execute("INSERT INTO authors (name) VALUES (?)", libsql::params![name])
-            .await?;
-        let mut rows = self
-            .conn
-            .query("SELECT id, name FROM authors WHERE name = ?", libsql::params![name])
-            .await?;
-        if let Some(row) = rows.next().await? {
-            Ok(Author {
-                id: row.get(0)?,
-                name: row.get(1)?,
-            })
-        } else {
-            anyhow::bail!("Failed to create author")
+    /// Applies a JSON Merge Patch to a book. Returns false if the book doesn't exist.
+    pub async fn patch_book(&self, book_id: i32, patch: &PatchBookRequest) -> Result<bool> {
+        let _guard = self.begin().await?;
+
+        let result = async {
+            let before = self.get_book_by_id(book_id).await?;
+            let found = self.patch_book_internal(book_id, patch).await?;
+            if let Some(before) = before.filter(|_| found) {
+                self.record_book_revisions(&before).await?;
+            }
+            Ok::<bool, anyhow::Error>(found)
         }
-    }
+        .await;
 
-    pub async fn create_tag(&self, name: &str) -> Result<Tag> {
-        self.conn
+        match result {
+            Ok(found) => {
+                self.commit().await?;
+                Ok(found)
+            }
+            Err(e) => {
+                let _ = self.rollback().await;
+                Err(e)
+            }
+        }
+    }
+
+    async fn patch_book_internal(&self, book_id: i32, patch: &PatchBookRequest) -> Result<bool> {
+        let mut set = SetClause::new();
+        set.set("title", &patch.title);
+        set.set("description", &patch.description);
+        set.set("cover_url", &patch.cover_url);
+        set.set("pages", &patch.pages);
+        set.set("ratings", &patch.ratings);
+        set.set("isbn", &patch.isbn);
+        set.set("publish_date", &patch.publish_date);
+        // The column is NOT NULL, so clearing it resets to the default
+        match &patch.reading_status {
+            Patch::Null => set.set("reading_status", &Patch::Value(ReadingStatus::default())),
+            status => set.set("reading_status", status),
+        }
+        match &patch.visibility {
+            Patch::Null => set.set("visibility", &Patch::Value(Visibility::default())),
+            visibility => set.set("visibility", visibility),
+        }
+        if !patch.cover_url.is_absent() {
+            // A hand-set cover is no longer the one we credited
+            set.set("cover_attribution", &Patch::<String>::Null);
+        }
+
+        // Touching only relations still bumps updated_at
+        let (query, params) = set.into_update("books", book_id);
+        if self.connection().execute(&query, params).await? == 0 {
+            return Ok(false);
+        }
+
+        let links = [
+            ("book_authors", "author_id", &patch.author_ids),
+            ("book_tags", "tag_id", &patch.tag_ids),
+            ("book_categories", "category_id", &patch.category_ids),
+        ];
+        for (table, column, ids) in links {
+            match ids {
+                Patch::Absent => {}
+                Patch::Null => self.replace_book_links(table, column, book_id, &[]).await?,
+                Patch::Value(ids) => self.replace_book_links(table, column, book_id, ids).await?,
+            }
+        }
+
+        Ok(true)
+    }
+
+    pub async fn create_author(&self, name: &str) -> Result<Author> {
+        self.connection()
+            .execute("INSERT INTO authors (name) VALUES (?)", libsql::params![name])
+            .await?;
+        let mut rows = self
+            .connection()
+            .query(&format!("SELECT {} FROM authors WHERE name = ?", AUTHOR_COLUMNS), libsql::params![name])
+            .await?;
+        if let Some(row) = rows.next().await? {
+            let author = Self::row_to_author(&row)?;
+            self.add_author_alias(author.id, &author.name).await?;
+            Ok(author)
+        } else {
+            anyhow::bail!("Failed to create author")
+        }
+    }
+
+    fn row_to_author(row: &libsql::Row) -> Result<Author> {
+        Ok(Author {
+            id: row.get(0)?,
+            name: row.get(1)?,
+            bio: row.get(2)?,
+            photo_url: row.get(3)?,
+            canonical_name: row.get(4)?,
+            birth_year: row.get(5)?,
+            authority_id: row.get(6)?,
+            aliases: vec![],
+        })
+    }
+
+    pub async fn get_author(&self, author_id: i32) -> Result<Option<Author>> {
+        let mut rows = self
+            .connection()
+            .query(&format!("SELECT {} FROM authors WHERE id = ?", AUTHOR_COLUMNS), libsql::params![author_id])
+            .await?;
+        let Some(row) = rows.next().await? else {
+            return Ok(None);
+        };
+        let mut author = Self::row_to_author(&row)?;
+        author.aliases = self
+            .get_author_aliases(author_id)
+            .await?
+            .into_iter()
+            .filter(|alias| *alias != author.name)
+            .collect();
+        Ok(Some(author))
+    }
+
+    /// Stores what an authority record says about the author. Its name and
+    /// alternate names become aliases, so later books under any of them link here.
+    pub async fn apply_author_authority(&self, author_id: i32, authority: &AuthorAuthority) -> Result<Option<Author>> {
+        let query = r#"
+            UPDATE authors
+            SET canonical_name = ?, birth_year = COALESCE(?, birth_year), authority_id = ?,
+                enriched_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
+            WHERE id = ?
+        "#;
+        let params = libsql::params![
+            authority.name.as_str(),
+            authority.birth_year,
+            authority.authority_id.as_str(),
+            author_id
+        ];
+        if self.connection().execute(query, params).await? == 0 {
+            return Ok(None);
+        }
+        for alias in std::iter::once(&authority.name).chain(&authority.alternate_names) {
+            self.add_author_alias(author_id, alias).await?;
+        }
+        self.get_author(author_id).await
+    }
+
+    fn author_filter(search: Option<&str>) -> (&'static str, Vec<libsql::Value>) {
+        match search.map(str::trim).filter(|s| !s.is_empty()) {
+            Some(search) => ("WHERE authors.name LIKE ?", vec![format!("%{}%", search).into()]),
+            None => ("", vec![]),
+        }
+    }
+
+    pub async fn count_authors(&self, search: Option<&str>) -> Result<u32> {
+        let (filter, values) = Self::author_filter(search);
+        let mut rows = self
+            .connection()
+            .query(&format!("SELECT COUNT(*) FROM authors {}", filter), values)
+            .await?;
+        match rows.next().await? {
+            Some(row) => Ok(row.get::<i32>(0)? as u32),
+            None => Ok(0),
+        }
+    }
+
+    /// Authors by name with the number of books in the library (trashed books excluded)
+    pub async fn list_authors(&self, search: Option<&str>, limit: u32, offset: u32) -> Result<Vec<AuthorAggregate>> {
+        let (filter, mut values) = Self::author_filter(search);
+        let query = format!(
+            r#"
+SELECT authors.id, authors.name, authors.bio, authors.photo_url, authors.canonical_name, authors.birth_year,
+    authors.authority_id, COUNT(books.id)
+FROM authors
+LEFT JOIN book_authors ON book_authors.author_id = authors.id
+LEFT JOIN books ON books.id = book_authors.book_id AND books.deleted_at IS NULL
+{}
+GROUP BY authors.id
+ORDER BY authors.name COLLATE NOCASE ASC
+LIMIT ? OFFSET ?
+"#,
+            filter
+        );
+        values.push(libsql::Value::from(limit as i32));
+        values.push(libsql::Value::from(offset as i32));
+
+        let mut rows = self.connection().query(&query, values).await?;
+        let mut authors = vec![];
+        while let Some(row) = rows.next().await? {
+            authors.push(AuthorAggregate {
+                author: Self::row_to_author(&row)?,
+                count: row.get(7)?,
+            });
+        }
+        Ok(authors)
+    }
+
+    pub async fn update_author(&self, author_id: i32, patch: &UpdateAuthorRequest) -> Result<Option<Author>> {
+        let mut set = SetClause::new();
+        set.set("name", &patch.name);
+        set.set("bio", &patch.bio);
+        set.set("photo_url", &patch.photo_url);
+
+        let (query, params) = set.into_update("authors", author_id);
+        if self.connection().execute(&query, params).await? == 0 {
+            return Ok(None);
+        }
+        if let Patch::Value(name) = &patch.name {
+            self.add_author_alias(author_id, name).await?;
+        }
+        self.get_author(author_id).await
+    }
+
+    /// Books linked to the author, including ones in the trash
+    pub async fn count_author_books(&self, author_id: i32) -> Result<i32> {
+        let mut rows = self
+            .connection()
+            .query("SELECT COUNT(*) FROM book_authors WHERE author_id = ?", libsql::params![author_id])
+            .await?;
+        match rows.next().await? {
+            Some(row) => Ok(row.get(0)?),
+            None => Ok(0),
+        }
+    }
+
+    /// Deletes the author and unlinks them from any books
+    pub async fn delete_author(&self, author_id: i32) -> Result<bool> {
+        let _guard = self.begin().await?;
+
+        let result = async {
+            self.connection()
+                .execute("DELETE FROM book_authors WHERE author_id = ?", libsql::params![author_id])
+                .await?;
+            self.connection()
+                .execute("DELETE FROM author_aliases WHERE author_id = ?", libsql::params![author_id])
+                .await?;
+            let deleted = self
+                .connection()
+                .execute("DELETE FROM authors WHERE id = ?", libsql::params![author_id])
+                .await?;
+            Ok::<bool, anyhow::Error>(deleted > 0)
+        }
+        .await;
+
+        match result {
+            Ok(deleted) => {
+                self.commit().await?;
+                Ok(deleted)
+            }
+            Err(e) => {
+                let _ = self.rollback().await;
+                Err(e)
+            }
+        }
+    }
+
+    pub async fn create_tag(&self, name: &str) -> Result<Tag> {
+        self.connection()
             .execute("INSERT INTO tags (name) VALUES (?)", libsql::params![name])
             .await?;
         let mut rows = self
-            .conn
+            .connection()
             .query("SELECT id, name FROM tags WHERE name = ?", libsql::params![name])
             .await?;
         if let Some(row) = rows.next().await? {
@@ -713,11 +1400,11 @@ GROUP BY books.id, books.title, books.url, books.cover_url, books.ratings
     }
 
     pub async fn create_category(&self, name: &str) -> Result<Category> {
-        self.conn
+        self.connection()
             .execute("INSERT INTO categories (name) VALUES (?)", libsql::params![name])
             .await?;
         let mut rows = self
-            .conn
+            .connection()
             .query("SELECT id, name FROM categories WHERE name = ?", libsql::params![name])
             .await?;
         if let Some(row) = rows.next().await? {
@@ -730,8 +1417,47 @@ GROUP BY books.id, books.title, books.url, books.cover_url, books.ratings
         }
     }
 
+    /// Sets the favorite flag, or flips it when `favorite` is `None`. Returns the
+    /// new value, or `None` if the book doesn't exist.
+    pub async fn set_book_favorite(&self, book_id: i32, favorite: Option<bool>) -> Result<Option<bool>> {
+        let query = r#"
+UPDATE books SET
+    is_favorite = COALESCE(?, 1 - is_favorite),
+    updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
+WHERE id = ?
+RETURNING is_favorite
+"#;
+        let mut rows = self
+            .connection()
+            .query(query, libsql::params![favorite.map(|f| f as i32), book_id])
+            .await?;
+        match rows.next().await? {
+            Some(row) => Ok(Some(row.get::<i32>(0)? != 0)),
+            None => Ok(None),
+        }
+    }
+
+    /// Records that the book was opened. Progress and position are kept from the
+    /// previous open unless given. Returns false if there is no such book.
+    pub async fn record_book_open(&self, book_id: i32, progress: Option<f64>, position: Option<&str>) -> Result<bool> {
+        let query = r#"
+INSERT INTO book_opens (book_id, open_count, progress, position)
+SELECT id, 1, ?, ? FROM books WHERE id = ? AND deleted_at IS NULL
+ON CONFLICT (book_id) DO UPDATE SET
+    open_count = open_count + 1,
+    last_opened_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now'),
+    progress = COALESCE(excluded.progress, progress),
+    position = COALESCE(excluded.position, position)
+"#;
+        let changed = self
+            .connection()
+            .execute(query, libsql::params![progress, position, book_id])
+            .await?;
+        Ok(changed > 0)
+    }
+
     pub async fn update_book_status(&self, book_id: i32, status: &str) -> Result<()> {
-        self.conn
+        self.connection()
             .execute(
                 "UPDATE books SET status = ?, updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now') WHERE id = ?",
                 libsql::params![status, book_id],
@@ -740,22 +1466,323 @@ GROUP BY books.id, books.title, books.url, books.cover_url, books.ratings
         Ok(())
     }
 
-    pub async fn delete_book(&self, book_id: i32) -> Result<()> {
-        let _guard = self.tx_lock.lock().await;
+    /// Id and last update of every public book that isn't in the trash, for the sitemap
+    pub async fn list_book_updates(&self) -> Result<Vec<(i32, String)>> {
+        let mut rows = self
+            .connection()
+            .query(
+                "SELECT id, updated_at FROM books WHERE deleted_at IS NULL AND visibility = 'public' ORDER BY id",
+                (),
+            )
+            .await?;
+        let mut books = Vec::new();
+        while let Some(row) = rows.next().await? {
+            books.push((row.get(0)?, row.get(1)?));
+        }
+        Ok(books)
+    }
+
+    pub async fn get_book_author_names(&self, book_id: i32) -> Result<Vec<String>> {
+        let query = r#"
+SELECT authors.name FROM authors
+JOIN book_authors ON book_authors.author_id = authors.id
+WHERE book_authors.book_id = ?
+ORDER BY authors.name
+"#;
+        let mut rows = self.connection().query(query, libsql::params![book_id]).await?;
+        let mut names = Vec::new();
+        while let Some(row) = rows.next().await? {
+            names.push(row.get::<String>(0)?);
+        }
+        Ok(names)
+    }
+
+    /// Shelf names keyed by book id, for every book that is on at least one shelf
+    pub async fn get_book_shelf_names(&self) -> Result<HashMap<i32, Vec<String>>> {
+        let query = r#"
+SELECT book_shelves.book_id, shelves.name FROM shelves
+JOIN book_shelves ON book_shelves.shelf_id = shelves.id
+ORDER BY shelves.name
+"#;
+        let mut rows = self.connection().query(query, ()).await?;
+        let mut shelves: HashMap<i32, Vec<String>> = HashMap::new();
+        while let Some(row) = rows.next().await? {
+            shelves.entry(row.get(0)?).or_default().push(row.get(1)?);
+        }
+        Ok(shelves)
+    }
+
+    /// Fills in only the fields that are still empty; anything set by hand or from
+    /// PDF metadata is left alone. A cover already used by another book is skipped
+    /// because `cover_url` is unique, and the attribution is only recorded alongside
+    /// a cover that was actually applied.
+    pub async fn apply_enrichment(&self, book_id: i32, found: &EnrichedMetadata) -> Result<()> {
+        let query = r#"
+UPDATE books SET
+    isbn = COALESCE(NULLIF(isbn, ''), ?1),
+    description = COALESCE(NULLIF(description, ''), ?2),
+    pages = COALESCE(NULLIF(pages, 0), ?3),
+    publish_date = COALESCE(NULLIF(publish_date, ''), ?4),
+    cover_attribution = CASE
+        WHEN NULLIF(cover_url, '') IS NULL
+            AND ?5 IS NOT NULL
+            AND NOT EXISTS (SELECT 1 FROM books WHERE cover_url = ?5 AND id != ?7)
+        THEN ?6
+        ELSE cover_attribution
+    END,
+    cover_url = COALESCE(
+        NULLIF(cover_url, ''),
+        (SELECT ?5 WHERE NOT EXISTS (SELECT 1 FROM books WHERE cover_url = ?5 AND id != ?7))
+    ),
+    updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
+WHERE id = ?7
+"#;
+        self.connection()
+            .execute(
+                query,
+                libsql::params![
+                    found.isbn.clone(),
+                    found.description.clone(),
+                    found.pages,
+                    found.publish_date.clone(),
+                    found.cover_url.clone(),
+                    found.cover_attribution.clone(),
+                    book_id
+                ],
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Returns the file url with its recorded size and SHA-256, if the book exists
+    pub async fn get_book_file(&self, book_id: i32) -> Result<Option<BookFile>> {
+        let mut rows = self
+            .connection()
+            .query("SELECT url, file_size, file_sha256 FROM books WHERE id = ?", libsql::params![book_id])
+            .await?;
+        match rows.next().await? {
+            Some(row) => Ok(Some(BookFile {
+                url: row.get(0)?,
+                size: row.get(1)?,
+                sha256: row.get(2)?,
+            })),
+            None => Ok(None),
+        }
+    }
+
+    pub async fn set_book_file_checksum(&self, book_id: i32, size: i64, sha256: &str) -> Result<()> {
+        self.connection()
+            .execute(
+                "UPDATE books SET file_size = ?, file_sha256 = ? WHERE id = ?",
+                libsql::params![size, sha256, book_id],
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Remembers that the parts of `upload_id` are sent straight to the bucket
+    pub async fn record_direct_upload(&self, upload_id: &str, file_size: i64) -> Result<()> {
+        self.connection()
+            .execute(
+                "INSERT OR REPLACE INTO direct_uploads (upload_id, file_size) VALUES (?, ?)",
+                libsql::params![upload_id, file_size],
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// The size declared for a direct upload, or None if its parts come through the server
+    pub async fn direct_upload_size(&self, upload_id: &str) -> Result<Option<i64>> {
+        let mut rows = self
+            .connection()
+            .query("SELECT file_size FROM direct_uploads WHERE upload_id = ?", libsql::params![upload_id])
+            .await?;
+        match rows.next().await? {
+            Some(row) => Ok(Some(row.get(0)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub async fn forget_direct_upload(&self, upload_id: &str) -> Result<()> {
+        self.connection()
+            .execute("DELETE FROM direct_uploads WHERE upload_id = ?", libsql::params![upload_id])
+            .await?;
+        Ok(())
+    }
+
+    /// Makes `url` the book's file, keeping the one it replaces as a version.
+    /// Returns false if the book doesn't exist.
+    pub async fn replace_book_file(
+        &self,
+        book_id: i32,
+        url: &str,
+        size: Option<i64>,
+        sha256: Option<&str>,
+    ) -> Result<bool> {
+        let _guard = self.begin().await?;
+
+        let result = async {
+            // The current file has been the book's since the last replacement, or
+            // since the book was created
+            let archive = r#"
+                INSERT INTO book_versions (book_id, url, file_size, file_sha256, uploaded_at)
+                SELECT id, url, file_size, file_sha256,
+                       COALESCE((SELECT MAX(replaced_at) FROM book_versions WHERE book_id = books.id), created_at)
+                FROM books
+                WHERE id = ?
+            "#;
+            if self.connection().execute(archive, libsql::params![book_id]).await? == 0 {
+                return Ok(false);
+            }
+            self.connection()
+                .execute(
+                    r#"
+                    UPDATE books
+                    SET url = ?, file_size = ?, file_sha256 = ?,
+                        updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
+                    WHERE id = ?
+                    "#,
+                    libsql::params![url, size, sha256, book_id],
+                )
+                .await?;
+            Ok::<_, anyhow::Error>(true)
+        }
+        .await;
+
+        match result {
+            Ok(replaced) => {
+                self.commit().await?;
+                Ok(replaced)
+            }
+            Err(e) => {
+                let _ = self.rollback().await;
+                Err(e)
+            }
+        }
+    }
+
+    /// Newest first
+    pub async fn list_book_versions(&self, book_id: i32) -> Result<Vec<BookVersion>> {
+        let query = r#"
+            SELECT id, book_id, url, file_size, file_sha256, uploaded_at, replaced_at
+            FROM book_versions
+            WHERE book_id = ?
+            ORDER BY id DESC
+        "#;
+        let mut rows = self.connection().query(query, libsql::params![book_id]).await?;
+        let mut versions = Vec::new();
+        while let Some(row) = rows.next().await? {
+            versions.push(Self::row_to_book_version(&row)?);
+        }
+        Ok(versions)
+    }
+
+    pub async fn get_book_version(&self, book_id: i32, version_id: i32) -> Result<Option<BookVersion>> {
+        let query = r#"
+            SELECT id, book_id, url, file_size, file_sha256, uploaded_at, replaced_at
+            FROM book_versions
+            WHERE book_id = ? AND id = ?
+        "#;
+        let mut rows = self
+            .connection()
+            .query(query, libsql::params![book_id, version_id])
+            .await?;
+        match rows.next().await? {
+            Some(row) => Ok(Some(Self::row_to_book_version(&row)?)),
+            None => Ok(None),
+        }
+    }
 
-        self.conn.execute("BEGIN TRANSACTION", ()).await?;
+    fn row_to_book_version(row: &libsql::Row) -> Result<BookVersion> {
+        Ok(BookVersion {
+            id: row.get(0)?,
+            book_id: row.get(1)?,
+            url: row.get(2)?,
+            size: row.get(3)?,
+            sha256: row.get(4)?,
+            uploaded_at: row.get(5)?,
+            replaced_at: row.get(6)?,
+        })
+    }
+
+    /// Books with the same title or ISBN as an upload but a different file, which the
+    /// upload may be a new version of. A file whose checksum isn't known counts as
+    /// different.
+    pub async fn find_version_candidates(
+        &self,
+        title: &str,
+        isbn: Option<&str>,
+        sha256: Option<&str>,
+    ) -> Result<Vec<Book>> {
+        let query = r#"
+            SELECT id
+            FROM books
+            WHERE deleted_at IS NULL
+              AND (lower(trim(title)) = lower(trim(?1)) OR (COALESCE(?2, '') != '' AND isbn = ?2))
+              AND (?3 IS NULL OR file_sha256 IS NULL OR file_sha256 != ?3)
+            ORDER BY id
+        "#;
+        let mut rows = self
+            .connection()
+            .query(query, libsql::params![title, isbn, sha256])
+            .await?;
+        let mut ids = Vec::new();
+        while let Some(row) = rows.next().await? {
+            ids.push(row.get::<i32>(0)?);
+        }
+        let mut books = Vec::new();
+        for id in ids {
+            if let Some(book) = self.get_book_by_id(id).await? {
+                books.push(book);
+            }
+        }
+        Ok(books)
+    }
+
+    /// Moves a book to the trash. Returns false if it doesn't exist or is already there.
+    pub async fn soft_delete_book(&self, book_id: i32) -> Result<bool> {
+        let query = r#"
+            UPDATE books
+            SET deleted_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
+            WHERE id = ? AND deleted_at IS NULL
+        "#;
+        Ok(self.connection().execute(query, libsql::params![book_id]).await? > 0)
+    }
+
+    /// Takes a book out of the trash. Returns false if it isn't in the trash.
+    pub async fn restore_book(&self, book_id: i32) -> Result<bool> {
+        let query = r#"
+            UPDATE books
+            SET deleted_at = NULL, updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
+            WHERE id = ? AND deleted_at IS NOT NULL
+        "#;
+        Ok(self.connection().execute(query, libsql::params![book_id]).await? > 0)
+    }
+
+    /// Permanently removes a book and its links
+    pub async fn delete_book(&self, book_id: i32) -> Result<()> {
+        let _guard = self.begin().await?;
 
         let result = async {
-            self.conn
+            self.connection()
                 .execute("DELETE FROM book_authors WHERE book_id = ?", libsql::params![book_id])
                 .await?;
-            self.conn
+            self.connection()
                 .execute("DELETE FROM book_tags WHERE book_id = ?", libsql::params![book_id])
                 .await?;
-            self.conn
+            self.connection()
                 .execute("DELETE FROM book_categories WHERE book_id = ?", libsql::params![book_id])
                 .await?;
-            self.conn
+            self.connection()
+                .execute("DELETE FROM book_shelves WHERE book_id = ?", libsql::params![book_id])
+                .await?;
+            self.connection()
+                .execute("DELETE FROM book_revisions WHERE book_id = ?", libsql::params![book_id])
+                .await?;
+            self.connection()
+                .execute("DELETE FROM book_versions WHERE book_id = ?", libsql::params![book_id])
+                .await?;
+            self.connection()
                 .execute("DELETE FROM books WHERE id = ?", libsql::params![book_id])
                 .await?;
             Ok::<(), anyhow::Error>(())
@@ -764,13 +1791,260 @@ GROUP BY books.id, books.title, books.url, books.cover_url, books.ratings
 
         match result {
             Ok(_) => {
-                self.conn.execute("COMMIT", ()).await?;
+                self.commit().await?;
                 Ok(())
             }
             Err(e) => {
-                let _ = self.conn.execute("ROLLBACK", ()).await;
+                let _ = self.rollback().await;
+                Err(e)
+            }
+        }
+    }
+
+    fn row_to_shelf(row: &libsql::Row) -> Result<Shelf> {
+        Ok(Shelf {
+            id: row.get(0)?,
+            name: row.get(1)?,
+            description: row.get(2)?,
+            book_count: row.get(3)?,
+            created_at: row.get(4)?,
+            updated_at: row.get(5)?,
+        })
+    }
+
+    pub async fn list_shelves(&self) -> Result<Vec<Shelf>> {
+        let query = r#"
+SELECT shelves.id, shelves.name, shelves.description, COUNT(book_shelves.id), shelves.created_at, shelves.updated_at
+FROM shelves
+LEFT JOIN book_shelves ON book_shelves.shelf_id = shelves.id
+GROUP BY shelves.id
+ORDER BY shelves.name ASC
+"#;
+        let mut rows = self.connection().query(query, ()).await?;
+        let mut shelves = vec![];
+        while let Some(row) = rows.next().await? {
+            shelves.push(Self::row_to_shelf(&row)?);
+        }
+        Ok(shelves)
+    }
+
+    pub async fn get_shelf(&self, shelf_id: i32) -> Result<Option<Shelf>> {
+        let query = r#"
+SELECT shelves.id, shelves.name, shelves.description, COUNT(book_shelves.id), shelves.created_at, shelves.updated_at
+FROM shelves
+LEFT JOIN book_shelves ON book_shelves.shelf_id = shelves.id
+WHERE shelves.id = ?
+GROUP BY shelves.id
+"#;
+        let mut rows = self.connection().query(query, libsql::params![shelf_id]).await?;
+        match rows.next().await? {
+            Some(row) => Ok(Some(Self::row_to_shelf(&row)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub async fn create_shelf(&self, name: &str, description: Option<&str>) -> Result<Shelf> {
+        let mut rows = self
+            .connection()
+            .query(
+                "INSERT INTO shelves (name, description) VALUES (?, ?) RETURNING id",
+                libsql::params![name, description],
+            )
+            .await?;
+        let shelf_id: i32 = match rows.next().await? {
+            Some(row) => row.get(0)?,
+            None => anyhow::bail!("Failed to create shelf"),
+        };
+        match self.get_shelf(shelf_id).await? {
+            Some(shelf) => Ok(shelf),
+            None => anyhow::bail!("Failed to create shelf"),
+        }
+    }
+
+    pub async fn update_shelf(
+        &self,
+        shelf_id: i32,
+        name: &Patch<String>,
+        description: &Patch<String>,
+    ) -> Result<Option<Shelf>> {
+        let mut set = SetClause::new();
+        set.set("name", name);
+        set.set("description", description);
+        let (query, params) = set.into_update("shelves", shelf_id);
+        self.connection().execute(&query, params).await?;
+        self.get_shelf(shelf_id).await
+    }
+
+    pub async fn delete_shelf(&self, shelf_id: i32) -> Result<bool> {
+        let _guard = self.begin().await?;
+
+        let result = async {
+            self.connection()
+                .execute("DELETE FROM book_shelves WHERE shelf_id = ?", libsql::params![shelf_id])
+                .await?;
+            let deleted = self
+                .connection()
+                .execute("DELETE FROM shelves WHERE id = ?", libsql::params![shelf_id])
+                .await?;
+            Ok::<bool, anyhow::Error>(deleted > 0)
+        }
+        .await;
+
+        match result {
+            Ok(deleted) => {
+                self.commit().await?;
+                Ok(deleted)
+            }
+            Err(e) => {
+                let _ = self.rollback().await;
                 Err(e)
             }
         }
     }
+
+    pub async fn get_books_by_shelf(&self, shelf_id: i32) -> Result<Vec<Book>> {
+        let query = r#"
+SELECT
+    books.id as book_id,
+    books.title,
+    books.url,
+    books.cover_url,
+    books.ratings,
+    books.description,
+    books.pages,
+    GROUP_CONCAT(DISTINCT CAST(authors.id AS TEXT)) as author_ids,
+    GROUP_CONCAT(DISTINCT CAST(tags.id AS TEXT)) as tag_ids,
+    GROUP_CONCAT(DISTINCT CAST(categories.id AS TEXT)) as category_ids,
+    books.isbn,
+    books.publish_date,
+    books.cover_attribution,
+    books.is_favorite,
+    books.reading_status,
+    books.deleted_at,
+    (SELECT COUNT(*) FROM annotations JOIN resources ON resources.id = annotations.resource_id
+     WHERE resources.book_id = books.id AND resources.deleted_at IS NULL AND annotations.deleted_at IS NULL) as annotation_count,
+    (SELECT COUNT(*) FROM notes JOIN resources ON resources.id = notes.resource_id
+     WHERE resources.book_id = books.id AND resources.deleted_at IS NULL AND notes.deleted_at IS NULL) as note_count,
+    (SELECT MAX(annotations.created_at) FROM annotations JOIN resources ON resources.id = annotations.resource_id
+     WHERE resources.book_id = books.id AND resources.deleted_at IS NULL AND annotations.deleted_at IS NULL) as last_annotated_at,
+    book_opens.last_opened_at,
+    book_opens.progress,
+    book_opens.position,
+    (SELECT json_group_object(kind, status)
+     FROM (SELECT kind, status FROM jobs WHERE jobs.book_id = books.id ORDER BY id)) as jobs,
+    books.visibility
+FROM books
+JOIN book_shelves ON book_shelves.book_id = books.id
+LEFT JOIN book_authors ON book_authors.book_id = books.id
+LEFT JOIN authors ON authors.id = book_authors.author_id
+LEFT JOIN book_tags ON book_tags.book_id = books.id
+LEFT JOIN tags ON tags.id = book_tags.tag_id
+LEFT JOIN book_categories ON book_categories.book_id = books.id
+LEFT JOIN categories ON categories.id = book_categories.category_id
+LEFT JOIN book_opens ON book_opens.book_id = books.id
+WHERE book_shelves.shelf_id = ? AND books.deleted_at IS NULL
+GROUP BY books.id, books.title, books.url, books.cover_url, books.ratings
+ORDER BY book_shelves.created_at DESC
+"#;
+
+        let mut rows = self.connection().query(query, libsql::params![shelf_id]).await?;
+        let mut books: Vec<Book> = vec![];
+        while let Some(row) = rows.next().await? {
+            books.push(Self::row_to_book(&row)?);
+        }
+        Ok(books)
+    }
+
+    /// Most recently opened books that are neither finished nor abandoned
+    pub async fn get_continue_reading(&self, limit: u32) -> Result<Vec<Book>> {
+        let query = r#"
+SELECT
+    books.id as book_id,
+    books.title,
+    books.url,
+    books.cover_url,
+    books.ratings,
+    books.description,
+    books.pages,
+    GROUP_CONCAT(DISTINCT CAST(authors.id AS TEXT)) as author_ids,
+    GROUP_CONCAT(DISTINCT CAST(tags.id AS TEXT)) as tag_ids,
+    GROUP_CONCAT(DISTINCT CAST(categories.id AS TEXT)) as category_ids,
+    books.isbn,
+    books.publish_date,
+    books.cover_attribution,
+    books.is_favorite,
+    books.reading_status,
+    books.deleted_at,
+    (SELECT COUNT(*) FROM annotations JOIN resources ON resources.id = annotations.resource_id
+     WHERE resources.book_id = books.id AND resources.deleted_at IS NULL AND annotations.deleted_at IS NULL) as annotation_count,
+    (SELECT COUNT(*) FROM notes JOIN resources ON resources.id = notes.resource_id
+     WHERE resources.book_id = books.id AND resources.deleted_at IS NULL AND notes.deleted_at IS NULL) as note_count,
+    (SELECT MAX(annotations.created_at) FROM annotations JOIN resources ON resources.id = annotations.resource_id
+     WHERE resources.book_id = books.id AND resources.deleted_at IS NULL AND annotations.deleted_at IS NULL) as last_annotated_at,
+    book_opens.last_opened_at,
+    book_opens.progress,
+    book_opens.position,
+    (SELECT json_group_object(kind, status)
+     FROM (SELECT kind, status FROM jobs WHERE jobs.book_id = books.id ORDER BY id)) as jobs,
+    books.visibility
+FROM books
+LEFT JOIN book_authors ON book_authors.book_id = books.id
+LEFT JOIN authors ON authors.id = book_authors.author_id
+LEFT JOIN book_tags ON book_tags.book_id = books.id
+LEFT JOIN tags ON tags.id = book_tags.tag_id
+LEFT JOIN book_categories ON book_categories.book_id = books.id
+LEFT JOIN categories ON categories.id = book_categories.category_id
+JOIN book_opens ON book_opens.book_id = books.id
+WHERE books.deleted_at IS NULL AND books.reading_status NOT IN ('finished', 'abandoned')
+GROUP BY books.id, books.title, books.url, books.cover_url, books.ratings
+ORDER BY book_opens.last_opened_at DESC
+LIMIT ?
+"#;
+
+        let mut rows = self.connection().query(query, libsql::params![limit]).await?;
+        let mut books: Vec<Book> = vec![];
+        while let Some(row) = rows.next().await? {
+            books.push(Self::row_to_book(&row)?);
+        }
+        Ok(books)
+    }
+
+    /// Puts the books on the shelf. If some of them aren't in the library, or are in
+    /// the trash, nothing is added and their ids are returned.
+    pub async fn add_books_to_shelf(&self, shelf_id: i32, book_ids: &[i32]) -> Result<Vec<i32>> {
+        self.write(|| async {
+            let mut missing = Vec::new();
+            for book_id in book_ids {
+                let mut rows = self
+                    .connection()
+                    .query("SELECT 1 FROM books WHERE id = ? AND deleted_at IS NULL", libsql::params![*book_id])
+                    .await?;
+                if rows.next().await?.is_none() && !missing.contains(book_id) {
+                    missing.push(*book_id);
+                }
+            }
+            if !missing.is_empty() {
+                return Ok(missing);
+            }
+
+            for book_id in book_ids {
+                self.connection()
+                    .execute(
+                        "INSERT OR IGNORE INTO book_shelves (book_id, shelf_id) VALUES (?, ?)",
+                        libsql::params![*book_id, shelf_id],
+                    )
+                    .await?;
+            }
+            Ok(missing)
+        })
+        .await
+    }
+
+    pub async fn remove_book_from_shelf(&self, shelf_id: i32, book_id: i32) -> Result<bool> {
+        let removed = self
+            .connection()
+            .execute("DELETE FROM book_shelves WHERE shelf_id = ? AND book_id = ?", libsql::params![shelf_id, book_id])
+            .await?;
+        Ok(removed > 0)
+    }
 }
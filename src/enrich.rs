@@ -0,0 +1,669 @@
+use crate::authors;
+use crate::db::Database;
+use crate::jobs::JobHandler;
+use crate::model::Author;
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+
+const OPENLIBRARY_URL: &str = "https://openlibrary.org";
+const OPENLIBRARY_COVERS_URL: &str = "https://covers.openlibrary.org";
+const GOOGLE_BOOKS_URL: &str = "https://www.googleapis.com/books/v1";
+const REQUEST_TIMEOUT_SECS: u64 = 10;
+
+/// What we know about a book before enrichment. An ISBN gives an exact match;
+/// otherwise title + author is used as a search query.
+#[derive(Debug, Clone)]
+pub struct EnrichQuery {
+    pub isbn: Option<String>,
+    pub title: String,
+    pub author: Option<String>,
+}
+
+/// Metadata found by a provider. Fields the provider doesn't know are `None`.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct EnrichedMetadata {
+    pub source: String,
+    pub isbn: Option<String>,
+    pub description: Option<String>,
+    pub pages: Option<i32>,
+    pub publish_date: Option<String>,
+    pub cover_url: Option<String>,
+    /// Credit for `cover_url`, shown wherever the cover is displayed
+    pub cover_attribution: Option<String>,
+}
+
+impl EnrichedMetadata {
+    fn is_empty(&self) -> bool {
+        self.description.is_none() && self.pages.is_none() && self.publish_date.is_none() && self.cover_url.is_none()
+    }
+}
+
+/// An Open Library author record matched to one of ours
+#[derive(Debug, Clone, Serialize)]
+pub struct AuthorAuthority {
+    /// Open Library author key, e.g. "OL26320A"
+    pub authority_id: String,
+    pub name: String,
+    pub birth_year: Option<i32>,
+    pub alternate_names: Vec<String>,
+}
+
+/// Looks up book metadata on OpenLibrary, falling back to Google Books when
+/// OpenLibrary has no match or nothing useful.
+pub struct Enricher {
+    client: reqwest::Client,
+    openlibrary_url: String,
+    google_books_url: String,
+}
+
+impl Enricher {
+    /// Fails if the HTTP client can't be built, rather than going on without the
+    /// request timeout
+    pub fn new() -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(REQUEST_TIMEOUT_SECS))
+            .user_agent(concat!("bibliotek/", env!("CARGO_PKG_VERSION")))
+            .build()?;
+        Ok(Self {
+            client,
+            openlibrary_url: OPENLIBRARY_URL.to_string(),
+            google_books_url: GOOGLE_BOOKS_URL.to_string(),
+        })
+    }
+
+    /// Points the lookups at other hosts, such as a local stand-in for the providers
+    #[cfg(test)]
+    fn with_urls(openlibrary_url: &str, google_books_url: &str) -> Self {
+        Self {
+            openlibrary_url: openlibrary_url.to_string(),
+            google_books_url: google_books_url.to_string(),
+            ..Self::new().expect("failed to build HTTP client")
+        }
+    }
+
+    pub async fn lookup(&self, query: &EnrichQuery) -> Result<Option<EnrichedMetadata>> {
+        match self.openlibrary(query).await {
+            Ok(Some(found)) if !found.is_empty() => return Ok(Some(found)),
+            Ok(_) => {}
+            Err(e) => tracing::warn!("openlibrary lookup failed: {}", e),
+        }
+
+        let found = self.google_books(query).await?;
+        Ok(found.filter(|m| !m.is_empty()))
+    }
+
+    /// Checks OpenLibrary answers a search
+    pub async fn ping_openlibrary(&self) -> Result<()> {
+        let url = format!("{}/search.json?q=bibliotek&limit=1", self.openlibrary_url);
+        self.client.get(&url).send().await?.error_for_status()?;
+        Ok(())
+    }
+
+    /// Checks Google Books answers a search
+    pub async fn ping_google_books(&self) -> Result<()> {
+        let url = format!("{}/volumes?q=bibliotek&maxResults=1", self.google_books_url);
+        self.client.get(&url).send().await?.error_for_status()?;
+        Ok(())
+    }
+
+    /// Searches Open Library's authors for `name`. Only a record whose name or one of
+    /// its alternate names normalizes to the same key is accepted, so a search that
+    /// merely ranks someone else first doesn't attach the wrong person.
+    pub async fn author_authority(&self, name: &str) -> Result<Option<AuthorAuthority>> {
+        let url = format!("{}/search/authors.json?limit=10&q={}", self.openlibrary_url, urlencoding::encode(name));
+        let resp: OpenLibraryAuthorSearch = self.client.get(&url).send().await?.error_for_status()?.json().await?;
+
+        let key = authors::name_key(name);
+        let Some(doc) = resp.docs.into_iter().find(|doc| {
+            std::iter::once(&doc.name)
+                .chain(&doc.alternate_names)
+                .any(|n| authors::name_key(n) == key)
+        }) else {
+            return Ok(None);
+        };
+
+        let authority_id = doc.key.trim_start_matches("/authors/").to_string();
+        Ok(Some(AuthorAuthority {
+            birth_year: doc.birth_date.as_deref().and_then(birth_year),
+            alternate_names: doc.alternate_names.into_iter().filter(|n| *n != doc.name).collect(),
+            name: doc.name,
+            authority_id,
+        }))
+    }
+
+    async fn openlibrary(&self, query: &EnrichQuery) -> Result<Option<EnrichedMetadata>> {
+        if let Some(isbn) = &query.isbn {
+            let url = format!("{}/isbn/{}.json", self.openlibrary_url, urlencoding::encode(isbn));
+            let resp = self.client.get(&url).send().await?;
+            if resp.status().is_success() {
+                let edition: OpenLibraryEdition = resp.json().await?;
+                let cover_url = edition
+                    .covers
+                    .first()
+                    .filter(|id| **id > 0)
+                    .map(|id| openlibrary_cover(*id));
+                let mut found = EnrichedMetadata {
+                    source: "openlibrary".to_string(),
+                    isbn: Some(isbn.clone()),
+                    description: edition.description.map(|d| d.into_text()),
+                    pages: edition.number_of_pages,
+                    publish_date: edition.publish_date,
+                    cover_attribution: cover_url.as_ref().map(|_| openlibrary_attribution(&url)),
+                    cover_url,
+                };
+                if found.description.is_none()
+                    && let Some(work) = edition.works.first()
+                {
+                    found.description = self.openlibrary_work_description(&work.key).await.unwrap_or(None);
+                }
+                return Ok(Some(found));
+            }
+        }
+
+        let mut url = format!(
+            "{}/search.json?limit=1&fields=key,isbn,number_of_pages_median,first_publish_year,cover_i&title={}",
+            self.openlibrary_url,
+            urlencoding::encode(&query.title)
+        );
+        if let Some(author) = &query.author {
+            url.push_str(&format!("&author={}", urlencoding::encode(author)));
+        }
+
+        let resp: OpenLibrarySearch = self.client.get(&url).send().await?.error_for_status()?.json().await?;
+        let Some(doc) = resp.docs.into_iter().next() else {
+            return Ok(None);
+        };
+
+        let description = match &doc.key {
+            Some(key) => self.openlibrary_work_description(key).await.unwrap_or(None),
+            None => None,
+        };
+
+        let cover_url = doc.cover_i.filter(|id| *id > 0).map(openlibrary_cover);
+        let page = match &doc.key {
+            Some(key) => format!("{}{}", self.openlibrary_url, key),
+            None => self.openlibrary_url.clone(),
+        };
+
+        Ok(Some(EnrichedMetadata {
+            source: "openlibrary".to_string(),
+            isbn: query.isbn.clone().or_else(|| doc.isbn.into_iter().next()),
+            description,
+            pages: doc.number_of_pages_median,
+            publish_date: doc.first_publish_year.map(|y| y.to_string()),
+            cover_attribution: cover_url.as_ref().map(|_| openlibrary_attribution(&page)),
+            cover_url,
+        }))
+    }
+
+    /// Open Library's covers API can serve a cover by ISBN even when the edition
+    /// record has none. `default=false` makes a missing cover a 404 instead of a
+    /// blank placeholder image.
+    pub async fn openlibrary_cover_by_isbn(&self, isbn: &str) -> Result<Option<String>> {
+        let cover_url = format!("{}/b/isbn/{}-L.jpg", OPENLIBRARY_COVERS_URL, urlencoding::encode(isbn));
+        let resp = self.client.head(format!("{}?default=false", cover_url)).send().await?;
+        Ok(resp.status().is_success().then_some(cover_url))
+    }
+
+    async fn openlibrary_work_description(&self, key: &str) -> Result<Option<String>> {
+        let url = format!("{}{}.json", self.openlibrary_url, key);
+        let work: OpenLibraryWork = self.client.get(&url).send().await?.error_for_status()?.json().await?;
+        Ok(work.description.map(|d| d.into_text()))
+    }
+
+    async fn google_books(&self, query: &EnrichQuery) -> Result<Option<EnrichedMetadata>> {
+        let q = match (&query.isbn, &query.author) {
+            (Some(isbn), _) => format!("isbn:{}", isbn),
+            (None, Some(author)) => format!("intitle:{} inauthor:{}", query.title, author),
+            (None, None) => format!("intitle:{}", query.title),
+        };
+        let url = format!("{}/volumes?maxResults=1&q={}", self.google_books_url, urlencoding::encode(&q));
+
+        let resp: GoogleVolumes = self.client.get(&url).send().await?.error_for_status()?.json().await?;
+        let Some(volume) = resp.items.into_iter().next() else {
+            return Ok(None);
+        };
+        let info = volume.volume_info;
+
+        let isbn = query.isbn.clone().or_else(|| {
+            let ids = &info.industry_identifiers;
+            ids.iter()
+                .find(|id| id.kind == "ISBN_13")
+                .or_else(|| ids.iter().find(|id| id.kind == "ISBN_10"))
+                .map(|id| id.identifier.clone())
+        });
+
+        let cover_url = info
+            .image_links
+            .and_then(|links| links.thumbnail)
+            .map(|u| u.replacen("http://", "https://", 1));
+        let page = info.info_link.as_deref().unwrap_or("https://books.google.com");
+
+        Ok(Some(EnrichedMetadata {
+            source: "google_books".to_string(),
+            isbn,
+            description: info.description,
+            pages: info.page_count.filter(|p| *p > 0),
+            publish_date: info.published_date,
+            cover_attribution: cover_url
+                .as_ref()
+                .map(|_| format!("Cover from Google Books ({})", page)),
+            cover_url,
+        }))
+    }
+}
+
+fn openlibrary_cover(id: i64) -> String {
+    format!("{}/b/id/{}-L.jpg", OPENLIBRARY_COVERS_URL, id)
+}
+
+/// Open Library asks that covers link back to the page they came from
+fn openlibrary_attribution(page: &str) -> String {
+    let page = page.strip_suffix(".json").unwrap_or(page);
+    format!("Cover from Open Library ({})", page)
+}
+
+/// OpenLibrary returns descriptions either as a plain string or as
+/// `{"type": "/type/text", "value": "..."}`.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum OpenLibraryText {
+    Plain(String),
+    Typed { value: String },
+}
+
+impl OpenLibraryText {
+    fn into_text(self) -> String {
+        match self {
+            OpenLibraryText::Plain(s) => s,
+            OpenLibraryText::Typed { value } => value,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenLibraryKey {
+    key: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenLibraryEdition {
+    description: Option<OpenLibraryText>,
+    number_of_pages: Option<i32>,
+    publish_date: Option<String>,
+    #[serde(default)]
+    covers: Vec<i64>,
+    #[serde(default)]
+    works: Vec<OpenLibraryKey>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenLibraryWork {
+    description: Option<OpenLibraryText>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenLibrarySearch {
+    #[serde(default)]
+    docs: Vec<OpenLibraryDoc>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenLibraryDoc {
+    key: Option<String>,
+    #[serde(default)]
+    isbn: Vec<String>,
+    number_of_pages_median: Option<i32>,
+    first_publish_year: Option<i32>,
+    cover_i: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenLibraryAuthorSearch {
+    #[serde(default)]
+    docs: Vec<OpenLibraryAuthorDoc>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenLibraryAuthorDoc {
+    key: String,
+    name: String,
+    #[serde(default)]
+    alternate_names: Vec<String>,
+    birth_date: Option<String>,
+}
+
+/// Open Library birth dates are free text ("10 January 1938", "1938", "c. 1564");
+/// the year is the last four-digit number in it
+fn birth_year(date: &str) -> Option<i32> {
+    date.split(|c: char| !c.is_ascii_digit())
+        .rfind(|part| part.len() == 4)
+        .and_then(|year| year.parse().ok())
+}
+
+#[derive(Debug, Deserialize)]
+struct GoogleVolumes {
+    #[serde(default)]
+    items: Vec<GoogleVolume>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GoogleVolume {
+    volume_info: GoogleVolumeInfo,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GoogleVolumeInfo {
+    description: Option<String>,
+    page_count: Option<i32>,
+    published_date: Option<String>,
+    image_links: Option<GoogleImageLinks>,
+    info_link: Option<String>,
+    #[serde(default)]
+    industry_identifiers: Vec<GoogleIdentifier>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GoogleImageLinks {
+    thumbnail: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GoogleIdentifier {
+    #[serde(rename = "type")]
+    kind: String,
+    identifier: String,
+}
+
+/// Looks up `book_id` and backfills whatever the providers know. `isbn` overrides
+/// the ISBN stored on the book, unless it normalizes to nothing. Returns `None` when neither provider had a match.
+pub async fn enrich_book(
+    db: &Database,
+    enricher: &Enricher,
+    book_id: i32,
+    isbn: Option<String>,
+) -> Result<Option<EnrichedMetadata>> {
+    let book = db
+        .get_book_by_id(book_id)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("book {} not found", book_id))?;
+    let authors = db.get_book_author_names(book_id).await?;
+
+    // An override with nothing of an ISBN in it falls back to the stored one
+    let isbn = isbn
+        .map(|i| normalize_isbn(&i))
+        .filter(|i| !i.is_empty())
+        .or_else(|| Some(normalize_isbn(&book.isbn)).filter(|i| !i.is_empty()));
+    let query = EnrichQuery {
+        isbn,
+        title: book.title,
+        author: authors.into_iter().next(),
+    };
+
+    let mut found = enricher.lookup(&query).await?;
+
+    let has_cover = !book.cover_url.is_empty() || found.as_ref().is_some_and(|f| f.cover_url.is_some());
+    if !has_cover && let Some(isbn) = &query.isbn {
+        match enricher.openlibrary_cover_by_isbn(isbn).await {
+            Ok(Some(cover_url)) => {
+                let found = found.get_or_insert_with(|| EnrichedMetadata {
+                    source: "openlibrary".to_string(),
+                    ..Default::default()
+                });
+                found.cover_url = Some(cover_url);
+                found.cover_attribution = Some(openlibrary_attribution(&format!("{}/isbn/{}", OPENLIBRARY_URL, isbn)));
+            }
+            Ok(None) => {}
+            Err(e) => tracing::warn!("openlibrary cover lookup failed: {}", e),
+        }
+    }
+
+    let Some(found) = found else {
+        return Ok(None);
+    };
+    db.write(|| db.apply_enrichment(book_id, &found)).await?;
+    Ok(Some(found))
+}
+
+pub const ENRICH_JOB: &str = "book.enrich";
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EnrichPayload {
+    pub book_id: i32,
+}
+
+/// Runs [`enrich_book`] for books queued after upload
+pub struct EnrichBookJob {
+    enricher: Arc<Enricher>,
+}
+
+impl EnrichBookJob {
+    pub fn new(enricher: Arc<Enricher>) -> Self {
+        Self { enricher }
+    }
+}
+
+#[async_trait]
+impl JobHandler for EnrichBookJob {
+    fn kind(&self) -> &'static str {
+        ENRICH_JOB
+    }
+
+    async fn run(&self, db: &Database, payload: &serde_json::Value) -> Result<()> {
+        let EnrichPayload { book_id } = serde_json::from_value(payload.clone())?;
+        match enrich_book(db, &self.enricher, book_id, None).await? {
+            Some(found) => tracing::info!("enriched book {} from {}", book_id, found.source),
+            None => tracing::info!("no enrichment metadata found for book {}", book_id),
+        }
+        Ok(())
+    }
+}
+
+/// Looks up `author_id` on Open Library and stores the matching authority record.
+/// Returns `None` when no record matches the author's name.
+pub async fn enrich_author(db: &Database, enricher: &Enricher, author_id: i32) -> Result<Option<Author>> {
+    let author = db
+        .get_author(author_id)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("author {} not found", author_id))?;
+    let Some(authority) = enricher.author_authority(&author.name).await? else {
+        return Ok(None);
+    };
+    db.apply_author_authority(author_id, &authority).await
+}
+
+fn normalize_isbn(isbn: &str) -> String {
+    isbn.chars()
+        .filter(|c| c.is_ascii_digit() || *c == 'X' || *c == 'x')
+        .collect::<String>()
+        .to_uppercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::PatchBookRequest;
+    use crate::patch::Patch;
+    use crate::test_support::test_db;
+    use axum::{Json, Router, extract::Query, http::StatusCode, routing::get};
+    use std::collections::HashMap;
+
+    /// Serves the handful of provider endpoints the lookups touch. Open Library
+    /// knows nothing about ISBNs and only finds "Known Title" by search; Google
+    /// Books answers every query.
+    async fn stub_providers() -> String {
+        let app = Router::new()
+            .route("/ol/isbn/:isbn", get(|| async { StatusCode::NOT_FOUND }))
+            .route(
+                "/ol/search.json",
+                get(|Query(q): Query<HashMap<String, String>>| async move {
+                    let docs = if q.get("title").map(String::as_str) == Some("Known Title") {
+                        serde_json::json!([{"key": "/works/OL1W", "number_of_pages_median": 320}])
+                    } else {
+                        serde_json::json!([])
+                    };
+                    Json(serde_json::json!({ "docs": docs }))
+                }),
+            )
+            .route(
+                "/ol/works/OL1W.json",
+                get(|| async {
+                    Json(serde_json::json!({"description": {"type": "/type/text", "value": "From Open Library"}}))
+                }),
+            )
+            .route(
+                "/gb/volumes",
+                get(|| async {
+                    Json(serde_json::json!({"items": [{"volumeInfo": {
+                        "description": "From Google Books",
+                        "pageCount": 200,
+                        "industryIdentifiers": [
+                            {"type": "ISBN_10", "identifier": "0000000000"},
+                            {"type": "ISBN_13", "identifier": "9780000000002"}
+                        ]
+                    }}]}))
+                }),
+            );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        format!("http://{}", addr)
+    }
+
+    fn query(title: &str, isbn: Option<&str>) -> EnrichQuery {
+        EnrichQuery {
+            isbn: isbn.map(str::to_string),
+            title: title.to_string(),
+            author: None,
+        }
+    }
+
+    #[test]
+    fn test_normalize_isbn() {
+        assert_eq!(normalize_isbn("978-0-14-044913-6"), "9780140449136");
+        assert_eq!(normalize_isbn(" 0-8044-2957-x "), "080442957X");
+        assert_eq!(normalize_isbn("ISBN: n/a"), "");
+    }
+
+    #[tokio::test]
+    async fn test_lookup_falls_back_to_google_books() {
+        let base = stub_providers().await;
+        let enricher = Enricher::with_urls(&format!("{}/ol", base), &format!("{}/gb", base));
+
+        let found = enricher.lookup(&query("Known Title", None)).await.unwrap().unwrap();
+        assert_eq!(found.source, "openlibrary");
+        assert_eq!(found.description.as_deref(), Some("From Open Library"));
+        assert_eq!(found.pages, Some(320));
+
+        // No ISBN edition and no search hit on Open Library
+        let found = enricher
+            .lookup(&query("Unknown Title", Some("9780140449136")))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(found.source, "google_books");
+        assert_eq!(found.description.as_deref(), Some("From Google Books"));
+        assert_eq!(found.isbn.as_deref(), Some("9780140449136"));
+
+        // Without an ISBN of our own, Google's ISBN-13 is preferred
+        let found = enricher.lookup(&query("Unknown Title", None)).await.unwrap().unwrap();
+        assert_eq!(found.isbn.as_deref(), Some("9780000000002"));
+    }
+
+    #[tokio::test]
+    async fn test_blank_isbn_override_falls_back_to_the_stored_isbn() {
+        let base = stub_providers().await;
+        let enricher = Enricher::with_urls(&format!("{}/ol", base), &format!("{}/gb", base));
+        let db = test_db().await;
+        // With a cover of its own, so no cover is looked up
+        let book_id = db
+            .create_book(
+                "Unknown Title",
+                "books/unknown.epub",
+                Some("https://covers.test/unknown.jpg"),
+                None,
+                None,
+                None,
+                &[],
+                &[],
+                &[],
+                "complete",
+            )
+            .await
+            .unwrap();
+        let patch = PatchBookRequest {
+            isbn: Patch::Value("9780140449136".to_string()),
+            ..Default::default()
+        };
+        db.patch_book(book_id, &patch).await.unwrap();
+
+        let found = enrich_book(&db, &enricher, book_id, Some("n/a".to_string()))
+            .await
+            .unwrap()
+            .unwrap();
+        // Looked up by the stored ISBN rather than without one
+        assert_eq!(found.isbn.as_deref(), Some("9780140449136"));
+    }
+
+    #[tokio::test]
+    async fn test_apply_enrichment_only_fills_gaps() {
+        let db = test_db().await;
+        let book_id = db
+            .create_book("Kept", "books/kept.epub", None, Some("Our own"), None, None, &[], &[], &[], "complete")
+            .await
+            .unwrap();
+        let other_id = db
+            .create_book(
+                "Other",
+                "books/other.epub",
+                Some("https://covers.test/taken.jpg"),
+                None,
+                None,
+                None,
+                &[],
+                &[],
+                &[],
+                "complete",
+            )
+            .await
+            .unwrap();
+
+        let found = EnrichedMetadata {
+            source: "openlibrary".to_string(),
+            isbn: Some("9780140449136".to_string()),
+            description: Some("Theirs".to_string()),
+            pages: Some(320),
+            publish_date: Some("1869".to_string()),
+            cover_url: Some("https://covers.test/taken.jpg".to_string()),
+            cover_attribution: Some("Cover from Open Library".to_string()),
+        };
+        db.apply_enrichment(book_id, &found).await.unwrap();
+
+        let book = db.get_book_by_id(book_id).await.unwrap().unwrap();
+        assert_eq!(book.description, "Our own");
+        assert_eq!(book.isbn, "9780140449136");
+        assert_eq!(book.pages, 320);
+        assert_eq!(book.publish_date, "1869");
+        // The cover already belongs to another book, so it isn't shared
+        assert_eq!(book.cover_url, "");
+        assert_eq!(book.cover_attribution, "");
+
+        let found = EnrichedMetadata {
+            pages: Some(999),
+            cover_url: Some("https://covers.test/fresh.jpg".to_string()),
+            ..found
+        };
+        db.apply_enrichment(book_id, &found).await.unwrap();
+        let book = db.get_book_by_id(book_id).await.unwrap().unwrap();
+        assert_eq!(book.pages, 320);
+        assert_eq!(book.cover_url, "https://covers.test/fresh.jpg");
+
+        let other = db.get_book_by_id(other_id).await.unwrap().unwrap();
+        assert_eq!(other.cover_url, "https://covers.test/taken.jpg");
+    }
+}
@@ -0,0 +1,145 @@
+use anyhow::{Context, Result};
+use quick_xml::Reader;
+use quick_xml::events::{BytesStart, Event};
+use std::io::{Cursor, Read};
+use zip::ZipArchive;
+
+/// Dublin Core metadata read from an EPUB's OPF package document
+#[derive(Debug, Default, Clone)]
+pub struct EpubMetadata {
+    pub title: Option<String>,
+    pub creators: Vec<String>,
+    pub subjects: Vec<String>,
+    pub description: Option<String>,
+    pub isbn: Option<String>,
+    pub publish_date: Option<String>,
+}
+
+pub fn is_epub(file_name: &str) -> bool {
+    file_name.to_lowercase().ends_with(".epub")
+}
+
+/// Extract OPF metadata from the raw bytes of an EPUB file
+pub fn extract_metadata_from_bytes(bytes: &[u8]) -> Result<EpubMetadata> {
+    let mut archive = ZipArchive::new(Cursor::new(bytes)).context("not a valid epub (zip) archive")?;
+
+    let container = read_entry(&mut archive, "META-INF/container.xml")?;
+    let opf_path = find_rootfile(&container).context("container.xml has no rootfile")?;
+    let opf = read_entry(&mut archive, &opf_path)?;
+
+    parse_opf(&opf)
+}
+
+fn read_entry(archive: &mut ZipArchive<Cursor<&[u8]>>, name: &str) -> Result<String> {
+    let mut file = archive
+        .by_name(name)
+        .with_context(|| format!("epub is missing {}", name))?;
+    let mut content = String::new();
+    file.read_to_string(&mut content)?;
+    Ok(content)
+}
+
+fn attr(e: &BytesStart, name: &[u8]) -> Option<String> {
+    e.attributes()
+        .flatten()
+        .find(|a| a.key.local_name().as_ref() == name)
+        .and_then(|a| a.unescape_value().ok())
+        .map(|v| v.into_owned())
+}
+
+fn find_rootfile(container: &str) -> Option<String> {
+    let mut reader = Reader::from_str(container);
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(e)) | Ok(Event::Empty(e)) if e.local_name().as_ref() == b"rootfile" => {
+                return attr(&e, b"full-path");
+            }
+            Ok(Event::Eof) | Err(_) => return None,
+            _ => {}
+        }
+    }
+}
+
+fn parse_opf(opf: &str) -> Result<EpubMetadata> {
+    let mut reader = Reader::from_str(opf);
+    reader.config_mut().trim_text(true);
+
+    let mut meta = EpubMetadata::default();
+    let mut in_metadata = false;
+    // Current Dublin Core element and, for identifiers, its scheme
+    let mut current: Option<(Vec<u8>, Option<String>)> = None;
+
+    loop {
+        match reader.read_event().context("failed to parse opf")? {
+            Event::Start(e) => {
+                let name = e.local_name().as_ref().to_vec();
+                if name == b"metadata" {
+                    in_metadata = true;
+                } else if in_metadata {
+                    current = Some((name, attr(&e, b"scheme")));
+                }
+            }
+            Event::End(e) => {
+                if e.local_name().as_ref() == b"metadata" {
+                    break;
+                }
+                current = None;
+            }
+            Event::Text(t) => {
+                let Some((name, scheme)) = &current else {
+                    continue;
+                };
+                let text = t.unescape()?.trim().to_string();
+                if text.is_empty() {
+                    continue;
+                }
+                match name.as_slice() {
+                    b"title" if meta.title.is_none() => meta.title = Some(text),
+                    b"creator" => meta.creators.push(text),
+                    b"subject" => meta.subjects.push(text),
+                    b"description" if meta.description.is_none() => meta.description = Some(text),
+                    b"date" if meta.publish_date.is_none() => meta.publish_date = Some(text),
+                    b"identifier" if meta.isbn.is_none() => meta.isbn = isbn_from_identifier(&text, scheme.as_deref()),
+                    _ => {}
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    Ok(meta)
+}
+
+fn isbn_from_identifier(value: &str, scheme: Option<&str>) -> Option<String> {
+    let lower = value.to_lowercase();
+    let raw = if let Some(rest) = lower.strip_prefix("urn:isbn:") {
+        rest.to_string()
+    } else if scheme.is_some_and(|s| s.eq_ignore_ascii_case("isbn")) {
+        lower
+    } else {
+        return None;
+    };
+    let isbn: String = raw
+        .chars()
+        .filter(|c| c.is_ascii_digit() || *c == 'x')
+        .collect::<String>()
+        .to_uppercase();
+    (isbn.len() == 10 || isbn.len() == 13).then_some(isbn)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::sample_epub;
+
+    #[test]
+    fn test_extract_metadata_from_bytes() {
+        let epub = sample_epub("Structure &amp; Interpretation", &["Harold Abelson", "Gerald Jay Sussman"]);
+        let meta = extract_metadata_from_bytes(&epub).unwrap();
+        assert_eq!(meta.title.as_deref(), Some("Structure & Interpretation"));
+        assert_eq!(meta.creators, vec!["Harold Abelson", "Gerald Jay Sussman"]);
+        assert_eq!(meta.subjects, vec!["Computer programming"]);
+        assert_eq!(meta.isbn.as_deref(), Some("9780262510875"));
+    }
+}
@@ -5,10 +5,14 @@ pub enum ObjectStorageError {
     UploadIdMissing,
     SessionAlreadyExists(String),
     SessionNotFound(String),
+    ObjectNotFound(String),
+    AccessDenied(String),
     S3Error(Box<dyn Error + Send + Sync + 'static>),
     EnvError(std::env::VarError),
     LockError(String),
     ETagMissing,
+    /// No bucket behind this instance
+    Unavailable(String),
 }
 
 impl std::error::Error for ObjectStorageError {
@@ -28,10 +32,13 @@ impl fmt::Display for ObjectStorageError {
             UploadIdMissing => write!(f, "UploadIdMissing"),
             SessionAlreadyExists(s) => write!(f, "SessionAlreadyExists: {}", s),
             SessionNotFound(s) => write!(f, "SessionNotFound: {}", s),
+            ObjectNotFound(s) => write!(f, "ObjectNotFound: {}", s),
+            AccessDenied(s) => write!(f, "AccessDenied: {}", s),
             S3Error(e) => write!(f, "S3Error: {}", e),
             EnvError(e) => write!(f, "EnvError: {}", e),
             LockError(s) => write!(f, "LockError: {}", s),
             ETagMissing => write!(f, "ETagMissing"),
+            Unavailable(s) => write!(f, "Unavailable: {}", s),
         }
     }
 }
@@ -46,6 +53,7 @@ impl From<std::env::VarError> for ObjectStorageError {
 pub enum HandlerError {
     ObjectStorageError(ObjectStorageError),
     ValidationError(String),
+    DatabaseError(anyhow::Error),
 }
 
 impl fmt::Display for HandlerError {
@@ -54,6 +62,7 @@ impl fmt::Display for HandlerError {
         match self {
             ObjectStorageError(s) => write!(f, "ObjectStorageError: {}", crate::unpack_error(s)),
             ValidationError(s) => write!(f, "ValidationError: {}", s),
+            DatabaseError(e) => write!(f, "DatabaseError: {}", e),
         }
     }
 }
@@ -73,3 +82,9 @@ impl From<ObjectStorageError> for HandlerError {
         HandlerError::ObjectStorageError(error)
     }
 }
+
+impl From<anyhow::Error> for HandlerError {
+    fn from(error: anyhow::Error) -> Self {
+        HandlerError::DatabaseError(error)
+    }
+}
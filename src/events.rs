@@ -0,0 +1,144 @@
+//! In-process domain events. Database and commonplace operations publish to the
+//! bus; features that react to changes (webhooks, live streams, exports) subscribe
+//! instead of being called from handlers.
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use tokio_util::sync::CancellationToken;
+
+/// Events buffered per subscriber before the slowest one starts missing events
+const DEFAULT_CAPACITY: usize = 256;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    BookCreated {
+        book_id: i32,
+        title: String,
+    },
+    ResourceCreated {
+        resource_id: i32,
+        resource_type: String,
+        title: String,
+    },
+    AnnotationUpserted {
+        annotation_id: i32,
+        resource_id: i32,
+        created: bool,
+        /// Server-relative link to the annotation, `/a/:public_id`
+        #[serde(default)]
+        permalink: String,
+    },
+    SyncCompleted {
+        source: String,
+        created: i32,
+        updated: i32,
+        deleted: i32,
+        /// What the sync answered, as its source's `SyncResponse`
+        #[serde(default)]
+        response: serde_json::Value,
+    },
+}
+
+impl Event {
+    /// The serialized `type` tag
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Event::BookCreated { .. } => "book_created",
+            Event::ResourceCreated { .. } => "resource_created",
+            Event::AnnotationUpserted { .. } => "annotation_upserted",
+            Event::SyncCompleted { .. } => "sync_completed",
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<Event>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+impl EventBus {
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity);
+        Self { sender }
+    }
+
+    /// Publishing never blocks or fails; with no subscribers the event is dropped.
+    pub fn publish(&self, event: Event) {
+        let _ = self.sender.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.sender.subscribe()
+    }
+
+    /// Traces every event at debug level until `cancel` fires
+    pub fn start_logger(&self, cancel: CancellationToken) {
+        let mut events = self.subscribe();
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    received = events.recv() => match received {
+                        Ok(event) => tracing::debug!(?event, "event"),
+                        Err(RecvError::Lagged(missed)) => tracing::warn!("event logger missed {} events", missed),
+                        Err(RecvError::Closed) => break,
+                    },
+                    _ = cancel.cancelled() => break,
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commonplace::{CreateAnnotation, CreateResource, ResourceType};
+    use crate::test_support::{seed_book, test_db};
+
+    #[tokio::test]
+    async fn test_operations_publish_events() {
+        let db = test_db().await;
+        let mut events = db.events().subscribe();
+
+        let book_id = seed_book(&db, "Gödel, Escher, Bach", &["Douglas Hofstadter"]).await;
+        let lib = db.commonplace();
+        let resource = lib
+            .create_resource(CreateResource {
+                title: "GEB".to_string(),
+                resource_type: ResourceType::Pdf,
+                external_id: None,
+                content_hash: None,
+            })
+            .await
+            .unwrap();
+        let annotation = lib
+            .create_annotation(CreateAnnotation {
+                resource_id: resource.id,
+                text: "strange loop".to_string(),
+                color: None,
+                boundary: None,
+                external_id: None,
+                content_hash: None,
+            })
+            .await
+            .unwrap();
+
+        assert!(matches!(events.recv().await.unwrap(), Event::BookCreated { book_id: id, .. } if id == book_id));
+        assert!(matches!(
+            events.recv().await.unwrap(),
+            Event::ResourceCreated { resource_id, .. } if resource_id == resource.id
+        ));
+        assert!(matches!(
+            events.recv().await.unwrap(),
+            Event::AnnotationUpserted { annotation_id, created: true, .. } if annotation_id == annotation.id
+        ));
+    }
+}
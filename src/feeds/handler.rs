@@ -0,0 +1,187 @@
+use anyhow::Result;
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+    response::Response,
+};
+use libsql::Connection;
+use serde::Deserialize;
+
+use super::poller;
+use super::store;
+use crate::commonplace::capture::is_capturable;
+use crate::handler::AppState;
+use crate::response::{bad_gateway, bad_request, conflict, internal_error, not_found, success};
+
+const DEFAULT_ENTRIES_LIMIT: i64 = 50;
+
+#[derive(Debug, Deserialize)]
+pub struct CreateFeedRequest {
+    pub url: String,
+    /// Snapshot the page of each new entry
+    #[serde(default)]
+    pub archive: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateFeedRequest {
+    pub title: Option<String>,
+    pub archive: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EntriesParams {
+    pub limit: Option<i64>,
+}
+
+pub async fn list_feeds(State(state): State<AppState>) -> Response {
+    match store::list_feeds(&state.db.connection()).await {
+        Ok(feeds) => success(feeds),
+        Err(e) => {
+            tracing::error!("Failed to list feeds: {}", e);
+            internal_error("Failed to list feeds")
+        }
+    }
+}
+
+pub async fn get_feed(State(state): State<AppState>, Path(id): Path<i64>) -> Response {
+    match store::get_feed(&state.db.connection(), id).await {
+        Ok(Some(feed)) => success(feed),
+        Ok(None) => not_found("Feed not found"),
+        Err(e) => {
+            tracing::error!("Failed to get feed {}: {}", id, e);
+            internal_error("Failed to get feed")
+        }
+    }
+}
+
+/// Registers a feed once it has been fetched and parsed. Its entries are added by the
+/// poller's next tick, the ones already in the feed included.
+pub async fn create_feed(State(state): State<AppState>, Json(req): Json<CreateFeedRequest>) -> Response {
+    let url = req.url.trim();
+    if !is_capturable(url) {
+        return bad_request("url must be an http(s) URL");
+    }
+    let title = match poller::fetch(&poller::client(), url, None, None).await {
+        Ok(fetched) => fetched.and_then(|fetched| fetched.feed.title),
+        Err(e) => {
+            tracing::warn!("Failed to fetch feed {}: {}", url, e);
+            return bad_gateway(&format!("Could not read a feed at {}: {}", url, e));
+        }
+    };
+
+    let conn = &state.db.connection();
+    match insert_feed(conn, url, title, req.archive).await {
+        Ok(Some(id)) => get_feed(State(state), Path(id)).await,
+        Ok(None) => conflict("Feed already registered"),
+        Err(e) => {
+            tracing::error!("Failed to register feed {}: {}", url, e);
+            internal_error("Failed to register feed")
+        }
+    }
+}
+
+async fn insert_feed(conn: &Connection, url: &str, title: Option<String>, archive: bool) -> Result<Option<i64>> {
+    let mut rows = conn
+        .query(
+            "INSERT INTO feeds (url, title, archive) VALUES (?, ?, ?) ON CONFLICT (url) DO NOTHING RETURNING id",
+            libsql::params![url, title, archive],
+        )
+        .await?;
+    match rows.next().await? {
+        Some(row) => Ok(Some(row.get(0)?)),
+        None => Ok(None),
+    }
+}
+
+pub async fn update_feed(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    Json(req): Json<UpdateFeedRequest>,
+) -> Response {
+    let title = req
+        .title
+        .map(|title| title.trim().to_string())
+        .filter(|title| !title.is_empty());
+    let result = state
+        .db
+        .connection()
+        .execute(
+            r#"
+            UPDATE feeds
+            SET title = COALESCE(?, title), archive = COALESCE(?, archive),
+                updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
+            WHERE id = ?
+            "#,
+            libsql::params![title, req.archive, id],
+        )
+        .await;
+    match result {
+        Ok(0) => not_found("Feed not found"),
+        Ok(_) => get_feed(State(state), Path(id)).await,
+        Err(e) => {
+            tracing::error!("Failed to update feed {}: {}", id, e);
+            internal_error("Failed to update feed")
+        }
+    }
+}
+
+/// Stops polling a feed. The resources its entries became stay.
+pub async fn delete_feed(State(state): State<AppState>, Path(id): Path<i64>) -> Response {
+    let conn = &state.db.connection();
+    let result = async {
+        conn.execute("DELETE FROM feed_entries WHERE feed_id = ?", libsql::params![id])
+            .await?;
+        conn.execute("DELETE FROM feeds WHERE id = ?", libsql::params![id])
+            .await
+    }
+    .await;
+    match result {
+        Ok(0) => not_found("Feed not found"),
+        Ok(_) => success(serde_json::json!({ "removed": id })),
+        Err(e) => {
+            tracing::error!("Failed to delete feed {}: {}", id, e);
+            internal_error("Failed to delete feed")
+        }
+    }
+}
+
+/// Has the poller fetch the feed on its next tick rather than when it is due
+pub async fn poll_feed(State(state): State<AppState>, Path(id): Path<i64>) -> Response {
+    let result = state
+        .db
+        .connection()
+        .execute("UPDATE feeds SET last_polled_at = NULL WHERE id = ?", libsql::params![id])
+        .await;
+    match result {
+        Ok(0) => not_found("Feed not found"),
+        Ok(_) => get_feed(State(state), Path(id)).await,
+        Err(e) => {
+            tracing::error!("Failed to schedule poll of feed {}: {}", id, e);
+            internal_error("Failed to schedule poll")
+        }
+    }
+}
+
+pub async fn list_entries(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    Query(params): Query<EntriesParams>,
+) -> Response {
+    let conn = &state.db.connection();
+    let limit = params.limit.unwrap_or(DEFAULT_ENTRIES_LIMIT).clamp(1, 500);
+    match store::get_feed(conn, id).await {
+        Ok(Some(_)) => match store::list_entries(conn, id, limit).await {
+            Ok(entries) => success(entries),
+            Err(e) => {
+                tracing::error!("Failed to list entries of feed {}: {}", id, e);
+                internal_error("Failed to list feed entries")
+            }
+        },
+        Ok(None) => not_found("Feed not found"),
+        Err(e) => {
+            tracing::error!("Failed to get feed {}: {}", id, e);
+            internal_error("Failed to get feed")
+        }
+    }
+}
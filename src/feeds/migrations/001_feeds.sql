@@ -0,0 +1,30 @@
+-- RSS and Atom feeds polled for new entries
+-- Each new entry becomes a website resource; `feed_entries` remembers which entries
+-- were seen so an entry is only added once, even after its resource is deleted.
+
+CREATE TABLE IF NOT EXISTS feeds (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    url TEXT NOT NULL UNIQUE,
+    title TEXT,
+    archive INTEGER NOT NULL DEFAULT 0, -- snapshot each new entry's page, see commonplace::snapshot
+    etag TEXT,
+    last_modified TEXT,
+    last_polled_at TEXT,
+    last_error TEXT,
+    created_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    updated_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))
+);
+
+CREATE TABLE IF NOT EXISTS feed_entries (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    feed_id INTEGER NOT NULL REFERENCES feeds(id) ON DELETE CASCADE,
+    guid TEXT NOT NULL, -- the entry's guid or id, else its link
+    url TEXT NOT NULL,
+    title TEXT,
+    published_at TEXT,
+    resource_id INTEGER REFERENCES resources(id) ON DELETE SET NULL,
+    created_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    UNIQUE (feed_id, guid)
+);
+
+CREATE INDEX IF NOT EXISTS idx_feed_entries_resource ON feed_entries(resource_id);
@@ -0,0 +1,23 @@
+//! RSS and Atom feeds as a reading inbox. Registered feeds (`POST /feeds`) are polled
+//! in the background every `app.feed_poll_interval_minutes`, and each entry not seen
+//! before becomes a website resource named by its link, or is matched to the one
+//! already there. With `archive` set on a feed, a snapshot of each new entry's page is
+//! queued too, see [`crate::commonplace::snapshot`].
+
+mod handler;
+mod parse;
+mod poller;
+mod routes;
+mod store;
+
+pub use poller::FeedPoller;
+pub use routes::routes;
+pub use store::{FeedSourceConfig, export_config, import_config};
+
+pub fn migrations() -> &'static [(&'static str, &'static str)] {
+    &[("feeds_001_feeds.sql", include_str!("migrations/001_feeds.sql"))]
+}
+
+pub fn down_migrations() -> &'static [(&'static str, &'static str)] {
+    &[("feeds_001_feeds.sql", include_str!("migrations/down/001_feeds.sql"))]
+}
@@ -0,0 +1,197 @@
+use anyhow::{Context, Result};
+use quick_xml::Reader;
+use quick_xml::events::{BytesStart, Event};
+
+/// What is kept of an RSS, RDF or Atom document
+#[derive(Debug, Default)]
+pub struct ParsedFeed {
+    pub title: Option<String>,
+    pub entries: Vec<FeedEntry>,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct FeedEntry {
+    /// `<guid>` or `<id>`, else the link
+    pub guid: String,
+    pub url: String,
+    pub title: Option<String>,
+    /// As the feed wrote it, RFC 822 for RSS and RFC 3339 for Atom
+    pub published_at: Option<String>,
+}
+
+/// Entry being read; fields stay empty until their element is seen
+#[derive(Default)]
+struct Partial {
+    guid: Option<String>,
+    link: Option<String>,
+    title: Option<String>,
+    published_at: Option<String>,
+}
+
+impl Partial {
+    /// None for an entry without a link, which has nothing to open
+    fn finish(self) -> Option<FeedEntry> {
+        let url = self.link.filter(|link| !link.is_empty())?;
+        Some(FeedEntry {
+            guid: self.guid.filter(|guid| !guid.is_empty()).unwrap_or_else(|| url.clone()),
+            url,
+            title: self.title.filter(|title| !title.is_empty()),
+            published_at: self.published_at,
+        })
+    }
+}
+
+fn attr(e: &BytesStart, name: &[u8]) -> Option<String> {
+    e.attributes()
+        .flatten()
+        .find(|a| a.key.local_name().as_ref() == name)
+        .and_then(|a| a.unescape_value().ok())
+        .map(|v| v.into_owned())
+}
+
+/// An Atom `<link>` pointing at the entry's page rather than a comment feed or enclosure
+fn is_alternate(e: &BytesStart) -> bool {
+    attr(e, b"rel").is_none_or(|rel| rel == "alternate")
+}
+
+/// Reads RSS 2.0, RSS 1.0 (RDF) and Atom alike: entries are `<item>` or `<entry>`
+/// elements wherever they are, and the feed's title is the first `<title>` outside them.
+pub fn parse(xml: &str) -> Result<ParsedFeed> {
+    let mut reader = Reader::from_str(xml);
+    reader.config_mut().trim_text(true);
+
+    let mut feed = ParsedFeed::default();
+    let mut entry: Option<Partial> = None;
+    // Element whose text is being read
+    let mut current: Option<Vec<u8>> = None;
+    let mut seen_root = false;
+
+    loop {
+        match reader.read_event().context("failed to parse feed")? {
+            Event::Start(e) => {
+                let name = e.local_name().as_ref().to_vec();
+                if !seen_root {
+                    if !matches!(name.as_slice(), b"rss" | b"RDF" | b"feed") {
+                        anyhow::bail!("not an RSS or Atom feed");
+                    }
+                    seen_root = true;
+                }
+                match name.as_slice() {
+                    b"item" | b"entry" => entry = Some(Partial::default()),
+                    b"link" if is_alternate(&e) => {
+                        if let (Some(entry), Some(href)) = (entry.as_mut(), attr(&e, b"href")) {
+                            entry.link.get_or_insert(href);
+                        }
+                    }
+                    _ => {}
+                }
+                current = Some(name);
+            }
+            Event::Empty(e) => {
+                if e.local_name().as_ref() == b"link"
+                    && is_alternate(&e)
+                    && let (Some(entry), Some(href)) = (entry.as_mut(), attr(&e, b"href"))
+                {
+                    entry.link.get_or_insert(href);
+                }
+            }
+            Event::End(e) => {
+                if matches!(e.local_name().as_ref(), b"item" | b"entry")
+                    && let Some(done) = entry.take().and_then(Partial::finish)
+                {
+                    feed.entries.push(done);
+                }
+                current = None;
+            }
+            Event::Text(t) => {
+                let Some(name) = &current else {
+                    continue;
+                };
+                let text = t.unescape()?.trim().to_string();
+                store_text(&mut feed, entry.as_mut(), name, text);
+            }
+            Event::CData(t) => {
+                let Some(name) = &current else {
+                    continue;
+                };
+                let text = String::from_utf8_lossy(&t.into_inner()).trim().to_string();
+                store_text(&mut feed, entry.as_mut(), name, text);
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    if !seen_root {
+        anyhow::bail!("not an RSS or Atom feed");
+    }
+    Ok(feed)
+}
+
+fn store_text(feed: &mut ParsedFeed, entry: Option<&mut Partial>, name: &[u8], text: String) {
+    if text.is_empty() {
+        return;
+    }
+    let Some(entry) = entry else {
+        if name == b"title" && feed.title.is_none() {
+            feed.title = Some(text);
+        }
+        return;
+    };
+    match name {
+        b"title" if entry.title.is_none() => entry.title = Some(text),
+        b"link" if entry.link.is_none() => entry.link = Some(text),
+        b"guid" | b"id" if entry.guid.is_none() => entry.guid = Some(text),
+        b"pubDate" | b"published" | b"date" => entry.published_at = Some(text),
+        b"updated" if entry.published_at.is_none() => entry.published_at = Some(text),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rss_and_atom_entries() {
+        let rss = r#"<?xml version="1.0"?>
+            <rss version="2.0"><channel>
+                <title>Example &amp; Co</title>
+                <link>https://example.com/</link>
+                <item>
+                    <title><![CDATA[On <em>Reading</em>]]></title>
+                    <link>https://example.com/reading</link>
+                    <guid isPermaLink="false">post-1</guid>
+                    <pubDate>Tue, 10 Jun 2025 04:00:00 GMT</pubDate>
+                </item>
+                <item><title>No link</title></item>
+                <item><link>https://example.com/untitled</link></item>
+            </channel></rss>"#;
+        let feed = parse(rss).unwrap();
+        assert_eq!(feed.title.as_deref(), Some("Example & Co"));
+        let guids: Vec<_> = feed.entries.iter().map(|e| e.guid.as_str()).collect();
+        assert_eq!(guids, ["post-1", "https://example.com/untitled"]);
+        assert_eq!(feed.entries[0].title.as_deref(), Some("On <em>Reading</em>"));
+        assert_eq!(feed.entries[0].url, "https://example.com/reading");
+
+        let atom = r#"<feed xmlns="http://www.w3.org/2005/Atom">
+                <title>Atom Example</title>
+                <link href="https://example.org/"/>
+                <entry>
+                    <title>Slow Reading</title>
+                    <link rel="replies" href="https://example.org/slow/comments"/>
+                    <link href="https://example.org/slow"/>
+                    <id>tag:example.org,2025:slow</id>
+                    <updated>2025-06-11T10:00:00Z</updated>
+                </entry>
+            </feed>"#;
+        let feed = parse(atom).unwrap();
+        assert_eq!(feed.title.as_deref(), Some("Atom Example"));
+        assert_eq!(feed.entries.len(), 1);
+        assert_eq!(feed.entries[0].url, "https://example.org/slow");
+        assert_eq!(feed.entries[0].guid, "tag:example.org,2025:slow");
+        assert_eq!(feed.entries[0].published_at.as_deref(), Some("2025-06-11T10:00:00Z"));
+
+        assert!(parse("<html><body>Not a feed</body></html>").is_err());
+    }
+}
@@ -0,0 +1,244 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use libsql::Connection;
+use reqwest::{StatusCode, header};
+use tokio_util::sync::CancellationToken;
+
+use super::parse::{self, ParsedFeed};
+use super::store::{self, Feed};
+use crate::commonplace::capture::is_capturable;
+use crate::commonplace::{compute_resource_hash, snapshot};
+use crate::db::Database;
+
+const REQUEST_TIMEOUT_SECS: u64 = 30;
+const TICK: Duration = Duration::from_secs(60);
+
+/// A feed as fetched, with the validators to send the next time
+pub struct Fetched {
+    pub feed: ParsedFeed,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+pub fn client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .timeout(Duration::from_secs(REQUEST_TIMEOUT_SECS))
+        .user_agent(concat!("bibliotek/", env!("CARGO_PKG_VERSION")))
+        .build()
+        .unwrap_or_default()
+}
+
+fn header_value(resp: &reqwest::Response, name: header::HeaderName) -> Option<String> {
+    resp.headers()
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string)
+}
+
+/// Fetches and parses a feed, sending the validators of the last fetch so an
+/// unchanged feed is not downloaded again. None when it had not changed.
+pub async fn fetch(
+    client: &reqwest::Client,
+    url: &str,
+    etag: Option<&str>,
+    last_modified: Option<&str>,
+) -> Result<Option<Fetched>> {
+    let mut request = client.get(url);
+    if let Some(etag) = etag {
+        request = request.header(header::IF_NONE_MATCH, etag);
+    }
+    if let Some(last_modified) = last_modified {
+        request = request.header(header::IF_MODIFIED_SINCE, last_modified);
+    }
+    let resp = request.send().await?;
+    if resp.status() == StatusCode::NOT_MODIFIED {
+        return Ok(None);
+    }
+    let resp = resp.error_for_status()?;
+    let etag = header_value(&resp, header::ETAG);
+    let last_modified = header_value(&resp, header::LAST_MODIFIED);
+    let feed = parse::parse(&resp.text().await?)?;
+    Ok(Some(Fetched {
+        feed,
+        etag,
+        last_modified,
+    }))
+}
+
+/// Polls registered feeds on an interval and adds their new entries as website
+/// resources
+pub struct FeedPoller {
+    db: Arc<Database>,
+    client: reqwest::Client,
+    interval_minutes: u64,
+    /// Whether every new website resource is snapshotted anyway (`app.snapshot_websites`)
+    snapshot_websites: bool,
+}
+
+impl FeedPoller {
+    pub fn new(db: Arc<Database>, interval_minutes: u64, snapshot_websites: bool) -> Self {
+        Self {
+            db,
+            client: client(),
+            interval_minutes,
+            snapshot_websites,
+        }
+    }
+
+    /// Polls one feed and returns how many new entries it had
+    pub async fn poll(&self, feed: &Feed) -> Result<usize> {
+        let fetched = fetch(&self.client, &feed.url, feed.etag.as_deref(), feed.last_modified.as_deref()).await;
+        let (added, title, etag, last_modified) = match fetched {
+            Ok(Some(fetched)) => {
+                (self.add_entries(feed, &fetched.feed).await, fetched.feed.title, fetched.etag, fetched.last_modified)
+            }
+            Ok(None) => (Ok(0), None, feed.etag.clone(), feed.last_modified.clone()),
+            Err(e) => (Err(e), None, feed.etag.clone(), feed.last_modified.clone()),
+        };
+        let error = added.as_ref().err().map(|e| e.to_string());
+        let query = r#"
+            UPDATE feeds
+            SET title = COALESCE(title, ?), etag = ?, last_modified = ?, last_error = ?,
+                last_polled_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now'),
+                updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
+            WHERE id = ?
+        "#;
+        let params = libsql::params![title, etag, last_modified, error, feed.id];
+        self.db
+            .write(|| async { Ok(self.db.connection().execute(query, params).await?) })
+            .await?;
+        added
+    }
+
+    /// Adds the entries not seen before, oldest first, in one transaction
+    async fn add_entries(&self, feed: &Feed, parsed: &ParsedFeed) -> Result<usize> {
+        let conn = self.db.connection();
+        self.db.write(|| self.add_new(&conn, feed, parsed)).await
+    }
+
+    async fn add_new(&self, conn: &Connection, feed: &Feed, parsed: &ParsedFeed) -> Result<usize> {
+        let lib = self.db.commonplace();
+        let mut added = 0;
+        // Feeds list the newest entry first
+        for entry in parsed.entries.iter().rev() {
+            let mut rows = conn
+                .query(
+                    "SELECT 1 FROM feed_entries WHERE feed_id = ? AND guid = ?",
+                    libsql::params![feed.id, entry.guid.clone()],
+                )
+                .await?;
+            if rows.next().await?.is_some() {
+                continue;
+            }
+
+            let (resource, created) = lib
+                .find_or_create_website(&entry.url, Some(compute_resource_hash(&entry.url)))
+                .await?;
+            conn.execute(
+                r#"
+                INSERT INTO feed_entries (feed_id, guid, url, title, published_at, resource_id)
+                VALUES (?, ?, ?, ?, ?, ?)
+                "#,
+                libsql::params![
+                    feed.id,
+                    entry.guid.clone(),
+                    entry.url.clone(),
+                    entry.title.clone(),
+                    entry.published_at.clone(),
+                    resource.id
+                ],
+            )
+            .await?;
+            // A new resource is already snapshotted when every website is
+            if feed.archive && is_capturable(&entry.url) && !(created && self.snapshot_websites) {
+                snapshot::queue(conn, resource.id, &entry.url).await?;
+            }
+            added += 1;
+        }
+        Ok(added)
+    }
+
+    /// Polls every feed that is due and returns how many new entries they had
+    pub async fn poll_due(&self) -> Result<usize> {
+        let mut added = 0;
+        for feed in store::due_feeds(&self.db.connection(), self.interval_minutes).await? {
+            match self.poll(&feed).await {
+                Ok(n) => added += n,
+                Err(e) => tracing::warn!("Failed to poll feed {}: {}", feed.url, e),
+            }
+        }
+        Ok(added)
+    }
+
+    pub fn start(self, cancel: CancellationToken) {
+        if self.interval_minutes == 0 {
+            tracing::info!("Feed poller disabled");
+            return;
+        }
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(TICK);
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => {
+                        match self.poll_due().await {
+                            Ok(0) => {}
+                            Ok(added) => tracing::info!("Added {} feed entries", added),
+                            Err(e) => tracing::warn!("Failed to poll feeds: {}", e),
+                        }
+                    }
+                    _ = cancel.cancelled() => {
+                        tracing::info!("Feed poller shutting down");
+                        break;
+                    }
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::test_db;
+
+    #[tokio::test]
+    async fn test_entries_are_added_once() {
+        let db = test_db().await;
+        let conn = &db.connection();
+        conn.execute("INSERT INTO feeds (url, archive) VALUES ('https://example.com/feed.xml', 1)", ())
+            .await
+            .unwrap();
+        let feed = store::get_feed(conn, 1).await.unwrap().unwrap();
+        let parsed = parse::parse(
+            r#"<rss><channel><title>Example</title>
+                <item><link>https://example.com/two</link><guid>2</guid></item>
+                <item><link>https://example.com/one</link><guid>1</guid></item>
+            </channel></rss>"#,
+        )
+        .unwrap();
+
+        let poller = FeedPoller::new(db.clone(), 60, false);
+        assert_eq!(poller.add_entries(&feed, &parsed).await.unwrap(), 2);
+        assert_eq!(poller.add_entries(&feed, &parsed).await.unwrap(), 0);
+
+        let entries = store::list_entries(conn, feed.id, 10).await.unwrap();
+        let urls: Vec<_> = entries.iter().map(|e| e.url.as_str()).collect();
+        assert_eq!(urls, ["https://example.com/two", "https://example.com/one"]);
+        let resource = db
+            .commonplace()
+            .get_resource(entries[0].resource_id.unwrap())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(resource.title, "https://example.com/two");
+
+        let mut rows = conn
+            .query("SELECT COUNT(*) FROM jobs WHERE kind = ?", libsql::params![snapshot::SNAPSHOT_JOB])
+            .await
+            .unwrap();
+        let queued: i64 = rows.next().await.unwrap().unwrap().get(0).unwrap();
+        assert_eq!(queued, 2);
+    }
+}
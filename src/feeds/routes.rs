@@ -0,0 +1,20 @@
+use axum::{
+    Router,
+    routing::{get, post},
+};
+
+use super::handler;
+use crate::handler::AppState;
+
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/", get(handler::list_feeds).post(handler::create_feed))
+        .route(
+            "/:id",
+            get(handler::get_feed)
+                .patch(handler::update_feed)
+                .delete(handler::delete_feed),
+        )
+        .route("/:id/entries", get(handler::list_entries))
+        .route("/:id/poll", post(handler::poll_feed))
+}
@@ -0,0 +1,145 @@
+use anyhow::Result;
+use libsql::Connection;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Feed {
+    pub id: i64,
+    pub url: String,
+    pub title: Option<String>,
+    /// Snapshot the page of each new entry
+    pub archive: bool,
+    #[serde(skip)]
+    pub etag: Option<String>,
+    #[serde(skip)]
+    pub last_modified: Option<String>,
+    pub last_polled_at: Option<String>,
+    /// Why the last poll failed; cleared by one that succeeds
+    pub last_error: Option<String>,
+    pub entries: i64,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+/// What a sync config archive keeps of a registered feed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeedSourceConfig {
+    pub url: String,
+    #[serde(default)]
+    pub title: Option<String>,
+    #[serde(default)]
+    pub archive: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StoredEntry {
+    pub id: i64,
+    pub guid: String,
+    pub url: String,
+    pub title: Option<String>,
+    pub published_at: Option<String>,
+    /// Unset once the resource is purged
+    pub resource_id: Option<i32>,
+    pub created_at: String,
+}
+
+const FEED_COLUMNS: &str = r#"
+    id, url, title, archive, etag, last_modified, last_polled_at, last_error,
+    (SELECT COUNT(*) FROM feed_entries WHERE feed_entries.feed_id = feeds.id), created_at, updated_at
+"#;
+
+fn row_to_feed(row: &libsql::Row) -> Result<Feed> {
+    Ok(Feed {
+        id: row.get(0)?,
+        url: row.get(1)?,
+        title: row.get(2)?,
+        archive: row.get::<i64>(3)? != 0,
+        etag: row.get(4)?,
+        last_modified: row.get(5)?,
+        last_polled_at: row.get(6)?,
+        last_error: row.get(7)?,
+        entries: row.get(8)?,
+        created_at: row.get(9)?,
+        updated_at: row.get(10)?,
+    })
+}
+
+async fn query_feeds(conn: &Connection, filter: &str, params: Vec<libsql::Value>) -> Result<Vec<Feed>> {
+    let query = format!("SELECT {} FROM feeds {} ORDER BY id", FEED_COLUMNS, filter);
+    let mut rows = conn.query(&query, params).await?;
+    let mut feeds = Vec::new();
+    while let Some(row) = rows.next().await? {
+        feeds.push(row_to_feed(&row)?);
+    }
+    Ok(feeds)
+}
+
+pub async fn list_feeds(conn: &Connection) -> Result<Vec<Feed>> {
+    query_feeds(conn, "", Vec::new()).await
+}
+
+pub async fn get_feed(conn: &Connection, id: i64) -> Result<Option<Feed>> {
+    Ok(query_feeds(conn, "WHERE id = ?", vec![id.into()]).await?.pop())
+}
+
+/// Feeds never polled, or last polled more than `interval_minutes` ago
+pub async fn due_feeds(conn: &Connection, interval_minutes: u64) -> Result<Vec<Feed>> {
+    let cutoff = format!("-{} minutes", interval_minutes);
+    query_feeds(
+        conn,
+        "WHERE last_polled_at IS NULL OR last_polled_at < strftime('%Y-%m-%dT%H:%M:%fZ', 'now', ?)",
+        vec![cutoff.into()],
+    )
+    .await
+}
+
+/// Every registered feed, for the sync config archive
+pub async fn export_config(conn: &Connection) -> Result<Vec<FeedSourceConfig>> {
+    Ok(list_feeds(conn)
+        .await?
+        .into_iter()
+        .map(|feed| FeedSourceConfig {
+            url: feed.url,
+            title: feed.title,
+            archive: feed.archive,
+        })
+        .collect())
+}
+
+/// Registers the feed, or updates the one with its URL. It isn't fetched first, unlike
+/// `POST /feeds`; the poller picks it up on its next tick.
+pub async fn import_config(conn: &Connection, config: &FeedSourceConfig) -> Result<()> {
+    let query = r#"
+        INSERT INTO feeds (url, title, archive) VALUES (?, ?, ?)
+        ON CONFLICT (url) DO UPDATE SET
+            title = COALESCE(excluded.title, feeds.title),
+            archive = excluded.archive,
+            updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
+    "#;
+    conn.execute(query, libsql::params![config.url.clone(), config.title.clone(), config.archive])
+        .await?;
+    Ok(())
+}
+
+/// Newest first
+pub async fn list_entries(conn: &Connection, feed_id: i64, limit: i64) -> Result<Vec<StoredEntry>> {
+    let query = r#"
+        SELECT id, guid, url, title, published_at, resource_id, created_at
+        FROM feed_entries WHERE feed_id = ?
+        ORDER BY id DESC LIMIT ?
+    "#;
+    let mut rows = conn.query(query, libsql::params![feed_id, limit]).await?;
+    let mut entries = Vec::new();
+    while let Some(row) = rows.next().await? {
+        entries.push(StoredEntry {
+            id: row.get(0)?,
+            guid: row.get(1)?,
+            url: row.get(2)?,
+            title: row.get(3)?,
+            published_at: row.get(4)?,
+            resource_id: row.get(5)?,
+            created_at: row.get(6)?,
+        });
+    }
+    Ok(entries)
+}
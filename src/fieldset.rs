@@ -0,0 +1,96 @@
+//! Sparse fieldsets (`?fields=id,text`) and relation expansion (`?include=comments`)
+//! for list endpoints. Handlers serialize items to JSON, attach the requested
+//! relations, then project down to the requested fields.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::HashSet;
+
+#[derive(Debug, Default, Deserialize)]
+pub struct FieldsetParams {
+    pub fields: Option<String>,
+    pub include: Option<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct Fieldset {
+    fields: Option<HashSet<String>>,
+    include: HashSet<String>,
+}
+
+fn split_list(s: &str) -> HashSet<String> {
+    s.split(',')
+        .map(|p| p.trim().to_string())
+        .filter(|p| !p.is_empty())
+        .collect()
+}
+
+impl Fieldset {
+    /// Parses the query params, rejecting includes the endpoint doesn't support.
+    pub fn parse(params: &FieldsetParams, allowed_includes: &[&str]) -> Result<Self, String> {
+        let fields = params.fields.as_deref().map(split_list).filter(|f| !f.is_empty());
+        let include = params.include.as_deref().map(split_list).unwrap_or_default();
+
+        if let Some(unknown) = include.iter().find(|i| !allowed_includes.contains(&i.as_str())) {
+            return Err(if allowed_includes.is_empty() {
+                format!("unknown include '{}': this endpoint has no expansions", unknown)
+            } else {
+                format!("unknown include '{}' (expected one of: {})", unknown, allowed_includes.join(", "))
+            });
+        }
+
+        Ok(Self { fields, include })
+    }
+
+    pub fn includes(&self, relation: &str) -> bool {
+        self.include.contains(relation)
+    }
+
+    /// True when the response can be serialized as-is
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_none() && self.include.is_empty()
+    }
+
+    pub fn to_value<T: Serialize>(item: &T) -> Map<String, Value> {
+        match serde_json::to_value(item) {
+            Ok(Value::Object(map)) => map,
+            _ => Map::new(),
+        }
+    }
+
+    /// Keeps only the requested fields plus any included relations.
+    pub fn project(&self, mut item: Map<String, Value>) -> Value {
+        if let Some(fields) = &self.fields {
+            item.retain(|k, _| fields.contains(k) || self.include.contains(k));
+        }
+        Value::Object(item)
+    }
+
+    pub fn project_all<T: Serialize>(&self, items: &[T]) -> Vec<Value> {
+        items.iter().map(|item| self.project(Self::to_value(item))).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_project_keeps_fields_and_includes() {
+        let params = FieldsetParams {
+            fields: Some("id, text".to_string()),
+            include: Some("comments".to_string()),
+        };
+        let fieldset = Fieldset::parse(&params, &["comments"]).unwrap();
+
+        let mut item = Fieldset::to_value(&serde_json::json!({"id": 1, "text": "t", "color": "yellow"}));
+        item.insert("comments".to_string(), Value::Array(vec![]));
+        assert_eq!(fieldset.project(item), serde_json::json!({"id": 1, "text": "t", "comments": []}));
+
+        let bad = FieldsetParams {
+            fields: None,
+            include: Some("words".to_string()),
+        };
+        assert!(Fieldset::parse(&bad, &["comments"]).is_err());
+    }
+}
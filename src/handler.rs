@@ -1,25 +1,58 @@
 use axum::{
     Json,
+    body::Body,
     extract::{Multipart, Path, Query, State},
-    http::StatusCode,
+    http::{HeaderMap, HeaderName, HeaderValue, StatusCode, header},
     response::{Html, IntoResponse, Response},
 };
+use futures_util::{StreamExt, stream};
 use std::fs;
 use std::sync::Arc;
 
 use tracing::info;
 
 use crate::{
-    api::{APIResponse, CreateEntityRequest, EntityResponse, PendingUploadsResponse, QueryParams, UpdateBookRequest, UploadInitResponse},
+    api::{
+        APIResponse, AttachVersionRequest, AuthorQueryParams, ContinueReadingQuery, CreateEntityRequest,
+        CreateShelfRequest, DeleteAuthorQuery, EnrichBookRequest, EntityResponse, ExportQuery, FavoriteRequest,
+        OpenBookRequest, PartUrl, PatchBookRequest, PendingUploadsResponse, QueryParams, ShelfBooksRequest,
+        UpdateAuthorRequest, UpdateBookRequest, UpdateShelfRequest, UploadInitResponse,
+    },
+    catalog::{self, CatalogEntry, CoverImage},
+    commonplace::{dictionary::Dictionary, is_unique_violation},
+    cover,
+    enrich::{self, EnrichedMetadata, Enricher},
+    epub_extract::{extract_metadata_from_bytes, is_epub},
+    jobs::{self, JobHandler},
+    model::{BookFile, ReadingStatus, Visibility},
+    object_store::{ObjectStore, ObjectStream},
+    patch::Patch,
     pdf_extract::{infer_category_from_metadata, parse_keywords},
-    resumable::ResumableUploadManager,
+    resumable::{ObjectInfo, ResumableUploadManager},
+    text_extract::TextExtraction,
+    titles::TitleCleaner,
 };
-use crate::{db::Database, error::HandlerError};
+use crate::{
+    db::Database,
+    error::{HandlerError, ObjectStorageError},
+};
+use sha2::{Digest, Sha256};
 
 #[derive(Clone)]
 pub struct AppState {
     pub db: Arc<Database>,
-    pub resumable: Arc<ResumableUploadManager>,
+    pub resumable: Arc<dyn ObjectStore>,
+    pub enricher: Arc<Enricher>,
+    pub enrich_on_upload: bool,
+    pub sync_diff_log: bool,
+    pub sync: Arc<crate::config::SyncSettings>,
+    pub dictionary: Arc<Dictionary>,
+    pub titles: Arc<TitleCleaner>,
+    pub retention: Arc<crate::config::Retention>,
+    pub public: Arc<crate::config::Public>,
+    pub extraction: Arc<TextExtraction>,
+    pub wallabag: Arc<crate::config::Wallabag>,
+    pub webhook: Arc<crate::config::Webhook>,
 }
 
 #[derive(Debug)]
@@ -36,8 +69,18 @@ pub struct Form {
     pub pdf_author: Option<String>,
     pub pdf_subject: Option<String>,
     pub pdf_keywords: Option<String>,
+    /// Book the completed upload is a new version of, rather than a book of its own
+    pub version_of: Option<i32>,
+    /// Have the client upload parts straight to the bucket through presigned URLs
+    pub direct: bool,
+    /// SHA-256 of the whole file, hex, to verify the assembled object against
+    pub sha256: Option<String>,
 }
 
+/// How long the presigned part URLs of a direct upload stay valid. Initializing the
+/// upload again resumes it with fresh URLs.
+const PART_URL_EXPIRY_SECS: u64 = 3600;
+
 const DEFAULT_PAGE: u32 = 1;
 const DEFAULT_LIMIT: u32 = 50;
 
@@ -48,19 +91,28 @@ pub struct HandlerParams {
     pub limit: u32,
     pub offset: u32,
     pub state: Option<String>,
+    pub favorite: Option<bool>,
+    pub reading_status: Option<ReadingStatus>,
+    pub visibility: Option<Visibility>,
+    /// List books in the trash instead of the library
+    pub trashed: bool,
 }
 
 impl QueryParams {
     pub fn into_handler_params(self) -> HandlerParams {
         let page = self.page.unwrap_or(DEFAULT_PAGE).max(1);
-        let limit = self.limit.unwrap_or(DEFAULT_LIMIT).max(1).min(DEFAULT_LIMIT);
+        let limit = self.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, DEFAULT_LIMIT);
 
         HandlerParams {
             query: self.q,
-            page: page,
-            limit: limit,
+            page,
+            limit,
             offset: (page - 1) * limit,
             state: self.state,
+            favorite: self.favorite,
+            reading_status: self.status,
+            visibility: self.visibility,
+            trashed: false,
         }
     }
 }
@@ -75,7 +127,7 @@ pub async fn healthcheck() -> impl IntoResponse {
 
 pub async fn get_books(State(state): State<AppState>, Query(qp): Query<QueryParams>) -> Response {
     let hp = qp.into_handler_params();
-    let search_query = hp.query.clone();
+    let total_books = state.db.count_books(&hp).await.ok();
     let db_call = state.db.get_books(hp).await;
 
     if let Err(e) = db_call {
@@ -83,8 +135,6 @@ pub async fn get_books(State(state): State<AppState>, Query(qp): Query<QueryPara
         return crate::bad_request(APIResponse::new_from_msg("failed to get books"));
     }
 
-    let total_books = state.db.count_books(search_query.as_deref()).await.ok();
-
     tracing::info!("got books");
     crate::good_response(APIResponse {
         books: db_call.ok().unwrap_or_default(),
@@ -124,6 +174,9 @@ async fn extract_form(multipart: &mut Multipart) -> Result<Form, HandlerError> {
         pdf_author: None,
         pdf_subject: None,
         pdf_keywords: None,
+        version_of: None,
+        direct: false,
+        sha256: None,
     };
 
     while let Ok(Some(field)) = multipart.next_field().await {
@@ -155,6 +208,17 @@ async fn extract_form(multipart: &mut Multipart) -> Result<Form, HandlerError> {
                 let val = crate::safe_parse_str("pdf_keywords", field).await?;
                 if !val.is_empty() { form.pdf_keywords = Some(val); }
             }
+            "version_of" => form.version_of = Some(crate::safe_parse_num("version_of", field).await?),
+            "sha256" => {
+                let val = crate::safe_parse_str("sha256", field).await?;
+                if !val.trim().is_empty() {
+                    form.sha256 = Some(val.trim().to_ascii_lowercase());
+                }
+            }
+            "direct" => {
+                let val = crate::safe_parse_str("direct", field).await?;
+                form.direct = matches!(val.trim(), "true" | "1");
+            }
             _ => {
                 tracing::warn!("unknown form field: {}", form_field_name);
                 continue;
@@ -177,12 +241,34 @@ async fn handle_init_upload(state: &AppState, multipart: &mut Multipart) -> Resu
     if form.file_name.is_empty() {
         return Err(HandlerError::ValidationError("file_name is required".to_string()));
     }
+    if let Some(sha256) = &form.sha256
+        && (sha256.len() != 64 || !sha256.chars().all(|c| c.is_ascii_hexdigit()))
+    {
+        return Err(HandlerError::ValidationError("sha256 must be 64 hex characters".to_string()));
+    }
 
     let init_response = state
         .resumable
-        .init_or_resume(&form.file_signature, &form.file_name, form.file_size)
+        .init_or_resume(&form.file_signature, &form.file_name, form.file_size, form.sha256.as_deref())
         .await?;
 
+    // Parts already uploaded are presigned too: the client only knows how many there
+    // are, and uploading a part again replaces it
+    let mut part_urls = Vec::new();
+    if form.direct {
+        state
+            .db
+            .record_direct_upload(&init_response.upload_id, form.file_size)
+            .await?;
+        for part_number in 1..=init_response.total_chunks as i32 {
+            let url = state
+                .resumable
+                .presign_upload_part(&init_response.upload_id, &init_response.key, part_number, PART_URL_EXPIRY_SECS)
+                .await?;
+            part_urls.push(PartUrl { part_number, url });
+        }
+    }
+
     Ok(UploadInitResponse {
         upload_id: init_response.upload_id,
         status: if init_response.is_resume { "resuming" } else { "ok" }.to_string(),
@@ -190,6 +276,7 @@ async fn handle_init_upload(state: &AppState, multipart: &mut Multipart) -> Resu
         total_chunks: init_response.total_chunks,
         completed_chunks: init_response.completed_chunks,
         key: Some(init_response.key),
+        part_urls,
     })
 }
 
@@ -267,6 +354,48 @@ pub async fn upload(
             return crate::bad_request(APIResponse::new_from_msg("upload_id and key are required"));
         }
 
+        // Parts uploaded directly never passed through here, so check the bucket has them all
+        let direct_size = match state.db.direct_upload_size(&form.upload_id).await {
+            Ok(size) => size,
+            Err(e) => {
+                tracing::error!("failed to get upload {}: {}", form.upload_id, e);
+                return crate::server_error(APIResponse::new_from_msg("failed to get upload"));
+            }
+        };
+        if let Some(declared) = direct_size
+            && form.file_size != declared
+        {
+            return crate::bad_request(APIResponse::new_from_msg(&format!(
+                "file_size is required to complete a direct upload, and must be the {} bytes declared at init",
+                declared
+            )));
+        }
+        if form.file_size > 0 {
+            match state.resumable.uploaded_bytes(&form.upload_id, &form.key).await {
+                Ok(uploaded) if uploaded != form.file_size => {
+                    return crate::bad_request(APIResponse::new_from_msg(&format!(
+                        "upload is incomplete: {} of {} bytes received",
+                        uploaded, form.file_size
+                    )));
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    tracing::error!("failed to check upload size: {}", e);
+                    return crate::server_error(APIResponse::new_from_msg(&format!(
+                        "failed to check upload size: {}",
+                        e
+                    )));
+                }
+            }
+        }
+
+        // Before anything is written: a 404 later on would roll it back
+        if let Some(book_id) = form.version_of
+            && let Err(response) = require_book(&state, book_id).await
+        {
+            return response;
+        }
+
         // Get filename from key
         let file_name = ResumableUploadManager::get_filename_from_key(&form.key)
             .unwrap_or_else(|| "unknown.pdf".to_string());
@@ -279,20 +408,55 @@ pub async fn upload(
                 return crate::server_error(APIResponse::new_from_msg(&format!("failed to complete upload: {}", e)));
             }
         };
+        if direct_size.is_some()
+            && let Err(e) = state.db.forget_direct_upload(&form.upload_id).await
+        {
+            tracing::warn!("failed to forget direct upload {}: {}", form.upload_id, e);
+        }
 
-        // Use client-provided metadata (extracted via pdf.js in browser)
-        let title_from_filename = || {
-            let without_ext = if let Some(dot_pos) = file_name.rfind('.') {
-                &file_name[..dot_pos]
-            } else {
-                &file_name
-            };
-            without_ext.replace('_', " ").replace('-', " ").trim().to_string()
+        // EPUBs carry their metadata in the OPF package, so it is read server-side
+        let epub = if is_epub(&file_name) {
+            match state.resumable.download_file(&form.key).await {
+                Ok(bytes) => Some(bytes),
+                Err(e) => {
+                    tracing::warn!("failed to download epub for metadata extraction: {}", e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        let verified = verify_upload_checksum(state.resumable.as_ref(), &form.key, epub.as_deref()).await;
+        let mut file_checksum = match verified {
+            Ok(checksum) => checksum,
+            Err(response) => return response,
         };
 
-        let title = match &form.pdf_title {
-            Some(t) if !t.trim().is_empty() => t.clone(),
-            _ => title_from_filename(),
+        let mut epub_meta = None;
+        if let Some(bytes) = &epub {
+            if file_checksum.is_none() {
+                file_checksum = Some((bytes.len() as i64, hex::encode(Sha256::digest(bytes))));
+            }
+            match extract_metadata_from_bytes(bytes) {
+                Ok(meta) => epub_meta = Some(meta),
+                Err(e) => tracing::warn!("failed to extract epub metadata: {}", e),
+            }
+        }
+        if let Some(book_id) = form.version_of {
+            return attach_upload(&state, book_id, &object_url, Some(&form.key), file_checksum).await;
+        }
+
+        let epub_subjects = epub_meta
+            .as_ref()
+            .filter(|m| !m.subjects.is_empty())
+            .map(|m| m.subjects.join(", "));
+
+        // Use client-provided metadata (extracted via pdf.js in browser)
+        let title = match (&form.pdf_title, epub_meta.as_ref().and_then(|m| m.title.as_ref())) {
+            (Some(t), _) if !t.trim().is_empty() => t.clone(),
+            (_, Some(t)) if !t.trim().is_empty() => t.clone(),
+            _ => state.titles.from_file_name(&file_name),
         };
 
         let author_names: Vec<String> = if let Some(author) = &form.pdf_author {
@@ -301,29 +465,53 @@ pub async fn upload(
                 .map(|s| s.trim().to_string())
                 .filter(|s| !s.is_empty())
                 .collect()
+        } else if let Some(meta) = &epub_meta {
+            meta.creators.clone()
         } else {
             vec![]
         };
 
-        let tag_names = if let Some(keywords) = &form.pdf_keywords {
+        let keywords = form.pdf_keywords.as_ref().or(epub_subjects.as_ref());
+        let tag_names = if let Some(keywords) = keywords {
             parse_keywords(keywords)
         } else {
             vec![]
         };
 
+        let subject = form.pdf_subject.as_ref().or(epub_subjects.as_ref());
         let mut category_names = vec![];
-        if let Some(category) =
-            infer_category_from_metadata(form.pdf_subject.as_deref(), form.pdf_keywords.as_deref())
+        if let Some(category) = infer_category_from_metadata(subject.map(|s| s.as_str()), keywords.map(|k| k.as_str()))
         {
             category_names.push(category);
         }
 
+        let description = epub_meta
+            .as_ref()
+            .and_then(|m| m.description.clone())
+            .or_else(|| form.pdf_subject.clone());
+
         tracing::info!(
             "Using client-provided metadata: title={:?}, author={:?}",
             form.pdf_title,
             form.pdf_author
         );
 
+        // Offered as versions to attach the upload to, rather than creating a duplicate
+        let isbn = epub_meta.as_ref().and_then(|m| m.isbn.as_deref());
+        let sha256 = file_checksum.as_ref().map(|(_, sha256)| sha256.as_str());
+        match state.db.find_version_candidates(&title, isbn, sha256).await {
+            Ok(matches) if !matches.is_empty() => {
+                return crate::good_response(APIResponse {
+                    status: "upload completed and matches an existing book".to_owned(),
+                    upload_id: Some(object_url),
+                    matches,
+                    ..Default::default()
+                });
+            }
+            Ok(_) => {}
+            Err(e) => tracing::warn!("failed to look for books {:?} may be a version of: {}", title, e),
+        }
+
         let mut created_book = None;
         match state
             .db
@@ -331,7 +519,7 @@ pub async fn upload(
                 &title,
                 &object_url,
                 None,
-                form.pdf_subject.as_deref(),
+                description.as_deref(),
                 None,
                 None,
                 &author_names,
@@ -343,9 +531,43 @@ pub async fn upload(
         {
             Ok(book_id) => {
                 tracing::info!("Created book with ID: {}", book_id);
+                if let Some(meta) = &epub_meta {
+                    let identifiers = EnrichedMetadata {
+                        source: "epub".to_string(),
+                        isbn: meta.isbn.clone(),
+                        publish_date: meta.publish_date.clone(),
+                        ..Default::default()
+                    };
+                    if let Err(e) = state.db.apply_enrichment(book_id, &identifiers).await {
+                        tracing::warn!("failed to store epub identifiers: {}", e);
+                    }
+                }
+                match file_checksum {
+                    Some((size, sha256)) => {
+                        if let Err(e) = state.db.set_book_file_checksum(book_id, size, &sha256).await {
+                            tracing::warn!("failed to store file checksum: {}", e);
+                        }
+                    }
+                    None => {
+                        let payload = ChecksumPayload {
+                            book_id,
+                            key: form.key.clone(),
+                        };
+                        if let Err(e) = jobs::enqueue(&state.db.connection(), CHECKSUM_JOB, &payload).await {
+                            tracing::warn!("failed to queue checksum for book {}: {}", book_id, e);
+                        }
+                    }
+                }
+                queue_object_tags(&state.db, book_id, &form.key).await;
                 if let Ok(Some(book)) = state.db.get_book_by_id(book_id).await {
                     created_book = Some(book);
                 }
+                if state.enrich_on_upload {
+                    let payload = enrich::EnrichPayload { book_id };
+                    if let Err(e) = jobs::enqueue(&state.db.connection(), enrich::ENRICH_JOB, &payload).await {
+                        tracing::warn!("failed to queue enrichment for book {}: {}", book_id, e);
+                    }
+                }
             }
             Err(e) => {
                 tracing::error!("Failed to create book record: {}", e);
@@ -369,6 +591,172 @@ pub async fn upload(
     (StatusCode::OK, Json(APIResponse::new_from_msg("Files uploaded successfully"))).into_response()
 }
 
+/// Hashes an assembled upload and checks it against the SHA-256 declared when it
+/// started, deleting the object if they differ. Returns its size and checksum if one
+/// was declared. `downloaded` is the object's body if the caller already has it,
+/// otherwise it is streamed from the store.
+async fn verify_upload_checksum(
+    store: &dyn ObjectStore,
+    key: &str,
+    downloaded: Option<&[u8]>,
+) -> Result<Option<(i64, String)>, Response> {
+    let failed = |e: ObjectStorageError| {
+        tracing::error!("failed to verify checksum of {}: {}", key, e);
+        crate::server_error(APIResponse::new_from_msg(&format!("failed to verify upload: {}", e)))
+    };
+    let Some(expected) = store.head(key).await.map_err(failed)?.sha256 else {
+        return Ok(None);
+    };
+
+    let mut hasher = Sha256::new();
+    let mut size = 0;
+    if let Some(bytes) = downloaded {
+        size = bytes.len() as i64;
+        hasher.update(bytes);
+    } else {
+        let (_, mut body) = store.stream_file(key).await.map_err(failed)?;
+        while let Some(chunk) = body.next().await {
+            let chunk = chunk.map_err(|e| failed(ObjectStorageError::S3Error(Box::new(e))))?;
+            size += chunk.len() as i64;
+            hasher.update(&chunk);
+        }
+    }
+    let actual = hex::encode(hasher.finalize());
+    if actual == expected {
+        return Ok(Some((size, actual)));
+    }
+
+    tracing::warn!("upload {} is corrupt: expected sha256 {}, got {}", key, expected, actual);
+    if let Err(e) = store.delete_object(key).await {
+        tracing::error!("failed to delete corrupt upload {}: {}", key, e);
+    }
+    Err(crate::bad_request(APIResponse::new_from_msg(&format!(
+        "checksum mismatch: expected sha256 {}, got {}; upload the file again",
+        expected, actual
+    ))))
+}
+
+/// Punctuation S3 allows in tag values, besides letters, digits and spaces
+const TAG_VALUE_PUNCTUATION: &[char] = &['_', '.', ':', '/', '=', '+', '-', '@'];
+const MAX_TAG_VALUE_CHARS: usize = 256;
+
+/// `value` with what S3 would reject in a tag replaced by spaces, and cut to length
+fn object_tag_value(value: &str) -> String {
+    let cleaned: String = value
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || TAG_VALUE_PUNCTUATION.contains(&c) {
+                c
+            } else {
+                ' '
+            }
+        })
+        .collect();
+    cleaned
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .chars()
+        .take(MAX_TAG_VALUE_CHARS)
+        .collect()
+}
+
+/// Tags a book's file with its id, title and categories, so bucket tooling and
+/// lifecycle rules can tell objects apart without the database
+async fn tag_book_object(db: &Database, store: &dyn ObjectStore, book_id: i32, key: &str) -> anyhow::Result<()> {
+    // Deleted before its turn came
+    let Some(book) = db.get_book_by_id(book_id).await? else {
+        return Ok(());
+    };
+    let mut tags = vec![("book_id".to_string(), book.id.to_string())];
+    let title = object_tag_value(&book.title);
+    if !title.is_empty() {
+        tags.push(("title".to_string(), title));
+    }
+    let categories = db.get_book_category_names(book.id).await?;
+    if !categories.is_empty() {
+        tags.push(("category".to_string(), object_tag_value(&categories.join(" / "))));
+    }
+    store.set_tags(key, &tags).await?;
+    Ok(())
+}
+
+/// Queues tagging of a book's file, which waits for the bucket, so the request
+/// doesn't hold the transaction lock for it
+async fn queue_object_tags(db: &Database, book_id: i32, key: &str) {
+    let payload = TagPayload {
+        book_id,
+        key: key.to_string(),
+    };
+    if let Err(e) = jobs::enqueue(&db.connection(), TAG_JOB, &payload).await {
+        tracing::warn!("failed to queue tagging of book {}: {}", book_id, e);
+    }
+}
+
+/// Answers 404 unless `book_id` is a book. Checked before a handler writes
+/// anything, since a 404 rolls the request's transaction back.
+async fn require_book(state: &AppState, book_id: i32) -> Result<(), Response> {
+    match state.db.get_book_by_id(book_id).await {
+        Ok(Some(_)) => Ok(()),
+        Ok(None) => Err(crate::not_found(APIResponse::new_from_msg("book not found"))),
+        Err(e) => {
+            tracing::error!("failed to get book {}: {}", book_id, e);
+            Err(crate::server_error(APIResponse::new_from_msg("failed to get book")))
+        }
+    }
+}
+
+/// Makes a completed upload the new file of `book_id`, keeping the old one as a version
+async fn attach_upload(
+    state: &AppState,
+    book_id: i32,
+    object_url: &str,
+    key: Option<&str>,
+    file_checksum: Option<(i64, String)>,
+) -> Response {
+    if let Err(response) = require_book(state, book_id).await {
+        return response;
+    }
+    let (size, sha256) = file_checksum.unzip();
+    match state
+        .db
+        .replace_book_file(book_id, object_url, size, sha256.as_deref())
+        .await
+    {
+        Ok(true) => {}
+        Ok(false) => {
+            tracing::error!("book {} went away while attaching an upload to it", book_id);
+            return crate::server_error(APIResponse::new_from_msg("failed to attach upload"));
+        }
+        Err(e) => {
+            tracing::error!("failed to attach upload to book {}: {}", book_id, e);
+            return crate::server_error(APIResponse::new_from_msg("failed to attach upload"));
+        }
+    }
+    if let (None, Some(key)) = (&sha256, key) {
+        let payload = ChecksumPayload {
+            book_id,
+            key: key.to_string(),
+        };
+        if let Err(e) = jobs::enqueue(&state.db.connection(), CHECKSUM_JOB, &payload).await {
+            tracing::warn!("failed to queue checksum for book {}: {}", book_id, e);
+        }
+    }
+
+    let mut response = APIResponse {
+        status: "upload completed and attached as a new version".to_owned(),
+        upload_id: Some(object_url.to_string()),
+        ..Default::default()
+    };
+    if let Some(key) = key {
+        queue_object_tags(&state.db, book_id, key).await;
+    }
+    if let Ok(Some(book)) = state.db.get_book_by_id(book_id).await {
+        response.books.push(book);
+    }
+    crate::good_response(response)
+}
+
 pub async fn get_pending_uploads(State(state): State<AppState>) -> Response {
     match state.resumable.list_pending().await {
         Ok(uploads) => {
@@ -399,6 +787,9 @@ pub async fn abort_upload(
 
     match state.resumable.abort(&form.upload_id, &form.key).await {
         Ok(()) => {
+            if let Err(e) = state.db.forget_direct_upload(&form.upload_id).await {
+                tracing::warn!("failed to forget direct upload {}: {}", form.upload_id, e);
+            }
             crate::good_response(APIResponse::new_from_msg("upload aborted"))
         }
         Err(e) => {
@@ -426,7 +817,14 @@ pub async fn update_book(
 ) -> Response {
     match state
         .db
-        .update_book(book_id, &payload.title, &payload.author_ids, &payload.tag_ids, &payload.category_ids)
+        .update_book(
+            book_id,
+            &payload.title,
+            &payload.author_ids,
+            &payload.tag_ids,
+            &payload.category_ids,
+            payload.reading_status,
+        )
         .await
     {
         Ok(_) => match state.db.get_book_by_id(book_id).await {
@@ -448,65 +846,1768 @@ pub async fn update_book(
     }
 }
 
-pub async fn create_author(State(state): State<AppState>, Json(payload): Json<CreateEntityRequest>) -> Response {
-    match state.db.create_author(&payload.name).await {
-        Ok(author) => (StatusCode::CREATED, Json(EntityResponse { entity: author })).into_response(),
+pub async fn patch_book(
+    State(state): State<AppState>,
+    Path(book_id): Path<i32>,
+    Json(payload): Json<PatchBookRequest>,
+) -> Response {
+    match &payload.title {
+        Patch::Null => return crate::bad_request(APIResponse::new_from_msg("title cannot be null")),
+        Patch::Value(title) if title.trim().is_empty() => {
+            return crate::bad_request(APIResponse::new_from_msg("title cannot be empty"));
+        }
+        _ => {}
+    }
+
+    match state.db.patch_book(book_id, &payload).await {
+        Ok(true) => match state.db.get_book_by_id(book_id).await {
+            Ok(Some(book)) => crate::good_response(APIResponse {
+                books: vec![book],
+                status: "ok".to_owned(),
+                ..Default::default()
+            }),
+            _ => crate::good_response(APIResponse::new_from_msg("book updated")),
+        },
+        Ok(false) => crate::not_found(APIResponse::new_from_msg("book not found")),
         Err(e) => {
-            tracing::error!("failed to create author: {}", e);
-            crate::bad_request(APIResponse::new_from_msg("failed to create author"))
+            tracing::error!("failed to patch book: {}", e);
+            crate::bad_request(APIResponse::new_from_msg("failed to update book"))
         }
     }
 }
 
-pub async fn create_tag(State(state): State<AppState>, Json(payload): Json<CreateEntityRequest>) -> Response {
-    match state.db.create_tag(&payload.name).await {
-        Ok(tag) => (StatusCode::CREATED, Json(EntityResponse { entity: tag })).into_response(),
+pub async fn get_book_history(State(state): State<AppState>, Path(book_id): Path<i32>) -> Response {
+    match state.db.get_book_by_id(book_id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return crate::not_found(APIResponse::new_from_msg("book not found")),
         Err(e) => {
-            tracing::error!("failed to create tag: {}", e);
-            crate::bad_request(APIResponse::new_from_msg("failed to create tag"))
+            tracing::error!("failed to fetch book {}: {}", book_id, e);
+            return crate::server_error(APIResponse::new_from_msg("failed to get book history"));
+        }
+    }
+
+    match state.db.get_book_revisions(book_id).await {
+        Ok(revisions) => crate::good_response(APIResponse {
+            revisions,
+            status: "ok".to_owned(),
+            ..Default::default()
+        }),
+        Err(e) => {
+            tracing::error!("failed to get book history: {}", e);
+            crate::server_error(APIResponse::new_from_msg("failed to get book history"))
         }
     }
 }
 
-pub async fn create_category(State(state): State<AppState>, Json(payload): Json<CreateEntityRequest>) -> Response {
-    match state.db.create_category(&payload.name).await {
-        Ok(category) => (StatusCode::CREATED, Json(EntityResponse { entity: category })).into_response(),
+pub async fn get_book_versions(State(state): State<AppState>, Path(book_id): Path<i32>) -> Response {
+    match state.db.get_book_by_id(book_id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return crate::not_found(APIResponse::new_from_msg("book not found")),
         Err(e) => {
-            tracing::error!("failed to create category: {}", e);
-            crate::bad_request(APIResponse::new_from_msg("failed to create category"))
+            tracing::error!("failed to fetch book {}: {}", book_id, e);
+            return crate::server_error(APIResponse::new_from_msg("failed to list book versions"));
+        }
+    }
+
+    match state.db.list_book_versions(book_id).await {
+        Ok(versions) => crate::good_response(APIResponse {
+            versions,
+            status: "ok".to_owned(),
+            ..Default::default()
+        }),
+        Err(e) => {
+            tracing::error!("failed to list book versions: {}", e);
+            crate::server_error(APIResponse::new_from_msg("failed to list book versions"))
         }
     }
 }
 
-#[derive(serde::Deserialize)]
-pub struct DownloadQuery {
-    pub key: String,
+/// Attaches an upload that matched the book, found by its object key, as the book's
+/// new version
+pub async fn attach_book_version(
+    State(state): State<AppState>,
+    Path(book_id): Path<i32>,
+    Json(payload): Json<AttachVersionRequest>,
+) -> Response {
+    if payload.key.trim().is_empty() {
+        return crate::bad_request(APIResponse::new_from_msg("key is required"));
+    }
+    if let Err(e) = state.resumable.head(&payload.key).await {
+        return storage_error_response(&payload.key, e);
+    }
+    let url = state.resumable.get_file_url(&payload.key);
+    attach_upload(&state, book_id, &url, Some(&payload.key), None).await
 }
 
-#[derive(serde::Serialize)]
-pub struct DownloadResponse {
-    pub url: String,
+/// Background jobs run for a book, most recent first, so a missing cover or
+/// metadata can be traced to the job that failed
+pub async fn get_book_jobs(State(state): State<AppState>, Path(book_id): Path<i32>) -> Response {
+    match state.db.get_book_by_id(book_id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return crate::not_found(APIResponse::new_from_msg("book not found")),
+        Err(e) => {
+            tracing::error!("failed to fetch book {}: {}", book_id, e);
+            return crate::server_error(APIResponse::new_from_msg("failed to get book jobs"));
+        }
+    }
+
+    match jobs::list_book_jobs(&state.db.connection(), book_id).await {
+        Ok(jobs) => crate::good_response(APIResponse {
+            jobs,
+            status: "ok".to_owned(),
+            ..Default::default()
+        }),
+        Err(e) => {
+            tracing::error!("failed to get book jobs: {}", e);
+            crate::server_error(APIResponse::new_from_msg("failed to get book jobs"))
+        }
+    }
 }
 
-pub async fn get_download_url(
+/// Queues the book's failed jobs again and returns them
+pub async fn retry_book_jobs(State(state): State<AppState>, Path(book_id): Path<i32>) -> Response {
+    match jobs::requeue_failed(&state.db.connection(), None, Some(book_id)).await {
+        Ok(jobs) => crate::good_response(APIResponse {
+            status: format!("{} jobs queued", jobs.len()),
+            jobs,
+            ..Default::default()
+        }),
+        Err(e) => {
+            tracing::error!("failed to retry jobs for book {}: {}", book_id, e);
+            crate::server_error(APIResponse::new_from_msg("failed to retry book jobs"))
+        }
+    }
+}
+
+pub async fn set_favorite(
     State(state): State<AppState>,
-    Query(query): Query<DownloadQuery>,
+    Path(book_id): Path<i32>,
+    payload: Option<Json<FavoriteRequest>>,
 ) -> Response {
-    // Generate presigned URL valid for 1 hour
-    match state.resumable.get_presigned_url(&query.key, 3600).await {
-        Ok(url) => (StatusCode::OK, Json(DownloadResponse { url })).into_response(),
+    let favorite = payload.and_then(|Json(p)| p.is_favorite);
+    match state.db.set_book_favorite(book_id, favorite).await {
+        Ok(Some(_)) => match state.db.get_book_by_id(book_id).await {
+            Ok(Some(book)) => crate::good_response(APIResponse {
+                books: vec![book],
+                status: "ok".to_owned(),
+                ..Default::default()
+            }),
+            _ => crate::good_response(APIResponse::new_from_msg("favorite updated")),
+        },
+        Ok(None) => crate::not_found(APIResponse::new_from_msg("book not found")),
         Err(e) => {
-            tracing::error!("failed to generate download url: {}", e);
-            crate::server_error(APIResponse::new_from_msg(&format!(
-                "failed to generate download url: {}",
-                e
-            )))
+            tracing::error!("failed to update favorite: {}", e);
+            crate::server_error(APIResponse::new_from_msg("failed to update favorite"))
         }
     }
 }
 
-#[cfg(test)]
-mod tests {
-    #[test]
-    fn test_params_deserialize() {}
+/// Records that the reader opened the book, optionally with how far in they are
+pub async fn open_book(
+    State(state): State<AppState>,
+    Path(book_id): Path<i32>,
+    payload: Option<Json<OpenBookRequest>>,
+) -> Response {
+    let payload = payload.map(|Json(p)| p).unwrap_or_default();
+    if payload.progress.is_some_and(|p| !(0.0..=1.0).contains(&p)) {
+        return crate::bad_request(APIResponse::new_from_msg("progress must be between 0 and 1"));
+    }
+
+    match state
+        .db
+        .record_book_open(book_id, payload.progress, payload.position.as_deref())
+        .await
+    {
+        Ok(true) => match state.db.get_book_by_id(book_id).await {
+            Ok(Some(book)) => crate::good_response(APIResponse {
+                books: vec![book],
+                status: "ok".to_owned(),
+                ..Default::default()
+            }),
+            _ => crate::good_response(APIResponse::new_from_msg("book opened")),
+        },
+        Ok(false) => crate::not_found(APIResponse::new_from_msg("book not found")),
+        Err(e) => {
+            tracing::error!("failed to record book open: {}", e);
+            crate::server_error(APIResponse::new_from_msg("failed to record book open"))
+        }
+    }
+}
+
+const DEFAULT_CONTINUE_READING: u32 = 10;
+const MAX_CONTINUE_READING: u32 = 50;
+
+/// Recently opened books that are still in progress, most recent first
+pub async fn continue_reading(State(state): State<AppState>, Query(query): Query<ContinueReadingQuery>) -> Response {
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_CONTINUE_READING)
+        .clamp(1, MAX_CONTINUE_READING);
+    match state.db.get_continue_reading(limit).await {
+        Ok(books) => crate::good_response(APIResponse {
+            total_books: Some(books.len() as u32),
+            books,
+            status: "ok".to_owned(),
+            ..Default::default()
+        }),
+        Err(e) => {
+            tracing::error!("failed to get continue reading: {}", e);
+            crate::server_error(APIResponse::new_from_msg("failed to get continue reading"))
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Default)]
+pub struct DeleteBookQuery {
+    /// Remove the book for good instead of moving it to the trash
+    #[serde(default)]
+    pub permanent: bool,
+}
+
+pub async fn delete_book(
+    State(state): State<AppState>,
+    Path(book_id): Path<i32>,
+    Query(query): Query<DeleteBookQuery>,
+) -> Response {
+    if !query.permanent {
+        return match state.db.soft_delete_book(book_id).await {
+            Ok(true) => crate::good_response(APIResponse::new_from_msg("book moved to trash")),
+            Ok(false) => crate::not_found(APIResponse::new_from_msg("book not found")),
+            Err(e) => {
+                tracing::error!("failed to delete book: {}", e);
+                crate::server_error(APIResponse::new_from_msg("failed to delete book"))
+            }
+        };
+    }
+
+    match state.db.get_book_by_id(book_id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return crate::not_found(APIResponse::new_from_msg("book not found")),
+        Err(e) => {
+            tracing::error!("failed to fetch book {}: {}", book_id, e);
+            return crate::server_error(APIResponse::new_from_msg("failed to delete book"));
+        }
+    }
+    match state.db.delete_book(book_id).await {
+        Ok(()) => crate::good_response(APIResponse::new_from_msg("book deleted")),
+        Err(e) => {
+            tracing::error!("failed to delete book: {}", e);
+            crate::server_error(APIResponse::new_from_msg("failed to delete book"))
+        }
+    }
+}
+
+pub async fn get_trash(State(state): State<AppState>, Query(qp): Query<QueryParams>) -> Response {
+    let hp = HandlerParams {
+        trashed: true,
+        ..qp.into_handler_params()
+    };
+    let total_books = state.db.count_books(&hp).await.ok();
+    match state.db.get_books(hp).await {
+        Ok(books) => crate::good_response(APIResponse {
+            books,
+            total_books,
+            status: "ok".to_owned(),
+            ..Default::default()
+        }),
+        Err(e) => {
+            tracing::error!("failed to get trash: {}", e);
+            crate::server_error(APIResponse::new_from_msg("failed to get trash"))
+        }
+    }
+}
+
+pub async fn restore_book(State(state): State<AppState>, Path(book_id): Path<i32>) -> Response {
+    match state.db.restore_book(book_id).await {
+        Ok(true) => match state.db.get_book_by_id(book_id).await {
+            Ok(Some(book)) => crate::good_response(APIResponse {
+                books: vec![book],
+                status: "ok".to_owned(),
+                ..Default::default()
+            }),
+            _ => crate::good_response(APIResponse::new_from_msg("book restored")),
+        },
+        Ok(false) => crate::not_found(APIResponse::new_from_msg("book not in trash")),
+        Err(e) => {
+            tracing::error!("failed to restore book: {}", e);
+            crate::server_error(APIResponse::new_from_msg("failed to restore book"))
+        }
+    }
+}
+
+pub async fn enrich_book(
+    State(state): State<AppState>,
+    Path(book_id): Path<i32>,
+    payload: Option<Json<EnrichBookRequest>>,
+) -> Response {
+    match state.db.get_book_by_id(book_id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return crate::not_found(APIResponse::new_from_msg("book not found")),
+        Err(e) => {
+            tracing::error!("failed to get book: {}", e);
+            return crate::server_error(APIResponse::new_from_msg("failed to get book"));
+        }
+    }
+
+    let isbn = payload.and_then(|Json(p)| p.isbn);
+    let status = match enrich::enrich_book(&state.db, &state.enricher, book_id, isbn).await {
+        Ok(Some(found)) => format!("enriched from {}", found.source),
+        Ok(None) => "no metadata found".to_owned(),
+        Err(e) => {
+            tracing::error!("failed to enrich book: {}", e);
+            return crate::server_error(APIResponse::new_from_msg(&format!("failed to enrich book: {}", e)));
+        }
+    };
+
+    match state.db.get_book_by_id(book_id).await {
+        Ok(Some(book)) => crate::good_response(APIResponse {
+            books: vec![book],
+            status,
+            ..Default::default()
+        }),
+        _ => crate::good_response(APIResponse::new_from_msg(&status)),
+    }
+}
+
+/// Matches the author to an Open Library authority record, filling in birth year,
+/// canonical name and aliases
+pub async fn enrich_author(State(state): State<AppState>, Path(author_id): Path<i32>) -> Response {
+    match state.db.get_author(author_id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return crate::not_found(APIResponse::new_from_msg("author not found")),
+        Err(e) => {
+            tracing::error!("failed to get author: {}", e);
+            return crate::server_error(APIResponse::new_from_msg("failed to get author"));
+        }
+    }
+
+    match enrich::enrich_author(&state.db, &state.enricher, author_id).await {
+        Ok(Some(author)) => (StatusCode::OK, Json(EntityResponse { entity: author })).into_response(),
+        Ok(None) => crate::not_found(APIResponse::new_from_msg("no authority record matches this author")),
+        Err(e) => {
+            tracing::error!("failed to enrich author: {}", e);
+            crate::server_error(APIResponse::new_from_msg(&format!("failed to enrich author: {}", e)))
+        }
+    }
+}
+
+pub async fn create_author(State(state): State<AppState>, Json(payload): Json<CreateEntityRequest>) -> Response {
+    match state.db.create_author(&payload.name).await {
+        Ok(author) => (StatusCode::CREATED, Json(EntityResponse { entity: author })).into_response(),
+        Err(e) => {
+            tracing::error!("failed to create author: {}", e);
+            crate::bad_request(APIResponse::new_from_msg("failed to create author"))
+        }
+    }
+}
+
+pub async fn list_authors(State(state): State<AppState>, Query(qp): Query<AuthorQueryParams>) -> Response {
+    let page = qp.page.unwrap_or(DEFAULT_PAGE).max(1);
+    let limit = qp.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, DEFAULT_LIMIT);
+    let search = qp.q.as_deref();
+
+    let total = match state.db.count_authors(search).await {
+        Ok(total) => total,
+        Err(e) => {
+            tracing::error!("failed to count authors: {}", e);
+            return crate::server_error(APIResponse::new_from_msg("failed to list authors"));
+        }
+    };
+
+    match state.db.list_authors(search, limit, (page - 1) * limit).await {
+        Ok(authors) => crate::good_response(APIResponse {
+            status: "ok".to_owned(),
+            authors,
+            total_authors: Some(total),
+            ..Default::default()
+        }),
+        Err(e) => {
+            tracing::error!("failed to list authors: {}", e);
+            crate::server_error(APIResponse::new_from_msg("failed to list authors"))
+        }
+    }
+}
+
+pub async fn update_author(
+    State(state): State<AppState>,
+    Path(author_id): Path<i32>,
+    Json(mut payload): Json<UpdateAuthorRequest>,
+) -> Response {
+    match &payload.name {
+        Patch::Null => return crate::bad_request(APIResponse::new_from_msg("author name cannot be empty")),
+        Patch::Value(name) if name.trim().is_empty() => {
+            return crate::bad_request(APIResponse::new_from_msg("author name cannot be empty"));
+        }
+        Patch::Value(name) => payload.name = Patch::Value(name.trim().to_string()),
+        Patch::Absent => {}
+    }
+
+    match state.db.update_author(author_id, &payload).await {
+        Ok(Some(author)) => (StatusCode::OK, Json(EntityResponse { entity: author })).into_response(),
+        Ok(None) => crate::not_found(APIResponse::new_from_msg("author not found")),
+        Err(e) => {
+            tracing::error!("failed to update author: {}", e);
+            crate::bad_request(APIResponse::new_from_msg("failed to update author"))
+        }
+    }
+}
+
+pub async fn delete_author(
+    State(state): State<AppState>,
+    Path(author_id): Path<i32>,
+    Query(query): Query<DeleteAuthorQuery>,
+) -> Response {
+    if !query.force {
+        match state.db.count_author_books(author_id).await {
+            Ok(0) => {}
+            Ok(count) => {
+                let msg = format!("author is linked to {} book(s); pass force=true to unlink them", count);
+                return (StatusCode::CONFLICT, Json(APIResponse::new_from_msg(&msg))).into_response();
+            }
+            Err(e) => {
+                tracing::error!("failed to count author books: {}", e);
+                return crate::server_error(APIResponse::new_from_msg("failed to delete author"));
+            }
+        }
+    }
+
+    match state.db.delete_author(author_id).await {
+        Ok(true) => crate::good_response(APIResponse::new_from_msg("author deleted")),
+        Ok(false) => crate::not_found(APIResponse::new_from_msg("author not found")),
+        Err(e) => {
+            tracing::error!("failed to delete author: {}", e);
+            crate::server_error(APIResponse::new_from_msg("failed to delete author"))
+        }
+    }
+}
+
+pub async fn create_tag(State(state): State<AppState>, Json(payload): Json<CreateEntityRequest>) -> Response {
+    match state.db.create_tag(&payload.name).await {
+        Ok(tag) => (StatusCode::CREATED, Json(EntityResponse { entity: tag })).into_response(),
+        Err(e) => {
+            tracing::error!("failed to create tag: {}", e);
+            crate::bad_request(APIResponse::new_from_msg("failed to create tag"))
+        }
+    }
+}
+
+pub async fn create_category(State(state): State<AppState>, Json(payload): Json<CreateEntityRequest>) -> Response {
+    match state.db.create_category(&payload.name).await {
+        Ok(category) => (StatusCode::CREATED, Json(EntityResponse { entity: category })).into_response(),
+        Err(e) => {
+            tracing::error!("failed to create category: {}", e);
+            crate::bad_request(APIResponse::new_from_msg("failed to create category"))
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct DownloadQuery {
+    pub key: String,
+}
+
+#[derive(serde::Serialize)]
+pub struct DownloadResponse {
+    pub url: String,
+}
+
+pub async fn get_download_url(
+    State(state): State<AppState>,
+    Query(query): Query<DownloadQuery>,
+) -> Response {
+    // Generate presigned URL valid for 1 hour
+    match state.resumable.get_presigned_url(&query.key, 3600).await {
+        Ok(url) => (StatusCode::OK, Json(DownloadResponse { url })).into_response(),
+        Err(e) => {
+            tracing::error!("failed to generate download url: {}", e);
+            crate::server_error(APIResponse::new_from_msg(&format!(
+                "failed to generate download url: {}",
+                e
+            )))
+        }
+    }
+}
+
+/// Redirects to the book's cover, or renders a typographic one when it has none
+pub async fn get_book_cover(State(state): State<AppState>, Path(book_id): Path<i32>) -> Response {
+    let book = match state.db.get_book_by_id(book_id).await {
+        Ok(Some(book)) if !state.public.enabled || book.visibility.is_linkable() => book,
+        Ok(_) => return crate::not_found(APIResponse::new_from_msg("book not found")),
+        Err(e) => {
+            tracing::error!("failed to get book {}: {}", book_id, e);
+            return crate::server_error(APIResponse::new_from_msg("failed to get book"));
+        }
+    };
+
+    if !book.cover_url.is_empty()
+        && let Ok(location) = HeaderValue::from_str(&book.cover_url)
+    {
+        return (StatusCode::FOUND, [(header::LOCATION, location)]).into_response();
+    }
+
+    let author = state
+        .db
+        .get_book_author_names(book_id)
+        .await
+        .unwrap_or_default()
+        .into_iter()
+        .next();
+    match cover::typographic_png(&book.title, author.as_deref()) {
+        Ok(png) => (
+            StatusCode::OK,
+            [
+                (header::CONTENT_TYPE, "image/png"),
+                (header::CACHE_CONTROL, "public, max-age=3600"),
+            ],
+            png,
+        )
+            .into_response(),
+        Err(e) => {
+            tracing::error!("failed to render cover for book {}: {}", book_id, e);
+            crate::server_error(APIResponse::new_from_msg("failed to render cover"))
+        }
+    }
+}
+
+/// Most books a single export will include
+const MAX_EXPORT_BOOKS: u32 = 5000;
+/// Remote covers downloaded at the same time during an export
+const EXPORT_COVER_CONCURRENCY: usize = 8;
+
+/// Printable catalog of the books matching the usual list filters
+pub async fn export_books(
+    State(state): State<AppState>,
+    Query(export): Query<ExportQuery>,
+    Query(qp): Query<QueryParams>,
+) -> Response {
+    if export.format != "pdf" {
+        return crate::bad_request(APIResponse::new_from_msg("unsupported export format, expected pdf"));
+    }
+
+    let mut params = qp.into_handler_params();
+    params.limit = MAX_EXPORT_BOOKS;
+    params.offset = 0;
+    let books = match state.db.get_books(params).await {
+        Ok(books) => books,
+        Err(e) => {
+            tracing::error!("failed to get books for export: {}", e);
+            return crate::server_error(APIResponse::new_from_msg("failed to export books"));
+        }
+    };
+    let mut shelves = match state.db.get_book_shelf_names().await {
+        Ok(shelves) => shelves,
+        Err(e) => {
+            tracing::error!("failed to get shelves for export: {}", e);
+            return crate::server_error(APIResponse::new_from_msg("failed to export books"));
+        }
+    };
+
+    let client = reqwest::Client::builder()
+        .timeout(std::time::Duration::from_secs(10))
+        .build()
+        .unwrap_or_default();
+    let entries: Vec<CatalogEntry> = stream::iter(books)
+        .map(|book| {
+            let (db, client) = (&state.db, &client);
+            let shelves = shelves.remove(&book.id).unwrap_or_default();
+            async move {
+                let authors = db.get_book_author_names(book.id).await.unwrap_or_default();
+                let mut cover = None;
+                if export.covers && !book.cover_url.is_empty() {
+                    cover = catalog::fetch_cover(client, &book.cover_url).await;
+                }
+                let cover = cover.or_else(|| CoverImage::generated(&book.title, authors.first().map(String::as_str)));
+                CatalogEntry {
+                    title: book.title,
+                    authors,
+                    shelves,
+                    isbn: book.isbn,
+                    publish_date: book.publish_date,
+                    pages: book.pages,
+                    cover,
+                }
+            }
+        })
+        .buffered(EXPORT_COVER_CONCURRENCY)
+        .collect()
+        .await;
+
+    let today = chrono::Utc::now().format("%Y-%m-%d").to_string();
+    match catalog::render_pdf("Bibliotek catalog", &today, &entries) {
+        Ok(pdf) => {
+            let disposition = format!("attachment; filename=\"bibliotek-catalog-{}.pdf\"", today);
+            (
+                StatusCode::OK,
+                [
+                    (header::CONTENT_TYPE, "application/pdf".to_string()),
+                    (header::CONTENT_DISPOSITION, disposition),
+                ],
+                pdf,
+            )
+                .into_response()
+        }
+        Err(e) => {
+            tracing::error!("failed to render catalog: {}", e);
+            crate::server_error(APIResponse::new_from_msg("failed to export books"))
+        }
+    }
+}
+
+/// Hashes the stored object so downloads can advertise its checksum
+async fn record_file_checksum(db: &Database, store: &dyn ObjectStore, book_id: i32, key: &str) -> anyhow::Result<()> {
+    let bytes = store.download_file(key).await?;
+    let sha256 = hex::encode(Sha256::digest(&bytes));
+    db.write(|| db.set_book_file_checksum(book_id, bytes.len() as i64, &sha256))
+        .await
+}
+
+pub const CHECKSUM_JOB: &str = "book.checksum";
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+struct ChecksumPayload {
+    book_id: i32,
+    key: String,
+}
+
+/// Checksums uploads whose parts were not hashed as they arrived
+pub struct FileChecksumJob {
+    store: Arc<dyn ObjectStore>,
+}
+
+impl FileChecksumJob {
+    pub fn new(store: Arc<dyn ObjectStore>) -> Self {
+        Self { store }
+    }
+}
+
+#[async_trait::async_trait]
+impl JobHandler for FileChecksumJob {
+    fn kind(&self) -> &'static str {
+        CHECKSUM_JOB
+    }
+
+    async fn run(&self, db: &Database, payload: &serde_json::Value) -> anyhow::Result<()> {
+        let ChecksumPayload { book_id, key } = serde_json::from_value(payload.clone())?;
+        record_file_checksum(db, self.store.as_ref(), book_id, &key).await
+    }
+}
+
+pub const TAG_JOB: &str = "book.tag";
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+struct TagPayload {
+    book_id: i32,
+    key: String,
+}
+
+/// Tags uploaded files with the book they belong to
+pub struct ObjectTagJob {
+    store: Arc<dyn ObjectStore>,
+}
+
+impl ObjectTagJob {
+    pub fn new(store: Arc<dyn ObjectStore>) -> Self {
+        Self { store }
+    }
+}
+
+#[async_trait::async_trait]
+impl JobHandler for ObjectTagJob {
+    fn kind(&self) -> &'static str {
+        TAG_JOB
+    }
+
+    async fn run(&self, db: &Database, payload: &serde_json::Value) -> anyhow::Result<()> {
+        let TagPayload { book_id, key } = serde_json::from_value(payload.clone())?;
+        tag_book_object(db, self.store.as_ref(), book_id, &key).await
+    }
+}
+
+/// With `public.enabled`, a private book is served as if it didn't exist: its file,
+/// versions and cover are only for books that can be linked to
+async fn check_public_book(state: &AppState, book_id: i32) -> Result<(), Response> {
+    if !state.public.enabled {
+        return Ok(());
+    }
+    match state.db.get_book_by_id(book_id).await {
+        Ok(Some(book)) if book.visibility.is_linkable() => Ok(()),
+        Ok(_) => Err(crate::not_found(APIResponse::new_from_msg("book not found"))),
+        Err(e) => {
+            tracing::error!("failed to get book {}: {}", book_id, e);
+            Err(crate::server_error(APIResponse::new_from_msg("failed to get book")))
+        }
+    }
+}
+
+/// Finds the book's file and the object key it is stored under
+async fn resolve_book_file(state: &AppState, book_id: i32) -> Result<(BookFile, String), Response> {
+    check_public_book(state, book_id).await?;
+    let file = match state.db.get_book_file(book_id).await {
+        Ok(Some(file)) => file,
+        Ok(None) => return Err(crate::not_found(APIResponse::new_from_msg("book not found"))),
+        Err(e) => {
+            tracing::error!("failed to get book {}: {}", book_id, e);
+            return Err(crate::server_error(APIResponse::new_from_msg("failed to get book")));
+        }
+    };
+    match state.resumable.key_for_url(&file.url) {
+        Some(key) => Ok((file, key)),
+        None => Err(crate::not_found(APIResponse::new_from_msg("book has no stored file"))),
+    }
+}
+
+fn storage_error_response(key: &str, e: ObjectStorageError) -> Response {
+    match e {
+        ObjectStorageError::ObjectNotFound(_) => {
+            crate::not_found(APIResponse::new_from_msg("book file not found in storage"))
+        }
+        e => {
+            tracing::error!("failed to read book file {}: {}", key, e);
+            crate::server_error(APIResponse::new_from_msg("failed to read book file"))
+        }
+    }
+}
+
+/// Integrity headers for a book's file. Size and ETag come from the store; the
+/// SHA-256 is whatever was recorded at upload.
+fn book_file_headers(key: &str, file: &BookFile, info: ObjectInfo) -> HeaderMap {
+    let content_type = info.content_type.unwrap_or_else(|| content_type_for(key).to_string());
+    let entries = [
+        (header::CONTENT_LENGTH, Some(info.size.to_string())),
+        (header::CONTENT_TYPE, Some(content_type)),
+        (header::ETAG, info.etag),
+        (HeaderName::from_static("x-checksum-sha256"), file.sha256.clone()),
+    ];
+
+    let mut headers = HeaderMap::new();
+    for (name, value) in entries {
+        if let Some(value) = value.and_then(|v| HeaderValue::from_str(&v).ok()) {
+            headers.insert(name, value);
+        }
+    }
+    if file.size.is_some_and(|size| size != info.size) {
+        tracing::warn!("{} differs in size from what was recorded at upload", key);
+    }
+    headers
+}
+
+fn content_type_for(key: &str) -> &'static str {
+    if is_epub(key) {
+        "application/epub+zip"
+    } else if key.to_lowercase().ends_with(".pdf") {
+        "application/pdf"
+    } else {
+        "application/octet-stream"
+    }
+}
+
+/// `attachment; filename="..."` with an RFC 5987 `filename*` for non-ASCII names
+fn content_disposition(key: &str, inline: bool) -> Option<HeaderValue> {
+    let file_name = ResumableUploadManager::get_filename_from_key(key).unwrap_or_else(|| key.to_string());
+    let ascii: String = file_name
+        .chars()
+        .map(|c| {
+            if (c.is_ascii_graphic() && c != '"' && c != '\\') || c == ' ' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let kind = if inline { "inline" } else { "attachment" };
+    HeaderValue::from_str(&format!(
+        "{}; filename=\"{}\"; filename*=UTF-8''{}",
+        kind,
+        ascii,
+        urlencoding::encode(&file_name)
+    ))
+    .ok()
+}
+
+/// Integrity metadata for a book's file without the body, so sync clients can
+/// check a local copy against `Content-Length` and `X-Checksum-Sha256`.
+pub async fn head_book_download(State(state): State<AppState>, Path(book_id): Path<i32>) -> Response {
+    let (file, key) = match resolve_book_file(&state, book_id).await {
+        Ok(found) => found,
+        Err(resp) => return resp,
+    };
+    match state.resumable.head(&key).await {
+        Ok(info) => (StatusCode::OK, book_file_headers(&key, &file, info)).into_response(),
+        Err(e) => storage_error_response(&key, e),
+    }
+}
+
+#[derive(serde::Deserialize, Default)]
+pub struct BookDownloadQuery {
+    /// Serve with `Content-Disposition: inline` so browsers display the file
+    #[serde(default)]
+    pub inline: bool,
+}
+
+/// Streams the book's file through the server, so the bucket itself can stay private
+pub async fn download_book(
+    State(state): State<AppState>,
+    Path(book_id): Path<i32>,
+    Query(query): Query<BookDownloadQuery>,
+) -> Response {
+    let (file, key) = match resolve_book_file(&state, book_id).await {
+        Ok(found) => found,
+        Err(resp) => return resp,
+    };
+    let (info, stream) = match state.resumable.stream_file(&key).await {
+        Ok(found) => found,
+        Err(e) => return storage_error_response(&key, e),
+    };
+    if let Err(e) = state.db.record_book_open(book_id, None, None).await {
+        tracing::warn!("failed to record open of book {}: {}", book_id, e);
+    }
+
+    book_file_response(&key, &file, info, stream, query.inline)
+}
+
+/// Text of the book's PDF, from the first extraction backend whose output doesn't
+/// look garbled
+pub async fn get_book_text(State(state): State<AppState>, Path(book_id): Path<i32>) -> Response {
+    let (_, key) = match resolve_book_file(&state, book_id).await {
+        Ok(found) => found,
+        Err(resp) => return resp,
+    };
+    if !key.to_lowercase().ends_with(".pdf") {
+        return crate::bad_request(APIResponse::new_from_msg("text can only be extracted from PDFs"));
+    }
+    let pdf = match state.resumable.download_file(&key).await {
+        Ok(pdf) => pdf,
+        Err(e) => return storage_error_response(&key, e),
+    };
+    match state.extraction.extract(&pdf).await {
+        Ok(extracted) => (StatusCode::OK, Json(EntityResponse { entity: extracted })).into_response(),
+        Err(e) => {
+            tracing::error!("failed to extract text of book {}: {:#}", book_id, e);
+            crate::server_error(APIResponse::new_from_msg("failed to extract text"))
+        }
+    }
+}
+
+fn book_file_response(key: &str, file: &BookFile, info: ObjectInfo, stream: ObjectStream, inline: bool) -> Response {
+    let mut headers = book_file_headers(key, file, info);
+    if let Some(disposition) = content_disposition(key, inline) {
+        headers.insert(header::CONTENT_DISPOSITION, disposition);
+    }
+    (StatusCode::OK, headers, Body::from_stream(stream)).into_response()
+}
+
+/// Streams a file the book had before it was replaced
+pub async fn download_book_version(
+    State(state): State<AppState>,
+    Path((book_id, version_id)): Path<(i32, i32)>,
+    Query(query): Query<BookDownloadQuery>,
+) -> Response {
+    if let Err(resp) = check_public_book(&state, book_id).await {
+        return resp;
+    }
+    let version = match state.db.get_book_version(book_id, version_id).await {
+        Ok(Some(version)) => version,
+        Ok(None) => return crate::not_found(APIResponse::new_from_msg("book version not found")),
+        Err(e) => {
+            tracing::error!("failed to get version {} of book {}: {}", version_id, book_id, e);
+            return crate::server_error(APIResponse::new_from_msg("failed to get book version"));
+        }
+    };
+    let Some(key) = state.resumable.key_for_url(&version.url) else {
+        return crate::not_found(APIResponse::new_from_msg("book version has no stored file"));
+    };
+    let (info, stream) = match state.resumable.stream_file(&key).await {
+        Ok(found) => found,
+        Err(e) => return storage_error_response(&key, e),
+    };
+    let file = BookFile {
+        url: version.url,
+        size: version.size,
+        sha256: version.sha256,
+    };
+    book_file_response(&key, &file, info, stream, query.inline)
+}
+
+pub async fn list_shelves(State(state): State<AppState>) -> Response {
+    match state.db.list_shelves().await {
+        Ok(shelves) => crate::good_response(APIResponse {
+            status: "ok".to_owned(),
+            shelves,
+            ..Default::default()
+        }),
+        Err(e) => {
+            tracing::error!("failed to list shelves: {}", e);
+            crate::bad_request(APIResponse::new_from_msg("failed to list shelves"))
+        }
+    }
+}
+
+pub async fn create_shelf(State(state): State<AppState>, Json(payload): Json<CreateShelfRequest>) -> Response {
+    if payload.name.trim().is_empty() {
+        return crate::bad_request(APIResponse::new_from_msg("shelf name is required"));
+    }
+
+    match state
+        .db
+        .create_shelf(payload.name.trim(), payload.description.as_deref())
+        .await
+    {
+        Ok(shelf) => (StatusCode::CREATED, Json(EntityResponse { entity: shelf })).into_response(),
+        Err(e) if is_unique_violation(&e) => shelf_name_taken(),
+        Err(e) => {
+            tracing::error!("failed to create shelf: {}", e);
+            crate::bad_request(APIResponse::new_from_msg("failed to create shelf"))
+        }
+    }
+}
+
+pub async fn update_shelf(
+    State(state): State<AppState>,
+    Path(shelf_id): Path<i32>,
+    Json(payload): Json<UpdateShelfRequest>,
+) -> Response {
+    let name = match payload.name {
+        Patch::Null => return crate::bad_request(APIResponse::new_from_msg("shelf name can't be cleared")),
+        Patch::Value(name) if name.trim().is_empty() => {
+            return crate::bad_request(APIResponse::new_from_msg("shelf name can't be blank"));
+        }
+        Patch::Value(name) => Patch::Value(name.trim().to_string()),
+        Patch::Absent => Patch::Absent,
+    };
+
+    match state.db.update_shelf(shelf_id, &name, &payload.description).await {
+        Ok(Some(shelf)) => (StatusCode::OK, Json(EntityResponse { entity: shelf })).into_response(),
+        Ok(None) => crate::not_found(APIResponse::new_from_msg("shelf not found")),
+        Err(e) if is_unique_violation(&e) => shelf_name_taken(),
+        Err(e) => {
+            tracing::error!("failed to update shelf: {}", e);
+            crate::bad_request(APIResponse::new_from_msg("failed to update shelf"))
+        }
+    }
+}
+
+fn shelf_name_taken() -> Response {
+    (StatusCode::CONFLICT, Json(APIResponse::new_from_msg("a shelf with this name already exists"))).into_response()
+}
+
+pub async fn delete_shelf(State(state): State<AppState>, Path(shelf_id): Path<i32>) -> Response {
+    match state.db.delete_shelf(shelf_id).await {
+        Ok(true) => crate::good_response(APIResponse::new_from_msg("shelf deleted")),
+        Ok(false) => crate::not_found(APIResponse::new_from_msg("shelf not found")),
+        Err(e) => {
+            tracing::error!("failed to delete shelf: {}", e);
+            crate::server_error(APIResponse::new_from_msg("failed to delete shelf"))
+        }
+    }
+}
+
+pub async fn get_shelf_books(State(state): State<AppState>, Path(shelf_id): Path<i32>) -> Response {
+    match state.db.get_shelf(shelf_id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return crate::not_found(APIResponse::new_from_msg("shelf not found")),
+        Err(e) => {
+            tracing::error!("failed to get shelf: {}", e);
+            return crate::server_error(APIResponse::new_from_msg("failed to get shelf"));
+        }
+    }
+
+    match state.db.get_books_by_shelf(shelf_id).await {
+        Ok(books) => crate::good_response(APIResponse {
+            total_books: Some(books.len() as u32),
+            books,
+            status: "ok".to_owned(),
+            ..Default::default()
+        }),
+        Err(e) => {
+            tracing::error!("failed to get shelf books: {}", e);
+            crate::bad_request(APIResponse::new_from_msg("failed to get shelf books"))
+        }
+    }
+}
+
+pub async fn add_books_to_shelf(
+    State(state): State<AppState>,
+    Path(shelf_id): Path<i32>,
+    Json(payload): Json<ShelfBooksRequest>,
+) -> Response {
+    match state.db.get_shelf(shelf_id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return crate::not_found(APIResponse::new_from_msg("shelf not found")),
+        Err(e) => {
+            tracing::error!("failed to get shelf: {}", e);
+            return crate::server_error(APIResponse::new_from_msg("failed to get shelf"));
+        }
+    }
+
+    if payload.book_ids.is_empty() {
+        return crate::bad_request(APIResponse::new_from_msg("book_ids is required"));
+    }
+
+    match state.db.add_books_to_shelf(shelf_id, &payload.book_ids).await {
+        Ok(missing) if !missing.is_empty() => {
+            let ids = missing.iter().map(i32::to_string).collect::<Vec<_>>().join(", ");
+            crate::not_found(APIResponse::new_from_msg(&format!("books not found: {}", ids)))
+        }
+        Ok(_) => match state.db.get_shelf(shelf_id).await {
+            Ok(Some(shelf)) => (StatusCode::OK, Json(EntityResponse { entity: shelf })).into_response(),
+            _ => crate::good_response(APIResponse::new_from_msg("books added to shelf")),
+        },
+        Err(e) => {
+            tracing::error!("failed to add books to shelf: {}", e);
+            crate::bad_request(APIResponse::new_from_msg("failed to add books to shelf"))
+        }
+    }
+}
+
+pub async fn remove_book_from_shelf(
+    State(state): State<AppState>,
+    Path((shelf_id, book_id)): Path<(i32, i32)>,
+) -> Response {
+    match state.db.remove_book_from_shelf(shelf_id, book_id).await {
+        Ok(true) => crate::good_response(APIResponse::new_from_msg("book removed from shelf")),
+        Ok(false) => crate::not_found(APIResponse::new_from_msg("book is not on this shelf")),
+        Err(e) => {
+            tracing::error!("failed to remove book from shelf: {}", e);
+            crate::server_error(APIResponse::new_from_msg("failed to remove book from shelf"))
+        }
+    }
+}
+
+/// Connection settings in effect and lock contention since startup
+pub async fn db_stats(State(state): State<AppState>) -> Response {
+    match state.db.stats().await {
+        Ok(stats) => crate::response::success(stats),
+        Err(e) => {
+            tracing::error!("failed to read database stats: {}", e);
+            crate::response::internal_error("Failed to read database stats")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::QueryParams;
+    use crate::api::{
+        AttachVersionRequest, AuthorQueryParams, CreateShelfRequest, DeleteAuthorQuery, OpenBookRequest,
+        ShelfBooksRequest,
+    };
+    use crate::enrich::AuthorAuthority;
+    use crate::handler;
+    use crate::object_store::{MemoryObjectStore, ObjectStore};
+    use crate::patch::Patch;
+    use crate::test_support::{
+        multipart, read_json, sample_epub, seed_book, test_db, test_state, test_state_with_store,
+    };
+    use axum::Json;
+    use axum::extract::{Path, Query, State};
+    use axum::http::StatusCode;
+
+    #[test]
+    fn test_params_deserialize() {}
+
+    fn upload_query(state: &str) -> Query<QueryParams> {
+        Query(QueryParams {
+            q: None,
+            page: None,
+            limit: None,
+            state: Some(state.to_string()),
+            favorite: None,
+            status: None,
+            visibility: None,
+        })
+    }
+
+    #[tokio::test]
+    async fn epub_upload_creates_book_from_opf() {
+        let store = Arc::new(MemoryObjectStore::new());
+        let state = test_state_with_store(store.clone()).await;
+        let epub = sample_epub("Structure and Interpretation", &["Harold Abelson"]);
+        let size = epub.len().to_string();
+
+        let form = multipart(&[
+            ("file_name", b"sicp.epub"),
+            ("file_size", size.as_bytes()),
+            ("file_signature", b"0123456789abcdef"),
+        ])
+        .await;
+        let (status, init) = read_json(handler::upload(State(state.clone()), upload_query("init"), form).await).await;
+        assert_eq!(status, StatusCode::OK);
+        let upload_id = init["upload_id"].as_str().unwrap().to_string();
+        let key = init["key"].as_str().unwrap().to_string();
+
+        let form = multipart(&[
+            ("upload_id", upload_id.as_bytes()),
+            ("key", key.as_bytes()),
+            ("part_number", b"1"),
+            ("chunk", &epub),
+        ])
+        .await;
+        let resp = handler::upload(State(state.clone()), upload_query("continue"), form).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(store.list_pending().await.unwrap()[0].completed_chunks, 1);
+
+        let form = multipart(&[("upload_id", upload_id.as_bytes()), ("key", key.as_bytes())]).await;
+        let (status, body) =
+            read_json(handler::upload(State(state.clone()), upload_query("complete"), form).await).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["books"][0]["title"], "Structure and Interpretation");
+        assert_eq!(body["books"][0]["isbn"], "9780262510875");
+        assert_eq!(store.get(&key).await.unwrap(), epub);
+
+        let book_id = body["books"][0]["id"].as_i64().unwrap() as i32;
+        assert!(store.tags(&key).await.is_empty());
+        crate::jobs::JobRunner::new(state.db.clone(), 1)
+            .with_handler(Arc::new(handler::ObjectTagJob::new(store.clone())))
+            .run_pending()
+            .await
+            .unwrap();
+        let tags = store.tags(&key).await;
+        assert!(tags.contains(&("book_id".to_string(), book_id.to_string())));
+        assert!(tags.contains(&("title".to_string(), "Structure and Interpretation".to_string())));
+        let resp = handler::head_book_download(State(state.clone()), Path(book_id)).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers()["content-length"], size.as_str());
+        assert_eq!(resp.headers()["content-type"], "application/epub+zip");
+        assert_eq!(
+            resp.headers()["x-checksum-sha256"],
+            hex::encode(<sha2::Sha256 as sha2::Digest>::digest(&epub)).as_str()
+        );
+
+        let resp = handler::download_book(State(state.clone()), Path(book_id), Query(Default::default())).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert!(
+            resp.headers()["content-disposition"]
+                .to_str()
+                .unwrap()
+                .starts_with("attachment; filename=\"sicp.epub\"")
+        );
+        let bytes = axum::body::to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(bytes.as_ref(), epub.as_slice());
+    }
+
+    #[tokio::test]
+    async fn shelf_handlers_round_trip() {
+        let state = test_state().await;
+        let book_id = seed_book(&state.db, "Deep Work", &["Cal Newport"]).await;
+
+        let resp = handler::create_shelf(
+            State(state.clone()),
+            Json(CreateShelfRequest {
+                name: "focus".to_string(),
+                description: None,
+            }),
+        )
+        .await;
+        let (status, body) = read_json(resp).await;
+        assert_eq!(status, StatusCode::CREATED);
+        let shelf_id = body["entity"]["id"].as_i64().unwrap() as i32;
+
+        let resp = handler::add_books_to_shelf(
+            State(state.clone()),
+            Path(shelf_id),
+            Json(ShelfBooksRequest {
+                book_ids: vec![book_id],
+            }),
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let (status, body) = read_json(handler::get_shelf_books(State(state.clone()), Path(shelf_id)).await).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["books"][0]["title"], "Deep Work");
+    }
+
+    #[tokio::test]
+    async fn shelf_names_are_unique_and_descriptions_can_be_cleared() {
+        let state = test_state().await;
+        let create = |name: &str| {
+            handler::create_shelf(
+                State(state.clone()),
+                Json(CreateShelfRequest {
+                    name: name.to_string(),
+                    description: Some("deep work".to_string()),
+                }),
+            )
+        };
+        let (_, body) = read_json(create("focus").await).await;
+        let focus = body["entity"]["id"].as_i64().unwrap() as i32;
+        let (_, body) = read_json(create("later").await).await;
+        let later = body["entity"]["id"].as_i64().unwrap() as i32;
+        assert_eq!(create(" focus ").await.status(), StatusCode::CONFLICT);
+
+        let update = |shelf_id: i32, patch: serde_json::Value| {
+            let payload: UpdateShelfRequest = serde_json::from_value(patch).unwrap();
+            handler::update_shelf(State(state.clone()), Path(shelf_id), Json(payload))
+        };
+        let resp = update(later, serde_json::json!({"name": "focus"})).await;
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+        for patch in [serde_json::json!({"name": null}), serde_json::json!({"name": "  "})] {
+            assert_eq!(update(focus, patch).await.status(), StatusCode::BAD_REQUEST);
+        }
+
+        // An absent description is left alone, a null one cleared
+        let (status, body) = read_json(update(focus, serde_json::json!({"name": "deep"})).await).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["entity"]["description"], "deep work");
+        let (_, body) = read_json(update(focus, serde_json::json!({"description": null})).await).await;
+        assert_eq!(body["entity"]["name"], "deep");
+        assert!(body["entity"]["description"].is_null());
+    }
+
+    #[tokio::test]
+    async fn only_live_books_go_on_shelves() {
+        let state = test_state().await;
+        let book_id = seed_book(&state.db, "Deep Work", &["Cal Newport"]).await;
+        let trashed = seed_book(&state.db, "Shallows", &["Nicholas Carr"]).await;
+        handler::delete_book(State(state.clone()), Path(trashed), Query(Default::default())).await;
+        let shelf = state.db.create_shelf("focus", None).await.unwrap();
+        let add = |book_ids: Vec<i32>| {
+            handler::add_books_to_shelf(State(state.clone()), Path(shelf.id), Json(ShelfBooksRequest { book_ids }))
+        };
+
+        assert_eq!(add(vec![]).await.status(), StatusCode::BAD_REQUEST);
+        let (status, body) = read_json(add(vec![book_id, trashed, 999]).await).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["status"], format!("books not found: {}, 999", trashed));
+        // Nothing was added, not even the book that exists
+        assert!(state.db.get_books_by_shelf(shelf.id).await.unwrap().is_empty());
+
+        assert_eq!(add(vec![book_id]).await.status(), StatusCode::OK);
+        assert_eq!(state.db.get_books_by_shelf(shelf.id).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn deleted_books_move_to_trash_and_back() {
+        let state = test_state().await;
+        let book_id = seed_book(&state.db, "Thinking in Systems", &["Donella Meadows"]).await;
+        let listed = |state: AppState| async move {
+            let (_, body) = read_json(handler::get_books(State(state), Query(QueryParams::default())).await).await;
+            body["books"].as_array().map_or(0, |books| books.len())
+        };
+
+        let resp = handler::delete_book(State(state.clone()), Path(book_id), Query(Default::default())).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(listed(state.clone()).await, 0);
+
+        let (_, body) = read_json(handler::get_trash(State(state.clone()), Query(QueryParams::default())).await).await;
+        assert_eq!(body["books"][0]["id"], book_id);
+        assert!(body["books"][0]["deleted_at"].is_string());
+
+        let resp = handler::restore_book(State(state.clone()), Path(book_id)).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(listed(state.clone()).await, 1);
+        let resp = handler::restore_book(State(state), Path(book_id)).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn authors_with_books_need_force_to_delete() {
+        let state = test_state().await;
+        seed_book(&state.db, "Seeing Like a State", &["James C. Scott"]).await;
+        let query = |q: &str| AuthorQueryParams {
+            q: Some(q.to_string()),
+            ..Default::default()
+        };
+
+        let (_, body) = read_json(handler::list_authors(State(state.clone()), Query(query("scott"))).await).await;
+        assert_eq!(body["total_authors"], 1);
+        assert_eq!(body["authors"][0]["count"], 1);
+        let author_id = body["authors"][0]["author"]["id"].as_i64().unwrap() as i32;
+
+        let resp = handler::delete_author(State(state.clone()), Path(author_id), Query(Default::default())).await;
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+        let force = DeleteAuthorQuery { force: true };
+        let resp = handler::delete_author(State(state.clone()), Path(author_id), Query(force)).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let (_, body) = read_json(handler::list_authors(State(state), Query(query("scott"))).await).await;
+        assert_eq!(body["total_authors"], 0);
+    }
+
+    #[tokio::test]
+    async fn books_report_annotations_from_linked_resources() {
+        use crate::commonplace::{CreateAnnotation, CreateNote, CreateResource, ResourceType};
+
+        let state = test_state().await;
+        let book_id = seed_book(&state.db, "The Power Broker", &["Robert Caro"]).await;
+        let lib = state.db.commonplace();
+        let resource = lib
+            .create_resource(CreateResource {
+                title: "the power broker".to_string(),
+                resource_type: ResourceType::Pdf,
+                external_id: None,
+                content_hash: None,
+            })
+            .await
+            .unwrap();
+        assert_eq!(resource.book_id, Some(book_id));
+
+        for text in ["Moses", "Parkways"] {
+            lib.create_annotation(CreateAnnotation {
+                resource_id: resource.id,
+                text: text.to_string(),
+                color: None,
+                boundary: None,
+                external_id: None,
+                content_hash: None,
+            })
+            .await
+            .unwrap();
+        }
+        lib.create_note(CreateNote {
+            resource_id: resource.id,
+            content: "Chapter 37".to_string(),
+            external_id: None,
+            content_hash: None,
+        })
+        .await
+        .unwrap();
+
+        let book = state.db.get_book_by_id(book_id).await.unwrap().unwrap();
+        assert_eq!((book.annotation_count, book.note_count), (2, 1));
+        assert!(book.last_annotated_at.is_some());
+
+        lib.set_resource_book(resource.id, None).await.unwrap();
+        let book = state.db.get_book_by_id(book_id).await.unwrap().unwrap();
+        assert_eq!((book.annotation_count, book.last_annotated_at), (0, None));
+    }
+
+    #[tokio::test]
+    async fn soft_deleted_highlights_restore_after_their_resource() {
+        use crate::commonplace::{CreateAnnotation, CreateResource, ResourceType, Restore};
+        use crate::sync::runs::Entity;
+
+        let db = test_db().await;
+        let lib = db.commonplace();
+        let resource = lib
+            .create_resource(CreateResource {
+                title: "https://example.com/essay".to_string(),
+                resource_type: ResourceType::Website,
+                external_id: None,
+                content_hash: None,
+            })
+            .await
+            .unwrap();
+        let annotation = lib
+            .create_annotation(CreateAnnotation {
+                resource_id: resource.id,
+                text: "worth keeping".to_string(),
+                color: None,
+                boundary: None,
+                external_id: None,
+                content_hash: None,
+            })
+            .await
+            .unwrap();
+        lib.soft_delete_annotation(annotation.id).await.unwrap();
+        lib.soft_delete_resource(resource.id).await.unwrap();
+
+        let restored = lib.restore(Entity::Annotation, annotation.id).await.unwrap();
+        assert_eq!(
+            restored,
+            Restore::ParentDeleted {
+                entity: "resources",
+                id: resource.id
+            }
+        );
+        assert_eq!(lib.restore(Entity::Resource, resource.id).await.unwrap(), Restore::Restored);
+        assert!(lib.list_annotations_by_resource(resource.id).await.unwrap().is_empty());
+        assert_eq!(lib.restore(Entity::Annotation, annotation.id).await.unwrap(), Restore::Restored);
+        assert_eq!(lib.list_annotations_by_resource(resource.id).await.unwrap().len(), 1);
+        assert_eq!(lib.restore(Entity::Note, 42).await.unwrap(), Restore::NotFound);
+    }
+
+    #[tokio::test]
+    async fn opened_books_show_up_in_continue_reading() {
+        let state = test_state().await;
+        let first = seed_book(&state.db, "Middlemarch", &["George Eliot"]).await;
+        let second = seed_book(&state.db, "Bleak House", &["Charles Dickens"]).await;
+
+        let open = |progress| {
+            Some(Json(OpenBookRequest {
+                progress,
+                position: None,
+            }))
+        };
+        let resp = handler::open_book(State(state.clone()), Path(first), open(Some(0.25))).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        handler::open_book(State(state.clone()), Path(second), None).await;
+        handler::open_book(State(state.clone()), Path(first), None).await;
+        let resp = handler::open_book(State(state.clone()), Path(first), open(Some(1.5))).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        let (_, body) =
+            read_json(handler::continue_reading(State(state.clone()), Query(Default::default())).await).await;
+        assert_eq!(body["total_books"], 2);
+        let opened = body["books"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|b| b["id"] == first)
+            .unwrap();
+        assert_eq!(opened["progress"], 0.25);
+
+        let finished = "UPDATE books SET reading_status = 'finished' WHERE id = ?";
+        state
+            .db
+            .connection()
+            .execute(finished, libsql::params![second])
+            .await
+            .unwrap();
+        let (_, body) = read_json(handler::continue_reading(State(state), Query(Default::default())).await).await;
+        assert_eq!(body["total_books"], 1);
+    }
+
+    #[tokio::test]
+    async fn book_edits_are_recorded_in_history() {
+        let state = test_state().await;
+        let book_id = seed_book(&state.db, "Antifragile", &["Nassim Taleb"]).await;
+        let patch = crate::api::PatchBookRequest {
+            title: Patch::Value("Antifragile: Things That Gain from Disorder".to_string()),
+            pages: Patch::Value(519),
+            ..Default::default()
+        };
+        assert!(state.db.patch_book(book_id, &patch).await.unwrap());
+
+        let (status, body) = read_json(handler::get_book_history(State(state), Path(book_id)).await).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["revisions"][0]["field"], "pages");
+        assert!(body["revisions"][0]["old_value"].is_null());
+        assert_eq!(body["revisions"][1]["field"], "title");
+        assert_eq!(body["revisions"][1]["old_value"], "Antifragile");
+        assert_eq!(body["revisions"].as_array().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn interrupted_import_commit_resumes() {
+        use crate::imports::{CreateImportBatch, Imports, StagedRecord};
+
+        let db = test_db().await;
+        let book = |title: &str| StagedRecord::Book {
+            title: title.to_string(),
+            authors: vec!["Italo Calvino".to_string()],
+            tags: vec![],
+            categories: vec![],
+            description: None,
+            pages: None,
+            ratings: None,
+            isbn: None,
+            url: None,
+        };
+        let imports = Imports::new(&db);
+        let input = CreateImportBatch {
+            source: "calibre".to_string(),
+            items: vec![book("Invisible Cities"), book("Cosmicomics"), book("Mr Palomar")],
+        };
+        let batch = imports.stage(input).await.unwrap();
+        let items = imports.list_items(batch.id).await.unwrap();
+
+        // A commit that got through the first item before the process died
+        let first = seed_book(&db, "Invisible Cities", &["Italo Calvino"]).await;
+        let interrupted = r#"
+            UPDATE import_batches SET commit_started_at = '2024-01-01T00:00:00.000Z', committed_through = ?
+            WHERE id = ?
+        "#;
+        let conn = &db.connection();
+        conn.execute(interrupted, libsql::params![items[0].id, batch.id])
+            .await
+            .unwrap();
+        conn.execute("UPDATE import_items SET result_id = ? WHERE id = ?", libsql::params![first, items[0].id])
+            .await
+            .unwrap();
+
+        let batch = imports.get_batch(batch.id).await.unwrap().unwrap();
+        let summary = imports.commit(&batch).await.unwrap();
+        assert_eq!((summary.created, summary.already_processed), (2, 1));
+        assert_eq!(summary.resumed_after, Some(items[0].id));
+
+        let mut rows = conn
+            .query("SELECT COUNT(*) FROM books WHERE title = 'Invisible Cities'", ())
+            .await
+            .unwrap();
+        let count: i64 = rows.next().await.unwrap().unwrap().get(0).unwrap();
+        assert_eq!(count, 1);
+        let batch = imports.get_batch(batch.id).await.unwrap().unwrap();
+        assert_eq!(batch.committed_through, Some(items[2].id));
+    }
+
+    #[tokio::test]
+    async fn author_spellings_share_one_author() {
+        let db = test_db().await;
+        let conn = &db.connection();
+        // An author from before aliases were recorded
+        conn.execute("INSERT INTO authors (name) VALUES ('Ursula K. Le Guin')", ())
+            .await
+            .unwrap();
+
+        seed_book(&db, "The Art of Computer Programming", &["Donald E. Knuth"]).await;
+        seed_book(&db, "Literate Programming", &["Knuth, Donald"]).await;
+        seed_book(&db, "The Dispossessed", &["Le Guin, Ursula"]).await;
+        let authors = db.list_authors(None, 10, 0).await.unwrap();
+        let names: Vec<_> = authors.iter().map(|a| (a.author.name.as_str(), a.count)).collect();
+        assert_eq!(names, [("Donald E. Knuth", 2), ("Ursula K. Le Guin", 1)]);
+
+        let knuth = authors[0].author.id;
+        let authority = AuthorAuthority {
+            authority_id: "OL26320A".to_string(),
+            name: "Donald Knuth".to_string(),
+            birth_year: Some(1938),
+            alternate_names: vec!["Donald Ervin Knuth".to_string()],
+        };
+        let author = db.apply_author_authority(knuth, &authority).await.unwrap().unwrap();
+        assert_eq!(author.birth_year, Some(1938));
+        assert!(author.aliases.contains(&"Donald Ervin Knuth".to_string()));
+        assert_eq!(db.get_or_create_author("Donald Ervin Knuth").await.unwrap(), knuth);
+    }
+
+    #[tokio::test]
+    async fn concurrent_syncs_upsert_one_resource() {
+        use crate::commonplace::{CreateResource, ResourceType, Upsert, compute_resource_hash};
+
+        let db = test_db().await;
+        let input = |title: &str| CreateResource {
+            title: title.to_string(),
+            resource_type: ResourceType::Pdf,
+            external_id: Some("research:1".to_string()),
+            content_hash: Some(compute_resource_hash(title)),
+        };
+        let (lib_a, lib_b) = (db.commonplace(), db.commonplace());
+        let (a, b) = tokio::join!(lib_a.upsert_resource(input("Paper")), lib_b.upsert_resource(input("Paper")));
+        let outcomes = [a.unwrap(), b.unwrap()];
+        assert_eq!(outcomes.iter().filter(|o| matches!(o, Upsert::Created(_))).count(), 1);
+        assert!(outcomes.iter().any(|o| matches!(o, Upsert::Unchanged(_))));
+
+        let lib = db.commonplace();
+        let Upsert::Updated(resource) = lib.upsert_resource(input("Paper, revised")).await.unwrap() else {
+            panic!("expected the title change to update the resource");
+        };
+        assert_eq!(resource.title, "Paper, revised");
+
+        let (site, created) = lib.find_or_create_website("https://example.com", None).await.unwrap();
+        assert!(created);
+        let (again, created) = lib.find_or_create_website("https://example.com", None).await.unwrap();
+        assert_eq!((again.id, created), (site.id, false));
+    }
+
+    #[tokio::test]
+    async fn direct_upload_is_checked_before_completing() {
+        let store = Arc::new(MemoryObjectStore::new());
+        let state = test_state_with_store(store.clone()).await;
+        let epub = sample_epub("Structure and Interpretation", &["Harold Abelson"]);
+        let size = epub.len().to_string();
+
+        let form = multipart(&[
+            ("file_name", b"sicp.epub"),
+            ("file_size", size.as_bytes()),
+            ("file_signature", b"0123456789abcdef"),
+            ("direct", b"true"),
+        ])
+        .await;
+        let (status, init) = read_json(handler::upload(State(state.clone()), upload_query("init"), form).await).await;
+        assert_eq!(status, StatusCode::OK);
+        let upload_id = init["upload_id"].as_str().unwrap().to_string();
+        let key = init["key"].as_str().unwrap().to_string();
+        assert_eq!(init["part_urls"].as_array().unwrap().len(), 1);
+        assert_eq!(init["part_urls"][0]["part_number"], 1);
+
+        // The client puts the parts in the bucket itself; only half has arrived
+        let (head, tail) = epub.split_at(epub.len() / 2);
+        store.upload_part(&upload_id, &key, head.to_vec(), 1).await.unwrap();
+        let without_size = multipart(&[("upload_id", upload_id.as_bytes()), ("key", key.as_bytes())]).await;
+        let resp = handler::upload(State(state.clone()), upload_query("complete"), without_size).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let complete: &[(&str, &[u8])] = &[
+            ("upload_id", upload_id.as_bytes()),
+            ("key", key.as_bytes()),
+            ("file_size", size.as_bytes()),
+        ];
+        let resp = handler::upload(State(state.clone()), upload_query("complete"), multipart(complete).await).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(store.list_pending().await.unwrap().len(), 1);
+
+        store.upload_part(&upload_id, &key, tail.to_vec(), 2).await.unwrap();
+        let (status, body) =
+            read_json(handler::upload(State(state.clone()), upload_query("complete"), multipart(complete).await).await)
+                .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["books"][0]["title"], "Structure and Interpretation");
+        assert_eq!(store.get(&key).await.unwrap(), epub);
+    }
+
+    #[tokio::test]
+    async fn upload_is_verified_against_declared_checksum() {
+        let store = Arc::new(MemoryObjectStore::new());
+        let state = test_state_with_store(store.clone()).await;
+        let pdf = b"%PDF-1.4 a scanned book".to_vec();
+        let size = pdf.len().to_string();
+        let sha256 = hex::encode(<sha2::Sha256 as sha2::Digest>::digest(&pdf));
+
+        for (signature, corrupt) in [("0123456789abcdef", true), ("fedcba9876543210", false)] {
+            let form = multipart(&[
+                ("file_name", b"scan.pdf"),
+                ("file_size", size.as_bytes()),
+                ("file_signature", signature.as_bytes()),
+                ("sha256", sha256.to_uppercase().as_bytes()),
+            ])
+            .await;
+            let (_, init) = read_json(handler::upload(State(state.clone()), upload_query("init"), form).await).await;
+            let (upload_id, key) = (init["upload_id"].as_str().unwrap(), init["key"].as_str().unwrap());
+            let mut received = pdf.clone();
+            if corrupt {
+                received[3] ^= 1;
+            }
+            store.upload_part(upload_id, key, received, 1).await.unwrap();
+
+            let form = multipart(&[("upload_id", upload_id.as_bytes()), ("key", key.as_bytes())]).await;
+            let resp = handler::upload(State(state.clone()), upload_query("complete"), form).await;
+            if corrupt {
+                assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+                assert!(store.get(key).await.is_none());
+                continue;
+            }
+            let (status, body) = read_json(resp).await;
+            assert_eq!(status, StatusCode::OK);
+            let book_id = body["books"][0]["id"].as_i64().unwrap() as i32;
+            let resp = handler::head_book_download(State(state.clone()), Path(book_id)).await;
+            assert_eq!(resp.headers()["x-checksum-sha256"], sha256.as_str());
+        }
+    }
+
+    /// Runs an upload through init, one chunk and complete, returning its key and the
+    /// complete response
+    async fn upload_file(
+        state: &AppState,
+        signature: &str,
+        file_name: &str,
+        bytes: &[u8],
+    ) -> (String, serde_json::Value) {
+        let size = bytes.len().to_string();
+        let form = multipart(&[
+            ("file_name", file_name.as_bytes()),
+            ("file_size", size.as_bytes()),
+            ("file_signature", signature.as_bytes()),
+        ])
+        .await;
+        let (_, init) = read_json(handler::upload(State(state.clone()), upload_query("init"), form).await).await;
+        let (upload_id, key) = (init["upload_id"].as_str().unwrap(), init["key"].as_str().unwrap());
+        let form = multipart(&[
+            ("upload_id", upload_id.as_bytes()),
+            ("key", key.as_bytes()),
+            ("part_number", b"1"),
+            ("chunk", bytes),
+        ])
+        .await;
+        handler::upload(State(state.clone()), upload_query("continue"), form).await;
+        let form = multipart(&[("upload_id", upload_id.as_bytes()), ("key", key.as_bytes())]).await;
+        let (status, body) =
+            read_json(handler::upload(State(state.clone()), upload_query("complete"), form).await).await;
+        assert_eq!(status, StatusCode::OK);
+        (key.to_string(), body)
+    }
+
+    #[tokio::test]
+    async fn reupload_becomes_a_version() {
+        let store = Arc::new(MemoryObjectStore::new());
+        let state = test_state_with_store(store.clone()).await;
+        let first = sample_epub("Structure and Interpretation", &["Harold Abelson"]);
+        let second = sample_epub("Structure and Interpretation", &["Gerald Jay Sussman"]);
+
+        let (_, body) = upload_file(&state, "0123456789abcdef", "sicp.epub", &first).await;
+        assert!(body.get("matches").is_none());
+        let book_id = body["books"][0]["id"].as_i64().unwrap() as i32;
+
+        // Same title and ISBN, different file: offered as a version instead of a new book
+        let (key, body) = upload_file(&state, "fedcba9876543210", "sicp-2e.epub", &second).await;
+        assert_eq!(body["matches"][0]["id"], book_id);
+        assert!(body.get("books").is_none());
+
+        let resp =
+            handler::attach_book_version(State(state.clone()), Path(book_id), Json(AttachVersionRequest { key })).await;
+        let (status, body) = read_json(resp).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["books"][0]["download_url"], "memory://fedcba9876543210_sicp-2e.epub");
+
+        let (_, body) = read_json(handler::get_book_versions(State(state.clone()), Path(book_id)).await).await;
+        assert_eq!(body["versions"].as_array().unwrap().len(), 1);
+        let version = &body["versions"][0];
+        assert_eq!(version["sha256"], hex::encode(<sha2::Sha256 as sha2::Digest>::digest(&first)).as_str());
+
+        let resp = handler::download_book(State(state.clone()), Path(book_id), Query(Default::default())).await;
+        let bytes = axum::body::to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(bytes.as_ref(), second.as_slice());
+        let version_id = version["id"].as_i64().unwrap() as i32;
+        let resp = handler::download_book_version(
+            State(state.clone()),
+            Path((book_id, version_id)),
+            Query(Default::default()),
+        )
+        .await;
+        let bytes = axum::body::to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(bytes.as_ref(), first.as_slice());
+    }
+
+    #[tokio::test]
+    async fn private_books_are_not_served_in_public_mode() {
+        let store = Arc::new(MemoryObjectStore::new());
+        let mut state = test_state_with_store(store).await;
+        let first = sample_epub("Structure and Interpretation", &["Harold Abelson"]);
+        let second = sample_epub("Structure and Interpretation", &["Gerald Jay Sussman"]);
+        let (_, body) = upload_file(&state, "0123456789abcdef", "sicp.epub", &first).await;
+        let book_id = body["books"][0]["id"].as_i64().unwrap() as i32;
+        let (key, _) = upload_file(&state, "fedcba9876543210", "sicp-2e.epub", &second).await;
+        handler::attach_book_version(State(state.clone()), Path(book_id), Json(AttachVersionRequest { key })).await;
+        let (_, body) = read_json(handler::get_book_versions(State(state.clone()), Path(book_id)).await).await;
+        let version_id = body["versions"][0]["id"].as_i64().unwrap() as i32;
+        state
+            .db
+            .connection()
+            .execute("UPDATE books SET visibility = 'private' WHERE id = ?", libsql::params![book_id])
+            .await
+            .unwrap();
+
+        let statuses = |state: AppState| async move {
+            let download = handler::download_book(State(state.clone()), Path(book_id), Query(Default::default()));
+            let head = handler::head_book_download(State(state.clone()), Path(book_id));
+            let version = handler::download_book_version(
+                State(state.clone()),
+                Path((book_id, version_id)),
+                Query(Default::default()),
+            );
+            let cover = handler::get_book_cover(State(state.clone()), Path(book_id));
+            [download.await, head.await, version.await, cover.await].map(|resp| resp.status())
+        };
+        // Only the public library hides private books; the owner's app still gets them
+        assert!(
+            statuses(state.clone())
+                .await
+                .iter()
+                .all(|status| *status != StatusCode::NOT_FOUND)
+        );
+        state.public = Arc::new(crate::config::Public {
+            enabled: true,
+            base_url: None,
+        });
+        assert_eq!(statuses(state.clone()).await, [StatusCode::NOT_FOUND; 4]);
+
+        state
+            .db
+            .connection()
+            .execute("UPDATE books SET visibility = 'shared' WHERE id = ?", libsql::params![book_id])
+            .await
+            .unwrap();
+        assert!(
+            statuses(state)
+                .await
+                .iter()
+                .all(|status| *status != StatusCode::NOT_FOUND)
+        );
+    }
+
+    #[tokio::test]
+    async fn enrich_unknown_book_is_not_found() {
+        let state = test_state().await;
+        let resp = handler::enrich_book(State(state), Path(42), None).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
 }
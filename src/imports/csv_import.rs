@@ -0,0 +1,214 @@
+//! Stages highlights from any CSV export (`POST /import/csv`). Rather than an
+//! importer per tool, the request says which column holds what:
+//!
+//! ```json
+//! {"highlight": "Quote", "url": "Link", "title": "Article", "note": "Comment", "date": "Created"}
+//! ```
+//!
+//! Only `highlight` is required, along with `url` or `title` to know where a highlight
+//! was made. Rows with a web URL go to that page's website resource; the others to the
+//! resource named by their title. Each highlight gets an external id from its text and
+//! resource, so staging the same file again skips what was already imported.
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use serde::Deserialize;
+
+use super::lib::{CreateImportBatch, StagedRecord};
+use crate::commonplace::capture::is_capturable;
+use crate::commonplace::compute_resource_hash;
+
+/// Largest CSV accepted by the import endpoint
+pub const MAX_CSV_BYTES: usize = 32 * 1024 * 1024;
+
+const DEFAULT_SOURCE: &str = "csv";
+
+/// Date formats tried, after RFC 3339, for the `date` column
+const DATE_TIME_FORMATS: &[&str] = &[
+    "%Y-%m-%d %H:%M:%S",
+    "%Y-%m-%dT%H:%M:%S",
+    "%Y-%m-%d %H:%M",
+    "%B %d, %Y %I:%M %p",
+];
+const DATE_FORMATS: &[&str] = &["%Y-%m-%d", "%B %d, %Y", "%d %B %Y"];
+
+/// Which column holds what, by header name (matched without regard to case)
+#[derive(Debug, Clone, Deserialize)]
+pub struct CsvMapping {
+    pub highlight: String,
+    #[serde(default)]
+    pub url: Option<String>,
+    #[serde(default)]
+    pub title: Option<String>,
+    #[serde(default)]
+    pub note: Option<String>,
+    #[serde(default)]
+    pub date: Option<String>,
+    #[serde(default)]
+    pub color: Option<String>,
+    /// Names the batch, e.g. the tool the file came from; "csv" if not given
+    #[serde(default)]
+    pub source: Option<String>,
+    /// Defaults to a comma
+    #[serde(default)]
+    pub delimiter: Option<char>,
+}
+
+/// A date as the timestamps here are written, or None if it is in no known format
+fn parse_date(value: &str) -> Option<String> {
+    let utc = if let Ok(date) = DateTime::parse_from_rfc3339(value) {
+        date.with_timezone(&Utc)
+    } else if let Some(date) = DATE_TIME_FORMATS
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+    {
+        date.and_utc()
+    } else {
+        let date = DATE_FORMATS
+            .iter()
+            .find_map(|format| NaiveDate::parse_from_str(value, format).ok())?;
+        date.and_hms_opt(0, 0, 0)?.and_utc()
+    };
+    Some(utc.format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string())
+}
+
+/// Reads the CSV into a batch to stage, or says what is wrong with it
+pub fn parse(body: &[u8], mapping: &CsvMapping) -> Result<CreateImportBatch, String> {
+    if mapping.url.is_none() && mapping.title.is_none() {
+        return Err("mapping needs a url or title column".to_string());
+    }
+    let delimiter = mapping.delimiter.unwrap_or(',');
+    if !delimiter.is_ascii() {
+        return Err("delimiter must be an ASCII character".to_string());
+    }
+
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter as u8)
+        .flexible(true)
+        .from_reader(body);
+    let headers = reader.headers().map_err(|e| format!("Invalid CSV: {}", e))?.clone();
+    let column = |name: &Option<String>| -> Result<Option<usize>, String> {
+        let Some(name) = name else {
+            return Ok(None);
+        };
+        let name = name.trim().trim_start_matches('\u{feff}');
+        headers
+            .iter()
+            .position(|header| header.trim().trim_start_matches('\u{feff}').eq_ignore_ascii_case(name))
+            .map(Some)
+            .ok_or_else(|| format!("No column named \"{}\"", name))
+    };
+    let highlight = column(&Some(mapping.highlight.clone()))?;
+    let (url, title, note) = (column(&mapping.url)?, column(&mapping.title)?, column(&mapping.note)?);
+    let (date, color) = (column(&mapping.date)?, column(&mapping.color)?);
+
+    let mut items = Vec::new();
+    for (index, row) in reader.records().enumerate() {
+        // Line 1 is the header
+        let line = index + 2;
+        let row = row.map_err(|e| format!("Invalid CSV: {}", e))?;
+        let field = |column: Option<usize>| {
+            column
+                .and_then(|i| row.get(i))
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+        };
+        let Some(text) = field(highlight) else {
+            continue;
+        };
+        let url = field(url).filter(|url| is_capturable(url));
+        let Some(resource_title) = url.or(field(title)) else {
+            return Err(format!("Line {} has neither a URL nor a title", line));
+        };
+        let highlighted_at = match field(date) {
+            Some(value) => {
+                Some(parse_date(value).ok_or_else(|| format!("Line {}: unrecognized date \"{}\"", line, value))?)
+            }
+            None => None,
+        };
+        let hash = compute_resource_hash(&format!("{}\n{}", resource_title, text));
+
+        items.push(StagedRecord::Annotation {
+            resource_title: resource_title.to_string(),
+            text: text.to_string(),
+            color: field(color).map(str::to_string),
+            note: field(note).map(str::to_string),
+            external_id: Some(format!("csv:{}", &hash[..16])),
+            url: url.map(str::to_string),
+            highlighted_at,
+        });
+    }
+
+    let source = mapping
+        .source
+        .as_deref()
+        .map(str::trim)
+        .filter(|source| !source.is_empty())
+        .unwrap_or(DEFAULT_SOURCE);
+    Ok(CreateImportBatch {
+        source: source.to_string(),
+        items,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::imports::{ImportAction, Imports};
+    use crate::test_support::test_db;
+
+    #[tokio::test]
+    async fn test_mapped_columns_stage_highlights() {
+        let csv = "Quote;Link;Article;Comment;Created\n\
+                   Read slowly;https://example.com/essay;An essay;Yes;2024-03-01 10:30:00\n\
+                   A finding;;A Paper;;March 2, 2024\n\
+                   ;https://example.com/essay;An essay;;\n";
+        let mapping: CsvMapping = serde_json::from_value(serde_json::json!({
+            "highlight": "quote", "url": "Link", "title": "Article", "note": "Comment", "date": "Created",
+            "source": "instapaper", "delimiter": ";",
+        }))
+        .unwrap();
+        let batch = parse(csv.as_bytes(), &mapping).unwrap();
+        assert_eq!(batch.source, "instapaper");
+        let staged: Vec<_> = batch
+            .items
+            .iter()
+            .map(|item| match item {
+                StagedRecord::Annotation {
+                    resource_title,
+                    url,
+                    highlighted_at,
+                    ..
+                } => (resource_title.as_str(), url.is_some(), highlighted_at.as_deref()),
+                _ => unreachable!(),
+            })
+            .collect();
+        assert_eq!(
+            staged,
+            [
+                ("https://example.com/essay", true, Some("2024-03-01T10:30:00.000Z")),
+                ("A Paper", false, Some("2024-03-02T00:00:00.000Z")),
+            ]
+        );
+
+        let db = test_db().await;
+        let imports = Imports::new(&db);
+        let first = imports.stage(batch.clone()).await.unwrap();
+        let summary = imports.commit(&first).await.unwrap();
+        assert_eq!(summary.created, 2);
+        let again = imports.stage(batch).await.unwrap();
+        let actions: Vec<_> = imports
+            .list_items(again.id)
+            .await
+            .unwrap()
+            .iter()
+            .map(|item| item.action)
+            .collect();
+        assert_eq!(actions, [ImportAction::Skip, ImportAction::Skip]);
+
+        let bad = CsvMapping {
+            highlight: "Missing".to_string(),
+            ..mapping
+        };
+        assert!(parse(csv.as_bytes(), &bad).is_err());
+    }
+}
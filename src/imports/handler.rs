@@ -0,0 +1,344 @@
+use axum::{
+    Json,
+    extract::{Multipart, Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+
+use super::csv_import::{self, CsvMapping};
+use super::lib::{BatchStatus, CreateImportBatch, ImportAction, ImportBatch, ImportBatchDetail, Imports, RemapItem};
+use crate::handler::AppState;
+use crate::response::{bad_request, internal_error, not_found, success};
+
+/// Loads a batch that can still be changed, or the response explaining why not.
+async fn pending_batch(imports: &Imports<'_>, id: i32) -> Result<ImportBatch, Response> {
+    match imports.get_batch(id).await {
+        Ok(Some(batch)) if batch.status == BatchStatus::Pending => Ok(batch),
+        Ok(Some(batch)) => Err(bad_request(&format!("Import batch is already {}", batch.status.as_str()))),
+        Ok(None) => Err(not_found("Import batch not found")),
+        Err(e) => {
+            tracing::error!("Failed to get import batch: {}", e);
+            Err(internal_error("Failed to get import batch"))
+        }
+    }
+}
+
+pub async fn stage_batch(State(state): State<AppState>, Json(payload): Json<CreateImportBatch>) -> Response {
+    if payload.source.trim().is_empty() {
+        return bad_request("source is required");
+    }
+    if payload.items.is_empty() {
+        return bad_request("items cannot be empty");
+    }
+
+    match Imports::new(&state.db).stage(payload).await {
+        Ok(batch) => (StatusCode::CREATED, Json(crate::response::ApiResponse { data: batch })).into_response(),
+        Err(e) => {
+            tracing::error!("Failed to stage import batch: {}", e);
+            internal_error("Failed to stage import batch")
+        }
+    }
+}
+
+/// Stages a CSV of highlights (multipart fields `file` and `mapping`, the latter
+/// the JSON column mapping described in [`csv_import`]).
+pub async fn import_csv(State(state): State<AppState>, mut multipart: Multipart) -> Response {
+    let mut file = None;
+    let mut mapping = None;
+    loop {
+        let field = match multipart.next_field().await {
+            Ok(Some(field)) => field,
+            Ok(None) => break,
+            Err(e) => return bad_request(&format!("Invalid form: {}", e)),
+        };
+        match field.name() {
+            Some("file") => match field.bytes().await {
+                Ok(bytes) => file = Some(bytes),
+                Err(e) => return bad_request(&format!("Failed to read file: {}", e)),
+            },
+            Some("mapping") => {
+                let text = match field.text().await {
+                    Ok(text) => text,
+                    Err(e) => return bad_request(&format!("Failed to read mapping: {}", e)),
+                };
+                match serde_json::from_str::<CsvMapping>(&text) {
+                    Ok(parsed) => mapping = Some(parsed),
+                    Err(e) => return bad_request(&format!("Invalid mapping: {}", e)),
+                }
+            }
+            _ => {}
+        }
+    }
+    let (Some(file), Some(mapping)) = (file, mapping) else {
+        return bad_request("file and mapping are required");
+    };
+
+    let batch = match csv_import::parse(&file, &mapping) {
+        Ok(batch) => batch,
+        Err(e) => return bad_request(&e),
+    };
+    if batch.items.is_empty() {
+        return bad_request("No highlights found in the CSV");
+    }
+
+    match Imports::new(&state.db).stage(batch).await {
+        Ok(batch) => (StatusCode::CREATED, Json(crate::response::ApiResponse { data: batch })).into_response(),
+        Err(e) => {
+            tracing::error!("Failed to stage CSV import: {}", e);
+            internal_error("Failed to stage CSV import")
+        }
+    }
+}
+
+pub async fn list_batches(State(state): State<AppState>) -> Response {
+    match Imports::new(&state.db).list_batches().await {
+        Ok(batches) => success(batches),
+        Err(e) => {
+            tracing::error!("Failed to list import batches: {}", e);
+            internal_error("Failed to list import batches")
+        }
+    }
+}
+
+pub async fn get_batch(State(state): State<AppState>, Path(id): Path<i32>) -> Response {
+    let imports = Imports::new(&state.db);
+    let batch = match imports.get_batch(id).await {
+        Ok(Some(batch)) => batch,
+        Ok(None) => return not_found("Import batch not found"),
+        Err(e) => {
+            tracing::error!("Failed to get import batch: {}", e);
+            return internal_error("Failed to get import batch");
+        }
+    };
+
+    match imports.list_items(id).await {
+        Ok(items) => success(ImportBatchDetail { batch, items }),
+        Err(e) => {
+            tracing::error!("Failed to list import items: {}", e);
+            internal_error("Failed to list import items")
+        }
+    }
+}
+
+pub async fn remap_item(
+    State(state): State<AppState>,
+    Path((id, item_id)): Path<(i32, i32)>,
+    Json(payload): Json<RemapItem>,
+) -> Response {
+    let imports = Imports::new(&state.db);
+    if let Err(resp) = pending_batch(&imports, id).await {
+        return resp;
+    }
+
+    if payload.action == ImportAction::Merge {
+        let target_exists = match (payload.target_book_id, payload.target_resource_id) {
+            (Some(book_id), _) => state.db.get_book_by_id(book_id).await.map(|b| b.is_some()),
+            (None, Some(resource_id)) => state
+                .db
+                .commonplace()
+                .get_resource(resource_id)
+                .await
+                .map(|r| r.is_some()),
+            (None, None) => return bad_request("merge requires target_book_id or target_resource_id"),
+        };
+        match target_exists {
+            Ok(true) => {}
+            Ok(false) => return bad_request("Merge target not found"),
+            Err(e) => {
+                tracing::error!("Failed to check merge target: {}", e);
+                return internal_error("Failed to remap import item");
+            }
+        }
+    }
+
+    match imports.remap_item(id, item_id, &payload).await {
+        Ok(Some(item)) => success(item),
+        Ok(None) => not_found("Import item not found"),
+        Err(e) => {
+            tracing::error!("Failed to remap import item: {}", e);
+            internal_error("Failed to remap import item")
+        }
+    }
+}
+
+pub async fn commit_batch(State(state): State<AppState>, Path(id): Path<i32>) -> Response {
+    let imports = Imports::new(&state.db);
+    let batch = match pending_batch(&imports, id).await {
+        Ok(batch) => batch,
+        Err(resp) => return resp,
+    };
+
+    match imports.commit(&batch).await {
+        Ok(summary) => {
+            tracing::info!(batch = id, ?summary, "committed import batch");
+            success(summary)
+        }
+        Err(e) => {
+            tracing::error!("Failed to commit import batch: {}", e);
+            internal_error("Failed to commit import batch")
+        }
+    }
+}
+
+pub async fn discard_batch(State(state): State<AppState>, Path(id): Path<i32>) -> Response {
+    let imports = Imports::new(&state.db);
+    if let Err(resp) = pending_batch(&imports, id).await {
+        return resp;
+    }
+
+    if let Err(e) = imports.set_status(id, BatchStatus::Discarded).await {
+        tracing::error!("Failed to discard import batch: {}", e);
+        return internal_error("Failed to discard import batch");
+    }
+    match imports.get_batch(id).await {
+        Ok(Some(batch)) => success(batch),
+        Ok(None) => not_found("Import batch not found"),
+        Err(e) => {
+            tracing::error!("Failed to get import batch: {}", e);
+            internal_error("Failed to get import batch")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::imports::StagedRecord;
+    use crate::test_support::{read_json, seed_book, test_state};
+
+    fn book(title: &str) -> StagedRecord {
+        StagedRecord::Book {
+            title: title.to_string(),
+            authors: vec![],
+            tags: vec![],
+            categories: vec![],
+            description: None,
+            pages: None,
+            ratings: None,
+            isbn: None,
+            url: None,
+        }
+    }
+
+    async fn stage(state: &AppState, items: Vec<StagedRecord>) -> i32 {
+        let payload = CreateImportBatch {
+            source: "goodreads".to_string(),
+            items,
+        };
+        let (status, body) = read_json(stage_batch(State(state.clone()), Json(payload)).await).await;
+        assert_eq!(status, StatusCode::CREATED);
+        body["data"]["id"].as_i64().unwrap() as i32
+    }
+
+    async fn count_books(state: &AppState, title: &str) -> i64 {
+        let mut rows = state
+            .db
+            .connection()
+            .query("SELECT COUNT(*) FROM books WHERE title = ?", libsql::params![title])
+            .await
+            .unwrap();
+        rows.next().await.unwrap().unwrap().get(0).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_stage_preview_commit() {
+        let state = test_state().await;
+        let dune = seed_book(&state.db, "Dune", &["Frank Herbert"]).await;
+        let id = stage(
+            &state,
+            vec![
+                book("dune"),
+                book("Hyperion"),
+                StagedRecord::Annotation {
+                    resource_title: "Hyperion".to_string(),
+                    text: "The Shrike waited".to_string(),
+                    color: None,
+                    note: Some("ominous".to_string()),
+                    external_id: Some("gr-1".to_string()),
+                    url: None,
+                    highlighted_at: None,
+                },
+            ],
+        )
+        .await;
+
+        // Nothing reaches the library until the batch is committed
+        assert_eq!(count_books(&state, "Hyperion").await, 0);
+        let (status, body) = read_json(get_batch(State(state.clone()), Path(id)).await).await;
+        assert_eq!(status, StatusCode::OK);
+        let items = body["data"]["items"].as_array().unwrap();
+        let actions: Vec<_> = items.iter().map(|i| i["action"].as_str().unwrap()).collect();
+        assert_eq!(actions, vec!["merge", "create", "create"]);
+        assert_eq!(items[0]["target_book_id"], dune);
+
+        let hyperion = items[1]["id"].as_i64().unwrap() as i32;
+        let remap = RemapItem {
+            action: ImportAction::Skip,
+            target_book_id: None,
+            target_resource_id: None,
+        };
+        let resp = remap_item(State(state.clone()), Path((id, hyperion)), Json(remap)).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let (status, body) = read_json(commit_batch(State(state.clone()), Path(id)).await).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["data"]["merged"], 1);
+        assert_eq!(body["data"]["skipped"], 1);
+        assert_eq!(body["data"]["created"], 1);
+        assert_eq!(body["data"]["failed"], 0);
+        assert_eq!(count_books(&state, "Hyperion").await, 0);
+        let annotation = state
+            .db
+            .commonplace()
+            .find_annotation_by_external_id("gr-1")
+            .await
+            .unwrap();
+        assert!(annotation.is_some());
+
+        // A committed batch can't be committed again
+        let resp = commit_batch(State(state.clone()), Path(id)).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_failed_commit_rolls_back() {
+        let state = test_state().await;
+        let id = stage(&state, vec![book("Hyperion"), book("Endymion")]).await;
+
+        // Fail the second item after its book was created and the first item was saved
+        state
+            .db
+            .connection()
+            .execute_batch(
+                r#"
+                CREATE TRIGGER fail_endymion BEFORE UPDATE OF result_id ON import_items
+                WHEN json_extract(NEW.data, '$.title') = 'Endymion'
+                BEGIN SELECT RAISE(ABORT, 'disk full'); END;
+                "#,
+            )
+            .await
+            .unwrap();
+
+        let resp = crate::tx::run(&state.db, commit_batch(State(state.clone()), Path(id))).await;
+        assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(count_books(&state, "Hyperion").await, 0);
+        assert_eq!(count_books(&state, "Endymion").await, 0);
+        let batch = Imports::new(&state.db).get_batch(id).await.unwrap().unwrap();
+        assert_eq!(batch.status, BatchStatus::Pending);
+        assert!(batch.commit_started_at.is_none());
+        assert!(batch.committed_through.is_none());
+
+        state
+            .db
+            .connection()
+            .execute("DROP TRIGGER fail_endymion", ())
+            .await
+            .unwrap();
+        let resp = crate::tx::run(&state.db, commit_batch(State(state.clone()), Path(id))).await;
+        let (status, body) = read_json(resp).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["data"]["created"], 2);
+        assert_eq!(body["data"]["already_processed"], 0);
+        assert_eq!(count_books(&state, "Endymion").await, 1);
+    }
+}
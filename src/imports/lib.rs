@@ -0,0 +1,579 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::api::PatchBookRequest;
+use crate::commonplace::capture::is_capturable;
+use crate::commonplace::{
+    CreateAnnotation, CreateComment, CreateResource, ResourceType, compute_annotation_hash, compute_resource_hash,
+};
+use crate::db::Database;
+use crate::patch::Patch;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BatchStatus {
+    Pending,
+    Committed,
+    Discarded,
+}
+
+impl BatchStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BatchStatus::Pending => "pending",
+            BatchStatus::Committed => "committed",
+            BatchStatus::Discarded => "discarded",
+        }
+    }
+
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "pending" => Some(BatchStatus::Pending),
+            "committed" => Some(BatchStatus::Committed),
+            "discarded" => Some(BatchStatus::Discarded),
+            _ => None,
+        }
+    }
+}
+
+/// What committing a staged item does: create a new row, map it onto an existing book
+/// or resource, or leave it out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportAction {
+    Create,
+    Merge,
+    Skip,
+}
+
+impl ImportAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ImportAction::Create => "create",
+            ImportAction::Merge => "merge",
+            ImportAction::Skip => "skip",
+        }
+    }
+
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "create" => Some(ImportAction::Create),
+            "merge" => Some(ImportAction::Merge),
+            "skip" => Some(ImportAction::Skip),
+            _ => None,
+        }
+    }
+}
+
+/// A record parsed by an importer
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum StagedRecord {
+    Book {
+        title: String,
+        #[serde(default)]
+        authors: Vec<String>,
+        #[serde(default)]
+        tags: Vec<String>,
+        #[serde(default)]
+        categories: Vec<String>,
+        description: Option<String>,
+        pages: Option<i32>,
+        ratings: Option<i32>,
+        isbn: Option<String>,
+        /// File url; books imported without a file get an `import://` placeholder
+        url: Option<String>,
+    },
+    Annotation {
+        /// Title of the book or page the highlight was made in
+        resource_title: String,
+        text: String,
+        color: Option<String>,
+        note: Option<String>,
+        external_id: Option<String>,
+        /// Page the highlight was made on; it goes to that page's website resource
+        #[serde(default)]
+        url: Option<String>,
+        /// When the highlight was made, if the export says
+        #[serde(default)]
+        highlighted_at: Option<String>,
+    },
+}
+
+impl StagedRecord {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            StagedRecord::Book { .. } => "book",
+            StagedRecord::Annotation { .. } => "annotation",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportBatch {
+    pub id: i32,
+    pub source: String,
+    pub status: BatchStatus,
+    pub item_count: i32,
+    pub committed_at: Option<String>,
+    /// Set once a commit has started; a pending batch with it set was interrupted and
+    /// committing it again resumes after `committed_through`
+    pub commit_started_at: Option<String>,
+    /// Id of the last item processed by a commit
+    pub committed_through: Option<i32>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportItem {
+    pub id: i32,
+    pub batch_id: i32,
+    pub record: StagedRecord,
+    pub action: ImportAction,
+    pub target_book_id: Option<i32>,
+    pub target_resource_id: Option<i32>,
+    /// Book or annotation created (or merged into) on commit
+    pub result_id: Option<i32>,
+    pub error: Option<String>,
+    pub updated_at: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImportBatchDetail {
+    #[serde(flatten)]
+    pub batch: ImportBatch,
+    pub items: Vec<ImportItem>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CreateImportBatch {
+    pub source: String,
+    pub items: Vec<StagedRecord>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RemapItem {
+    pub action: ImportAction,
+    pub target_book_id: Option<i32>,
+    pub target_resource_id: Option<i32>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct CommitSummary {
+    pub created: i32,
+    pub merged: i32,
+    pub skipped: i32,
+    pub failed: i32,
+    /// Items processed by an earlier, interrupted commit and left alone this time
+    pub already_processed: i32,
+    pub resumed_after: Option<i32>,
+}
+
+const BATCH_COLUMNS: &str = r#"
+    import_batches.id, import_batches.source, import_batches.status, import_batches.committed_at,
+    import_batches.created_at, import_batches.updated_at,
+    (SELECT COUNT(*) FROM import_items WHERE import_items.batch_id = import_batches.id),
+    import_batches.commit_started_at, import_batches.committed_through
+"#;
+
+const ITEM_COLUMNS: &str =
+    "id, batch_id, data, action, target_book_id, target_resource_id, result_id, error, updated_at";
+
+pub struct Imports<'a> {
+    db: &'a Database,
+}
+
+impl<'a> Imports<'a> {
+    pub fn new(db: &'a Database) -> Self {
+        Self { db }
+    }
+
+    fn row_to_batch(row: &libsql::Row) -> Result<ImportBatch> {
+        let status: String = row.get(2)?;
+        Ok(ImportBatch {
+            id: row.get(0)?,
+            source: row.get(1)?,
+            status: BatchStatus::from_str(&status).unwrap_or(BatchStatus::Pending),
+            committed_at: row.get(3)?,
+            created_at: row.get(4)?,
+            updated_at: row.get(5)?,
+            item_count: row.get(6)?,
+            commit_started_at: row.get(7)?,
+            committed_through: row.get(8)?,
+        })
+    }
+
+    fn row_to_item(row: &libsql::Row) -> Result<ImportItem> {
+        let data: String = row.get(2)?;
+        let action: String = row.get(3)?;
+        Ok(ImportItem {
+            id: row.get(0)?,
+            batch_id: row.get(1)?,
+            record: serde_json::from_str(&data)?,
+            action: ImportAction::from_str(&action).unwrap_or(ImportAction::Create),
+            target_book_id: row.get(4)?,
+            target_resource_id: row.get(5)?,
+            result_id: row.get(6)?,
+            error: row.get(7)?,
+            updated_at: row.get(8)?,
+        })
+    }
+
+    /// Stages `input` as a new pending batch. Records whose title matches an existing
+    /// book or resource are pre-mapped onto it, so re-importing doesn't duplicate them,
+    /// and annotations whose external id was already imported are skipped.
+    pub async fn stage(&self, input: CreateImportBatch) -> Result<ImportBatch> {
+        // Staging thousands of rows one autocommit at a time is slow on a synced replica
+        let batch_id = self.db.write(|| self.stage_internal(input)).await?;
+        self.get_batch(batch_id)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("import batch {} vanished after staging", batch_id))
+    }
+
+    async fn stage_internal(&self, input: CreateImportBatch) -> Result<i32> {
+        let conn = &self.db.connection();
+        let mut rows = conn
+            .query("INSERT INTO import_batches (source) VALUES (?) RETURNING id", libsql::params![input.source])
+            .await?;
+        let batch_id: i32 = rows
+            .next()
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("import batch insert returned no row"))?
+            .get(0)?;
+
+        for record in input.items {
+            let (target_book_id, target_resource_id) = match &record {
+                StagedRecord::Book { title, .. } => (self.find_book_id(title).await?, None),
+                StagedRecord::Annotation { resource_title, .. } => {
+                    let resource = self.db.commonplace().find_resource_by_title(resource_title).await?;
+                    (None, resource.map(|r| r.id))
+                }
+            };
+            let imported = match &record {
+                StagedRecord::Annotation {
+                    external_id: Some(external_id),
+                    ..
+                } => self
+                    .db
+                    .commonplace()
+                    .find_annotation_by_external_id(external_id)
+                    .await?
+                    .is_some(),
+                _ => false,
+            };
+            let action = match (&record, target_book_id, target_resource_id) {
+                _ if imported => ImportAction::Skip,
+                (StagedRecord::Book { .. }, Some(_), _) => ImportAction::Merge,
+                (StagedRecord::Annotation { .. }, _, Some(_)) => ImportAction::Merge,
+                _ => ImportAction::Create,
+            };
+
+            conn.execute(
+                r#"
+                INSERT INTO import_items (batch_id, kind, data, action, target_book_id, target_resource_id)
+                VALUES (?, ?, ?, ?, ?, ?)
+                "#,
+                libsql::params![
+                    batch_id,
+                    record.kind(),
+                    serde_json::to_string(&record)?,
+                    action.as_str(),
+                    target_book_id,
+                    target_resource_id
+                ],
+            )
+            .await?;
+        }
+
+        Ok(batch_id)
+    }
+
+    async fn find_book_id(&self, title: &str) -> Result<Option<i32>> {
+        let mut rows = self
+            .db
+            .connection()
+            .query(
+                "SELECT id FROM books WHERE title = ? COLLATE NOCASE AND deleted_at IS NULL LIMIT 1",
+                libsql::params![title.trim()],
+            )
+            .await?;
+        match rows.next().await? {
+            Some(row) => Ok(Some(row.get(0)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub async fn list_batches(&self) -> Result<Vec<ImportBatch>> {
+        let query = format!("SELECT {} FROM import_batches ORDER BY id DESC", BATCH_COLUMNS);
+        let mut rows = self.db.connection().query(&query, ()).await?;
+        let mut batches = Vec::new();
+        while let Some(row) = rows.next().await? {
+            batches.push(Self::row_to_batch(&row)?);
+        }
+        Ok(batches)
+    }
+
+    pub async fn get_batch(&self, id: i32) -> Result<Option<ImportBatch>> {
+        let query = format!("SELECT {} FROM import_batches WHERE id = ?", BATCH_COLUMNS);
+        let mut rows = self.db.connection().query(&query, libsql::params![id]).await?;
+        match rows.next().await? {
+            Some(row) => Ok(Some(Self::row_to_batch(&row)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub async fn list_items(&self, batch_id: i32) -> Result<Vec<ImportItem>> {
+        let query = format!("SELECT {} FROM import_items WHERE batch_id = ? ORDER BY id", ITEM_COLUMNS);
+        let mut rows = self.db.connection().query(&query, libsql::params![batch_id]).await?;
+        let mut items = Vec::new();
+        while let Some(row) = rows.next().await? {
+            items.push(Self::row_to_item(&row)?);
+        }
+        Ok(items)
+    }
+
+    pub async fn remap_item(&self, batch_id: i32, item_id: i32, remap: &RemapItem) -> Result<Option<ImportItem>> {
+        let query = format!(
+            r#"
+            UPDATE import_items
+            SET action = ?, target_book_id = ?, target_resource_id = ?,
+                updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
+            WHERE id = ? AND batch_id = ?
+            RETURNING {}
+            "#,
+            ITEM_COLUMNS
+        );
+        let mut rows = self
+            .db
+            .connection()
+            .query(
+                &query,
+                libsql::params![
+                    remap.action.as_str(),
+                    remap.target_book_id,
+                    remap.target_resource_id,
+                    item_id,
+                    batch_id
+                ],
+            )
+            .await?;
+        match rows.next().await? {
+            Some(row) => Ok(Some(Self::row_to_item(&row)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Applies every item of a pending batch to the library. Items are committed one by
+    /// one in id order; failures are recorded on the item and don't stop the rest of the
+    /// batch. Progress is saved after each item, so committing a batch whose previous
+    /// commit was interrupted only processes the items that commit never reached.
+    pub async fn commit(&self, batch: &ImportBatch) -> Result<CommitSummary> {
+        let mut summary = CommitSummary {
+            resumed_after: batch.committed_through,
+            ..Default::default()
+        };
+        if let Some(through) = batch.committed_through {
+            tracing::info!(batch = batch.id, "resuming import commit after item {}", through);
+        }
+        if batch.commit_started_at.is_none() {
+            // Through `write` so it opens the request's transaction and rolls back with it
+            self.db
+                .write(|| async {
+                    self.db
+                        .connection()
+                        .execute(
+                            "UPDATE import_batches SET commit_started_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now') WHERE id = ?",
+                            libsql::params![batch.id],
+                        )
+                        .await?;
+                    Ok(())
+                })
+                .await?;
+        }
+
+        for item in self.list_items(batch.id).await? {
+            // result_id also covers an item committed just before an interruption
+            // that hit before its progress was saved
+            if batch.committed_through.is_some_and(|through| item.id <= through) || item.result_id.is_some() {
+                summary.already_processed += 1;
+                continue;
+            }
+            if item.action == ImportAction::Skip {
+                summary.skipped += 1;
+                self.save_progress(batch.id, item.id).await?;
+                continue;
+            }
+
+            let (result_id, error) = match self.commit_item(batch, &item).await {
+                Ok(id) => {
+                    match item.action {
+                        ImportAction::Merge => summary.merged += 1,
+                        _ => summary.created += 1,
+                    }
+                    (Some(id), None)
+                }
+                Err(e) => {
+                    tracing::warn!("failed to commit import item {}: {}", item.id, e);
+                    summary.failed += 1;
+                    (None, Some(e.to_string()))
+                }
+            };
+            self.db
+                .connection()
+                .execute(
+                    r#"
+                    UPDATE import_items
+                    SET result_id = ?, error = ?, updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
+                    WHERE id = ?
+                    "#,
+                    libsql::params![result_id, error, item.id],
+                )
+                .await?;
+            self.save_progress(batch.id, item.id).await?;
+        }
+
+        self.set_status(batch.id, BatchStatus::Committed).await?;
+        Ok(summary)
+    }
+
+    async fn save_progress(&self, batch_id: i32, item_id: i32) -> Result<()> {
+        self.db
+            .connection()
+            .execute("UPDATE import_batches SET committed_through = ? WHERE id = ?", libsql::params![item_id, batch_id])
+            .await?;
+        Ok(())
+    }
+
+    /// Returns the id of the book or annotation the item ended up as.
+    async fn commit_item(&self, batch: &ImportBatch, item: &ImportItem) -> Result<i32> {
+        match &item.record {
+            StagedRecord::Book {
+                title,
+                authors,
+                tags,
+                categories,
+                description,
+                pages,
+                ratings,
+                isbn,
+                url,
+            } => {
+                if item.action == ImportAction::Merge {
+                    return item
+                        .target_book_id
+                        .ok_or_else(|| anyhow::anyhow!("merge without a target book"));
+                }
+                let url = url
+                    .clone()
+                    .unwrap_or_else(|| format!("import://{}/{}/{}", batch.source, batch.id, item.id));
+                let book_id = self
+                    .db
+                    .create_book(
+                        title,
+                        &url,
+                        None,
+                        description.as_deref(),
+                        *pages,
+                        *ratings,
+                        authors,
+                        tags,
+                        categories,
+                        "complete",
+                    )
+                    .await?;
+                if let Some(isbn) = isbn {
+                    let patch = PatchBookRequest {
+                        isbn: Patch::Value(isbn.clone()),
+                        ..Default::default()
+                    };
+                    self.db.patch_book(book_id, &patch).await?;
+                }
+                Ok(book_id)
+            }
+            StagedRecord::Annotation {
+                resource_title,
+                text,
+                color,
+                note,
+                external_id,
+                url,
+                highlighted_at,
+            } => {
+                let lib = self.db.commonplace();
+                let resource_id = match (item.action, item.target_resource_id) {
+                    (ImportAction::Merge, Some(id)) => id,
+                    (ImportAction::Merge, None) => anyhow::bail!("merge without a target resource"),
+                    _ if url.as_deref().is_some_and(is_capturable) => {
+                        let url = url.as_deref().unwrap_or_default();
+                        lib.find_or_create_website(url, Some(compute_resource_hash(url)))
+                            .await?
+                            .0
+                            .id
+                    }
+                    _ => match lib.find_resource_by_title(resource_title).await? {
+                        Some(resource) => resource.id,
+                        None => {
+                            lib.create_resource(CreateResource {
+                                title: resource_title.clone(),
+                                resource_type: ResourceType::Pdf,
+                                external_id: None,
+                                content_hash: Some(compute_resource_hash(resource_title)),
+                            })
+                            .await?
+                            .id
+                        }
+                    },
+                };
+
+                let annotation = lib
+                    .create_annotation(CreateAnnotation {
+                        resource_id,
+                        text: text.clone(),
+                        color: color.clone(),
+                        boundary: None,
+                        external_id: external_id.clone(),
+                        content_hash: Some(compute_annotation_hash(text, color.as_deref())),
+                    })
+                    .await?;
+                if let Some(highlighted_at) = highlighted_at {
+                    self.db
+                        .connection()
+                        .execute(
+                            "UPDATE annotations SET created_at = ? WHERE id = ?",
+                            libsql::params![highlighted_at.clone(), annotation.id],
+                        )
+                        .await?;
+                }
+                if let Some(note) = note.as_deref().filter(|n| !n.trim().is_empty()) {
+                    lib.create_comment(CreateComment {
+                        annotation_id: annotation.id,
+                        content: note.to_string(),
+                        external_id: None,
+                        content_hash: None,
+                        parent_comment_id: None,
+                    })
+                    .await?;
+                }
+                Ok(annotation.id)
+            }
+        }
+    }
+
+    pub async fn set_status(&self, batch_id: i32, status: BatchStatus) -> Result<()> {
+        let query = r#"
+            UPDATE import_batches
+            SET status = ?,
+                committed_at = CASE WHEN ? = 'committed' THEN strftime('%Y-%m-%dT%H:%M:%fZ', 'now') END,
+                updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
+            WHERE id = ?
+        "#;
+        self.db
+            .connection()
+            .execute(query, libsql::params![status.as_str(), status.as_str(), batch_id])
+            .await?;
+        Ok(())
+    }
+}
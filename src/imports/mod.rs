@@ -0,0 +1,12 @@
+//! Staged imports: importers (Calibre, Goodreads, Kindle) stage parsed records in a
+//! batch, which is reviewed and remapped onto existing books or resources through the
+//! API before being committed into the live library or discarded.
+
+pub mod csv_import;
+mod handler;
+mod lib;
+mod routes;
+
+pub use handler::import_csv;
+pub use lib::*;
+pub use routes::routes;
@@ -0,0 +1,16 @@
+use axum::{
+    Router,
+    routing::{get, post, put},
+};
+
+use super::handler;
+use crate::handler::AppState;
+
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/batches", get(handler::list_batches).post(handler::stage_batch))
+        .route("/batches/:id", get(handler::get_batch))
+        .route("/batches/:id/items/:item_id", put(handler::remap_item))
+        .route("/batches/:id/commit", post(handler::commit_batch))
+        .route("/batches/:id/discard", post(handler::discard_batch))
+}
@@ -0,0 +1,136 @@
+//! Healthcheck of the external services bibliotek depends on
+//! (`GET /admin/integrations/status`). Each one is pinged with a short timeout and
+//! reported as ok, rejecting our credentials, unreachable or not configured, so a
+//! misconfiguration shows up before a background job trips over it.
+
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+use axum::{extract::State, response::Response};
+use serde::Serialize;
+
+use crate::error::ObjectStorageError;
+use crate::handler::AppState;
+use crate::research;
+use crate::response::success;
+
+const CHECK_TIMEOUT_SECS: u64 = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Health {
+    Ok,
+    Unauthorized,
+    Unreachable,
+    NotConfigured,
+}
+
+#[derive(Debug, Serialize)]
+pub struct IntegrationStatus {
+    pub name: &'static str,
+    pub status: Health,
+    pub latency_ms: Option<u64>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StatusReport {
+    /// False if any configured integration is failing
+    pub healthy: bool,
+    pub integrations: Vec<IntegrationStatus>,
+}
+
+enum Failure {
+    Unauthorized(String),
+    Unreachable(String),
+}
+
+/// HTTP 401/403 means the service is up but does not accept our credentials
+fn http_failure(e: anyhow::Error) -> Failure {
+    let status = e.downcast_ref::<reqwest::Error>().and_then(|e| e.status());
+    match status {
+        Some(reqwest::StatusCode::UNAUTHORIZED | reqwest::StatusCode::FORBIDDEN) => {
+            Failure::Unauthorized(e.to_string())
+        }
+        _ => Failure::Unreachable(e.to_string()),
+    }
+}
+
+/// Runs one ping; it resolves to false when the integration is not configured
+async fn check<F>(name: &'static str, ping: F) -> IntegrationStatus
+where
+    F: Future<Output = Result<bool, Failure>>,
+{
+    let started = Instant::now();
+    let result = tokio::time::timeout(Duration::from_secs(CHECK_TIMEOUT_SECS), ping).await;
+    let latency_ms = Some(started.elapsed().as_millis() as u64);
+
+    let (status, latency_ms, error) = match result {
+        Ok(Ok(true)) => (Health::Ok, latency_ms, None),
+        Ok(Ok(false)) => (Health::NotConfigured, None, None),
+        Ok(Err(Failure::Unauthorized(e))) => (Health::Unauthorized, latency_ms, Some(e)),
+        Ok(Err(Failure::Unreachable(e))) => (Health::Unreachable, latency_ms, Some(e)),
+        Err(_) => (Health::Unreachable, latency_ms, Some(format!("no response within {}s", CHECK_TIMEOUT_SECS))),
+    };
+    if let Some(error) = &error {
+        tracing::warn!(integration = name, status = ?status, "integration check failed: {}", error);
+    }
+    IntegrationStatus {
+        name,
+        status,
+        latency_ms,
+        error,
+    }
+}
+
+pub async fn status(State(state): State<AppState>) -> Response {
+    let (storage, dictionary, openlibrary, google_books, research) = tokio::join!(
+        check("s3", async {
+            match state.resumable.ping().await {
+                Ok(()) => Ok(true),
+                Err(ObjectStorageError::AccessDenied(bucket)) => {
+                    Err(Failure::Unauthorized(format!("access denied to bucket {}", bucket)))
+                }
+                Err(e) => Err(Failure::Unreachable(crate::unpack_error(&e))),
+            }
+        }),
+        check("dictionary", async {
+            if !state.dictionary.is_enabled() {
+                return Ok(false);
+            }
+            state
+                .dictionary
+                .define("hello")
+                .await
+                .map(|_| true)
+                .map_err(http_failure)
+        }),
+        check("openlibrary", async {
+            state
+                .enricher
+                .ping_openlibrary()
+                .await
+                .map(|_| true)
+                .map_err(http_failure)
+        }),
+        check("google_books", async {
+            state
+                .enricher
+                .ping_google_books()
+                .await
+                .map(|_| true)
+                .map_err(http_failure)
+        }),
+        check("research", async {
+            research::check_database(&state.db.connection())
+                .await
+                .map_err(|e| Failure::Unreachable(e.to_string()))
+        }),
+    );
+
+    let integrations = vec![storage, dictionary, openlibrary, google_books, research];
+    let healthy = integrations
+        .iter()
+        .all(|i| matches!(i.status, Health::Ok | Health::NotConfigured));
+    success(StatusReport { healthy, integrations })
+}
@@ -0,0 +1,560 @@
+//! Persistent background jobs. Work a request kicks off but should not wait on
+//! (hashing an uploaded file, looking up book metadata, capturing a page) is written
+//! to the `jobs` table and run by a small pool of workers, rather than spawned as a
+//! task that a restart would lose. A failed job is retried with backoff until it runs
+//! out of attempts, then kept as `failed` for inspection in `GET /admin/jobs`.
+//!
+//! Events for external sinks go through the [outbox](crate::outbox) instead, which
+//! fans each event out to every sink.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use axum::{
+    extract::{Path, Query, State},
+    response::Response,
+};
+use libsql::Connection;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio_util::sync::CancellationToken;
+
+use crate::db::Database;
+use crate::handler::AppState;
+use crate::response::{bad_request, conflict, internal_error, not_found, success};
+
+pub const DEFAULT_MAX_ATTEMPTS: i64 = 5;
+pub const DEFAULT_LIMIT: i32 = 50;
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+const PRUNE_INTERVAL: Duration = Duration::from_secs(3600);
+const BASE_BACKOFF_SECS: i64 = 30;
+const MAX_BACKOFF_SECS: i64 = 3600;
+/// Finished jobs are kept this long before being pruned
+const RETENTION_DAYS: i64 = 7;
+const JOB_COLUMNS: &str =
+    "id, kind, payload, status, attempts, max_attempts, last_error, run_at, started_at, finished_at, created_at";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Done,
+    Failed,
+}
+
+impl JobStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JobStatus::Queued => "queued",
+            JobStatus::Running => "running",
+            JobStatus::Done => "done",
+            JobStatus::Failed => "failed",
+        }
+    }
+
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "queued" => Some(JobStatus::Queued),
+            "running" => Some(JobStatus::Running),
+            "done" => Some(JobStatus::Done),
+            "failed" => Some(JobStatus::Failed),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Job {
+    pub id: i64,
+    pub kind: String,
+    pub payload: Value,
+    pub status: JobStatus,
+    pub attempts: i64,
+    pub max_attempts: i64,
+    pub last_error: Option<String>,
+    pub run_at: String,
+    pub started_at: Option<String>,
+    pub finished_at: Option<String>,
+    pub created_at: String,
+}
+
+/// Runs the jobs of one kind. A job whose worker was stopped part way through is
+/// run again from the start, so handlers must be safe to repeat.
+#[async_trait]
+pub trait JobHandler: Send + Sync {
+    fn kind(&self) -> &'static str;
+
+    async fn run(&self, db: &Database, payload: &Value) -> Result<()>;
+}
+
+/// Queues a job to run as soon as a worker is free
+pub async fn enqueue(conn: &Connection, kind: &str, payload: &impl Serialize) -> Result<i64> {
+    let payload = serde_json::to_string(payload)?;
+    let mut rows = conn
+        .query(
+            "INSERT INTO jobs (kind, payload, max_attempts) VALUES (?, ?, ?) RETURNING id",
+            libsql::params![kind, payload, DEFAULT_MAX_ATTEMPTS],
+        )
+        .await?;
+    let row = rows
+        .next()
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("job insert returned no row"))?;
+    Ok(row.get(0)?)
+}
+
+/// Seconds to wait before the next attempt after `attempts` failures
+fn backoff_secs(attempts: i64) -> i64 {
+    let exponent = attempts.saturating_sub(1).clamp(0, 20) as u32;
+    BASE_BACKOFF_SECS.saturating_mul(1 << exponent).min(MAX_BACKOFF_SECS)
+}
+
+fn row_to_job(row: &libsql::Row) -> Result<Job> {
+    let payload: String = row.get(2)?;
+    let status: String = row.get(3)?;
+    Ok(Job {
+        id: row.get(0)?,
+        kind: row.get(1)?,
+        payload: serde_json::from_str(&payload).unwrap_or(Value::Null),
+        status: JobStatus::from_str(&status).ok_or_else(|| anyhow::anyhow!("unknown job status {}", status))?,
+        attempts: row.get(4)?,
+        max_attempts: row.get(5)?,
+        last_error: row.get(6)?,
+        run_at: row.get(7)?,
+        started_at: row.get(8)?,
+        finished_at: row.get(9)?,
+        created_at: row.get(10)?,
+    })
+}
+
+async fn query_jobs(conn: &Connection, query: &str, params: impl libsql::params::IntoParams) -> Result<Vec<Job>> {
+    let mut rows = conn.query(query, params).await?;
+    let mut jobs = Vec::new();
+    while let Some(row) = rows.next().await? {
+        jobs.push(row_to_job(&row)?);
+    }
+    Ok(jobs)
+}
+
+pub async fn get_job(conn: &Connection, id: i64) -> Result<Option<Job>> {
+    let query = format!("SELECT {} FROM jobs WHERE id = ?", JOB_COLUMNS);
+    Ok(query_jobs(conn, &query, libsql::params![id]).await?.pop())
+}
+
+/// Most recent jobs first, optionally only those with `status` or of `kind`
+pub async fn list_jobs(
+    conn: &Connection,
+    status: Option<JobStatus>,
+    kind: Option<&str>,
+    limit: i32,
+) -> Result<Vec<Job>> {
+    let query = format!(
+        r#"
+        SELECT {}
+        FROM jobs
+        WHERE (?1 IS NULL OR status = ?1)
+          AND (?2 IS NULL OR kind = ?2)
+        ORDER BY id DESC
+        LIMIT ?3
+        "#,
+        JOB_COLUMNS
+    );
+    query_jobs(conn, &query, libsql::params![status.map(|s| s.as_str()), kind, limit]).await
+}
+
+/// Every job run for `book_id`, most recent first
+pub async fn list_book_jobs(conn: &Connection, book_id: i32) -> Result<Vec<Job>> {
+    let query = format!("SELECT {} FROM jobs WHERE book_id = ? ORDER BY id DESC", JOB_COLUMNS);
+    query_jobs(conn, &query, libsql::params![book_id]).await
+}
+
+/// Queues failed jobs again with fresh attempts: the job `id`, or every failed job
+/// of `book_id`. Returns the jobs that were queued.
+pub async fn requeue_failed(conn: &Connection, id: Option<i64>, book_id: Option<i32>) -> Result<Vec<Job>> {
+    let query = format!(
+        r#"
+        UPDATE jobs
+        SET status = 'queued',
+            attempts = 0,
+            last_error = NULL,
+            run_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now'),
+            started_at = NULL,
+            finished_at = NULL
+        WHERE status = 'failed'
+          AND (?1 IS NULL OR id = ?1)
+          AND (?2 IS NULL OR book_id = ?2)
+        RETURNING {}
+        "#,
+        JOB_COLUMNS
+    );
+    query_jobs(conn, &query, libsql::params![id, book_id]).await
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct JobCounts {
+    pub queued: i64,
+    pub running: i64,
+    pub done: i64,
+    pub failed: i64,
+}
+
+pub async fn count_jobs(conn: &Connection) -> Result<JobCounts> {
+    let mut rows = conn
+        .query("SELECT status, COUNT(*) FROM jobs GROUP BY status", ())
+        .await?;
+    let mut counts = JobCounts::default();
+    while let Some(row) = rows.next().await? {
+        let status: String = row.get(0)?;
+        let count: i64 = row.get(1)?;
+        match JobStatus::from_str(&status) {
+            Some(JobStatus::Queued) => counts.queued = count,
+            Some(JobStatus::Running) => counts.running = count,
+            Some(JobStatus::Done) => counts.done = count,
+            Some(JobStatus::Failed) => counts.failed = count,
+            None => {}
+        }
+    }
+    Ok(counts)
+}
+
+pub struct JobRunner {
+    db: Arc<Database>,
+    workers: usize,
+    handlers: HashMap<&'static str, Arc<dyn JobHandler>>,
+}
+
+impl JobRunner {
+    pub fn new(db: Arc<Database>, workers: usize) -> Self {
+        Self {
+            db,
+            workers: workers.max(1),
+            handlers: HashMap::new(),
+        }
+    }
+
+    pub fn with_handler(mut self, handler: Arc<dyn JobHandler>) -> Self {
+        self.handlers.insert(handler.kind(), handler);
+        self
+    }
+
+    /// Runs a single-statement write under the transaction lock, so it can't end up
+    /// inside a transaction another task has open on the shared connection
+    async fn write(&self, query: &str, params: impl libsql::params::IntoParams) -> Result<Vec<Job>> {
+        let conn = self.db.connection();
+        self.db.write(|| query_jobs(&conn, query, params)).await
+    }
+
+    /// Takes the next due job, counting the attempt
+    async fn claim(&self) -> Result<Option<Job>> {
+        let query = format!(
+            r#"
+            UPDATE jobs
+            SET status = 'running',
+                attempts = attempts + 1,
+                started_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now'),
+                finished_at = NULL
+            WHERE id = (
+                SELECT id FROM jobs
+                WHERE status = 'queued' AND run_at <= strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
+                ORDER BY run_at, id
+                LIMIT 1
+            )
+            RETURNING {}
+            "#,
+            JOB_COLUMNS
+        );
+        Ok(self.write(&query, ()).await?.pop())
+    }
+
+    async fn finish(&self, job: &Job, result: Result<()>) -> Result<()> {
+        let (status, error, delay) = match result {
+            Ok(()) => (JobStatus::Done, None, 0),
+            Err(e) if job.attempts < job.max_attempts => {
+                tracing::warn!("job {} ({}) failed (attempt {}): {}", job.id, job.kind, job.attempts, e);
+                (JobStatus::Queued, Some(e.to_string()), backoff_secs(job.attempts))
+            }
+            Err(e) => {
+                tracing::error!("job {} ({}) failed for good after {} attempts: {}", job.id, job.kind, job.attempts, e);
+                (JobStatus::Failed, Some(e.to_string()), 0)
+            }
+        };
+        let query = format!(
+            r#"
+            UPDATE jobs
+            SET status = ?1,
+                last_error = ?2,
+                run_at = CASE WHEN ?1 = 'queued' THEN strftime('%Y-%m-%dT%H:%M:%fZ', 'now', ?3) ELSE run_at END,
+                finished_at = CASE WHEN ?1 = 'queued' THEN NULL ELSE strftime('%Y-%m-%dT%H:%M:%fZ', 'now') END
+            WHERE id = ?4
+            RETURNING {}
+            "#,
+            JOB_COLUMNS
+        );
+        let delay = format!("+{} seconds", delay);
+        self.write(&query, libsql::params![status.as_str(), error, delay, job.id])
+            .await?;
+        Ok(())
+    }
+
+    async fn run_job(&self, job: &Job) -> Result<()> {
+        let result = match self.handlers.get(job.kind.as_str()) {
+            Some(handler) => handler.run(&self.db, &job.payload).await,
+            None => Err(anyhow::anyhow!("no handler for job kind {}", job.kind)),
+        };
+        self.finish(job, result).await
+    }
+
+    /// Runs due jobs one at a time until none are left and returns how many ran
+    pub async fn run_pending(&self) -> Result<usize> {
+        let mut ran = 0;
+        while let Some(job) = self.claim().await? {
+            self.run_job(&job).await?;
+            ran += 1;
+        }
+        Ok(ran)
+    }
+
+    /// Queues jobs that were running when the process last stopped
+    pub async fn recover(&self) -> Result<u64> {
+        let query = r#"
+            UPDATE jobs
+            SET status = 'queued', run_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
+            WHERE status = 'running'
+        "#;
+        self.db
+            .write(|| async { Ok(self.db.connection().execute(query, ()).await?) })
+            .await
+    }
+
+    /// Removes finished jobs older than the retention window; failed ones are kept
+    pub async fn prune(&self) -> Result<u64> {
+        let query = r#"
+            DELETE FROM jobs
+            WHERE status = 'done'
+              AND finished_at < strftime('%Y-%m-%dT%H:%M:%fZ', 'now', ?)
+        "#;
+        let cutoff = format!("-{} days", RETENTION_DAYS);
+        self.db
+            .write(|| async { Ok(self.db.connection().execute(query, libsql::params![cutoff]).await?) })
+            .await
+    }
+
+    async fn work(&self, cancel: CancellationToken) {
+        loop {
+            match self.claim().await {
+                Ok(Some(job)) => {
+                    if let Err(e) = self.run_job(&job).await {
+                        tracing::warn!("failed to record result of job {}: {}", job.id, e);
+                    }
+                    continue;
+                }
+                Ok(None) => {}
+                Err(e) => tracing::warn!("failed to claim job: {}", e),
+            }
+            tokio::select! {
+                _ = tokio::time::sleep(POLL_INTERVAL) => {}
+                _ = cancel.cancelled() => break,
+            }
+        }
+    }
+
+    /// Requeues interrupted jobs, then starts the workers and an hourly prune
+    pub fn start(self, cancel: CancellationToken) {
+        let runner = Arc::new(self);
+        tokio::spawn(async move {
+            match runner.recover().await {
+                Ok(0) => {}
+                Ok(n) => tracing::info!("Requeued {} interrupted jobs", n),
+                Err(e) => tracing::warn!("failed to requeue interrupted jobs: {}", e),
+            }
+            for _ in 0..runner.workers {
+                let runner = runner.clone();
+                let cancel = cancel.clone();
+                tokio::spawn(async move { runner.work(cancel).await });
+            }
+
+            let mut interval = tokio::time::interval(PRUNE_INTERVAL);
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => {
+                        if let Err(e) = runner.prune().await {
+                            tracing::warn!("failed to prune jobs: {}", e);
+                        }
+                    }
+                    _ = cancel.cancelled() => {
+                        tracing::info!("Job workers shutting down");
+                        break;
+                    }
+                }
+            }
+        });
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct JobParams {
+    pub status: Option<String>,
+    pub kind: Option<String>,
+    pub limit: Option<i32>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct JobsOverview {
+    pub counts: JobCounts,
+    pub jobs: Vec<Job>,
+}
+
+/// `GET /admin/jobs`: counts per status and the most recent jobs
+pub async fn list(State(state): State<AppState>, Query(params): Query<JobParams>) -> Response {
+    let status = match params.status.as_deref().map(JobStatus::from_str) {
+        Some(None) => return bad_request("Unknown status, expected queued, running, done or failed"),
+        Some(status) => status,
+        None => None,
+    };
+    let limit = params.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, 500);
+    let conn = &state.db.connection();
+    let overview = async {
+        Ok::<_, anyhow::Error>(JobsOverview {
+            counts: count_jobs(conn).await?,
+            jobs: list_jobs(conn, status, params.kind.as_deref(), limit).await?,
+        })
+    }
+    .await;
+    match overview {
+        Ok(overview) => success(overview),
+        Err(e) => {
+            tracing::error!("Failed to list jobs: {}", e);
+            internal_error("Failed to list jobs")
+        }
+    }
+}
+
+/// `POST /admin/jobs/:id/retry`: queues a failed job again with fresh attempts
+pub async fn retry(State(state): State<AppState>, Path(id): Path<i64>) -> Response {
+    let conn = &state.db.connection();
+    match get_job(conn, id).await {
+        Ok(Some(job)) if job.status == JobStatus::Failed => {}
+        Ok(Some(_)) => return conflict("Only failed jobs can be retried"),
+        Ok(None) => return not_found("Job not found"),
+        Err(e) => {
+            tracing::error!("Failed to get job {}: {}", id, e);
+            return internal_error("Failed to retry job");
+        }
+    }
+    match requeue_failed(conn, Some(id), None).await {
+        Ok(mut jobs) => match jobs.pop() {
+            Some(job) => success(job),
+            None => conflict("Only failed jobs can be retried"),
+        },
+        Err(e) => {
+            tracing::error!("Failed to retry job {}: {}", id, e);
+            internal_error("Failed to retry job")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{seed_book, test_db};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Fails its first run, then succeeds
+    #[derive(Default)]
+    struct FlakyJob {
+        calls: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl JobHandler for FlakyJob {
+        fn kind(&self) -> &'static str {
+            "flaky"
+        }
+
+        async fn run(&self, _db: &Database, payload: &Value) -> Result<()> {
+            assert_eq!(payload["n"], 1);
+            if self.calls.fetch_add(1, Ordering::SeqCst) == 0 {
+                anyhow::bail!("timed out");
+            }
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failed_job_is_retried_and_unknown_kinds_fail() {
+        let db = test_db().await;
+        let runner = JobRunner::new(db.clone(), 1).with_handler(Arc::new(FlakyJob::default()));
+        let conn = &db.connection();
+        let flaky = enqueue(conn, "flaky", &serde_json::json!({"n": 1})).await.unwrap();
+        let orphan = enqueue(conn, "missing", &serde_json::json!({})).await.unwrap();
+        conn.execute("UPDATE jobs SET max_attempts = 1 WHERE id = ?", libsql::params![orphan])
+            .await
+            .unwrap();
+
+        assert_eq!(runner.run_pending().await.unwrap(), 2);
+        let job = get_job(conn, flaky).await.unwrap().unwrap();
+        assert_eq!((job.status, job.attempts), (JobStatus::Queued, 1));
+        assert_eq!(job.last_error.as_deref(), Some("timed out"));
+        let job = get_job(conn, orphan).await.unwrap().unwrap();
+        assert_eq!(job.status, JobStatus::Failed);
+
+        // Not due until the backoff has passed
+        assert_eq!(runner.run_pending().await.unwrap(), 0);
+        conn.execute("UPDATE jobs SET run_at = '2020-01-01T00:00:00.000Z' WHERE id = ?", libsql::params![flaky])
+            .await
+            .unwrap();
+        assert_eq!(runner.run_pending().await.unwrap(), 1);
+        let job = get_job(conn, flaky).await.unwrap().unwrap();
+        assert_eq!((job.status, job.attempts), (JobStatus::Done, 2));
+
+        let counts = count_jobs(conn).await.unwrap();
+        assert_eq!((counts.queued, counts.done, counts.failed), (0, 1, 1));
+    }
+
+    struct BrokenJob;
+
+    #[async_trait]
+    impl JobHandler for BrokenJob {
+        fn kind(&self) -> &'static str {
+            "book.enrich"
+        }
+
+        async fn run(&self, _db: &Database, _payload: &Value) -> Result<()> {
+            anyhow::bail!("provider unavailable")
+        }
+    }
+
+    #[tokio::test]
+    async fn test_book_shows_its_failed_jobs() {
+        let db = test_db().await;
+        let book_id = seed_book(&db, "Godel, Escher, Bach", &["Douglas Hofstadter"]).await;
+        let conn = &db.connection();
+        let id = enqueue(conn, "book.enrich", &serde_json::json!({ "book_id": book_id }))
+            .await
+            .unwrap();
+        conn.execute("UPDATE jobs SET max_attempts = 1", ()).await.unwrap();
+        JobRunner::new(db.clone(), 1)
+            .with_handler(Arc::new(BrokenJob))
+            .run_pending()
+            .await
+            .unwrap();
+
+        let book = db.get_book_by_id(book_id).await.unwrap().unwrap();
+        assert_eq!(book.jobs.get("book.enrich"), Some(&JobStatus::Failed));
+        assert_eq!(list_book_jobs(conn, book_id).await.unwrap()[0].id, id);
+        let requeued = requeue_failed(conn, None, Some(book_id)).await.unwrap();
+        assert_eq!((requeued.len(), requeued[0].status), (1, JobStatus::Queued));
+    }
+
+    #[test]
+    fn test_backoff_is_capped() {
+        assert_eq!(backoff_secs(1), 30);
+        assert_eq!(backoff_secs(DEFAULT_MAX_ATTEMPTS), 480);
+        assert_eq!(backoff_secs(20), MAX_BACKOFF_SECS);
+    }
+}
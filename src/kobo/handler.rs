@@ -0,0 +1,440 @@
+//! Highlights, notes and bookmarks from a Kobo e-reader, read straight from the
+//! device's database (`.kobo/KoboReader.sqlite` on the mounted reader, or a copy of
+//! it). Each book with something marked in it is matched to a resource: the one this
+//! sync created for it before, else the resource of the library book with the same
+//! ISBN, else a resource with the same title, and only then a new PDF resource.
+//! Highlights become annotations, with the note typed on them as a comment; notes
+//! without highlighted text and bookmarks become notes.
+//!
+//! The device is synced through the sync engine as the "kobo" source. Whatever was
+//! removed on the device is soft-deleted here on the next sync, but only for books
+//! still on it: deleting a finished book from the reader keeps its highlights.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use axum::{Json, extract::State, response::Response};
+use libsql::{Builder, Connection};
+use serde::{Deserialize, Serialize};
+
+use crate::commonplace::ResourceType;
+use crate::commonplace::boundary::Boundary;
+use crate::handler::AppState;
+use crate::response::{bad_request, internal_error, success};
+use crate::sync::engine::{self, SyncError, SyncResponse};
+use crate::sync::{ResourceKey, SourceAnnotation, SourceComment, SourceNote, SourceResource, SyncSource};
+
+/// `content.ContentType` of a book, as opposed to its chapters
+const CONTENT_TYPE_BOOK: i64 = 6;
+
+#[derive(Debug, Deserialize)]
+pub struct SetConfigRequest {
+    pub db_path: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ConfigResponse {
+    pub db_path: Option<String>,
+    pub last_sync_at: Option<String>,
+}
+
+/// Portable representation of the Kobo source used by the sync config archive
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KoboSourceConfig {
+    pub db_path: String,
+    pub last_sync_at: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct KoboBook {
+    content_id: String,
+    title: String,
+    isbn: Option<String>,
+    bookmarks: Vec<KoboBookmark>,
+}
+
+#[derive(Debug)]
+struct KoboBookmark {
+    id: String,
+    kind: String,
+    text: String,
+    annotation: String,
+    chapter: Option<String>,
+    chapter_title: Option<String>,
+    chapter_progress: Option<f64>,
+    start_path: Option<String>,
+}
+
+impl KoboBook {
+    fn title(&self) -> &str {
+        match self.title.trim() {
+            "" => &self.content_id,
+            title => title,
+        }
+    }
+
+    /// Highlights become annotations, with the note typed on them as a comment;
+    /// everything else that has something to say becomes a note
+    fn into_resource(self) -> SourceResource {
+        let title = self.title().to_string();
+        let mut annotations = Vec::new();
+        let mut notes = Vec::new();
+        for bookmark in self.bookmarks {
+            let external_id = format!("kobo:{}", bookmark.id);
+            let text = bookmark.text.trim();
+            if text.is_empty() {
+                if let Some(content) = bookmark.note() {
+                    notes.push(SourceNote { external_id, content });
+                }
+                continue;
+            }
+
+            let comments = Some(bookmark.annotation.trim())
+                .filter(|comment| !comment.is_empty())
+                .map(|comment| SourceComment {
+                    external_id: format!("kobo:comment:{}", bookmark.id),
+                    content: comment.to_string(),
+                })
+                .into_iter()
+                .collect();
+            let boundary = Boundary::from_kobo(
+                text,
+                bookmark.chapter.as_deref(),
+                bookmark.chapter_progress,
+                bookmark.start_path.as_deref(),
+            );
+            annotations.push(SourceAnnotation {
+                external_id,
+                text: text.to_string(),
+                color: None,
+                boundary: Some(boundary.into_value()),
+                comments,
+            });
+        }
+        SourceResource {
+            key: ResourceKey::Book {
+                external_id: format!("kobo:{}", self.content_id),
+                isbn: self.isbn,
+            },
+            title,
+            resource_type: ResourceType::Pdf,
+            annotations,
+            notes,
+            archived: None,
+        }
+    }
+}
+
+impl KoboBookmark {
+    /// What a note is made of when nothing was highlighted
+    fn note(&self) -> Option<String> {
+        let annotation = self.annotation.trim();
+        if !annotation.is_empty() {
+            return Some(annotation.to_string());
+        }
+        if self.kind != "dogear" {
+            return None;
+        }
+        Some(match self.chapter_title.as_deref().map(str::trim) {
+            Some(chapter) if !chapter.is_empty() => format!("Bookmark in {}", chapter),
+            _ => "Bookmark".to_string(),
+        })
+    }
+}
+
+pub async fn export_config(conn: &Connection) -> anyhow::Result<Option<KoboSourceConfig>> {
+    let query = r#"SELECT db_path, last_sync_at FROM kobo_config WHERE id = 1"#;
+    let mut rows = conn.query(query, ()).await?;
+
+    match rows.next().await? {
+        Some(row) => Ok(Some(KoboSourceConfig {
+            db_path: row.get(0)?,
+            last_sync_at: row.get(1)?,
+        })),
+        None => Ok(None),
+    }
+}
+
+/// The reader is often not plugged in, so the path does not need to exist
+pub async fn import_config(conn: &Connection, config: &KoboSourceConfig) -> anyhow::Result<()> {
+    let query = r#"
+        INSERT INTO kobo_config (id, db_path, last_sync_at)
+        VALUES (1, ?, ?)
+        ON CONFLICT(id) DO UPDATE SET
+            db_path = excluded.db_path,
+            last_sync_at = excluded.last_sync_at,
+            updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
+    "#;
+
+    conn.execute(query, libsql::params![config.db_path.clone(), config.last_sync_at.clone()])
+        .await?;
+    Ok(())
+}
+
+pub async fn get_config(State(state): State<AppState>) -> Response {
+    match export_config(&state.db.connection()).await {
+        Ok(config) => success(ConfigResponse {
+            db_path: config.as_ref().map(|c| c.db_path.clone()),
+            last_sync_at: config.and_then(|c| c.last_sync_at),
+        }),
+        Err(e) => {
+            tracing::error!("Failed to get Kobo config: {}", e);
+            internal_error("Failed to get config")
+        }
+    }
+}
+
+pub async fn set_config(State(state): State<AppState>, Json(payload): Json<SetConfigRequest>) -> Response {
+    if !Path::new(&payload.db_path).exists() {
+        return bad_request("Database file does not exist at the specified path");
+    }
+
+    let query = r#"
+        INSERT INTO kobo_config (id, db_path)
+        VALUES (1, ?)
+        ON CONFLICT(id) DO UPDATE SET
+            db_path = excluded.db_path,
+            updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
+    "#;
+
+    match state
+        .db
+        .connection()
+        .execute(query, libsql::params![payload.db_path.clone()])
+        .await
+    {
+        Ok(_) => success(ConfigResponse {
+            db_path: Some(payload.db_path),
+            last_sync_at: None,
+        }),
+        Err(e) => {
+            tracing::error!("Failed to set Kobo config: {}", e);
+            internal_error("Failed to save configuration")
+        }
+    }
+}
+
+/// The books read off the device, applied as a sync of the "kobo" source
+struct KoboDevice {
+    books: Mutex<Vec<KoboBook>>,
+}
+
+#[async_trait]
+impl SyncSource for KoboDevice {
+    fn name(&self) -> String {
+        "kobo".to_string()
+    }
+
+    /// A book taken off the reader is gone from it, not deleted
+    fn returned_only(&self) -> bool {
+        true
+    }
+
+    async fn fetch(&self) -> anyhow::Result<Vec<SourceResource>> {
+        let books = std::mem::take(&mut *self.books.lock().unwrap());
+        Ok(books.into_iter().map(KoboBook::into_resource).collect())
+    }
+}
+
+pub async fn sync(State(state): State<AppState>) -> Response {
+    let conn = &state.db.connection();
+    let db_path = match export_config(conn).await {
+        Ok(Some(config)) => config.db_path,
+        Ok(None) => return bad_request("Kobo database path not configured. Please set the path first."),
+        Err(e) => {
+            tracing::error!("Failed to get Kobo config: {}", e);
+            return internal_error("Failed to get config");
+        }
+    };
+    if !Path::new(&db_path).exists() {
+        return bad_request("Kobo database file does not exist at the configured path; is the reader connected?");
+    }
+
+    let books = async { read_device(&open_kobo_db(&db_path).await?).await }.await;
+    let books = match books {
+        Ok(books) => books,
+        Err(e) => {
+            tracing::error!("Failed to read Kobo database: {}", e);
+            return internal_error("Failed to read the Kobo database");
+        }
+    };
+
+    let source = KoboDevice {
+        books: Mutex::new(books),
+    };
+    let report = match engine::run(&state, &source).await {
+        Ok(report) => report,
+        Err(SyncError::Fetch(e)) => {
+            tracing::error!("Failed to read Kobo books: {}", e);
+            return internal_error("Failed to read the Kobo database");
+        }
+        Err(SyncError::StartRun(e)) => {
+            tracing::error!("Failed to start kobo sync run: {}", e);
+            return internal_error("Failed to start sync run");
+        }
+    };
+
+    let query = r#"
+        UPDATE kobo_config
+        SET last_sync_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now'),
+            updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
+        WHERE id = 1
+    "#;
+    if let Err(e) = state.db.write(|| async { Ok(conn.execute(query, ()).await?) }).await {
+        tracing::error!("Failed to record Kobo sync: {}", e);
+    }
+
+    success(SyncResponse::from(&report))
+}
+
+async fn open_kobo_db(db_path: &str) -> anyhow::Result<Connection> {
+    let db = Builder::new_local(db_path)
+        .flags(libsql::OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .build()
+        .await?;
+    Ok(db.connect()?)
+}
+
+/// Every book on the device, with its highlights, notes and bookmarks
+pub async fn read_device(kobo: &Connection) -> anyhow::Result<Vec<KoboBook>> {
+    let query = r#"
+        SELECT ContentID, Title, ISBN
+        FROM content
+        WHERE ContentType = ?
+        ORDER BY ContentID
+    "#;
+    let mut rows = kobo.query(query, libsql::params![CONTENT_TYPE_BOOK]).await?;
+    let mut books = Vec::new();
+    while let Some(row) = rows.next().await? {
+        books.push(KoboBook {
+            content_id: row.get(0)?,
+            title: row.get::<Option<String>>(1)?.unwrap_or_default(),
+            isbn: row
+                .get::<Option<String>>(2)?
+                .map(|isbn| isbn.trim().to_string())
+                .filter(|isbn| !isbn.is_empty()),
+            bookmarks: Vec::new(),
+        });
+    }
+
+    let query = r#"
+        SELECT b.BookmarkID, b.VolumeID, b.Type, b.Text, b.Annotation, b.ContentID, ch.Title,
+               b.ChapterProgress, b.StartContainerPath
+        FROM Bookmark b
+        LEFT JOIN content ch ON ch.ContentID = b.ContentID
+        ORDER BY b.VolumeID, b.DateCreated, b.BookmarkID
+    "#;
+    let mut rows = kobo.query(query, ()).await?;
+    let mut by_volume: HashMap<String, Vec<KoboBookmark>> = HashMap::new();
+    while let Some(row) = rows.next().await? {
+        let volume: String = row.get(1)?;
+        by_volume.entry(volume).or_default().push(KoboBookmark {
+            id: row.get(0)?,
+            kind: row.get::<Option<String>>(2)?.unwrap_or_default(),
+            text: row.get::<Option<String>>(3)?.unwrap_or_default(),
+            annotation: row.get::<Option<String>>(4)?.unwrap_or_default(),
+            chapter: row.get(5)?,
+            chapter_title: row.get(6)?,
+            chapter_progress: row.get(7)?,
+            start_path: row.get(8)?,
+        });
+    }
+
+    for book in &mut books {
+        book.bookmarks = by_volume.remove(&book.content_id).unwrap_or_default();
+    }
+    Ok(books)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commonplace::CreateResource;
+    use crate::test_support::test_state;
+
+    /// The parts of the Kobo schema the sync reads
+    const SCHEMA: &str = r#"
+        CREATE TABLE content (ContentID TEXT PRIMARY KEY, ContentType TEXT, Title TEXT, Attribution TEXT,
+            ISBN TEXT, BookID TEXT);
+        CREATE TABLE Bookmark (BookmarkID TEXT PRIMARY KEY, VolumeID TEXT, ContentID TEXT,
+            StartContainerPath TEXT, Text TEXT, Annotation TEXT, ChapterProgress REAL, DateCreated TEXT,
+            Type TEXT);
+        INSERT INTO content VALUES
+            ('file:///mnt/onboard/smart-notes.epub', '6', 'How to Take Smart Notes', 'Sönke Ahrens', '', NULL),
+            ('file:///mnt/onboard/smart-notes.epub#(3)ch03.xhtml', '9', 'Writing Is All That Matters', NULL, NULL,
+             'file:///mnt/onboard/smart-notes.epub'),
+            ('5f2c', '6', 'SICP', 'Abelson', '978-0-262-51087-5', NULL),
+            ('a0b1', '6', 'Unread on the shelf', NULL, NULL, NULL);
+        INSERT INTO Bookmark VALUES
+            ('b1', 'file:///mnt/onboard/smart-notes.epub', 'file:///mnt/onboard/smart-notes.epub#(3)ch03.xhtml',
+             'span#kobo\.4\.1', 'Writing is thinking.', 'see Luhmann', 0.25, '2026-01-02T10:00:00', 'note'),
+            ('b2', 'file:///mnt/onboard/smart-notes.epub', 'file:///mnt/onboard/smart-notes.epub#(3)ch03.xhtml',
+             'span#kobo\.9\.1', NULL, NULL, 0.5, '2026-01-02T10:05:00', 'dogear'),
+            ('b3', '5f2c', '5f2c!OEBPS!ch01.html', 'span#kobo\.2\.1', 'Programs must be written for people to read',
+             NULL, 0.1, '2026-01-03T09:00:00', 'highlight');
+    "#;
+
+    #[tokio::test]
+    async fn test_device_is_matched_and_synced() {
+        let kobo = Builder::new_local(":memory:").build().await.unwrap().connect().unwrap();
+        kobo.execute_batch(SCHEMA).await.unwrap();
+        let state = test_state().await;
+        let db = &state.db;
+        let lib = db.commonplace();
+        let sync = |books| KoboDevice {
+            books: Mutex::new(books),
+        };
+        let book_id = db
+            .create_book("SICP", "sicp.pdf", None, None, None, None, &[], &[], &[], "not_started")
+            .await
+            .unwrap();
+        db.connection()
+            .execute("UPDATE books SET isbn = '9780262510875' WHERE id = ?", libsql::params![book_id])
+            .await
+            .unwrap();
+        let sicp = lib
+            .create_resource(CreateResource {
+                title: "Structure and Interpretation of Computer Programs".to_string(),
+                resource_type: ResourceType::Pdf,
+                external_id: None,
+                content_hash: None,
+            })
+            .await
+            .unwrap();
+        db.connection()
+            .execute("UPDATE resources SET book_id = ? WHERE id = ?", libsql::params![book_id, sicp.id])
+            .await
+            .unwrap();
+
+        let books = read_device(&kobo).await.unwrap();
+        assert_eq!(books.len(), 3);
+        let first = engine::run(&state, &sync(books)).await.unwrap();
+        // The unmarked book is left alone, SICP matched by its ISBN
+        assert_eq!((first.resources.created, first.resources.unchanged), (1, 1));
+        assert_eq!((first.annotations.created, first.comments.created, first.notes.created), (2, 1, 1));
+        let highlight = lib.find_annotation_by_external_id("kobo:b3").await.unwrap().unwrap();
+        assert_eq!(highlight.resource_id, sicp.id);
+        let bookmark = lib.find_note_by_external_id("kobo:b2").await.unwrap().unwrap();
+        assert_eq!(bookmark.content, "Bookmark in Writing Is All That Matters");
+
+        let again = engine::run(&state, &sync(read_device(&kobo).await.unwrap()))
+            .await
+            .unwrap();
+        assert_eq!(again.totals().created + again.totals().updated + again.totals().deleted, 0);
+
+        // Removed on the device, but a deleted book keeps its highlights
+        kobo.execute_batch(
+            "DELETE FROM Bookmark WHERE BookmarkID = 'b1'; DELETE FROM content WHERE ContentID = '5f2c';",
+        )
+        .await
+        .unwrap();
+        let pruned = engine::run(&state, &sync(read_device(&kobo).await.unwrap()))
+            .await
+            .unwrap();
+        assert_eq!((pruned.annotations.deleted, pruned.comments.deleted, pruned.notes.deleted), (1, 1, 0));
+        assert_eq!(pruned.resources.deleted, 0);
+        assert!(lib.find_annotation_by_external_id("kobo:b3").await.unwrap().is_some());
+    }
+}
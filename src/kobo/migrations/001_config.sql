@@ -0,0 +1,10 @@
+-- Kobo Module Configuration
+-- Stores the path to the e-reader's database (.kobo/KoboReader.sqlite on the device)
+
+CREATE TABLE IF NOT EXISTS kobo_config (
+    id INTEGER PRIMARY KEY CHECK (id = 1), -- Only one config row allowed
+    db_path TEXT NOT NULL,
+    last_sync_at TEXT,
+    created_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    updated_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))
+);
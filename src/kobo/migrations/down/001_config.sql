@@ -0,0 +1 @@
+DROP TABLE IF EXISTS kobo_config;
@@ -10,17 +10,49 @@ use std::error::Error;
 
 pub mod api;
 pub mod assets;
+pub mod authors;
+pub mod catalog;
 pub mod commonplace;
 pub mod config;
+pub mod cover;
 pub mod db;
+pub mod enrich;
+pub mod epub_extract;
 pub mod error;
+pub mod events;
+pub mod feeds;
+pub mod fieldset;
 pub mod handler;
+pub mod imports;
+pub mod integrations;
+pub mod jobs;
+pub mod kobo;
 pub mod light;
+pub mod migrate;
+pub mod mirror;
 pub mod model;
+pub mod object_store;
+pub mod outbox;
+pub mod palette;
+pub mod patch;
 pub mod pdf_extract;
+pub mod pocket;
+pub mod public;
+pub mod queue;
+pub mod readwise;
 pub mod research;
 pub mod resumable;
+pub mod seed;
 pub mod sync;
+#[cfg(any(test, feature = "test-support"))]
+pub mod test_support;
+pub mod text_extract;
+pub mod titles;
+pub mod tx;
+pub mod views;
+pub mod wallabag;
+pub mod webhook;
+pub mod zotero;
 
 /// Generic response helpers for all modules
 pub mod response {
@@ -47,6 +79,18 @@ pub mod response {
     pub fn internal_error(msg: &str) -> Response {
         (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse { error: msg.to_string() })).into_response()
     }
+
+    pub fn not_found(msg: &str) -> Response {
+        (StatusCode::NOT_FOUND, Json(ErrorResponse { error: msg.to_string() })).into_response()
+    }
+
+    pub fn conflict(msg: &str) -> Response {
+        (StatusCode::CONFLICT, Json(ErrorResponse { error: msg.to_string() })).into_response()
+    }
+
+    pub fn bad_gateway(msg: &str) -> Response {
+        (StatusCode::BAD_GATEWAY, Json(ErrorResponse { error: msg.to_string() })).into_response()
+    }
 }
 
 // Legacy helpers for books module (uses APIResponse)
@@ -62,6 +106,10 @@ pub fn bad_request(body: APIResponse) -> Response {
     (StatusCode::BAD_REQUEST, Json(body)).into_response()
 }
 
+pub fn not_found(body: APIResponse) -> Response {
+    (StatusCode::NOT_FOUND, Json(body)).into_response()
+}
+
 fn good_response(body: APIResponse) -> Response {
     (StatusCode::OK, Json(body)).into_response()
 }
@@ -95,15 +143,15 @@ pub fn get_s3_url(service: &str, bucket: &str, key: &str) -> String {
 async fn safe_parse_str<'a>(field_name: &str, s: axum::extract::multipart::Field<'a>) -> Result<String, HandlerError> {
     s.text()
         .await
-        .map_err(|e| HandlerError::ValidationError(format!("{}: {}", field_name, e.to_string())))
+        .map_err(|e| HandlerError::ValidationError(format!("{}: {}", field_name, e)))
 }
 
 async fn safe_parse_num<'a>(field_name: &str, s: axum::extract::multipart::Field<'a>) -> Result<i32, HandlerError> {
     s.text()
         .await
-        .map_err(|e| HandlerError::ValidationError(format!("{}: {}", field_name, e.to_string())))?
+        .map_err(|e| HandlerError::ValidationError(format!("{}: {}", field_name, e)))?
         .parse::<i32>()
-        .map_err(|e| HandlerError::ValidationError(format!("{}: {}", field_name, e.to_string())))
+        .map_err(|e| HandlerError::ValidationError(format!("{}: {}", field_name, e)))
 }
 
 async fn safe_parse_bytes<'a>(
@@ -112,5 +160,5 @@ async fn safe_parse_bytes<'a>(
 ) -> Result<axum::body::Bytes, HandlerError> {
     s.bytes()
         .await
-        .map_err(|e| HandlerError::ValidationError(format!("{}: {}", field_name, e.to_string())))
+        .map_err(|e| HandlerError::ValidationError(format!("{}: {}", field_name, e)))
 }
@@ -1,16 +1,15 @@
+use async_trait::async_trait;
 use axum::{Json, extract::State, response::Response};
 use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
 
-use crate::commonplace::{
-    Commonplace, CreateAnnotation, CreateResource, ResourceType, UpdateAnnotation, compute_annotation_hash,
-    compute_resource_hash,
-};
+use crate::commonplace::ResourceType;
+use crate::commonplace::boundary::Boundary;
+use crate::commonplace::capture;
 use crate::handler::AppState;
-use crate::response::success;
-use crate::sync::{
-    SyncResult, Syncable, handle_create_result_unit, handle_update_result_unit, is_orphan, is_unchanged, log_find_error,
-};
+use crate::response::{bad_request, internal_error, success};
+use crate::sync::engine::{self, SyncError};
+use crate::sync::{ResourceKey, SourceAnnotation, SourceResource, SyncSource};
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct LightHighlight {
@@ -30,203 +29,128 @@ pub struct SyncRequest {
     pub highlights: HashMap<String, Vec<LightHighlight>>,
 }
 
+/// One page's highlights, sent as they change. Orphans are only looked for on that
+/// page, so the rest of the extension's store doesn't have to come along.
+#[derive(Debug, Deserialize)]
+pub struct UrlSyncRequest {
+    pub source: String,
+    pub url: String,
+    #[serde(default)]
+    pub highlights: Vec<LightHighlight>,
+}
+
 #[derive(Debug, Serialize, Default)]
 pub struct SyncResponse {
+    pub run_id: i64,
     pub resources_created: i32,
     pub annotations_created: i32,
     pub annotations_updated: i32,
     pub annotations_deleted: i32,
     pub annotations_unchanged: i32,
+    pub conflicts: i32,
+    pub errors: i32,
 }
 
-pub async fn sync_highlights(State(state): State<AppState>, Json(payload): Json<SyncRequest>) -> Response {
-    let lib = Commonplace::new(state.db.connection());
-    let mut stats = SyncResponse::default();
-    let mut seen_external_ids = HashSet::new();
-
-    for (url, highlights) in &payload.highlights {
-        let resource_id = match find_or_create_resource(&lib, url, &mut stats).await {
-            Some(id) => id,
-            None => continue,
-        };
-
-        for highlight in highlights {
-            sync_highlight(&lib, &payload.source, resource_id, highlight, &mut stats, &mut seen_external_ids).await;
-        }
-    }
-
-    soft_delete_orphan_annotations(&lib, &payload, &seen_external_ids, &mut stats).await;
-
-    success(stats)
+/// Highlights pushed by one browser's Light extension. Pages become website
+/// resources, shared with other sources, and highlights are keyed by
+/// `<source>:<groupID>`. With a scope, only that page's highlights were sent.
+struct LightSource {
+    payload: SyncRequest,
 }
 
-async fn find_or_create_resource(lib: &Commonplace<'_>, url: &str, stats: &mut SyncResponse) -> Option<i32> {
-    match lib.find_resource_by_title(url).await {
-        Ok(Some(resource)) => return Some(resource.id),
-        Ok(None) => {}
-        Err(e) => {
-            tracing::error!("Failed to find resource for {}: {}", url, e);
-            return None;
-        }
-    }
-
-    let content_hash = compute_resource_hash(url);
-    match lib
-        .create_resource(CreateResource {
-            title: url.to_string(),
-            resource_type: ResourceType::Website,
-            external_id: None,
-            content_hash: Some(content_hash),
-        })
-        .await
-    {
-        Ok(resource) => {
-            stats.resources_created += 1;
-            Some(resource.id)
-        }
-        Err(e) => {
-            tracing::error!("Failed to create resource for {}: {}", url, e);
-            None
-        }
+#[async_trait]
+impl SyncSource for LightSource {
+    fn name(&self) -> String {
+        format!("light:{}", self.payload.source)
     }
-}
 
-async fn sync_highlight(
-    lib: &Commonplace<'_>,
-    source: &str,
-    resource_id: i32,
-    highlight: &LightHighlight,
-    stats: &mut SyncResponse,
-    seen: &mut HashSet<String>,
-) {
-    let external_id = format!("{}:{}", source, highlight.group_id);
-    let content_hash = compute_annotation_hash(&highlight.repr, Some("yellow"));
-    seen.insert(external_id.clone());
-
-    match upsert_highlight(lib, &external_id, resource_id, highlight, &content_hash).await {
-        SyncResult::Created(()) => stats.annotations_created += 1,
-        SyncResult::Updated(()) => stats.annotations_updated += 1,
-        SyncResult::Unchanged(()) => stats.annotations_unchanged += 1,
-        SyncResult::Error => {}
+    fn prefix(&self) -> String {
+        self.payload.source.clone()
     }
-}
-
-async fn upsert_highlight(
-    lib: &Commonplace<'_>,
-    external_id: &str,
-    resource_id: i32,
-    highlight: &LightHighlight,
-    content_hash: &str,
-) -> SyncResult<()> {
-    let existing = match lib.find_annotation_by_external_id(external_id).await {
-        Ok(a) => a,
-        Err(e) => {
-            log_find_error("annotation", external_id, e);
-            return SyncResult::Error;
-        }
-    };
 
-    let boundary = serde_json::json!({
-        "groupID": highlight.group_id,
-        "date": highlight.date,
-        "chunks": highlight.chunks,
-        "url": highlight.url,
-    });
-
-    let Some(ann) = existing else {
-        return create_highlight(lib, external_id, resource_id, highlight, content_hash, boundary).await;
-    };
-
-    if is_unchanged(&ann, content_hash) {
-        return SyncResult::Unchanged(());
+    fn scope(&self) -> Option<String> {
+        self.payload.scope.clone()
     }
 
-    update_highlight(lib, external_id, ann.id, highlight, content_hash, boundary).await
-}
-
-async fn create_highlight(
-    lib: &Commonplace<'_>,
-    external_id: &str,
-    resource_id: i32,
-    highlight: &LightHighlight,
-    content_hash: &str,
-    boundary: serde_json::Value,
-) -> SyncResult<()> {
-    let result = lib
-        .create_annotation(CreateAnnotation {
-            resource_id,
+    async fn fetch(&self) -> anyhow::Result<Vec<SourceResource>> {
+        let source = &self.payload.source;
+        let to_annotation = |highlight: &LightHighlight| SourceAnnotation {
+            external_id: format!("{}:{}", source, highlight.group_id),
             text: highlight.repr.clone(),
             color: Some("yellow".to_string()),
-            boundary: Some(boundary),
-            external_id: Some(external_id.to_string()),
-            content_hash: Some(content_hash.to_string()),
-        })
-        .await;
-
-    handle_create_result_unit(result, "annotation", external_id)
+            boundary: Some(
+                Boundary::from_light(
+                    &highlight.repr,
+                    &highlight.chunks,
+                    &highlight.date,
+                    highlight.group_id,
+                    &highlight.url,
+                )
+                .into_value(),
+            ),
+            comments: Vec::new(),
+        };
+        Ok(self
+            .payload
+            .highlights
+            .iter()
+            .map(|(url, highlights)| SourceResource {
+                key: ResourceKey::Website(url.clone()),
+                title: url.clone(),
+                resource_type: ResourceType::Website,
+                annotations: highlights.iter().map(to_annotation).collect(),
+                notes: Vec::new(),
+                archived: None,
+            })
+            .collect())
+    }
 }
 
-async fn update_highlight(
-    lib: &Commonplace<'_>,
-    external_id: &str,
-    id: i32,
-    highlight: &LightHighlight,
-    content_hash: &str,
-    boundary: serde_json::Value,
-) -> SyncResult<()> {
-    let result = lib
-        .update_annotation(
-            id,
-            UpdateAnnotation {
-                text: Some(highlight.repr.clone()),
-                color: Some("yellow".to_string()),
-                boundary: Some(boundary),
-                content_hash: Some(content_hash.to_string()),
-            },
-        )
-        .await;
-
-    handle_update_result_unit(result, id, "annotation", external_id)
+pub async fn sync_highlights(State(state): State<AppState>, Json(payload): Json<SyncRequest>) -> Response {
+    sync(&state, payload).await
 }
 
-async fn soft_delete_orphan_annotations(
-    lib: &Commonplace<'_>,
-    payload: &SyncRequest,
-    seen: &HashSet<String>,
-    stats: &mut SyncResponse,
-) {
-    let scope_resource_id = match &payload.scope {
-        Some(scope_url) => match lib.find_resource_by_title(scope_url).await {
-            Ok(Some(resource)) => Some(resource.id),
-            Ok(None) => {
-                tracing::warn!("Scope resource {} not found, skipping orphan detection", scope_url);
-                return;
-            }
-            Err(e) => {
-                tracing::error!("Failed to find scope resource {}: {}", scope_url, e);
-                return;
-            }
-        },
-        None => None,
+pub async fn sync_url(State(state): State<AppState>, Json(payload): Json<UrlSyncRequest>) -> Response {
+    let url = payload.url.trim().to_string();
+    if url.is_empty() {
+        return bad_request("url is required");
+    }
+    // No highlights left only prunes, and doesn't create a page that was never synced
+    let mut highlights = HashMap::new();
+    if !payload.highlights.is_empty() {
+        highlights.insert(url.clone(), payload.highlights);
+    }
+    let payload = SyncRequest {
+        source: payload.source,
+        scope: Some(url),
+        highlights,
     };
+    sync(&state, payload).await
+}
 
-    let orphans = match lib
-        .find_annotations_by_source_prefix(&payload.source, scope_resource_id)
-        .await
-    {
-        Ok(o) => o,
-        Err(e) => {
-            tracing::error!("Failed to find orphan annotations: {}", e);
-            return;
+async fn sync(state: &AppState, payload: SyncRequest) -> Response {
+    let source = LightSource { payload };
+    let report = match engine::run(state, &source).await {
+        Ok(report) => report,
+        Err(SyncError::Fetch(e) | SyncError::StartRun(e)) => {
+            tracing::error!("Failed to start sync run for {}: {}", source.name(), e);
+            return internal_error("Failed to start sync run");
         }
     };
 
-    for orphan in orphans {
-        let ext_id = orphan.external_id().map(|s| s.to_string());
-        if is_orphan(&ext_id, seen) {
-            if lib.soft_delete_annotation(orphan.id()).await.unwrap_or(false) {
-                stats.annotations_deleted += 1;
-            }
-        }
+    // Pages that got new highlights are captured so later edits to them can be diffed
+    if let Err(e) = capture::enqueue_captures(&state.db, report.annotated.clone()).await {
+        tracing::warn!("Failed to queue page captures: {}", e);
     }
+
+    success(SyncResponse {
+        run_id: report.run_id,
+        resources_created: report.resources.created,
+        annotations_created: report.annotations.created,
+        annotations_updated: report.annotations.updated,
+        annotations_deleted: report.annotations.deleted,
+        annotations_unchanged: report.annotations.unchanged,
+        conflicts: report.totals().conflicts,
+        errors: report.totals().errors,
+    })
 }
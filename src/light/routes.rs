@@ -4,5 +4,7 @@ use super::handler;
 use crate::handler::AppState;
 
 pub fn routes() -> Router<AppState> {
-    Router::new().route("/sync", post(handler::sync_highlights))
+    Router::new()
+        .route("/sync", post(handler::sync_highlights))
+        .route("/sync/url", post(handler::sync_url))
 }
@@ -2,26 +2,63 @@ use std::sync::Arc;
 
 use axum::extract::DefaultBodyLimit;
 use axum::http::Method;
+use axum::middleware;
 use axum::{
     Router,
-    routing::{get, post, put},
+    routing::{delete, get, post, put},
 };
 use bibliotek::assets::serve_embedded;
-use bibliotek::commonplace;
-use bibliotek::config::{Cli, Config, default_config_dir, default_config_path};
+use bibliotek::commonplace::{
+    self,
+    capture::CaptureJob,
+    dictionary::Dictionary,
+    digest::DigestScheduler,
+    links::LinkChecker,
+    retention::{self, RetentionScheduler},
+    snapshot::{self, SnapshotJob},
+};
+use bibliotek::config::{Cli, Command, Config, default_config_dir, default_config_path};
 use bibliotek::db::Database;
+use bibliotek::enrich::{EnrichBookJob, Enricher};
+use bibliotek::feeds::{self, FeedPoller};
 use bibliotek::handler::{
-    AppState, abort_upload, create_author, create_category, create_tag, get_books, get_download_url, get_metadata,
-    get_pending_uploads, healthcheck, update_book, upload,
+    AppState, FileChecksumJob, ObjectTagJob, abort_upload, add_books_to_shelf, attach_book_version, continue_reading,
+    create_author, create_category, create_shelf, create_tag, db_stats, delete_author, delete_book, delete_shelf,
+    download_book, download_book_version, enrich_author, enrich_book, export_books, get_book_cover, get_book_history,
+    get_book_jobs, get_book_text, get_book_versions, get_books, get_download_url, get_metadata, get_pending_uploads,
+    get_shelf_books, get_trash, head_book_download, healthcheck, list_authors, list_shelves, open_book, patch_book,
+    remove_book_from_shelf, restore_book, retry_book_jobs, set_favorite, update_author, update_book, update_shelf,
+    upload,
 };
+use bibliotek::imports;
+use bibliotek::integrations;
+use bibliotek::jobs::{self, JobRunner};
+use bibliotek::kobo;
 use bibliotek::light;
+use bibliotek::migrate::{self, Direction};
+use bibliotek::mirror::{self, MirrorStore, Primary};
+use bibliotek::object_store::{ObjectStore, UploadCleaner};
+use bibliotek::outbox::OutboxDispatcher;
+use bibliotek::palette;
+use bibliotek::pocket;
+use bibliotek::public;
+use bibliotek::queue;
+use bibliotek::readwise;
 use bibliotek::research;
 use bibliotek::resumable::ResumableUploadManager;
+use bibliotek::seed::{self, SeedOptions};
+use bibliotek::sync;
+use bibliotek::text_extract::TextExtraction;
+use bibliotek::titles::TitleCleaner;
+use bibliotek::tx;
+use bibliotek::views;
+use bibliotek::wallabag;
+use bibliotek::webhook::WebhookSink;
+use bibliotek::zotero;
 use clap::Parser;
 use tokio::{signal, sync::mpsc};
 use tokio_util::sync::CancellationToken;
 use tower_http::cors::{Any, CorsLayer};
-use tracing;
 
 #[tokio::main]
 async fn main() {
@@ -62,65 +99,198 @@ async fn main() {
         tracing::error!(error = %e, path = ?config_path, "failed to load config file");
         std::process::exit(1);
     });
+
+    match args.command {
+        Some(Command::Migrate { dry_run, to }) => {
+            run_migrate(&cfg, dry_run, to).await;
+            return;
+        }
+        Some(Command::Seed {
+            books,
+            annotations,
+            words,
+            seed,
+        }) => {
+            let opts = SeedOptions {
+                books,
+                annotations,
+                words: words.unwrap_or(annotations / 10),
+                seed,
+            };
+            run_seed(&cfg, &opts).await;
+            return;
+        }
+        None => {}
+    }
+
     let db = Arc::new(Database::new(&cfg).await.unwrap_or_else(|e| {
         tracing::error!(error = %e, "failed to setup database");
         std::process::exit(1);
     }));
-    let resumable = Arc::new(ResumableUploadManager::new(&cfg).await.unwrap_or_else(|e| {
-        tracing::error!(error = %e, "failed to setup resumable upload manager");
+    // A mirror has no bucket of its own and reads files through the primary
+    let primary = cfg.mirror.primary_url.as_deref().map(|url| Arc::new(Primary::new(url)));
+    let resumable: Arc<dyn ObjectStore> = match &primary {
+        Some(primary) => {
+            if !db.is_replica() {
+                tracing::warn!("running as a mirror without turso_url; the database won't follow the primary");
+            }
+            Arc::new(MirrorStore::new(primary.clone()))
+        }
+        None => Arc::new(ResumableUploadManager::new(&cfg).await.unwrap_or_else(|e| {
+            tracing::error!(error = %e, "failed to setup resumable upload manager");
+            std::process::exit(1);
+        })),
+    };
+    let titles = Arc::new(TitleCleaner::new(&cfg.titles).unwrap_or_else(|e| {
+        tracing::error!(error = %e, "invalid title cleanup rules");
+        std::process::exit(1);
+    }));
+
+    let enricher = Arc::new(Enricher::new().unwrap_or_else(|e| {
+        tracing::error!(error = %e, "failed to setup metadata enricher");
         std::process::exit(1);
     }));
 
-    let address = format!("0.0.0.0:{}", cfg.app.get_port().to_string());
+    let address = format!("0.0.0.0:{}", cfg.app.get_port());
     let cancellation_token = CancellationToken::new();
     let (shutdown_complete_tx, mut shutdown_complete_rx) = mpsc::channel::<()>(1);
 
     db.start_sync_task(cfg.app.sync_interval_seconds, cancellation_token.clone());
-
-    // Background task to clean up expired uploads every hour
-    let cleanup_resumable = resumable.clone();
-    let cleanup_token = cancellation_token.clone();
-    tokio::spawn(async move {
-        let mut interval = tokio::time::interval(std::time::Duration::from_secs(3600)); // 1 hour
-        loop {
-            tokio::select! {
-                _ = interval.tick() => {
-                    if let Err(e) = cleanup_resumable.cleanup_expired(24).await { // 24 hours
-                        tracing::warn!("Failed to cleanup expired uploads: {}", e);
-                    }
-                }
-                _ = cleanup_token.cancelled() => {
-                    tracing::info!("Upload cleanup task shutting down");
-                    break;
-                }
-            }
+    db.events().start_logger(cancellation_token.clone());
+    if cfg.app.snapshot_websites {
+        snapshot::start(db.clone(), cancellation_token.clone());
+    }
+    // A mirror shares the primary's database, outbox and job queue, so scheduled
+    // and queued work is left to the primary
+    if primary.is_none() {
+        match sync::runs::mark_interrupted(&db.connection()).await {
+            Ok(0) => {}
+            Ok(n) => tracing::warn!("marked {} sync runs interrupted by the last shutdown", n),
+            Err(e) => tracing::warn!("Failed to mark interrupted sync runs: {}", e),
         }
-    });
+        let mut outbox = OutboxDispatcher::new(db.clone());
+        if let Some(webhook) = WebhookSink::new(&cfg.webhook) {
+            outbox = outbox.with_sink(Arc::new(webhook));
+        }
+        outbox.start(cancellation_token.clone());
+        LinkChecker::new(db.clone(), cfg.app.link_check_interval_hours).start(cancellation_token.clone());
+        FeedPoller::new(db.clone(), cfg.app.feed_poll_interval_minutes, cfg.app.snapshot_websites)
+            .start(cancellation_token.clone());
+        DigestScheduler::new(db.clone(), cfg.app.cold_digest_size).start(cancellation_token.clone());
+        RetentionScheduler::new(db.clone(), resumable.clone(), cfg.retention.clone()).start(cancellation_token.clone());
+        JobRunner::new(db.clone(), cfg.app.job_workers)
+            .with_handler(Arc::new(FileChecksumJob::new(resumable.clone())))
+            .with_handler(Arc::new(ObjectTagJob::new(resumable.clone())))
+            .with_handler(Arc::new(EnrichBookJob::new(enricher.clone())))
+            .with_handler(Arc::new(CaptureJob::default()))
+            .with_handler(Arc::new(SnapshotJob::new(resumable.clone())))
+            .start(cancellation_token.clone());
+
+        UploadCleaner::new(resumable.clone(), cfg.app.upload_cleanup_interval_minutes, cfg.app.upload_max_age_hours)
+            .start(cancellation_token.clone());
+    }
 
     let cors = CorsLayer::new()
         .allow_origin(Any)
-        .allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE])
+        .allow_methods([Method::GET, Method::POST, Method::PUT, Method::PATCH, Method::DELETE])
         .allow_headers(Any);
 
+    let state = AppState {
+        db,
+        resumable,
+        enricher,
+        enrich_on_upload: cfg.app.enrich_on_upload,
+        sync_diff_log: cfg.app.sync_diff_log,
+        sync: Arc::new(cfg.sync.clone()),
+        dictionary: Arc::new(Dictionary::new(cfg.dictionary.clone())),
+        titles,
+        retention: Arc::new(cfg.retention.clone()),
+        public: Arc::new(cfg.public.clone()),
+        extraction: Arc::new(TextExtraction::new(&cfg.extraction)),
+        wallabag: Arc::new(cfg.wallabag.clone()),
+        webhook: Arc::new(cfg.webhook.clone()),
+    };
+
     let app = Router::new()
         .route("/", get(healthcheck))
         .route("/books", get(get_books))
-        .route("/books/:id", put(update_book))
+        .route("/books/trash", get(get_trash))
+        .route("/books/export", get(export_books))
+        .route("/books/continue", get(continue_reading))
+        .route("/books/:id", put(update_book).patch(patch_book).delete(delete_book))
+        .route("/books/:id/restore", post(restore_book))
+        .route("/books/:id/history", get(get_book_history))
+        .route("/books/:id/jobs", get(get_book_jobs))
+        .route("/books/:id/jobs/retry", post(retry_book_jobs))
+        .route("/books/:id/enrich", post(enrich_book))
+        .route("/books/:id/favorite", put(set_favorite))
+        .route("/books/:id/open", post(open_book))
+        .route("/books/:id/cover", get(get_book_cover))
+        .route("/books/:id/download", get(download_book).head(head_book_download))
+        .route("/books/:id/versions", get(get_book_versions).post(attach_book_version))
+        .route("/books/:id/versions/:version_id/download", get(download_book_version))
+        .route("/books/:id/text", get(get_book_text))
         .route("/metadata", get(get_metadata))
-        .route("/authors", post(create_author))
+        .route("/authors", get(list_authors).post(create_author))
+        .route("/authors/:id", put(update_author).delete(delete_author))
+        .route("/authors/:id/enrich", post(enrich_author))
         .route("/tags", post(create_tag))
         .route("/categories", post(create_category))
+        .route("/shelves", get(list_shelves).post(create_shelf))
+        .route("/shelves/:id", put(update_shelf).delete(delete_shelf))
+        .route("/shelves/:id/books", get(get_shelf_books).post(add_books_to_shelf))
+        .route("/shelves/:id/books/:book_id", delete(remove_book_from_shelf))
         .route("/upload", post(upload))
         .route("/upload/pending", get(get_pending_uploads))
         .route("/upload/abort", post(abort_upload))
         .route("/download", get(get_download_url))
+        .route("/queue", get(queue::get_queue).post(queue::push_item))
+        .route("/queue/pop", post(queue::pop_item))
+        .route("/queue/order", put(queue::reorder_queue))
+        .route("/queue/burndown", get(queue::get_burndown))
+        .route("/queue/:id", delete(queue::remove_item).patch(queue::update_item))
+        // Library edits, uploads and queue changes each run in one transaction
+        .route_layer(middleware::from_fn_with_state(state.clone(), tx::transaction))
+        .route("/quick", post(commonplace::quick::quick_capture))
+        .route("/a/:public_id", get(commonplace::permalink::open_permalink))
+        .route("/sitemap.xml", get(public::sitemap))
+        .route("/palette", get(palette::get_palette))
+        .route("/admin/integrations/status", get(integrations::status))
+        .route("/admin/db/stats", get(db_stats))
+        .route("/admin/jobs", get(jobs::list))
+        .route("/admin/jobs/:id/retry", post(jobs::retry))
+        .route("/admin/retention", get(retention::list_policies))
+        .route("/admin/retention/:policy/reports", get(retention::list_policy_reports))
+        .route("/admin/retention/:policy/run", post(retention::run_policy))
         .layer(DefaultBodyLimit::max(10 * 1024 * 1024))
+        .route(
+            "/import/bookmarks",
+            post(commonplace::import_bookmarks)
+                .layer(DefaultBodyLimit::max(commonplace::bookmarks::MAX_BOOKMARKS_BYTES)),
+        )
+        .route(
+            "/import/csv",
+            post(imports::import_csv).layer(DefaultBodyLimit::max(imports::csv_import::MAX_CSV_BYTES)),
+        )
         .nest("/commonplace", commonplace::routes())
+        .nest("/feeds", feeds::routes())
+        .nest("/imports", imports::routes())
+        .nest("/kobo", kobo::routes())
         .nest("/light", light::routes())
+        .nest("/pocket", pocket::routes())
+        .nest("/readwise", readwise::routes())
         .nest("/research", research::routes())
+        .nest("/sync", sync::routes())
+        .nest("/wallabag", wallabag::routes())
+        .nest("/zotero", zotero::routes())
+        .nest("/html", views::routes())
         .fallback(serve_embedded)
         .layer(cors)
-        .with_state(AppState { db, resumable });
+        .with_state(state);
+    let app = match primary {
+        Some(primary) => app.layer(middleware::from_fn_with_state(primary, mirror::forward_files)),
+        None => app,
+    };
 
     let listener = tokio::net::TcpListener::bind(&address).await.unwrap_or_else(|e| {
         tracing::error!(error = %e, "failed to setup tcp listener");
@@ -145,3 +315,67 @@ async fn main() {
     shutdown_complete_rx.recv().await;
     tracing::info!("bibliotek.svc going off, graceful shutdown complete");
 }
+
+async fn run_seed(cfg: &Config, opts: &SeedOptions) {
+    let db = Database::new(cfg).await.unwrap_or_else(|e| {
+        tracing::error!(error = %e, "failed to setup database");
+        std::process::exit(1);
+    });
+
+    match seed::run(&db, opts).await {
+        Ok(summary) => println!(
+            "seeded {} books, {} resources, {} annotations, {} comments, {} words",
+            summary.books, summary.resources, summary.annotations, summary.comments, summary.words
+        ),
+        Err(e) => {
+            eprintln!("seed failed: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+async fn run_migrate(cfg: &Config, dry_run: bool, to: Option<String>) {
+    let db = Database::open(cfg).await.unwrap_or_else(|e| {
+        tracing::error!(error = %e, "failed to open database");
+        std::process::exit(1);
+    });
+
+    let plan = match &to {
+        Some(target) => migrate::plan_to(&db.connection(), target).await,
+        None => migrate::plan_pending(&db.connection()).await,
+    };
+    let steps = plan.unwrap_or_else(|e| {
+        eprintln!("failed to plan migrations: {}", e);
+        std::process::exit(1);
+    });
+
+    if steps.is_empty() {
+        println!("nothing to do");
+        return;
+    }
+
+    if dry_run {
+        for step in &steps {
+            let action = match step.direction {
+                Direction::Up => "apply",
+                Direction::Down => "revert",
+            };
+            println!("-- {} {} ({})", action, step.name, step.set);
+            println!("{}\n", step.sql.trim());
+        }
+        println!("-- {} migration(s) would run", steps.len());
+        return;
+    }
+
+    if let Err(e) = migrate::execute(&db.connection(), &steps).await {
+        eprintln!("migration failed: {}", e);
+        std::process::exit(1);
+    }
+    for step in &steps {
+        let action = match step.direction {
+            Direction::Up => "applied",
+            Direction::Down => "reverted",
+        };
+        println!("{} {}", action, step.name);
+    }
+}
@@ -0,0 +1,297 @@
+use anyhow::Result;
+use libsql::Connection;
+
+const SYSTEM_MIGRATIONS: &[(&str, &str)] =
+    &[("system/000_migrations_table.sql", include_str!("migrations/system/000_migrations_table.sql"))];
+
+const MIGRATIONS: &[(&str, &str)] = &[
+    ("001_schema.sql", include_str!("migrations/001_schema.sql")),
+    ("002_seed_categories.sql", include_str!("migrations/002_seed_categories.sql")),
+    ("003_add_book_status.sql", include_str!("migrations/003_add_book_status.sql")),
+    ("004_shelves.sql", include_str!("migrations/004_shelves.sql")),
+    ("005_book_metadata.sql", include_str!("migrations/005_book_metadata.sql")),
+    ("006_book_file_checksum.sql", include_str!("migrations/006_book_file_checksum.sql")),
+    ("007_cover_attribution.sql", include_str!("migrations/007_cover_attribution.sql")),
+    ("008_book_favorites.sql", include_str!("migrations/008_book_favorites.sql")),
+    ("009_reading_status.sql", include_str!("migrations/009_reading_status.sql")),
+    ("010_outbox.sql", include_str!("migrations/010_outbox.sql")),
+    ("011_book_soft_delete.sql", include_str!("migrations/011_book_soft_delete.sql")),
+    ("012_book_revisions.sql", include_str!("migrations/012_book_revisions.sql")),
+    ("013_import_staging.sql", include_str!("migrations/013_import_staging.sql")),
+    ("014_author_profile.sql", include_str!("migrations/014_author_profile.sql")),
+    ("015_book_opens.sql", include_str!("migrations/015_book_opens.sql")),
+    ("016_import_progress.sql", include_str!("migrations/016_import_progress.sql")),
+    ("017_author_authority.sql", include_str!("migrations/017_author_authority.sql")),
+    ("018_jobs.sql", include_str!("migrations/018_jobs.sql")),
+    ("019_job_books.sql", include_str!("migrations/019_job_books.sql")),
+    ("020_book_visibility.sql", include_str!("migrations/020_book_visibility.sql")),
+    ("021_palette_changes.sql", include_str!("migrations/021_palette_changes.sql")),
+    ("022_reading_queue.sql", include_str!("migrations/022_reading_queue.sql")),
+    ("023_book_versions.sql", include_str!("migrations/023_book_versions.sql")),
+    ("024_direct_uploads.sql", include_str!("migrations/024_direct_uploads.sql")),
+];
+
+/// An ordered group of migrations owned by one module. Down migrations are keyed by
+/// the name of the up migration they revert; a set without them can only move forward.
+pub struct MigrationSet {
+    pub name: &'static str,
+    pub up: &'static [(&'static str, &'static str)],
+    pub down: &'static [(&'static str, &'static str)],
+}
+
+impl MigrationSet {
+    fn down_sql(&self, name: &str) -> Option<&'static str> {
+        self.down.iter().find(|(n, _)| *n == name).map(|(_, sql)| *sql)
+    }
+
+    fn position(&self, target: &str) -> Option<usize> {
+        self.up
+            .iter()
+            .position(|(name, _)| *name == target || name.trim_end_matches(".sql") == target)
+    }
+}
+
+/// Sets in the order they are applied on startup.
+pub fn migration_sets() -> Vec<MigrationSet> {
+    vec![
+        MigrationSet {
+            name: "system",
+            up: SYSTEM_MIGRATIONS,
+            down: &[],
+        },
+        MigrationSet {
+            name: "books",
+            up: MIGRATIONS,
+            down: &[],
+        },
+        MigrationSet {
+            name: "commonplace",
+            up: crate::commonplace::migrations(),
+            down: crate::commonplace::down_migrations(),
+        },
+        MigrationSet {
+            name: "research",
+            up: crate::research::migrations(),
+            down: crate::research::down_migrations(),
+        },
+        MigrationSet {
+            name: "readwise",
+            up: crate::readwise::migrations(),
+            down: crate::readwise::down_migrations(),
+        },
+        MigrationSet {
+            name: "zotero",
+            up: crate::zotero::migrations(),
+            down: crate::zotero::down_migrations(),
+        },
+        MigrationSet {
+            name: "pocket",
+            up: crate::pocket::migrations(),
+            down: crate::pocket::down_migrations(),
+        },
+        MigrationSet {
+            name: "kobo",
+            up: crate::kobo::migrations(),
+            down: crate::kobo::down_migrations(),
+        },
+        MigrationSet {
+            name: "sync",
+            up: crate::sync::migrations(),
+            down: crate::sync::down_migrations(),
+        },
+        MigrationSet {
+            name: "feeds",
+            up: crate::feeds::migrations(),
+            down: crate::feeds::down_migrations(),
+        },
+    ]
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Up,
+    Down,
+}
+
+#[derive(Debug)]
+pub struct Step {
+    pub set: &'static str,
+    pub name: &'static str,
+    pub direction: Direction,
+    pub sql: &'static str,
+}
+
+async fn is_migration_applied(conn: &Connection, name: &str) -> Result<bool> {
+    let query = "SELECT 1 FROM _migrations WHERE name = ?";
+    match conn.query(query, libsql::params![name]).await {
+        Ok(mut rows) => Ok(rows.next().await?.is_some()),
+        Err(e) => {
+            if e.to_string().contains("no such table") {
+                Ok(false)
+            } else {
+                Err(e.into())
+            }
+        }
+    }
+}
+
+async fn record_migration(conn: &Connection, name: &str) -> Result<()> {
+    let query = r#"
+        INSERT INTO _migrations (name, applied_at)
+        VALUES (?, strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))
+    "#;
+    match conn.execute(query, libsql::params![name]).await {
+        Ok(_) => Ok(()),
+        Err(e) => {
+            if e.to_string().contains("no such table") {
+                Ok(())
+            } else {
+                Err(e.into())
+            }
+        }
+    }
+}
+
+async fn forget_migration(conn: &Connection, name: &str) -> Result<()> {
+    conn.execute("DELETE FROM _migrations WHERE name = ?", libsql::params![name])
+        .await?;
+    Ok(())
+}
+
+/// Every pending up migration across all sets, in startup order.
+pub async fn plan_pending(conn: &Connection) -> Result<Vec<Step>> {
+    let mut steps = Vec::new();
+    for set in migration_sets() {
+        for (name, sql) in set.up {
+            if !is_migration_applied(conn, name).await? {
+                steps.push(Step {
+                    set: set.name,
+                    name,
+                    direction: Direction::Up,
+                    sql,
+                });
+            }
+        }
+    }
+    Ok(steps)
+}
+
+/// Steps needed to bring the set owning `target` to exactly that migration: pending
+/// migrations up to and including it are applied, later applied ones are reverted.
+pub async fn plan_to(conn: &Connection, target: &str) -> Result<Vec<Step>> {
+    let sets = migration_sets();
+    let (set, target_idx) = sets
+        .iter()
+        .find_map(|set| set.position(target).map(|idx| (set, idx)))
+        .ok_or_else(|| anyhow::anyhow!("unknown migration: {}", target))?;
+
+    let mut steps = Vec::new();
+
+    for (name, sql) in &set.up[..=target_idx] {
+        if !is_migration_applied(conn, name).await? {
+            steps.push(Step {
+                set: set.name,
+                name,
+                direction: Direction::Up,
+                sql,
+            });
+        }
+    }
+
+    for (name, _) in set.up[target_idx + 1..].iter().rev() {
+        if !is_migration_applied(conn, name).await? {
+            continue;
+        }
+        let sql = set
+            .down_sql(name)
+            .ok_or_else(|| anyhow::anyhow!("migration {} in set {} has no down migration", name, set.name))?;
+        steps.push(Step {
+            set: set.name,
+            name,
+            direction: Direction::Down,
+            sql,
+        });
+    }
+
+    Ok(steps)
+}
+
+pub async fn execute(conn: &Connection, steps: &[Step]) -> Result<()> {
+    for step in steps {
+        match step.direction {
+            Direction::Up => {
+                tracing::info!("applying migration: {}", step.name);
+                conn.execute_batch(step.sql)
+                    .await
+                    .map_err(|e| anyhow::anyhow!("failed to execute migration {}: {e}", step.name))?;
+                record_migration(conn, step.name).await?;
+            }
+            Direction::Down => {
+                tracing::info!("reverting migration: {}", step.name);
+                conn.execute_batch(step.sql)
+                    .await
+                    .map_err(|e| anyhow::anyhow!("failed to revert migration {}: {e}", step.name))?;
+                forget_migration(conn, step.name).await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Startup path: apply everything that is pending.
+pub async fn run_pending(conn: &Connection) -> Result<()> {
+    let steps = plan_pending(conn).await?;
+    execute(conn, &steps).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::test_db;
+
+    fn names(steps: &[Step]) -> Vec<(&str, Direction)> {
+        steps.iter().map(|s| (s.name, s.direction)).collect()
+    }
+
+    #[tokio::test]
+    async fn test_plan_to_reverts_then_reapplies() {
+        let db = test_db().await;
+        let conn = &db.connection();
+
+        // Fully migrated: the target itself is already applied, later ones are reverted newest first
+        let steps = plan_to(conn, "research_001_config").await.unwrap();
+        assert_eq!(
+            names(&steps),
+            vec![
+                ("research_003_sources.sql", Direction::Down),
+                ("research_002_remote.sql", Direction::Down),
+            ]
+        );
+        execute(conn, &steps).await.unwrap();
+        assert!(!is_migration_applied(conn, "research_002_remote.sql").await.unwrap());
+        assert!(plan_to(conn, "research_001_config.sql").await.unwrap().is_empty());
+
+        let steps = plan_to(conn, "research_003_sources").await.unwrap();
+        assert_eq!(
+            names(&steps),
+            vec![
+                ("research_002_remote.sql", Direction::Up),
+                ("research_003_sources.sql", Direction::Up),
+            ]
+        );
+        execute(conn, &steps).await.unwrap();
+        assert!(plan_pending(conn).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_plan_to_requires_down_migrations() {
+        let db = test_db().await;
+
+        // The books set has no down migrations, so it can't be moved backwards
+        let err = plan_to(&db.connection(), "001_schema").await.unwrap_err();
+        assert!(err.to_string().contains("has no down migration"), "{}", err);
+
+        let err = plan_to(&db.connection(), "999_missing").await.unwrap_err();
+        assert!(err.to_string().contains("unknown migration"), "{}", err);
+    }
+}
@@ -0,0 +1,22 @@
+-- Shelves: user-curated collections of books ("deep work", "to-read 2025").
+-- Unlike categories, shelves are never inferred from PDF metadata.
+CREATE TABLE IF NOT EXISTS shelves (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL UNIQUE,
+    description TEXT,
+    created_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    updated_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))
+);
+
+CREATE TABLE IF NOT EXISTS book_shelves (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    book_id INTEGER NOT NULL,
+    shelf_id INTEGER NOT NULL,
+    created_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    updated_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    FOREIGN KEY (book_id) REFERENCES books (id),
+    FOREIGN KEY (shelf_id) REFERENCES shelves (id)
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_book_shelf_unique ON book_shelves (book_id, shelf_id);
+CREATE INDEX IF NOT EXISTS idx_book_shelves_shelf_id ON book_shelves (shelf_id);
@@ -0,0 +1,4 @@
+-- Fields backfilled by metadata enrichment (OpenLibrary / Google Books)
+ALTER TABLE books ADD COLUMN isbn TEXT;
+ALTER TABLE books ADD COLUMN publish_date TEXT;
+CREATE INDEX IF NOT EXISTS idx_books_isbn ON books(isbn);
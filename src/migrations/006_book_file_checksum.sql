@@ -0,0 +1,3 @@
+-- Integrity metadata for the stored book file, served on /books/:id/download
+ALTER TABLE books ADD COLUMN file_size INTEGER;
+ALTER TABLE books ADD COLUMN file_sha256 TEXT;
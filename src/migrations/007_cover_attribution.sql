@@ -0,0 +1,2 @@
+-- Credit for covers fetched from external sources (e.g. Open Library)
+ALTER TABLE books ADD COLUMN cover_attribution TEXT;
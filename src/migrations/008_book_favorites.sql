@@ -0,0 +1,3 @@
+-- Books pinned by the reader
+ALTER TABLE books ADD COLUMN is_favorite INTEGER NOT NULL DEFAULT 0;
+CREATE INDEX IF NOT EXISTS idx_books_is_favorite ON books(is_favorite);
@@ -0,0 +1,4 @@
+-- Reading progress, separate from the upload `status` column
+ALTER TABLE books ADD COLUMN reading_status TEXT NOT NULL DEFAULT 'to-read'
+    CHECK (reading_status IN ('to-read', 'reading', 'finished', 'abandoned'));
+CREATE INDEX IF NOT EXISTS idx_books_reading_status ON books(reading_status);
@@ -0,0 +1,4 @@
+-- Deleted books go to the trash first and can be restored until purged
+ALTER TABLE books ADD COLUMN deleted_at TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_books_deleted_at ON books (deleted_at);
@@ -0,0 +1,12 @@
+-- One row per changed field on every book edit, so edits can be reviewed and undone
+CREATE TABLE IF NOT EXISTS book_revisions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    book_id INTEGER NOT NULL,
+    field TEXT NOT NULL,
+    old_value TEXT,
+    new_value TEXT,
+    created_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    FOREIGN KEY (book_id) REFERENCES books (id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_book_revisions_book_id ON book_revisions (book_id, created_at);
@@ -0,0 +1,27 @@
+-- Staging area for library imports. Importers write parsed records here; nothing
+-- touches the live library until the batch is reviewed and committed.
+CREATE TABLE IF NOT EXISTS import_batches (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    source TEXT NOT NULL, -- calibre, goodreads, kindle, ...
+    status TEXT NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'committed', 'discarded')),
+    committed_at TEXT,
+    created_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    updated_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))
+);
+
+CREATE TABLE IF NOT EXISTS import_items (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    batch_id INTEGER NOT NULL,
+    kind TEXT NOT NULL CHECK (kind IN ('book', 'annotation')),
+    data TEXT NOT NULL, -- JSON-serialized StagedRecord
+    action TEXT NOT NULL DEFAULT 'create' CHECK (action IN ('create', 'merge', 'skip')),
+    target_book_id INTEGER,
+    target_resource_id INTEGER,
+    result_id INTEGER, -- book or annotation id once committed
+    error TEXT,
+    created_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    updated_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    FOREIGN KEY (batch_id) REFERENCES import_batches (id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_import_items_batch_id ON import_items (batch_id);
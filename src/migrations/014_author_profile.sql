@@ -0,0 +1,3 @@
+-- Optional author profile shown on author pages
+ALTER TABLE authors ADD COLUMN bio TEXT;
+ALTER TABLE authors ADD COLUMN photo_url TEXT;
@@ -0,0 +1,11 @@
+-- When each book was last opened and how far in the reader got, for "continue reading"
+CREATE TABLE IF NOT EXISTS book_opens (
+    book_id INTEGER PRIMARY KEY,
+    open_count INTEGER NOT NULL DEFAULT 0,
+    last_opened_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    progress REAL CHECK (progress IS NULL OR (progress >= 0 AND progress <= 1)),
+    position TEXT, -- reader-specific location, e.g. an EPUB CFI or page number
+    FOREIGN KEY (book_id) REFERENCES books (id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_book_opens_last_opened_at ON book_opens (last_opened_at);
@@ -0,0 +1,5 @@
+-- Commit progress of import batches. Items are committed in id order and
+-- committed_through is the last one processed, so a commit that was interrupted
+-- (crash, restart, timeout) picks up after it instead of importing everything again.
+ALTER TABLE import_batches ADD COLUMN commit_started_at TEXT;
+ALTER TABLE import_batches ADD COLUMN committed_through INTEGER;
@@ -0,0 +1,17 @@
+-- Author identity from an external authority (Open Library) and the alternate
+-- spellings used to match new books to existing authors
+ALTER TABLE authors ADD COLUMN canonical_name TEXT;
+ALTER TABLE authors ADD COLUMN birth_year INTEGER;
+ALTER TABLE authors ADD COLUMN authority_id TEXT;
+ALTER TABLE authors ADD COLUMN enriched_at TEXT;
+
+-- name_key is the normalized spelling (see authors::name_key); one author per key
+CREATE TABLE IF NOT EXISTS author_aliases (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    author_id INTEGER NOT NULL,
+    alias TEXT NOT NULL,
+    name_key TEXT NOT NULL UNIQUE,
+    FOREIGN KEY (author_id) REFERENCES authors (id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_author_aliases_author ON author_aliases (author_id);
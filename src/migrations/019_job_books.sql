@@ -0,0 +1,4 @@
+-- The book a job works on, for jobs whose payload names one, so a book's processing
+-- state can be looked up without scanning every payload
+ALTER TABLE jobs ADD COLUMN book_id INTEGER GENERATED ALWAYS AS (json_extract(payload, '$.book_id')) VIRTUAL;
+CREATE INDEX IF NOT EXISTS idx_jobs_book_id ON jobs (book_id) WHERE book_id IS NOT NULL;
@@ -0,0 +1,5 @@
+-- Who can see a book once the library is published: public books are listed,
+-- shared ones are reachable by direct link only, private ones never leave the app
+ALTER TABLE books ADD COLUMN visibility TEXT NOT NULL DEFAULT 'public'
+    CHECK (visibility IN ('private', 'shared', 'public'));
+CREATE INDEX IF NOT EXISTS idx_books_visibility ON books (visibility);
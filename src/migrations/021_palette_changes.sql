@@ -0,0 +1,68 @@
+-- Journal of changes to what the command palette lists (books, shelves and, through
+-- commonplace_023, resources), so clients can refresh it from their last cursor
+-- instead of refetching everything. Only changes to listed fields are recorded.
+CREATE TABLE IF NOT EXISTS palette_changes (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    kind TEXT NOT NULL CHECK (kind IN ('book', 'shelf', 'resource')),
+    entity_id INTEGER NOT NULL,
+    created_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))
+);
+
+CREATE TRIGGER IF NOT EXISTS palette_books_insert
+AFTER INSERT ON books
+BEGIN
+    INSERT INTO palette_changes (kind, entity_id) VALUES ('book', NEW.id);
+END;
+
+CREATE TRIGGER IF NOT EXISTS palette_books_update
+AFTER UPDATE OF title, deleted_at ON books
+WHEN OLD.title IS NOT NEW.title OR OLD.deleted_at IS NOT NEW.deleted_at
+BEGIN
+    INSERT INTO palette_changes (kind, entity_id) VALUES ('book', NEW.id);
+END;
+
+CREATE TRIGGER IF NOT EXISTS palette_books_delete
+AFTER DELETE ON books
+BEGIN
+    INSERT INTO palette_changes (kind, entity_id) VALUES ('book', OLD.id);
+END;
+
+-- A book's authors are part of its palette entry
+CREATE TRIGGER IF NOT EXISTS palette_book_authors_insert
+AFTER INSERT ON book_authors
+BEGIN
+    INSERT INTO palette_changes (kind, entity_id) VALUES ('book', NEW.book_id);
+END;
+
+CREATE TRIGGER IF NOT EXISTS palette_book_authors_delete
+AFTER DELETE ON book_authors
+BEGIN
+    INSERT INTO palette_changes (kind, entity_id) VALUES ('book', OLD.book_id);
+END;
+
+CREATE TRIGGER IF NOT EXISTS palette_authors_update
+AFTER UPDATE OF name ON authors
+WHEN OLD.name IS NOT NEW.name
+BEGIN
+    INSERT INTO palette_changes (kind, entity_id)
+    SELECT 'book', book_id FROM book_authors WHERE author_id = NEW.id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS palette_shelves_insert
+AFTER INSERT ON shelves
+BEGIN
+    INSERT INTO palette_changes (kind, entity_id) VALUES ('shelf', NEW.id);
+END;
+
+CREATE TRIGGER IF NOT EXISTS palette_shelves_update
+AFTER UPDATE OF name, description ON shelves
+WHEN OLD.name IS NOT NEW.name OR OLD.description IS NOT NEW.description
+BEGIN
+    INSERT INTO palette_changes (kind, entity_id) VALUES ('shelf', NEW.id);
+END;
+
+CREATE TRIGGER IF NOT EXISTS palette_shelves_delete
+AFTER DELETE ON shelves
+BEGIN
+    INSERT INTO palette_changes (kind, entity_id) VALUES ('shelf', OLD.id);
+END;
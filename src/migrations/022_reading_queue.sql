@@ -0,0 +1,18 @@
+-- What to read next: books and commonplace resources in the order they should be
+-- read. Entries are kept after they leave the queue, popped to be read or removed
+-- unread, so the queue's size can be charted over time.
+CREATE TABLE IF NOT EXISTS reading_queue (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    kind TEXT NOT NULL CHECK (kind IN ('book', 'resource')),
+    entity_id INTEGER NOT NULL,
+    priority INTEGER NOT NULL DEFAULT 0, -- higher is read sooner
+    position INTEGER NOT NULL, -- order among entries of the same priority
+    added_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    popped_at TEXT,
+    removed_at TEXT
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_reading_queue_queued
+    ON reading_queue (kind, entity_id) WHERE popped_at IS NULL AND removed_at IS NULL;
+CREATE INDEX IF NOT EXISTS idx_reading_queue_order
+    ON reading_queue (priority DESC, position) WHERE popped_at IS NULL AND removed_at IS NULL;
@@ -0,0 +1,14 @@
+-- Files a book had before a re-upload replaced them, served on /books/:id/versions
+CREATE TABLE IF NOT EXISTS book_versions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    book_id INTEGER NOT NULL,
+    url TEXT NOT NULL,
+    file_size INTEGER,
+    file_sha256 TEXT,
+    -- When the file became the book's, and when a newer one took its place
+    uploaded_at TEXT NOT NULL,
+    replaced_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    FOREIGN KEY (book_id) REFERENCES books (id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_book_versions_book_id ON book_versions (book_id, replaced_at);
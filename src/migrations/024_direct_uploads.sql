@@ -0,0 +1,7 @@
+-- Uploads whose parts go straight to the bucket, so completing one can check that
+-- every byte arrived
+CREATE TABLE IF NOT EXISTS direct_uploads (
+    upload_id TEXT PRIMARY KEY,
+    file_size INTEGER NOT NULL,
+    created_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))
+);
@@ -0,0 +1,236 @@
+//! Metadata-only mirrors, for serving the library from a small VPS while the files
+//! stay with the primary (on a home NAS, say). A mirror is an embedded replica of
+//! the primary's database (`app.turso_url`) with `mirror.primary_url` set. Browsing
+//! and commonplace are served from its own copy of the database. Nothing is copied
+//! from the bucket. Requests that read a file (downloads, extracted text, website
+//! snapshots) are passed on to the primary, and its answer is streamed back.
+//!
+//! Whatever else needs the bucket fails on a mirror: uploads, and the background
+//! jobs, retention and upload clean-up, which are left to the primary.
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::{HeaderMap, Method, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::error::ObjectStorageError;
+use crate::object_store::{ObjectStore, ObjectStream};
+use crate::response::bad_gateway;
+use crate::resumable::{InitResponse, ObjectInfo, PendingUpload};
+
+/// Headers that only concern one connection and are not passed along
+const HOP_BY_HOP: &[header::HeaderName] = &[
+    header::CONNECTION,
+    header::HOST,
+    header::TRANSFER_ENCODING,
+    header::TE,
+    header::TRAILER,
+    header::UPGRADE,
+    header::PROXY_AUTHORIZATION,
+];
+
+/// Paths of the routes that read from the bucket
+fn serves_files(path: &str) -> bool {
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    matches!(
+        segments.as_slice(),
+        ["download"]
+            | ["books", _, "download"]
+            | ["books", _, "text"]
+            | ["books", _, "versions", _, "download"]
+            | ["commonplace", "resources", _, "snapshot"]
+    )
+}
+
+fn forwarded(headers: &HeaderMap) -> HeaderMap {
+    let mut headers = headers.clone();
+    for name in HOP_BY_HOP {
+        headers.remove(name);
+    }
+    headers
+}
+
+/// The instance holding the files
+pub struct Primary {
+    client: reqwest::Client,
+    url: String,
+}
+
+impl Primary {
+    pub fn new(url: &str) -> Self {
+        Self {
+            client: reqwest::Client::builder()
+                .user_agent(concat!("bibliotek/", env!("CARGO_PKG_VERSION")))
+                .build()
+                .unwrap_or_default(),
+            url: url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    /// Sends `request` on to the primary and streams back its answer
+    async fn forward(&self, request: Request) -> Response {
+        let path = request.uri().path_and_query().map_or("/", |p| p.as_str());
+        let url = format!("{}{}", self.url, path);
+        let resp = self
+            .client
+            .request(request.method().clone(), &url)
+            .headers(forwarded(request.headers()))
+            .send()
+            .await;
+        match resp {
+            Ok(resp) => {
+                let status = resp.status();
+                let headers = forwarded(resp.headers());
+                let body = Body::from_stream(resp.bytes_stream());
+                (status, headers, body).into_response()
+            }
+            Err(e) => {
+                tracing::warn!("Failed to reach the primary for {}: {}", url, e);
+                bad_gateway("Failed to reach the primary instance")
+            }
+        }
+    }
+}
+
+/// Middleware passing the requests for files on to the primary
+pub async fn forward_files(State(primary): State<Arc<Primary>>, request: Request, next: Next) -> Response {
+    if matches!(*request.method(), Method::GET | Method::HEAD) && serves_files(request.uri().path()) {
+        return primary.forward(request).await;
+    }
+    next.run(request).await
+}
+
+/// Object store of a mirror, which has no bucket. Files are read through the
+/// primary by [`forward_files`] before a handler would get here.
+pub struct MirrorStore {
+    primary: Arc<Primary>,
+}
+
+impl MirrorStore {
+    pub fn new(primary: Arc<Primary>) -> Self {
+        Self { primary }
+    }
+}
+
+fn unavailable<T>() -> Result<T, ObjectStorageError> {
+    Err(ObjectStorageError::Unavailable(
+        "this instance is a mirror; files are kept on the primary".to_string(),
+    ))
+}
+
+#[async_trait]
+impl ObjectStore for MirrorStore {
+    async fn init_or_resume(
+        &self,
+        _: &str,
+        _: &str,
+        _: i64,
+        _: Option<&str>,
+    ) -> Result<InitResponse, ObjectStorageError> {
+        unavailable()
+    }
+
+    async fn upload_part(&self, _: &str, _: &str, _: Vec<u8>, _: i32) -> Result<String, ObjectStorageError> {
+        unavailable()
+    }
+
+    async fn complete(&self, _: &str, _: &str) -> Result<String, ObjectStorageError> {
+        unavailable()
+    }
+
+    async fn abort(&self, _: &str, _: &str) -> Result<(), ObjectStorageError> {
+        unavailable()
+    }
+
+    async fn presign_upload_part(&self, _: &str, _: &str, _: i32, _: u64) -> Result<String, ObjectStorageError> {
+        unavailable()
+    }
+
+    async fn uploaded_bytes(&self, _: &str, _: &str) -> Result<i64, ObjectStorageError> {
+        unavailable()
+    }
+
+    async fn list_pending(&self) -> Result<Vec<PendingUpload>, ObjectStorageError> {
+        Ok(Vec::new())
+    }
+
+    async fn cleanup_expired(&self, _: u64) -> Result<usize, ObjectStorageError> {
+        Ok(0)
+    }
+
+    fn get_file_url(&self, key: &str) -> String {
+        format!("{}/{}", self.primary.url, urlencoding::encode(key))
+    }
+
+    async fn get_presigned_url(&self, _: &str, _: u64) -> Result<String, ObjectStorageError> {
+        unavailable()
+    }
+
+    async fn head(&self, _: &str) -> Result<ObjectInfo, ObjectStorageError> {
+        unavailable()
+    }
+
+    async fn stream_file(&self, _: &str) -> Result<(ObjectInfo, ObjectStream), ObjectStorageError> {
+        unavailable()
+    }
+
+    async fn download_file(&self, _: &str) -> Result<Vec<u8>, ObjectStorageError> {
+        unavailable()
+    }
+
+    async fn delete_object(&self, _: &str) -> Result<(), ObjectStorageError> {
+        unavailable()
+    }
+
+    async fn put_object(&self, _: &str, _: Vec<u8>, _: &str) -> Result<String, ObjectStorageError> {
+        unavailable()
+    }
+
+    async fn set_tags(&self, _: &str, _: &[(String, String)]) -> Result<(), ObjectStorageError> {
+        unavailable()
+    }
+
+    /// Whether the primary, and so the files, can be reached
+    async fn ping(&self) -> Result<(), ObjectStorageError> {
+        self.primary
+            .client
+            .get(format!("{}/", self.primary.url))
+            .send()
+            .await
+            .and_then(|resp| resp.error_for_status())
+            .map(|_| ())
+            .map_err(|e| ObjectStorageError::Unavailable(format!("primary unreachable: {}", e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_only_file_routes_are_forwarded() {
+        for path in [
+            "/download",
+            "/books/12/download",
+            "/books/12/text",
+            "/books/12/versions/3/download",
+            "/commonplace/resources/7/snapshot",
+        ] {
+            assert!(serves_files(path), "{} is not forwarded", path);
+        }
+        for path in [
+            "/books",
+            "/books/12/cover",
+            "/books/12/versions",
+            "/commonplace/resources/7",
+        ] {
+            assert!(!serves_files(path), "{} is forwarded", path);
+        }
+    }
+}
@@ -1,5 +1,9 @@
+use std::collections::BTreeMap;
+
 use serde::{Deserialize, Serialize};
 
+use crate::jobs::JobStatus;
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Book {
     pub id: i32,
@@ -12,12 +16,149 @@ pub struct Book {
     pub category_ids: Vec<String>,
     pub description: String,
     pub pages: i32,
+    pub isbn: String,
+    pub publish_date: String,
+    pub cover_attribution: String,
+    pub is_favorite: bool,
+    pub reading_status: ReadingStatus,
+    pub deleted_at: Option<String>,
+    /// Activity on the commonplace resources linked to this book
+    pub annotation_count: i32,
+    pub note_count: i32,
+    pub last_annotated_at: Option<String>,
+    /// Reading position recorded by `POST /books/:id/open` and downloads
+    pub last_opened_at: Option<String>,
+    pub progress: Option<f64>,
+    pub position: Option<String>,
+    /// Status of the latest background job of each kind run for this book, keyed
+    /// by kind (`book.enrich`, `book.checksum`)
+    #[serde(default)]
+    pub jobs: BTreeMap<String, JobStatus>,
+    #[serde(default)]
+    pub visibility: Visibility,
+}
+
+/// Who can see a book or resource on the public surfaces (see [`crate::public`]).
+/// The app itself always shows everything.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Visibility {
+    /// Never shown publicly
+    Private,
+    /// Reachable by direct link, but not listed or in the sitemap
+    Shared,
+    #[default]
+    Public,
+}
+
+impl Visibility {
+    pub const ALL: [Visibility; 3] = [Visibility::Private, Visibility::Shared, Visibility::Public];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Visibility::Private => "private",
+            Visibility::Shared => "shared",
+            Visibility::Public => "public",
+        }
+    }
+
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|visibility| visibility.as_str() == s)
+    }
+
+    /// Whether a public page can be opened by a link to it
+    pub fn is_linkable(&self) -> bool {
+        *self != Visibility::Private
+    }
+}
+
+impl From<Visibility> for libsql::Value {
+    fn from(visibility: Visibility) -> Self {
+        libsql::Value::Text(visibility.as_str().to_string())
+    }
+}
+
+/// Where the reader is with a book. Stored in `books.reading_status`; the older
+/// `status` column tracks upload state.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ReadingStatus {
+    #[default]
+    ToRead,
+    Reading,
+    Finished,
+    Abandoned,
+}
+
+impl ReadingStatus {
+    pub const ALL: [ReadingStatus; 4] = [
+        ReadingStatus::ToRead,
+        ReadingStatus::Reading,
+        ReadingStatus::Finished,
+        ReadingStatus::Abandoned,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReadingStatus::ToRead => "to-read",
+            ReadingStatus::Reading => "reading",
+            ReadingStatus::Finished => "finished",
+            ReadingStatus::Abandoned => "abandoned",
+        }
+    }
+
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|status| status.as_str() == s)
+    }
+}
+
+impl From<ReadingStatus> for libsql::Value {
+    fn from(status: ReadingStatus) -> Self {
+        libsql::Value::Text(status.as_str().to_string())
+    }
+}
+
+/// The stored file behind a book, with integrity metadata once it has been hashed
+#[derive(Debug, Clone)]
+pub struct BookFile {
+    pub url: String,
+    pub size: Option<i64>,
+    pub sha256: Option<String>,
+}
+
+/// A file a book had before a re-upload replaced it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BookVersion {
+    pub id: i32,
+    pub book_id: i32,
+    pub url: String,
+    pub size: Option<i64>,
+    pub sha256: Option<String>,
+    pub uploaded_at: String,
+    pub replaced_at: String,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Author {
     pub id: i32,
     pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bio: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub photo_url: Option<String>,
+    /// Name used by the authority record the author was enriched from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub canonical_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub birth_year: Option<i32>,
+    /// Open Library author key, e.g. "OL26320A"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub authority_id: Option<String>,
+    /// Other spellings matched to this author; only loaded for a single author
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub aliases: Vec<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -32,6 +173,27 @@ pub struct Category {
     pub name: String,
 }
 
+/// A single field change made by a book edit. Relation fields hold comma-separated ids.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BookRevision {
+    pub id: i32,
+    pub book_id: i32,
+    pub field: String,
+    pub old_value: Option<String>,
+    pub new_value: Option<String>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Shelf {
+    pub id: i32,
+    pub name: String,
+    pub description: Option<String>,
+    pub book_count: i32,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Rating {
     pub id: i32,
@@ -56,6 +218,12 @@ pub struct RatingAggregate {
     pub count: i32,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReadingStatusAggregate {
+    pub status: ReadingStatus,
+    pub count: i32,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CategoryAggregate {
     pub category: Category,
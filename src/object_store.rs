@@ -0,0 +1,476 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use axum::body::Bytes;
+use futures_util::stream::BoxStream;
+use tokio::sync::Mutex;
+use tokio_util::sync::CancellationToken;
+
+use crate::error::ObjectStorageError;
+use crate::resumable::{DEFAULT_CHUNK_SIZE, InitResponse, ObjectInfo, PendingUpload, ResumableUploadManager};
+
+/// Body of an object being read from the store
+pub type ObjectStream = BoxStream<'static, Result<Bytes, std::io::Error>>;
+
+/// Storage backend for book files. Uploads are resumable multipart sessions keyed by
+/// `{signature}_{file_name}`; `ResumableUploadManager` implements this against S3.
+#[async_trait]
+pub trait ObjectStore: Send + Sync {
+    async fn init_or_resume(
+        &self,
+        signature: &str,
+        file_name: &str,
+        file_size: i64,
+        sha256: Option<&str>,
+    ) -> Result<InitResponse, ObjectStorageError>;
+
+    async fn upload_part(
+        &self,
+        upload_id: &str,
+        key: &str,
+        data: Vec<u8>,
+        part_number: i32,
+    ) -> Result<String, ObjectStorageError>;
+
+    /// Assembles the uploaded parts and returns the public URL of the object
+    async fn complete(&self, upload_id: &str, key: &str) -> Result<String, ObjectStorageError>;
+
+    async fn abort(&self, upload_id: &str, key: &str) -> Result<(), ObjectStorageError>;
+
+    /// URL a client can upload one part to directly, bypassing this server
+    async fn presign_upload_part(
+        &self,
+        upload_id: &str,
+        key: &str,
+        part_number: i32,
+        expires_in_secs: u64,
+    ) -> Result<String, ObjectStorageError>;
+
+    /// Bytes received so far by an upload, to check it before completing
+    async fn uploaded_bytes(&self, upload_id: &str, key: &str) -> Result<i64, ObjectStorageError>;
+
+    async fn list_pending(&self) -> Result<Vec<PendingUpload>, ObjectStorageError>;
+
+    async fn cleanup_expired(&self, max_age_hours: u64) -> Result<usize, ObjectStorageError>;
+
+    fn get_file_url(&self, key: &str) -> String;
+
+    /// Inverse of `get_file_url`: recovers the object key from a stored book url
+    fn key_for_url(&self, url: &str) -> Option<String> {
+        let (_, rest) = url.split_once("://")?;
+        let (_, path) = rest.split_once('/')?;
+        urlencoding::decode(path)
+            .ok()
+            .map(|k| k.into_owned())
+            .filter(|k| !k.is_empty())
+    }
+
+    async fn get_presigned_url(&self, key: &str, expires_in_secs: u64) -> Result<String, ObjectStorageError>;
+
+    async fn head(&self, key: &str) -> Result<ObjectInfo, ObjectStorageError>;
+
+    async fn stream_file(&self, key: &str) -> Result<(ObjectInfo, ObjectStream), ObjectStorageError>;
+
+    async fn download_file(&self, key: &str) -> Result<Vec<u8>, ObjectStorageError>;
+
+    async fn delete_object(&self, key: &str) -> Result<(), ObjectStorageError>;
+
+    /// Stores a small object in one request and returns its URL
+    async fn put_object(&self, key: &str, data: Vec<u8>, content_type: &str) -> Result<String, ObjectStorageError>;
+
+    /// Replaces the tags on an object, for bucket tooling and lifecycle rules
+    async fn set_tags(&self, key: &str, tags: &[(String, String)]) -> Result<(), ObjectStorageError>;
+
+    /// Checks the backend is reachable and the credentials are accepted
+    async fn ping(&self) -> Result<(), ObjectStorageError>;
+}
+
+#[async_trait]
+impl ObjectStore for ResumableUploadManager {
+    async fn init_or_resume(
+        &self,
+        signature: &str,
+        file_name: &str,
+        file_size: i64,
+        sha256: Option<&str>,
+    ) -> Result<InitResponse, ObjectStorageError> {
+        ResumableUploadManager::init_or_resume(self, signature, file_name, file_size, sha256).await
+    }
+
+    async fn upload_part(
+        &self,
+        upload_id: &str,
+        key: &str,
+        data: Vec<u8>,
+        part_number: i32,
+    ) -> Result<String, ObjectStorageError> {
+        ResumableUploadManager::upload_part(self, upload_id, key, data, part_number).await
+    }
+
+    async fn complete(&self, upload_id: &str, key: &str) -> Result<String, ObjectStorageError> {
+        ResumableUploadManager::complete(self, upload_id, key).await
+    }
+
+    async fn abort(&self, upload_id: &str, key: &str) -> Result<(), ObjectStorageError> {
+        ResumableUploadManager::abort(self, upload_id, key).await
+    }
+
+    async fn presign_upload_part(
+        &self,
+        upload_id: &str,
+        key: &str,
+        part_number: i32,
+        expires_in_secs: u64,
+    ) -> Result<String, ObjectStorageError> {
+        ResumableUploadManager::presign_upload_part(self, upload_id, key, part_number, expires_in_secs).await
+    }
+
+    async fn uploaded_bytes(&self, upload_id: &str, key: &str) -> Result<i64, ObjectStorageError> {
+        ResumableUploadManager::uploaded_bytes(self, upload_id, key).await
+    }
+
+    async fn list_pending(&self) -> Result<Vec<PendingUpload>, ObjectStorageError> {
+        ResumableUploadManager::list_pending(self).await
+    }
+
+    async fn cleanup_expired(&self, max_age_hours: u64) -> Result<usize, ObjectStorageError> {
+        ResumableUploadManager::cleanup_expired(self, max_age_hours).await
+    }
+
+    fn get_file_url(&self, key: &str) -> String {
+        ResumableUploadManager::get_file_url(self, key)
+    }
+
+    async fn get_presigned_url(&self, key: &str, expires_in_secs: u64) -> Result<String, ObjectStorageError> {
+        ResumableUploadManager::get_presigned_url(self, key, expires_in_secs).await
+    }
+
+    async fn head(&self, key: &str) -> Result<ObjectInfo, ObjectStorageError> {
+        ResumableUploadManager::head(self, key).await
+    }
+
+    async fn stream_file(&self, key: &str) -> Result<(ObjectInfo, ObjectStream), ObjectStorageError> {
+        ResumableUploadManager::stream_file(self, key).await
+    }
+
+    async fn download_file(&self, key: &str) -> Result<Vec<u8>, ObjectStorageError> {
+        ResumableUploadManager::download_file(self, key).await
+    }
+
+    async fn delete_object(&self, key: &str) -> Result<(), ObjectStorageError> {
+        ResumableUploadManager::delete_object(self, key).await
+    }
+
+    async fn put_object(&self, key: &str, data: Vec<u8>, content_type: &str) -> Result<String, ObjectStorageError> {
+        ResumableUploadManager::put_object(self, key, data, content_type).await
+    }
+
+    async fn set_tags(&self, key: &str, tags: &[(String, String)]) -> Result<(), ObjectStorageError> {
+        ResumableUploadManager::set_tags(self, key, tags).await
+    }
+
+    async fn ping(&self) -> Result<(), ObjectStorageError> {
+        ResumableUploadManager::ping(self).await
+    }
+}
+
+/// Periodically aborts uploads that were started but never completed, so their parts
+/// stop taking up space in the bucket
+pub struct UploadCleaner {
+    store: Arc<dyn ObjectStore>,
+    interval_minutes: u64,
+    max_age_hours: u64,
+}
+
+impl UploadCleaner {
+    pub fn new(store: Arc<dyn ObjectStore>, interval_minutes: u64, max_age_hours: u64) -> Self {
+        Self {
+            store,
+            interval_minutes,
+            max_age_hours,
+        }
+    }
+
+    pub fn start(self, cancel: CancellationToken) {
+        if self.interval_minutes == 0 {
+            tracing::info!("Upload cleanup disabled");
+            return;
+        }
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(self.interval_minutes * 60));
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => {
+                        match self.store.cleanup_expired(self.max_age_hours).await {
+                            Ok(0) => {}
+                            Ok(aborted) => tracing::info!(
+                                "Aborted {} uploads unfinished after {} hours",
+                                aborted,
+                                self.max_age_hours
+                            ),
+                            Err(e) => tracing::warn!("Failed to clean up expired uploads: {}", e),
+                        }
+                    }
+                    _ = cancel.cancelled() => {
+                        tracing::info!("Upload cleanup shutting down");
+                        break;
+                    }
+                }
+            }
+        });
+    }
+}
+
+struct MemoryUpload {
+    key: String,
+    parts: BTreeMap<i32, Vec<u8>>,
+    sha256: Option<String>,
+    created_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Default)]
+struct MemoryState {
+    next_upload_id: u64,
+    uploads: HashMap<String, MemoryUpload>,
+    objects: HashMap<String, Vec<u8>>,
+    tags: HashMap<String, Vec<(String, String)>>,
+    checksums: HashMap<String, String>,
+}
+
+/// In-process store with the same session semantics as S3 multipart uploads. Used by
+/// tests and handy for running the service locally without a bucket.
+#[derive(Default)]
+pub struct MemoryObjectStore {
+    state: Mutex<MemoryState>,
+}
+
+impl MemoryObjectStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stores an object directly, bypassing the multipart flow
+    pub async fn put(&self, key: &str, data: Vec<u8>) {
+        self.state.lock().await.objects.insert(key.to_string(), data);
+    }
+
+    pub async fn get(&self, key: &str) -> Option<Vec<u8>> {
+        self.state.lock().await.objects.get(key).cloned()
+    }
+
+    pub async fn tags(&self, key: &str) -> Vec<(String, String)> {
+        self.state.lock().await.tags.get(key).cloned().unwrap_or_default()
+    }
+}
+
+#[async_trait]
+impl ObjectStore for MemoryObjectStore {
+    async fn init_or_resume(
+        &self,
+        signature: &str,
+        file_name: &str,
+        file_size: i64,
+        sha256: Option<&str>,
+    ) -> Result<InitResponse, ObjectStorageError> {
+        let mut state = self.state.lock().await;
+        let total_chunks = (file_size + DEFAULT_CHUNK_SIZE - 1) / DEFAULT_CHUNK_SIZE;
+        let prefix = format!("{}_", signature);
+
+        if let Some((upload_id, upload)) = state.uploads.iter().find(|(_, u)| u.key.starts_with(&prefix)) {
+            return Ok(InitResponse {
+                upload_id: upload_id.clone(),
+                key: upload.key.clone(),
+                chunk_size: DEFAULT_CHUNK_SIZE,
+                total_chunks,
+                completed_chunks: upload.parts.len() as i64,
+                is_resume: true,
+            });
+        }
+
+        state.next_upload_id += 1;
+        let upload_id = format!("mem-{}", state.next_upload_id);
+        let key = ResumableUploadManager::build_key(signature, file_name);
+        state.uploads.insert(
+            upload_id.clone(),
+            MemoryUpload {
+                key: key.clone(),
+                parts: BTreeMap::new(),
+                sha256: sha256.map(str::to_string),
+                created_at: chrono::Utc::now(),
+            },
+        );
+
+        Ok(InitResponse {
+            upload_id,
+            key,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            total_chunks,
+            completed_chunks: 0,
+            is_resume: false,
+        })
+    }
+
+    async fn upload_part(
+        &self,
+        upload_id: &str,
+        key: &str,
+        data: Vec<u8>,
+        part_number: i32,
+    ) -> Result<String, ObjectStorageError> {
+        let mut state = self.state.lock().await;
+        let upload = state
+            .uploads
+            .get_mut(upload_id)
+            .filter(|u| u.key == key)
+            .ok_or_else(|| ObjectStorageError::SessionNotFound(upload_id.to_string()))?;
+        let etag = format!("\"{}-{}\"", part_number, data.len());
+        upload.parts.insert(part_number, data);
+        Ok(etag)
+    }
+
+    async fn complete(&self, upload_id: &str, key: &str) -> Result<String, ObjectStorageError> {
+        let mut state = self.state.lock().await;
+        let upload = state
+            .uploads
+            .remove(upload_id)
+            .filter(|u| u.key == key)
+            .ok_or_else(|| ObjectStorageError::SessionNotFound(upload_id.to_string()))?;
+        if upload.parts.is_empty() {
+            return Err(ObjectStorageError::SessionNotFound("No parts uploaded".to_string()));
+        }
+
+        let data = upload.parts.into_values().flatten().collect();
+        state.objects.insert(key.to_string(), data);
+        match upload.sha256 {
+            Some(sha256) => state.checksums.insert(key.to_string(), sha256),
+            None => state.checksums.remove(key),
+        };
+        Ok(self.get_file_url(key))
+    }
+
+    async fn abort(&self, upload_id: &str, _key: &str) -> Result<(), ObjectStorageError> {
+        self.state
+            .lock()
+            .await
+            .uploads
+            .remove(upload_id)
+            .map(|_| ())
+            .ok_or_else(|| ObjectStorageError::SessionNotFound(upload_id.to_string()))
+    }
+
+    async fn presign_upload_part(
+        &self,
+        upload_id: &str,
+        key: &str,
+        part_number: i32,
+        _expires_in_secs: u64,
+    ) -> Result<String, ObjectStorageError> {
+        Ok(format!("memory://{}?uploadId={}&partNumber={}", key, upload_id, part_number))
+    }
+
+    async fn uploaded_bytes(&self, upload_id: &str, key: &str) -> Result<i64, ObjectStorageError> {
+        let state = self.state.lock().await;
+        let upload = state
+            .uploads
+            .get(upload_id)
+            .filter(|u| u.key == key)
+            .ok_or_else(|| ObjectStorageError::SessionNotFound(upload_id.to_string()))?;
+        Ok(upload.parts.values().map(|p| p.len() as i64).sum())
+    }
+
+    async fn list_pending(&self) -> Result<Vec<PendingUpload>, ObjectStorageError> {
+        let state = self.state.lock().await;
+        let mut pending: Vec<PendingUpload> = state
+            .uploads
+            .iter()
+            .filter_map(|(upload_id, upload)| {
+                let (signature, file_name) = upload.key.split_once('_')?;
+                Some(PendingUpload {
+                    upload_id: upload_id.clone(),
+                    key: upload.key.clone(),
+                    file_name: file_name.to_string(),
+                    file_signature: signature.to_string(),
+                    completed_chunks: upload.parts.len() as i64,
+                    bytes_uploaded: upload.parts.values().map(|p| p.len() as i64).sum(),
+                    created_at: upload.created_at.to_rfc3339(),
+                })
+            })
+            .collect();
+        pending.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+        Ok(pending)
+    }
+
+    async fn cleanup_expired(&self, max_age_hours: u64) -> Result<usize, ObjectStorageError> {
+        let cutoff = chrono::Utc::now() - chrono::Duration::hours(max_age_hours as i64);
+        let mut state = self.state.lock().await;
+        let before = state.uploads.len();
+        state.uploads.retain(|_, u| u.created_at >= cutoff);
+        Ok(before - state.uploads.len())
+    }
+
+    fn get_file_url(&self, key: &str) -> String {
+        format!("memory://{}", key)
+    }
+
+    fn key_for_url(&self, url: &str) -> Option<String> {
+        url.strip_prefix("memory://").map(|k| k.to_string())
+    }
+
+    async fn get_presigned_url(&self, key: &str, _expires_in_secs: u64) -> Result<String, ObjectStorageError> {
+        Ok(self.get_file_url(key))
+    }
+
+    async fn head(&self, key: &str) -> Result<ObjectInfo, ObjectStorageError> {
+        let state = self.state.lock().await;
+        let data = state
+            .objects
+            .get(key)
+            .ok_or_else(|| ObjectStorageError::ObjectNotFound(key.to_string()))?;
+        Ok(ObjectInfo {
+            size: data.len() as i64,
+            etag: Some(format!("\"{}\"", data.len())),
+            content_type: None,
+            sha256: state.checksums.get(key).cloned(),
+        })
+    }
+
+    async fn stream_file(&self, key: &str) -> Result<(ObjectInfo, ObjectStream), ObjectStorageError> {
+        let info = self.head(key).await?;
+        let data = self.download_file(key).await?;
+        Ok((info, Box::pin(futures_util::stream::once(async move { Ok(Bytes::from(data)) }))))
+    }
+
+    async fn download_file(&self, key: &str) -> Result<Vec<u8>, ObjectStorageError> {
+        self.get(key)
+            .await
+            .ok_or_else(|| ObjectStorageError::ObjectNotFound(key.to_string()))
+    }
+
+    async fn delete_object(&self, key: &str) -> Result<(), ObjectStorageError> {
+        let mut state = self.state.lock().await;
+        state.objects.remove(key);
+        state.tags.remove(key);
+        state.checksums.remove(key);
+        Ok(())
+    }
+
+    async fn put_object(&self, key: &str, data: Vec<u8>, _content_type: &str) -> Result<String, ObjectStorageError> {
+        self.put(key, data).await;
+        Ok(self.get_file_url(key))
+    }
+
+    async fn set_tags(&self, key: &str, tags: &[(String, String)]) -> Result<(), ObjectStorageError> {
+        let mut state = self.state.lock().await;
+        if !state.objects.contains_key(key) {
+            return Err(ObjectStorageError::ObjectNotFound(key.to_string()));
+        }
+        state.tags.insert(key.to_string(), tags.to_vec());
+        Ok(())
+    }
+
+    async fn ping(&self) -> Result<(), ObjectStorageError> {
+        Ok(())
+    }
+}
@@ -0,0 +1,292 @@
+//! Reliable delivery of domain events to external sinks (webhooks, Readwise, exports).
+//! Events are written to the `outbox` table in the same transaction as the change
+//! that emitted them, and a background dispatcher delivers pending rows with retries.
+//! A restart mid-dispatch only delays delivery; it never loses an event.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use libsql::Connection;
+use serde::Serialize;
+use tokio::sync::broadcast::error::RecvError;
+use tokio_util::sync::CancellationToken;
+
+use crate::db::Database;
+use crate::events::Event;
+
+/// Entries that fail this many times stay in the table for inspection but are no
+/// longer retried
+pub const MAX_ATTEMPTS: i64 = 10;
+const BATCH_SIZE: i64 = 100;
+const POLL_INTERVAL: Duration = Duration::from_secs(30);
+const BASE_BACKOFF_SECS: i64 = 30;
+const MAX_BACKOFF_SECS: i64 = 3600;
+/// Delivered entries are kept this long before being pruned
+const RETENTION_DAYS: i64 = 7;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OutboxEntry {
+    pub id: i64,
+    pub event: Event,
+    pub attempts: i64,
+    pub created_at: String,
+}
+
+/// A destination for outbox events. An entry is retried until every sink accepts it,
+/// so a sink may see the same entry more than once and must deduplicate on `entry.id`.
+#[async_trait]
+pub trait OutboxSink: Send + Sync {
+    fn name(&self) -> &str;
+
+    async fn deliver(&self, entry: &OutboxEntry) -> Result<()>;
+}
+
+/// Appends `event` to the outbox. Call it on the connection running the mutation's
+/// transaction so the event is committed, or rolled back, together with the change.
+pub async fn record(conn: &Connection, event: &Event) -> Result<i64> {
+    let payload = serde_json::to_string(event)?;
+    let mut rows = conn
+        .query(
+            "INSERT INTO outbox (event_type, payload) VALUES (?, ?) RETURNING id",
+            libsql::params![event.kind(), payload],
+        )
+        .await?;
+    let row = rows
+        .next()
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("outbox insert returned no row"))?;
+    Ok(row.get(0)?)
+}
+
+/// Seconds to wait before the next attempt after `attempts` failures
+fn backoff_secs(attempts: i64) -> i64 {
+    let exponent = attempts.saturating_sub(1).clamp(0, 20) as u32;
+    BASE_BACKOFF_SECS.saturating_mul(1 << exponent).min(MAX_BACKOFF_SECS)
+}
+
+pub struct OutboxDispatcher {
+    db: Arc<Database>,
+    sinks: Vec<Arc<dyn OutboxSink>>,
+}
+
+impl OutboxDispatcher {
+    pub fn new(db: Arc<Database>) -> Self {
+        Self { db, sinks: Vec::new() }
+    }
+
+    pub fn with_sink(mut self, sink: Arc<dyn OutboxSink>) -> Self {
+        self.sinks.push(sink);
+        self
+    }
+
+    async fn pending(&self) -> Result<Vec<(i64, String, i64, String)>> {
+        let query = r#"
+            SELECT id, payload, attempts, created_at
+            FROM outbox
+            WHERE delivered_at IS NULL
+              AND attempts < ?
+              AND next_attempt_at <= strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
+            ORDER BY id
+            LIMIT ?
+        "#;
+        let mut rows = self
+            .db
+            .connection()
+            .query(query, libsql::params![MAX_ATTEMPTS, BATCH_SIZE])
+            .await?;
+        let mut pending = Vec::new();
+        while let Some(row) = rows.next().await? {
+            pending.push((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?));
+        }
+        Ok(pending)
+    }
+
+    async fn deliver(&self, entry: &OutboxEntry) -> Result<()> {
+        for sink in &self.sinks {
+            sink.deliver(entry)
+                .await
+                .map_err(|e| anyhow::anyhow!("{}: {}", sink.name(), e))?;
+        }
+        Ok(())
+    }
+
+    async fn mark_delivered(&self, id: i64) -> Result<()> {
+        let query = r#"
+            UPDATE outbox
+            SET delivered_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now'), last_error = NULL
+            WHERE id = ?
+        "#;
+        self.db
+            .write(|| async { Ok(self.db.connection().execute(query, libsql::params![id]).await?) })
+            .await?;
+        Ok(())
+    }
+
+    async fn mark_failed(&self, id: i64, attempts: i64, error: &str) -> Result<()> {
+        let query = r#"
+            UPDATE outbox
+            SET attempts = ?,
+                last_error = ?,
+                next_attempt_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now', ?)
+            WHERE id = ?
+        "#;
+        let delay = format!("+{} seconds", backoff_secs(attempts));
+        self.db
+            .write(|| async {
+                Ok(self
+                    .db
+                    .connection()
+                    .execute(query, libsql::params![attempts, error, delay, id])
+                    .await?)
+            })
+            .await?;
+        Ok(())
+    }
+
+    /// Delivers every entry that is due and returns how many were delivered.
+    pub async fn dispatch_pending(&self) -> Result<usize> {
+        // The connection is shared: rows written by an open transaction are visible
+        // here but may still be rolled back, so wait until it has finished.
+        if !self.db.connection().is_autocommit() {
+            return Ok(0);
+        }
+
+        let mut delivered = 0;
+        for (id, payload, attempts, created_at) in self.pending().await? {
+            let result = match serde_json::from_str::<Event>(&payload) {
+                Ok(event) => {
+                    let entry = OutboxEntry {
+                        id,
+                        event,
+                        attempts,
+                        created_at,
+                    };
+                    self.deliver(&entry).await
+                }
+                Err(e) => Err(anyhow::anyhow!("invalid payload: {}", e)),
+            };
+
+            match result {
+                Ok(()) => {
+                    self.mark_delivered(id).await?;
+                    delivered += 1;
+                }
+                Err(e) => {
+                    tracing::warn!("outbox entry {} failed (attempt {}): {}", id, attempts + 1, e);
+                    self.mark_failed(id, attempts + 1, &e.to_string()).await?;
+                }
+            }
+        }
+        Ok(delivered)
+    }
+
+    /// Removes delivered entries older than the retention window.
+    pub async fn prune(&self) -> Result<u64> {
+        let query = r#"
+            DELETE FROM outbox
+            WHERE delivered_at IS NOT NULL
+              AND delivered_at < strftime('%Y-%m-%dT%H:%M:%fZ', 'now', ?)
+        "#;
+        let cutoff = format!("-{} days", RETENTION_DAYS);
+        self.db
+            .write(|| async { Ok(self.db.connection().execute(query, libsql::params![cutoff]).await?) })
+            .await
+    }
+
+    /// Dispatches whenever an event is published and on a fixed interval, so entries
+    /// left over from a previous run and retries that come due are picked up too.
+    pub fn start(self, cancel: CancellationToken) {
+        let mut events = self.db.events().subscribe();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(POLL_INTERVAL);
+            loop {
+                tokio::select! {
+                    received = events.recv() => {
+                        if let Err(RecvError::Closed) = received {
+                            break;
+                        }
+                    }
+                    _ = interval.tick() => {
+                        if let Err(e) = self.prune().await {
+                            tracing::warn!("failed to prune outbox: {}", e);
+                        }
+                    }
+                    _ = cancel.cancelled() => {
+                        tracing::info!("Outbox dispatcher shutting down");
+                        break;
+                    }
+                }
+
+                if let Err(e) = self.dispatch_pending().await {
+                    tracing::warn!("failed to dispatch outbox: {}", e);
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{seed_book, test_db};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::sync::Mutex;
+
+    /// Fails the first delivery, then records what it receives
+    #[derive(Default)]
+    struct FlakySink {
+        calls: AtomicUsize,
+        received: Mutex<Vec<i64>>,
+    }
+
+    #[async_trait]
+    impl OutboxSink for FlakySink {
+        fn name(&self) -> &str {
+            "flaky"
+        }
+
+        async fn deliver(&self, entry: &OutboxEntry) -> Result<()> {
+            if self.calls.fetch_add(1, Ordering::SeqCst) == 0 {
+                anyhow::bail!("connection refused");
+            }
+            self.received.lock().await.push(entry.id);
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failed_delivery_is_retried() {
+        let db = test_db().await;
+        seed_book(&db, "The Mythical Man-Month", &["Fred Brooks"]).await;
+
+        let sink = Arc::new(FlakySink::default());
+        let dispatcher = OutboxDispatcher::new(db.clone()).with_sink(sink.clone());
+        assert_eq!(dispatcher.dispatch_pending().await.unwrap(), 0);
+
+        let conn = &db.connection();
+        let mut rows = conn
+            .query("SELECT id, attempts, last_error FROM outbox", ())
+            .await
+            .unwrap();
+        let row = rows.next().await.unwrap().unwrap();
+        let id: i64 = row.get(0).unwrap();
+        assert_eq!(row.get::<i64>(1).unwrap(), 1);
+        assert_eq!(row.get::<String>(2).unwrap(), "flaky: connection refused");
+
+        conn.execute("UPDATE outbox SET next_attempt_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now', '-1 seconds')", ())
+            .await
+            .unwrap();
+        assert_eq!(dispatcher.dispatch_pending().await.unwrap(), 1);
+        assert_eq!(*sink.received.lock().await, vec![id]);
+        assert_eq!(dispatcher.dispatch_pending().await.unwrap(), 0);
+    }
+
+    #[test]
+    fn test_backoff_is_capped() {
+        assert_eq!(backoff_secs(1), 30);
+        assert_eq!(backoff_secs(2), 60);
+        assert_eq!(backoff_secs(MAX_ATTEMPTS), MAX_BACKOFF_SECS);
+    }
+}
@@ -0,0 +1,304 @@
+//! Data for a client-side command palette (`GET /palette`): every book, shelf and
+//! commonplace resource as a title with the route that opens it, plus the admin
+//! actions worth reaching from the keyboard. A full fetch returns a `cursor`; passing
+//! it back as `?since=` returns only the entries changed after it and the ones that
+//! went away, read from the `palette_changes` journal that triggers keep up to date.
+//!
+//! There are no saved searches in the library yet, so shelves stand in for them.
+
+use std::collections::{HashMap, HashSet};
+
+use anyhow::Result;
+use axum::{
+    extract::{Query, State},
+    response::Response,
+};
+use libsql::Connection;
+use serde::{Deserialize, Serialize};
+
+use crate::handler::AppState;
+use crate::response::{internal_error, success};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    Book,
+    Shelf,
+    Resource,
+}
+
+impl Kind {
+    const ALL: [Kind; 3] = [Kind::Book, Kind::Shelf, Kind::Resource];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Kind::Book => "book",
+            Kind::Shelf => "shelf",
+            Kind::Resource => "resource",
+        }
+    }
+
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Option<Self> {
+        Kind::ALL.into_iter().find(|kind| kind.as_str() == s)
+    }
+
+    /// Query for live entries as `(id, title, subtitle)`, to be followed by a
+    /// condition on `id` and the ordering
+    fn query(&self) -> &'static str {
+        match self {
+            Kind::Book => {
+                r#"
+                SELECT id, title, subtitle FROM (
+                    SELECT b.id, b.title, GROUP_CONCAT(a.name, ', ') AS subtitle
+                    FROM books b
+                    LEFT JOIN book_authors ba ON ba.book_id = b.id
+                    LEFT JOIN authors a ON a.id = ba.author_id
+                    WHERE b.deleted_at IS NULL
+                    GROUP BY b.id
+                )
+                "#
+            }
+            Kind::Shelf => "SELECT id, name, description FROM shelves",
+            Kind::Resource => "SELECT id, title, type FROM resources WHERE deleted_at IS NULL",
+        }
+    }
+
+    fn route(&self, id: i64) -> String {
+        match self {
+            Kind::Book => format!("/html/books/{}", id),
+            Kind::Shelf => format!("/shelves/{}/books", id),
+            Kind::Resource => format!("/commonplace/resources/{}", id),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PaletteItem {
+    pub kind: Kind,
+    pub id: i64,
+    pub title: String,
+    /// Authors of a book, description of a shelf, type of a resource
+    pub subtitle: Option<String>,
+    pub route: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PaletteRef {
+    pub kind: Kind,
+    pub id: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PaletteAction {
+    pub id: &'static str,
+    pub title: &'static str,
+    pub method: &'static str,
+    pub route: &'static str,
+}
+
+const fn action(id: &'static str, title: &'static str, method: &'static str, route: &'static str) -> PaletteAction {
+    PaletteAction {
+        id,
+        title,
+        method,
+        route,
+    }
+}
+
+pub const ACTIONS: &[PaletteAction] = &[
+    action("books.continue", "Continue reading", "GET", "/books/continue"),
+    action("books.trash", "Show trashed books", "GET", "/books/trash"),
+    action("books.export", "Export library", "GET", "/books/export"),
+    action("commonplace.review", "Daily review", "GET", "/commonplace/review"),
+    action("commonplace.inbox", "Open inbox", "GET", "/commonplace/inbox"),
+    action("commonplace.digest", "Build a digest of cold highlights", "POST", "/commonplace/digest"),
+    action("commonplace.export", "Export commonplace archive", "GET", "/commonplace/export"),
+    action("commonplace.trash.purge", "Purge commonplace trash", "POST", "/commonplace/trash/purge"),
+    action("research.sync", "Sync Research", "POST", "/research/sync"),
+    action("readwise.sync", "Sync Readwise", "POST", "/readwise/sync"),
+    action("readwise.push", "Push highlights to Readwise", "POST", "/readwise/push"),
+    action("zotero.sync", "Sync Zotero", "POST", "/zotero/sync"),
+    action("pocket.sync", "Sync Pocket", "POST", "/pocket/sync"),
+    action("kobo.sync", "Sync Kobo", "POST", "/kobo/sync"),
+    action("sync.config.export", "Export sync settings", "GET", "/sync/config/export"),
+    action("admin.integrations", "Integration status", "GET", "/admin/integrations/status"),
+    action("admin.db", "Database stats", "GET", "/admin/db/stats"),
+    action("admin.jobs", "Background jobs", "GET", "/admin/jobs"),
+    action("admin.retention", "Retention policies", "GET", "/admin/retention"),
+];
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Palette {
+    /// Pass back as `?since=` to get what changed after this response
+    pub cursor: i64,
+    /// Whether `items` is the whole palette rather than the changes since a cursor
+    pub full: bool,
+    pub items: Vec<PaletteItem>,
+    /// Entries deleted or trashed since the cursor
+    pub removed: Vec<PaletteRef>,
+    pub actions: &'static [PaletteAction],
+}
+
+async fn latest_change(conn: &Connection) -> Result<i64> {
+    let mut rows = conn
+        .query("SELECT COALESCE(MAX(id), 0) FROM palette_changes", ())
+        .await?;
+    match rows.next().await? {
+        Some(row) => Ok(row.get(0)?),
+        None => Ok(0),
+    }
+}
+
+/// Live entries of `kind`, all of them or only those in `ids`
+async fn load(conn: &Connection, kind: Kind, ids: Option<&[i64]>) -> Result<Vec<PaletteItem>> {
+    let mut query = format!("SELECT * FROM ({})", kind.query());
+    let mut params: Vec<libsql::Value> = Vec::new();
+    if let Some(ids) = ids {
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        query.push_str(&format!(" WHERE id IN ({})", vec!["?"; ids.len()].join(", ")));
+        params.extend(ids.iter().map(|id| libsql::Value::from(*id)));
+    }
+    query.push_str(" ORDER BY 2 COLLATE NOCASE, 1");
+
+    let mut rows = conn.query(&query, params).await?;
+    let mut items = Vec::new();
+    while let Some(row) = rows.next().await? {
+        let id: i64 = row.get(0)?;
+        items.push(PaletteItem {
+            kind,
+            id,
+            title: row.get(1)?,
+            subtitle: row.get::<Option<String>>(2)?.filter(|s| !s.is_empty()),
+            route: kind.route(id),
+        });
+    }
+    Ok(items)
+}
+
+/// The whole palette, or with `since` only what changed after that cursor
+pub async fn palette(conn: &Connection, since: Option<i64>) -> Result<Palette> {
+    // Read before the entries, so a change made meanwhile is sent again next time
+    // rather than missed
+    let cursor = latest_change(conn).await?;
+
+    let Some(since) = since else {
+        let mut items = Vec::new();
+        for kind in Kind::ALL {
+            items.extend(load(conn, kind, None).await?);
+        }
+        return Ok(Palette {
+            cursor,
+            full: true,
+            items,
+            removed: Vec::new(),
+            actions: ACTIONS,
+        });
+    };
+
+    let mut rows = conn
+        .query(
+            "SELECT DISTINCT kind, entity_id FROM palette_changes WHERE id > ? AND id <= ?",
+            libsql::params![since, cursor],
+        )
+        .await?;
+    let mut changed: HashMap<Kind, Vec<i64>> = HashMap::new();
+    while let Some(row) = rows.next().await? {
+        let kind: String = row.get(0)?;
+        let kind = Kind::from_str(&kind).ok_or_else(|| anyhow::anyhow!("Invalid palette change kind: {}", kind))?;
+        changed.entry(kind).or_default().push(row.get(1)?);
+    }
+
+    let mut items = Vec::new();
+    let mut removed = Vec::new();
+    for kind in Kind::ALL {
+        let Some(ids) = changed.get(&kind) else {
+            continue;
+        };
+        let live = load(conn, kind, Some(ids)).await?;
+        let found: HashSet<i64> = live.iter().map(|item| item.id).collect();
+        removed.extend(
+            ids.iter()
+                .filter(|id| !found.contains(id))
+                .map(|&id| PaletteRef { kind, id }),
+        );
+        items.extend(live);
+    }
+
+    Ok(Palette {
+        cursor,
+        full: false,
+        items,
+        removed,
+        actions: ACTIONS,
+    })
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PaletteParams {
+    pub since: Option<i64>,
+}
+
+pub async fn get_palette(State(state): State<AppState>, Query(params): Query<PaletteParams>) -> Response {
+    match palette(&state.db.connection(), params.since).await {
+        Ok(palette) => success(palette),
+        Err(e) => {
+            tracing::error!("failed to build palette: {}", e);
+            internal_error("Failed to build palette")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{seed_book, test_db};
+
+    #[tokio::test]
+    async fn test_palette_refreshes_from_cursor() {
+        let db = test_db().await;
+        let conn = &db.connection();
+        let dune = seed_book(&db, "Dune", &["Frank Herbert"]).await as i64;
+        let emma = seed_book(&db, "Emma", &["Jane Austen"]).await as i64;
+
+        let full = palette(conn, None).await.unwrap();
+        assert!(full.full);
+        let dune_item = full.items.iter().find(|item| item.id == dune).unwrap();
+        assert_eq!(dune_item.subtitle.as_deref(), Some("Frank Herbert"));
+        assert_eq!(dune_item.route, format!("/html/books/{}", dune));
+
+        // Nothing changed yet
+        let unchanged = palette(conn, Some(full.cursor)).await.unwrap();
+        assert!(unchanged.items.is_empty() && unchanged.removed.is_empty());
+        assert_eq!(unchanged.cursor, full.cursor);
+
+        conn.execute("UPDATE books SET title = 'Dune Messiah' WHERE id = ?", libsql::params![dune])
+            .await
+            .unwrap();
+        conn.execute(
+            "UPDATE books SET deleted_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now') WHERE id = ?",
+            libsql::params![emma],
+        )
+        .await
+        .unwrap();
+        // Fields the palette doesn't show leave no trace
+        conn.execute("UPDATE books SET pages = 10 WHERE id = ?", libsql::params![dune])
+            .await
+            .unwrap();
+
+        let changes = palette(conn, Some(full.cursor)).await.unwrap();
+        assert!(!changes.full);
+        let titles: Vec<&str> = changes.items.iter().map(|item| item.title.as_str()).collect();
+        assert_eq!(titles, vec!["Dune Messiah"]);
+        assert_eq!(
+            changes.removed,
+            vec![PaletteRef {
+                kind: Kind::Book,
+                id: emma
+            }]
+        );
+        assert!(palette(conn, Some(changes.cursor)).await.unwrap().items.is_empty());
+    }
+}
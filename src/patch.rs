@@ -0,0 +1,97 @@
+//! RFC 7396 JSON Merge Patch support. A member missing from the patch leaves the
+//! column untouched, `null` clears it, and any other value replaces it.
+
+use serde::{Deserialize, Deserializer};
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum Patch<T> {
+    #[default]
+    Absent,
+    Null,
+    Value(T),
+}
+
+/// Only called for members that are present, so a missing member falls back to
+/// `Default` (`Absent`). Fields must be declared with `#[serde(default)]`.
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Patch<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(match Option::<T>::deserialize(deserializer)? {
+            Some(value) => Patch::Value(value),
+            None => Patch::Null,
+        })
+    }
+}
+
+impl<T> Patch<T> {
+    pub fn is_absent(&self) -> bool {
+        matches!(self, Patch::Absent)
+    }
+
+    pub fn is_null(&self) -> bool {
+        matches!(self, Patch::Null)
+    }
+}
+
+/// Accumulates `column = ?` assignments for a partial UPDATE
+#[derive(Default)]
+pub struct SetClause {
+    assignments: Vec<String>,
+    params: Vec<libsql::Value>,
+}
+
+impl SetClause {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set<T: Clone + Into<libsql::Value>>(&mut self, column: &str, patch: &Patch<T>) {
+        match patch {
+            Patch::Absent => {}
+            Patch::Null => self.assignments.push(format!("{} = NULL", column)),
+            Patch::Value(value) => {
+                self.assignments.push(format!("{} = ?", column));
+                self.params.push(value.clone().into());
+            }
+        }
+    }
+
+    /// Builds `UPDATE {table} SET ... WHERE id = ?`, bumping `updated_at`
+    pub fn into_update(mut self, table: &str, id: i32) -> (String, Vec<libsql::Value>) {
+        self.assignments
+            .push("updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now')".to_string());
+        self.params.push(id.into());
+        let query = format!("UPDATE {} SET {} WHERE id = ?", table, self.assignments.join(", "));
+        (query, self.params)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Deserialize)]
+    struct Sample {
+        #[serde(default)]
+        title: Patch<String>,
+        #[serde(default)]
+        pages: Patch<i32>,
+        #[serde(default)]
+        isbn: Patch<String>,
+    }
+
+    #[test]
+    fn test_merge_patch_members() {
+        let patch: Sample = serde_json::from_str(r#"{"title": "Dune", "pages": null}"#).unwrap();
+        assert_eq!(patch.title, Patch::Value("Dune".to_string()));
+        assert!(patch.pages.is_null());
+        assert!(patch.isbn.is_absent());
+
+        let mut set = SetClause::new();
+        set.set("title", &patch.title);
+        set.set("pages", &patch.pages);
+        set.set("isbn", &patch.isbn);
+        let (query, params) = set.into_update("books", 7);
+        assert!(query.starts_with("UPDATE books SET title = ?, pages = NULL, updated_at = "));
+        assert_eq!(params.len(), 2);
+    }
+}
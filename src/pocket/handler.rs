@@ -0,0 +1,510 @@
+//! Saved articles from Pocket, pulled from its retrieve API
+//! (https://getpocket.com/developer/docs/v3/retrieve) into commonplace as website
+//! resources named by their URL, like pages highlighted with Light. Pocket's highlights
+//! come along as annotations.
+//!
+//! Archiving an article in Pocket marks its resource done; moving it back to the list
+//! marks a done resource unread again, but leaves one being read alone. Articles
+//! deleted in Pocket are soft-deleted. Only what changed since the last sync is pulled,
+//! unless `?full=true` is passed.
+//!
+//! Pocket hands out access tokens through an OAuth flow for registered apps, which is
+//! left to the user: `POST /pocket/config` takes the app's consumer key together with
+//! an access token obtained for it.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use axum::{
+    Json,
+    extract::{Query, State},
+    response::Response,
+};
+use libsql::Connection;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::commonplace::ResourceType;
+use crate::handler::AppState;
+use crate::response::{bad_request, internal_error, success};
+use crate::sync::engine::{self, SyncError, SyncResponse};
+use crate::sync::{Deleted, ResourceKey, SourceAnnotation, SourceResource, SyncSource, is_redacted, redact_secret};
+
+const API_URL: &str = "https://getpocket.com/v3/get";
+const REQUEST_TIMEOUT_SECS: u64 = 30;
+/// Articles asked for per request
+const PAGE_SIZE: usize = 500;
+
+const STATUS_ARCHIVED: &str = "1";
+const STATUS_DELETED: &str = "2";
+
+#[derive(Debug, Deserialize)]
+pub struct SetConfigRequest {
+    pub consumer_key: String,
+    pub access_token: String,
+}
+
+/// What a sync config archive keeps of the Pocket settings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PocketSourceConfig {
+    pub consumer_key: String,
+    /// Redacted on export; importing a redacted token keeps the credentials already set
+    pub access_token: String,
+    /// Pocket's timestamp from the last response
+    pub since: Option<i64>,
+    pub last_sync_at: Option<String>,
+}
+
+/// The credentials themselves are never sent back
+#[derive(Debug, Serialize)]
+pub struct ConfigResponse {
+    pub configured: bool,
+    pub last_sync_at: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Default)]
+pub struct SyncParams {
+    /// Pull everything rather than only what changed since the last sync
+    #[serde(default)]
+    pub full: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct RetrievePage {
+    /// An object keyed by item id, or an empty array when nothing matched
+    #[serde(default)]
+    list: Value,
+    #[serde(default)]
+    since: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PocketItem {
+    pub item_id: String,
+    #[serde(default)]
+    pub given_url: Option<String>,
+    #[serde(default)]
+    pub resolved_url: Option<String>,
+    /// "0" on the list, "1" archived, "2" deleted
+    #[serde(default)]
+    pub status: String,
+    #[serde(default)]
+    pub annotations: Vec<PocketHighlight>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PocketHighlight {
+    pub annotation_id: String,
+    #[serde(default)]
+    pub quote: String,
+}
+
+impl PocketItem {
+    fn url(&self) -> Option<&str> {
+        [self.resolved_url.as_deref(), self.given_url.as_deref()]
+            .into_iter()
+            .flatten()
+            .map(str::trim)
+            .find(|url| !url.is_empty())
+    }
+}
+
+fn parse_items(list: Value) -> Result<Vec<PocketItem>> {
+    match list {
+        Value::Object(items) => {
+            let items: HashMap<String, PocketItem> = serde_json::from_value(Value::Object(items))?;
+            let mut items: Vec<PocketItem> = items.into_values().collect();
+            items.sort_by_key(|item| item.item_id.parse::<i64>().unwrap_or_default());
+            Ok(items)
+        }
+        _ => Ok(Vec::new()),
+    }
+}
+
+struct PocketClient {
+    client: reqwest::Client,
+    consumer_key: String,
+    access_token: String,
+}
+
+impl PocketClient {
+    fn new(consumer_key: &str, access_token: &str) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(REQUEST_TIMEOUT_SECS))
+            .user_agent(concat!("bibliotek/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .unwrap_or_default();
+        Self {
+            client,
+            consumer_key: consumer_key.to_string(),
+            access_token: access_token.to_string(),
+        }
+    }
+
+    async fn retrieve(&self, params: Value) -> Result<reqwest::Response> {
+        let mut body = serde_json::json!({
+            "consumer_key": self.consumer_key,
+            "access_token": self.access_token,
+        });
+        if let (Some(body), Value::Object(params)) = (body.as_object_mut(), params) {
+            body.extend(params);
+        }
+        Ok(self
+            .client
+            .post(API_URL)
+            .header("X-Accept", "application/json")
+            .json(&body)
+            .send()
+            .await?)
+    }
+
+    /// Whether Pocket accepts the credentials
+    async fn check_credentials(&self) -> Result<bool> {
+        let resp = self.retrieve(serde_json::json!({ "count": 1 })).await?;
+        match resp.status() {
+            status if status.is_success() => Ok(true),
+            reqwest::StatusCode::UNAUTHORIZED | reqwest::StatusCode::FORBIDDEN => Ok(false),
+            status => anyhow::bail!("Pocket answered {}", status),
+        }
+    }
+
+    /// Every article changed after `since`, with Pocket's timestamp to pass next time
+    async fn changes(&self, since: Option<i64>) -> Result<(Vec<PocketItem>, Option<i64>)> {
+        let mut items = Vec::new();
+        let mut latest = None;
+        loop {
+            let mut params = serde_json::json!({
+                "state": "all",
+                "detailType": "complete",
+                "annotations": 1,
+                "sort": "oldest",
+                "count": PAGE_SIZE,
+                "offset": items.len(),
+            });
+            if let Some(since) = since {
+                params["since"] = since.into();
+            }
+            let page: RetrievePage = self.retrieve(params).await?.error_for_status()?.json().await?;
+            latest = page.since.or(latest);
+            let batch = parse_items(page.list)?;
+            let done = batch.len() < PAGE_SIZE;
+            items.extend(batch);
+            if done {
+                return Ok((items, latest));
+            }
+        }
+    }
+}
+
+struct PocketConfig {
+    consumer_key: String,
+    access_token: String,
+    since: Option<i64>,
+    last_sync_at: Option<String>,
+}
+
+async fn load_config(conn: &Connection) -> Result<Option<PocketConfig>> {
+    let mut rows = conn
+        .query("SELECT consumer_key, access_token, since, last_sync_at FROM pocket_config WHERE id = 1", ())
+        .await?;
+    match rows.next().await? {
+        Some(row) => Ok(Some(PocketConfig {
+            consumer_key: row.get(0)?,
+            access_token: row.get(1)?,
+            since: row.get(2)?,
+            last_sync_at: row.get(3)?,
+        })),
+        None => Ok(None),
+    }
+}
+
+/// The settings with the access token redacted, for the sync config archive
+pub async fn export_config(conn: &Connection) -> Result<Option<PocketSourceConfig>> {
+    Ok(load_config(conn).await?.map(|config| PocketSourceConfig {
+        consumer_key: config.consumer_key,
+        access_token: redact_secret(&config.access_token),
+        since: config.since,
+        last_sync_at: config.last_sync_at,
+    }))
+}
+
+/// Returns false if nothing was imported: the archive's access token is redacted and
+/// there are no credentials here to keep. They aren't checked with Pocket, unlike
+/// `set_config`.
+pub async fn import_config(conn: &Connection, config: &PocketSourceConfig) -> Result<bool> {
+    let params = libsql::params![config.since, config.last_sync_at.clone()];
+    if is_redacted(&config.access_token) {
+        let query = r#"
+            UPDATE pocket_config
+            SET since = ?, last_sync_at = ?, updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
+            WHERE id = 1
+        "#;
+        return Ok(conn.execute(query, params).await? > 0);
+    }
+
+    let query = r#"
+        INSERT INTO pocket_config (id, consumer_key, access_token, since, last_sync_at)
+        VALUES (1, ?, ?, ?, ?)
+        ON CONFLICT(id) DO UPDATE SET
+            consumer_key = excluded.consumer_key,
+            access_token = excluded.access_token,
+            since = excluded.since,
+            last_sync_at = excluded.last_sync_at,
+            updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
+    "#;
+    let params = libsql::params![
+        config.consumer_key.clone(),
+        config.access_token.clone(),
+        config.since,
+        config.last_sync_at.clone()
+    ];
+    conn.execute(query, params).await?;
+    Ok(true)
+}
+
+pub async fn get_config(State(state): State<AppState>) -> Response {
+    match load_config(&state.db.connection()).await {
+        Ok(config) => success(ConfigResponse {
+            configured: config.is_some(),
+            last_sync_at: config.and_then(|config| config.last_sync_at),
+        }),
+        Err(e) => {
+            tracing::error!("Failed to get Pocket config: {}", e);
+            internal_error("Failed to get config")
+        }
+    }
+}
+
+pub async fn set_config(State(state): State<AppState>, Json(payload): Json<SetConfigRequest>) -> Response {
+    let (consumer_key, access_token) = (payload.consumer_key.trim(), payload.access_token.trim());
+    if consumer_key.is_empty() || access_token.is_empty() {
+        return bad_request("consumer_key and access_token are required");
+    }
+    match PocketClient::new(consumer_key, access_token).check_credentials().await {
+        Ok(true) => {}
+        Ok(false) => return bad_request("Pocket did not accept the credentials"),
+        Err(e) => {
+            tracing::error!("Failed to check Pocket credentials: {}", e);
+            return internal_error("Failed to reach Pocket");
+        }
+    }
+
+    // New credentials may belong to another account, so the next sync starts over
+    let query = r#"
+        INSERT INTO pocket_config (id, consumer_key, access_token)
+        VALUES (1, ?, ?)
+        ON CONFLICT(id) DO UPDATE SET
+            consumer_key = excluded.consumer_key,
+            access_token = excluded.access_token,
+            since = NULL,
+            last_sync_at = NULL,
+            updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
+    "#;
+    match state
+        .db
+        .connection()
+        .execute(query, libsql::params![consumer_key, access_token])
+        .await
+    {
+        Ok(_) => success(ConfigResponse {
+            configured: true,
+            last_sync_at: None,
+        }),
+        Err(e) => {
+            tracing::error!("Failed to set Pocket config: {}", e);
+            internal_error("Failed to save configuration")
+        }
+    }
+}
+
+/// Articles retrieved from Pocket, applied as a sync of the "pocket" source
+struct PocketSync {
+    resources: Mutex<Vec<SourceResource>>,
+    deleted: Vec<Deleted>,
+    /// Everything was retrieved, so what is missing was deleted
+    full: bool,
+}
+
+impl PocketSync {
+    fn new(items: Vec<PocketItem>, full: bool) -> Self {
+        let mut resources = Vec::new();
+        let mut deleted = Vec::new();
+        for item in items {
+            let external_id = format!("pocket:{}", item.item_id);
+            if item.status == STATUS_DELETED {
+                deleted.push(Deleted::Resource(external_id));
+                continue;
+            }
+            let Some(url) = item.url() else {
+                tracing::warn!("Pocket item {} has no URL", item.item_id);
+                continue;
+            };
+            resources.push(SourceResource {
+                key: ResourceKey::External(external_id),
+                title: url.to_string(),
+                resource_type: ResourceType::Website,
+                annotations: item.annotations.iter().map(to_annotation).collect(),
+                notes: Vec::new(),
+                archived: Some(item.status == STATUS_ARCHIVED),
+            });
+        }
+        Self {
+            resources: Mutex::new(resources),
+            deleted,
+            full,
+        }
+    }
+}
+
+fn to_annotation(highlight: &PocketHighlight) -> SourceAnnotation {
+    SourceAnnotation {
+        external_id: format!("pocket:highlight:{}", highlight.annotation_id),
+        text: highlight.quote.clone(),
+        color: None,
+        boundary: None,
+        comments: Vec::new(),
+    }
+}
+
+#[async_trait]
+impl SyncSource for PocketSync {
+    fn name(&self) -> String {
+        "pocket".to_string()
+    }
+
+    fn additive(&self) -> bool {
+        !self.full
+    }
+
+    fn deleted(&self) -> Vec<Deleted> {
+        self.deleted.clone()
+    }
+
+    async fn fetch(&self) -> Result<Vec<SourceResource>> {
+        Ok(std::mem::take(&mut *self.resources.lock().unwrap()))
+    }
+}
+
+pub async fn sync(State(state): State<AppState>, Query(params): Query<SyncParams>) -> Response {
+    let conn = &state.db.connection();
+    let config = match load_config(conn).await {
+        Ok(Some(config)) => config,
+        Ok(None) => return bad_request("Pocket credentials not configured. Please set them first."),
+        Err(e) => {
+            tracing::error!("Failed to get Pocket config: {}", e);
+            return internal_error("Failed to get config");
+        }
+    };
+
+    let since = config.since.filter(|_| !params.full);
+    let client = PocketClient::new(&config.consumer_key, &config.access_token);
+    let (items, latest) = match client.changes(since).await {
+        Ok(changes) => changes,
+        Err(e) => {
+            tracing::error!("Failed to retrieve from Pocket: {}", e);
+            return internal_error("Failed to fetch articles from Pocket");
+        }
+    };
+
+    let source = PocketSync::new(items, params.full);
+    let report = match engine::run(&state, &source).await {
+        Ok(report) => report,
+        Err(SyncError::Fetch(e)) => {
+            tracing::error!("Failed to read Pocket articles: {}", e);
+            return internal_error("Failed to fetch articles from Pocket");
+        }
+        Err(SyncError::StartRun(e)) => {
+            tracing::error!("Failed to start pocket sync run: {}", e);
+            return internal_error("Failed to start sync run");
+        }
+    };
+
+    let query = r#"
+        UPDATE pocket_config
+        SET since = COALESCE(?, since),
+            last_sync_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now'),
+            updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
+        WHERE id = 1
+    "#;
+    if let Err(e) = state
+        .db
+        .write(|| async { Ok(conn.execute(query, libsql::params![latest]).await?) })
+        .await
+    {
+        tracing::error!("Failed to record Pocket sync: {}", e);
+    }
+
+    success(SyncResponse::from(&report))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commonplace::{ResourceStatus, UpdateResource};
+    use crate::test_support::test_state;
+
+    fn retrieved(status: &str) -> Vec<PocketItem> {
+        let page: RetrievePage = serde_json::from_value(serde_json::json!({
+            "status": 1,
+            "since": 1760000000,
+            "list": {
+                "229279689": {
+                    "item_id": "229279689",
+                    "given_url": "http://example.com/essay?utm_source=pocket",
+                    "resolved_url": "https://example.com/essay",
+                    "status": status,
+                    "annotations": [{ "annotation_id": "a1", "quote": "Read slowly" }],
+                },
+            },
+        }))
+        .unwrap();
+        parse_items(page.list).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_pocket_status_follows_the_article() {
+        let state = test_state().await;
+        let lib = state.db.commonplace();
+        let empty: RetrievePage = serde_json::from_value(serde_json::json!({ "list": [] })).unwrap();
+        assert!(parse_items(empty.list).unwrap().is_empty());
+
+        let sync = |status: &str| PocketSync::new(retrieved(status), false);
+        let first = engine::run(&state, &sync("0")).await.unwrap();
+        assert_eq!((first.resources.created, first.annotations.created), (1, 1));
+        let resource = lib
+            .find_resource_by_external_id("pocket:229279689")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!((resource.title.as_str(), resource.status), ("https://example.com/essay", ResourceStatus::Unread));
+
+        // Being read here isn't undone by the article still being on the list
+        let reading = UpdateResource {
+            status: Some(ResourceStatus::Reading),
+            ..Default::default()
+        };
+        lib.update_resource(resource.id, reading).await.unwrap();
+        let again = engine::run(&state, &sync("0")).await.unwrap();
+        assert_eq!((again.resources.unchanged, again.annotations.unchanged), (1, 1));
+
+        let archived = engine::run(&state, &sync(STATUS_ARCHIVED)).await.unwrap();
+        assert_eq!(archived.resources.updated, 1);
+        let resource = lib.get_resource(resource.id).await.unwrap().unwrap();
+        assert_eq!(resource.status, ResourceStatus::Done);
+
+        let listed = engine::run(&state, &sync("0")).await.unwrap();
+        assert_eq!(listed.resources.updated, 1);
+        let resource = lib.get_resource(resource.id).await.unwrap().unwrap();
+        assert_eq!(resource.status, ResourceStatus::Unread);
+
+        // Only what changed is pulled, so an article missing from it is kept
+        let nothing = engine::run(&state, &PocketSync::new(Vec::new(), false)).await.unwrap();
+        assert_eq!(nothing.resources.deleted, 0);
+
+        let deleted = engine::run(&state, &sync(STATUS_DELETED)).await.unwrap();
+        assert_eq!(deleted.resources.deleted, 1);
+    }
+}
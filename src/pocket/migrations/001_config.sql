@@ -0,0 +1,12 @@
+-- Pocket Module Configuration
+-- Stores the API credentials and where the last sync left off
+
+CREATE TABLE IF NOT EXISTS pocket_config (
+    id INTEGER PRIMARY KEY CHECK (id = 1), -- Only one config row allowed
+    consumer_key TEXT NOT NULL,
+    access_token TEXT NOT NULL,
+    since INTEGER, -- Pocket's own timestamp from the last response, so only changes since then are pulled
+    last_sync_at TEXT,
+    created_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    updated_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))
+);
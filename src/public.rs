@@ -0,0 +1,139 @@
+//! What a library published with `public.enabled` offers search engines and link
+//! previews: `/sitemap.xml` listing the `/html` pages of public books (see
+//! [`crate::model::Visibility`]), and schema.org `Book` JSON-LD plus Open Graph tags
+//! in each of those pages. URLs are made absolute with `public.base_url`, or the
+//! origin the request was made to when that isn't set.
+
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use quick_xml::escape::escape;
+use serde_json::{Value, json};
+
+use crate::commonplace::permalink;
+use crate::config::Public;
+use crate::handler::AppState;
+use crate::model::Book;
+
+/// Absolute URL the `/html` pages are published under, without a trailing slash
+pub fn base_url(public: &Public, headers: &HeaderMap) -> String {
+    public
+        .base_url
+        .clone()
+        .or_else(|| permalink::origin(headers))
+        .unwrap_or_default()
+        .trim_end_matches('/')
+        .to_string()
+}
+
+/// Metadata embedded in the head of a published book page
+pub struct BookMeta {
+    pub url: String,
+    pub image: Option<String>,
+    pub json_ld: String,
+}
+
+impl BookMeta {
+    pub fn new(book: &Book, authors: &[String], base_url: &str) -> Self {
+        let url = format!("{}/html/books/{}", base_url, book.id);
+        let image = (!book.cover_url.is_empty()).then(|| format!("{}/books/{}/cover", base_url, book.id));
+
+        let mut ld = json!({
+            "@context": "https://schema.org",
+            "@type": "Book",
+            "name": book.title,
+            "url": url,
+        });
+        if !authors.is_empty() {
+            ld["author"] = authors
+                .iter()
+                .map(|name| json!({"@type": "Person", "name": name}))
+                .collect::<Value>();
+        }
+        if let Some(image) = &image {
+            ld["image"] = json!(image);
+        }
+        if !book.description.is_empty() {
+            ld["description"] = json!(book.description);
+        }
+        if !book.isbn.is_empty() {
+            ld["isbn"] = json!(book.isbn);
+        }
+        if book.pages > 0 {
+            ld["numberOfPages"] = json!(book.pages);
+        }
+        if !book.publish_date.is_empty() {
+            ld["datePublished"] = json!(book.publish_date);
+        }
+        if book.ratings > 0 {
+            ld["review"] = json!({
+                "@type": "Review",
+                "reviewRating": {"@type": "Rating", "ratingValue": book.ratings, "bestRating": 5, "worstRating": 1},
+            });
+        }
+
+        Self {
+            url,
+            image,
+            // Goes inside a <script> element, which a literal "</" could close early
+            json_ld: ld.to_string().replace("</", "<\\/"),
+        }
+    }
+}
+
+pub async fn sitemap(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    if !state.public.enabled {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    let books = match state.db.list_book_updates().await {
+        Ok(books) => books,
+        Err(e) => {
+            tracing::error!("failed to list books for sitemap: {}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    let base = base_url(&state.public, &headers);
+    let mut xml = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n",
+    );
+    xml.push_str(&format!("  <url><loc>{}/html/books</loc></url>\n", escape(base.as_str())));
+    for (id, updated_at) in books {
+        xml.push_str(&format!(
+            "  <url><loc>{}/html/books/{}</loc><lastmod>{}</lastmod></url>\n",
+            escape(base.as_str()),
+            id,
+            escape(updated_at.as_str())
+        ));
+    }
+    xml.push_str("</urlset>\n");
+
+    ([(header::CONTENT_TYPE, "application/xml; charset=utf-8")], xml).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{seed_book, test_db};
+
+    #[tokio::test]
+    async fn test_book_meta_json_ld() {
+        let db = test_db().await;
+        let id = seed_book(&db, "Dune", &["Frank Herbert"]).await;
+        let mut book = db.get_book_by_id(id).await.unwrap().unwrap();
+        book.ratings = 4;
+        book.description = "Spice </script> and sand".to_string();
+
+        let meta = BookMeta::new(&book, &["Frank Herbert".to_string()], "https://books.example.com");
+        assert_eq!(meta.url, format!("https://books.example.com/html/books/{}", id));
+        assert!(!meta.json_ld.contains("</script>"));
+        let ld: Value = serde_json::from_str(&meta.json_ld).unwrap();
+        assert_eq!(ld["@type"], "Book");
+        assert_eq!(ld["name"], "Dune");
+        assert_eq!(ld["author"][0]["name"], "Frank Herbert");
+        assert_eq!(ld["review"]["reviewRating"]["ratingValue"], 4);
+        assert_eq!(ld["description"], "Spice </script> and sand");
+    }
+}
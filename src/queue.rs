@@ -0,0 +1,429 @@
+//! Reading queue: books and commonplace resources in the order they should be read.
+//! Entries are ordered by priority, highest first, then by position, which
+//! `PUT /queue/order` rearranges. `POST /queue/pop` takes the next one off the top.
+//!
+//! Entries that leave the queue, popped or removed unread, are kept, and
+//! `GET /queue/burndown` charts from them how the queue grew and shrank day by day.
+
+use anyhow::Result;
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+    response::Response,
+};
+use libsql::Connection;
+use serde::{Deserialize, Serialize};
+
+use crate::db::Database;
+use crate::handler::AppState;
+use crate::response::{bad_request, conflict, internal_error, not_found, success};
+
+pub const DEFAULT_BURNDOWN_DAYS: i32 = 30;
+pub const MAX_BURNDOWN_DAYS: i32 = 365;
+const QUEUED: &str = "q.popped_at IS NULL AND q.removed_at IS NULL";
+const ORDER: &str = "q.priority DESC, q.position, q.id";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QueueKind {
+    Book,
+    Resource,
+}
+
+impl QueueKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            QueueKind::Book => "book",
+            QueueKind::Resource => "resource",
+        }
+    }
+
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "book" => Some(QueueKind::Book),
+            "resource" => Some(QueueKind::Resource),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct QueueItem {
+    pub id: i64,
+    pub kind: QueueKind,
+    pub entity_id: i64,
+    /// Title of the book or resource, `None` if it has since been purged
+    pub title: Option<String>,
+    pub priority: i64,
+    pub position: i64,
+    pub added_at: String,
+    pub popped_at: Option<String>,
+    pub removed_at: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BurndownDay {
+    pub date: String,
+    pub added: i64,
+    pub popped: i64,
+    pub removed: i64,
+    /// Entries still queued at the end of the day
+    pub remaining: i64,
+}
+
+pub enum Push {
+    Queued(QueueItem),
+    NotFound,
+    AlreadyQueued(QueueItem),
+}
+
+fn select(condition: &str) -> String {
+    format!(
+        r#"
+        SELECT q.id, q.kind, q.entity_id, COALESCE(b.title, r.title), q.priority, q.position,
+            q.added_at, q.popped_at, q.removed_at
+        FROM reading_queue q
+        LEFT JOIN books b ON q.kind = 'book' AND b.id = q.entity_id
+        LEFT JOIN resources r ON q.kind = 'resource' AND r.id = q.entity_id
+        WHERE {}
+        "#,
+        condition
+    )
+}
+
+async fn query_items(
+    conn: &Connection,
+    query: &str,
+    params: impl libsql::params::IntoParams,
+) -> Result<Vec<QueueItem>> {
+    let mut rows = conn.query(query, params).await?;
+    let mut items = Vec::new();
+    while let Some(row) = rows.next().await? {
+        let kind: String = row.get(1)?;
+        items.push(QueueItem {
+            id: row.get(0)?,
+            kind: QueueKind::from_str(&kind).ok_or_else(|| anyhow::anyhow!("Invalid queue kind: {}", kind))?,
+            entity_id: row.get(2)?,
+            title: row.get(3)?,
+            priority: row.get(4)?,
+            position: row.get(5)?,
+            added_at: row.get(6)?,
+            popped_at: row.get(7)?,
+            removed_at: row.get(8)?,
+        });
+    }
+    Ok(items)
+}
+
+pub async fn get_item(conn: &Connection, id: i64) -> Result<Option<QueueItem>> {
+    let items = query_items(conn, &select("q.id = ?"), libsql::params![id]).await?;
+    Ok(items.into_iter().next())
+}
+
+/// Queued entries, next to be read first
+pub async fn list(conn: &Connection) -> Result<Vec<QueueItem>> {
+    query_items(conn, &format!("{} ORDER BY {}", select(QUEUED), ORDER), ()).await
+}
+
+/// Adds a book or resource to the end of its priority
+pub async fn push(conn: &Connection, kind: QueueKind, entity_id: i64, priority: i64) -> Result<Push> {
+    let exists = match kind {
+        QueueKind::Book => "SELECT 1 FROM books WHERE id = ? AND deleted_at IS NULL",
+        QueueKind::Resource => "SELECT 1 FROM resources WHERE id = ? AND deleted_at IS NULL",
+    };
+    if conn
+        .query(exists, libsql::params![entity_id])
+        .await?
+        .next()
+        .await?
+        .is_none()
+    {
+        return Ok(Push::NotFound);
+    }
+    let queued = select(&format!("{} AND q.kind = ? AND q.entity_id = ?", QUEUED));
+    if let Some(item) = query_items(conn, &queued, libsql::params![kind.as_str(), entity_id])
+        .await?
+        .pop()
+    {
+        return Ok(Push::AlreadyQueued(item));
+    }
+
+    let query = r#"
+        INSERT INTO reading_queue (kind, entity_id, priority, position)
+        SELECT ?, ?, ?, COALESCE(MAX(position), 0) + 1 FROM reading_queue
+        RETURNING id
+    "#;
+    let mut rows = conn
+        .query(query, libsql::params![kind.as_str(), entity_id, priority])
+        .await?;
+    let id: i64 = match rows.next().await? {
+        Some(row) => row.get(0)?,
+        None => anyhow::bail!("Failed to queue {} {}", kind.as_str(), entity_id),
+    };
+    let item = get_item(conn, id)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Queue entry {} vanished", id))?;
+    Ok(Push::Queued(item))
+}
+
+/// Takes the next entry off the queue
+pub async fn pop(conn: &Connection) -> Result<Option<QueueItem>> {
+    let query = format!(
+        r#"
+        UPDATE reading_queue SET popped_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
+        WHERE id = (SELECT q.id FROM reading_queue q WHERE {} ORDER BY {} LIMIT 1)
+        RETURNING id
+        "#,
+        QUEUED, ORDER
+    );
+    let mut rows = conn.query(&query, ()).await?;
+    let Some(row) = rows.next().await? else {
+        return Ok(None);
+    };
+    let id: i64 = row.get(0)?;
+    get_item(conn, id).await
+}
+
+/// Takes an entry off the queue unread. `false` if it wasn't queued.
+pub async fn remove(conn: &Connection, id: i64) -> Result<bool> {
+    let query = format!(
+        "UPDATE reading_queue AS q SET removed_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now') WHERE q.id = ? AND {}",
+        QUEUED
+    );
+    Ok(conn.execute(&query, libsql::params![id]).await? > 0)
+}
+
+/// Moves a queued entry to another priority, at the end of it
+pub async fn set_priority(conn: &Connection, id: i64, priority: i64) -> Result<Option<QueueItem>> {
+    let query = format!(
+        r#"
+        UPDATE reading_queue AS q
+        SET priority = ?, position = (SELECT COALESCE(MAX(position), 0) + 1 FROM reading_queue)
+        WHERE q.id = ? AND {}
+        "#,
+        QUEUED
+    );
+    if conn.execute(&query, libsql::params![priority, id]).await? == 0 {
+        return Ok(None);
+    }
+    get_item(conn, id).await
+}
+
+/// Puts the queued entries `ids` in the given order, within the positions they
+/// already take up, so entries left out keep their place. Returns the ids that
+/// aren't queued, and changes nothing, if there are any.
+pub async fn reorder(db: &Database, ids: &[i64]) -> Result<Vec<i64>> {
+    let conn = &db.connection();
+    let mut positions = Vec::new();
+    let mut missing = Vec::new();
+    for &id in ids {
+        match get_item(conn, id).await? {
+            Some(item) if item.popped_at.is_none() && item.removed_at.is_none() => positions.push(item.position),
+            _ => missing.push(id),
+        }
+    }
+    if !missing.is_empty() {
+        return Ok(missing);
+    }
+    positions.sort_unstable();
+
+    let _guard = db.begin().await?;
+    for (id, position) in ids.iter().zip(positions) {
+        if let Err(e) = conn
+            .execute("UPDATE reading_queue SET position = ? WHERE id = ?", libsql::params![position, *id])
+            .await
+        {
+            let _ = db.rollback().await;
+            return Err(e.into());
+        }
+    }
+    db.commit().await?;
+    Ok(Vec::new())
+}
+
+/// Entries added, popped and removed each day over the last `days` days, and how
+/// many were left queued at the end of it
+pub async fn burndown(conn: &Connection, days: i32) -> Result<Vec<BurndownDay>> {
+    let query = r#"
+        WITH RECURSIVE days(day) AS (
+            SELECT date('now', ?)
+            UNION ALL
+            SELECT date(day, '+1 day') FROM days WHERE day < date('now')
+        )
+        SELECT day,
+            (SELECT COUNT(*) FROM reading_queue WHERE date(added_at) = day),
+            (SELECT COUNT(*) FROM reading_queue WHERE date(popped_at) = day),
+            (SELECT COUNT(*) FROM reading_queue WHERE date(removed_at) = day),
+            (SELECT COUNT(*) FROM reading_queue
+                WHERE date(added_at) <= day
+                    AND (popped_at IS NULL OR date(popped_at) > day)
+                    AND (removed_at IS NULL OR date(removed_at) > day))
+        FROM days
+        ORDER BY day
+    "#;
+    let mut rows = conn
+        .query(query, libsql::params![format!("-{} days", days - 1)])
+        .await?;
+    let mut chart = Vec::new();
+    while let Some(row) = rows.next().await? {
+        chart.push(BurndownDay {
+            date: row.get(0)?,
+            added: row.get(1)?,
+            popped: row.get(2)?,
+            removed: row.get(3)?,
+            remaining: row.get(4)?,
+        });
+    }
+    Ok(chart)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PushRequest {
+    pub kind: QueueKind,
+    pub id: i64,
+    #[serde(default)]
+    pub priority: i64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReorderRequest {
+    pub ids: Vec<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PriorityRequest {
+    pub priority: i64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BurndownParams {
+    pub days: Option<i32>,
+}
+
+pub async fn get_queue(State(state): State<AppState>) -> Response {
+    match list(&state.db.connection()).await {
+        Ok(items) => success(items),
+        Err(e) => {
+            tracing::error!("Failed to list reading queue: {}", e);
+            internal_error("Failed to list reading queue")
+        }
+    }
+}
+
+pub async fn push_item(State(state): State<AppState>, Json(req): Json<PushRequest>) -> Response {
+    match push(&state.db.connection(), req.kind, req.id, req.priority).await {
+        Ok(Push::Queued(item)) => success(item),
+        Ok(Push::NotFound) => not_found(&format!("{} {} not found", req.kind.as_str(), req.id)),
+        Ok(Push::AlreadyQueued(item)) => conflict(&format!("Already queued as entry {}", item.id)),
+        Err(e) => {
+            tracing::error!("Failed to queue {} {}: {}", req.kind.as_str(), req.id, e);
+            internal_error("Failed to queue item")
+        }
+    }
+}
+
+pub async fn pop_item(State(state): State<AppState>) -> Response {
+    match pop(&state.db.connection()).await {
+        Ok(Some(item)) => success(item),
+        Ok(None) => not_found("Reading queue is empty"),
+        Err(e) => {
+            tracing::error!("Failed to pop reading queue: {}", e);
+            internal_error("Failed to pop reading queue")
+        }
+    }
+}
+
+pub async fn reorder_queue(State(state): State<AppState>, Json(req): Json<ReorderRequest>) -> Response {
+    let mut seen = std::collections::HashSet::new();
+    if !req.ids.iter().all(|id| seen.insert(*id)) {
+        return bad_request("ids must not repeat");
+    }
+    match reorder(&state.db, &req.ids).await {
+        Ok(missing) if missing.is_empty() => get_queue(State(state)).await,
+        Ok(missing) => bad_request(&format!("Not in the queue: {:?}", missing)),
+        Err(e) => {
+            tracing::error!("Failed to reorder reading queue: {}", e);
+            internal_error("Failed to reorder reading queue")
+        }
+    }
+}
+
+pub async fn update_item(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    Json(req): Json<PriorityRequest>,
+) -> Response {
+    match set_priority(&state.db.connection(), id, req.priority).await {
+        Ok(Some(item)) => success(item),
+        Ok(None) => not_found("Queue entry not found"),
+        Err(e) => {
+            tracing::error!("Failed to update queue entry {}: {}", id, e);
+            internal_error("Failed to update queue entry")
+        }
+    }
+}
+
+pub async fn remove_item(State(state): State<AppState>, Path(id): Path<i64>) -> Response {
+    match remove(&state.db.connection(), id).await {
+        Ok(true) => success(serde_json::json!({ "removed": id })),
+        Ok(false) => not_found("Queue entry not found"),
+        Err(e) => {
+            tracing::error!("Failed to remove queue entry {}: {}", id, e);
+            internal_error("Failed to remove queue entry")
+        }
+    }
+}
+
+pub async fn get_burndown(State(state): State<AppState>, Query(params): Query<BurndownParams>) -> Response {
+    let days = params.days.unwrap_or(DEFAULT_BURNDOWN_DAYS).clamp(1, MAX_BURNDOWN_DAYS);
+    match burndown(&state.db.connection(), days).await {
+        Ok(chart) => success(chart),
+        Err(e) => {
+            tracing::error!("Failed to chart reading queue: {}", e);
+            internal_error("Failed to chart reading queue")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{seed_book, test_db};
+
+    #[tokio::test]
+    async fn test_queue_order_and_burndown() {
+        let db = test_db().await;
+        let conn = &db.connection();
+        let mut entries = Vec::new();
+        for (title, priority) in [("Dune", 0), ("Emma", 0), ("Ulysses", 0), ("Urgent", 5)] {
+            let book = seed_book(&db, title, &[]).await as i64;
+            let Push::Queued(item) = push(conn, QueueKind::Book, book, priority).await.unwrap() else {
+                panic!("{} was not queued", title);
+            };
+            entries.push(item);
+        }
+        assert!(matches!(
+            push(conn, QueueKind::Book, entries[0].entity_id, 0).await.unwrap(),
+            Push::AlreadyQueued(_)
+        ));
+        assert!(matches!(push(conn, QueueKind::Resource, 99, 0).await.unwrap(), Push::NotFound));
+
+        // Ulysses before Dune, Emma stays where it was
+        assert!(reorder(&db, &[entries[2].id, entries[0].id]).await.unwrap().is_empty());
+        let titles = |items: Vec<QueueItem>| items.into_iter().map(|i| i.title.unwrap()).collect::<Vec<_>>();
+        assert_eq!(titles(list(conn).await.unwrap()), vec!["Urgent", "Ulysses", "Emma", "Dune"]);
+        assert_eq!(reorder(&db, &[entries[0].id, 404]).await.unwrap(), vec![404]);
+
+        assert_eq!(pop(conn).await.unwrap().unwrap().title.as_deref(), Some("Urgent"));
+        assert!(remove(conn, entries[1].id).await.unwrap());
+        assert!(!remove(conn, entries[1].id).await.unwrap());
+        assert_eq!(titles(list(conn).await.unwrap()), vec!["Ulysses", "Dune"]);
+
+        let chart = burndown(conn, 7).await.unwrap();
+        assert_eq!(chart.len(), 7);
+        let today = chart.last().unwrap();
+        assert_eq!((today.added, today.popped, today.removed, today.remaining), (4, 1, 1, 2));
+        assert_eq!(chart[0].remaining, 0);
+    }
+}
@@ -0,0 +1,778 @@
+//! Books and highlights from Readwise, pulled from its export API
+//! (https://readwise.io/api_deets) into commonplace. Each Readwise book becomes a
+//! resource and each highlight an annotation, with the highlight's note as a comment
+//! on it. Everything is keyed by its Readwise id, so syncing again updates in place.
+//!
+//! Articles with a source URL become website resources named by their URL, like
+//! pages highlighted with Light; books, tweets, podcasts and the rest become PDF
+//! resources named by their title. The export is applied through the sync engine as
+//! the "readwise" source. Only what changed since the last sync is pulled, unless
+//! `?full=true` is passed; highlights and notes deleted in Readwise are deleted here
+//! either way.
+
+use std::sync::Mutex;
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use axum::{
+    Json,
+    extract::{Query, State},
+    http::HeaderMap,
+    response::Response,
+};
+use libsql::Connection;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::commonplace::boundary::Boundary;
+use crate::commonplace::{Commonplace, ResourceType, permalink};
+use crate::db::Database;
+use crate::handler::AppState;
+use crate::public;
+use crate::response::{bad_request, internal_error, success};
+use crate::sync::engine::{self, SyncError, SyncResponse};
+use crate::sync::{
+    Deleted, ResourceKey, SourceAnnotation, SourceComment, SourceResource, SyncSource, is_redacted, log_find_error,
+    redact_secret,
+};
+
+const API_URL: &str = "https://readwise.io/api/v2";
+const REQUEST_TIMEOUT_SECS: u64 = 30;
+/// Rate limited requests are retried this many times, waiting as long as Readwise asks
+const MAX_RETRIES: u32 = 3;
+const DEFAULT_RETRY_AFTER_SECS: u64 = 60;
+/// Highlights sent per create request
+const PUSH_BATCH_SIZE: usize = 100;
+
+#[derive(Debug, Deserialize)]
+pub struct SetConfigRequest {
+    pub token: String,
+}
+
+/// What a sync config archive keeps of the Readwise settings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReadwiseSourceConfig {
+    /// Redacted on export; importing a redacted token keeps the one already set
+    pub token: String,
+    pub last_sync_at: Option<String>,
+}
+
+/// The token itself is never sent back
+#[derive(Debug, Serialize)]
+pub struct ConfigResponse {
+    pub configured: bool,
+    pub last_sync_at: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Default)]
+pub struct SyncParams {
+    /// Pull everything rather than only what changed since the last sync
+    #[serde(default)]
+    pub full: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct ExportPage {
+    #[serde(default, rename = "nextPageCursor")]
+    next_page_cursor: Option<Value>,
+    #[serde(default)]
+    results: Vec<ReadwiseBook>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReadwiseBook {
+    pub user_book_id: i64,
+    #[serde(default)]
+    pub title: String,
+    #[serde(default)]
+    pub category: Option<String>,
+    #[serde(default)]
+    pub source_url: Option<String>,
+    #[serde(default)]
+    pub highlights: Vec<ReadwiseHighlight>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReadwiseHighlight {
+    pub id: i64,
+    #[serde(default)]
+    pub text: String,
+    #[serde(default)]
+    pub note: Option<String>,
+    #[serde(default)]
+    pub color: Option<String>,
+    #[serde(default)]
+    pub location: Option<i64>,
+    #[serde(default)]
+    pub location_type: Option<String>,
+    #[serde(default)]
+    pub is_deleted: bool,
+    /// Link the highlight was created with; highlights pushed from here carry their
+    /// annotation's permalink
+    #[serde(default)]
+    pub url: Option<String>,
+}
+
+impl ReadwiseBook {
+    fn resource(&self) -> (String, ResourceType) {
+        match self.source_url.as_deref().map(str::trim) {
+            Some(url) if self.category.as_deref() == Some("articles") && !url.is_empty() => {
+                (url.to_string(), ResourceType::Website)
+            }
+            _ => (self.title.clone(), ResourceType::Pdf),
+        }
+    }
+
+    /// Deleted highlights and emptied notes go to `deleted` rather than the resource
+    fn into_resource(self, deleted: &mut Vec<Deleted>) -> SourceResource {
+        let (title, resource_type) = self.resource();
+        let mut annotations = Vec::new();
+        for highlight in self.highlights {
+            let external_id = format!("readwise:{}", highlight.id);
+            let note_id = format!("readwise:note:{}", highlight.id);
+            if highlight.is_deleted {
+                deleted.push(Deleted::Annotation(external_id));
+                continue;
+            }
+            let note = highlight.note.as_deref().map(str::trim).unwrap_or_default();
+            let comments = if note.is_empty() {
+                deleted.push(Deleted::Comment(note_id));
+                Vec::new()
+            } else {
+                vec![SourceComment {
+                    external_id: note_id,
+                    content: note.to_string(),
+                }]
+            };
+            let boundary =
+                Boundary::from_readwise(&highlight.text, highlight.location, highlight.location_type.as_deref());
+            annotations.push(SourceAnnotation {
+                external_id,
+                text: highlight.text,
+                color: highlight.color,
+                boundary: Some(boundary.into_value()),
+                comments,
+            });
+        }
+        SourceResource {
+            key: ResourceKey::External(format!("readwise:book:{}", self.user_book_id)),
+            title,
+            resource_type,
+            annotations,
+            notes: Vec::new(),
+            archived: None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct PushResponse {
+    pub pushed: usize,
+    /// Left for the next push when Readwise failed part way
+    pub remaining: usize,
+}
+
+/// An annotation not yet pushed to Readwise
+#[derive(Debug)]
+pub struct PendingHighlight {
+    pub annotation_id: i32,
+    pub text: String,
+    pub note: Option<String>,
+    pub page: Option<i64>,
+    pub created_at: String,
+    pub public_id: String,
+    pub resource_title: String,
+    pub resource_type: ResourceType,
+}
+
+/// A highlight as the Readwise create API takes it
+#[derive(Debug, Serialize)]
+pub struct NewHighlight {
+    pub text: String,
+    pub title: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source_url: Option<String>,
+    pub source_type: &'static str,
+    pub category: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub location: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub location_type: Option<&'static str>,
+    pub highlighted_at: String,
+    pub highlight_url: String,
+}
+
+impl PendingHighlight {
+    /// `base_url` makes the permalink absolute, which is how a pushed highlight is
+    /// recognized when it comes back in an export
+    fn highlight(&self, base_url: &str) -> NewHighlight {
+        let website = self.resource_type == ResourceType::Website;
+        NewHighlight {
+            text: self.text.clone(),
+            title: self.resource_title.clone(),
+            source_url: website.then(|| self.resource_title.clone()),
+            source_type: "bibliotek",
+            category: if website { "articles" } else { "books" },
+            note: self.note.clone(),
+            location: self.page,
+            location_type: self.page.map(|_| "page"),
+            highlighted_at: self.created_at.clone(),
+            highlight_url: format!("{}{}", base_url, permalink(&self.public_id)),
+        }
+    }
+}
+
+struct ReadwiseClient {
+    client: reqwest::Client,
+    token: String,
+}
+
+impl ReadwiseClient {
+    fn new(token: &str) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(REQUEST_TIMEOUT_SECS))
+            .user_agent(concat!("bibliotek/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .unwrap_or_default();
+        Self {
+            client,
+            token: token.to_string(),
+        }
+    }
+
+    /// Sends the request `build` makes, again while Readwise rate limits it
+    async fn send(&self, build: impl Fn() -> reqwest::RequestBuilder) -> Result<reqwest::Response> {
+        let mut retries = 0;
+        loop {
+            let resp = build()
+                .header(reqwest::header::AUTHORIZATION, format!("Token {}", self.token))
+                .send()
+                .await?;
+            if resp.status() != reqwest::StatusCode::TOO_MANY_REQUESTS || retries == MAX_RETRIES {
+                return Ok(resp);
+            }
+            let wait = resp
+                .headers()
+                .get(reqwest::header::RETRY_AFTER)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(DEFAULT_RETRY_AFTER_SECS);
+            tracing::info!("Readwise rate limit hit, retrying in {}s", wait);
+            tokio::time::sleep(Duration::from_secs(wait)).await;
+            retries += 1;
+        }
+    }
+
+    async fn get(&self, path: &str, query: &[(&str, String)]) -> Result<reqwest::Response> {
+        self.send(|| self.client.get(format!("{}{}", API_URL, path)).query(query))
+            .await
+    }
+
+    /// Whether Readwise accepts the token
+    async fn check_token(&self) -> Result<bool> {
+        let resp = self.get("/auth/", &[]).await?;
+        match resp.status() {
+            status if status.is_success() => Ok(true),
+            reqwest::StatusCode::UNAUTHORIZED | reqwest::StatusCode::FORBIDDEN => Ok(false),
+            status => anyhow::bail!("Readwise answered {}", status),
+        }
+    }
+
+    /// Every book with highlights changed after `updated_after`, following the
+    /// export's page cursor
+    async fn export(&self, updated_after: Option<&str>) -> Result<Vec<ReadwiseBook>> {
+        let mut books = Vec::new();
+        let mut cursor: Option<String> = None;
+        loop {
+            let mut query = Vec::new();
+            if let Some(updated_after) = updated_after {
+                query.push(("updatedAfter", updated_after.to_string()));
+            }
+            if let Some(cursor) = &cursor {
+                query.push(("pageCursor", cursor.clone()));
+            }
+            let page: ExportPage = self.get("/export/", &query).await?.error_for_status()?.json().await?;
+            books.extend(page.results);
+            cursor = match page.next_page_cursor {
+                Some(Value::String(next)) => Some(next),
+                Some(Value::Number(next)) => Some(next.to_string()),
+                _ => None,
+            };
+            if cursor.is_none() {
+                return Ok(books);
+            }
+        }
+    }
+
+    /// Adds highlights to Readwise, which files them under books by title
+    async fn create_highlights(&self, highlights: &[NewHighlight]) -> Result<()> {
+        let body = serde_json::json!({ "highlights": highlights });
+        self.send(|| self.client.post(format!("{}/highlights/", API_URL)).json(&body))
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+/// The stored token and where the last sync left off
+async fn load_config(conn: &Connection) -> Result<Option<(String, Option<String>)>> {
+    let mut rows = conn
+        .query("SELECT token, last_sync_at FROM readwise_config WHERE id = 1", ())
+        .await?;
+    match rows.next().await? {
+        Some(row) => Ok(Some((row.get(0)?, row.get(1)?))),
+        None => Ok(None),
+    }
+}
+
+/// The settings with the token redacted, for the sync config archive
+pub async fn export_config(conn: &Connection) -> Result<Option<ReadwiseSourceConfig>> {
+    Ok(load_config(conn)
+        .await?
+        .map(|(token, last_sync_at)| ReadwiseSourceConfig {
+            token: redact_secret(&token),
+            last_sync_at,
+        }))
+}
+
+/// Returns false if nothing was imported: the archive's token is redacted and there
+/// is none here to keep. The token isn't checked with Readwise, unlike `set_config`.
+pub async fn import_config(conn: &Connection, config: &ReadwiseSourceConfig) -> Result<bool> {
+    if is_redacted(&config.token) {
+        let query = r#"
+            UPDATE readwise_config
+            SET last_sync_at = ?, updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
+            WHERE id = 1
+        "#;
+        let updated = conn
+            .execute(query, libsql::params![config.last_sync_at.clone()])
+            .await?;
+        return Ok(updated > 0);
+    }
+
+    let query = r#"
+        INSERT INTO readwise_config (id, token, last_sync_at)
+        VALUES (1, ?, ?)
+        ON CONFLICT(id) DO UPDATE SET
+            token = excluded.token,
+            last_sync_at = excluded.last_sync_at,
+            updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
+    "#;
+    conn.execute(query, libsql::params![config.token.clone(), config.last_sync_at.clone()])
+        .await?;
+    Ok(true)
+}
+
+/// Live annotations without a push marker, leaving out those that came from Readwise.
+/// Their comments are joined into the note.
+pub async fn pending_highlights(conn: &Connection) -> Result<Vec<PendingHighlight>> {
+    let query = r#"
+        SELECT a.id, a.text,
+               (SELECT group_concat(c.content, char(10) || char(10))
+                FROM (SELECT content FROM comments
+                      WHERE annotation_id = a.id AND deleted_at IS NULL
+                      ORDER BY created_at, id) c),
+               json_extract(a.boundary, '$.page'), a.created_at, a.public_id, r.title, r.type
+        FROM annotations a
+        JOIN resources r ON r.id = a.resource_id
+        LEFT JOIN readwise_pushed p ON p.annotation_id = a.id
+        WHERE p.annotation_id IS NULL
+          AND a.deleted_at IS NULL
+          AND r.deleted_at IS NULL
+          AND (a.external_id IS NULL OR a.external_id NOT LIKE 'readwise:%')
+        ORDER BY a.id
+    "#;
+    let mut rows = conn.query(query, ()).await?;
+    let mut pending = Vec::new();
+    while let Some(row) = rows.next().await? {
+        let resource_type: String = row.get(7)?;
+        let Some(resource_type) = ResourceType::from_str(&resource_type) else {
+            continue;
+        };
+        pending.push(PendingHighlight {
+            annotation_id: row.get(0)?,
+            text: row.get(1)?,
+            note: row.get(2)?,
+            page: row.get(3).ok(),
+            created_at: row.get(4)?,
+            public_id: row.get(5)?,
+            resource_title: row.get(6)?,
+            resource_type,
+        });
+    }
+    Ok(pending)
+}
+
+async fn mark_pushed(conn: &Connection, annotation_id: i32, highlight_url: &str) -> Result<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO readwise_pushed (annotation_id, highlight_url) VALUES (?, ?)",
+        libsql::params![annotation_id, highlight_url],
+    )
+    .await?;
+    Ok(())
+}
+
+pub async fn get_config(State(state): State<AppState>) -> Response {
+    match load_config(&state.db.connection()).await {
+        Ok(config) => success(ConfigResponse {
+            configured: config.is_some(),
+            last_sync_at: config.and_then(|(_, last_sync_at)| last_sync_at),
+        }),
+        Err(e) => {
+            tracing::error!("Failed to get Readwise config: {}", e);
+            internal_error("Failed to get config")
+        }
+    }
+}
+
+pub async fn set_config(State(state): State<AppState>, Json(payload): Json<SetConfigRequest>) -> Response {
+    let token = payload.token.trim();
+    if token.is_empty() {
+        return bad_request("token is required");
+    }
+    match ReadwiseClient::new(token).check_token().await {
+        Ok(true) => {}
+        Ok(false) => return bad_request("Readwise did not accept the token"),
+        Err(e) => {
+            tracing::error!("Failed to check Readwise token: {}", e);
+            return internal_error("Failed to reach Readwise");
+        }
+    }
+
+    // A new token may belong to another account, so the next sync starts over
+    let query = r#"
+        INSERT INTO readwise_config (id, token)
+        VALUES (1, ?)
+        ON CONFLICT(id) DO UPDATE SET
+            token = excluded.token,
+            last_sync_at = NULL,
+            updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
+    "#;
+    match state.db.connection().execute(query, libsql::params![token]).await {
+        Ok(_) => success(ConfigResponse {
+            configured: true,
+            last_sync_at: None,
+        }),
+        Err(e) => {
+            tracing::error!("Failed to set Readwise config: {}", e);
+            internal_error("Failed to save configuration")
+        }
+    }
+}
+
+pub async fn sync(State(state): State<AppState>, Query(params): Query<SyncParams>) -> Response {
+    let conn = &state.db.connection();
+    let (token, last_sync_at) = match load_config(conn).await {
+        Ok(Some(config)) => config,
+        Ok(None) => return bad_request("Readwise token not configured. Please set it first."),
+        Err(e) => {
+            tracing::error!("Failed to get Readwise config: {}", e);
+            return internal_error("Failed to get config");
+        }
+    };
+
+    // Taken before the export, so changes made while it runs are pulled next time
+    let started_at = chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true);
+    let updated_after = last_sync_at.filter(|_| !params.full);
+    let books = match ReadwiseClient::new(&token).export(updated_after.as_deref()).await {
+        Ok(books) => books,
+        Err(e) => {
+            tracing::error!("Failed to export from Readwise: {}", e);
+            return internal_error("Failed to fetch highlights from Readwise");
+        }
+    };
+
+    let source = ReadwiseExport {
+        db: &state.db,
+        books: Mutex::new(books),
+        deleted: Mutex::new(Vec::new()),
+        full: params.full,
+    };
+    let report = match engine::run(&state, &source).await {
+        Ok(report) => report,
+        Err(SyncError::Fetch(e)) => {
+            tracing::error!("Failed to read Readwise export: {}", e);
+            return internal_error("Failed to fetch highlights from Readwise");
+        }
+        Err(SyncError::StartRun(e)) => {
+            tracing::error!("Failed to start readwise sync run: {}", e);
+            return internal_error("Failed to start sync run");
+        }
+    };
+
+    let query = r#"
+        UPDATE readwise_config
+        SET last_sync_at = ?,
+            updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
+        WHERE id = 1
+    "#;
+    let recorded = state
+        .db
+        .write(|| async { Ok(conn.execute(query, libsql::params![started_at.clone()]).await?) })
+        .await;
+    if let Err(e) = recorded {
+        tracing::error!("Failed to record Readwise sync: {}", e);
+    }
+
+    success(SyncResponse::from(&report))
+}
+
+/// Sends annotations made here to Readwise, each once, so they can be reviewed there
+pub async fn push(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    let conn = &state.db.connection();
+    let token = match load_config(conn).await {
+        Ok(Some((token, _))) => token,
+        Ok(None) => return bad_request("Readwise token not configured. Please set it first."),
+        Err(e) => {
+            tracing::error!("Failed to get Readwise config: {}", e);
+            return internal_error("Failed to get config");
+        }
+    };
+    let pending = match pending_highlights(conn).await {
+        Ok(pending) => pending,
+        Err(e) => {
+            tracing::error!("Failed to list annotations to push to Readwise: {}", e);
+            return internal_error("Failed to list annotations");
+        }
+    };
+
+    let base_url = public::base_url(&state.public, &headers);
+    let client = ReadwiseClient::new(&token);
+    let mut pushed = 0;
+    for batch in pending.chunks(PUSH_BATCH_SIZE) {
+        let highlights: Vec<NewHighlight> = batch.iter().map(|h| h.highlight(&base_url)).collect();
+        if let Err(e) = client.create_highlights(&highlights).await {
+            tracing::error!("Failed to push highlights to Readwise: {}", e);
+            if pushed == 0 {
+                return internal_error("Failed to push highlights to Readwise");
+            }
+            break;
+        }
+        for (pending, highlight) in batch.iter().zip(&highlights) {
+            if let Err(e) = mark_pushed(conn, pending.annotation_id, &highlight.highlight_url).await {
+                tracing::error!("Failed to mark annotation {} pushed: {}", pending.annotation_id, e);
+            }
+        }
+        pushed += batch.len();
+    }
+
+    success(PushResponse {
+        pushed,
+        remaining: pending.len() - pushed,
+    })
+}
+
+/// A Readwise export, applied as a sync of the "readwise" source
+struct ReadwiseExport<'a> {
+    db: &'a Database,
+    books: Mutex<Vec<ReadwiseBook>>,
+    /// What the export marked deleted, gathered by `fetch`
+    deleted: Mutex<Vec<Deleted>>,
+    /// Everything was exported, so what is missing was deleted
+    full: bool,
+}
+
+#[async_trait]
+impl SyncSource for ReadwiseExport<'_> {
+    fn name(&self) -> String {
+        "readwise".to_string()
+    }
+
+    fn additive(&self) -> bool {
+        !self.full
+    }
+
+    fn deleted(&self) -> Vec<Deleted> {
+        std::mem::take(&mut *self.deleted.lock().unwrap())
+    }
+
+    async fn fetch(&self) -> Result<Vec<SourceResource>> {
+        let lib = self.db.commonplace();
+        let mut books = std::mem::take(&mut *self.books.lock().unwrap());
+        for book in &mut books {
+            let mut highlights = Vec::with_capacity(book.highlights.len());
+            for highlight in std::mem::take(&mut book.highlights) {
+                if !is_own_highlight(&lib, &highlight).await {
+                    highlights.push(highlight);
+                }
+            }
+            book.highlights = highlights;
+        }
+
+        let mut deleted = Vec::new();
+        let resources = books.into_iter().map(|book| book.into_resource(&mut deleted)).collect();
+        *self.deleted.lock().unwrap() = deleted;
+        Ok(resources)
+    }
+}
+
+/// Whether the highlight was pushed from here, its link being the permalink of an
+/// annotation that is already in commonplace
+async fn is_own_highlight(lib: &Commonplace<'_>, highlight: &ReadwiseHighlight) -> bool {
+    let Some(public_id) = highlight
+        .url
+        .as_deref()
+        .and_then(|url| url.rsplit_once("/a/"))
+        .map(|(_, id)| id)
+    else {
+        return false;
+    };
+    match lib.find_annotation_by_public_id(public_id).await {
+        Ok(found) => found.is_some(),
+        Err(e) => {
+            log_find_error("annotation", public_id, e);
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commonplace::{CreateAnnotation, CreateComment, CreateResource};
+    use crate::test_support::test_state;
+
+    fn export(text: &str, note: &str, is_deleted: bool) -> Vec<ReadwiseBook> {
+        let page: ExportPage = serde_json::from_value(serde_json::json!({
+            "count": 1,
+            "nextPageCursor": null,
+            "results": [{
+                "user_book_id": 12,
+                "title": "How to Take Smart Notes",
+                "author": "Sönke Ahrens",
+                "category": "books",
+                "source_url": null,
+                "highlights": [{
+                    "id": 345,
+                    "text": text,
+                    "note": note,
+                    "location": 1021,
+                    "location_type": "location",
+                    "color": "yellow",
+                    "is_deleted": is_deleted,
+                }],
+            }],
+        }))
+        .unwrap();
+        page.results
+    }
+
+    fn source(db: &Database, books: Vec<ReadwiseBook>) -> ReadwiseExport<'_> {
+        ReadwiseExport {
+            db,
+            books: Mutex::new(books),
+            deleted: Mutex::new(Vec::new()),
+            full: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_export_is_applied_idempotently() {
+        let state = test_state().await;
+        let lib = state.db.commonplace();
+        let sync = |text, note, is_deleted| source(&state.db, export(text, note, is_deleted));
+
+        let first = engine::run(&state, &sync("Writing is thinking.", "see Luhmann", false))
+            .await
+            .unwrap();
+        assert_eq!((first.resources.created, first.annotations.created, first.comments.created), (1, 1, 1));
+
+        let again = engine::run(&state, &sync("Writing is thinking.", "see Luhmann", false))
+            .await
+            .unwrap();
+        assert_eq!(again.totals().created + again.totals().updated, 0);
+        assert_eq!(again.annotations.unchanged, 1);
+
+        let edited = engine::run(&state, &sync("Writing is thinking!", "", false))
+            .await
+            .unwrap();
+        assert_eq!((edited.annotations.updated, edited.comments.deleted), (1, 1));
+        let annotation = lib
+            .find_annotation_by_external_id("readwise:345")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(annotation.text, "Writing is thinking!");
+        assert_eq!(annotation.boundary.unwrap()["extra"]["location"], 1021);
+
+        let deleted = engine::run(&state, &sync("Writing is thinking!", "", true))
+            .await
+            .unwrap();
+        assert_eq!(deleted.annotations.deleted, 1);
+        assert!(
+            lib.find_annotation_by_external_id("readwise:345")
+                .await
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_push_sends_each_annotation_once() {
+        let state = test_state().await;
+        let db = &state.db;
+        let lib = db.commonplace();
+        engine::run(&state, &source(db, export("Writing is thinking.", "", false)))
+            .await
+            .unwrap();
+        let resource = lib
+            .create_resource(CreateResource {
+                title: "https://example.com/essay".to_string(),
+                resource_type: ResourceType::Website,
+                external_id: None,
+                content_hash: None,
+            })
+            .await
+            .unwrap();
+        let annotation = lib
+            .create_annotation(CreateAnnotation {
+                resource_id: resource.id,
+                text: "Read slowly".to_string(),
+                color: None,
+                boundary: None,
+                external_id: None,
+                content_hash: None,
+            })
+            .await
+            .unwrap();
+        lib.create_comment(CreateComment {
+            annotation_id: annotation.id,
+            content: "and twice".to_string(),
+            external_id: None,
+            content_hash: None,
+            parent_comment_id: None,
+        })
+        .await
+        .unwrap();
+
+        // The highlight that came from Readwise is not sent back
+        let pending = pending_highlights(&db.connection()).await.unwrap();
+        assert_eq!(pending.len(), 1);
+        let highlight = pending[0].highlight("https://books.example.com");
+        assert_eq!(highlight.note.as_deref(), Some("and twice"));
+        assert_eq!(highlight.source_url.as_deref(), Some("https://example.com/essay"));
+        assert_eq!(highlight.highlight_url, format!("https://books.example.com{}", annotation.permalink()));
+
+        mark_pushed(&db.connection(), annotation.id, &highlight.highlight_url)
+            .await
+            .unwrap();
+        assert!(pending_highlights(&db.connection()).await.unwrap().is_empty());
+
+        // and when it comes back in an export, it is not made into a second annotation
+        let page: ExportPage = serde_json::from_value(serde_json::json!({
+            "results": [{
+                "user_book_id": 13,
+                "title": "https://example.com/essay",
+                "category": "articles",
+                "source_url": "https://example.com/essay",
+                "highlights": [{ "id": 346, "text": "Read slowly", "url": highlight.highlight_url }],
+            }],
+        }))
+        .unwrap();
+        let pulled = engine::run(&state, &source(db, page.results)).await.unwrap();
+        assert_eq!(pulled.annotations.created, 0);
+    }
+}
@@ -0,0 +1,10 @@
+-- Readwise Module Configuration
+-- Stores the access token for the Readwise export API and where the last sync left off
+
+CREATE TABLE IF NOT EXISTS readwise_config (
+    id INTEGER PRIMARY KEY CHECK (id = 1), -- Only one config row allowed
+    token TEXT NOT NULL,
+    last_sync_at TEXT, -- passed as updatedAfter, so only changes since then are pulled
+    created_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    updated_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))
+);
@@ -0,0 +1,7 @@
+-- Annotations already sent to Readwise by POST /readwise/push, so each is sent once
+
+CREATE TABLE IF NOT EXISTS readwise_pushed (
+    annotation_id INTEGER PRIMARY KEY REFERENCES annotations (id) ON DELETE CASCADE,
+    highlight_url TEXT NOT NULL, -- the permalink sent along, which the highlight comes back with
+    pushed_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))
+);
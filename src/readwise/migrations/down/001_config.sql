@@ -0,0 +1 @@
+DROP TABLE IF EXISTS readwise_config;
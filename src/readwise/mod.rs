@@ -0,0 +1,20 @@
+mod handler;
+mod reader;
+mod routes;
+
+pub use handler::{ReadwiseSourceConfig, export_config, import_config};
+pub use routes::routes;
+
+pub fn migrations() -> &'static [(&'static str, &'static str)] {
+    &[
+        ("readwise_001_config.sql", include_str!("migrations/001_config.sql")),
+        ("readwise_002_pushed.sql", include_str!("migrations/002_pushed.sql")),
+    ]
+}
+
+pub fn down_migrations() -> &'static [(&'static str, &'static str)] {
+    &[
+        ("readwise_001_config.sql", include_str!("migrations/down/001_config.sql")),
+        ("readwise_002_pushed.sql", include_str!("migrations/down/002_pushed.sql")),
+    ]
+}
@@ -0,0 +1,349 @@
+//! Imports a Readwise Reader export (`POST /readwise/import`), so a library can move
+//! from Reader in one request. Three exports are read:
+//!
+//! - JSON documents as Reader's API lists them, a bare array or a page with
+//!   `results`. Highlights are the documents of category `highlight`, hung off their
+//!   document by `parent_id`, and `note` documents are comments on a highlight.
+//! - The highlights CSV (`Highlight`, `Book Title`, `Note`, `Location`, ...).
+//! - The library CSV (`Title`, `URL`, `ID`, ...), documents without highlights.
+//!
+//! Documents with a web URL become website resources named by it, shared with Light
+//! and the other sources; PDFs, EPUBs and anything without a URL become PDF resources
+//! named by their title. The import runs as a sync of the "reader" source, so
+//! importing a newer export updates in place, but nothing missing from a file is
+//! deleted: a library CSV does not hold the highlights a highlights CSV brought.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use axum::{body::Bytes, extract::State, response::Response};
+use serde::Deserialize;
+
+use crate::commonplace::boundary::Boundary;
+use crate::commonplace::capture::is_capturable;
+use crate::commonplace::{ResourceType, compute_resource_hash};
+use crate::handler::AppState;
+use crate::response::{bad_request, internal_error, success};
+use crate::sync::engine::{self, SyncError, SyncResponse};
+use crate::sync::{ResourceKey, SourceAnnotation, SourceComment, SourceResource, SyncSource};
+
+/// Largest export accepted by the import endpoint
+pub const MAX_EXPORT_BYTES: usize = 64 * 1024 * 1024;
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum ReaderExport {
+    Documents(Vec<ReaderDocument>),
+    Page { results: Vec<ReaderDocument> },
+}
+
+#[derive(Debug, Deserialize)]
+struct ReaderDocument {
+    id: String,
+    #[serde(default)]
+    title: Option<String>,
+    #[serde(default)]
+    source_url: Option<String>,
+    #[serde(default)]
+    category: Option<String>,
+    #[serde(default)]
+    parent_id: Option<String>,
+    /// The highlighted text of a highlight, the text of a note
+    #[serde(default)]
+    content: Option<String>,
+    /// Written on a highlight
+    #[serde(default)]
+    notes: Option<String>,
+}
+
+/// How a document is stored, from its Reader category: web pages by URL, files and
+/// pages without a URL by title
+fn document_resource(
+    external_id: String,
+    title: Option<&str>,
+    url: Option<&str>,
+    category: Option<&str>,
+) -> SourceResource {
+    let url = url.map(str::trim).filter(|url| is_capturable(url));
+    let title = title.map(str::trim).filter(|title| !title.is_empty());
+    let (key, title, resource_type) = match (category, url) {
+        (Some("pdf" | "epub"), _) | (_, None) => (
+            ResourceKey::External(external_id),
+            title.or(url).unwrap_or("Untitled").to_string(),
+            ResourceType::Pdf,
+        ),
+        (_, Some(url)) => (ResourceKey::Website(url.to_string()), url.to_string(), ResourceType::Website),
+    };
+    SourceResource {
+        key,
+        title,
+        resource_type,
+        annotations: Vec::new(),
+        notes: Vec::new(),
+        archived: None,
+    }
+}
+
+fn non_empty(text: Option<&str>) -> Option<String> {
+    text.map(str::trim).filter(|text| !text.is_empty()).map(str::to_string)
+}
+
+/// Short stable id for rows that have none
+fn row_id(parts: &[&str]) -> String {
+    compute_resource_hash(&parts.join("\n"))[..16].to_string()
+}
+
+fn from_json(documents: Vec<ReaderDocument>) -> Vec<SourceResource> {
+    let (children, documents): (Vec<_>, Vec<_>) = documents
+        .into_iter()
+        .partition(|doc| matches!(doc.category.as_deref(), Some("highlight" | "note")));
+    let (highlights, notes): (Vec<_>, Vec<_>) = children
+        .into_iter()
+        .partition(|doc| doc.category.as_deref() == Some("highlight"));
+
+    let mut comments: HashMap<&str, Vec<SourceComment>> = HashMap::new();
+    for note in &notes {
+        if let (Some(parent), Some(content)) = (note.parent_id.as_deref(), non_empty(note.content.as_deref())) {
+            comments.entry(parent).or_default().push(SourceComment {
+                external_id: format!("reader:{}", note.id),
+                content,
+            });
+        }
+    }
+
+    let mut annotations: HashMap<&str, Vec<SourceAnnotation>> = HashMap::new();
+    for highlight in &highlights {
+        let (Some(parent), Some(text)) = (highlight.parent_id.as_deref(), non_empty(highlight.content.as_deref()))
+        else {
+            continue;
+        };
+        let mut highlight_comments = comments.remove(highlight.id.as_str()).unwrap_or_default();
+        if let Some(note) = non_empty(highlight.notes.as_deref()) {
+            highlight_comments.insert(
+                0,
+                SourceComment {
+                    external_id: format!("reader:comment:{}", highlight.id),
+                    content: note,
+                },
+            );
+        }
+        annotations.entry(parent).or_default().push(SourceAnnotation {
+            external_id: format!("reader:{}", highlight.id),
+            boundary: Some(Boundary::from_readwise(&text, None, None).into_value()),
+            text,
+            color: None,
+            comments: highlight_comments,
+        });
+    }
+
+    documents
+        .iter()
+        .map(|doc| {
+            let mut resource = document_resource(
+                format!("reader:{}", doc.id),
+                doc.title.as_deref(),
+                doc.source_url.as_deref(),
+                doc.category.as_deref(),
+            );
+            resource.annotations = annotations.remove(doc.id.as_str()).unwrap_or_default();
+            resource
+        })
+        .collect()
+}
+
+fn field(row: &csv::StringRecord, index: Option<usize>) -> Option<&str> {
+    index.and_then(|i| row.get(i)).map(str::trim)
+}
+
+fn from_csv(body: &[u8]) -> Result<Vec<SourceResource>, String> {
+    let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(body);
+    let headers = reader.headers().map_err(|e| format!("Invalid CSV: {}", e))?.clone();
+    let column = |name: &str| {
+        headers
+            .iter()
+            .position(|header| header.trim().eq_ignore_ascii_case(name))
+    };
+
+    let mut rows = Vec::new();
+    for row in reader.records() {
+        rows.push(row.map_err(|e| format!("Invalid CSV: {}", e))?);
+    }
+
+    if let Some(highlight) = column("Highlight") {
+        let (title, url, note, color) = (column("Book Title"), column("URL"), column("Note"), column("Color"));
+        let (location, location_type) = (column("Location"), column("Location Type"));
+        // Rows of a document are not necessarily next to each other
+        let mut resources: Vec<SourceResource> = Vec::new();
+        let mut by_title: HashMap<String, usize> = HashMap::new();
+        for row in &rows {
+            let Some(text) = non_empty(field(row, Some(highlight))) else {
+                continue;
+            };
+            let book = field(row, title).unwrap_or_default();
+            let index = *by_title.entry(book.to_string()).or_insert_with(|| {
+                let external_id = format!("reader:book:{}", row_id(&[book]));
+                // Exports without a URL column title articles by their URL
+                let url = field(row, url).filter(|url| !url.is_empty()).unwrap_or(book);
+                resources.push(document_resource(external_id, Some(book), Some(url), None));
+                resources.len() - 1
+            });
+
+            let id = row_id(&[book, &text]);
+            let location_value = field(row, location).and_then(|location| location.parse().ok());
+            let comments = non_empty(field(row, note))
+                .map(|content| SourceComment {
+                    external_id: format!("reader:comment:{}", id),
+                    content,
+                })
+                .into_iter()
+                .collect();
+            resources[index].annotations.push(SourceAnnotation {
+                external_id: format!("reader:highlight:{}", id),
+                boundary: Some(Boundary::from_readwise(&text, location_value, field(row, location_type)).into_value()),
+                text,
+                color: non_empty(field(row, color)),
+                comments,
+            });
+        }
+        return Ok(resources);
+    }
+
+    let (Some(title), Some(url)) = (column("Title"), column("URL")) else {
+        return Err("Not a Reader export: expected a Highlight column, or Title and URL".to_string());
+    };
+    let id = column("ID");
+    Ok(rows
+        .iter()
+        .map(|row| {
+            let (title, url) = (field(row, Some(title)), field(row, Some(url)));
+            let id = non_empty(field(row, id))
+                .unwrap_or_else(|| row_id(&[title.unwrap_or_default(), url.unwrap_or_default()]));
+            document_resource(format!("reader:{}", id), title, url, None)
+        })
+        .collect())
+}
+
+/// Reads an export, JSON or CSV, into the resources it holds
+fn parse(body: &[u8]) -> Result<Vec<SourceResource>, String> {
+    let text = String::from_utf8_lossy(body);
+    let text = text.trim_start_matches('\u{feff}').trim_start();
+    if text.starts_with('[') || text.starts_with('{') {
+        let export: ReaderExport = serde_json::from_str(text).map_err(|e| format!("Invalid export: {}", e))?;
+        let documents = match export {
+            ReaderExport::Documents(documents) | ReaderExport::Page { results: documents } => documents,
+        };
+        Ok(from_json(documents))
+    } else {
+        from_csv(text.as_bytes())
+    }
+}
+
+/// The parsed export, handed to the engine once
+struct ReaderImport {
+    resources: Mutex<Vec<SourceResource>>,
+}
+
+#[async_trait]
+impl SyncSource for ReaderImport {
+    fn name(&self) -> String {
+        "reader".to_string()
+    }
+
+    fn additive(&self) -> bool {
+        true
+    }
+
+    async fn fetch(&self) -> anyhow::Result<Vec<SourceResource>> {
+        Ok(std::mem::take(&mut *self.resources.lock().unwrap()))
+    }
+}
+
+pub async fn import(State(state): State<AppState>, body: Bytes) -> Response {
+    let resources = match parse(&body) {
+        Ok(resources) if resources.is_empty() => return bad_request("The export has no documents"),
+        Ok(resources) => resources,
+        Err(msg) => return bad_request(&msg),
+    };
+
+    let source = ReaderImport {
+        resources: Mutex::new(resources),
+    };
+    match engine::run(&state, &source).await {
+        Ok(report) => success(SyncResponse::from(&report)),
+        Err(SyncError::Fetch(e)) => {
+            tracing::error!("Failed to read Reader export: {}", e);
+            internal_error("Failed to read export")
+        }
+        Err(SyncError::StartRun(e)) => {
+            tracing::error!("Failed to start reader import run: {}", e);
+            internal_error("Failed to start sync run")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn summary(resources: &[SourceResource]) -> Vec<(String, &str, usize, usize)> {
+        resources
+            .iter()
+            .map(|r| {
+                let key = match &r.key {
+                    ResourceKey::External(id) | ResourceKey::Book { external_id: id, .. } => {
+                        id.split(':').take(2).collect::<Vec<_>>().join(":")
+                    }
+                    ResourceKey::Website(url) => url.clone(),
+                };
+                let comments = r.annotations.iter().map(|a| a.comments.len()).sum();
+                (key, r.resource_type.as_str(), r.annotations.len(), comments)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_categories_map_to_resource_types() {
+        let json = serde_json::json!({
+            "count": 5,
+            "results": [
+                { "id": "d1", "title": "An essay", "category": "article", "source_url": "https://example.com/essay" },
+                { "id": "d2", "title": "A paper", "category": "pdf", "source_url": "https://example.com/paper.pdf" },
+                { "id": "h1", "category": "highlight", "parent_id": "d1", "content": "Read slowly", "notes": "Yes" },
+                { "id": "n1", "category": "note", "parent_id": "h1", "content": "And again" },
+                { "id": "h2", "category": "highlight", "parent_id": "d2", "content": "A finding" },
+            ],
+        });
+        let resources = parse(json.to_string().as_bytes()).unwrap();
+        assert_eq!(
+            summary(&resources),
+            [
+                ("https://example.com/essay".to_string(), "website", 1, 2),
+                ("reader:d2".to_string(), "pdf", 1, 0),
+            ]
+        );
+        assert_eq!(resources[1].title, "A paper");
+
+        let csv = "Highlight,Book Title,Book Author,Note,Color,Location Type,Location\n\
+                   First,A Book,Someone,,yellow,page,12\n\
+                   Elsewhere,https://example.com/post,,Note,,,\n\
+                   Second,A Book,Someone,Mine,,page,14\n";
+        let resources = parse(csv.as_bytes()).unwrap();
+        assert_eq!(
+            summary(&resources),
+            [
+                ("reader:book".to_string(), "pdf", 2, 1),
+                ("https://example.com/post".to_string(), "website", 1, 1),
+            ]
+        );
+        assert_eq!(resources[0].annotations[0].color.as_deref(), Some("yellow"));
+
+        let library = "Title,URL,ID,Document tags,Saved date,Reading progress,Location,Seen\n\
+                       Essay,https://example.com/essay,01abc,,2024-01-01,0,later,false\n";
+        assert_eq!(
+            summary(&parse(library.as_bytes()).unwrap()),
+            [("https://example.com/essay".to_string(), "website", 0, 0)]
+        );
+        assert!(parse(b"name,value\na,b\n").is_err());
+    }
+}
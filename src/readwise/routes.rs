@@ -0,0 +1,17 @@
+use axum::{
+    Router,
+    extract::DefaultBodyLimit,
+    routing::{get, post},
+};
+
+use super::{handler, reader};
+use crate::handler::AppState;
+
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/config", get(handler::get_config))
+        .route("/config", post(handler::set_config))
+        .route("/sync", post(handler::sync))
+        .route("/push", post(handler::push))
+        .route("/import", post(reader::import).layer(DefaultBodyLimit::max(reader::MAX_EXPORT_BYTES)))
+}
@@ -1,80 +1,97 @@
-use axum::{Json, extract::State, response::Response};
+use async_trait::async_trait;
+use axum::{
+    Json,
+    extract::{Path as AxumPath, Query, State},
+    http::StatusCode,
+    response::{
+        IntoResponse, Response,
+        sse::{Event as SseEvent, KeepAlive, Sse},
+    },
+};
+use futures_util::stream;
 use libsql::{Builder, Connection};
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
 use std::path::Path;
+use tokio::sync::mpsc;
 
-use crate::commonplace::{
-    Commonplace, CreateAnnotation, CreateComment, CreateNote, CreateResource, ResourceType, UpdateAnnotation,
-    UpdateComment, UpdateNote, UpdateResource, compute_annotation_hash, compute_comment_hash, compute_note_hash,
-    compute_resource_hash,
-};
+use crate::commonplace::ResourceType;
+use crate::commonplace::boundary::Boundary;
 use crate::handler::AppState;
-use crate::response::{bad_request, internal_error, success};
+use crate::response::{bad_gateway, bad_request, internal_error, not_found, success};
+use crate::sync::engine::{self, Entities, ItemProgress, Progress, SyncError, SyncReport, SyncResponse};
 use crate::sync::{
-    SyncResult, SyncStats, delete_orphans, handle_create_result, handle_create_result_unit, handle_update_result,
-    handle_update_result_unit, is_unchanged, log_find_error,
+    ResourceKey, SourceAnnotation, SourceComment, SourceNote, SourceResource, SyncSource, is_redacted, redact_secret,
 };
 
+/// Name of the database configured before sources had names, and the one
+/// `/research/config` means when none is given
+pub const DEFAULT_SOURCE: &str = "default";
+
 #[derive(Debug, Deserialize)]
 pub struct SetConfigRequest {
+    /// Which Research database this is, `default` if not given
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Path to the Research app's database, or a `libsql://` URL for one on Turso
     pub db_path: String,
+    /// Token for a remote database
+    #[serde(default)]
+    pub auth_token: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
 pub struct ConfigResponse {
+    pub name: String,
     pub db_path: Option<String>,
+    /// Redacted, see `redact_secret`
+    pub auth_token: Option<String>,
     pub last_sync_at: Option<String>,
 }
 
-#[derive(Debug, Serialize, Default)]
-pub struct SyncResponse {
-    pub resources_created: i32,
-    pub resources_updated: i32,
-    pub resources_deleted: i32,
-    pub resources_unchanged: i32,
-    pub annotations_created: i32,
-    pub annotations_updated: i32,
-    pub annotations_deleted: i32,
-    pub annotations_unchanged: i32,
-    pub comments_created: i32,
-    pub comments_updated: i32,
-    pub comments_deleted: i32,
-    pub comments_unchanged: i32,
-    pub notes_created: i32,
-    pub notes_updated: i32,
-    pub notes_deleted: i32,
-    pub notes_unchanged: i32,
-}
-
-impl SyncResponse {
-    fn apply_resources(&mut self, stats: &SyncStats) {
-        self.resources_created = stats.created;
-        self.resources_updated = stats.updated;
-        self.resources_deleted = stats.deleted;
-        self.resources_unchanged = stats.unchanged;
-    }
+#[derive(Debug, Deserialize)]
+pub struct SourceParams {
+    /// A configured database's name; every one of them if not given
+    pub source: Option<String>,
+}
 
-    fn apply_annotations(&mut self, stats: &SyncStats) {
-        self.annotations_created = stats.created;
-        self.annotations_updated = stats.updated;
-        self.annotations_deleted = stats.deleted;
-        self.annotations_unchanged = stats.unchanged;
-    }
+#[derive(Debug, Default, Deserialize)]
+pub struct SyncRequest {
+    /// What to sync besides resources: "annotations" (with their comments) and
+    /// "notes". Everything if not given; `["resources"]` refreshes titles only.
+    #[serde(default)]
+    pub entities: Option<Vec<String>>,
+}
 
-    fn apply_comments(&mut self, stats: &SyncStats) {
-        self.comments_created = stats.created;
-        self.comments_updated = stats.updated;
-        self.comments_deleted = stats.deleted;
-        self.comments_unchanged = stats.unchanged;
-    }
+#[derive(Debug, Deserialize)]
+pub struct StreamParams {
+    pub source: Option<String>,
+    /// Comma separated, as `entities` of `POST /research/sync`
+    pub entities: Option<String>,
+}
 
-    fn apply_notes(&mut self, stats: &SyncStats) {
-        self.notes_created = stats.created;
-        self.notes_updated = stats.updated;
-        self.notes_deleted = stats.deleted;
-        self.notes_unchanged = stats.unchanged;
-    }
+/// How one database's sync went, as part of syncing several
+#[derive(Debug, Serialize)]
+pub struct SourceSync {
+    pub source: String,
+    #[serde(flatten)]
+    pub response: Option<SyncResponse>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// A sync event of one of the databases being streamed
+#[derive(Debug, Serialize)]
+struct SourceEvent<'a, T: Serialize> {
+    source: &'a str,
+    #[serde(flatten)]
+    data: T,
+}
+
+/// A configured database, with its token in clear
+struct ResearchDb {
+    name: String,
+    db_path: String,
+    auth_token: Option<String>,
 }
 
 #[derive(Debug)]
@@ -104,630 +121,490 @@ struct ResearchNote {
     content: String,
 }
 
-pub async fn get_config(State(state): State<AppState>) -> Response {
-    let query = r#"SELECT db_path, last_sync_at FROM research_config WHERE id = 1"#;
-    let conn = state.db.connection();
+impl From<ResearchSourceConfig> for ConfigResponse {
+    fn from(config: ResearchSourceConfig) -> Self {
+        Self {
+            name: config.name,
+            db_path: Some(config.db_path),
+            auth_token: config.auth_token,
+            last_sync_at: config.last_sync_at,
+        }
+    }
+}
 
-    match conn.query(query, ()).await {
-        Ok(mut rows) => match rows.next().await {
-            Ok(Some(row)) => success(ConfigResponse {
-                db_path: row.get(0).ok(),
-                last_sync_at: row.get(1).ok(),
-            }),
-            Ok(None) => success(ConfigResponse {
+/// Names go in external ids (`research:<name>:<id>`), so they are kept to lowercase
+/// letters, digits, `-` and `_`
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 64
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
+}
+
+/// The default database's config, or another's with `?source=`
+pub async fn get_config(State(state): State<AppState>, Query(params): Query<SourceParams>) -> Response {
+    let name = params.source.unwrap_or_else(|| DEFAULT_SOURCE.to_string());
+    match export_config(&state.db.connection()).await {
+        Ok(configs) => match configs.into_iter().find(|config| config.name == name) {
+            Some(config) => success(ConfigResponse::from(config)),
+            None => success(ConfigResponse {
+                name,
                 db_path: None,
+                auth_token: None,
                 last_sync_at: None,
             }),
-            Err(e) => {
-                tracing::error!("Failed to get config: {}", e);
-                internal_error("Failed to get config")
-            }
         },
         Err(e) => {
-            tracing::error!("Failed to query config: {}", e);
-            internal_error("Failed to query config")
+            tracing::error!("Failed to get config: {}", e);
+            internal_error("Failed to get config")
+        }
+    }
+}
+
+pub async fn list_sources(State(state): State<AppState>) -> Response {
+    match export_config(&state.db.connection()).await {
+        Ok(configs) => success(configs.into_iter().map(ConfigResponse::from).collect::<Vec<_>>()),
+        Err(e) => {
+            tracing::error!("Failed to list research sources: {}", e);
+            internal_error("Failed to list research sources")
         }
     }
 }
 
 pub async fn set_config(State(state): State<AppState>, Json(payload): Json<SetConfigRequest>) -> Response {
-    if !Path::new(&payload.db_path).exists() {
+    let name = payload
+        .name
+        .as_deref()
+        .map(str::trim)
+        .unwrap_or(DEFAULT_SOURCE)
+        .to_string();
+    if !is_valid_name(&name) {
+        return bad_request("name may only have lowercase letters, digits, - and _");
+    }
+    let db_path = payload.db_path.trim().to_string();
+    let auth_token = payload.auth_token.filter(|token| !token.is_empty());
+    if is_remote(&db_path) {
+        // A remote database can't be checked for on disk, so make sure it answers
+        if let Err(e) = probe(&db_path, auth_token.as_deref()).await {
+            tracing::warn!("Research database at {} is not reachable: {}", db_path, e);
+            return bad_gateway(&format!("Could not read the Research database at {}: {}", db_path, e));
+        }
+    } else if !Path::new(&db_path).exists() {
         return bad_request("Database file does not exist at the specified path");
     }
 
     let query = r#"
-        INSERT INTO research_config (id, db_path)
-        VALUES (1, ?)
-        ON CONFLICT(id) DO UPDATE SET 
+        INSERT INTO research_config (name, db_path, auth_token)
+        VALUES (?, ?, ?)
+        ON CONFLICT(name) DO UPDATE SET 
             db_path = excluded.db_path,
+            auth_token = excluded.auth_token,
             updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
+        RETURNING last_sync_at
     "#;
 
-    let conn = state.db.connection();
-    match conn.execute(query, libsql::params![payload.db_path.clone()]).await {
-        Ok(_) => success(ConfigResponse {
-            db_path: Some(payload.db_path),
-            last_sync_at: None,
-        }),
+    let conn = &state.db.connection();
+    let params = libsql::params![name.clone(), db_path.clone(), auth_token.clone()];
+    let last_sync_at = match conn.query(query, params).await {
+        Ok(mut rows) => match rows.next().await {
+            Ok(row) => row.and_then(|row| row.get(0).ok()),
+            Err(e) => {
+                tracing::error!("Failed to set config: {}", e);
+                return internal_error("Failed to save configuration");
+            }
+        },
         Err(e) => {
             tracing::error!("Failed to set config: {}", e);
-            internal_error("Failed to save configuration")
+            return internal_error("Failed to save configuration");
         }
-    }
-}
-
-pub async fn sync(State(state): State<AppState>) -> Response {
-    let conn = state.db.connection();
-
-    let db_path = match get_research_db_path(conn).await {
-        Ok(path) => path,
-        Err(response) => return response,
-    };
-
-    let research_conn = match open_research_db(&db_path).await {
-        Ok(conn) => conn,
-        Err(response) => return response,
     };
+    success(ConfigResponse {
+        name,
+        db_path: Some(db_path),
+        auth_token: auth_token.as_deref().map(redact_secret),
+        last_sync_at,
+    })
+}
 
-    let items = match fetch_research_items(&research_conn).await {
-        Ok(items) => items,
+/// Forgets a database. What it synced stays, and is no longer pruned by any sync.
+pub async fn delete_source(State(state): State<AppState>, AxumPath(name): AxumPath<String>) -> Response {
+    let conn = &state.db.connection();
+    match conn
+        .execute("DELETE FROM research_config WHERE name = ?", libsql::params![name.clone()])
+        .await
+    {
+        Ok(0) => not_found("Research source not found"),
+        Ok(_) => (StatusCode::NO_CONTENT, ()).into_response(),
         Err(e) => {
-            tracing::error!("Failed to fetch items: {}", e);
-            return internal_error("Failed to fetch items from Research database");
+            tracing::error!("Failed to delete research source {}: {}", name, e);
+            internal_error("Failed to delete research source")
         }
-    };
-
-    let lib = Commonplace::new(conn);
-    let stats = sync_all_entities(&lib, &research_conn, items).await;
-
-    let _ = conn
-        .execute(
-            r#"
-            UPDATE research_config 
-            SET last_sync_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now'),
-                updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
-            WHERE id = 1
-        "#,
-            (),
-        )
-        .await;
-
-    success(stats)
-}
-
-async fn get_research_db_path(conn: &libsql::Connection) -> Result<String, Response> {
-    let query = r#"SELECT db_path FROM research_config WHERE id = 1"#;
-    let not_configured = "Research database path not configured. Please set the path first.";
-
-    let mut rows = conn.query(query, ()).await.map_err(|e| {
-        tracing::error!("Failed to query config: {}", e);
-        internal_error("Failed to query config")
-    })?;
-
-    let row = rows.next().await.map_err(|e| {
-        tracing::error!("Failed to get config: {}", e);
-        internal_error("Failed to get config")
-    })?;
-
-    let path: String = row
-        .ok_or_else(|| bad_request(not_configured))?
-        .get(0)
-        .map_err(|_| bad_request(not_configured))?;
-
-    if !Path::new(&path).exists() {
-        return Err(bad_request("Research database file no longer exists at the configured path"));
     }
-
-    Ok(path)
 }
 
-async fn open_research_db(db_path: &str) -> Result<Connection, Response> {
-    let db = Builder::new_local(db_path)
-        .flags(libsql::OpenFlags::SQLITE_OPEN_READ_ONLY)
-        .build()
-        .await
-        .map_err(|e| {
-            tracing::error!("Failed to open Research database: {}", e);
-            internal_error("Failed to open Research database")
-        })?;
-
-    db.connect().map_err(|e| {
-        tracing::error!("Failed to connect to Research database: {}", e);
-        internal_error("Failed to connect to Research database")
-    })
+/// Portable representation of a research source used by the sync config archive
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResearchSourceConfig {
+    /// Archives from before named sources only had the default one
+    #[serde(default = "default_source_name")]
+    pub name: String,
+    pub db_path: String,
+    /// Redacted on export; importing a redacted token keeps the one already set
+    #[serde(default)]
+    pub auth_token: Option<String>,
+    pub last_sync_at: Option<String>,
 }
 
-#[derive(Default)]
-struct SeenIds {
-    resources: HashSet<String>,
-    annotations: HashSet<String>,
-    comments: HashSet<String>,
-    notes: HashSet<String>,
+fn default_source_name() -> String {
+    DEFAULT_SOURCE.to_string()
 }
 
-async fn sync_all_entities(
-    lib: &Commonplace<'_>,
-    research_conn: &Connection,
-    items: Vec<ResearchItem>,
-) -> SyncResponse {
-    let mut response = SyncResponse::default();
-    let mut seen = SeenIds::default();
-
-    let mut resource_stats = SyncStats::default();
-    let mut annotation_stats = SyncStats::default();
-    let mut comment_stats = SyncStats::default();
-    let mut note_stats = SyncStats::default();
-
-    for item in items {
-        let resource_id = match sync_resource(lib, &item, &mut resource_stats, &mut seen.resources).await {
-            Some(id) => id,
-            None => continue,
-        };
+/// Every configured database, by name, with tokens redacted
+pub async fn export_config(conn: &Connection) -> anyhow::Result<Vec<ResearchSourceConfig>> {
+    let query = r#"SELECT name, db_path, last_sync_at, auth_token FROM research_config ORDER BY name"#;
+    let mut rows = conn.query(query, ()).await?;
 
-        sync_item_annotations(
-            lib,
-            research_conn,
-            &item,
-            resource_id,
-            &mut annotation_stats,
-            &mut comment_stats,
-            &mut seen,
-        )
-        .await;
-        sync_item_notes(lib, research_conn, &item, resource_id, &mut note_stats, &mut seen.notes).await;
+    let mut configs = Vec::new();
+    while let Some(row) = rows.next().await? {
+        configs.push(ResearchSourceConfig {
+            name: row.get(0)?,
+            db_path: row.get(1)?,
+            auth_token: row.get::<Option<String>>(3)?.as_deref().map(redact_secret),
+            last_sync_at: row.get(2)?,
+        });
     }
-
-    soft_delete_orphans(lib, &seen, &mut resource_stats, &mut annotation_stats, &mut comment_stats, &mut note_stats)
-        .await;
-
-    response.apply_resources(&resource_stats);
-    response.apply_annotations(&annotation_stats);
-    response.apply_comments(&comment_stats);
-    response.apply_notes(&note_stats);
-
-    response
+    Ok(configs)
 }
 
-async fn sync_resource(
-    lib: &Commonplace<'_>,
-    item: &ResearchItem,
-    stats: &mut SyncStats,
-    seen: &mut HashSet<String>,
-) -> Option<i32> {
-    let external_id = format!("research:{}", item.id);
-    let content_hash = compute_resource_hash(&item.title);
-    seen.insert(external_id.clone());
-
-    upsert_resource(lib, &external_id, &item.title, &content_hash)
-        .await
-        .record(stats)
-}
+/// Unlike `set_config`, this does not require the path to exist: an archive is usually
+/// imported on a different machine before the Research database has been copied over.
+/// A redacted auth token keeps the one set here; returns false if there is none to
+/// keep, though the rest of the config is imported all the same.
+pub async fn import_config(conn: &Connection, config: &ResearchSourceConfig) -> anyhow::Result<bool> {
+    let query = r#"
+        INSERT INTO research_config (name, db_path, last_sync_at, auth_token)
+        VALUES (?, ?, ?, ?)
+        ON CONFLICT(name) DO UPDATE SET
+            db_path = excluded.db_path,
+            last_sync_at = excluded.last_sync_at,
+            auth_token = CASE WHEN ? THEN research_config.auth_token ELSE excluded.auth_token END,
+            updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
+        RETURNING auth_token IS NOT NULL
+    "#;
 
-async fn upsert_resource(lib: &Commonplace<'_>, external_id: &str, title: &str, content_hash: &str) -> SyncResult<i32> {
-    let existing = match lib.find_resource_by_external_id(external_id).await {
-        Ok(r) => r,
-        Err(e) => {
-            log_find_error("resource", external_id, e);
-            return SyncResult::Error;
-        }
+    if !is_valid_name(&config.name) {
+        anyhow::bail!("invalid research source name {:?}", config.name);
+    }
+    let redacted = config.auth_token.as_deref().is_some_and(is_redacted);
+    let auth_token = config.auth_token.clone().filter(|_| !redacted);
+    let params = libsql::params![
+        config.name.clone(),
+        config.db_path.clone(),
+        config.last_sync_at.clone(),
+        auth_token,
+        redacted
+    ];
+    let mut rows = conn.query(query, params).await?;
+    let has_token = match rows.next().await? {
+        Some(row) => row.get::<i32>(0)? != 0,
+        None => false,
     };
-
-    let Some(resource) = existing else {
-        return create_resource(lib, external_id, title, content_hash).await;
+    Ok(!redacted || has_token)
+}
+
+/// Whether `db_path` is the URL of a database on Turso or another libsql server
+pub fn is_remote(db_path: &str) -> bool {
+    ["libsql://", "https://", "http://"]
+        .iter()
+        .any(|scheme| db_path.starts_with(scheme))
+}
+
+/// Opens a local Research database read-only, or connects to a remote one
+async fn connect(db_path: &str, auth_token: Option<&str>) -> anyhow::Result<Connection> {
+    let db = if is_remote(db_path) {
+        Builder::new_remote(db_path.to_string(), auth_token.unwrap_or_default().to_string())
+            .build()
+            .await?
+    } else {
+        Builder::new_local(db_path)
+            .flags(libsql::OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .build()
+            .await?
     };
-
-    if is_unchanged(&resource, content_hash) {
-        return SyncResult::Unchanged(resource.id);
-    }
-
-    update_resource(lib, external_id, resource.id, title, content_hash).await
+    Ok(db.connect()?)
 }
 
-async fn create_resource(lib: &Commonplace<'_>, external_id: &str, title: &str, content_hash: &str) -> SyncResult<i32> {
-    let result = lib
-        .create_resource(CreateResource {
-            title: title.to_string(),
-            resource_type: ResourceType::Pdf,
-            external_id: Some(external_id.to_string()),
-            content_hash: Some(content_hash.to_string()),
-        })
-        .await;
-
-    handle_create_result(result, |r| r.id, "resource", external_id)
-}
-
-async fn update_resource(
-    lib: &Commonplace<'_>,
-    external_id: &str,
-    id: i32,
-    title: &str,
-    content_hash: &str,
-) -> SyncResult<i32> {
-    let result = lib
-        .update_resource(
-            id,
-            UpdateResource {
-                title: Some(title.to_string()),
-                resource_type: None,
-                content_hash: Some(content_hash.to_string()),
-                config: None,
-            },
-        )
-        .await;
-
-    handle_update_result(result, id, "resource", external_id)
+/// Errors unless the database's items can be read
+async fn probe(db_path: &str, auth_token: Option<&str>) -> anyhow::Result<()> {
+    connect(db_path, auth_token)
+        .await?
+        .query("SELECT 1 FROM items LIMIT 1", ())
+        .await?;
+    Ok(())
 }
 
-async fn sync_item_annotations(
-    lib: &Commonplace<'_>,
-    research_conn: &Connection,
-    item: &ResearchItem,
-    resource_id: i32,
-    annotation_stats: &mut SyncStats,
-    comment_stats: &mut SyncStats,
-    seen: &mut SeenIds,
-) {
-    let annotations = match fetch_research_annotations(research_conn, &item.id).await {
-        Ok(a) => a,
-        Err(e) => {
-            tracing::error!("Failed to fetch annotations for {}: {}", item.id, e);
-            return;
+/// Whether a Research database is configured. Errors if one is configured but its
+/// items cannot be read.
+pub async fn check_database(conn: &Connection) -> anyhow::Result<bool> {
+    let dbs = load_sources(conn, None).await?;
+    for db in &dbs {
+        if !is_remote(&db.db_path) && !Path::new(&db.db_path).exists() {
+            anyhow::bail!("{}: database file does not exist at {}", db.name, db.db_path);
         }
-    };
-
-    for annotation in annotations {
-        let annotation_id =
-            match sync_annotation(lib, &annotation, resource_id, annotation_stats, &mut seen.annotations).await {
-                Some(id) => id,
-                None => continue,
-            };
-
-        sync_annotation_comments(lib, research_conn, &annotation, annotation_id, comment_stats, &mut seen.comments)
-            .await;
+        probe(&db.db_path, db.auth_token.as_deref())
+            .await
+            .map_err(|e| anyhow::anyhow!("{}: {}", db.name, e))?;
     }
+    Ok(!dbs.is_empty())
 }
 
-async fn sync_annotation(
-    lib: &Commonplace<'_>,
-    annotation: &ResearchAnnotation,
-    resource_id: i32,
-    stats: &mut SyncStats,
-    seen: &mut HashSet<String>,
-) -> Option<i32> {
-    let external_id = format!("research:{}", annotation.id);
-    let content_hash = compute_annotation_hash(&annotation.text, annotation.color.as_deref());
-    seen.insert(external_id.clone());
-
-    let boundary = serde_json::json!({
-        "pageNumber": annotation.page_number,
-        "position": annotation.position,
-        "source": "research",
-    });
+/// The configured databases, or just `name`
+async fn load_sources(conn: &Connection, name: Option<&str>) -> anyhow::Result<Vec<ResearchDb>> {
+    let mut query = "SELECT name, db_path, auth_token FROM research_config".to_string();
+    let mut params: Vec<libsql::Value> = Vec::new();
+    if let Some(name) = name {
+        query.push_str(" WHERE name = ?");
+        params.push(name.into());
+    }
+    query.push_str(" ORDER BY name");
 
-    upsert_annotation(lib, &external_id, annotation, resource_id, &content_hash, boundary)
-        .await
-        .record(stats)
-}
-
-async fn upsert_annotation(
-    lib: &Commonplace<'_>,
-    external_id: &str,
-    annotation: &ResearchAnnotation,
-    resource_id: i32,
-    content_hash: &str,
-    boundary: serde_json::Value,
-) -> SyncResult<i32> {
-    let existing = match lib.find_annotation_by_external_id(external_id).await {
-        Ok(a) => a,
-        Err(e) => {
-            log_find_error("annotation", external_id, e);
-            return SyncResult::Error;
-        }
+    let mut rows = conn.query(&query, params).await?;
+    let mut dbs = Vec::new();
+    while let Some(row) = rows.next().await? {
+        dbs.push(ResearchDb {
+            name: row.get(0)?,
+            db_path: row.get(1)?,
+            auth_token: row.get(2)?,
+        });
+    }
+    Ok(dbs)
+}
+
+/// Syncs every configured database, or the one named by `?source=`, each as its own
+/// run. Answers with how each went, unless they all failed.
+pub async fn sync(
+    State(state): State<AppState>,
+    Query(params): Query<SourceParams>,
+    payload: Option<Json<SyncRequest>>,
+) -> Response {
+    let payload = payload.map(|Json(p)| p).unwrap_or_default();
+    let entities = match payload.entities.as_deref().map(Entities::parse).transpose() {
+        Ok(entities) => entities.unwrap_or_default(),
+        Err(msg) => return bad_request(&msg),
     };
-
-    let Some(ann) = existing else {
-        return create_annotation(lib, external_id, annotation, resource_id, content_hash, boundary).await;
+    let dbs = match sources_to_sync(&state, params.source.as_deref()).await {
+        Ok(dbs) => dbs,
+        Err(response) => return response,
     };
 
-    if is_unchanged(&ann, content_hash) {
-        return SyncResult::Unchanged(ann.id);
+    let mut results = Vec::new();
+    for db in &dbs {
+        let result = run_sync(&state, db, entities, &|_| {}).await;
+        results.push(SourceSync {
+            source: db.name.clone(),
+            response: result.as_ref().ok().map(SyncResponse::from),
+            error: result.err(),
+        });
     }
 
-    update_annotation(lib, external_id, ann.id, annotation, content_hash, boundary).await
-}
-
-async fn create_annotation(
-    lib: &Commonplace<'_>,
-    external_id: &str,
-    annotation: &ResearchAnnotation,
-    resource_id: i32,
-    content_hash: &str,
-    boundary: serde_json::Value,
-) -> SyncResult<i32> {
-    let result = lib
-        .create_annotation(CreateAnnotation {
-            resource_id,
-            text: annotation.text.clone(),
-            color: annotation.color.clone(),
-            boundary: Some(boundary),
-            external_id: Some(external_id.to_string()),
-            content_hash: Some(content_hash.to_string()),
-        })
-        .await;
-
-    handle_create_result(result, |a| a.id, "annotation", external_id)
-}
+    if results.iter().all(|result| result.error.is_some()) {
+        let errors: Vec<String> = results
+            .into_iter()
+            .filter_map(|result| result.error.map(|error| format!("{}: {}", result.source, error)))
+            .collect();
+        return internal_error(&errors.join("; "));
+    }
+    success(results)
+}
+
+/// Runs the sync like `POST /research/sync`, streaming events as it goes: an `item`
+/// event for each item done, then a `stats` event with the usual response, or an
+/// `error` event, for each database. Every event names its database in `source`. The
+/// sync carries on if the client goes away.
+pub async fn sync_stream(State(state): State<AppState>, Query(params): Query<StreamParams>) -> Response {
+    let names: Option<Vec<&str>> = params.entities.as_deref().map(|names| names.split(',').collect());
+    let entities = match names.as_deref().map(Entities::parse).transpose() {
+        Ok(entities) => entities.unwrap_or_default(),
+        Err(msg) => return bad_request(&msg),
+    };
+    let dbs = match sources_to_sync(&state, params.source.as_deref()).await {
+        Ok(dbs) => dbs,
+        Err(response) => return response,
+    };
 
-async fn update_annotation(
-    lib: &Commonplace<'_>,
-    external_id: &str,
-    id: i32,
-    annotation: &ResearchAnnotation,
-    content_hash: &str,
-    boundary: serde_json::Value,
-) -> SyncResult<i32> {
-    let result = lib
-        .update_annotation(
-            id,
-            UpdateAnnotation {
-                text: Some(annotation.text.clone()),
-                color: annotation.color.clone(),
-                boundary: Some(boundary),
-                content_hash: Some(content_hash.to_string()),
-            },
-        )
-        .await;
+    let (tx, rx) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        for db in &dbs {
+            let source = db.name.as_str();
+            let progress = |item: ItemProgress| {
+                let _ = tx.send(
+                    SseEvent::default()
+                        .event("item")
+                        .json_data(SourceEvent { source, data: item }),
+                );
+            };
+            let last = match run_sync(&state, db, entities, &progress).await {
+                Ok(report) => SseEvent::default().event("stats").json_data(SourceEvent {
+                    source,
+                    data: SyncResponse::from(&report),
+                }),
+                Err(error) => SseEvent::default().event("error").json_data(SourceEvent {
+                    source,
+                    data: serde_json::json!({ "error": error }),
+                }),
+            };
+            let _ = tx.send(last);
+        }
+    });
 
-    handle_update_result(result, id, "annotation", external_id)
+    let events = stream::unfold(rx, |mut rx| async move { rx.recv().await.map(|event| (event, rx)) });
+    Sse::new(events).keep_alive(KeepAlive::default()).into_response()
 }
 
-async fn sync_annotation_comments(
-    lib: &Commonplace<'_>,
-    research_conn: &Connection,
-    annotation: &ResearchAnnotation,
-    annotation_id: i32,
-    stats: &mut SyncStats,
-    seen: &mut HashSet<String>,
-) {
-    let comments = match fetch_research_comments(research_conn, &annotation.id).await {
-        Ok(c) => c,
-        Err(e) => {
-            tracing::error!("Failed to fetch comments for {}: {}", annotation.id, e);
-            return;
-        }
-    };
-
-    for comment in comments {
-        sync_comment(lib, &comment, annotation_id, stats, seen).await;
+/// The databases a sync request is for, or the response explaining there are none
+async fn sources_to_sync(state: &AppState, source: Option<&str>) -> Result<Vec<ResearchDb>, Response> {
+    let dbs = load_sources(&state.db.connection(), source).await.map_err(|e| {
+        tracing::error!("Failed to get config: {}", e);
+        internal_error("Failed to get config")
+    })?;
+    match source {
+        Some(_) if dbs.is_empty() => Err(not_found("Research source not found")),
+        None if dbs.is_empty() => Err(bad_request("Research database path not configured. Please set the path first.")),
+        _ => Ok(dbs),
     }
 }
 
-async fn sync_comment(
-    lib: &Commonplace<'_>,
-    comment: &ResearchComment,
-    annotation_id: i32,
-    stats: &mut SyncStats,
-    seen: &mut HashSet<String>,
-) {
-    let external_id = format!("research:{}", comment.id);
-    let content_hash = compute_comment_hash(&comment.content);
-    seen.insert(external_id.clone());
+/// Syncs one database and records when; errors are logged and returned as the message
+/// to answer with
+async fn run_sync(
+    state: &AppState,
+    db: &ResearchDb,
+    entities: Entities,
+    progress: &Progress<'_>,
+) -> Result<SyncReport, String> {
+    if !is_remote(&db.db_path) && !Path::new(&db.db_path).exists() {
+        return Err("Research database file no longer exists at the configured path".to_string());
+    }
+    let conn = connect(&db.db_path, db.auth_token.as_deref()).await.map_err(|e| {
+        tracing::error!("Failed to open Research database {}: {}", db.name, e);
+        "Failed to open Research database".to_string()
+    })?;
 
-    upsert_comment(lib, &external_id, &comment.content, annotation_id, &content_hash)
-        .await
-        .record_unit(stats);
-}
-
-async fn upsert_comment(
-    lib: &Commonplace<'_>,
-    external_id: &str,
-    content: &str,
-    annotation_id: i32,
-    content_hash: &str,
-) -> SyncResult<()> {
-    let existing = match lib.find_comment_by_external_id(external_id).await {
-        Ok(c) => c,
-        Err(e) => {
-            log_find_error("comment", external_id, e);
-            return SyncResult::Error;
-        }
+    let source = ResearchSource {
+        name: db.name.clone(),
+        conn,
+        entities,
     };
-
-    let Some(cmt) = existing else {
-        return create_comment(lib, external_id, content, annotation_id, content_hash).await;
+    let report = match engine::run_with_progress(state, &source, progress).await {
+        Ok(report) => report,
+        Err(SyncError::Fetch(e)) => {
+            tracing::error!("Failed to fetch items from {}: {}", source.name(), e);
+            return Err("Failed to fetch items from Research database".to_string());
+        }
+        Err(SyncError::StartRun(e)) => {
+            tracing::error!("Failed to start {} sync run: {}", source.name(), e);
+            return Err("Failed to start sync run".to_string());
+        }
     };
 
-    if is_unchanged(&cmt, content_hash) {
-        return SyncResult::Unchanged(());
-    }
-
-    update_comment(lib, external_id, cmt.id, content, content_hash).await
-}
-
-async fn create_comment(
-    lib: &Commonplace<'_>,
-    external_id: &str,
-    content: &str,
-    annotation_id: i32,
-    content_hash: &str,
-) -> SyncResult<()> {
-    let result = lib
-        .create_comment(CreateComment {
-            annotation_id,
-            content: content.to_string(),
-            external_id: Some(external_id.to_string()),
-            content_hash: Some(content_hash.to_string()),
+    // The streaming sync runs in its own task, outside the request's transaction
+    let query = r#"
+        UPDATE research_config
+        SET last_sync_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now'),
+            updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
+        WHERE name = ?
+    "#;
+    let _ = state
+        .db
+        .write(|| async {
+            Ok(state
+                .db
+                .connection()
+                .execute(query, libsql::params![db.name.clone()])
+                .await?)
         })
         .await;
 
-    handle_create_result_unit(result, "comment", external_id)
+    Ok(report)
 }
 
-async fn update_comment(
-    lib: &Commonplace<'_>,
-    external_id: &str,
-    id: i32,
-    content: &str,
-    content_hash: &str,
-) -> SyncResult<()> {
-    let result = lib
-        .update_comment(
-            id,
-            UpdateComment {
-                content: content.to_string(),
-                content_hash: Some(content_hash.to_string()),
-            },
-        )
-        .await;
-
-    handle_update_result_unit(result, id, "comment", external_id)
+/// A Research database: items become PDF resources with their annotations, the
+/// comments on those, and notes, all keyed by Research's ids under the database's
+/// name (`research:<name>:<id>`)
+struct ResearchSource {
+    name: String,
+    conn: Connection,
+    entities: Entities,
 }
 
-async fn sync_item_notes(
-    lib: &Commonplace<'_>,
-    research_conn: &Connection,
-    item: &ResearchItem,
-    resource_id: i32,
-    stats: &mut SyncStats,
-    seen: &mut HashSet<String>,
-) {
-    let notes = match fetch_research_notes(research_conn, &item.id).await {
-        Ok(n) => n,
-        Err(e) => {
-            tracing::error!("Failed to fetch notes for {}: {}", item.id, e);
-            return;
-        }
-    };
-
-    for note in notes {
-        sync_note(lib, &note, resource_id, stats, seen).await;
+#[async_trait]
+impl SyncSource for ResearchSource {
+    fn name(&self) -> String {
+        format!("research:{}", self.name)
     }
-}
 
-async fn sync_note(
-    lib: &Commonplace<'_>,
-    note: &ResearchNote,
-    resource_id: i32,
-    stats: &mut SyncStats,
-    seen: &mut HashSet<String>,
-) {
-    let external_id = format!("research:{}", note.id);
-    let content_hash = compute_note_hash(&note.content);
-    seen.insert(external_id.clone());
+    fn entities(&self) -> Entities {
+        self.entities
+    }
 
-    upsert_note(lib, &external_id, &note.content, resource_id, &content_hash)
-        .await
-        .record_unit(stats);
-}
-
-async fn upsert_note(
-    lib: &Commonplace<'_>,
-    external_id: &str,
-    content: &str,
-    resource_id: i32,
-    content_hash: &str,
-) -> SyncResult<()> {
-    let existing = match lib.find_note_by_external_id(external_id).await {
-        Ok(n) => n,
-        Err(e) => {
-            log_find_error("note", external_id, e);
-            return SyncResult::Error;
+    async fn fetch(&self) -> anyhow::Result<Vec<SourceResource>> {
+        let prefix = self.prefix();
+        let external_id = |id: &str| format!("{}:{}", prefix, id);
+        let mut resources = Vec::new();
+        for item in fetch_research_items(&self.conn).await? {
+            let mut annotations = Vec::new();
+            let research_annotations = if self.entities.annotations {
+                fetch_research_annotations(&self.conn, &item.id).await?
+            } else {
+                Vec::new()
+            };
+            for annotation in research_annotations {
+                let comments = fetch_research_comments(&self.conn, &annotation.id)
+                    .await?
+                    .into_iter()
+                    .map(|comment| SourceComment {
+                        external_id: external_id(&comment.id),
+                        content: comment.content,
+                    })
+                    .collect();
+                let position = annotation
+                    .position
+                    .as_deref()
+                    .and_then(|p| serde_json::from_str(p).ok());
+                let boundary = Boundary::from_research(&annotation.text, annotation.page_number, position.as_ref());
+                annotations.push(SourceAnnotation {
+                    external_id: external_id(&annotation.id),
+                    text: annotation.text,
+                    color: annotation.color,
+                    boundary: Some(boundary.into_value()),
+                    comments,
+                });
+            }
+            let research_notes = if self.entities.notes {
+                fetch_research_notes(&self.conn, &item.id).await?
+            } else {
+                Vec::new()
+            };
+            let notes = research_notes
+                .into_iter()
+                .map(|note| SourceNote {
+                    external_id: external_id(&note.id),
+                    content: note.content,
+                })
+                .collect();
+
+            resources.push(SourceResource {
+                key: ResourceKey::External(external_id(&item.id)),
+                title: item.title,
+                resource_type: ResourceType::Pdf,
+                annotations,
+                notes,
+                archived: None,
+            });
         }
-    };
-
-    let Some(n) = existing else {
-        return create_note(lib, external_id, content, resource_id, content_hash).await;
-    };
-
-    if is_unchanged(&n, content_hash) {
-        return SyncResult::Unchanged(());
+        Ok(resources)
     }
-
-    update_note(lib, external_id, n.id, content, content_hash).await
-}
-
-async fn create_note(
-    lib: &Commonplace<'_>,
-    external_id: &str,
-    content: &str,
-    resource_id: i32,
-    content_hash: &str,
-) -> SyncResult<()> {
-    let result = lib
-        .create_note(CreateNote {
-            resource_id,
-            content: content.to_string(),
-            external_id: Some(external_id.to_string()),
-            content_hash: Some(content_hash.to_string()),
-        })
-        .await;
-
-    handle_create_result_unit(result, "note", external_id)
-}
-
-async fn update_note(
-    lib: &Commonplace<'_>,
-    external_id: &str,
-    id: i32,
-    content: &str,
-    content_hash: &str,
-) -> SyncResult<()> {
-    let result = lib
-        .update_note(
-            id,
-            UpdateNote {
-                content: content.to_string(),
-                content_hash: Some(content_hash.to_string()),
-            },
-        )
-        .await;
-
-    handle_update_result_unit(result, id, "note", external_id)
-}
-
-async fn soft_delete_orphans(
-    lib: &Commonplace<'_>,
-    seen: &SeenIds,
-    resource_stats: &mut SyncStats,
-    annotation_stats: &mut SyncStats,
-    comment_stats: &mut SyncStats,
-    note_stats: &mut SyncStats,
-) {
-    delete_orphans(
-        || lib.find_comments_by_source_prefix("research"),
-        |id| lib.soft_delete_comment(id),
-        &seen.comments,
-        comment_stats,
-        "comment",
-    )
-    .await;
-
-    delete_orphans(
-        || lib.find_annotations_by_source_prefix("research", None),
-        |id| lib.soft_delete_annotation(id),
-        &seen.annotations,
-        annotation_stats,
-        "annotation",
-    )
-    .await;
-
-    delete_orphans(
-        || lib.find_notes_by_source_prefix("research"),
-        |id| lib.soft_delete_note(id),
-        &seen.notes,
-        note_stats,
-        "note",
-    )
-    .await;
-
-    delete_orphans(
-        || lib.find_resources_by_source_prefix("research"),
-        |id| lib.soft_delete_resource(id),
-        &seen.resources,
-        resource_stats,
-        "resource",
-    )
-    .await;
 }
 
 async fn fetch_research_items(conn: &Connection) -> anyhow::Result<Vec<ResearchItem>> {
@@ -826,3 +703,37 @@ async fn fetch_research_notes(conn: &Connection, item_id: &str) -> anyhow::Resul
 
     Ok(notes)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::test_db;
+
+    #[tokio::test]
+    async fn test_archived_token_is_redacted_and_kept_on_import() {
+        let db = test_db().await;
+        let conn = &db.connection();
+        let config = ResearchSourceConfig {
+            name: "turso".to_string(),
+            db_path: "libsql://research-me.turso.io".to_string(),
+            auth_token: Some("token".to_string()),
+            last_sync_at: None,
+        };
+        import_config(conn, &config).await.unwrap();
+
+        let exported = export_config(conn).await.unwrap().remove(0);
+        assert!(exported.auth_token.as_deref().is_some_and(is_redacted));
+        import_config(conn, &exported).await.unwrap();
+        let db = load_sources(conn, Some("turso")).await.unwrap().remove(0);
+        assert_eq!((db.db_path.as_str(), db.auth_token.as_deref()), ("libsql://research-me.turso.io", Some("token")));
+    }
+
+    #[test]
+    fn test_source_names_fit_in_external_ids() {
+        assert!(is_valid_name("work"));
+        assert!(is_valid_name("phd-2024_notes"));
+        assert!(!is_valid_name(""));
+        assert!(!is_valid_name("a:b"));
+        assert!(!is_valid_name("Work"));
+    }
+}
@@ -0,0 +1,4 @@
+-- Research databases hosted on Turso or another libsql server
+-- `db_path` then holds a libsql:// URL, and this the token to connect with.
+
+ALTER TABLE research_config ADD COLUMN auth_token TEXT;
@@ -0,0 +1,24 @@
+-- Several Research databases, each under its own name
+-- The existing database becomes "default", and what it synced moves under that name:
+-- external ids go from `research:<id>` to `research:default:<id>`.
+
+CREATE TABLE research_config_new (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL UNIQUE,
+    db_path TEXT NOT NULL,
+    auth_token TEXT,
+    last_sync_at TEXT,
+    created_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    updated_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))
+);
+
+INSERT INTO research_config_new (name, db_path, auth_token, last_sync_at, created_at, updated_at)
+SELECT 'default', db_path, auth_token, last_sync_at, created_at, updated_at FROM research_config;
+
+DROP TABLE research_config;
+ALTER TABLE research_config_new RENAME TO research_config;
+
+UPDATE resources SET external_id = 'research:default:' || substr(external_id, 10) WHERE external_id LIKE 'research:%';
+UPDATE annotations SET external_id = 'research:default:' || substr(external_id, 10) WHERE external_id LIKE 'research:%';
+UPDATE comments SET external_id = 'research:default:' || substr(external_id, 10) WHERE external_id LIKE 'research:%';
+UPDATE notes SET external_id = 'research:default:' || substr(external_id, 10) WHERE external_id LIKE 'research:%';
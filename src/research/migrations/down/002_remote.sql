@@ -0,0 +1 @@
+ALTER TABLE research_config DROP COLUMN auth_token;
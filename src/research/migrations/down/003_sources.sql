@@ -0,0 +1,22 @@
+-- Only the default database survives; what other databases synced stays but is no
+-- longer owned by any sync.
+
+CREATE TABLE research_config_old (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    db_path TEXT NOT NULL,
+    last_sync_at TEXT,
+    created_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    updated_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    auth_token TEXT
+);
+
+INSERT INTO research_config_old (id, db_path, last_sync_at, created_at, updated_at, auth_token)
+SELECT 1, db_path, last_sync_at, created_at, updated_at, auth_token FROM research_config WHERE name = 'default';
+
+DROP TABLE research_config;
+ALTER TABLE research_config_old RENAME TO research_config;
+
+UPDATE resources SET external_id = 'research:' || substr(external_id, 18) WHERE external_id LIKE 'research:default:%';
+UPDATE annotations SET external_id = 'research:' || substr(external_id, 18) WHERE external_id LIKE 'research:default:%';
+UPDATE comments SET external_id = 'research:' || substr(external_id, 18) WHERE external_id LIKE 'research:default:%';
+UPDATE notes SET external_id = 'research:' || substr(external_id, 18) WHERE external_id LIKE 'research:default:%';
@@ -1,8 +1,21 @@
 mod handler;
 mod routes;
 
+pub use handler::{DEFAULT_SOURCE, ResearchSourceConfig, check_database, export_config, import_config, is_remote};
 pub use routes::routes;
 
 pub fn migrations() -> &'static [(&'static str, &'static str)] {
-    &[("research_001_config.sql", include_str!("migrations/001_config.sql"))]
+    &[
+        ("research_001_config.sql", include_str!("migrations/001_config.sql")),
+        ("research_002_remote.sql", include_str!("migrations/002_remote.sql")),
+        ("research_003_sources.sql", include_str!("migrations/003_sources.sql")),
+    ]
+}
+
+pub fn down_migrations() -> &'static [(&'static str, &'static str)] {
+    &[
+        ("research_001_config.sql", include_str!("migrations/down/001_config.sql")),
+        ("research_002_remote.sql", include_str!("migrations/down/002_remote.sql")),
+        ("research_003_sources.sql", include_str!("migrations/down/003_sources.sql")),
+    ]
 }
@@ -1,6 +1,6 @@
 use axum::{
     Router,
-    routing::{get, post},
+    routing::{delete, get, post},
 };
 
 use super::handler;
@@ -10,5 +10,8 @@ pub fn routes() -> Router<AppState> {
     Router::new()
         .route("/config", get(handler::get_config))
         .route("/config", post(handler::set_config))
+        .route("/sources", get(handler::list_sources))
+        .route("/sources/:name", delete(handler::delete_source))
         .route("/sync", post(handler::sync))
+        .route("/sync/stream", get(handler::sync_stream))
 }
@@ -1,13 +1,18 @@
 use crate::config::Config;
 use crate::error::ObjectStorageError;
+use crate::object_store::ObjectStream;
 use aws_sdk_s3::config::Credentials;
 use aws_sdk_s3::presigning::PresigningConfig;
 use aws_sdk_s3::Client;
-use aws_sdk_s3::types::{CompletedMultipartUpload, CompletedPart};
+use aws_sdk_s3::types::{CompletedMultipartUpload, CompletedPart, Tag, Tagging};
 use serde::Serialize;
+use std::collections::HashMap;
 use std::time::Duration;
 
-const DEFAULT_CHUNK_SIZE: i64 = 2 * 1024 * 1024;
+pub const DEFAULT_CHUNK_SIZE: i64 = 2 * 1024 * 1024;
+
+/// Object metadata holding the SHA-256 the client declared when starting the upload
+pub const SHA256_METADATA: &str = "sha256";
 
 #[derive(Debug, Serialize)]
 pub struct InitResponse {
@@ -30,6 +35,16 @@ pub struct PendingUpload {
     pub created_at: String,
 }
 
+/// What the store knows about a stored object, without fetching its body
+#[derive(Debug, Clone)]
+pub struct ObjectInfo {
+    pub size: i64,
+    pub etag: Option<String>,
+    pub content_type: Option<String>,
+    /// SHA-256 declared by the uploader, to verify the object against
+    pub sha256: Option<String>,
+}
+
 #[derive(Debug)]
 pub struct UploadMetadata {
     pub signature: String,
@@ -70,7 +85,7 @@ impl ResumableUploadManager {
         })
     }
 
-    fn build_key(signature: &str, file_name: &str) -> String {
+    pub(crate) fn build_key(signature: &str, file_name: &str) -> String {
         format!("{}_{}", signature, file_name)
     }
 
@@ -100,13 +115,13 @@ impl ResumableUploadManager {
             .await
             .map_err(|e| ObjectStorageError::S3Error(Box::new(e)))?;
 
-        if let Some(uploads) = response.uploads {
-            if let Some(upload) = uploads.first() {
-                let upload_id = upload.upload_id().unwrap_or_default().to_string();
-                let key = upload.key().unwrap_or_default().to_string();
-                if !upload_id.is_empty() && !key.is_empty() {
-                    return Ok(Some((upload_id, key)));
-                }
+        if let Some(uploads) = response.uploads
+            && let Some(upload) = uploads.first()
+        {
+            let upload_id = upload.upload_id().unwrap_or_default().to_string();
+            let key = upload.key().unwrap_or_default().to_string();
+            if !upload_id.is_empty() && !key.is_empty() {
+                return Ok(Some((upload_id, key)));
             }
         }
 
@@ -156,6 +171,7 @@ impl ResumableUploadManager {
         signature: &str,
         file_name: &str,
         file_size: i64,
+        sha256: Option<&str>,
     ) -> Result<InitResponse, ObjectStorageError> {
         if let Some((upload_id, key)) = self.find_upload_by_signature(signature).await? {
             let (_, completed_count, _, chunk_size) = self.get_parts_info(&upload_id, &key).await?;
@@ -187,6 +203,7 @@ impl ResumableUploadManager {
             .create_multipart_upload()
             .bucket(&self.bucket)
             .key(&key)
+            .set_metadata(sha256.map(|sha256| HashMap::from([(SHA256_METADATA.to_string(), sha256.to_string())])))
             .send()
             .await
             .map_err(|e| ObjectStorageError::S3Error(Box::new(e)))?;
@@ -370,23 +387,19 @@ impl ResumableUploadManager {
 
                 if let Some(initiated) = upload.initiated() {
                     let initiated_str = initiated.to_string();
-                    if let Ok(initiated_dt) = chrono::DateTime::parse_from_rfc3339(&initiated_str) {
-                        if initiated_dt.with_timezone(&chrono::Utc) < cutoff {
-                            if let Err(e) = self.abort(upload_id, key).await {
-                                tracing::warn!("Failed to abort expired upload {}: {}", upload_id, e);
-                            } else {
-                                count += 1;
-                            }
+                    if let Ok(initiated_dt) = chrono::DateTime::parse_from_rfc3339(&initiated_str)
+                        && initiated_dt.with_timezone(&chrono::Utc) < cutoff
+                    {
+                        if let Err(e) = self.abort(upload_id, key).await {
+                            tracing::warn!("Failed to abort expired upload {}: {}", upload_id, e);
+                        } else {
+                            count += 1;
                         }
                     }
                 }
             }
         }
 
-        if count > 0 {
-            tracing::info!("Cleaned up {} expired uploads", count);
-        }
-
         Ok(count)
     }
 
@@ -414,10 +427,161 @@ impl ResumableUploadManager {
         Ok(presigned.uri().to_string())
     }
 
+    /// URL the client can PUT one part of a multipart upload to, straight to the bucket
+    pub async fn presign_upload_part(
+        &self,
+        upload_id: &str,
+        key: &str,
+        part_number: i32,
+        expires_in_secs: u64,
+    ) -> Result<String, ObjectStorageError> {
+        let presigning_config = PresigningConfig::expires_in(Duration::from_secs(expires_in_secs))
+            .map_err(|e| ObjectStorageError::S3Error(Box::new(e)))?;
+
+        let presigned = self
+            .client
+            .upload_part()
+            .bucket(&self.bucket)
+            .key(key)
+            .upload_id(upload_id)
+            .part_number(part_number)
+            .presigned(presigning_config)
+            .await
+            .map_err(|e| ObjectStorageError::S3Error(Box::new(e)))?;
+
+        Ok(presigned.uri().to_string())
+    }
+
+    /// Bytes received so far by a multipart upload
+    pub async fn uploaded_bytes(&self, upload_id: &str, key: &str) -> Result<i64, ObjectStorageError> {
+        let (_, _, bytes_uploaded, _) = self.get_parts_info(upload_id, key).await?;
+        Ok(bytes_uploaded)
+    }
+
     pub fn get_filename_from_key(key: &str) -> Option<String> {
         Self::parse_key(key).map(|m| m.file_name)
     }
 
+    /// Checks the bucket can be reached with the configured credentials
+    pub async fn ping(&self) -> Result<(), ObjectStorageError> {
+        self.client
+            .head_bucket()
+            .bucket(&self.bucket)
+            .send()
+            .await
+            .map_err(|e| match e.raw_response().map(|r| r.status().as_u16()) {
+                Some(401 | 403) => ObjectStorageError::AccessDenied(self.bucket.clone()),
+                _ => ObjectStorageError::S3Error(Box::new(e)),
+            })?;
+        Ok(())
+    }
+
+    pub async fn head(&self, key: &str) -> Result<ObjectInfo, ObjectStorageError> {
+        let response = self
+            .client
+            .head_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .send()
+            .await
+            .map_err(|e| {
+                if e.as_service_error().is_some_and(|se| se.is_not_found()) {
+                    ObjectStorageError::ObjectNotFound(key.to_string())
+                } else {
+                    ObjectStorageError::S3Error(Box::new(e))
+                }
+            })?;
+
+        Ok(ObjectInfo {
+            size: response.content_length().unwrap_or(0),
+            etag: response.e_tag().map(|s| s.to_string()),
+            content_type: response.content_type().map(|s| s.to_string()),
+            sha256: response.metadata().and_then(|m| m.get(SHA256_METADATA)).cloned(),
+        })
+    }
+
+    /// Starts a GET and hands back the body as a stream, so large files are never
+    /// buffered in memory
+    pub async fn stream_file(&self, key: &str) -> Result<(ObjectInfo, ObjectStream), ObjectStorageError> {
+        let response = self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .send()
+            .await
+            .map_err(|e| {
+                if e.as_service_error().is_some_and(|se| se.is_no_such_key()) {
+                    ObjectStorageError::ObjectNotFound(key.to_string())
+                } else {
+                    ObjectStorageError::S3Error(Box::new(e))
+                }
+            })?;
+
+        let info = ObjectInfo {
+            size: response.content_length().unwrap_or(0),
+            etag: response.e_tag().map(|s| s.to_string()),
+            content_type: response.content_type().map(|s| s.to_string()),
+            sha256: response.metadata().and_then(|m| m.get(SHA256_METADATA)).cloned(),
+        };
+        let stream = futures_util::stream::unfold(response.body, |mut body| async move {
+            body.next()
+                .await
+                .map(|chunk| (chunk.map_err(std::io::Error::other), body))
+        });
+        Ok((info, Box::pin(stream)))
+    }
+
+    /// Uploads `data` in a single request, for objects small enough not to need a
+    /// multipart session. Returns the object's URL.
+    pub async fn put_object(&self, key: &str, data: Vec<u8>, content_type: &str) -> Result<String, ObjectStorageError> {
+        self.client
+            .put_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .content_type(content_type)
+            .body(data.into())
+            .send()
+            .await
+            .map_err(|e| ObjectStorageError::S3Error(Box::new(e)))?;
+
+        Ok(self.get_file_url(key))
+    }
+
+    /// Replaces the object's tags
+    pub async fn set_tags(&self, key: &str, tags: &[(String, String)]) -> Result<(), ObjectStorageError> {
+        let tag_set = tags
+            .iter()
+            .map(|(key, value)| Tag::builder().key(key).value(value).build())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| ObjectStorageError::S3Error(Box::new(e)))?;
+        let tagging = Tagging::builder()
+            .set_tag_set(Some(tag_set))
+            .build()
+            .map_err(|e| ObjectStorageError::S3Error(Box::new(e)))?;
+
+        self.client
+            .put_object_tagging()
+            .bucket(&self.bucket)
+            .key(key)
+            .tagging(tagging)
+            .send()
+            .await
+            .map_err(|e| ObjectStorageError::S3Error(Box::new(e)))?;
+        Ok(())
+    }
+
+    pub async fn delete_object(&self, key: &str) -> Result<(), ObjectStorageError> {
+        self.client
+            .delete_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .send()
+            .await
+            .map_err(|e| ObjectStorageError::S3Error(Box::new(e)))?;
+        Ok(())
+    }
+
     pub async fn download_file(&self, key: &str) -> Result<Vec<u8>, ObjectStorageError> {
         let response = self
             .client
@@ -0,0 +1,350 @@
+//! Fake library data for development: `bibliotek seed --books 200 --annotations 5000`.
+//! Everything generated here is deterministic for a given `--seed`, and commonplace
+//! rows carry a `seed:` external_id so they are easy to tell apart from real syncs.
+
+use anyhow::Result;
+use std::collections::HashSet;
+
+use crate::commonplace::boundary::{Boundary, Rect};
+use crate::commonplace::{
+    CreateAnnotation, CreateComment, CreateResource, CreateWord, ResourceType, compute_annotation_hash,
+    compute_resource_hash,
+};
+use crate::db::Database;
+
+const ADJECTIVES: &[&str] = &[
+    "Quiet",
+    "Hidden",
+    "Infinite",
+    "Broken",
+    "Gentle",
+    "Invisible",
+    "Last",
+    "Distant",
+    "Deep",
+    "Restless",
+    "Silent",
+    "Forgotten",
+    "Wandering",
+    "Unfinished",
+    "Practical",
+    "Elegant",
+    "Radical",
+    "Ordinary",
+];
+
+const NOUNS: &[&str] = &[
+    "Algorithms",
+    "Cities",
+    "Gardens",
+    "Machines",
+    "Rivers",
+    "Empires",
+    "Minds",
+    "Mathematics",
+    "Habits",
+    "Systems",
+    "Oceans",
+    "Letters",
+    "Maps",
+    "Numbers",
+    "Stars",
+    "Markets",
+    "Languages",
+    "Forests",
+];
+
+const SUBTITLES: &[&str] = &[
+    "A History",
+    "An Introduction",
+    "Notes on Craft",
+    "How Things Work",
+    "A Field Guide",
+    "Essays",
+    "Theory and Practice",
+    "A Memoir",
+];
+
+const FIRST_NAMES: &[&str] = &[
+    "Ada", "Kwame", "Lena", "Tomas", "Yuki", "Amara", "Rafael", "Ingrid", "Kofi", "Mei", "Nadia", "Omar", "Priya",
+    "Samuel", "Zora", "Elif",
+];
+
+const LAST_NAMES: &[&str] = &[
+    "Mensah",
+    "Okafor",
+    "Lindqvist",
+    "Moreau",
+    "Tanaka",
+    "Haddad",
+    "Novak",
+    "Castillo",
+    "Abernathy",
+    "Osei",
+    "Kowalski",
+    "Fischer",
+    "Adeyemi",
+    "Sato",
+];
+
+const TAGS: &[&str] = &[
+    "classic",
+    "reference",
+    "favorite",
+    "to-reread",
+    "dense",
+    "lecture-notes",
+    "essays",
+    "primary-source",
+];
+
+const CATEGORIES: &[&str] = &[
+    "mathematics",
+    "physics",
+    "biology",
+    "computers",
+    "philosophy",
+    "literature",
+    "history",
+    "economics",
+    "psychology",
+];
+
+const OPENINGS: &[&str] = &[
+    "The point is not",
+    "What matters most",
+    "Every system eventually",
+    "We rarely notice that",
+    "In practice,",
+    "The hardest part",
+    "It turns out that",
+    "History suggests that",
+    "Good work",
+    "A careful reader",
+];
+
+const MIDDLES: &[&str] = &[
+    "to avoid mistakes but to learn from them quickly",
+    "is the attention we bring to ordinary things",
+    "drifts toward the shape of its incentives",
+    "most progress is made in small, unglamorous steps",
+    "simplicity is the result of many discarded drafts",
+    "is deciding what to leave out",
+    "the map is not the territory",
+    "institutions outlive the people who built them",
+    "compounds slowly and then all at once",
+    "asks why the author chose this example",
+];
+
+const CLOSINGS: &[&str] = &[
+    ".",
+    ", and that changes everything.",
+    ", even when nobody is watching.",
+    "; the rest is commentary.",
+    ", which is easy to forget.",
+];
+
+const COLORS: &[&str] = &["yellow", "green", "blue", "pink", "purple"];
+
+const WORDS: &[(&str, &str)] = &[
+    ("ephemeral", "lasting for a very short time"),
+    ("obdurate", "stubbornly refusing to change one's opinion or course of action"),
+    ("palimpsest", "something reused or altered but still bearing visible traces of its earlier form"),
+    ("sinecure", "a position requiring little or no work but giving status or benefit"),
+    ("lacuna", "an unfilled space or interval; a gap"),
+    ("apophenia", "the tendency to perceive meaningful connections between unrelated things"),
+    ("quotidian", "of or occurring every day; ordinary"),
+    ("recondite", "little known; abstruse"),
+    ("perspicacious", "having a ready insight into and understanding of things"),
+    ("sesquipedalian", "characterized by long words; long-winded"),
+    ("limerence", "the state of being infatuated with another person"),
+    ("verisimilitude", "the appearance of being true or real"),
+    ("anodyne", "not likely to provoke dissent or offense; inoffensive"),
+    ("hermeneutic", "concerning interpretation, especially of texts"),
+    ("inchoate", "just begun and so not fully formed or developed"),
+    ("sprezzatura", "studied carelessness; effortless grace"),
+];
+
+pub struct SeedOptions {
+    pub books: usize,
+    pub annotations: usize,
+    pub words: usize,
+    pub seed: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct SeedSummary {
+    pub books: usize,
+    pub resources: usize,
+    pub annotations: usize,
+    pub comments: usize,
+    pub words: usize,
+}
+
+/// xorshift64*: good enough for fake data and keeps the output reproducible
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Self(seed.max(1))
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n.max(1) as u64) as usize
+    }
+
+    fn pick<'a>(&mut self, items: &[&'a str]) -> &'a str {
+        items[self.below(items.len())]
+    }
+
+    fn chance(&mut self, percent: usize) -> bool {
+        self.below(100) < percent
+    }
+}
+
+pub async fn run(db: &Database, opts: &SeedOptions) -> Result<SeedSummary> {
+    let mut rng = Rng::new(opts.seed);
+    let mut summary = SeedSummary::default();
+    let mut titles = HashSet::new();
+    let mut book_titles = Vec::with_capacity(opts.books);
+
+    for i in 0..opts.books {
+        let mut title = format!("The {} {}: {}", rng.pick(ADJECTIVES), rng.pick(NOUNS), rng.pick(SUBTITLES));
+        if !titles.insert(title.clone()) {
+            title = format!("{} (Vol. {})", title, i + 1);
+            titles.insert(title.clone());
+        }
+
+        let authors: Vec<String> = (0..1 + rng.below(2))
+            .map(|_| format!("{} {}", rng.pick(FIRST_NAMES), rng.pick(LAST_NAMES)))
+            .collect();
+        let tags: Vec<String> = (0..rng.below(3)).map(|_| rng.pick(TAGS).to_string()).collect();
+        let categories = vec![rng.pick(CATEGORIES).to_string()];
+        let url = format!("https://example.com/seed/{}-{}.pdf", opts.seed, i + 1);
+        let description = format!("{} {}{}", rng.pick(OPENINGS), rng.pick(MIDDLES), rng.pick(CLOSINGS));
+        let pages = 80 + rng.below(700) as i32;
+        let ratings = rng.below(6) as i32;
+
+        db.create_book(
+            &title,
+            &url,
+            None,
+            Some(&description),
+            Some(pages),
+            Some(ratings),
+            &authors,
+            &tags,
+            &categories,
+            "complete",
+        )
+        .await?;
+        summary.books += 1;
+        book_titles.push((title, pages));
+    }
+
+    if book_titles.is_empty() || (opts.annotations == 0 && opts.words == 0) {
+        return Ok(summary);
+    }
+
+    // Commonplace rows go through one transaction; thousands of autocommitted
+    // inserts are slow on a synced replica.
+    let conn = &db.connection();
+    conn.execute("BEGIN TRANSACTION", ()).await?;
+    let result = seed_commonplace(db, opts, &mut rng, &book_titles, &mut summary).await;
+    match result {
+        Ok(()) => {
+            conn.execute("COMMIT", ()).await?;
+            Ok(summary)
+        }
+        Err(e) => {
+            let _ = conn.execute("ROLLBACK", ()).await;
+            Err(e)
+        }
+    }
+}
+
+async fn seed_commonplace(
+    db: &Database,
+    opts: &SeedOptions,
+    rng: &mut Rng,
+    book_titles: &[(String, i32)],
+    summary: &mut SeedSummary,
+) -> Result<()> {
+    let lib = db.commonplace();
+
+    let mut resources = Vec::with_capacity(book_titles.len());
+    for (i, (title, pages)) in book_titles.iter().enumerate() {
+        let resource = lib
+            .create_resource(CreateResource {
+                title: title.clone(),
+                resource_type: ResourceType::Pdf,
+                external_id: Some(format!("seed:{}:resource:{}", opts.seed, i + 1)),
+                content_hash: Some(compute_resource_hash(title)),
+            })
+            .await?;
+        resources.push((resource.id, *pages));
+        summary.resources += 1;
+    }
+
+    for i in 0..opts.annotations {
+        let (resource_id, pages) = resources[rng.below(resources.len())];
+        let text = format!("{} {}{}", rng.pick(OPENINGS), rng.pick(MIDDLES), rng.pick(CLOSINGS));
+        let color = rng.pick(COLORS);
+        let page = 1 + rng.below(pages as usize);
+        let mut boundary = Boundary::new("seed").with_quote(&text);
+        boundary.page = Some(page as i64);
+        boundary.rects.push(Rect {
+            page: Some(page as i64),
+            x: (5 + rng.below(10)) as f64 / 100.0,
+            y: rng.below(90) as f64 / 100.0,
+            width: (50 + rng.below(30)) as f64 / 100.0,
+            height: (2 * (1 + rng.below(4))) as f64 / 100.0,
+        });
+
+        let annotation = lib
+            .create_annotation(CreateAnnotation {
+                resource_id,
+                content_hash: Some(compute_annotation_hash(&text, Some(color))),
+                text,
+                color: Some(color.to_string()),
+                boundary: Some(boundary.into_value()),
+                external_id: Some(format!("seed:{}:annotation:{}", opts.seed, i + 1)),
+            })
+            .await?;
+        summary.annotations += 1;
+
+        if rng.chance(15) {
+            lib.create_comment(CreateComment {
+                annotation_id: annotation.id,
+                content: format!("{} {}.", rng.pick(OPENINGS), rng.pick(MIDDLES)),
+                external_id: Some(format!("seed:{}:comment:{}", opts.seed, i + 1)),
+                content_hash: None,
+                parent_comment_id: None,
+            })
+            .await?;
+            summary.comments += 1;
+        }
+    }
+
+    for _ in 0..opts.words {
+        let (resource_id, _) = resources[rng.below(resources.len())];
+        let (name, meaning) = WORDS[rng.below(WORDS.len())];
+        lib.create_word(CreateWord {
+            resource_id,
+            name: name.to_string(),
+            meaning: meaning.to_string(),
+            ..Default::default()
+        })
+        .await?;
+        summary.words += 1;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,270 @@
+//! Sync conflicts: an annotation, comment or note edited here while its source
+//! changed it too. Edits made here keep the content hash of the last sync, so an entity
+//! whose own content no longer hashes to it was edited since. What a sync then does is
+//! up to the source's `ConflictPolicy`; with `manual` it records the conflict here and
+//! leaves the entity alone until `POST /sync/conflicts/:id/resolve` picks a side.
+
+use anyhow::Result;
+use libsql::Connection;
+use serde::Serialize;
+use serde_json::Value;
+
+use super::runs::Entity;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SyncConflict {
+    pub id: i64,
+    pub source: String,
+    pub run_id: Option<i64>,
+    pub entity: String,
+    pub entity_id: i64,
+    pub external_id: String,
+    /// The fields as edited here
+    pub local: Value,
+    /// The fields as the source has them
+    pub incoming: Value,
+    pub incoming_hash: String,
+    /// "local" or "source", once resolved
+    pub resolution: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+    pub resolved_at: Option<String>,
+}
+
+/// Both sides of a conflict found by a sync
+pub struct NewConflict<'a> {
+    pub source: &'a str,
+    pub run_id: Option<i64>,
+    pub entity: Entity,
+    pub entity_id: i32,
+    pub external_id: &'a str,
+    pub local: Value,
+    pub incoming: Value,
+    pub incoming_hash: &'a str,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+    Local,
+    Source,
+}
+
+impl Resolution {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Resolution::Local => "local",
+            Resolution::Source => "source",
+        }
+    }
+}
+
+/// Whether an entity synced with `synced_hash`, whose content now hashes to
+/// `local_hash`, conflicts with the source's version hashing to `incoming_hash`
+pub fn is_conflict(synced_hash: Option<&str>, local_hash: &str, incoming_hash: &str) -> bool {
+    synced_hash.is_some_and(|synced| synced != local_hash && synced != incoming_hash) && local_hash != incoming_hash
+}
+
+/// Records a conflict, or refreshes the entity's open one with what the source has now
+pub async fn record_conflict(conn: &Connection, conflict: &NewConflict<'_>) -> Result<()> {
+    let local = serde_json::to_string(&conflict.local)?;
+    let incoming = serde_json::to_string(&conflict.incoming)?;
+    let updated = conn
+        .execute(
+            r#"
+            UPDATE sync_conflicts
+            SET run_id = ?, local = ?, incoming = ?, incoming_hash = ?,
+                updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
+            WHERE entity = ? AND entity_id = ? AND resolved_at IS NULL
+            "#,
+            libsql::params![
+                conflict.run_id,
+                local.clone(),
+                incoming.clone(),
+                conflict.incoming_hash,
+                conflict.entity.table(),
+                conflict.entity_id
+            ],
+        )
+        .await?;
+    if updated == 0 {
+        conn.execute(
+            r#"
+            INSERT INTO sync_conflicts (source, run_id, entity, entity_id, external_id, local, incoming, incoming_hash)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+            libsql::params![
+                conflict.source,
+                conflict.run_id,
+                conflict.entity.table(),
+                conflict.entity_id,
+                conflict.external_id,
+                local,
+                incoming,
+                conflict.incoming_hash
+            ],
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+const CONFLICT_COLUMNS: &str = r#"
+    id, source, run_id, entity, entity_id, external_id, local, incoming, incoming_hash, resolution,
+    created_at, updated_at, resolved_at
+"#;
+
+fn row_to_conflict(row: &libsql::Row) -> Result<SyncConflict> {
+    let local: String = row.get(6)?;
+    let incoming: String = row.get(7)?;
+    Ok(SyncConflict {
+        id: row.get(0)?,
+        source: row.get(1)?,
+        run_id: row.get(2)?,
+        entity: row.get(3)?,
+        entity_id: row.get(4)?,
+        external_id: row.get(5)?,
+        local: serde_json::from_str(&local)?,
+        incoming: serde_json::from_str(&incoming)?,
+        incoming_hash: row.get(8)?,
+        resolution: row.get(9)?,
+        created_at: row.get(10)?,
+        updated_at: row.get(11)?,
+        resolved_at: row.get(12)?,
+    })
+}
+
+pub async fn get_conflict(conn: &Connection, id: i64) -> Result<Option<SyncConflict>> {
+    let query = format!("SELECT {} FROM sync_conflicts WHERE id = ?", CONFLICT_COLUMNS);
+    let mut rows = conn.query(&query, libsql::params![id]).await?;
+    match rows.next().await? {
+        Some(row) => Ok(Some(row_to_conflict(&row)?)),
+        None => Ok(None),
+    }
+}
+
+/// Open conflicts, oldest first, or every conflict with `resolved`. A source also
+/// matches its sub-sources, as in `list_runs`.
+pub async fn list_conflicts(conn: &Connection, source: Option<&str>, resolved: bool) -> Result<Vec<SyncConflict>> {
+    let mut query = format!("SELECT {} FROM sync_conflicts WHERE 1 = 1", CONFLICT_COLUMNS);
+    let mut params: Vec<libsql::Value> = Vec::new();
+    if !resolved {
+        query.push_str(" AND resolved_at IS NULL");
+    }
+    if let Some(source) = source {
+        query.push_str(" AND (source = ? OR source LIKE ?)");
+        params.push(source.into());
+        params.push(format!("{}:%", source).into());
+    }
+    query.push_str(" ORDER BY created_at, id");
+
+    let mut rows = conn.query(&query, params).await?;
+    let mut conflicts = Vec::new();
+    while let Some(row) = rows.next().await? {
+        conflicts.push(row_to_conflict(&row)?);
+    }
+    Ok(conflicts)
+}
+
+pub async fn mark_resolved(conn: &Connection, id: i64, resolution: Resolution) -> Result<()> {
+    conn.execute(
+        r#"
+        UPDATE sync_conflicts
+        SET resolution = ?, resolved_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now'),
+            updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
+        WHERE id = ?
+        "#,
+        libsql::params![resolution.as_str(), id],
+    )
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    use async_trait::async_trait;
+    use axum::extract::{Json, Path, State};
+
+    use super::*;
+    use crate::commonplace::{ResourceType, UpdateAnnotation};
+    use crate::config::{ConflictPolicy, SyncSettings};
+    use crate::sync::engine;
+    use crate::sync::handler::{ResolveRequest, resolve_conflict};
+    use crate::sync::{ResourceKey, SourceAnnotation, SourceResource, SyncSource};
+    use crate::test_support::{read_json, test_state};
+
+    /// A book on a Kobo with one highlight
+    struct Device(&'static str);
+
+    #[async_trait]
+    impl SyncSource for Device {
+        fn name(&self) -> String {
+            "kobo".to_string()
+        }
+
+        async fn fetch(&self) -> Result<Vec<SourceResource>> {
+            Ok(vec![SourceResource {
+                key: ResourceKey::External("kobo:book".to_string()),
+                title: "How to Take Smart Notes".to_string(),
+                resource_type: ResourceType::Pdf,
+                annotations: vec![SourceAnnotation {
+                    external_id: "kobo:b1".to_string(),
+                    text: self.0.to_string(),
+                    color: None,
+                    boundary: None,
+                    comments: Vec::new(),
+                }],
+                notes: Vec::new(),
+                archived: None,
+            }])
+        }
+    }
+
+    #[tokio::test]
+    async fn test_manual_policy_records_and_resolves_conflicts() {
+        let mut state = test_state().await;
+        state.sync = Arc::new(SyncSettings {
+            conflict_policies: HashMap::from([("kobo".to_string(), ConflictPolicy::Manual)]),
+            ..Default::default()
+        });
+        let lib = state.db.commonplace();
+        let conn = &state.db.connection();
+        engine::run(&state, &Device("Writing is thinking.")).await.unwrap();
+        let highlight = lib.find_annotation_by_external_id("kobo:b1").await.unwrap().unwrap();
+        let edit = UpdateAnnotation {
+            text: Some("Writing is thinking, edited here".to_string()),
+            color: None,
+            boundary: None,
+            content_hash: None,
+        };
+        lib.update_annotation(highlight.id, edit).await.unwrap();
+
+        // Syncing again refreshes the open conflict rather than adding another
+        for _ in 0..2 {
+            let report = engine::run(&state, &Device("Writing is thinking!")).await.unwrap();
+            assert_eq!((report.annotations.conflicts, report.annotations.updated), (1, 0));
+        }
+        let open = list_conflicts(conn, Some("kobo"), false).await.unwrap();
+        assert_eq!(open.len(), 1);
+        assert_eq!((open[0].source.as_str(), open[0].entity_id), ("kobo", highlight.id as i64));
+        assert_eq!(open[0].local["text"], "Writing is thinking, edited here");
+        assert_eq!(open[0].incoming["text"], "Writing is thinking!");
+
+        let keep = |keep: &str| Json(ResolveRequest { keep: keep.to_string() });
+        let resp = resolve_conflict(State(state.clone()), Path(open[0].id), keep("source")).await;
+        let (status, body) = read_json(resp).await;
+        assert_eq!(status, axum::http::StatusCode::OK);
+        assert_eq!(body["data"]["resolution"], "source");
+        let highlight = lib.get_annotation(highlight.id).await.unwrap().unwrap();
+        assert_eq!(highlight.text, "Writing is thinking!");
+
+        let resp = resolve_conflict(State(state.clone()), Path(open[0].id), keep("local")).await;
+        assert_eq!(resp.status(), axum::http::StatusCode::CONFLICT);
+        assert!(list_conflicts(conn, Some("kobo"), false).await.unwrap().is_empty());
+        assert_eq!(list_conflicts(conn, None, true).await.unwrap().len(), 1);
+        let settled = engine::run(&state, &Device("Writing is thinking!")).await.unwrap();
+        assert_eq!((settled.annotations.conflicts, settled.annotations.unchanged), (0, 1));
+    }
+}
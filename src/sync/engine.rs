@@ -0,0 +1,1057 @@
+//! Generic sync engine. A [`SyncSource`] only fetches its items and maps them to
+//! commonplace entities ([`SourceResource`] and what hangs off it); the engine upserts
+//! them by external id, soft-deletes whatever the source owns but no longer returned,
+//! and does the run bookkeeping: the run and its journal, the diff log and the
+//! `SyncCompleted` event.
+//!
+//! Entities are owned by prefix: a source named "research:work" owns every external id
+//! starting with "research:work:". Resources keyed by URL ([`ResourceKey::Website`]) are
+//! shared with other sources and never deleted, and so are the resources a book
+//! ([`ResourceKey::Book`]) is matched to. Sources that only pull what changed report
+//! their deletions instead ([`SyncSource::deleted`]). Annotations, comments and notes
+//! edited here since the source last changed them are settled by the source's
+//! [`ConflictPolicy`], see [`super::conflicts`].
+
+use std::collections::HashSet;
+
+use async_trait::async_trait;
+use serde::Serialize;
+use serde_json::{Value, json};
+
+use super::conflicts::{self, NewConflict};
+use super::runs::Entity;
+use super::{
+    SyncResult, SyncStats, Syncable, delete_orphans, handle_create_result, handle_create_result_unit,
+    handle_update_result, handle_update_result_unit, is_unchanged, log_find_error, log_update_error, runs,
+};
+use crate::commonplace::{
+    Commonplace, CreateAnnotation, CreateComment, CreateNote, CreateResource, ResourceStatus, ResourceType,
+    UpdateAnnotation, UpdateComment, UpdateNote, UpdateResource, Upsert, compute_annotation_hash, compute_comment_hash,
+    compute_note_hash, compute_resource_hash,
+};
+use crate::config::ConflictPolicy;
+use crate::events::Event;
+use crate::handler::AppState;
+
+#[async_trait]
+pub trait SyncSource: Send + Sync {
+    /// Recorded on the run and the `SyncCompleted` event, e.g. "research"
+    fn name(&self) -> String;
+
+    /// External ids the source owns start with `<prefix>:`
+    fn prefix(&self) -> String {
+        self.name()
+    }
+
+    /// Title of the only resource a partial sync covers. Orphans are then looked for
+    /// on that resource alone, and no resource is deleted.
+    fn scope(&self) -> Option<String> {
+        None
+    }
+
+    /// Whether what the source owns but did not return is left alone rather than
+    /// soft-deleted, for sources that are files which need not hold everything
+    fn additive(&self) -> bool {
+        false
+    }
+
+    /// What the sync covers besides resources
+    fn entities(&self) -> Entities {
+        Entities::ALL
+    }
+
+    /// Whether orphans are only looked for on the resources the fetch returned, and no
+    /// resource is deleted, for sources like an e-reader that only hold what is still
+    /// on them
+    fn returned_only(&self) -> bool {
+        false
+    }
+
+    /// What the source reported deleted, asked for after `fetch`. Soft-deleted even by
+    /// an additive source.
+    fn deleted(&self) -> Vec<Deleted> {
+        Vec::new()
+    }
+
+    async fn fetch(&self) -> anyhow::Result<Vec<SourceResource>>;
+}
+
+/// How a fetched resource is matched to a commonplace one
+pub enum ResourceKey {
+    /// Owned by the source, upserted by external id
+    External(String),
+    /// A web page, found or created by its URL
+    Website(String),
+    /// A book: the resource the source created for it, else the resource of the library
+    /// book with `isbn` or one with the same title, and only once something hangs off
+    /// it a new resource
+    Book { external_id: String, isbn: Option<String> },
+}
+
+pub struct SourceResource {
+    pub key: ResourceKey,
+    pub title: String,
+    pub resource_type: ResourceType,
+    pub annotations: Vec<SourceAnnotation>,
+    pub notes: Vec<SourceNote>,
+    /// Whether the source has it archived, which moves a resource the source owns to
+    /// done, and a done one back to unread once it no longer is. `None` leaves the
+    /// status alone.
+    pub archived: Option<bool>,
+}
+
+pub struct SourceAnnotation {
+    pub external_id: String,
+    pub text: String,
+    pub color: Option<String>,
+    pub boundary: Option<Value>,
+    pub comments: Vec<SourceComment>,
+}
+
+pub struct SourceComment {
+    pub external_id: String,
+    pub content: String,
+}
+
+pub struct SourceNote {
+    pub external_id: String,
+    pub content: String,
+}
+
+/// An entity the source reported deleted, by its external id
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Deleted {
+    Resource(String),
+    Annotation(String),
+    Comment(String),
+    Note(String),
+}
+
+/// What a sync covers besides resources, which are always synced since everything
+/// else hangs off them. What it leaves out is neither written nor deleted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Entities {
+    /// Annotations and their comments
+    pub annotations: bool,
+    pub notes: bool,
+}
+
+impl Default for Entities {
+    fn default() -> Self {
+        Self::ALL
+    }
+}
+
+impl Entities {
+    pub const ALL: Entities = Entities {
+        annotations: true,
+        notes: true,
+    };
+
+    /// From the names a request lists: "resources", "annotations" (which brings their
+    /// comments, also spelled "comments") and "notes"
+    pub fn parse<S: AsRef<str>>(names: &[S]) -> Result<Self, String> {
+        if names.is_empty() {
+            return Err("entities must list at least one of resources, annotations, notes".to_string());
+        }
+        let mut entities = Entities {
+            annotations: false,
+            notes: false,
+        };
+        for name in names {
+            match name.as_ref().trim() {
+                "resources" => {}
+                "annotations" | "comments" => entities.annotations = true,
+                "notes" => entities.notes = true,
+                other => {
+                    return Err(format!("Unknown entity \"{}\", expected resources, annotations or notes", other));
+                }
+            }
+        }
+        Ok(entities)
+    }
+}
+
+#[derive(Debug)]
+pub enum SyncError {
+    /// The source could not be read; nothing was written
+    Fetch(anyhow::Error),
+    StartRun(anyhow::Error),
+}
+
+#[derive(Debug, Default)]
+pub struct SyncReport {
+    pub run_id: i64,
+    pub resources: SyncStats,
+    pub annotations: SyncStats,
+    pub comments: SyncStats,
+    pub notes: SyncStats,
+    /// Resources that got new annotations, with their title
+    pub annotated: Vec<(i32, String)>,
+}
+
+impl SyncReport {
+    pub fn totals(&self) -> SyncStats {
+        let all = [&self.resources, &self.annotations, &self.comments, &self.notes];
+        SyncStats {
+            created: all.iter().map(|stats| stats.created).sum(),
+            updated: all.iter().map(|stats| stats.updated).sum(),
+            deleted: all.iter().map(|stats| stats.deleted).sum(),
+            unchanged: all.iter().map(|stats| stats.unchanged).sum(),
+            conflicts: all.iter().map(|stats| stats.conflicts).sum(),
+            errors: all.iter().map(|stats| stats.errors).sum(),
+        }
+    }
+}
+
+/// The report as sources answer their sync request
+#[derive(Debug, Serialize, Default)]
+pub struct SyncResponse {
+    pub run_id: i64,
+    pub resources_created: i32,
+    pub resources_updated: i32,
+    pub resources_deleted: i32,
+    pub resources_unchanged: i32,
+    pub annotations_created: i32,
+    pub annotations_updated: i32,
+    pub annotations_deleted: i32,
+    pub annotations_unchanged: i32,
+    pub comments_created: i32,
+    pub comments_updated: i32,
+    pub comments_deleted: i32,
+    pub comments_unchanged: i32,
+    pub notes_created: i32,
+    pub notes_updated: i32,
+    pub notes_deleted: i32,
+    pub notes_unchanged: i32,
+    pub conflicts: i32,
+    pub errors: i32,
+}
+
+impl From<&SyncReport> for SyncResponse {
+    fn from(report: &SyncReport) -> Self {
+        Self {
+            run_id: report.run_id,
+            resources_created: report.resources.created,
+            resources_updated: report.resources.updated,
+            resources_deleted: report.resources.deleted,
+            resources_unchanged: report.resources.unchanged,
+            annotations_created: report.annotations.created,
+            annotations_updated: report.annotations.updated,
+            annotations_deleted: report.annotations.deleted,
+            annotations_unchanged: report.annotations.unchanged,
+            comments_created: report.comments.created,
+            comments_updated: report.comments.updated,
+            comments_deleted: report.comments.deleted,
+            comments_unchanged: report.comments.unchanged,
+            notes_created: report.notes.created,
+            notes_updated: report.notes.updated,
+            notes_deleted: report.notes.deleted,
+            notes_unchanged: report.notes.unchanged,
+            conflicts: report.totals().conflicts,
+            errors: report.totals().errors,
+        }
+    }
+}
+
+/// A fetched resource the sync is done with, reported as it goes along
+#[derive(Debug, Clone, Serialize)]
+pub struct ItemProgress {
+    /// How many of the fetched resources are done, this one included
+    pub done: usize,
+    pub total: usize,
+    pub title: String,
+    /// None if the resource failed to sync
+    pub resource_id: Option<i32>,
+    pub annotations: usize,
+    pub notes: usize,
+}
+
+impl ItemProgress {
+    fn new(index: usize, total: usize, item: &SourceResource, resource_id: Option<i32>) -> Self {
+        Self {
+            done: index + 1,
+            total,
+            title: item.title.clone(),
+            resource_id,
+            annotations: item.annotations.len(),
+            notes: item.notes.len(),
+        }
+    }
+}
+
+/// Called with each resource a sync is done with
+pub type Progress<'a> = dyn Fn(ItemProgress) + Send + Sync + 'a;
+
+/// The syncing source and what it does with entities edited here in the meantime
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OnConflict<'a> {
+    pub source: &'a str,
+    pub policy: ConflictPolicy,
+}
+
+/// Fetches from `source` and applies it as one sync run
+pub async fn run(state: &AppState, source: &dyn SyncSource) -> Result<SyncReport, SyncError> {
+    run_with_progress(state, source, &|_| {}).await
+}
+
+/// Like `run`, telling `progress` about each resource as it is applied
+pub async fn run_with_progress(
+    state: &AppState,
+    source: &dyn SyncSource,
+    progress: &Progress<'_>,
+) -> Result<SyncReport, SyncError> {
+    let items = source.fetch().await.map_err(SyncError::Fetch)?;
+
+    let name = source.name();
+    let conn = &state.db.connection();
+    let run_id = state
+        .db
+        .write(|| runs::start_run(conn, &name))
+        .await
+        .map_err(SyncError::StartRun)?;
+    let lib = state.db.commonplace().with_sync_run(run_id);
+    let on_conflict = OnConflict {
+        source: &name,
+        policy: state.sync.conflict_policy(&name),
+    };
+    let orphans = if source.additive() {
+        Orphans::Keep
+    } else if source.returned_only() {
+        Orphans::Returned
+    } else {
+        Orphans::Delete(source.scope())
+    };
+    let entities = source.entities();
+    let mut report =
+        apply_with_progress(&lib, &source.prefix(), orphans, entities, on_conflict, &items, progress).await;
+    soft_delete_reported(&lib, &source.deleted(), entities, &mut report).await;
+    report.run_id = run_id;
+
+    let totals = report.totals();
+    if let Err(e) = state.db.write(|| runs::finish_run(conn, run_id, &totals)).await {
+        tracing::error!("Failed to finish sync run {}: {}", run_id, e);
+    }
+    if state.sync_diff_log
+        && let Err(e) = state.db.write(|| runs::record_diff(conn, run_id)).await
+    {
+        tracing::error!("Failed to record diff of sync run {}: {}", run_id, e);
+    }
+
+    state
+        .db
+        .emit(Event::SyncCompleted {
+            source: name,
+            created: totals.created,
+            updated: totals.updated,
+            deleted: totals.deleted,
+            response: serde_json::to_value(SyncResponse::from(&report)).unwrap_or_default(),
+        })
+        .await;
+
+    Ok(report)
+}
+
+#[derive(Default)]
+struct SeenIds {
+    /// Resources the sync found or created
+    returned: HashSet<i32>,
+    resources: HashSet<String>,
+    annotations: HashSet<String>,
+    comments: HashSet<String>,
+    notes: HashSet<String>,
+}
+
+/// Brings what `prefix` owns in commonplace in line with `items`
+pub async fn apply(
+    lib: &Commonplace<'_>,
+    prefix: &str,
+    scope: Option<&str>,
+    on_conflict: OnConflict<'_>,
+    items: &[SourceResource],
+) -> SyncReport {
+    let orphans = Orphans::Delete(scope.map(str::to_string));
+    apply_with_progress(lib, prefix, orphans, Entities::ALL, on_conflict, items, &|_| {}).await
+}
+
+/// What happens to entities the source owns but did not return
+enum Orphans {
+    /// Soft-deleted, on the resource with the given title only if there is one
+    Delete(Option<String>),
+    /// Soft-deleted on the resources the sync returned only
+    Returned,
+    Keep,
+}
+
+async fn apply_with_progress(
+    lib: &Commonplace<'_>,
+    prefix: &str,
+    orphans: Orphans,
+    entities: Entities,
+    on_conflict: OnConflict<'_>,
+    items: &[SourceResource],
+    progress: &Progress<'_>,
+) -> SyncReport {
+    let mut report = SyncReport::default();
+    let mut seen = SeenIds::default();
+
+    for (index, item) in items.iter().enumerate() {
+        if let ResourceKey::External(external_id) | ResourceKey::Book { external_id, .. } = &item.key {
+            seen.resources.insert(external_id.clone());
+        }
+        let upserted = upsert_resource(lib, item).await;
+        let Some(resource_id) = upserted.and_then(|result| result.record(&mut report.resources)) else {
+            progress(ItemProgress::new(index, items.len(), item, None));
+            continue;
+        };
+        seen.returned.insert(resource_id);
+
+        // A source may still return what the sync does not cover
+        let annotations: &[SourceAnnotation] = if entities.annotations { &item.annotations } else { &[] };
+        let notes: &[SourceNote] = if entities.notes { &item.notes } else { &[] };
+
+        let created_before = report.annotations.created;
+        for annotation in annotations {
+            seen.annotations.insert(annotation.external_id.clone());
+            let Some(annotation_id) = upsert_annotation(lib, on_conflict, annotation, resource_id)
+                .await
+                .record(&mut report.annotations)
+            else {
+                continue;
+            };
+            for comment in &annotation.comments {
+                seen.comments.insert(comment.external_id.clone());
+                upsert_comment(lib, on_conflict, comment, annotation_id)
+                    .await
+                    .record_unit(&mut report.comments);
+            }
+        }
+        if report.annotations.created > created_before {
+            report.annotated.push((resource_id, item.title.clone()));
+        }
+
+        for note in notes {
+            seen.notes.insert(note.external_id.clone());
+            upsert_note(lib, on_conflict, note, resource_id)
+                .await
+                .record_unit(&mut report.notes);
+        }
+        progress(ItemProgress::new(index, items.len(), item, Some(resource_id)));
+    }
+
+    match orphans {
+        Orphans::Keep => {}
+        Orphans::Delete(Some(title)) => match lib.find_resource_by_title(&title).await {
+            Ok(Some(resource)) => {
+                let scope = HashSet::from([resource.id]);
+                soft_delete_orphans(lib, prefix, Some(&scope), entities, &seen, &mut report).await
+            }
+            Ok(None) => tracing::warn!("Scope resource {} not found, skipping orphan detection", title),
+            Err(e) => {
+                tracing::error!("Failed to find scope resource {}: {}", title, e);
+                report.resources.errors += 1;
+            }
+        },
+        Orphans::Delete(None) => soft_delete_orphans(lib, prefix, None, entities, &seen, &mut report).await,
+        Orphans::Returned => soft_delete_orphans(lib, prefix, Some(&seen.returned), entities, &seen, &mut report).await,
+    }
+
+    report
+}
+
+/// `None` for a book with nothing to sync onto it and no resource yet
+async fn upsert_resource(lib: &Commonplace<'_>, item: &SourceResource) -> Option<SyncResult<i32>> {
+    let content_hash = Some(compute_resource_hash(&item.title));
+    let external_id = match &item.key {
+        ResourceKey::Website(url) => {
+            return Some(match lib.find_or_create_website(url, content_hash).await {
+                Ok((resource, true)) => SyncResult::Created(resource.id),
+                Ok((resource, false)) => SyncResult::Unchanged(resource.id),
+                Err(e) => {
+                    tracing::error!("Failed to find or create resource for {}: {}", url, e);
+                    SyncResult::Error
+                }
+            });
+        }
+        ResourceKey::External(external_id) => external_id,
+        ResourceKey::Book { external_id, isbn } => {
+            match match_book(lib, external_id, isbn.as_deref(), &item.title).await {
+                Ok(BookMatch::Existing(id)) => return Some(SyncResult::Unchanged(id)),
+                Ok(BookMatch::Unmatched) if item.annotations.is_empty() && item.notes.is_empty() => return None,
+                Ok(_) => external_id,
+                Err(e) => {
+                    log_find_error("resource", external_id, e);
+                    return Some(SyncResult::Error);
+                }
+            }
+        }
+    };
+
+    let result = lib
+        .upsert_resource(CreateResource {
+            title: item.title.clone(),
+            resource_type: item.resource_type,
+            external_id: Some(external_id.clone()),
+            content_hash,
+        })
+        .await;
+    let result = match result {
+        Ok(Upsert::Created(resource)) => SyncResult::Created(resource.id),
+        Ok(Upsert::Updated(resource)) => SyncResult::Updated(resource.id),
+        Ok(Upsert::Unchanged(resource)) => SyncResult::Unchanged(resource.id),
+        Err(e) => {
+            log_update_error("resource", external_id, e);
+            SyncResult::Error
+        }
+    };
+    Some(match item.archived {
+        Some(archived) => follow_archive(lib, archived, result, external_id).await,
+        None => result,
+    })
+}
+
+enum BookMatch {
+    /// The source created a resource for it before
+    Own,
+    /// Already there, from the library or another source
+    Existing(i32),
+    Unmatched,
+}
+
+async fn match_book(
+    lib: &Commonplace<'_>,
+    external_id: &str,
+    isbn: Option<&str>,
+    title: &str,
+) -> anyhow::Result<BookMatch> {
+    if lib.find_resource_by_external_id(external_id).await?.is_some() {
+        return Ok(BookMatch::Own);
+    }
+    if let Some(isbn) = isbn
+        && let Some(resource) = lib.find_resource_by_isbn(isbn).await?
+    {
+        return Ok(BookMatch::Existing(resource.id));
+    }
+    Ok(match lib.find_resource_by_title(title).await? {
+        Some(resource) => BookMatch::Existing(resource.id),
+        None => BookMatch::Unmatched,
+    })
+}
+
+/// Moves the resource to done when the source has it archived, and a done one back to
+/// unread when it no longer is, leaving one being read alone
+async fn follow_archive(
+    lib: &Commonplace<'_>,
+    archived: bool,
+    result: SyncResult<i32>,
+    external_id: &str,
+) -> SyncResult<i32> {
+    let id = match result {
+        SyncResult::Created(id) | SyncResult::Updated(id) | SyncResult::Unchanged(id) | SyncResult::Conflict(id) => id,
+        SyncResult::Error => return result,
+    };
+    let current = match lib.get_resource(id).await {
+        Ok(Some(resource)) => resource.status,
+        Ok(None) => return result,
+        Err(e) => {
+            log_find_error("resource", external_id, e);
+            return result;
+        }
+    };
+    let status = match (archived, current) {
+        (true, ResourceStatus::Done) | (false, ResourceStatus::Unread | ResourceStatus::Reading) => return result,
+        (true, _) => ResourceStatus::Done,
+        (false, ResourceStatus::Done) => ResourceStatus::Unread,
+    };
+
+    let update = UpdateResource {
+        status: Some(status),
+        ..Default::default()
+    };
+    match lib.update_resource(id, update).await {
+        Ok(_) => match result {
+            SyncResult::Unchanged(id) => SyncResult::Updated(id),
+            result => result,
+        },
+        Err(e) => {
+            log_update_error("resource", external_id, e);
+            result
+        }
+    }
+}
+
+async fn upsert_annotation(
+    lib: &Commonplace<'_>,
+    on_conflict: OnConflict<'_>,
+    annotation: &SourceAnnotation,
+    resource_id: i32,
+) -> SyncResult<i32> {
+    let external_id = &annotation.external_id;
+    let existing = match lib.find_annotation_by_external_id(external_id).await {
+        Ok(existing) => existing,
+        Err(e) => {
+            log_find_error("annotation", external_id, e);
+            return SyncResult::Error;
+        }
+    };
+
+    let content_hash = compute_annotation_hash(&annotation.text, annotation.color.as_deref());
+    match existing {
+        Some(existing) if is_unchanged(&existing, &content_hash) => SyncResult::Unchanged(existing.id),
+        Some(existing)
+            if edited_here(
+                on_conflict,
+                &existing,
+                &compute_annotation_hash(&existing.text, existing.color.as_deref()),
+                &content_hash,
+            ) =>
+        {
+            let conflict = NewConflict {
+                source: on_conflict.source,
+                run_id: lib.sync_run(),
+                entity: Entity::Annotation,
+                entity_id: existing.id,
+                external_id,
+                local: json!({ "text": existing.text, "color": existing.color }),
+                incoming: json!({ "text": annotation.text, "color": annotation.color, "boundary": annotation.boundary }),
+                incoming_hash: &content_hash,
+            };
+            keep_local(lib, on_conflict.policy, conflict, existing.id).await
+        }
+        Some(existing) => {
+            let result = lib
+                .update_annotation(
+                    existing.id,
+                    UpdateAnnotation {
+                        text: Some(annotation.text.clone()),
+                        color: annotation.color.clone(),
+                        boundary: annotation.boundary.clone(),
+                        content_hash: Some(content_hash),
+                    },
+                )
+                .await;
+            handle_update_result(result, existing.id, "annotation", external_id)
+        }
+        None => {
+            let result = lib
+                .create_annotation(CreateAnnotation {
+                    resource_id,
+                    text: annotation.text.clone(),
+                    color: annotation.color.clone(),
+                    boundary: annotation.boundary.clone(),
+                    external_id: Some(external_id.clone()),
+                    content_hash: Some(content_hash),
+                })
+                .await;
+            handle_create_result(result, |a| a.id, "annotation", external_id)
+        }
+    }
+}
+
+async fn upsert_comment(
+    lib: &Commonplace<'_>,
+    on_conflict: OnConflict<'_>,
+    comment: &SourceComment,
+    annotation_id: i32,
+) -> SyncResult<()> {
+    let external_id = &comment.external_id;
+    let existing = match lib.find_comment_by_external_id(external_id).await {
+        Ok(existing) => existing,
+        Err(e) => {
+            log_find_error("comment", external_id, e);
+            return SyncResult::Error;
+        }
+    };
+
+    let content_hash = compute_comment_hash(&comment.content);
+    match existing {
+        Some(existing) if is_unchanged(&existing, &content_hash) => SyncResult::Unchanged(()),
+        Some(existing)
+            if edited_here(on_conflict, &existing, &compute_comment_hash(&existing.content), &content_hash) =>
+        {
+            let conflict = NewConflict {
+                source: on_conflict.source,
+                run_id: lib.sync_run(),
+                entity: Entity::Comment,
+                entity_id: existing.id,
+                external_id,
+                local: json!({ "content": existing.content }),
+                incoming: json!({ "content": comment.content }),
+                incoming_hash: &content_hash,
+            };
+            keep_local(lib, on_conflict.policy, conflict, ()).await
+        }
+        Some(existing) => {
+            let result = lib
+                .update_comment(
+                    existing.id,
+                    UpdateComment {
+                        content: comment.content.clone(),
+                        content_hash: Some(content_hash),
+                    },
+                )
+                .await;
+            handle_update_result_unit(result, existing.id, "comment", external_id)
+        }
+        None => {
+            let result = lib
+                .create_comment(CreateComment {
+                    annotation_id,
+                    content: comment.content.clone(),
+                    external_id: Some(external_id.clone()),
+                    content_hash: Some(content_hash),
+                    parent_comment_id: None,
+                })
+                .await;
+            handle_create_result_unit(result, "comment", external_id)
+        }
+    }
+}
+
+async fn upsert_note(
+    lib: &Commonplace<'_>,
+    on_conflict: OnConflict<'_>,
+    note: &SourceNote,
+    resource_id: i32,
+) -> SyncResult<()> {
+    let external_id = &note.external_id;
+    let existing = match lib.find_note_by_external_id(external_id).await {
+        Ok(existing) => existing,
+        Err(e) => {
+            log_find_error("note", external_id, e);
+            return SyncResult::Error;
+        }
+    };
+
+    let content_hash = compute_note_hash(&note.content);
+    match existing {
+        Some(existing) if is_unchanged(&existing, &content_hash) => SyncResult::Unchanged(()),
+        Some(existing) if edited_here(on_conflict, &existing, &compute_note_hash(&existing.content), &content_hash) => {
+            let conflict = NewConflict {
+                source: on_conflict.source,
+                run_id: lib.sync_run(),
+                entity: Entity::Note,
+                entity_id: existing.id,
+                external_id,
+                local: json!({ "content": existing.content }),
+                incoming: json!({ "content": note.content }),
+                incoming_hash: &content_hash,
+            };
+            keep_local(lib, on_conflict.policy, conflict, ()).await
+        }
+        Some(existing) => {
+            let result = lib
+                .update_note(
+                    existing.id,
+                    UpdateNote {
+                        content: note.content.clone(),
+                        content_hash: Some(content_hash),
+                    },
+                )
+                .await;
+            handle_update_result_unit(result, existing.id, "note", external_id)
+        }
+        None => {
+            let result = lib
+                .create_note(CreateNote {
+                    resource_id,
+                    content: note.content.clone(),
+                    external_id: Some(external_id.clone()),
+                    content_hash: Some(content_hash),
+                })
+                .await;
+            handle_create_result_unit(result, "note", external_id)
+        }
+    }
+}
+
+/// Whether `existing`, whose content now hashes to `local_hash`, was edited here since
+/// the last sync while the source changed it to `incoming_hash`. Always false when the
+/// source simply wins.
+fn edited_here<T: Syncable>(on_conflict: OnConflict<'_>, existing: &T, local_hash: &str, incoming_hash: &str) -> bool {
+    on_conflict.policy != ConflictPolicy::SourceWins
+        && conflicts::is_conflict(existing.content_hash(), local_hash, incoming_hash)
+}
+
+/// Keeps the edit made here: with `local-wins` the source's version is recorded as
+/// synced, with `manual` the conflict is recorded for resolution
+async fn keep_local<T>(
+    lib: &Commonplace<'_>,
+    policy: ConflictPolicy,
+    conflict: NewConflict<'_>,
+    id: T,
+) -> SyncResult<T> {
+    let result = match policy {
+        ConflictPolicy::Manual => conflicts::record_conflict(lib.connection(), &conflict).await,
+        ConflictPolicy::SourceWins | ConflictPolicy::LocalWins => {
+            lib.set_content_hash(conflict.entity, conflict.entity_id, conflict.incoming_hash)
+                .await
+        }
+    };
+    match result {
+        Ok(()) => SyncResult::Conflict(id),
+        Err(e) => {
+            tracing::error!("Failed to keep local edit of {} {}: {}", conflict.entity.table(), conflict.external_id, e);
+            SyncResult::Error
+        }
+    }
+}
+
+/// Soft-deletes what the source reported deleted, among the `entities` synced
+async fn soft_delete_reported(lib: &Commonplace<'_>, deleted: &[Deleted], entities: Entities, report: &mut SyncReport) {
+    for deleted in deleted {
+        let (entity, external_id, stats) = match deleted {
+            Deleted::Resource(id) => ("resource", id, &mut report.resources),
+            Deleted::Annotation(id) if entities.annotations => ("annotation", id, &mut report.annotations),
+            Deleted::Comment(id) if entities.annotations => ("comment", id, &mut report.comments),
+            Deleted::Note(id) if entities.notes => ("note", id, &mut report.notes),
+            _ => continue,
+        };
+        let found = match deleted {
+            Deleted::Resource(_) => lib
+                .find_resource_by_external_id(external_id)
+                .await
+                .map(|r| r.map(|r| r.id)),
+            Deleted::Annotation(_) => lib
+                .find_annotation_by_external_id(external_id)
+                .await
+                .map(|a| a.map(|a| a.id)),
+            Deleted::Comment(_) => lib
+                .find_comment_by_external_id(external_id)
+                .await
+                .map(|c| c.map(|c| c.id)),
+            Deleted::Note(_) => lib.find_note_by_external_id(external_id).await.map(|n| n.map(|n| n.id)),
+        };
+        let id = match found {
+            Ok(Some(id)) => id,
+            Ok(None) => continue,
+            Err(e) => {
+                log_find_error(entity, external_id, e);
+                stats.errors += 1;
+                continue;
+            }
+        };
+        let result = match deleted {
+            Deleted::Resource(_) => lib.soft_delete_resource(id).await,
+            Deleted::Annotation(_) => lib.soft_delete_annotation(id).await,
+            Deleted::Comment(_) => lib.soft_delete_comment(id).await,
+            Deleted::Note(_) => lib.soft_delete_note(id).await,
+        };
+        match result {
+            Ok(true) => stats.deleted += 1,
+            Ok(false) => {}
+            Err(e) => {
+                log_update_error(entity, external_id, e);
+                stats.errors += 1;
+            }
+        }
+    }
+}
+
+/// Soft-deletes what `prefix` owns but wasn't seen, on the `scope` resources only if
+/// given, and only among the `entities` synced. Comments go first so that deleting
+/// an annotation doesn't hide them.
+async fn soft_delete_orphans(
+    lib: &Commonplace<'_>,
+    prefix: &str,
+    scope: Option<&HashSet<i32>>,
+    entities: Entities,
+    seen: &SeenIds,
+    report: &mut SyncReport,
+) {
+    if entities.annotations {
+        soft_delete_orphan_annotations(lib, prefix, scope, seen, report).await;
+    }
+    if entities.notes {
+        let in_scope = |resource_id: i32| scope.is_none_or(|scope| scope.contains(&resource_id));
+        delete_orphans(
+            || async {
+                let notes = lib.find_notes_by_source_prefix(prefix).await?;
+                Ok(notes
+                    .into_iter()
+                    .filter(|note| in_scope(note.resource_id))
+                    .collect::<Vec<_>>())
+            },
+            |id| lib.soft_delete_note(id),
+            &seen.notes,
+            &mut report.notes,
+            "note",
+        )
+        .await;
+    }
+
+    if scope.is_none() {
+        delete_orphans(
+            || lib.find_resources_by_source_prefix(prefix),
+            |id| lib.soft_delete_resource(id),
+            &seen.resources,
+            &mut report.resources,
+            "resource",
+        )
+        .await;
+    }
+}
+
+async fn soft_delete_orphan_annotations(
+    lib: &Commonplace<'_>,
+    prefix: &str,
+    scope: Option<&HashSet<i32>>,
+    seen: &SeenIds,
+    report: &mut SyncReport,
+) {
+    let annotations = match lib.find_annotations_by_source_prefix(prefix, None).await {
+        Ok(annotations) => annotations
+            .into_iter()
+            .filter(|annotation| scope.is_none_or(|scope| scope.contains(&annotation.resource_id)))
+            .collect::<Vec<_>>(),
+        Err(e) => {
+            tracing::error!("Failed to find orphan annotations: {}", e);
+            report.annotations.errors += 1;
+            return;
+        }
+    };
+    let scoped: HashSet<i32> = annotations.iter().map(|annotation| annotation.id).collect();
+
+    delete_orphans(
+        || async {
+            let comments = lib.find_comments_by_source_prefix(prefix).await?;
+            Ok(comments
+                .into_iter()
+                .filter(|comment| scope.is_none() || scoped.contains(&comment.annotation_id))
+                .collect::<Vec<_>>())
+        },
+        |id| lib.soft_delete_comment(id),
+        &seen.comments,
+        &mut report.comments,
+        "comment",
+    )
+    .await;
+
+    delete_orphans(
+        || async { Ok(annotations) },
+        |id| lib.soft_delete_annotation(id),
+        &seen.annotations,
+        &mut report.annotations,
+        "annotation",
+    )
+    .await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::test_db;
+
+    fn page(url: &str, highlights: &[&str]) -> SourceResource {
+        SourceResource {
+            key: ResourceKey::Website(url.to_string()),
+            title: url.to_string(),
+            resource_type: ResourceType::Website,
+            annotations: highlights
+                .iter()
+                .map(|id| SourceAnnotation {
+                    external_id: format!("chrome:{}", id),
+                    text: format!("highlight {}", id),
+                    color: None,
+                    boundary: None,
+                    comments: Vec::new(),
+                })
+                .collect(),
+            notes: Vec::new(),
+            archived: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_scoped_sync_only_prunes_its_resource() {
+        let db = test_db().await;
+        let lib = db.commonplace();
+        let (essay, notes) = ("https://example.com/essay", "https://example.com/notes");
+
+        let first =
+            apply(&lib, "chrome", None, OnConflict::default(), &[page(essay, &["1", "2"]), page(notes, &["3"])]).await;
+        assert_eq!((first.resources.created, first.annotations.created), (2, 3));
+        assert_eq!(first.annotated.len(), 2);
+
+        // Only the essay was sent, without highlight 2
+        let scoped = apply(&lib, "chrome", Some(essay), OnConflict::default(), &[page(essay, &["1"])]).await;
+        assert_eq!((scoped.annotations.unchanged, scoped.annotations.deleted), (1, 1));
+        assert!(lib.find_annotation_by_external_id("chrome:3").await.unwrap().is_some());
+
+        // A full sync prunes highlights, never the shared pages
+        let full = apply(&lib, "chrome", None, OnConflict::default(), &[page(essay, &["1"])]).await;
+        assert_eq!((full.annotations.deleted, full.resources.deleted), (1, 0));
+        assert!(lib.find_resource_by_title(notes).await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_sync_leaves_out_entities_it_does_not_cover() {
+        let db = test_db().await;
+        let lib = db.commonplace();
+        let essay = "https://example.com/essay";
+        apply(&lib, "chrome", None, OnConflict::default(), &[page(essay, &["1"])]).await;
+
+        let resources_only = Entities::parse(&["resources"]).unwrap();
+        let report = apply_with_progress(
+            &lib,
+            "chrome",
+            Orphans::Delete(None),
+            resources_only,
+            OnConflict::default(),
+            &[page(essay, &["2"])],
+            &|_| {},
+        )
+        .await;
+        assert_eq!((report.resources.unchanged, report.annotations.created, report.annotations.deleted), (1, 0, 0));
+        assert!(lib.find_annotation_by_external_id("chrome:1").await.unwrap().is_some());
+        assert!(lib.find_annotation_by_external_id("chrome:2").await.unwrap().is_none());
+        assert!(Entities::parse(&["highlights"]).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_conflicting_edits_follow_the_policy() {
+        let db = test_db().await;
+        let lib = db.commonplace();
+        let essay = "https://example.com/essay";
+        let manual = OnConflict {
+            source: "light:chrome",
+            policy: ConflictPolicy::Manual,
+        };
+        apply(&lib, "chrome", None, manual, &[page(essay, &["1"])]).await;
+
+        let highlight = lib.find_annotation_by_external_id("chrome:1").await.unwrap().unwrap();
+        let edit = UpdateAnnotation {
+            text: Some("edited here".to_string()),
+            color: None,
+            boundary: None,
+            content_hash: None,
+        };
+        lib.update_annotation(highlight.id, edit).await.unwrap();
+        let mut revised = page(essay, &["1"]);
+        revised.annotations[0].text = "revised in the source".to_string();
+
+        // Both sides stay as they are until the conflict is resolved, however often it syncs
+        for _ in 0..2 {
+            let report = apply(&lib, "chrome", None, manual, std::slice::from_ref(&revised)).await;
+            assert_eq!((report.annotations.conflicts, report.annotations.updated), (1, 0));
+        }
+        let open = conflicts::list_conflicts(&db.connection(), Some("light"), false)
+            .await
+            .unwrap();
+        assert_eq!(open.len(), 1);
+        assert_eq!(open[0].local["text"], "edited here");
+        assert_eq!(open[0].incoming["text"], "revised in the source");
+
+        // Keeping the local edit takes the source's version as synced
+        let local_wins = OnConflict {
+            policy: ConflictPolicy::LocalWins,
+            ..manual
+        };
+        let kept = apply(&lib, "chrome", None, local_wins, std::slice::from_ref(&revised)).await;
+        assert_eq!(kept.annotations.conflicts, 1);
+        let again = apply(&lib, "chrome", None, local_wins, std::slice::from_ref(&revised)).await;
+        assert_eq!((again.annotations.conflicts, again.annotations.unchanged), (0, 1));
+        let highlight = lib.get_annotation(highlight.id).await.unwrap().unwrap();
+        assert_eq!(highlight.text, "edited here");
+    }
+}
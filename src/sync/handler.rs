@@ -0,0 +1,711 @@
+use std::collections::BTreeMap;
+
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+    response::Response,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::commonplace::capture::is_capturable;
+use crate::commonplace::{UpdateAnnotation, UpdateComment, UpdateNote};
+use crate::feeds::{self, FeedSourceConfig};
+use crate::handler::AppState;
+use crate::kobo::{self, KoboSourceConfig};
+use crate::pocket::{self, PocketSourceConfig};
+use crate::readwise::{self, ReadwiseSourceConfig};
+use crate::research::{self, ResearchSourceConfig};
+use crate::response::{bad_request, conflict, internal_error, not_found, success};
+use crate::webhook::{self, WebhookConfig};
+use crate::zotero::{self, ZoteroSourceConfig};
+
+use super::conflicts::{self, Resolution, SyncConflict};
+use super::runs::{self, DiffEntry, Entity, Rollback, SyncRun};
+
+pub const CONFIG_ARCHIVE_VERSION: u32 = 1;
+
+const REDACTED_PREFIX: &str = "redacted:sha256:";
+
+/// Runs listed by `GET /sync/history` unless `limit` says otherwise
+const DEFAULT_HISTORY_LIMIT: i64 = 50;
+
+/// Everything needed to recreate the sync integrations of one instance on another.
+/// Secrets never leave the instance in clear text, see `redact_secret`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SyncConfigArchive {
+    pub version: u32,
+    pub exported_at: String,
+    /// The default Research database
+    #[serde(default)]
+    pub research: Option<ResearchSourceConfig>,
+    /// Research databases other than the default one
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub research_sources: Vec<ResearchSourceConfig>,
+    #[serde(default)]
+    pub zotero: Option<ZoteroSourceConfig>,
+    #[serde(default)]
+    pub kobo: Option<KoboSourceConfig>,
+    #[serde(default)]
+    pub readwise: Option<ReadwiseSourceConfig>,
+    #[serde(default)]
+    pub pocket: Option<PocketSourceConfig>,
+    /// Registered RSS and Atom feeds
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub feeds: Vec<FeedSourceConfig>,
+    /// The webhook told about finished syncs, which is set in the config file
+    #[serde(default)]
+    pub webhook: Option<WebhookConfig>,
+}
+
+#[derive(Debug, Serialize, Default)]
+pub struct ImportSummary {
+    pub imported: Vec<String>,
+    pub skipped: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DiffParams {
+    /// resources, annotations, comments or notes
+    pub entity: Option<String>,
+    pub external_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HistoryParams {
+    /// research, light, readwise, ...; "light" includes every browser
+    pub source: Option<String>,
+    pub limit: Option<i64>,
+}
+
+/// Where a source stands, as listed by `GET /sync/status`
+#[derive(Debug, Serialize)]
+pub struct SourceStatus {
+    pub source: String,
+    /// Whether this instance has settings for it; Light and imports need none
+    pub configured: bool,
+    pub running: bool,
+    /// When its last run finished
+    pub last_sync_at: Option<String>,
+    /// Its last finished run, with what it created, updated, deleted and failed on
+    pub last_run: Option<SyncRun>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ConflictParams {
+    pub source: Option<String>,
+    /// Include conflicts that were already resolved
+    #[serde(default)]
+    pub resolved: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ResolveRequest {
+    /// "local" keeps the edit made here, "source" takes the source's version
+    pub keep: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RunDiff {
+    pub run: SyncRun,
+    pub entries: Vec<DiffEntry>,
+}
+
+/// Replace a secret with a stable placeholder so archives can be diffed and shared
+/// without leaking credentials. Importing a placeholder keeps the existing secret.
+pub fn redact_secret(secret: &str) -> String {
+    let digest = hex::encode(Sha256::digest(secret.as_bytes()));
+    format!("{}{}", REDACTED_PREFIX, &digest[..12])
+}
+
+pub fn is_redacted(value: &str) -> bool {
+    value.starts_with(REDACTED_PREFIX)
+}
+
+pub async fn export_config(State(state): State<AppState>) -> Response {
+    let conn = &state.db.connection();
+
+    let (research, research_sources): (Vec<_>, Vec<_>) = match research::export_config(conn).await {
+        Ok(configs) => configs.into_iter().partition(|c| c.name == research::DEFAULT_SOURCE),
+        Err(e) => {
+            tracing::error!("Failed to export research config: {}", e);
+            return internal_error("Failed to export research config");
+        }
+    };
+
+    let zotero = match zotero::export_config(conn).await {
+        Ok(config) => config,
+        Err(e) => {
+            tracing::error!("Failed to export zotero config: {}", e);
+            return internal_error("Failed to export zotero config");
+        }
+    };
+
+    let kobo = match kobo::export_config(conn).await {
+        Ok(config) => config,
+        Err(e) => {
+            tracing::error!("Failed to export kobo config: {}", e);
+            return internal_error("Failed to export kobo config");
+        }
+    };
+
+    let readwise = match readwise::export_config(conn).await {
+        Ok(config) => config,
+        Err(e) => {
+            tracing::error!("Failed to export readwise config: {}", e);
+            return internal_error("Failed to export readwise config");
+        }
+    };
+
+    let pocket = match pocket::export_config(conn).await {
+        Ok(config) => config,
+        Err(e) => {
+            tracing::error!("Failed to export pocket config: {}", e);
+            return internal_error("Failed to export pocket config");
+        }
+    };
+
+    let feeds = match feeds::export_config(conn).await {
+        Ok(feeds) => feeds,
+        Err(e) => {
+            tracing::error!("Failed to export feeds: {}", e);
+            return internal_error("Failed to export feeds");
+        }
+    };
+
+    success(SyncConfigArchive {
+        version: CONFIG_ARCHIVE_VERSION,
+        exported_at: chrono::Utc::now().to_rfc3339(),
+        research: research.into_iter().next(),
+        research_sources,
+        zotero,
+        kobo,
+        readwise,
+        pocket,
+        feeds,
+        webhook: webhook::export_config(&state.webhook),
+    })
+}
+
+pub async fn import_config(State(state): State<AppState>, Json(archive): Json<SyncConfigArchive>) -> Response {
+    if archive.version > CONFIG_ARCHIVE_VERSION {
+        return bad_request(&format!(
+            "Unsupported archive version {} (max supported: {})",
+            archive.version, CONFIG_ARCHIVE_VERSION
+        ));
+    }
+
+    let conn = &state.db.connection();
+    let mut summary = ImportSummary::default();
+
+    match &archive.research {
+        Some(config) => {
+            let config = ResearchSourceConfig {
+                name: research::DEFAULT_SOURCE.to_string(),
+                ..config.clone()
+            };
+            if let Err(response) = import_research(conn, &config, "research", &mut summary).await {
+                return response;
+            }
+        }
+        None => summary.skipped.push("research".to_string()),
+    }
+    for config in &archive.research_sources {
+        let label = format!("research:{}", config.name);
+        if let Err(response) = import_research(conn, config, &label, &mut summary).await {
+            return response;
+        }
+    }
+
+    match &archive.zotero {
+        Some(config) => {
+            if let Err(e) = zotero::import_config(conn, config).await {
+                tracing::error!("Failed to import zotero config: {}", e);
+                return internal_error("Failed to import zotero config");
+            }
+            if !std::path::Path::new(&config.db_path).exists() {
+                summary
+                    .warnings
+                    .push(format!("zotero: database file does not exist at {}", config.db_path));
+            }
+            summary.imported.push("zotero".to_string());
+        }
+        None => summary.skipped.push("zotero".to_string()),
+    }
+
+    match &archive.kobo {
+        Some(config) => {
+            if let Err(e) = kobo::import_config(conn, config).await {
+                tracing::error!("Failed to import kobo config: {}", e);
+                return internal_error("Failed to import kobo config");
+            }
+            if !std::path::Path::new(&config.db_path).exists() {
+                summary
+                    .warnings
+                    .push(format!("kobo: database file does not exist at {}", config.db_path));
+            }
+            summary.imported.push("kobo".to_string());
+        }
+        None => summary.skipped.push("kobo".to_string()),
+    }
+
+    match &archive.readwise {
+        Some(config) => match readwise::import_config(conn, config).await {
+            Ok(true) => summary.imported.push("readwise".to_string()),
+            Ok(false) => {
+                summary.skipped.push("readwise".to_string());
+                summary
+                    .warnings
+                    .push("readwise: the token is redacted and none is set here; set it again".to_string());
+            }
+            Err(e) => {
+                tracing::error!("Failed to import readwise config: {}", e);
+                return internal_error("Failed to import readwise config");
+            }
+        },
+        None => summary.skipped.push("readwise".to_string()),
+    }
+
+    match &archive.pocket {
+        Some(config) => match pocket::import_config(conn, config).await {
+            Ok(true) => summary.imported.push("pocket".to_string()),
+            Ok(false) => {
+                summary.skipped.push("pocket".to_string());
+                summary
+                    .warnings
+                    .push("pocket: the access token is redacted and none is set here; set it again".to_string());
+            }
+            Err(e) => {
+                tracing::error!("Failed to import pocket config: {}", e);
+                return internal_error("Failed to import pocket config");
+            }
+        },
+        None => summary.skipped.push("pocket".to_string()),
+    }
+
+    for config in &archive.feeds {
+        let label = format!("feed:{}", config.url);
+        if !is_capturable(&config.url) {
+            summary.warnings.push(format!("{}: not an http(s) URL", label));
+            summary.skipped.push(label);
+            continue;
+        }
+        if let Err(e) = feeds::import_config(conn, config).await {
+            tracing::error!("Failed to import feed {}: {}", config.url, e);
+            return internal_error("Failed to import feeds");
+        }
+        summary.imported.push(label);
+    }
+
+    // Only the config file sets the webhook, so the archive's can't be applied here
+    match &archive.webhook {
+        Some(config) => {
+            summary.skipped.push("webhook".to_string());
+            if !webhook::matches(&state.webhook, config) {
+                let secret = if config.secret.is_some() {
+                    ", with its secret"
+                } else {
+                    ""
+                };
+                summary.warnings.push(format!(
+                    "webhook: set in the config file; set [webhook] url to {} there{}",
+                    config.url, secret
+                ));
+            }
+        }
+        None => summary.skipped.push("webhook".to_string()),
+    }
+
+    tracing::info!(imported = ?summary.imported, "imported sync config archive");
+    success(summary)
+}
+
+async fn import_research(
+    conn: &libsql::Connection,
+    config: &ResearchSourceConfig,
+    label: &str,
+    summary: &mut ImportSummary,
+) -> Result<(), Response> {
+    match research::import_config(conn, config).await {
+        Ok(true) => {}
+        Ok(false) => summary
+            .warnings
+            .push(format!("{}: the auth token is redacted and none is set here; set it again", label)),
+        Err(e) => {
+            tracing::error!("Failed to import {} config: {}", label, e);
+            return Err(internal_error(&format!("Failed to import {} config", label)));
+        }
+    }
+    if !research::is_remote(&config.db_path) && !std::path::Path::new(&config.db_path).exists() {
+        summary
+            .warnings
+            .push(format!("{}: database file does not exist at {}", label, config.db_path));
+    }
+    summary.imported.push(label.to_string());
+    Ok(())
+}
+
+pub async fn history(State(state): State<AppState>, Query(params): Query<HistoryParams>) -> Response {
+    let source = params.source.as_deref().map(str::trim).filter(|s| !s.is_empty());
+    let limit = params.limit.unwrap_or(DEFAULT_HISTORY_LIMIT).clamp(1, 500);
+    match runs::list_runs(&state.db.connection(), source, limit).await {
+        Ok(runs) => success(runs),
+        Err(e) => {
+            tracing::error!("Failed to list sync runs: {}", e);
+            internal_error("Failed to get sync history")
+        }
+    }
+}
+
+/// Sources this instance has settings for, under the names their runs are recorded as
+async fn configured_sources(state: &AppState) -> anyhow::Result<Vec<String>> {
+    let query = r#"
+        SELECT 'research:' || name FROM research_config
+        UNION ALL SELECT 'readwise' FROM readwise_config
+        UNION ALL SELECT 'zotero' FROM zotero_config
+        UNION ALL SELECT 'pocket' FROM pocket_config
+        UNION ALL SELECT 'kobo' FROM kobo_config
+    "#;
+    let mut rows = state.db.connection().query(query, ()).await?;
+    let mut sources = Vec::new();
+    while let Some(row) = rows.next().await? {
+        sources.push(row.get(0)?);
+    }
+    if state.wallabag.url.as_deref().is_some_and(|url| !url.trim().is_empty()) {
+        sources.push("wallabag".to_string());
+    }
+    Ok(sources)
+}
+
+/// Every configured source and every source that has synced, with its last run and
+/// whether it is syncing now
+pub async fn status(State(state): State<AppState>) -> Response {
+    let conn = &state.db.connection();
+    let result = async {
+        let configured = configured_sources(&state).await?;
+        let last_runs = runs::last_runs(conn).await?;
+        let running = runs::running_sources(conn).await?;
+        anyhow::Ok((configured, last_runs, running))
+    }
+    .await;
+    let (configured, last_runs, running) = match result {
+        Ok(result) => result,
+        Err(e) => {
+            tracing::error!("Failed to get sync status: {}", e);
+            return internal_error("Failed to get sync status");
+        }
+    };
+
+    let mut statuses: BTreeMap<String, SourceStatus> = BTreeMap::new();
+    let names = configured.iter().chain(running.iter()).cloned();
+    let names = names.chain(last_runs.iter().map(|run| run.source.clone()));
+    for source in names {
+        statuses.entry(source.clone()).or_insert_with(|| SourceStatus {
+            configured: configured.contains(&source),
+            running: running.contains(&source),
+            last_sync_at: None,
+            last_run: None,
+            source,
+        });
+    }
+    for run in last_runs {
+        if let Some(status) = statuses.get_mut(&run.source) {
+            status.last_sync_at = run.finished_at.clone();
+            status.last_run = Some(run);
+        }
+    }
+    success(statuses.into_values().collect::<Vec<_>>())
+}
+
+pub async fn rollback_run(State(state): State<AppState>, Path(id): Path<i64>) -> Response {
+    match runs::rollback_run(&state.db, id).await {
+        Ok(Rollback::Done(summary)) => {
+            tracing::info!(
+                run = id,
+                reverted = summary.reverted,
+                skipped = summary.skipped.len(),
+                "rolled back sync run"
+            );
+            success(summary)
+        }
+        Ok(Rollback::NotFound) => not_found("Sync run not found"),
+        Ok(Rollback::Running) => conflict("Sync run has not finished"),
+        Ok(Rollback::AlreadyRolledBack(at)) => conflict(&format!("Sync run was already rolled back at {}", at)),
+        Err(e) => {
+            tracing::error!("Failed to roll back sync run {}: {}", id, e);
+            internal_error("Failed to roll back sync run")
+        }
+    }
+}
+
+pub async fn get_run_diff(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    Query(params): Query<DiffParams>,
+) -> Response {
+    let entity = match params.entity.as_deref().map(Entity::from_str) {
+        Some(None) => return bad_request("entity must be one of resources, annotations, comments, notes"),
+        Some(entity) => entity,
+        None => None,
+    };
+    let conn = &state.db.connection();
+    let run = match runs::get_run(conn, id).await {
+        Ok(Some(run)) => run,
+        Ok(None) => return not_found("Sync run not found"),
+        Err(e) => {
+            tracing::error!("Failed to get sync run {}: {}", id, e);
+            return internal_error("Failed to get sync run");
+        }
+    };
+    match runs::list_diff(conn, id, entity, params.external_id.as_deref()).await {
+        Ok(entries) => success(RunDiff { run, entries }),
+        Err(e) => {
+            tracing::error!("Failed to list diff of sync run {}: {}", id, e);
+            internal_error("Failed to get sync run diff")
+        }
+    }
+}
+
+pub async fn list_conflicts(State(state): State<AppState>, Query(params): Query<ConflictParams>) -> Response {
+    let source = params.source.as_deref().map(str::trim).filter(|s| !s.is_empty());
+    match conflicts::list_conflicts(&state.db.connection(), source, params.resolved).await {
+        Ok(conflicts) => success(conflicts),
+        Err(e) => {
+            tracing::error!("Failed to list sync conflicts: {}", e);
+            internal_error("Failed to get sync conflicts")
+        }
+    }
+}
+
+/// Settles a conflict. Keeping the local edit takes the source's version as synced,
+/// so it only comes back if the source changes again.
+pub async fn resolve_conflict(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    Json(payload): Json<ResolveRequest>,
+) -> Response {
+    let resolution = match payload.keep.as_str() {
+        "local" => Resolution::Local,
+        "source" => Resolution::Source,
+        _ => return bad_request("keep must be local or source"),
+    };
+    let conn = &state.db.connection();
+    let pending = match conflicts::get_conflict(conn, id).await {
+        Ok(Some(found)) => match found.resolved_at {
+            Some(at) => return conflict(&format!("Sync conflict was already resolved at {}", at)),
+            None => found,
+        },
+        Ok(None) => return not_found("Sync conflict not found"),
+        Err(e) => {
+            tracing::error!("Failed to get sync conflict {}: {}", id, e);
+            return internal_error("Failed to get sync conflict");
+        }
+    };
+
+    if let Err(e) = apply_resolution(&state, &pending, resolution).await {
+        tracing::error!("Failed to resolve sync conflict {}: {}", id, e);
+        return internal_error("Failed to resolve sync conflict");
+    }
+    if let Err(e) = conflicts::mark_resolved(conn, id, resolution).await {
+        tracing::error!("Failed to mark sync conflict {} resolved: {}", id, e);
+        return internal_error("Failed to resolve sync conflict");
+    }
+    match conflicts::get_conflict(conn, id).await {
+        Ok(Some(resolved)) => success(resolved),
+        Ok(None) => not_found("Sync conflict not found"),
+        Err(e) => {
+            tracing::error!("Failed to get sync conflict {}: {}", id, e);
+            internal_error("Failed to get sync conflict")
+        }
+    }
+}
+
+/// Writes the chosen side to the entity, with the source's hash either way. An entity
+/// deleted since has nothing left to resolve.
+async fn apply_resolution(state: &AppState, conflict: &SyncConflict, resolution: Resolution) -> anyhow::Result<()> {
+    let lib = state.db.commonplace();
+    let id = conflict.entity_id as i32;
+    let hash = conflict.incoming_hash.clone();
+    let Some(entity) = Entity::from_str(&conflict.entity) else {
+        anyhow::bail!("unknown entity {}", conflict.entity);
+    };
+    if resolution == Resolution::Local {
+        return lib.set_content_hash(entity, id, &hash).await;
+    }
+
+    let incoming = &conflict.incoming;
+    let content = || incoming["content"].as_str().unwrap_or_default().to_string();
+    match entity {
+        Entity::Annotation => {
+            let update = UpdateAnnotation {
+                text: incoming["text"].as_str().map(str::to_string),
+                color: incoming["color"].as_str().map(str::to_string),
+                boundary: Some(incoming["boundary"].clone()).filter(|boundary| !boundary.is_null()),
+                content_hash: Some(hash),
+            };
+            lib.update_annotation(id, update).await?;
+        }
+        Entity::Comment => {
+            let update = UpdateComment {
+                content: content(),
+                content_hash: Some(hash),
+            };
+            lib.update_comment(id, update).await?;
+        }
+        Entity::Note => {
+            let update = UpdateNote {
+                content: content(),
+                content_hash: Some(hash),
+            };
+            lib.update_note(id, update).await?;
+        }
+        Entity::Resource => anyhow::bail!("resources are not synced with conflicts"),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{read_json, test_state};
+
+    async fn secret(state: &AppState, query: &str) -> Option<String> {
+        let mut rows = state.db.connection().query(query, ()).await.unwrap();
+        rows.next().await.unwrap().and_then(|row| row.get(0).unwrap())
+    }
+
+    async fn secrets(state: &AppState) -> [Option<String>; 3] {
+        [
+            secret(state, "SELECT auth_token FROM research_config").await,
+            secret(state, "SELECT token FROM readwise_config").await,
+            secret(state, "SELECT access_token FROM pocket_config").await,
+        ]
+    }
+
+    #[tokio::test]
+    async fn test_archive_redacts_secrets_and_import_keeps_them() {
+        let state = test_state().await;
+        let conn = &state.db.connection();
+        let research = ResearchSourceConfig {
+            name: research::DEFAULT_SOURCE.to_string(),
+            db_path: "libsql://research-me.turso.io".to_string(),
+            auth_token: Some("research-secret".to_string()),
+            last_sync_at: None,
+        };
+        research::import_config(conn, &research).await.unwrap();
+        let readwise = ReadwiseSourceConfig {
+            token: "readwise-secret".to_string(),
+            last_sync_at: None,
+        };
+        readwise::import_config(conn, &readwise).await.unwrap();
+        let pocket = PocketSourceConfig {
+            consumer_key: "1234-abcd".to_string(),
+            access_token: "pocket-secret".to_string(),
+            since: Some(1760000000),
+            last_sync_at: None,
+        };
+        pocket::import_config(conn, &pocket).await.unwrap();
+
+        let (status, body) = read_json(export_config(State(state.clone())).await).await;
+        assert_eq!(status, axum::http::StatusCode::OK);
+        assert!(!body.to_string().contains("-secret"));
+        let archive: SyncConfigArchive = serde_json::from_value(body["data"].clone()).unwrap();
+        assert!(
+            archive
+                .research
+                .as_ref()
+                .unwrap()
+                .auth_token
+                .as_deref()
+                .is_some_and(is_redacted)
+        );
+        assert!(is_redacted(&archive.readwise.as_ref().unwrap().token));
+        assert!(is_redacted(&archive.pocket.as_ref().unwrap().access_token));
+
+        // Imported back, the placeholders don't overwrite the secrets
+        let (_, body) = read_json(import_config(State(state.clone()), Json(archive)).await).await;
+        for source in ["research", "readwise", "pocket"] {
+            assert!(body["data"]["imported"].as_array().unwrap().contains(&source.into()));
+        }
+        let kept = secrets(&state).await.map(Option::unwrap);
+        assert_eq!(kept, ["research-secret", "readwise-secret", "pocket-secret"]);
+
+        // and on a new instance there is nothing to keep, so the tokens are set again
+        let (_, body) = read_json(export_config(State(state.clone())).await).await;
+        let archive: SyncConfigArchive = serde_json::from_value(body["data"].clone()).unwrap();
+        let fresh = test_state().await;
+        let (_, body) = read_json(import_config(State(fresh.clone()), Json(archive)).await).await;
+        assert_eq!(body["data"]["skipped"], serde_json::json!(["zotero", "kobo", "readwise", "pocket", "webhook"]));
+        assert_eq!(body["data"]["warnings"].as_array().unwrap().len(), 3);
+        assert_eq!(secrets(&fresh).await, [None, None, None]);
+    }
+
+    #[tokio::test]
+    async fn test_redacted_research_token_is_reported_when_none_is_set() {
+        let archive = SyncConfigArchive {
+            version: CONFIG_ARCHIVE_VERSION,
+            exported_at: chrono::Utc::now().to_rfc3339(),
+            research: Some(ResearchSourceConfig {
+                name: research::DEFAULT_SOURCE.to_string(),
+                db_path: "libsql://research-me.turso.io".to_string(),
+                auth_token: Some(redact_secret("research-secret")),
+                last_sync_at: None,
+            }),
+            research_sources: vec![],
+            zotero: None,
+            kobo: None,
+            readwise: None,
+            pocket: None,
+            feeds: vec![],
+            webhook: None,
+        };
+        let state = test_state().await;
+
+        let (status, body) = read_json(import_config(State(state.clone()), Json(archive)).await).await;
+        assert_eq!(status, axum::http::StatusCode::OK);
+        assert_eq!(body["data"]["imported"], serde_json::json!(["research"]));
+        assert_eq!(
+            body["data"]["warnings"],
+            serde_json::json!(["research: the auth token is redacted and none is set here; set it again"])
+        );
+        // The placeholder is never stored as the token
+        assert_eq!(secrets(&state).await, [None, None, None]);
+        let path = secret(&state, "SELECT db_path FROM research_config").await;
+        assert_eq!(path.as_deref(), Some("libsql://research-me.turso.io"));
+    }
+
+    #[tokio::test]
+    async fn test_archive_redacts_the_webhook_secret() {
+        let mut state = test_state().await;
+        state.webhook = std::sync::Arc::new(crate::config::Webhook {
+            url: Some("https://hooks.example.com/bibliotek".to_string()),
+            secret: Some("hook-secret".to_string()),
+            sources: vec!["light".to_string()],
+        });
+
+        let (_, body) = read_json(export_config(State(state.clone())).await).await;
+        assert!(!body.to_string().contains("hook-secret"));
+        let archive: SyncConfigArchive = serde_json::from_value(body["data"].clone()).unwrap();
+        let webhook = archive.webhook.as_ref().unwrap();
+        assert_eq!(webhook.url, "https://hooks.example.com/bibliotek");
+        assert!(webhook.secret.as_deref().is_some_and(is_redacted));
+
+        // Back on the same instance, the webhook is already the one set here
+        let (_, body) = read_json(import_config(State(state.clone()), Json(archive)).await).await;
+        assert!(body["data"]["skipped"].as_array().unwrap().contains(&"webhook".into()));
+        assert_eq!(body["data"]["warnings"], serde_json::json!([]));
+
+        // Elsewhere it has to be set in the config file, secret included
+        let (_, body) = read_json(export_config(State(state.clone())).await).await;
+        let archive: SyncConfigArchive = serde_json::from_value(body["data"].clone()).unwrap();
+        let fresh = test_state().await;
+        let (_, body) = read_json(import_config(State(fresh), Json(archive)).await).await;
+        assert_eq!(
+            body["data"]["warnings"],
+            serde_json::json!([
+                "webhook: set in the config file; set [webhook] url to https://hooks.example.com/bibliotek there, with its secret"
+            ])
+        );
+    }
+}
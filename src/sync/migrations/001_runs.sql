@@ -0,0 +1,32 @@
+-- Sync run history and change journal
+-- Every write a sync makes to the commonplace tables is journaled against its run,
+-- with a snapshot of the row as it was before updates and deletions, so the run can
+-- be rolled back.
+
+CREATE TABLE IF NOT EXISTS sync_runs (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    source TEXT NOT NULL, -- e.g. "research", "light:chrome"
+    status TEXT NOT NULL DEFAULT 'running' CHECK (status IN ('running', 'completed')),
+    created INTEGER NOT NULL DEFAULT 0,
+    updated INTEGER NOT NULL DEFAULT 0,
+    deleted INTEGER NOT NULL DEFAULT 0,
+    unchanged INTEGER NOT NULL DEFAULT 0,
+    started_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    finished_at TEXT,
+    rolled_back_at TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_sync_runs_source ON sync_runs(source, started_at);
+
+CREATE TABLE IF NOT EXISTS sync_changes (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    run_id INTEGER NOT NULL REFERENCES sync_runs(id) ON DELETE CASCADE,
+    entity TEXT NOT NULL CHECK (entity IN ('resources', 'annotations', 'comments', 'notes')),
+    entity_id INTEGER NOT NULL,
+    action TEXT NOT NULL CHECK (action IN ('created', 'updated', 'deleted')),
+    previous TEXT, -- JSON snapshot of the row before an update or deletion
+    created_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))
+);
+
+CREATE INDEX IF NOT EXISTS idx_sync_changes_run_id ON sync_changes(run_id);
+CREATE INDEX IF NOT EXISTS idx_sync_changes_entity ON sync_changes(entity, entity_id);
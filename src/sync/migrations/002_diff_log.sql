@@ -0,0 +1,19 @@
+-- Sync diff log
+-- With `app.sync_diff_log` on, each finished run also leaves one compact row per
+-- entity it touched: the content hash before and after, keyed by external id, so a
+-- change can be traced across runs long after the journal's snapshots moved on.
+
+CREATE TABLE IF NOT EXISTS sync_diffs (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    run_id INTEGER NOT NULL REFERENCES sync_runs(id) ON DELETE CASCADE,
+    entity TEXT NOT NULL CHECK (entity IN ('resources', 'annotations', 'comments', 'notes')),
+    entity_id INTEGER NOT NULL,
+    external_id TEXT,
+    action TEXT NOT NULL CHECK (action IN ('created', 'updated', 'deleted')),
+    old_hash TEXT,
+    new_hash TEXT,
+    created_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))
+);
+
+CREATE INDEX IF NOT EXISTS idx_sync_diffs_run_id ON sync_diffs(run_id);
+CREATE INDEX IF NOT EXISTS idx_sync_diffs_external_id ON sync_diffs(entity, external_id);
@@ -0,0 +1,2 @@
+-- Number of entities a run failed to sync, so failing syncs show up in the history
+ALTER TABLE sync_runs ADD COLUMN errors INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,26 @@
+-- Sync conflicts
+-- An annotation, comment or note edited here and changed in its source since the last
+-- sync, with the `manual` conflict policy. Both versions are kept until it is resolved;
+-- the entity is left as it was edited here in the meantime.
+
+CREATE TABLE IF NOT EXISTS sync_conflicts (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    source TEXT NOT NULL,
+    run_id INTEGER REFERENCES sync_runs(id) ON DELETE SET NULL,
+    entity TEXT NOT NULL CHECK (entity IN ('annotations', 'comments', 'notes')),
+    entity_id INTEGER NOT NULL,
+    external_id TEXT NOT NULL,
+    -- JSON of the fields as edited here, and as the source has them
+    local TEXT NOT NULL,
+    incoming TEXT NOT NULL,
+    -- Content hash of the source's version, which the entity takes once resolved
+    incoming_hash TEXT NOT NULL,
+    resolution TEXT CHECK (resolution IN ('local', 'source')),
+    created_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    updated_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    resolved_at TEXT
+);
+
+-- One open conflict per entity; later syncs refresh its incoming side
+CREATE UNIQUE INDEX IF NOT EXISTS idx_sync_conflicts_open ON sync_conflicts(entity, entity_id) WHERE resolved_at IS NULL;
+CREATE INDEX IF NOT EXISTS idx_sync_conflicts_source ON sync_conflicts(source);
@@ -0,0 +1,25 @@
+-- Runs the process stopped in the middle of are marked interrupted on startup, so a
+-- source does not look like it is still syncing. SQLite cannot change a CHECK
+-- constraint in place, so the table is rebuilt.
+
+CREATE TABLE sync_runs_new (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    source TEXT NOT NULL, -- e.g. "research:default", "light:chrome"
+    status TEXT NOT NULL DEFAULT 'running' CHECK (status IN ('running', 'completed', 'interrupted')),
+    created INTEGER NOT NULL DEFAULT 0,
+    updated INTEGER NOT NULL DEFAULT 0,
+    deleted INTEGER NOT NULL DEFAULT 0,
+    unchanged INTEGER NOT NULL DEFAULT 0,
+    started_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    finished_at TEXT,
+    rolled_back_at TEXT,
+    errors INTEGER NOT NULL DEFAULT 0
+);
+
+INSERT INTO sync_runs_new (id, source, status, created, updated, deleted, unchanged, started_at, finished_at, rolled_back_at, errors)
+SELECT id, source, status, created, updated, deleted, unchanged, started_at, finished_at, rolled_back_at, errors FROM sync_runs;
+
+DROP TABLE sync_runs;
+ALTER TABLE sync_runs_new RENAME TO sync_runs;
+
+CREATE INDEX IF NOT EXISTS idx_sync_runs_source ON sync_runs(source, started_at);
@@ -0,0 +1,23 @@
+CREATE TABLE sync_runs_old (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    source TEXT NOT NULL,
+    status TEXT NOT NULL DEFAULT 'running' CHECK (status IN ('running', 'completed')),
+    created INTEGER NOT NULL DEFAULT 0,
+    updated INTEGER NOT NULL DEFAULT 0,
+    deleted INTEGER NOT NULL DEFAULT 0,
+    unchanged INTEGER NOT NULL DEFAULT 0,
+    started_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    finished_at TEXT,
+    rolled_back_at TEXT,
+    errors INTEGER NOT NULL DEFAULT 0
+);
+
+INSERT INTO sync_runs_old (id, source, status, created, updated, deleted, unchanged, started_at, finished_at, rolled_back_at, errors)
+SELECT id, source, CASE status WHEN 'interrupted' THEN 'completed' ELSE status END,
+       created, updated, deleted, unchanged, started_at, finished_at, rolled_back_at, errors
+FROM sync_runs;
+
+DROP TABLE sync_runs;
+ALTER TABLE sync_runs_old RENAME TO sync_runs;
+
+CREATE INDEX IF NOT EXISTS idx_sync_runs_source ON sync_runs(source, started_at);
@@ -1,10 +1,42 @@
+pub mod conflicts;
+pub mod engine;
+mod handler;
+mod routes;
+pub mod runs;
+
 use std::collections::HashSet;
 use std::future::Future;
 
+pub use engine::{Deleted, ResourceKey, SourceAnnotation, SourceComment, SourceNote, SourceResource, SyncSource};
+pub use handler::{CONFIG_ARCHIVE_VERSION, SyncConfigArchive, is_redacted, redact_secret};
+pub use routes::routes;
+
+pub fn migrations() -> &'static [(&'static str, &'static str)] {
+    &[
+        ("sync_001_runs.sql", include_str!("migrations/001_runs.sql")),
+        ("sync_002_diff_log.sql", include_str!("migrations/002_diff_log.sql")),
+        ("sync_003_history.sql", include_str!("migrations/003_history.sql")),
+        ("sync_004_conflicts.sql", include_str!("migrations/004_conflicts.sql")),
+        ("sync_005_interrupted.sql", include_str!("migrations/005_interrupted.sql")),
+    ]
+}
+
+pub fn down_migrations() -> &'static [(&'static str, &'static str)] {
+    &[
+        ("sync_001_runs.sql", include_str!("migrations/down/001_runs.sql")),
+        ("sync_002_diff_log.sql", include_str!("migrations/down/002_diff_log.sql")),
+        ("sync_003_history.sql", include_str!("migrations/down/003_history.sql")),
+        ("sync_004_conflicts.sql", include_str!("migrations/down/004_conflicts.sql")),
+        ("sync_005_interrupted.sql", include_str!("migrations/down/005_interrupted.sql")),
+    ]
+}
+
 pub enum SyncResult<T> {
     Created(T),
     Updated(T),
     Unchanged(T),
+    /// Edited here and in the source; left to the conflict policy
+    Conflict(T),
     Error,
 }
 
@@ -23,7 +55,14 @@ impl<T: Copy> SyncResult<T> {
                 stats.unchanged += 1;
                 Some(id)
             }
-            SyncResult::Error => None,
+            SyncResult::Conflict(id) => {
+                stats.conflicts += 1;
+                Some(id)
+            }
+            SyncResult::Error => {
+                stats.errors += 1;
+                None
+            }
         }
     }
 }
@@ -34,7 +73,8 @@ impl SyncResult<()> {
             SyncResult::Created(()) => stats.created += 1,
             SyncResult::Updated(()) => stats.updated += 1,
             SyncResult::Unchanged(()) => stats.unchanged += 1,
-            SyncResult::Error => {}
+            SyncResult::Conflict(()) => stats.conflicts += 1,
+            SyncResult::Error => stats.errors += 1,
         }
     }
 }
@@ -45,6 +85,10 @@ pub struct SyncStats {
     pub updated: i32,
     pub deleted: i32,
     pub unchanged: i32,
+    /// Entities edited both here and in the source since the last sync
+    pub conflicts: i32,
+    /// Entities that failed to sync and were skipped
+    pub errors: i32,
 }
 
 pub trait Syncable {
@@ -58,7 +102,7 @@ pub fn is_unchanged<T: Syncable>(existing: &T, new_hash: &str) -> bool {
 }
 
 pub fn is_orphan(external_id: &Option<String>, seen: &HashSet<String>) -> bool {
-    external_id.as_ref().map_or(false, |id| !seen.contains(id))
+    external_id.as_ref().is_some_and(|id| !seen.contains(id))
 }
 
 pub fn log_find_error(entity: &str, external_id: &str, e: impl std::fmt::Display) {
@@ -165,15 +209,22 @@ pub async fn delete_orphans<T, I, F, Fut, D, DFut>(
         Ok(items) => items,
         Err(e) => {
             tracing::error!("Failed to find orphan {}s: {}", entity, e);
+            stats.errors += 1;
             return;
         }
     };
 
     for item in items {
         let ext_id = item.external_id().map(|s| s.to_string());
-        if is_orphan(&ext_id, seen) {
-            if delete_fn(item.id()).await.unwrap_or(false) {
-                stats.deleted += 1;
+        if !is_orphan(&ext_id, seen) {
+            continue;
+        }
+        match delete_fn(item.id()).await {
+            Ok(true) => stats.deleted += 1,
+            Ok(false) => {}
+            Err(e) => {
+                tracing::error!("Failed to delete orphan {} {}: {}", entity, item.id(), e);
+                stats.errors += 1;
             }
         }
     }
@@ -0,0 +1,19 @@
+use axum::{
+    Router,
+    routing::{get, post},
+};
+
+use super::handler;
+use crate::handler::AppState;
+
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/config/export", get(handler::export_config))
+        .route("/config/import", post(handler::import_config))
+        .route("/history", get(handler::history))
+        .route("/status", get(handler::status))
+        .route("/conflicts", get(handler::list_conflicts))
+        .route("/conflicts/:id/resolve", post(handler::resolve_conflict))
+        .route("/runs/:id/rollback", post(handler::rollback_run))
+        .route("/runs/:id/diff", get(handler::get_run_diff))
+}
@@ -0,0 +1,641 @@
+//! Sync run history and change journal. A sync opens a run, and every commonplace
+//! row it creates, updates or deletes is journaled against it (see
+//! `Commonplace::with_sync_run`), with a snapshot of the row as it was before updates
+//! and deletions. The run itself keeps the counts of what the sync did, including the
+//! entities it failed on, and is listed by `GET /sync/history`. `POST /sync/runs/:id/rollback` replays a run's journal backwards:
+//! rows it created are soft-deleted and rows it changed get their snapshot back.
+//!
+//! With `app.sync_diff_log` on, a finished run's journal is also condensed into the
+//! diff log (`GET /sync/runs/:id/diff`): one row per entity the run touched, keyed by
+//! external id, with its content hash before and after. It outlives the snapshots'
+//! usefulness for rollback and answers when a highlight's text changed, and to what.
+
+use std::collections::HashMap;
+
+use anyhow::Result;
+use libsql::Connection;
+use serde::Serialize;
+
+use super::SyncStats;
+use crate::db::Database;
+
+/// Commonplace tables a sync writes to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Entity {
+    Resource,
+    Annotation,
+    Comment,
+    Note,
+}
+
+impl Entity {
+    pub fn table(&self) -> &'static str {
+        match self {
+            Entity::Resource => "resources",
+            Entity::Annotation => "annotations",
+            Entity::Comment => "comments",
+            Entity::Note => "notes",
+        }
+    }
+
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "resources" => Some(Entity::Resource),
+            "annotations" => Some(Entity::Annotation),
+            "comments" => Some(Entity::Comment),
+            "notes" => Some(Entity::Note),
+            _ => None,
+        }
+    }
+
+    /// Columns a sync can change, which are the ones snapshotted and restored
+    fn columns(&self) -> &'static [&'static str] {
+        match self {
+            Entity::Resource => &["title", "type", "content_hash", "config", "deleted_at", "updated_at"],
+            Entity::Annotation => &["text", "color", "boundary", "content_hash", "deleted_at", "updated_at"],
+            Entity::Comment | Entity::Note => &["content", "content_hash", "deleted_at", "updated_at"],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+    Created,
+    Updated,
+    Deleted,
+}
+
+impl Change {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Change::Created => "created",
+            Change::Updated => "updated",
+            Change::Deleted => "deleted",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SyncRun {
+    pub id: i64,
+    pub source: String,
+    pub status: String,
+    pub created: i64,
+    pub updated: i64,
+    pub deleted: i64,
+    pub unchanged: i64,
+    pub errors: i64,
+    pub started_at: String,
+    pub finished_at: Option<String>,
+    /// How long the run took, once it finished
+    pub duration_ms: Option<i64>,
+    pub rolled_back_at: Option<String>,
+}
+
+/// A journaled change that was left alone because a later run touched the same row
+#[derive(Debug, Clone, Serialize)]
+pub struct SkippedChange {
+    pub entity: String,
+    pub entity_id: i64,
+    pub action: String,
+    pub superseded_by: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RollbackSummary {
+    pub run: SyncRun,
+    pub reverted: i64,
+    pub skipped: Vec<SkippedChange>,
+}
+
+/// An entity's net change in one run, as kept in the diff log
+#[derive(Debug, Clone, Serialize)]
+pub struct DiffEntry {
+    pub entity: String,
+    pub entity_id: i64,
+    pub external_id: Option<String>,
+    pub action: String,
+    pub old_hash: Option<String>,
+    pub new_hash: Option<String>,
+    pub created_at: String,
+}
+
+pub enum Rollback {
+    NotFound,
+    Running,
+    AlreadyRolledBack(String),
+    Done(RollbackSummary),
+}
+
+pub async fn start_run(conn: &Connection, source: &str) -> Result<i64> {
+    let mut rows = conn
+        .query("INSERT INTO sync_runs (source) VALUES (?) RETURNING id", libsql::params![source])
+        .await?;
+    match rows.next().await? {
+        Some(row) => Ok(row.get(0)?),
+        None => anyhow::bail!("Failed to start sync run"),
+    }
+}
+
+pub async fn finish_run(conn: &Connection, id: i64, stats: &SyncStats) -> Result<()> {
+    let query = r#"
+        UPDATE sync_runs
+        SET status = 'completed', created = ?, updated = ?, deleted = ?, unchanged = ?, errors = ?,
+            finished_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
+        WHERE id = ?
+    "#;
+    let params = libsql::params![
+        stats.created,
+        stats.updated,
+        stats.deleted,
+        stats.unchanged,
+        stats.errors,
+        id
+    ];
+    conn.execute(query, params).await?;
+    Ok(())
+}
+
+const RUN_COLUMNS: &str = r#"
+    id, source, status, created, updated, deleted, unchanged, errors, started_at, finished_at,
+    CAST(ROUND((julianday(finished_at) - julianday(started_at)) * 86400000) AS INTEGER), rolled_back_at
+"#;
+
+fn row_to_run(row: &libsql::Row) -> Result<SyncRun> {
+    Ok(SyncRun {
+        id: row.get(0)?,
+        source: row.get(1)?,
+        status: row.get(2)?,
+        created: row.get(3)?,
+        updated: row.get(4)?,
+        deleted: row.get(5)?,
+        unchanged: row.get(6)?,
+        errors: row.get(7)?,
+        started_at: row.get(8)?,
+        finished_at: row.get(9)?,
+        duration_ms: row.get(10)?,
+        rolled_back_at: row.get(11)?,
+    })
+}
+
+pub async fn get_run(conn: &Connection, id: i64) -> Result<Option<SyncRun>> {
+    let query = format!("SELECT {} FROM sync_runs WHERE id = ?", RUN_COLUMNS);
+    let mut rows = conn.query(&query, libsql::params![id]).await?;
+    match rows.next().await? {
+        Some(row) => Ok(Some(row_to_run(&row)?)),
+        None => Ok(None),
+    }
+}
+
+/// The latest `limit` runs, newest first. A source also matches its sub-sources, so
+/// "light" lists the runs of every browser ("light:chrome", ...).
+pub async fn list_runs(conn: &Connection, source: Option<&str>, limit: i64) -> Result<Vec<SyncRun>> {
+    let mut query = format!("SELECT {} FROM sync_runs", RUN_COLUMNS);
+    let mut params: Vec<libsql::Value> = Vec::new();
+    if let Some(source) = source {
+        query.push_str(" WHERE source = ? OR source LIKE ?");
+        params.push(source.into());
+        params.push(format!("{}:%", source).into());
+    }
+    query.push_str(" ORDER BY started_at DESC, id DESC LIMIT ?");
+    params.push(limit.into());
+
+    let mut rows = conn.query(&query, params).await?;
+    let mut runs = Vec::new();
+    while let Some(row) = rows.next().await? {
+        runs.push(row_to_run(&row)?);
+    }
+    Ok(runs)
+}
+
+/// The last finished run of each source, interrupted ones included
+pub async fn last_runs(conn: &Connection) -> Result<Vec<SyncRun>> {
+    let query = format!(
+        r#"
+        SELECT {} FROM sync_runs
+        WHERE id IN (SELECT MAX(id) FROM sync_runs WHERE finished_at IS NOT NULL GROUP BY source)
+        ORDER BY source
+        "#,
+        RUN_COLUMNS
+    );
+    let mut rows = conn.query(&query, ()).await?;
+    let mut runs = Vec::new();
+    while let Some(row) = rows.next().await? {
+        runs.push(row_to_run(&row)?);
+    }
+    Ok(runs)
+}
+
+/// Sources with a run that has not finished
+pub async fn running_sources(conn: &Connection) -> Result<Vec<String>> {
+    let mut rows = conn
+        .query("SELECT DISTINCT source FROM sync_runs WHERE status = 'running'", ())
+        .await?;
+    let mut sources = Vec::new();
+    while let Some(row) = rows.next().await? {
+        sources.push(row.get(0)?);
+    }
+    Ok(sources)
+}
+
+/// Marks the runs the process stopped in the middle of as interrupted. What they
+/// wrote is journaled, so they can still be rolled back.
+pub async fn mark_interrupted(conn: &Connection) -> Result<u64> {
+    let query = r#"
+        UPDATE sync_runs
+        SET status = 'interrupted', finished_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
+        WHERE status = 'running'
+    "#;
+    Ok(conn.execute(query, ()).await?)
+}
+
+/// Journals a change to `entity` row `id`. Updates and deletions must be recorded
+/// before they are made so the snapshot holds the old values.
+pub async fn record_change(conn: &Connection, run_id: i64, entity: Entity, id: i32, change: Change) -> Result<()> {
+    let previous = match change {
+        Change::Created => "NULL".to_string(),
+        Change::Updated | Change::Deleted => {
+            let fields: Vec<String> = entity.columns().iter().map(|c| format!("'{0}', {0}", c)).collect();
+            format!("json_object({})", fields.join(", "))
+        }
+    };
+    let query = format!(
+        "INSERT INTO sync_changes (run_id, entity, entity_id, action, previous) SELECT ?, ?, id, ?, {} FROM {} WHERE id = ?",
+        previous,
+        entity.table()
+    );
+    conn.execute(&query, libsql::params![run_id, entity.table(), change.as_str(), id])
+        .await?;
+    Ok(())
+}
+
+/// Writes run `id`'s diff log from its journal. Each entity gets the hash it had
+/// before the run's first change to it and the one the run left it with; a row the
+/// run created stays "created" through later edits. Call it once the run finished.
+pub async fn record_diff(conn: &Connection, id: i64) -> Result<usize> {
+    let query = r#"
+        SELECT entity, entity_id, action, json_extract(previous, '$.content_hash')
+        FROM sync_changes WHERE run_id = ?
+        ORDER BY id
+    "#;
+    let mut rows = conn.query(query, libsql::params![id]).await?;
+    let mut touched: Vec<(Entity, i64, String, Option<String>)> = Vec::new();
+    let mut seen: HashMap<(&'static str, i64), usize> = HashMap::new();
+    while let Some(row) = rows.next().await? {
+        let entity: String = row.get(0)?;
+        let entity = Entity::from_str(&entity).ok_or_else(|| anyhow::anyhow!("Invalid journal entity: {}", entity))?;
+        let entity_id: i64 = row.get(1)?;
+        let action: String = row.get(2)?;
+        match seen.get(&(entity.table(), entity_id)) {
+            Some(&i) => {
+                if touched[i].2 != Change::Created.as_str() || action == Change::Deleted.as_str() {
+                    touched[i].2 = action;
+                }
+            }
+            None => {
+                seen.insert((entity.table(), entity_id), touched.len());
+                touched.push((entity, entity_id, action, row.get(3)?));
+            }
+        }
+    }
+
+    conn.execute("DELETE FROM sync_diffs WHERE run_id = ?", libsql::params![id])
+        .await?;
+    for (entity, entity_id, action, old_hash) in &touched {
+        let query = format!(
+            r#"
+            INSERT INTO sync_diffs (run_id, entity, entity_id, external_id, action, old_hash, new_hash)
+            SELECT ?1, ?2, id, external_id, ?3, ?4, CASE WHEN ?3 = 'deleted' THEN NULL ELSE content_hash END
+            FROM {} WHERE id = ?5
+            "#,
+            entity.table()
+        );
+        conn.execute(&query, libsql::params![id, entity.table(), action.as_str(), old_hash.clone(), *entity_id])
+            .await?;
+    }
+    Ok(touched.len())
+}
+
+/// Run `id`'s diff log, optionally narrowed to one entity type or external id
+pub async fn list_diff(
+    conn: &Connection,
+    id: i64,
+    entity: Option<Entity>,
+    external_id: Option<&str>,
+) -> Result<Vec<DiffEntry>> {
+    let mut query = String::from(
+        "SELECT entity, entity_id, external_id, action, old_hash, new_hash, created_at FROM sync_diffs WHERE run_id = ?",
+    );
+    let mut params: Vec<libsql::Value> = vec![id.into()];
+    if let Some(entity) = entity {
+        query.push_str(" AND entity = ?");
+        params.push(entity.table().into());
+    }
+    if let Some(external_id) = external_id {
+        query.push_str(" AND external_id = ?");
+        params.push(external_id.into());
+    }
+    query.push_str(" ORDER BY id");
+
+    let mut rows = conn.query(&query, params).await?;
+    let mut entries = Vec::new();
+    while let Some(row) = rows.next().await? {
+        entries.push(DiffEntry {
+            entity: row.get(0)?,
+            entity_id: row.get(1)?,
+            external_id: row.get(2)?,
+            action: row.get(3)?,
+            old_hash: row.get(4)?,
+            new_hash: row.get(5)?,
+            created_at: row.get(6)?,
+        });
+    }
+    Ok(entries)
+}
+
+/// Reverts everything run `id` did, newest change first, in one transaction. Rows a
+/// later run (that has not itself been rolled back) also changed are skipped, since
+/// restoring them would undo that run as well.
+pub async fn rollback_run(db: &Database, id: i64) -> Result<Rollback> {
+    let conn = &db.connection();
+    let Some(run) = get_run(conn, id).await? else {
+        return Ok(Rollback::NotFound);
+    };
+    if let Some(at) = run.rolled_back_at {
+        return Ok(Rollback::AlreadyRolledBack(at));
+    }
+    if run.finished_at.is_none() {
+        return Ok(Rollback::Running);
+    }
+
+    let (reverted, skipped) = db.write(|| revert_changes(conn, id)).await?;
+    let run = get_run(conn, id)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Sync run {} vanished", id))?;
+    Ok(Rollback::Done(RollbackSummary { run, reverted, skipped }))
+}
+
+struct JournalEntry {
+    entity: Entity,
+    entity_id: i64,
+    action: String,
+    previous: Option<String>,
+}
+
+async fn revert_changes(conn: &Connection, run_id: i64) -> Result<(i64, Vec<SkippedChange>)> {
+    let query = r#"
+        SELECT entity, entity_id, action, previous
+        FROM sync_changes WHERE run_id = ?
+        ORDER BY id DESC
+    "#;
+    let mut rows = conn.query(query, libsql::params![run_id]).await?;
+    let mut entries = Vec::new();
+    while let Some(row) = rows.next().await? {
+        let entity: String = row.get(0)?;
+        entries.push(JournalEntry {
+            entity: Entity::from_str(&entity).ok_or_else(|| anyhow::anyhow!("Invalid journal entity: {}", entity))?,
+            entity_id: row.get(1)?,
+            action: row.get(2)?,
+            previous: row.get(3)?,
+        });
+    }
+
+    let mut reverted = 0;
+    let mut skipped = Vec::new();
+    for entry in entries {
+        if let Some(later_run) = superseded_by(conn, run_id, &entry).await? {
+            skipped.push(SkippedChange {
+                entity: entry.entity.table().to_string(),
+                entity_id: entry.entity_id,
+                action: entry.action,
+                superseded_by: later_run,
+            });
+            continue;
+        }
+        revert(conn, &entry).await?;
+        reverted += 1;
+    }
+
+    conn.execute(
+        "UPDATE sync_runs SET rolled_back_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now') WHERE id = ?",
+        libsql::params![run_id],
+    )
+    .await?;
+    Ok((reverted, skipped))
+}
+
+async fn superseded_by(conn: &Connection, run_id: i64, entry: &JournalEntry) -> Result<Option<i64>> {
+    let query = r#"
+        SELECT MIN(c.run_id) FROM sync_changes c
+        JOIN sync_runs r ON r.id = c.run_id
+        WHERE c.entity = ? AND c.entity_id = ? AND c.run_id > ? AND r.rolled_back_at IS NULL
+    "#;
+    let mut rows = conn
+        .query(query, libsql::params![entry.entity.table(), entry.entity_id, run_id])
+        .await?;
+    match rows.next().await? {
+        Some(row) => Ok(row.get(0)?),
+        None => Ok(None),
+    }
+}
+
+async fn revert(conn: &Connection, entry: &JournalEntry) -> Result<()> {
+    let table = entry.entity.table();
+    match &entry.previous {
+        // Created by the run: soft-delete it, like any other removal
+        None => {
+            let query = format!(
+                "UPDATE {} SET deleted_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now') WHERE id = ? AND deleted_at IS NULL",
+                table
+            );
+            conn.execute(&query, libsql::params![entry.entity_id]).await?;
+        }
+        Some(previous) => {
+            let sets: Vec<String> = entry
+                .entity
+                .columns()
+                .iter()
+                .map(|c| format!("{0} = json_extract(?1, '$.{0}')", c))
+                .collect();
+            let query = format!("UPDATE {} SET {} WHERE id = ?2", table, sets.join(", "));
+            conn.execute(&query, libsql::params![previous.as_str(), entry.entity_id])
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commonplace::{CreateAnnotation, CreateResource, ResourceType, UpdateAnnotation};
+    use crate::test_support::test_db;
+
+    fn annotation(resource_id: i32, text: &str) -> CreateAnnotation {
+        CreateAnnotation {
+            resource_id,
+            text: text.to_string(),
+            color: None,
+            boundary: None,
+            external_id: None,
+            content_hash: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rollback_reverts_a_run() {
+        let db = test_db().await;
+        let conn = &db.connection();
+        let lib = db.commonplace();
+        let resource = lib
+            .create_resource(CreateResource {
+                title: "https://example.com/essay".to_string(),
+                resource_type: ResourceType::Website,
+                external_id: None,
+                content_hash: None,
+            })
+            .await
+            .unwrap();
+        let edited = lib
+            .create_annotation(annotation(resource.id, "original"))
+            .await
+            .unwrap();
+        let removed = lib.create_annotation(annotation(resource.id, "removed")).await.unwrap();
+
+        let run_id = start_run(conn, "light:test").await.unwrap();
+        let synced = db.commonplace().with_sync_run(run_id);
+        let added = synced
+            .create_annotation(annotation(resource.id, "added"))
+            .await
+            .unwrap();
+        let update = UpdateAnnotation {
+            text: Some("rewritten".to_string()),
+            color: None,
+            boundary: None,
+            content_hash: None,
+        };
+        synced.update_annotation(edited.id, update).await.unwrap();
+        synced.soft_delete_annotation(removed.id).await.unwrap();
+
+        assert!(matches!(rollback_run(&db, run_id).await.unwrap(), Rollback::Running));
+        finish_run(conn, run_id, &SyncStats::default()).await.unwrap();
+
+        let Rollback::Done(summary) = rollback_run(&db, run_id).await.unwrap() else {
+            panic!("run was not rolled back");
+        };
+        assert_eq!(summary.reverted, 3);
+        assert!(summary.run.rolled_back_at.is_some());
+        assert!(lib.get_annotation(added.id).await.unwrap().is_none());
+        assert_eq!(lib.get_annotation(edited.id).await.unwrap().unwrap().text, "original");
+        assert!(lib.get_annotation(removed.id).await.unwrap().is_some());
+        assert!(matches!(rollback_run(&db, run_id).await.unwrap(), Rollback::AlreadyRolledBack(_)));
+    }
+
+    #[tokio::test]
+    async fn test_diff_log_condenses_a_run() {
+        let db = test_db().await;
+        let conn = &db.connection();
+        let lib = db.commonplace();
+        let resource = lib
+            .create_resource(CreateResource {
+                title: "https://example.com/essay".to_string(),
+                resource_type: ResourceType::Website,
+                external_id: None,
+                content_hash: None,
+            })
+            .await
+            .unwrap();
+        let mut edited = annotation(resource.id, "original");
+        edited.external_id = Some("light:1".to_string());
+        edited.content_hash = Some("hash-a".to_string());
+        let edited = lib.create_annotation(edited).await.unwrap();
+
+        let run_id = start_run(conn, "light:test").await.unwrap();
+        let synced = db.commonplace().with_sync_run(run_id);
+        let added = synced
+            .create_annotation(annotation(resource.id, "added"))
+            .await
+            .unwrap();
+        for (text, hash) in [("first", "hash-b"), ("second", "hash-c")] {
+            let update = UpdateAnnotation {
+                text: Some(text.to_string()),
+                color: None,
+                boundary: None,
+                content_hash: Some(hash.to_string()),
+            };
+            synced.update_annotation(edited.id, update).await.unwrap();
+        }
+        synced
+            .update_annotation(
+                added.id,
+                UpdateAnnotation {
+                    text: Some("added, then edited".to_string()),
+                    color: None,
+                    boundary: None,
+                    content_hash: None,
+                },
+            )
+            .await
+            .unwrap();
+        finish_run(conn, run_id, &SyncStats::default()).await.unwrap();
+
+        assert_eq!(record_diff(conn, run_id).await.unwrap(), 2);
+        let diff = list_diff(conn, run_id, None, None).await.unwrap();
+        assert_eq!(diff.len(), 2);
+        assert_eq!((diff[0].entity_id, diff[0].action.as_str()), (added.id as i64, "created"));
+        let edits = list_diff(conn, run_id, Some(Entity::Annotation), Some("light:1"))
+            .await
+            .unwrap();
+        assert_eq!(edits.len(), 1);
+        assert_eq!(edits[0].action, "updated");
+        assert_eq!(edits[0].old_hash.as_deref(), Some("hash-a"));
+        assert_eq!(edits[0].new_hash.as_deref(), Some("hash-c"));
+    }
+
+    #[tokio::test]
+    async fn test_history_lists_runs_by_source() {
+        let db = test_db().await;
+        let conn = &db.connection();
+        for source in ["research", "light:chrome", "light:firefox", "lightroom"] {
+            let id = start_run(conn, source).await.unwrap();
+            let stats = SyncStats {
+                created: 2,
+                errors: 1,
+                ..Default::default()
+            };
+            finish_run(conn, id, &stats).await.unwrap();
+        }
+
+        let light = list_runs(conn, Some("light"), 10).await.unwrap();
+        let sources: Vec<_> = light.iter().map(|run| run.source.as_str()).collect();
+        assert_eq!(sources, vec!["light:firefox", "light:chrome"]);
+        assert_eq!((light[0].created, light[0].errors), (2, 1));
+        assert!(light[0].duration_ms.is_some_and(|ms| ms >= 0));
+        assert_eq!(list_runs(conn, None, 3).await.unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_interrupted_runs_stop_running() {
+        let db = test_db().await;
+        let conn = &db.connection();
+        let first = start_run(conn, "zotero").await.unwrap();
+        finish_run(conn, first, &SyncStats::default()).await.unwrap();
+        let second = start_run(conn, "zotero").await.unwrap();
+        start_run(conn, "kobo").await.unwrap();
+
+        assert_eq!(running_sources(conn).await.unwrap().len(), 2);
+        let last: Vec<_> = last_runs(conn).await.unwrap().iter().map(|run| run.id).collect();
+        assert_eq!(last, [first]);
+
+        assert_eq!(mark_interrupted(conn).await.unwrap(), 2);
+        assert!(running_sources(conn).await.unwrap().is_empty());
+        let last = last_runs(conn).await.unwrap();
+        let last: Vec<_> = last
+            .iter()
+            .map(|run| (run.source.as_str(), run.status.as_str()))
+            .collect();
+        assert_eq!(last, [("kobo", "interrupted"), ("zotero", "interrupted")]);
+        assert!(get_run(conn, second).await.unwrap().unwrap().finished_at.is_some());
+    }
+}
@@ -0,0 +1,125 @@
+//! Fixtures for exercising handlers against a throwaway database and no S3 bucket.
+//! Available to unit tests, and to integration tests via the `test-support` feature.
+
+use std::io::{Cursor, Write};
+use std::sync::Arc;
+
+use axum::body::Body;
+use axum::extract::{FromRequest, Multipart, Request};
+use axum::http::header::CONTENT_TYPE;
+use axum::response::Response;
+
+use crate::commonplace::dictionary::Dictionary;
+use crate::db::Database;
+use crate::enrich::Enricher;
+use crate::handler::AppState;
+use crate::object_store::MemoryObjectStore;
+use crate::titles::TitleCleaner;
+
+const MULTIPART_BOUNDARY: &str = "bibliotek-test-boundary";
+
+pub async fn test_db() -> Arc<Database> {
+    Arc::new(
+        Database::new_in_memory()
+            .await
+            .expect("failed to create in-memory database"),
+    )
+}
+
+/// A complete `AppState` over a fresh scratch database. Upload enrichment is
+/// disabled so tests never make network calls.
+pub async fn test_state() -> AppState {
+    test_state_with_store(Arc::new(MemoryObjectStore::new())).await
+}
+
+/// Like `test_state`, but with a store the caller keeps a handle to for assertions.
+pub async fn test_state_with_store(store: Arc<MemoryObjectStore>) -> AppState {
+    AppState {
+        db: test_db().await,
+        resumable: store,
+        enricher: Arc::new(Enricher::new().expect("failed to build enricher")),
+        enrich_on_upload: false,
+        sync_diff_log: false,
+        sync: Arc::new(Default::default()),
+        dictionary: Arc::new(Dictionary::disabled()),
+        titles: Arc::new(TitleCleaner::default()),
+        retention: Arc::new(Default::default()),
+        public: Arc::new(Default::default()),
+        extraction: Arc::new(Default::default()),
+        wallabag: Arc::new(Default::default()),
+        webhook: Arc::new(Default::default()),
+    }
+}
+
+/// Inserts a book with the given authors and returns its id.
+pub async fn seed_book(db: &Database, title: &str, authors: &[&str]) -> i32 {
+    let url = format!("https://example.com/{}.pdf", title.to_lowercase().replace(' ', "-"));
+    let authors: Vec<String> = authors.iter().map(|a| a.to_string()).collect();
+    db.create_book(title, &url, None, None, None, None, &authors, &[], &[], "complete")
+        .await
+        .expect("failed to seed book")
+}
+
+/// Builds the `Multipart` extractor a handler would receive for these form fields.
+pub async fn multipart(fields: &[(&str, &[u8])]) -> Multipart {
+    let mut body = Vec::new();
+    for (name, value) in fields {
+        body.extend_from_slice(
+            format!("--{MULTIPART_BOUNDARY}\r\nContent-Disposition: form-data; name=\"{name}\"\r\n\r\n").as_bytes(),
+        );
+        body.extend_from_slice(value);
+        body.extend_from_slice(b"\r\n");
+    }
+    body.extend_from_slice(format!("--{MULTIPART_BOUNDARY}--\r\n").as_bytes());
+
+    let req = Request::builder()
+        .method("POST")
+        .header(CONTENT_TYPE, format!("multipart/form-data; boundary={MULTIPART_BOUNDARY}"))
+        .body(Body::from(body))
+        .expect("failed to build multipart request");
+    Multipart::from_request(req, &())
+        .await
+        .expect("invalid multipart request")
+}
+
+/// A minimal EPUB (container.xml + OPF) with the given title and one creator per author.
+pub fn sample_epub(title: &str, authors: &[&str]) -> Vec<u8> {
+    let container = r#"<?xml version="1.0"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles><rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/></rootfiles>
+</container>"#;
+    let creators: String = authors
+        .iter()
+        .map(|author| format!("\n    <dc:creator opf:role=\"aut\">{author}</dc:creator>"))
+        .collect();
+    let opf = format!(
+        r#"<?xml version="1.0"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="2.0">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:opf="http://www.idpf.org/2007/opf">
+    <dc:title>{title}</dc:title>{creators}
+    <dc:subject>Computer programming</dc:subject>
+    <dc:identifier opf:scheme="ISBN">978-0-262-51087-5</dc:identifier>
+  </metadata>
+</package>"#
+    );
+
+    let mut buf = Cursor::new(Vec::new());
+    let mut zip = zip::ZipWriter::new(&mut buf);
+    let options = zip::write::SimpleFileOptions::default();
+    zip.start_file("META-INF/container.xml", options).unwrap();
+    zip.write_all(container.as_bytes()).unwrap();
+    zip.start_file("OEBPS/content.opf", options).unwrap();
+    zip.write_all(opf.as_bytes()).unwrap();
+    zip.finish().unwrap();
+    buf.into_inner()
+}
+
+/// Collects a handler response into its status and JSON body.
+pub async fn read_json(resp: Response) -> (axum::http::StatusCode, serde_json::Value) {
+    let status = resp.status();
+    let bytes = axum::body::to_bytes(resp.into_body(), usize::MAX)
+        .await
+        .expect("failed to read response body");
+    let body = serde_json::from_slice(&bytes).unwrap_or(serde_json::Value::Null);
+    (status, body)
+}
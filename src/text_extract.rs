@@ -0,0 +1,569 @@
+//! Text of PDF files, behind [`TextExtractor`] so several backends can be tried
+//! (`GET /books/:id/text`). No single extractor copes with every publisher: fonts
+//! with custom encodings or embedded CID fonts come out of a naive reader as
+//! garbage. Each backend's output is scored by [`quality`], the share of characters
+//! that don't look garbled, and the next backend is tried until one scores high
+//! enough; see [`crate::config::Extraction`] for the order and threshold.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use flate2::read::ZlibDecoder;
+use serde::Serialize;
+
+use crate::config::{self, ExtractorBackend};
+
+#[async_trait]
+pub trait TextExtractor: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    async fn extract(&self, pdf: &[u8]) -> Result<String>;
+}
+
+/// Share of the non-whitespace characters of `text` that don't look garbled:
+/// replacement characters, control characters and private-use code points, which
+/// is what glyphs without a Unicode mapping usually turn into. Empty text scores 0.
+pub fn quality(text: &str) -> f64 {
+    let (mut total, mut garbled) = (0usize, 0usize);
+    for ch in text.chars().filter(|ch| !ch.is_whitespace()) {
+        total += 1;
+        if ch == char::REPLACEMENT_CHARACTER || ch.is_control() || ('\u{E000}'..='\u{F8FF}').contains(&ch) {
+            garbled += 1;
+        }
+    }
+    if total == 0 {
+        return 0.0;
+    }
+    1.0 - garbled as f64 / total as f64
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Attempt {
+    pub backend: &'static str,
+    pub quality: Option<f64>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Extracted {
+    /// Backend the text came from
+    pub backend: &'static str,
+    pub quality: f64,
+    pub text: String,
+    /// Every backend tried, in order
+    pub attempts: Vec<Attempt>,
+}
+
+pub struct TextExtraction {
+    backends: Vec<Box<dyn TextExtractor>>,
+    min_quality: f64,
+}
+
+impl Default for TextExtraction {
+    fn default() -> Self {
+        Self::new(&config::Extraction::default())
+    }
+}
+
+impl TextExtraction {
+    pub fn new(cfg: &config::Extraction) -> Self {
+        let backends = cfg
+            .backends
+            .iter()
+            .map(|backend| -> Box<dyn TextExtractor> {
+                match backend {
+                    ExtractorBackend::TextOps => Box::new(TextOps),
+                    ExtractorBackend::Poppler => Box::new(Poppler::new(&cfg.pdftotext)),
+                }
+            })
+            .collect();
+        Self::with_backends(backends, cfg.min_quality)
+    }
+
+    pub fn with_backends(backends: Vec<Box<dyn TextExtractor>>, min_quality: f64) -> Self {
+        Self { backends, min_quality }
+    }
+
+    /// Text of the first backend scoring at least the minimum quality, otherwise the
+    /// best scoring one. Errors only when every backend failed.
+    pub async fn extract(&self, pdf: &[u8]) -> Result<Extracted> {
+        let mut attempts = Vec::new();
+        let mut best: Option<(&'static str, f64, String)> = None;
+        for backend in &self.backends {
+            let text = match backend.extract(pdf).await {
+                Ok(text) => text,
+                Err(e) => {
+                    tracing::debug!("{} could not extract text: {:#}", backend.name(), e);
+                    attempts.push(Attempt {
+                        backend: backend.name(),
+                        quality: None,
+                        error: Some(format!("{:#}", e)),
+                    });
+                    continue;
+                }
+            };
+            let score = quality(&text);
+            attempts.push(Attempt {
+                backend: backend.name(),
+                quality: Some(score),
+                error: None,
+            });
+            if best.as_ref().is_none_or(|(_, best_score, _)| score > *best_score) {
+                best = Some((backend.name(), score, text));
+            }
+            if score >= self.min_quality {
+                break;
+            }
+        }
+
+        match best {
+            Some((backend, quality, text)) => Ok(Extracted {
+                backend,
+                quality,
+                text,
+                attempts,
+            }),
+            None => {
+                let errors: Vec<String> = attempts
+                    .iter()
+                    .map(|a| format!("{}: {}", a.backend, a.error.as_deref().unwrap_or_default()))
+                    .collect();
+                anyhow::bail!("no backend could extract text ({})", errors.join("; "))
+            }
+        }
+    }
+}
+
+/// Reads the text-showing operators (`Tj`, `TJ`, `'`, `"`) of every content stream,
+/// decoding strings as WinAnsi. Needs nothing installed and is right for most
+/// simple fonts, but knows nothing of font encodings or CID fonts.
+pub struct TextOps;
+
+#[async_trait]
+impl TextExtractor for TextOps {
+    fn name(&self) -> &'static str {
+        "text_ops"
+    }
+
+    async fn extract(&self, pdf: &[u8]) -> Result<String> {
+        let pdf = pdf.to_vec();
+        tokio::task::spawn_blocking(move || text_ops(&pdf)).await?
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8], from: usize) -> Option<usize> {
+    haystack
+        .get(from..)?
+        .windows(needle.len())
+        .position(|window| window == needle)
+        .map(|i| i + from)
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    find(haystack, needle, 0).is_some()
+}
+
+/// Dictionary entries of streams that never hold page text
+const NON_TEXT_STREAMS: &[&[u8]] = &[b"/Image", b"/XRef", b"/ObjStm", b"/Length1", b"/Length2", b"/Metadata"];
+
+fn text_ops(pdf: &[u8]) -> Result<String> {
+    if !pdf.starts_with(b"%PDF") {
+        anyhow::bail!("not a PDF");
+    }
+    let mut text = String::new();
+    let mut pos = 0;
+    while let Some(at) = find(pdf, b"stream", pos) {
+        pos = at + b"stream".len();
+        if pdf[..at].ends_with(b"end") {
+            continue;
+        }
+        let dict_start = pdf[..at].windows(3).rposition(|w| w == b"obj").unwrap_or(0);
+        let dict = &pdf[dict_start..at];
+        let mut start = pos;
+        if pdf.get(start) == Some(&b'\r') {
+            start += 1;
+        }
+        if pdf.get(start) == Some(&b'\n') {
+            start += 1;
+        }
+        let Some(end) = find(pdf, b"endstream", start) else {
+            break;
+        };
+        pos = end;
+        if NON_TEXT_STREAMS.iter().any(|key| contains(dict, key)) {
+            continue;
+        }
+
+        let raw = &pdf[start..end];
+        let data = if contains(dict, b"/FlateDecode") {
+            let mut inflated = Vec::new();
+            if std::io::Read::read_to_end(&mut ZlibDecoder::new(raw), &mut inflated).is_err() && inflated.is_empty() {
+                continue;
+            }
+            inflated
+        } else if contains(dict, b"/Filter") {
+            continue;
+        } else {
+            raw.to_vec()
+        };
+        if contains(&data, b"BT") {
+            show_text(&data, &mut text);
+        }
+    }
+    Ok(text.trim().to_string())
+}
+
+#[derive(Debug)]
+enum Operand {
+    Str(Vec<u8>),
+    Number(f64),
+    Array(Vec<Operand>),
+    Other,
+}
+
+/// Appends the text shown by a content stream to `out`
+fn show_text(data: &[u8], out: &mut String) {
+    let mut lexer = Lexer { data, pos: 0 };
+    let mut operands: Vec<Operand> = Vec::new();
+    let mut arrays: Vec<Vec<Operand>> = Vec::new();
+    let newline = |out: &mut String| {
+        if !out.is_empty() && !out.ends_with('\n') {
+            out.push('\n');
+        }
+    };
+
+    while let Some(token) = lexer.next() {
+        let operand = match token {
+            Token::Str(bytes) => Operand::Str(bytes),
+            Token::Number(n) => Operand::Number(n),
+            Token::Other => Operand::Other,
+            Token::ArrayStart => {
+                arrays.push(Vec::new());
+                continue;
+            }
+            Token::ArrayEnd => Operand::Array(arrays.pop().unwrap_or_default()),
+            Token::Operator(op) => {
+                match op.as_slice() {
+                    b"Tj" => show(operands.last(), out),
+                    b"'" | b"\"" => {
+                        newline(out);
+                        show(operands.last(), out);
+                    }
+                    b"TJ" => {
+                        if let Some(Operand::Array(items)) = operands.last() {
+                            for item in items {
+                                match item {
+                                    // Wide negative adjustments separate words
+                                    Operand::Number(n) if *n < -200.0 && !out.ends_with([' ', '\n']) => out.push(' '),
+                                    item => show(Some(item), out),
+                                }
+                            }
+                        }
+                    }
+                    b"Td" | b"TD" => {
+                        if matches!(operands.last(), Some(Operand::Number(ty)) if *ty != 0.0) {
+                            newline(out);
+                        } else if !out.ends_with([' ', '\n']) && !out.is_empty() {
+                            out.push(' ');
+                        }
+                    }
+                    b"T*" | b"Tm" | b"ET" => newline(out),
+                    b"ID" => lexer.skip_inline_image(),
+                    _ => {}
+                }
+                operands.clear();
+                continue;
+            }
+        };
+        match arrays.last_mut() {
+            Some(array) => array.push(operand),
+            None => operands.push(operand),
+        }
+    }
+}
+
+fn show(operand: Option<&Operand>, out: &mut String) {
+    if let Some(Operand::Str(bytes)) = operand {
+        out.extend(bytes.iter().map(|&b| win_ansi(b)));
+    }
+}
+
+/// WinAnsi, which is Latin-1 apart from 0x80-0x9F
+fn win_ansi(byte: u8) -> char {
+    const HIGH: [char; 32] = [
+        '€', '\u{FFFD}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\u{FFFD}', 'Ž', '\u{FFFD}',
+        '\u{FFFD}', '‘', '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\u{FFFD}', 'ž', 'Ÿ',
+    ];
+    match byte {
+        0x80..=0x9F => HIGH[(byte - 0x80) as usize],
+        b => b as char,
+    }
+}
+
+enum Token {
+    Str(Vec<u8>),
+    Number(f64),
+    ArrayStart,
+    ArrayEnd,
+    Operator(Vec<u8>),
+    /// Names, dictionaries and anything else that is never shown
+    Other,
+}
+
+struct Lexer<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+fn is_delimiter(b: u8) -> bool {
+    b.is_ascii_whitespace() || b"()<>[]{}/%".contains(&b)
+}
+
+impl Lexer<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.data.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        loop {
+            let b = self.peek()?;
+            match b {
+                b if b.is_ascii_whitespace() || b == 0 => self.pos += 1,
+                b'%' => {
+                    while self.peek().is_some_and(|b| b != b'\n' && b != b'\r') {
+                        self.pos += 1;
+                    }
+                }
+                _ => break,
+            }
+        }
+
+        let b = self.peek()?;
+        self.pos += 1;
+        Some(match b {
+            b'(' => Token::Str(self.literal_string()),
+            b'<' if self.peek() == Some(b'<') => {
+                self.pos += 1;
+                Token::Other
+            }
+            b'>' if self.peek() == Some(b'>') => {
+                self.pos += 1;
+                Token::Other
+            }
+            b'<' => Token::Str(self.hex_string()),
+            b'[' => Token::ArrayStart,
+            b']' => Token::ArrayEnd,
+            b'/' => {
+                self.take_regular();
+                Token::Other
+            }
+            b'+' | b'-' | b'.' | b'0'..=b'9' => {
+                let start = self.pos - 1;
+                self.take_regular();
+                std::str::from_utf8(&self.data[start..self.pos])
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .map_or(Token::Other, Token::Number)
+            }
+            b')' | b'>' | b'{' | b'}' => Token::Other,
+            _ => {
+                let start = self.pos - 1;
+                self.take_regular();
+                Token::Operator(self.data[start..self.pos].to_vec())
+            }
+        })
+    }
+
+    fn take_regular(&mut self) {
+        while self.peek().is_some_and(|b| !is_delimiter(b)) {
+            self.pos += 1;
+        }
+    }
+
+    fn literal_string(&mut self) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut depth = 1;
+        while let Some(b) = self.peek() {
+            self.pos += 1;
+            match b {
+                b'(' => {
+                    depth += 1;
+                    out.push(b);
+                }
+                b')' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        break;
+                    }
+                    out.push(b);
+                }
+                b'\\' => {
+                    let Some(escaped) = self.peek() else { break };
+                    self.pos += 1;
+                    match escaped {
+                        b'n' => out.push(b'\n'),
+                        b'r' => out.push(b'\r'),
+                        b't' => out.push(b'\t'),
+                        b'b' => out.push(0x08),
+                        b'f' => out.push(0x0C),
+                        b'0'..=b'7' => {
+                            let mut value = (escaped - b'0') as u32;
+                            for _ in 0..2 {
+                                match self.peek() {
+                                    Some(d @ b'0'..=b'7') => {
+                                        value = value * 8 + (d - b'0') as u32;
+                                        self.pos += 1;
+                                    }
+                                    _ => break,
+                                }
+                            }
+                            out.push(value as u8);
+                        }
+                        // A backslash before a line break continues the string
+                        b'\r' => {
+                            if self.peek() == Some(b'\n') {
+                                self.pos += 1;
+                            }
+                        }
+                        b'\n' => {}
+                        other => out.push(other),
+                    }
+                }
+                b => out.push(b),
+            }
+        }
+        out
+    }
+
+    fn hex_string(&mut self) -> Vec<u8> {
+        let mut digits = Vec::new();
+        while let Some(b) = self.peek() {
+            self.pos += 1;
+            match b {
+                b'>' => break,
+                b if b.is_ascii_hexdigit() => digits.push(b),
+                _ => {}
+            }
+        }
+        if digits.len() % 2 == 1 {
+            digits.push(b'0');
+        }
+        digits
+            .chunks(2)
+            .filter_map(|pair| u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok())
+            .collect()
+    }
+
+    /// Skips the binary data of an inline image, up to its `EI`
+    fn skip_inline_image(&mut self) {
+        let mut at = self.pos;
+        while let Some(found) = find(self.data, b"EI", at) {
+            let before = found.checked_sub(1).and_then(|i| self.data.get(i));
+            let after = self.data.get(found + 2);
+            if before.is_some_and(|b| b.is_ascii_whitespace()) && after.is_none_or(|b| is_delimiter(*b)) {
+                self.pos = found + 2;
+                return;
+            }
+            at = found + 2;
+        }
+        self.pos = self.data.len();
+    }
+}
+
+/// Poppler's `pdftotext`, which maps glyphs through the fonts' ToUnicode tables and
+/// handles far more PDFs than [`TextOps`], if it is installed
+pub struct Poppler {
+    program: String,
+}
+
+impl Poppler {
+    pub fn new(program: &str) -> Self {
+        Self {
+            program: program.to_string(),
+        }
+    }
+}
+
+/// Tells apart the files of extractions running at the same time
+static TEMP_FILES: AtomicU64 = AtomicU64::new(0);
+
+#[async_trait]
+impl TextExtractor for Poppler {
+    fn name(&self) -> &'static str {
+        "poppler"
+    }
+
+    async fn extract(&self, pdf: &[u8]) -> Result<String> {
+        // pdftotext cannot read from stdin
+        let path = std::env::temp_dir().join(format!(
+            "bibliotek-extract-{}-{}.pdf",
+            std::process::id(),
+            TEMP_FILES.fetch_add(1, Ordering::Relaxed)
+        ));
+        tokio::fs::write(&path, pdf).await?;
+        let output = tokio::process::Command::new(&self.program)
+            .args(["-q", "-enc", "UTF-8"])
+            .arg(&path)
+            .arg("-")
+            .output()
+            .await;
+        let _ = tokio::fs::remove_file(&path).await;
+
+        let output = output.with_context(|| format!("failed to run {}", self.program))?;
+        if !output.status.success() {
+            anyhow::bail!(
+                "{} exited with {}: {}",
+                self.program,
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Fixed(&'static str, &'static str);
+
+    #[async_trait]
+    impl TextExtractor for Fixed {
+        fn name(&self) -> &'static str {
+            self.0
+        }
+
+        async fn extract(&self, _pdf: &[u8]) -> Result<String> {
+            Ok(self.1.to_string())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_extraction_falls_back_on_garbled_text() {
+        let pdf = crate::catalog::render_pdf("Reading \u{2022} List", "1 March 2026", &[]).unwrap();
+        let text = TextOps.extract(&pdf).await.unwrap();
+        assert!(text.contains("Reading \u{2022} List"), "{:?}", text);
+        assert!(text.contains("Page 1 of 1"));
+        assert_eq!(quality(&text), 1.0);
+
+        // What a CID font shown through the WinAnsi reading looks like
+        let garbled = "\0$\0K\0H\0\u{FFFD} \0U";
+        assert!(quality(garbled) < 0.5);
+        let extraction = TextExtraction::with_backends(
+            vec![
+                Box::new(Fixed("naive", garbled)),
+                Box::new(Fixed("careful", "the text")),
+            ],
+            0.9,
+        );
+        let extracted = extraction.extract(&pdf).await.unwrap();
+        assert_eq!((extracted.backend, extracted.text.as_str()), ("careful", "the text"));
+        assert_eq!(extracted.attempts.len(), 2);
+
+        // With nothing good enough, the best of what there is
+        let extraction = TextExtraction::with_backends(vec![Box::new(Fixed("naive", garbled))], 0.9);
+        assert_eq!(extraction.extract(&pdf).await.unwrap().backend, "naive");
+    }
+}
@@ -0,0 +1,166 @@
+//! Book titles made from file names. Downloaded files tend to be named like
+//! `clean_code-a_handbook_(z-lib.org)_9780132350884.pdf`; the rules in
+//! [`config::Titles`] turn that into "Clean Code a Handbook".
+
+use anyhow::{Context, Result};
+use regex::Regex;
+
+use crate::config;
+
+const ISBN: &str = r"(?i)\bisbn(?:-?1[03])?[:\s]*[\dx][\dx\s-]{8,16}[\dx]\b|\b97[89](?:[\s-]?\d){10}\b|\b\d{9}[\dxX]\b";
+const SOURCE_TAG: &str =
+    r"(?i)\(\s*(?:z-?lib(?:rary)?(?:\.\w+)?|libgen(?:\.\w+)?|anna'?s[\s-]archive|ebook|retail|epub|pdf|mobi)\s*\)";
+const EDITION: &str = r"(?i)\b(?:\d+(?:st|nd|rd|th)|first|second|third|fourth|fifth|sixth|revised|updated|expanded|new|international|anniversary|illustrated|special)\s+ed(?:ition|\.|\b)";
+const BRACKETED: &str = r"\[[^\]]*\]|\{[^}]*\}";
+/// Kept lowercase by title-casing unless they start or end the title
+const SMALL_WORDS: &[&str] = &[
+    "a", "an", "and", "as", "at", "but", "by", "for", "in", "nor", "of", "on", "or", "the", "to", "vs", "with",
+];
+
+pub struct TitleCleaner {
+    separators: Vec<char>,
+    before_separators: Vec<Regex>,
+    after_separators: Vec<Regex>,
+    title_case: bool,
+}
+
+impl Default for TitleCleaner {
+    fn default() -> Self {
+        Self::new(&config::Titles::default()).expect("built-in title patterns are valid")
+    }
+}
+
+impl TitleCleaner {
+    /// Fails if one of the configured `strip_patterns` is not a valid regex
+    pub fn new(config: &config::Titles) -> Result<Self> {
+        // Tags like "(z-lib.org)" contain separators, so they go first
+        let mut before_separators = Vec::new();
+        if config.strip_source_tags {
+            before_separators.push(Regex::new(SOURCE_TAG)?);
+        }
+        if config.strip_bracketed {
+            before_separators.push(Regex::new(BRACKETED)?);
+        }
+
+        // ISBNs go after, since "_9780132350884" has no word boundary before the number
+        let mut after_separators = Vec::new();
+        if config.strip_isbn {
+            after_separators.push(Regex::new(ISBN)?);
+        }
+        if config.strip_edition {
+            after_separators.push(Regex::new(EDITION)?);
+        }
+        for pattern in &config.strip_patterns {
+            let regex = Regex::new(pattern).with_context(|| format!("invalid title pattern {:?}", pattern))?;
+            after_separators.push(regex);
+        }
+
+        Ok(Self {
+            separators: config.separators.chars().collect(),
+            before_separators,
+            after_separators,
+            title_case: config.title_case,
+        })
+    }
+
+    /// Title for a book that only has its file name to go on
+    pub fn from_file_name(&self, file_name: &str) -> String {
+        let stem = match file_name.rfind('.') {
+            Some(dot) if dot > 0 => &file_name[..dot],
+            _ => file_name,
+        };
+        let cleaned = self.clean(stem);
+        if cleaned.is_empty() {
+            // Everything was stripped; a messy title beats no title
+            return stem.replace(self.separators.as_slice(), " ").trim().to_string();
+        }
+        cleaned
+    }
+
+    pub fn clean(&self, raw: &str) -> String {
+        let mut title = raw.to_string();
+        for regex in &self.before_separators {
+            title = regex.replace_all(&title, " ").into_owned();
+        }
+        title = title.replace(self.separators.as_slice(), " ");
+        for regex in &self.after_separators {
+            title = regex.replace_all(&title, " ").into_owned();
+        }
+
+        let title = title.split_whitespace().collect::<Vec<_>>().join(" ");
+        let title = title.trim_matches(|c: char| c.is_whitespace() || ",.;:-_".contains(c));
+        if self.title_case {
+            title_case(title)
+        } else {
+            title.to_string()
+        }
+    }
+}
+
+/// Capitalizes each word except small ones in the middle. Words with capitals of
+/// their own ("iPhone", "NASA") are left as they are, unless the whole title is
+/// shouting.
+fn title_case(title: &str) -> String {
+    let shouting = !title.chars().any(|c| c.is_lowercase());
+    let words: Vec<&str> = title.split(' ').collect();
+    let last = words.len().saturating_sub(1);
+    words
+        .iter()
+        .enumerate()
+        .map(|(i, word)| {
+            let word = if shouting {
+                word.to_lowercase()
+            } else {
+                word.to_string()
+            };
+            if word.chars().skip(1).any(|c| c.is_uppercase()) {
+                return word;
+            }
+            if i != 0 && i != last && SMALL_WORDS.contains(&word.to_lowercase().as_str()) {
+                return word.to_lowercase();
+            }
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect(),
+                None => word,
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_names_become_titles() {
+        let cleaner = TitleCleaner::default();
+        let cases = [
+            ("clean_code-a_handbook_(z-lib.org)_9780132350884.pdf", "Clean Code a Handbook"),
+            ("The_Pragmatic_Programmer_2nd_Edition_[EPUB].epub", "The Pragmatic Programmer"),
+            ("ISBN 0-13-235088-2 refactoring.pdf", "Refactoring"),
+            ("THE ART OF WAR.pdf", "The Art of War"),
+            ("learning_iOS_development.epub", "Learning iOS Development"),
+            ("9780132350884.pdf", "9780132350884"),
+        ];
+        for (file_name, title) in cases {
+            assert_eq!(cleaner.from_file_name(file_name), title, "{}", file_name);
+        }
+
+        let config = config::Titles {
+            strip_patterns: vec![r"(?i)\bdraft\b".to_string()],
+            title_case: false,
+            ..Default::default()
+        };
+        let cleaner = TitleCleaner::new(&config).unwrap();
+        assert_eq!(cleaner.from_file_name("notes_on_rust_draft.pdf"), "notes on rust");
+        assert!(
+            TitleCleaner::new(&config::Titles {
+                strip_patterns: vec!["(".to_string()],
+                ..Default::default()
+            })
+            .is_err()
+        );
+    }
+}
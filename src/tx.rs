@@ -0,0 +1,213 @@
+//! Request-scoped transactions. Routes wrapped in [`transaction`] run each
+//! mutating request (anything but GET, HEAD and OPTIONS) as one transaction, so a
+//! handler that takes several steps, like creating a book and then storing its
+//! identifiers and queueing its jobs, either applies all of them or none.
+//!
+//! Each such request gets a connection of its own, which
+//! [`Database::connection`] hands out for the rest of the request. The
+//! transaction opens on it lazily, at the first [`Database::begin`] in the
+//! request, so a handler can talk to the bucket or other services before touching
+//! the database without holding the transaction lock; `begin`/`commit` pairs
+//! inside become savepoints. It commits when the handler answers with a success or
+//! redirect, and rolls back on any error status. Since the transaction is on its
+//! own connection, nothing else sees its writes before it commits, and writes from
+//! outside the request, like the commonplace routes or a sync, are never part of
+//! it: they wait for it to finish like any other SQLite writer.
+//!
+//! Writes in the request have to go through [`Database::begin`] rather than issue
+//! `BEGIN` themselves, and a handler mustn't wait on a task it spawned that writes:
+//! that task is outside the request and waits for the lock the request holds.
+//! Events are published as each step commits, so a subscriber can hear about a
+//! change the request later rolls back.
+
+use std::cell::RefCell;
+use std::future::Future;
+
+use axum::{
+    extract::{Request, State},
+    http::Method,
+    middleware::Next,
+    response::Response,
+};
+use libsql::Connection;
+use tokio::sync::OwnedMutexGuard;
+
+use crate::api::APIResponse;
+use crate::db::Database;
+use crate::handler::AppState;
+
+/// The request's connection, and the transaction lock once its transaction is open
+struct RequestTx {
+    conn: Connection,
+    lock: RefCell<Option<OwnedMutexGuard<()>>>,
+}
+
+tokio::task_local! {
+    static REQUEST_TX: RequestTx;
+}
+
+/// The request's own connection, within a request transaction
+pub(crate) fn request_connection() -> Option<Connection> {
+    REQUEST_TX.try_with(|tx| tx.conn.clone()).ok()
+}
+
+/// `None` outside a request transaction, otherwise whether it has been opened yet
+pub(crate) fn request_transaction() -> Option<bool> {
+    REQUEST_TX.try_with(|tx| tx.lock.borrow().is_some()).ok()
+}
+
+/// Hands the transaction lock to the request, which releases it when it ends
+pub(crate) fn opened(lock: OwnedMutexGuard<()>) {
+    let _ = REQUEST_TX.try_with(|tx| *tx.lock.borrow_mut() = Some(lock));
+}
+
+/// Middleware running each mutating request in a transaction
+pub async fn transaction(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    if matches!(*request.method(), Method::GET | Method::HEAD | Method::OPTIONS) {
+        return next.run(request).await;
+    }
+    run(&state.db, next.run(request)).await
+}
+
+/// Runs `handler` in a request transaction, committing or rolling back by the
+/// status it answers with
+pub async fn run(db: &Database, handler: impl Future<Output = Response>) -> Response {
+    let conn = match db.connect().await {
+        Ok(conn) => conn,
+        Err(e) => {
+            tracing::error!("failed to open request connection: {}", e);
+            return crate::server_error(APIResponse::new_from_msg("failed to save changes"));
+        }
+    };
+    let request = RequestTx {
+        conn: conn.clone(),
+        lock: RefCell::new(None),
+    };
+    let (response, lock) = REQUEST_TX
+        .scope(request, async {
+            let response = handler.await;
+            (response, REQUEST_TX.with(|tx| tx.lock.borrow_mut().take()))
+        })
+        .await;
+    // Nothing was written through a transaction, so there is nothing to finish
+    let Some(_lock) = lock else {
+        return response;
+    };
+
+    let status = response.status();
+    if status.is_success() || status.is_redirection() {
+        if let Err(e) = conn.execute("COMMIT", ()).await {
+            let e = e.into();
+            db.note_error(&e);
+            tracing::error!("failed to commit request transaction: {}", e);
+            let _ = conn.execute("ROLLBACK", ()).await;
+            return crate::server_error(APIResponse::new_from_msg("failed to save changes"));
+        }
+    } else if let Err(e) = conn.execute("ROLLBACK", ()).await {
+        tracing::error!("failed to roll back request transaction: {}", e);
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commonplace::{CreateResource, ResourceType};
+    use crate::test_support::{seed_book, test_db};
+    use axum::{http::StatusCode, response::IntoResponse};
+    use tokio::sync::oneshot;
+
+    #[tokio::test]
+    async fn test_request_transaction_rolls_back_on_error() {
+        let db = test_db().await;
+        let book_id = seed_book(&db, "Dune", &["Frank Herbert"]).await;
+        let rename = |title: &'static str, status: StatusCode| {
+            let db = &db;
+            async move {
+                let _guard = db.begin().await.unwrap();
+                db.connection()
+                    .execute("UPDATE books SET title = ? WHERE id = ?", libsql::params![title, book_id])
+                    .await
+                    .unwrap();
+                db.commit().await.unwrap();
+                // A second step after the first one committed
+                db.connection()
+                    .execute("UPDATE books SET pages = 10 WHERE id = ?", libsql::params![book_id])
+                    .await
+                    .unwrap();
+                status.into_response()
+            }
+        };
+
+        let response = run(&db, rename("Dune Messiah", StatusCode::INTERNAL_SERVER_ERROR)).await;
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        let book = db.get_book_by_id(book_id).await.unwrap().unwrap();
+        assert_eq!((book.title.as_str(), book.pages), ("Dune", 0));
+
+        run(&db, rename("Children of Dune", StatusCode::OK)).await;
+        let book = db.get_book_by_id(book_id).await.unwrap().unwrap();
+        assert_eq!((book.title.as_str(), book.pages), ("Children of Dune", 10));
+        // The lock was released with the request
+        let begin = tokio::time::timeout(std::time::Duration::from_secs(1), db.begin()).await;
+        let _guard = begin.expect("transaction lock still held").unwrap();
+        db.rollback().await.unwrap();
+    }
+
+    // Several threads, since the outside write blocks its thread waiting on SQLite
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_writes_outside_the_request_are_not_part_of_it() {
+        let db = test_db().await;
+        let book_id = seed_book(&db, "Dune", &["Frank Herbert"]).await;
+        let (opened, is_open) = oneshot::channel();
+        let (finish, finished) = oneshot::channel::<()>();
+
+        let request = tokio::spawn({
+            let db = db.clone();
+            async move {
+                let handler = async {
+                    db.write(|| async {
+                        let query = "UPDATE books SET title = 'Dune Messiah' WHERE id = ?";
+                        Ok(db.connection().execute(query, libsql::params![book_id]).await?)
+                    })
+                    .await
+                    .unwrap();
+                    opened.send(()).unwrap();
+                    let _ = finished.await;
+                    StatusCode::INTERNAL_SERVER_ERROR.into_response()
+                };
+                run(&db, handler).await.status()
+            }
+        });
+        is_open.await.unwrap();
+
+        // Nothing outside the request sees its writes before it commits
+        let book = db.get_book_by_id(book_id).await.unwrap().unwrap();
+        assert_eq!(book.title, "Dune");
+
+        // A write from a route outside the layer, made while the request is open
+        let outside = tokio::spawn({
+            let db = db.clone();
+            async move {
+                db.commonplace()
+                    .create_resource(CreateResource {
+                        title: "GEB".to_string(),
+                        resource_type: ResourceType::Pdf,
+                        external_id: None,
+                        content_hash: None,
+                    })
+                    .await
+                    .unwrap()
+                    .id
+            }
+        });
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        finish.send(()).unwrap();
+
+        assert_eq!(request.await.unwrap(), StatusCode::INTERNAL_SERVER_ERROR);
+        let resource_id = outside.await.unwrap();
+        // The request rolled back without taking the outside write with it
+        let book = db.get_book_by_id(book_id).await.unwrap().unwrap();
+        assert_eq!(book.title, "Dune");
+        assert!(db.commonplace().get_resource(resource_id).await.unwrap().is_some());
+    }
+}
@@ -0,0 +1,182 @@
+//! Minimal server-rendered pages under `/html` for browsers that can't run the web
+//! app in `web/dist`, like the ones on e-readers. Plain HTML with a little inline
+//! CSS and no JavaScript; templates live in `templates/`.
+//!
+//! When the library is published (`public.enabled`) these pages are what the world
+//! sees, so they honour each book's and resource's [`Visibility`]: only public
+//! ones are listed, and private ones aren't found at all.
+
+use askama::Template;
+use axum::{
+    Router,
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::{Html, IntoResponse, Response},
+    routing::get,
+};
+
+use crate::api::QueryParams;
+use crate::commonplace::{Resource, ResourceFull};
+use crate::handler::AppState;
+use crate::model::{Book, Visibility};
+use crate::public::{self, BookMeta};
+
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/books", get(books))
+        .route("/books/:id", get(book))
+        .route("/commonplace/resources/:id", get(resource))
+}
+
+struct BookRow {
+    id: i32,
+    title: String,
+    authors: String,
+    status: &'static str,
+}
+
+#[derive(Template)]
+#[template(path = "books.html")]
+struct BooksPage {
+    query: String,
+    books: Vec<BookRow>,
+    total: u32,
+    page: u32,
+    prev: Option<String>,
+    next: Option<String>,
+}
+
+#[derive(Template)]
+#[template(path = "book.html")]
+struct BookPage {
+    book: Book,
+    authors: String,
+    resources: Vec<Resource>,
+    /// Set when the library is published, see [`crate::public`]
+    meta: Option<BookMeta>,
+}
+
+#[derive(Template)]
+#[template(path = "resource.html")]
+struct ResourcePage {
+    resource: ResourceFull,
+}
+
+fn render(page: &impl Template) -> Response {
+    match page.render() {
+        Ok(html) => Html(html).into_response(),
+        Err(e) => {
+            tracing::error!("failed to render page: {}", e);
+            error_page(StatusCode::INTERNAL_SERVER_ERROR, "Something went wrong")
+        }
+    }
+}
+
+fn error_page(status: StatusCode, msg: &'static str) -> Response {
+    let html = format!(
+        "<!DOCTYPE html><meta charset=\"utf-8\"><title>{0}</title><p>{0}</p><p><a href=\"/html/books\">Books</a></p>",
+        msg
+    );
+    (status, Html(html)).into_response()
+}
+
+fn books_href(query: &str, page: u32) -> String {
+    if query.is_empty() {
+        format!("/html/books?page={}", page)
+    } else {
+        format!("/html/books?page={}&q={}", page, urlencoding::encode(query))
+    }
+}
+
+async fn books(State(state): State<AppState>, Query(qp): Query<QueryParams>) -> Response {
+    let mut params = qp.into_handler_params();
+    if state.public.enabled {
+        params.visibility = Some(Visibility::Public);
+    }
+    let query = params.query.clone().unwrap_or_default();
+    let (page, limit) = (params.page, params.limit);
+
+    let total = match state.db.count_books(&params).await {
+        Ok(total) => total,
+        Err(e) => {
+            tracing::error!("failed to count books: {}", e);
+            return error_page(StatusCode::INTERNAL_SERVER_ERROR, "Failed to list books");
+        }
+    };
+    let books = match state.db.get_books(params).await {
+        Ok(books) => books,
+        Err(e) => {
+            tracing::error!("failed to get books: {}", e);
+            return error_page(StatusCode::INTERNAL_SERVER_ERROR, "Failed to list books");
+        }
+    };
+
+    let mut rows = Vec::with_capacity(books.len());
+    for book in books {
+        let authors = state.db.get_book_author_names(book.id).await.unwrap_or_default();
+        rows.push(BookRow {
+            id: book.id,
+            title: book.title,
+            authors: authors.join(", "),
+            status: book.reading_status.as_str(),
+        });
+    }
+
+    render(&BooksPage {
+        prev: (page > 1).then(|| books_href(&query, page - 1)),
+        next: (page * limit < total).then(|| books_href(&query, page + 1)),
+        query,
+        books: rows,
+        total,
+        page,
+    })
+}
+
+async fn book(State(state): State<AppState>, Path(book_id): Path<i32>, headers: HeaderMap) -> Response {
+    let book = match state.db.get_book_by_id(book_id).await {
+        Ok(Some(book)) if book.deleted_at.is_none() && (!state.public.enabled || book.visibility.is_linkable()) => book,
+        Ok(_) => return error_page(StatusCode::NOT_FOUND, "Book not found"),
+        Err(e) => {
+            tracing::error!("failed to get book: {}", e);
+            return error_page(StatusCode::INTERNAL_SERVER_ERROR, "Failed to get book");
+        }
+    };
+    let authors = state.db.get_book_author_names(book_id).await.unwrap_or_default();
+    let resources = match state.db.commonplace().list_resources_by_book(book_id).await {
+        Ok(resources) if state.public.enabled => resources
+            .into_iter()
+            .filter(|r| r.visibility == Visibility::Public)
+            .collect(),
+        Ok(resources) => resources,
+        Err(e) => {
+            tracing::error!("failed to list book resources: {}", e);
+            Vec::new()
+        }
+    };
+
+    let meta = state
+        .public
+        .enabled
+        .then(|| BookMeta::new(&book, &authors, &public::base_url(&state.public, &headers)));
+
+    render(&BookPage {
+        book,
+        authors: authors.join(", "),
+        resources,
+        meta,
+    })
+}
+
+async fn resource(State(state): State<AppState>, Path(id): Path<i32>) -> Response {
+    match state.db.commonplace().get_resource_full(id).await {
+        Ok(Some(resource)) if !state.public.enabled || resource.resource.visibility.is_linkable() => {
+            render(&ResourcePage { resource })
+        }
+        Ok(Some(_)) => error_page(StatusCode::NOT_FOUND, "Resource not found"),
+        Ok(None) => error_page(StatusCode::NOT_FOUND, "Resource not found"),
+        Err(e) => {
+            tracing::error!("failed to get resource: {}", e);
+            error_page(StatusCode::INTERNAL_SERVER_ERROR, "Failed to get resource")
+        }
+    }
+}
@@ -0,0 +1,232 @@
+//! Entries saved in a self-hosted wallabag instance, pulled through its API
+//! (https://doc.wallabag.org/en/developer/api/readme) into commonplace as website
+//! resources named by their URL, like Pocket's articles. Highlights made in wallabag
+//! become annotations, with the note written on one as its comment.
+//!
+//! Every sync pulls all entries, so entries and highlights deleted in wallabag are
+//! soft-deleted here. The credentials are in the config (`wallabag:`), and a token is
+//! asked for with them on each sync.
+
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use axum::{extract::State, response::Response};
+use serde::Deserialize;
+
+use crate::commonplace::ResourceType;
+use crate::config::Wallabag;
+use crate::handler::AppState;
+use crate::response::{bad_request, internal_error, success};
+use crate::sync::engine::{self, SyncError, SyncResponse};
+use crate::sync::{ResourceKey, SourceAnnotation, SourceComment, SourceResource, SyncSource};
+
+const REQUEST_TIMEOUT_SECS: u64 = 30;
+/// Entries asked for per request
+const PAGE_SIZE: usize = 100;
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct EntriesPage {
+    page: u32,
+    pages: u32,
+    #[serde(rename = "_embedded")]
+    embedded: EmbeddedEntries,
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbeddedEntries {
+    items: Vec<WallabagEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct WallabagEntry {
+    id: i64,
+    url: String,
+    #[serde(default)]
+    annotations: Vec<WallabagAnnotation>,
+}
+
+#[derive(Debug, Deserialize)]
+struct WallabagAnnotation {
+    id: i64,
+    #[serde(default)]
+    quote: String,
+    /// The note written on the highlight, empty if none
+    #[serde(default)]
+    text: String,
+}
+
+impl WallabagEntry {
+    fn into_resource(self) -> SourceResource {
+        let annotations = self
+            .annotations
+            .into_iter()
+            .map(|annotation| {
+                let note = annotation.text.trim();
+                let comments = if note.is_empty() {
+                    Vec::new()
+                } else {
+                    vec![SourceComment {
+                        external_id: format!("wallabag:comment:{}", annotation.id),
+                        content: note.to_string(),
+                    }]
+                };
+                SourceAnnotation {
+                    external_id: format!("wallabag:annotation:{}", annotation.id),
+                    text: annotation.quote,
+                    color: None,
+                    boundary: None,
+                    comments,
+                }
+            })
+            .collect();
+        SourceResource {
+            key: ResourceKey::External(format!("wallabag:{}", self.id)),
+            title: self.url.trim().to_string(),
+            resource_type: ResourceType::Website,
+            annotations,
+            notes: Vec::new(),
+            archived: None,
+        }
+    }
+}
+
+struct WallabagSource {
+    client: reqwest::Client,
+    config: Wallabag,
+    url: String,
+}
+
+impl WallabagSource {
+    /// None when no instance is configured
+    fn new(config: &Wallabag) -> Option<Self> {
+        let url = config.url.as_deref().map(str::trim).filter(|url| !url.is_empty())?;
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(REQUEST_TIMEOUT_SECS))
+            .user_agent(concat!("bibliotek/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .unwrap_or_default();
+        Some(Self {
+            client,
+            config: config.clone(),
+            url: url.trim_end_matches('/').to_string(),
+        })
+    }
+
+    async fn token(&self) -> Result<String> {
+        let form = [
+            ("grant_type", "password"),
+            ("client_id", &self.config.client_id),
+            ("client_secret", &self.config.client_secret),
+            ("username", &self.config.username),
+            ("password", &self.config.password),
+        ];
+        let resp = self
+            .client
+            .post(format!("{}/oauth/v2/token", self.url))
+            .form(&form)
+            .send()
+            .await?;
+        if !resp.status().is_success() {
+            anyhow::bail!("wallabag did not accept the credentials ({})", resp.status());
+        }
+        Ok(resp.json::<TokenResponse>().await?.access_token)
+    }
+
+    async fn entries(&self, token: &str) -> Result<Vec<WallabagEntry>> {
+        let mut entries = Vec::new();
+        let mut page = 1;
+        loop {
+            let resp: EntriesPage = self
+                .client
+                .get(format!("{}/api/entries.json", self.url))
+                .bearer_auth(token)
+                .query(&[
+                    ("page", page.to_string()),
+                    ("perPage", PAGE_SIZE.to_string()),
+                    ("detail", "metadata".to_string()),
+                ])
+                .send()
+                .await?
+                .error_for_status()?
+                .json()
+                .await?;
+            entries.extend(resp.embedded.items);
+            if resp.page >= resp.pages {
+                return Ok(entries);
+            }
+            page = resp.page + 1;
+        }
+    }
+}
+
+#[async_trait]
+impl SyncSource for WallabagSource {
+    fn name(&self) -> String {
+        "wallabag".to_string()
+    }
+
+    async fn fetch(&self) -> Result<Vec<SourceResource>> {
+        let token = self.token().await?;
+        let entries = self.entries(&token).await?;
+        Ok(entries.into_iter().map(WallabagEntry::into_resource).collect())
+    }
+}
+
+pub async fn sync(State(state): State<AppState>) -> Response {
+    let Some(source) = WallabagSource::new(&state.wallabag) else {
+        return bad_request("wallabag is not configured. Set wallabag.url and its credentials in the config.");
+    };
+
+    match engine::run(&state, &source).await {
+        Ok(report) => success(SyncResponse::from(&report)),
+        Err(SyncError::Fetch(e)) => {
+            tracing::error!("Failed to fetch from wallabag: {}", e);
+            internal_error("Failed to fetch entries from wallabag")
+        }
+        Err(SyncError::StartRun(e)) => {
+            tracing::error!("Failed to start wallabag sync run: {}", e);
+            internal_error("Failed to start sync run")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entries_become_website_resources() {
+        let page: EntriesPage = serde_json::from_value(serde_json::json!({
+            "page": 1,
+            "limit": 100,
+            "pages": 1,
+            "total": 1,
+            "_embedded": {
+                "items": [{
+                    "id": 42,
+                    "title": "An essay",
+                    "url": "https://example.com/essay",
+                    "is_archived": 0,
+                    "annotations": [
+                        { "id": 7, "quote": "Read slowly", "text": "", "ranges": [] },
+                        { "id": 8, "quote": "Then again", "text": "Disagree" },
+                    ],
+                }],
+            },
+        }))
+        .unwrap();
+
+        let resource = page.embedded.items.into_iter().next().unwrap().into_resource();
+        assert!(matches!(&resource.key, ResourceKey::External(id) if id == "wallabag:42"));
+        assert_eq!(resource.title, "https://example.com/essay");
+        let comments: Vec<_> = resource.annotations.iter().map(|a| a.comments.len()).collect();
+        assert_eq!(comments, [0, 1]);
+        assert_eq!(resource.annotations[1].comments[0].content, "Disagree");
+    }
+}
@@ -0,0 +1,4 @@
+mod handler;
+mod routes;
+
+pub use routes::routes;
@@ -0,0 +1,161 @@
+//! Webhook told about finished syncs, for automation that reacts to new highlights.
+//! It is an outbox sink, so a delivery that fails is retried with the outbox's backoff
+//! and the receiver may see the same sync twice; `X-Bibliotek-Delivery` tells them apart.
+//!
+//! With a secret, the body is signed like GitHub's webhooks: `X-Bibliotek-Signature`
+//! is `sha256=` followed by the hex HMAC-SHA256 of the raw body under the secret.
+
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use crate::config::Webhook;
+use crate::events::Event;
+use crate::outbox::{OutboxEntry, OutboxSink};
+use crate::sync::{is_redacted, redact_secret};
+
+const REQUEST_TIMEOUT_SECS: u64 = 10;
+
+/// What the webhook is sent for a finished sync
+#[derive(Debug, Serialize)]
+struct SyncPayload<'a> {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    source: &'a str,
+    completed_at: &'a str,
+    /// The sync's `SyncResponse`
+    response: &'a serde_json::Value,
+}
+
+/// What a sync config archive keeps of the webhook settings. The webhook is set in
+/// the config file, so importing an archive can only say whether it matches.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookConfig {
+    pub url: String,
+    /// Redacted on export
+    #[serde(default)]
+    pub secret: Option<String>,
+    #[serde(default)]
+    pub sources: Vec<String>,
+}
+
+/// None when no URL is configured
+pub fn export_config(config: &Webhook) -> Option<WebhookConfig> {
+    let url = config.url.as_deref().map(str::trim).filter(|url| !url.is_empty())?;
+    Some(WebhookConfig {
+        url: url.to_string(),
+        secret: config
+            .secret
+            .as_deref()
+            .filter(|secret| !secret.is_empty())
+            .map(redact_secret),
+        sources: config.sources.clone(),
+    })
+}
+
+/// Whether `archived` is the webhook configured here. A redacted secret matches the
+/// secret it was made from.
+pub fn matches(config: &Webhook, archived: &WebhookConfig) -> bool {
+    let Some(current) = export_config(config) else {
+        return false;
+    };
+    let secret = match archived.secret.as_deref().filter(|secret| !secret.is_empty()) {
+        Some(secret) if !is_redacted(secret) => Some(redact_secret(secret)),
+        secret => secret.map(str::to_string),
+    };
+    current.url == archived.url && current.secret == secret && current.sources == archived.sources
+}
+
+pub struct WebhookSink {
+    client: reqwest::Client,
+    url: String,
+    secret: Option<String>,
+    sources: Vec<String>,
+}
+
+impl WebhookSink {
+    /// None when no URL is configured
+    pub fn new(config: &Webhook) -> Option<Self> {
+        let url = config.url.as_deref().map(str::trim).filter(|url| !url.is_empty())?;
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(REQUEST_TIMEOUT_SECS))
+            .user_agent(concat!("bibliotek/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .unwrap_or_default();
+        Some(Self {
+            client,
+            url: url.to_string(),
+            secret: config.secret.clone().filter(|secret| !secret.is_empty()),
+            sources: config.sources.clone(),
+        })
+    }
+
+    /// A configured source also covers its sub-sources, so "light" reports every browser
+    fn reports(&self, source: &str) -> bool {
+        let parent = source.split_once(':').map_or(source, |(parent, _)| parent);
+        self.sources.is_empty() || self.sources.iter().any(|s| s == source || s == parent)
+    }
+}
+
+/// `sha256=<hex HMAC-SHA256 of body>`, the value of `X-Bibliotek-Signature`
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any length");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+#[async_trait]
+impl OutboxSink for WebhookSink {
+    fn name(&self) -> &str {
+        "webhook"
+    }
+
+    async fn deliver(&self, entry: &OutboxEntry) -> Result<()> {
+        let Event::SyncCompleted { source, response, .. } = &entry.event else {
+            return Ok(());
+        };
+        if !self.reports(source) {
+            return Ok(());
+        }
+
+        let body = serde_json::to_vec(&SyncPayload {
+            kind: entry.event.kind(),
+            source,
+            completed_at: &entry.created_at,
+            response,
+        })?;
+        let mut request = self
+            .client
+            .post(&self.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header("X-Bibliotek-Event", entry.event.kind())
+            .header("X-Bibliotek-Delivery", entry.id.to_string());
+        if let Some(secret) = &self.secret {
+            request = request.header("X-Bibliotek-Signature", sign(secret, &body));
+        }
+
+        let resp = request.body(body).send().await?;
+        if !resp.status().is_success() {
+            anyhow::bail!("{} answered {}", self.url, resp.status());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signature_is_hmac_sha256() {
+        // RFC 4231, test case 2
+        assert_eq!(
+            sign("Jefe", b"what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+}
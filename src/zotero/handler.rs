@@ -0,0 +1,450 @@
+//! Items, annotations and notes from a local Zotero library, read straight from its
+//! SQLite database (`zotero.sqlite` in the Zotero data directory). Each top-level
+//! item becomes a resource, and so does a standalone attachment (a PDF dropped in
+//! without a parent). Highlights made in Zotero's PDF reader on an item's attachments
+//! become annotations of the item, with their comment as a comment on them;
+//! comment-only annotations and the item's child notes become notes. Everything is
+//! keyed by Zotero's item key, and whatever was removed or trashed in Zotero is
+//! soft-deleted here on the next sync.
+//!
+//! Zotero keeps its database locked while it runs, so a sync may have to wait for
+//! Zotero to be closed.
+
+use std::path::Path;
+
+use async_trait::async_trait;
+use axum::{Json, extract::State, response::Response};
+use libsql::{Builder, Connection};
+use serde::{Deserialize, Serialize};
+
+use crate::commonplace::ResourceType;
+use crate::commonplace::boundary::Boundary;
+use crate::commonplace::capture::page_text;
+use crate::handler::AppState;
+use crate::response::{bad_request, internal_error, success};
+use crate::sync::engine::{self, SyncError, SyncResponse};
+use crate::sync::{ResourceKey, SourceAnnotation, SourceComment, SourceNote, SourceResource, SyncSource};
+
+#[derive(Debug, Deserialize)]
+pub struct SetConfigRequest {
+    pub db_path: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ConfigResponse {
+    pub db_path: Option<String>,
+    pub last_sync_at: Option<String>,
+}
+
+/// Portable representation of the Zotero source used by the sync config archive
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ZoteroSourceConfig {
+    pub db_path: String,
+    pub last_sync_at: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct ZoteroItem {
+    id: i64,
+    key: String,
+    item_type: String,
+    title: String,
+    url: Option<String>,
+    annotations: Vec<ZoteroAnnotation>,
+    notes: Vec<ZoteroNote>,
+}
+
+#[derive(Debug)]
+struct ZoteroAnnotation {
+    key: String,
+    text: String,
+    comment: String,
+    color: Option<String>,
+    page_label: Option<String>,
+    position: Option<String>,
+}
+
+#[derive(Debug)]
+struct ZoteroNote {
+    key: String,
+    /// Zotero stores notes as HTML
+    html: String,
+}
+
+impl ZoteroItem {
+    fn resource(&self) -> (String, ResourceType) {
+        match self.url.as_deref().map(str::trim) {
+            Some(url) if self.item_type == "webpage" && !url.is_empty() => (url.to_string(), ResourceType::Website),
+            _ if self.title.trim().is_empty() => (self.key.clone(), ResourceType::Pdf),
+            _ => (self.title.trim().to_string(), ResourceType::Pdf),
+        }
+    }
+
+    fn into_resource(self) -> SourceResource {
+        let (title, resource_type) = self.resource();
+        let mut annotations = Vec::new();
+        let mut notes = Vec::new();
+        for annotation in self.annotations {
+            let external_id = format!("zotero:{}", annotation.key);
+            let comment = annotation.comment.trim();
+            if annotation.text.trim().is_empty() {
+                // A sticky note or text box: nothing highlighted, only what was written
+                if !comment.is_empty() {
+                    notes.push(SourceNote {
+                        external_id,
+                        content: comment.to_string(),
+                    });
+                }
+                continue;
+            }
+
+            let position = annotation
+                .position
+                .as_deref()
+                .and_then(|p| serde_json::from_str(p).ok());
+            let boundary = Boundary::from_zotero(&annotation.text, position.as_ref(), annotation.page_label.as_deref());
+            let comments = match comment {
+                "" => Vec::new(),
+                comment => vec![SourceComment {
+                    external_id: format!("zotero:comment:{}", annotation.key),
+                    content: comment.to_string(),
+                }],
+            };
+            annotations.push(SourceAnnotation {
+                external_id,
+                text: annotation.text,
+                color: annotation.color,
+                boundary: Some(boundary.into_value()),
+                comments,
+            });
+        }
+
+        for note in self.notes {
+            let content = page_text(&note.html).trim().to_string();
+            if !content.is_empty() {
+                notes.push(SourceNote {
+                    external_id: format!("zotero:{}", note.key),
+                    content,
+                });
+            }
+        }
+
+        SourceResource {
+            key: ResourceKey::External(format!("zotero:{}", self.key)),
+            title,
+            resource_type,
+            annotations,
+            notes,
+            archived: None,
+        }
+    }
+}
+
+struct ZoteroSource {
+    db_path: String,
+}
+
+#[async_trait]
+impl SyncSource for ZoteroSource {
+    fn name(&self) -> String {
+        "zotero".to_string()
+    }
+
+    async fn fetch(&self) -> anyhow::Result<Vec<SourceResource>> {
+        let items = read_library(&open_zotero_db(&self.db_path).await?).await?;
+        Ok(items.into_iter().map(ZoteroItem::into_resource).collect())
+    }
+}
+
+pub async fn export_config(conn: &Connection) -> anyhow::Result<Option<ZoteroSourceConfig>> {
+    let query = r#"SELECT db_path, last_sync_at FROM zotero_config WHERE id = 1"#;
+    let mut rows = conn.query(query, ()).await?;
+
+    match rows.next().await? {
+        Some(row) => Ok(Some(ZoteroSourceConfig {
+            db_path: row.get(0)?,
+            last_sync_at: row.get(1)?,
+        })),
+        None => Ok(None),
+    }
+}
+
+/// Like the Research one, this does not require the path to exist on this machine
+pub async fn import_config(conn: &Connection, config: &ZoteroSourceConfig) -> anyhow::Result<()> {
+    let query = r#"
+        INSERT INTO zotero_config (id, db_path, last_sync_at)
+        VALUES (1, ?, ?)
+        ON CONFLICT(id) DO UPDATE SET
+            db_path = excluded.db_path,
+            last_sync_at = excluded.last_sync_at,
+            updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
+    "#;
+
+    conn.execute(query, libsql::params![config.db_path.clone(), config.last_sync_at.clone()])
+        .await?;
+    Ok(())
+}
+
+pub async fn get_config(State(state): State<AppState>) -> Response {
+    match export_config(&state.db.connection()).await {
+        Ok(config) => success(ConfigResponse {
+            db_path: config.as_ref().map(|c| c.db_path.clone()),
+            last_sync_at: config.and_then(|c| c.last_sync_at),
+        }),
+        Err(e) => {
+            tracing::error!("Failed to get Zotero config: {}", e);
+            internal_error("Failed to get config")
+        }
+    }
+}
+
+pub async fn set_config(State(state): State<AppState>, Json(payload): Json<SetConfigRequest>) -> Response {
+    if !Path::new(&payload.db_path).exists() {
+        return bad_request("Database file does not exist at the specified path");
+    }
+
+    let query = r#"
+        INSERT INTO zotero_config (id, db_path)
+        VALUES (1, ?)
+        ON CONFLICT(id) DO UPDATE SET
+            db_path = excluded.db_path,
+            updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
+    "#;
+    match state
+        .db
+        .connection()
+        .execute(query, libsql::params![payload.db_path.clone()])
+        .await
+    {
+        Ok(_) => success(ConfigResponse {
+            db_path: Some(payload.db_path),
+            last_sync_at: None,
+        }),
+        Err(e) => {
+            tracing::error!("Failed to set Zotero config: {}", e);
+            internal_error("Failed to save configuration")
+        }
+    }
+}
+
+pub async fn sync(State(state): State<AppState>) -> Response {
+    let conn = &state.db.connection();
+    let db_path = match export_config(conn).await {
+        Ok(Some(config)) => config.db_path,
+        Ok(None) => return bad_request("Zotero database path not configured. Please set the path first."),
+        Err(e) => {
+            tracing::error!("Failed to get Zotero config: {}", e);
+            return internal_error("Failed to get config");
+        }
+    };
+    if !Path::new(&db_path).exists() {
+        return bad_request("Zotero database file no longer exists at the configured path");
+    }
+
+    let report = match engine::run(&state, &ZoteroSource { db_path }).await {
+        Ok(report) => report,
+        Err(SyncError::Fetch(e)) => {
+            tracing::error!("Failed to read Zotero database: {}", e);
+            return internal_error("Failed to read the Zotero database; if Zotero is running, close it and try again");
+        }
+        Err(SyncError::StartRun(e)) => {
+            tracing::error!("Failed to start zotero sync run: {}", e);
+            return internal_error("Failed to start sync run");
+        }
+    };
+
+    let _ = conn
+        .execute(
+            r#"
+            UPDATE zotero_config
+            SET last_sync_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now'),
+                updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
+            WHERE id = 1
+        "#,
+            (),
+        )
+        .await;
+
+    success(SyncResponse::from(&report))
+}
+
+async fn open_zotero_db(db_path: &str) -> anyhow::Result<Connection> {
+    let db = Builder::new_local(db_path)
+        .flags(libsql::OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .build()
+        .await?;
+    Ok(db.connect()?)
+}
+
+/// Top-level items and standalone attachments that aren't in the trash, with their
+/// annotations and notes
+pub async fn read_library(zotero: &Connection) -> anyhow::Result<Vec<ZoteroItem>> {
+    let query = r#"
+        SELECT i.itemID, i.key, t.typeName,
+               (SELECT v.value FROM itemData d
+                JOIN fields f ON f.fieldID = d.fieldID
+                JOIN itemDataValues v ON v.valueID = d.valueID
+                WHERE d.itemID = i.itemID AND f.fieldName = 'title'),
+               (SELECT v.value FROM itemData d
+                JOIN fields f ON f.fieldID = d.fieldID
+                JOIN itemDataValues v ON v.valueID = d.valueID
+                WHERE d.itemID = i.itemID AND f.fieldName = 'url')
+        FROM items i
+        JOIN itemTypes t ON t.itemTypeID = i.itemTypeID
+        LEFT JOIN itemAttachments att ON att.itemID = i.itemID
+        WHERE t.typeName NOT IN ('note', 'annotation')
+          AND (t.typeName != 'attachment' OR att.parentItemID IS NULL)
+          AND i.itemID NOT IN (SELECT itemID FROM deletedItems)
+        ORDER BY i.itemID
+    "#;
+    let mut rows = zotero.query(query, ()).await?;
+    let mut items = Vec::new();
+    while let Some(row) = rows.next().await? {
+        items.push(ZoteroItem {
+            id: row.get(0)?,
+            key: row.get(1)?,
+            item_type: row.get(2)?,
+            title: row.get::<Option<String>>(3)?.unwrap_or_default(),
+            url: row.get(4)?,
+            annotations: Vec::new(),
+            notes: Vec::new(),
+        });
+    }
+
+    for item in &mut items {
+        item.annotations = fetch_annotations(zotero, item.id).await?;
+        item.notes = fetch_notes(zotero, item.id).await?;
+    }
+    Ok(items)
+}
+
+/// Annotations on the item's attachments, or on the item itself when it is a
+/// standalone attachment
+async fn fetch_annotations(zotero: &Connection, item_id: i64) -> anyhow::Result<Vec<ZoteroAnnotation>> {
+    let query = r#"
+        SELECT i.key, a.text, a.comment, a.color, a.pageLabel, a.position
+        FROM itemAnnotations a
+        JOIN items i ON i.itemID = a.itemID
+        JOIN itemAttachments att ON att.itemID = a.parentItemID
+        WHERE (att.parentItemID = ?1 OR att.itemID = ?1)
+          AND a.itemID NOT IN (SELECT itemID FROM deletedItems)
+          AND att.itemID NOT IN (SELECT itemID FROM deletedItems)
+        ORDER BY a.sortIndex, a.itemID
+    "#;
+    let mut rows = zotero.query(query, libsql::params![item_id]).await?;
+    let mut annotations = Vec::new();
+    while let Some(row) = rows.next().await? {
+        annotations.push(ZoteroAnnotation {
+            key: row.get(0)?,
+            text: row.get::<Option<String>>(1)?.unwrap_or_default(),
+            comment: row.get::<Option<String>>(2)?.unwrap_or_default(),
+            color: row.get(3)?,
+            page_label: row.get(4)?,
+            position: row.get(5)?,
+        });
+    }
+    Ok(annotations)
+}
+
+async fn fetch_notes(zotero: &Connection, item_id: i64) -> anyhow::Result<Vec<ZoteroNote>> {
+    let query = r#"
+        SELECT i.key, n.note
+        FROM itemNotes n
+        JOIN items i ON i.itemID = n.itemID
+        WHERE n.parentItemID = ?
+          AND n.itemID NOT IN (SELECT itemID FROM deletedItems)
+        ORDER BY n.itemID
+    "#;
+    let mut rows = zotero.query(query, libsql::params![item_id]).await?;
+    let mut notes = Vec::new();
+    while let Some(row) = rows.next().await? {
+        notes.push(ZoteroNote {
+            key: row.get(0)?,
+            html: row.get::<Option<String>>(1)?.unwrap_or_default(),
+        });
+    }
+    Ok(notes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commonplace::Commonplace;
+    use crate::sync::engine::{OnConflict, SyncReport};
+    use crate::test_support::test_db;
+
+    async fn apply(lib: &Commonplace<'_>, items: Vec<ZoteroItem>) -> SyncReport {
+        let resources: Vec<_> = items.into_iter().map(ZoteroItem::into_resource).collect();
+        engine::apply(lib, "zotero", None, OnConflict::default(), &resources).await
+    }
+
+    /// The parts of Zotero's schema the sync reads
+    const SCHEMA: &str = r#"
+        CREATE TABLE itemTypes (itemTypeID INTEGER PRIMARY KEY, typeName TEXT);
+        CREATE TABLE items (itemID INTEGER PRIMARY KEY, itemTypeID INT, key TEXT);
+        CREATE TABLE fields (fieldID INTEGER PRIMARY KEY, fieldName TEXT);
+        CREATE TABLE itemDataValues (valueID INTEGER PRIMARY KEY, value);
+        CREATE TABLE itemData (itemID INT, fieldID INT, valueID INT);
+        CREATE TABLE itemAttachments (itemID INTEGER PRIMARY KEY, parentItemID INT, contentType TEXT, path TEXT);
+        CREATE TABLE itemAnnotations (itemID INTEGER PRIMARY KEY, parentItemID INT, type INT, text TEXT,
+            comment TEXT, color TEXT, pageLabel TEXT, sortIndex TEXT, position TEXT);
+        CREATE TABLE itemNotes (itemID INTEGER PRIMARY KEY, parentItemID INT, note TEXT, title TEXT);
+        CREATE TABLE deletedItems (itemID INTEGER PRIMARY KEY);
+        INSERT INTO itemTypes VALUES (1, 'book'), (2, 'attachment'), (3, 'note'), (4, 'annotation'), (5, 'webpage');
+        INSERT INTO fields VALUES (1, 'title'), (2, 'url');
+        INSERT INTO items VALUES (1, 1, 'BOOK1'), (2, 2, 'PDF1'), (3, 4, 'ANN1'), (4, 4, 'ANN2'), (5, 3, 'NOTE1'),
+            (6, 5, 'WEB1'), (7, 2, 'LOOSE1'), (8, 4, 'ANN3');
+        INSERT INTO itemDataValues VALUES (1, 'How to Take Smart Notes'), (2, 'An essay'),
+            (3, 'https://example.com/essay'), (4, 'scan.pdf');
+        INSERT INTO itemData VALUES (1, 1, 1), (6, 1, 2), (6, 2, 3), (7, 1, 4);
+        INSERT INTO itemAttachments VALUES (2, 1, 'application/pdf', 'storage:book.pdf'),
+            (7, NULL, 'application/pdf', 'storage:scan.pdf');
+        INSERT INTO itemAnnotations VALUES
+            (3, 2, 1, 'Writing is thinking.', 'see Luhmann', '#ffd400', '12', '00011|000100|00200',
+             '{"pageIndex": 11, "rects": [[72, 600, 300, 614]]}'),
+            (4, 2, 2, '', 'Check the second edition', '#ffd400', '14', '00013|000000|00000', '{"pageIndex": 13}'),
+            (8, 7, 1, 'A loose highlight', NULL, NULL, '1', '00000|000000|00000', '{"pageIndex": 0}');
+        INSERT INTO itemNotes VALUES (5, 1, '<div><p>Read <b>slowly</b>.</p></div>', 'Read slowly.');
+    "#;
+
+    #[tokio::test]
+    async fn test_library_is_synced_and_trash_removed() {
+        let zotero = Builder::new_local(":memory:").build().await.unwrap().connect().unwrap();
+        zotero.execute_batch(SCHEMA).await.unwrap();
+        let db = test_db().await;
+        let lib = db.commonplace();
+
+        let items = read_library(&zotero).await.unwrap();
+        let titles: Vec<_> = items.iter().map(|item| item.resource()).collect();
+        assert_eq!(
+            titles,
+            vec![
+                ("How to Take Smart Notes".to_string(), ResourceType::Pdf),
+                ("https://example.com/essay".to_string(), ResourceType::Website),
+                ("scan.pdf".to_string(), ResourceType::Pdf),
+            ]
+        );
+        let first = apply(&lib, items).await;
+        assert_eq!(
+            (first.resources.created, first.annotations.created, first.comments.created, first.notes.created),
+            (3, 2, 1, 2)
+        );
+        let annotation = lib
+            .find_annotation_by_external_id("zotero:ANN1")
+            .await
+            .unwrap()
+            .unwrap();
+        let boundary = annotation.boundary.unwrap();
+        assert_eq!((boundary["page"].as_i64(), boundary["extra"]["pageLabel"].as_str()), (Some(12), Some("12")));
+        let note = lib.find_note_by_external_id("zotero:NOTE1").await.unwrap().unwrap();
+        assert_eq!(note.content, "Read slowly.");
+
+        let again = apply(&lib, read_library(&zotero).await.unwrap()).await;
+        assert_eq!(again.totals().created + again.totals().updated + again.totals().deleted, 0);
+
+        // Trashing the attachment takes its highlights with it
+        zotero.execute("INSERT INTO deletedItems VALUES (2)", ()).await.unwrap();
+        let trashed = apply(&lib, read_library(&zotero).await.unwrap()).await;
+        assert_eq!((trashed.annotations.deleted, trashed.comments.deleted, trashed.notes.deleted), (1, 1, 1));
+    }
+}
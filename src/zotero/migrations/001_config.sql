@@ -0,0 +1,10 @@
+-- Zotero Module Configuration
+-- Stores the path to Zotero's SQLite database (zotero.sqlite in the Zotero data directory)
+
+CREATE TABLE IF NOT EXISTS zotero_config (
+    id INTEGER PRIMARY KEY CHECK (id = 1), -- Only one config row allowed
+    db_path TEXT NOT NULL,
+    last_sync_at TEXT,
+    created_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    updated_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))
+);
@@ -0,0 +1 @@
+DROP TABLE IF EXISTS zotero_config;
@@ -0,0 +1,13 @@
+mod handler;
+mod routes;
+
+pub use handler::{ZoteroSourceConfig, export_config, import_config};
+pub use routes::routes;
+
+pub fn migrations() -> &'static [(&'static str, &'static str)] {
+    &[("zotero_001_config.sql", include_str!("migrations/001_config.sql"))]
+}
+
+pub fn down_migrations() -> &'static [(&'static str, &'static str)] {
+    &[("zotero_001_config.sql", include_str!("migrations/down/001_config.sql"))]
+}
@@ -59,8 +59,8 @@ function transformCommonplaceToLight(resources) {
       const url = resource.title // In Light sync, URL is stored as title
 
       highlightsByUrl[url] = resource.annotations.map((annotation) => {
-        // Extract Light metadata from boundary if available
-        const boundary = annotation.boundary || {}
+        // Light metadata is kept under the boundary's source-specific extras
+        const boundary = annotation.boundary?.extra || {}
 
         return {
           chunks: boundary.chunks || [annotation.text],
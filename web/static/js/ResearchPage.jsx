@@ -56,8 +56,25 @@ export default function ResearchPage({ resourceId, onNavigate }) {
         throw new Error(data.error || "Sync failed");
       }
 
-      setSyncStats(data.data);
+      // One entry per Research database; add them up, and report the ones that failed
+      const totals = {};
+      const failures = [];
+      for (const { source, error, ...counts } of data.data) {
+        if (error) {
+          failures.push(`${source}: ${error}`);
+          continue;
+        }
+        for (const [key, value] of Object.entries(counts)) {
+          if (typeof value === "number" && key !== "run_id") {
+            totals[key] = (totals[key] || 0) + value;
+          }
+        }
+      }
+      setSyncStats(totals);
       await loadData();
+      if (failures.length > 0) {
+        setError(failures.join("; "));
+      }
     } catch (err) {
       setError(err.message);
     } finally {
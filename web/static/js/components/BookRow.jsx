@@ -96,21 +96,20 @@ export default function BookRow({ book, entities, onUpdate, onEntitiesChange })
     return null
   }
 
-  const handleView = async () => {
-    try {
-      const url = new URL(book.download_url)
-      const key = decodeURIComponent(url.pathname.slice(1))
-      const res = await fetch(`/download?key=${encodeURIComponent(key)}`)
-      if (res.ok) {
-        const data = await res.json()
-        window.open(data.url, '_blank')
-      } else {
-        console.error('Failed to get download URL')
-        window.open(book.download_url, '_blank')
-      }
-    } catch (e) {
-      console.error('Failed to get download URL:', e)
-      window.open(book.download_url, '_blank')
+  const handleView = () => {
+    window.open(`/books/${book.id}/download?inline=true`, '_blank')
+  }
+
+  const failedJobs = Object.entries(book.jobs || {})
+    .filter(([, status]) => status === 'failed')
+    .map(([kind]) => kind)
+
+  const handleRetry = async () => {
+    const res = await fetch(`/books/${book.id}/jobs/retry`, { method: 'POST' })
+    if (res.ok) {
+      const jobs = { ...book.jobs }
+      failedJobs.forEach(kind => { jobs[kind] = 'queued' })
+      onUpdate({ ...book, jobs })
     }
   }
 
@@ -145,6 +144,15 @@ export default function BookRow({ book, entities, onUpdate, onEntitiesChange })
           >
             view
           </button>
+          {failedJobs.length > 0 && (
+            <button
+              onClick={handleRetry}
+              title={`failed: ${failedJobs.join(', ')}`}
+              className="border border-gray-400 px-3 text-sm hover:bg-gray-100 ml-1"
+            >
+              retry
+            </button>
+          )}
         </td>
       </tr>
     )
@@ -11,8 +11,15 @@ async function computeSignature(file) {
   return hashHex.substring(0, 16) // First 16 hex chars
 }
 
+const isEpub = (file) =>
+  file.type === 'application/epub+zip' || file.name.toLowerCase().endsWith('.epub')
+
 // Extract PDF metadata using pdf.js
+// EPUB metadata is read from the OPF package on the server instead
 async function extractPdfMetadata(file) {
+  if (isEpub(file)) {
+    return { title: null, author: null, subject: null, keywords: null }
+  }
   try {
     const arrayBuffer = await file.arrayBuffer()
     const pdf = await pdfjsLib.getDocument({ data: arrayBuffer }).promise
@@ -85,11 +92,11 @@ export default function MassUploader({ onBookCreated }) {
   }, [])
 
   const handleFiles = async (files) => {
-    const pdfFiles = Array.from(files).filter(f =>
-      f.type === 'application/pdf' || f.name.toLowerCase().endsWith('.pdf')
+    const bookFiles = Array.from(files).filter(f =>
+      f.type === 'application/pdf' || f.name.toLowerCase().endsWith('.pdf') || isEpub(f)
     )
 
-    for (const file of pdfFiles) {
+    for (const file of bookFiles) {
       const signature = await computeSignature(file)
 
       // Check if this file matches a pending upload from server
@@ -312,7 +319,7 @@ export default function MassUploader({ onBookCreated }) {
           type="file"
           className="hidden"
           multiple
-          accept="application/pdf"
+          accept="application/pdf,application/epub+zip,.epub"
           onChange={(e) => handleFiles(e.target.files)}
         />
         <div className="file-drop-content">
@@ -320,7 +327,7 @@ export default function MassUploader({ onBookCreated }) {
             <path stroke="currentColor" strokeLinecap="round" strokeLinejoin="round" strokeWidth="2" d="M13 13h3a3 3 0 0 0 0-6h-.025A5.56 5.56 0 0 0 16 6.5 5.5 5.5 0 0 0 5.207 5.021C5.137 5.017 5.071 5 5 5a4 4 0 0 0 0 8h2.167M10 15V6m0 0L8 8m2-2 2 2" />
           </svg>
           <div className="file-drop-text">
-            <p className="text-sm font-bold">drop or click to add pdfs</p>
+            <p className="text-sm font-bold">drop or click to add pdfs or epubs</p>
           </div>
         </div>
       </div>
@@ -2,11 +2,26 @@ import React, { useState, useEffect } from "react";
 import ChapterEditor from "./ChapterEditor.jsx";
 import ChapterSidebar from "./ChapterSidebar.jsx";
 
+function CommentThread({ comment }) {
+  return (
+    <div className="research-comment">
+      <div dangerouslySetInnerHTML={{ __html: comment.content }} />
+      {comment.replies && comment.replies.length > 0 && (
+        <div className="research-comment-replies">
+          {comment.replies.map((reply) => (
+            <CommentThread key={reply.id} comment={reply} />
+          ))}
+        </div>
+      )}
+    </div>
+  );
+}
+
 function AnnotationItem({ annotation }) {
   const [showComments, setShowComments] = useState(false);
 
   return (
-    <div className="research-annotation">
+    <div id={`annotation-${annotation.id}`} className="research-annotation">
       <div className="research-annotation-text">
         <p>{annotation.text}</p>
       </div>
@@ -24,9 +39,7 @@ function AnnotationItem({ annotation }) {
           {showComments && (
             <div className="research-comments">
               {annotation.comments.map((comment) => (
-                <div key={comment.id} className="research-comment">
-                  <div dangerouslySetInnerHTML={{ __html: comment.content }} />
-                </div>
+                <CommentThread key={comment.id} comment={comment} />
               ))}
             </div>
           )}
@@ -40,7 +53,7 @@ function groupAnnotationsByPage(annotations) {
   const groups = {};
 
   for (const ann of annotations) {
-    const page = ann.boundary?.pageNumber ?? "No Page";
+    const page = ann.boundary?.page ?? "No Page";
     if (!groups[page]) {
       groups[page] = [];
     }
@@ -78,7 +91,7 @@ function parseChapters(config) {
 
 function getAnnotationsForChapter(annotations, chapter) {
   return annotations.filter((ann) => {
-    const page = ann.boundary?.pageNumber;
+    const page = ann.boundary?.page;
     if (page == null) return false;
     return page >= chapter.startPage && page <= chapter.endPage;
   });
@@ -95,7 +108,7 @@ function getChapterAnnotationCounts(annotations, chapters) {
 function getUnchapteredAnnotations(annotations, chapters) {
   if (chapters.length === 0) return annotations;
   return annotations.filter((ann) => {
-    const page = ann.boundary?.pageNumber;
+    const page = ann.boundary?.page;
     if (page == null) return true;
     return !chapters.some((ch) => page >= ch.startPage && page <= ch.endPage);
   });
@@ -110,6 +123,16 @@ function getChapterFromHash() {
   return null;
 }
 
+// Permalinks (/a/:public_id) land here as ?page=N#annotation-ID
+function getLinkTarget() {
+  const hash = window.location.hash;
+  if (!hash.startsWith("#annotation-")) return null;
+  const annotationId = parseInt(hash.replace("#annotation-", ""), 10);
+  if (isNaN(annotationId)) return null;
+  const page = parseInt(new URLSearchParams(window.location.search).get("page"), 10);
+  return { annotationId, page: isNaN(page) ? null : page };
+}
+
 export default function ResourceDetail({ resourceId, onBack }) {
   const [data, setData] = useState(null);
   const [loading, setLoading] = useState(true);
@@ -117,6 +140,7 @@ export default function ResourceDetail({ resourceId, onBack }) {
   const [savingConfig, setSavingConfig] = useState(false);
   const [editingChapters, setEditingChapters] = useState(false);
   const [selectedChapter, setSelectedChapter] = useState(getChapterFromHash);
+  const [linkTarget] = useState(getLinkTarget);
   const [darkMode, setDarkMode] = useState(() => {
     const saved = localStorage.getItem("research-dark-mode");
     return saved === "true";
@@ -141,6 +165,13 @@ export default function ResourceDetail({ resourceId, onBack }) {
     loadResourceFull();
   }, [resourceId]);
 
+  useEffect(() => {
+    if (!data || !linkTarget) return;
+    document
+      .getElementById(`annotation-${linkTarget.annotationId}`)
+      ?.scrollIntoView({ block: "center" });
+  }, [data, linkTarget, selectedChapter]);
+
   const loadResourceFull = async () => {
     try {
       setLoading(true);
@@ -154,6 +185,16 @@ export default function ResourceDetail({ resourceId, onBack }) {
         if (hashChapter && chapters.some((c) => c.key === hashChapter)) {
           setSelectedChapter(hashChapter);
         }
+        if (linkTarget) {
+          const annotation = result.data?.annotations?.find(
+            (ann) => ann.id === linkTarget.annotationId,
+          );
+          const page = linkTarget.page ?? annotation?.boundary?.page;
+          const chapter =
+            page != null &&
+            chapters.find((c) => page >= c.startPage && page <= c.endPage);
+          setSelectedChapter(chapter ? chapter.key : null);
+        }
       } else {
         setError("Resource not found");
       }
@@ -15,6 +15,13 @@ function trimTitle(title, maxLength = 60) {
   return title.substring(0, maxLength).trim() + '...'
 }
 
+function readingStatus(resource) {
+  if (resource.status === 'reading' && resource.progress > 0) {
+    return `${Math.round(resource.progress * 100)}%`
+  }
+  return resource.status === 'unread' ? '' : resource.status
+}
+
 export default function ResourceList({ resources, onNavigate }) {
   if (!resources.length) {
     return <p className="research-empty">No resources synced yet. Configure the database path and sync.</p>
@@ -29,6 +36,7 @@ export default function ResourceList({ resources, onNavigate }) {
           onClick={() => onNavigate(resource.id)}
         >
           <span className="research-list-title">{trimTitle(resource.title)}</span>
+          <span className="research-list-status">{readingStatus(resource)}</span>
           <span className="research-list-date">{formatDate(resource.created_at)}</span>
         </div>
       ))}
@@ -15,6 +15,12 @@ const apiProxy = {
   },
 };
 
+// Server-rendered pages are proxied whatever the request accepts
+const pageProxy = {
+  target: `http://localhost:${apiPort}`,
+  changeOrigin: true,
+};
+
 export default defineConfig({
   plugins: [react()],
   root: "static",
@@ -30,10 +36,26 @@ export default defineConfig({
       "/authors": apiProxy,
       "/tags": apiProxy,
       "/categories": apiProxy,
+      "/shelves": apiProxy,
       "/commonplace": apiProxy,
+      "/imports": apiProxy,
       "/light": apiProxy,
       "/research": apiProxy,
       "/download": apiProxy,
+      "/sync": apiProxy,
+      "/admin": apiProxy,
+      "/html": pageProxy,
+      "/quick": apiProxy,
+      "/sitemap.xml": pageProxy,
+      "/palette": apiProxy,
+      "/queue": apiProxy,
+      "/readwise": apiProxy,
+      "/zotero": apiProxy,
+      "/pocket": apiProxy,
+      "/kobo": apiProxy,
+      "/wallabag": apiProxy,
+      "/feeds": apiProxy,
+      "/import": apiProxy,
     },
   },
 });